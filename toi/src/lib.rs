@@ -18,9 +18,335 @@ pub struct Message {
     pub content: String,
 }
 
-#[derive(Builder, Debug, Deserialize, Serialize, ToSchema)]
+impl Message {
+    #[must_use]
+    pub fn user(content: impl Into<String>) -> Self {
+        Self {
+            role: MessageRole::User,
+            content: content.into(),
+        }
+    }
+
+    #[must_use]
+    pub fn assistant(content: impl Into<String>) -> Self {
+        Self {
+            role: MessageRole::Assistant,
+            content: content.into(),
+        }
+    }
+
+    #[must_use]
+    pub fn system(content: impl Into<String>) -> Self {
+        Self {
+            role: MessageRole::System,
+            content: content.into(),
+        }
+    }
+}
+
+/// Messages of a conversation in the order they were sent. It serializes
+/// the same as a list of messages.
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+#[serde(transparent)]
+pub struct Conversation(Vec<Message>);
+
+impl Conversation {
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    #[must_use]
+    pub fn messages(&self) -> &[Message] {
+        &self.0
+    }
+
+    #[must_use]
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    #[must_use]
+    pub fn last(&self) -> Option<&Message> {
+        self.0.last()
+    }
+
+    pub fn clear(&mut self) {
+        self.0.clear();
+    }
+
+    pub fn pop(&mut self) -> Option<Message> {
+        self.0.pop()
+    }
+
+    pub fn push(&mut self, message: Message) {
+        self.0.push(message);
+    }
+
+    pub fn push_user(&mut self, content: impl Into<String>) {
+        self.push(Message::user(content));
+    }
+
+    pub fn push_assistant(&mut self, content: impl Into<String>) {
+        self.push(Message::assistant(content));
+    }
+
+    /// Number of turns in the conversation. A turn starts with a user
+    /// message and includes the messages after it up until the next user
+    /// message.
+    #[must_use]
+    pub fn turns(&self) -> usize {
+        self.0
+            .iter()
+            .filter(|message| message.role == MessageRole::User)
+            .count()
+    }
+
+    /// Drop everything before the last `n` turns. Nothing is dropped if
+    /// there are `n` turns or fewer.
+    pub fn truncate_to_last_n_turns(&mut self, n: usize) {
+        if n == 0 {
+            self.0.clear();
+            return;
+        }
+        let start = self
+            .0
+            .iter()
+            .enumerate()
+            .rev()
+            .filter(|(_, message)| message.role == MessageRole::User)
+            .nth(n - 1)
+            .map(|(i, _)| i);
+        if let Some(start) = start
+            && self.turns() > n
+        {
+            self.0.drain(..start);
+        }
+    }
+
+    /// Rough number of tokens the conversation's messages take up, assuming
+    /// about four characters per token.
+    #[must_use]
+    pub fn token_estimate(&self) -> usize {
+        self.0
+            .iter()
+            .map(|message| message.content.chars().count())
+            .sum::<usize>()
+            .div_ceil(4)
+    }
+}
+
+impl From<Vec<Message>> for Conversation {
+    fn from(messages: Vec<Message>) -> Self {
+        Self(messages)
+    }
+}
+
+impl From<Conversation> for Vec<Message> {
+    fn from(conversation: Conversation) -> Self {
+        conversation.0
+    }
+}
+
+impl From<Conversation> for GenerationRequest {
+    fn from(conversation: Conversation) -> Self {
+        Self::builder().messages(conversation.0).build()
+    }
+}
+
+#[derive(Builder, Clone, Debug, Deserialize, Serialize, ToSchema)]
 pub struct GenerationRequest {
     pub messages: Vec<Message>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub conversation_id: Option<i32>,
+    /// Extra instructions on how the assistant should respond (e.g., its
+    /// tone). They're added after the server's own rules and can't override
+    /// them.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub style_instructions: Option<String>,
+    /// Path prefix of the endpoints to consider for the latest message
+    /// (e.g., "/recipes"), for when searching all of them picks the wrong
+    /// one. All endpoints are considered if none under the prefix are
+    /// relevant enough.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub endpoint_hint: Option<String>,
+    /// Sampling temperature for the response, from 0 to 2. Lower is more
+    /// deterministic, and higher is more creative. Defaults to the server's
+    /// configured temperature.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub temperature: Option<f32>,
+    /// Nucleus sampling probability mass for the response, from 0 (exclusive)
+    /// to 1. Defaults to the server's configured value.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub top_p: Option<f32>,
+    /// Max number of tokens to generate for the response. Defaults to the
+    /// server's configured limit, if any.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_tokens: Option<u32>,
+    /// Make the response stream resumable. Resumable streams include
+    /// checkpoint comments with a token for resuming them, and they keep
+    /// generating for a while even if the client disconnects.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub resumable: Option<bool>,
+    /// Pick an interrupted resumable response stream back up instead of
+    /// generating a new response.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub resume: Option<ResumePoint>,
     #[serde(skip_deserializing)]
     response_format: Option<Value>,
 }
+
+// Prefix of the SSE comment lines that mark resumable response stream
+// checkpoints.
+const CHECKPOINT_PREFIX: &str = ": checkpoint ";
+
+/// Where to pick an interrupted resumable response stream back up.
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize, ToSchema)]
+pub struct ResumePoint {
+    /// Token from the response stream's checkpoints.
+    pub token: String,
+    /// Number of bytes of the response stream already received.
+    pub offset: usize,
+}
+
+impl ResumePoint {
+    /// Format the resume point as an SSE comment so it can be sent as a
+    /// checkpoint within a response stream. Clients that don't know about
+    /// checkpoints ignore them like any other comment.
+    #[must_use]
+    pub fn to_checkpoint(&self) -> String {
+        format!(
+            "{CHECKPOINT_PREFIX}token={} offset={}\n\n",
+            self.token, self.offset
+        )
+    }
+
+    /// Parse a checkpoint line from a response stream, returning `None` if
+    /// the line isn't a checkpoint.
+    #[must_use]
+    pub fn from_checkpoint(line: &str) -> Option<Self> {
+        let mut fields = line.trim_end().strip_prefix(CHECKPOINT_PREFIX)?.split(' ');
+        let token = fields.next()?.strip_prefix("token=")?.to_string();
+        let offset = fields.next()?.strip_prefix("offset=")?.parse().ok()?;
+        Some(Self { token, offset })
+    }
+}
+
+/// Endpoint the assistant can use, as a compact alternative to the full
+/// OpenAPI spec.
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize, ToSchema)]
+pub struct Capability {
+    /// Endpoint path (e.g., "/notes").
+    pub path: String,
+    /// HTTP method (e.g., "POST").
+    pub method: String,
+    /// First line of the endpoint's description.
+    pub summary: String,
+    /// Whether the endpoint takes query parameters.
+    pub has_params: bool,
+    /// Whether the endpoint takes a JSON body.
+    pub has_body: bool,
+}
+
+/// What the server can do along with some metadata about it.
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize, ToSchema)]
+pub struct Capabilities {
+    /// Server version.
+    pub version: String,
+    /// Top-level paths the assistant's endpoints are under (e.g., "notes").
+    pub domains: Vec<String>,
+    /// Number of dimensions of the embedding model's embeddings, if any
+    /// endpoints have been embedded.
+    pub embedding_dimensions: Option<usize>,
+    /// Endpoints the assistant can use, ordered by path and method.
+    pub endpoints: Vec<Capability>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Conversation, GenerationRequest, Message, MessageRole};
+
+    fn contents(conversation: &Conversation) -> Vec<&str> {
+        conversation
+            .messages()
+            .iter()
+            .map(|message| message.content.as_str())
+            .collect()
+    }
+
+    #[test]
+    fn truncating_conversations() {
+        let mut conversation = Conversation::from(vec![Message::system("Be nice")]);
+        for (question, answer) in [
+            ("Hi", "Hello!"),
+            ("How are you?", "Good"),
+            ("Bye", "See ya"),
+        ] {
+            conversation.push_user(question);
+            conversation.push_assistant(answer);
+        }
+        assert_eq!(conversation.turns(), 3);
+
+        // Nothing's dropped if there aren't more turns than what's kept.
+        conversation.truncate_to_last_n_turns(3);
+        assert_eq!(conversation.len(), 7);
+
+        // Turns are dropped from the front, along with anything before
+        // them.
+        conversation.truncate_to_last_n_turns(2);
+        assert_eq!(
+            contents(&conversation),
+            ["How are you?", "Good", "Bye", "See ya"]
+        );
+
+        // A turn doesn't need a response to count.
+        conversation.push_user("Wait");
+        conversation.truncate_to_last_n_turns(1);
+        assert_eq!(contents(&conversation), ["Wait"]);
+        conversation.truncate_to_last_n_turns(0);
+        assert!(conversation.is_empty());
+    }
+
+    #[test]
+    fn estimating_tokens() {
+        let mut conversation = Conversation::new();
+        assert_eq!(conversation.token_estimate(), 0);
+
+        // Characters are counted rather than bytes, and partial tokens
+        // round up.
+        conversation.push_user("Hello");
+        assert_eq!(conversation.token_estimate(), 2);
+        conversation.push_assistant("Zoë");
+        assert_eq!(conversation.token_estimate(), 2);
+        conversation.push_assistant("!");
+        assert_eq!(conversation.token_estimate(), 3);
+    }
+
+    #[test]
+    fn serializing_conversations() {
+        let mut conversation = Conversation::new();
+        conversation.push_user("Hi");
+        conversation.push_assistant("Hello!");
+        let request = GenerationRequest::from(conversation.clone());
+        assert_eq!(request.messages[1].role, MessageRole::Assistant);
+
+        // Conversations serialize the same as the messages they wrap.
+        let messages: Vec<Message> = conversation.clone().into();
+        assert_eq!(
+            serde_json::to_value(&conversation).expect("conversation should serialize"),
+            serde_json::to_value(&messages).expect("messages should serialize")
+        );
+        assert_eq!(
+            serde_json::to_value(&request.messages).expect("messages should serialize"),
+            serde_json::json!([
+                {"role": "user", "content": "Hi"},
+                {"role": "assistant", "content": "Hello!"}
+            ])
+        );
+    }
+}
@@ -2,16 +2,17 @@ use ctrlc::set_handler;
 use futures::stream::TryStreamExt;
 use models::client::TokenUsage;
 use pico_args::Arguments;
+use reqwest::header::{self, HeaderMap, HeaderValue};
 use rustyline::config::Configurer;
 use rustyline::{
     Cmd, ConditionalEventHandler, DefaultEditor, Event, EventContext, EventHandler, KeyEvent,
     error::ReadlineError,
 };
-use std::io::{self, Write};
-use std::path::PathBuf;
-use std::time::Duration;
+use std::io::{self, IsTerminal, Write};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
 use std::{collections::VecDeque, thread};
-use toi::{GenerationRequest, Message, MessageRole};
+use toi::{Capabilities, Conversation, GenerationRequest, Message, MessageRole};
 use tokio::{
     io::AsyncBufReadExt,
     sync::mpsc::{Receiver, Sender},
@@ -19,122 +20,234 @@ use tokio::{
 use tokio_util::io::StreamReader;
 
 mod models;
+mod render;
+mod spinner;
 
 use models::{
-    client::GenerationResponseChunk,
-    repl::{ServerRequest, ServerResponse, UserRequest},
+    client::{GenerationResponseChunk, StreamProgress},
+    repl::{SLASH_COMMAND_HELP, ServerRequest, ServerResponse, SlashCommand, UserRequest},
+    transcript::Transcript,
 };
+use render::{OutputFormat, Renderer};
+use spinner::Spinner;
+
+/// How streaming a response from the server ended.
+enum StreamEnd {
+    /// The response finished or the user cancelled it.
+    Done,
+    /// The response was cut off partway through (e.g., the connection
+    /// dropped), so it might be resumable.
+    Interrupted(String),
+    /// The response can't be continued.
+    Failed(String),
+}
+
+/// Describe an unsuccessful response from the server.
+async fn describe_error_response(response: reqwest::Response) -> String {
+    match response.error_for_status() {
+        Ok(response) => {
+            let repr = format!("{response:?}");
+            let content = response
+                .text()
+                .await
+                .unwrap_or_else(|err| format!("{repr} with error {err:?}"));
+            format!("{repr} with content {content}")
+        }
+        Err(err) => format!("{err:?}"),
+    }
+}
+
+/// Server capabilities URL, assuming the assistant endpoint is at the
+/// server's `/assistant` path.
+fn capabilities_url(url: &str) -> String {
+    let url = url.trim_end_matches('/');
+    let base = url.strip_suffix("/assistant").unwrap_or(url);
+    format!("{base}/capabilities")
+}
+
+/// Describe the server's capabilities with one line per endpoint.
+fn describe_capabilities(capabilities: &Capabilities) -> String {
+    let dimensions = match capabilities.embedding_dimensions {
+        Some(dimensions) => format!("{dimensions}-dimensional embeddings"),
+        None => "no embeddings yet".to_string(),
+    };
+    let mut lines = vec![
+        format!("Server version {} ({dimensions})", capabilities.version),
+        format!("Domains: {}", capabilities.domains.join(", ")),
+    ];
+    let path_width = capabilities
+        .endpoints
+        .iter()
+        .map(|endpoint| endpoint.path.len())
+        .max()
+        .unwrap_or_default();
+    for endpoint in &capabilities.endpoints {
+        let inputs = match (endpoint.has_params, endpoint.has_body) {
+            (true, true) => " [params, body]",
+            (true, false) => " [params]",
+            (false, true) => " [body]",
+            (false, false) => "",
+        };
+        lines.push(format!(
+            "    {:<6} {:<path_width$}  {}{inputs}",
+            endpoint.method, endpoint.path, endpoint.summary
+        ));
+    }
+    lines.join("\n")
+}
+
+/// Get the server's capabilities, describing them or what went wrong.
+async fn fetch_capabilities(client: &reqwest::Client, url: &str) -> String {
+    let response = match client.get(url).send().await {
+        Ok(response) if response.status() == 200 => response,
+        Ok(response) => return describe_error_response(response).await,
+        Err(err) => return format!("{err:?}"),
+    };
+    match response.json::<Capabilities>().await {
+        Ok(capabilities) => describe_capabilities(&capabilities),
+        Err(err) => format!("{err:?}"),
+    }
+}
+
+/// Stream a response's chunks until it finishes, it's cancelled, or it fails,
+/// keeping track of how far it got so it can be resumed.
+async fn stream_response(
+    response: reqwest::Response,
+    idle_timeout: Duration,
+    progress: &mut StreamProgress,
+    rx: &mut Receiver<ServerRequest>,
+    tx: &Sender<ServerResponse>,
+) -> StreamEnd {
+    let stream = response.bytes_stream().map_err(std::io::Error::other);
+    let mut reader = StreamReader::new(stream);
+    let mut line = vec![];
+    loop {
+        line.clear();
+        tokio::select! {
+            result = tokio::time::timeout(idle_timeout, reader.read_until(b'\n', &mut line)) => {
+                let Ok(result) = result else {
+                    return StreamEnd::Interrupted(format!(
+                        "server didn't send a response chunk within the {}s idle timeout",
+                        idle_timeout.as_secs()
+                    ));
+                };
+                match result {
+                    // Lines cut off by the end of the stream aren't counted as
+                    // received so resuming picks them back up in full.
+                    Ok(_) if line.ends_with(b"\n") => progress.consume(&line),
+                    // This shouldn't get hit in a streaming response because streaming responses
+                    // end with the '[DONE]' string before returning no lines.
+                    Ok(_) => {
+                        return StreamEnd::Interrupted(
+                            "server response didn't end on [DONE]".to_string(),
+                        );
+                    }
+                    Err(err) => return StreamEnd::Interrupted(format!("{err:?}")),
+                }
+                let line = String::from_utf8_lossy(&line);
+                if let Some(data) = line.trim_end_matches(['\r', '\n']).strip_prefix("data: ") {
+                    match data {
+                        "[DONE]" => return StreamEnd::Done,
+                        "" => {}
+                        data => {
+                            let response = serde_json::from_str::<GenerationResponseChunk>(data);
+                            match response {
+                                Ok(chunk) => {
+                                    let message = ServerResponse::Chunk(chunk);
+                                    tx.send(message).await.expect("server response channel shouldn't be full");
+                                }
+                                Err(err) => return StreamEnd::Failed(format!("{err:?}")),
+                            }
+                        }
+                    }
+                }
+            }
+            Some(ServerRequest::Cancel) = rx.recv() => return StreamEnd::Done,
+        }
+    }
+}
 
 /// Loop for interacting with the server. Waits for a new message request,
 /// and, when one is received, attempts to stream the response in chunks
-/// until it finishes or an interrupt signal is caught.
+/// until it finishes or an interrupt signal is caught. The connect timeout
+/// bounds how long to wait for the server to start responding, while the
+/// idle timeout bounds how long to wait between response chunks. If the
+/// response is interrupted partway through, it's resumed once from where it
+/// left off so its chunks keep adding on to the same reply. Capabilities
+/// are fetched from the same server.
 async fn client(
     url: String,
-    timeout: Duration,
+    token: Option<String>,
+    connect_timeout: Duration,
+    idle_timeout: Duration,
     mut rx: Receiver<ServerRequest>,
     tx: Sender<ServerResponse>,
 ) {
+    let mut headers = HeaderMap::new();
+    if let Some(token) = token {
+        let mut authorization = HeaderValue::from_str(&format!("Bearer {token}"))
+            .expect("token should be a valid header value");
+        authorization.set_sensitive(true);
+        headers.insert(header::AUTHORIZATION, authorization);
+    }
     let client = reqwest::Client::builder()
-        .connect_timeout(timeout)
-        .http2_keep_alive_timeout(timeout)
-        .read_timeout(timeout)
+        .default_headers(headers)
+        .connect_timeout(connect_timeout)
+        .http2_keep_alive_timeout(idle_timeout)
         .build()
         .expect("shouldn't fail to build client");
+    let capabilities_url = capabilities_url(&url);
 
     loop {
-        if let Some(ServerRequest::Start(request)) = rx.recv().await {
-            tokio::select! {
-                response = client.post(&url).json(&request).send() => {
+        let mut request = match rx.recv().await {
+            Some(ServerRequest::Start(request)) => request,
+            Some(ServerRequest::Capabilities) => {
+                let description = fetch_capabilities(&client, &capabilities_url).await;
+                tx.send(ServerResponse::Capabilities(description))
+                    .await
+                    .expect("server response channel shouldn't be full");
+                continue;
+            }
+            _ => continue,
+        };
+        // Responses are always resumable so they can be picked back up if
+        // the connection drops partway through.
+        request.resumable = Some(true);
+        let mut progress = StreamProgress::default();
+        let mut resumed = false;
+        let message = loop {
+            let end = tokio::select! {
+                response = tokio::time::timeout(connect_timeout, client.post(&url).json(&request).send()) => {
                     match response {
-                        Err(err) => {
-                            let message = ServerResponse::Error(format!("{err:?}"));
-                            tx.send(message)
-                                .await
-                                .expect("server response channel shouldn't be full");
-                        }
-                        Ok(response) if response.status() == 200 => {
-                            let stream = response
-                                .bytes_stream()
-                                .map_err(std::io::Error::other);
-                            let reader = StreamReader::new(stream);
-                            let mut lines = reader.lines();
-                            loop {
-                                tokio::select! {
-                                    result = lines.next_line() => {
-                                        match result {
-                                            Ok(Some(line)) => {
-                                                if let Some(data) = line.strip_prefix("data: ") {
-                                                    match data {
-                                                        "[DONE]" => {
-                                                            let message = ServerResponse::Done;
-                                                            tx.send(message).await.expect("server response channel shouldn't be full");
-                                                            break
-                                                        }
-                                                        "\n" | "" => {}
-                                                        data => {
-                                                            let response = serde_json::from_str::<GenerationResponseChunk>(data);
-                                                            match response {
-                                                                Ok(chunk) => {
-                                                                    let message = ServerResponse::Chunk(chunk);
-                                                                    tx.send(message).await.expect("server response channel shouldn't be full");
-                                                                }
-                                                                Err(err) => {
-                                                                    let message = ServerResponse::Error(format!("{err:?}"));
-                                                                    tx.send(message).await.expect("server response channel shouldn't be full");
-                                                                    break
-                                                                }
-                                                            }
-                                                        }
-                                                    }
-                                                }
-                                            }
-                                            // This shouldn't get hit in a streaming response because streaming responses
-                                            // end with the '[DONE]' string before returning no lines.
-                                            Ok(None) => {
-                                                let message = ServerResponse::Error("server response didn't end on [DONE]".to_string());
-                                                tx.send(message).await.expect("server response channel shouldn't be full");
-                                                break
-                                            },
-                                            Err(err) => {
-                                                let message = ServerResponse::Error(format!("{err:?}"));
-                                                tx.send(message).await.expect("server response channel shouldn't be full");
-                                                break
-                                            },
-                                        }
-                                    }
-                                    Some(ServerRequest::Cancel) = rx.recv() => {
-                                        let message = ServerResponse::Done;
-                                        tx.send(message).await.expect("server response channel shouldn't be full");
-                                        break
-                                    }
-                                }
-                            }
-                        }
-                        Ok(response) => {
-                            let text = match response.error_for_status() {
-                                Ok(response) => {
-                                    let repr = format!("{response:?}");
-                                    let content = response
-                                        .text()
-                                        .await
-                                        .unwrap_or_else(|err| format!("{repr} with error {err:?}"));
-                                    format!("{repr} with content {content}")
-                                }
-                                Err(err) => format!("{err:?}"),
-                            };
-                            let message = ServerResponse::Error(text);
-                            tx.send(message)
-                                .await
-                                .expect("server response channel shouldn't be full");
+                        Err(_) => StreamEnd::Failed(format!(
+                            "server didn't respond within the {}s connect timeout",
+                            connect_timeout.as_secs()
+                        )),
+                        Ok(Err(err)) => StreamEnd::Failed(format!("{err:?}")),
+                        Ok(Ok(response)) if response.status() == 200 => {
+                            stream_response(response, idle_timeout, &mut progress, &mut rx, &tx).await
                         }
+                        Ok(Ok(response)) => StreamEnd::Failed(describe_error_response(response).await),
                     }
                 }
-                Some(ServerRequest::Cancel) = rx.recv() => {
-                    let message = ServerResponse::Done;
-                    tx.send(message).await.expect("server response channel shouldn't be full");
+                Some(ServerRequest::Cancel) = rx.recv() => StreamEnd::Done,
+            };
+            match end {
+                StreamEnd::Done => break ServerResponse::Done,
+                StreamEnd::Interrupted(err) => {
+                    if !resumed && let Some(resume_point) = progress.resume_point() {
+                        resumed = true;
+                        request.resume = Some(resume_point);
+                        continue;
+                    }
+                    break ServerResponse::Error(err);
                 }
+                StreamEnd::Failed(err) => break ServerResponse::Error(err),
             }
-        }
+        };
+        tx.send(message)
+            .await
+            .expect("server response channel shouldn't be full");
     }
 }
 
@@ -160,17 +273,14 @@ impl ConditionalEventHandler for InterruptEventHandler {
 /// using this loop. If a message is sent, a response is streamed from
 /// the server and this REPL is inactive until the response finishes or
 /// the stream is interrupted through the other CTRL+C handler.
-fn repl(mut rx: Receiver<()>, tx: &Sender<UserRequest>) -> Result<(), ReadlineError> {
+fn repl(
+    mut rx: Receiver<()>,
+    tx: &Sender<UserRequest>,
+    history_path: &Path,
+) -> Result<(), ReadlineError> {
     let mut rl = DefaultEditor::new()?;
-    let history_path = match dirs_next::home_dir() {
-        Some(mut dir) => {
-            dir.push(".toi_history");
-            dir
-        }
-        None => PathBuf::from(".toi_history"),
-    };
     let _ = rl.set_max_history_size(2048);
-    let _ = rl.load_history(&history_path);
+    let _ = rl.load_history(history_path);
     let interrupt_event_handler = Box::new(InterruptEventHandler);
     rl.bind_sequence(
         KeyEvent::ctrl('c'),
@@ -181,7 +291,10 @@ fn repl(mut rx: Receiver<()>, tx: &Sender<UserRequest>) -> Result<(), ReadlineEr
         loop {
             match rl.readline(">> ") {
                 Ok(input) => {
+                    // History is appended as it's entered so it isn't lost
+                    // if the client doesn't exit cleanly.
                     let _ = rl.add_history_entry(&input);
+                    let _ = rl.append_history(history_path);
                     let message = UserRequest::Prompt(input);
                     tx.blocking_send(message)
                         .expect("user request channel shouldn't be full");
@@ -190,8 +303,9 @@ fn repl(mut rx: Receiver<()>, tx: &Sender<UserRequest>) -> Result<(), ReadlineEr
                 Err(ReadlineError::Interrupted) => {
                     println!("^C");
                 }
+                // The main loop finishes writing any transcript entries
+                // before prompting again, so exiting here doesn't drop any.
                 Err(ReadlineError::Eof) => {
-                    let _ = rl.save_history(&history_path);
                     std::process::exit(0);
                 }
                 _ => {}
@@ -217,16 +331,29 @@ fn ctrlc_handler(tx: Sender<UserRequest>) -> Result<(), ctrlc::Error> {
 }
 
 /// History is used for maintaining a context limit. Context limit is
-/// set as a CLI option.
+/// set as a CLI option. Style instructions are sent along with every
+/// request but aren't part of the history itself, and an endpoint hint is
+/// only sent along with the next request.
 struct History {
     limit: u32,
     size: u32,
     buffer: Vec<String>,
-    messages: VecDeque<Message>,
+    messages: Conversation,
     usages: VecDeque<TokenUsage>,
+    style_instructions: Option<String>,
+    endpoint_hint: Option<String>,
+    temperature: Option<f32>,
+    max_tokens: Option<u32>,
 }
 
 impl History {
+    pub fn clear(&mut self) {
+        self.size = 0;
+        self.buffer.clear();
+        self.messages.clear();
+        self.usages.clear();
+    }
+
     pub fn len(&self) -> usize {
         self.messages.len()
     }
@@ -236,29 +363,37 @@ impl History {
             limit,
             size: 0,
             buffer: vec![],
-            messages: VecDeque::new(),
+            messages: Conversation::new(),
             usages: VecDeque::new(),
+            style_instructions: None,
+            endpoint_hint: None,
+            temperature: None,
+            max_tokens: None,
         }
     }
 
+    pub fn last(&self) -> Option<&Message> {
+        self.messages.last()
+    }
+
     pub fn pop_back(&mut self) {
-        self.messages.pop_back();
+        self.messages.pop();
     }
 
     pub fn push_assistant_and_token_usage(&mut self, usage: TokenUsage) {
-        let message = Message {
-            role: MessageRole::Assistant,
-            content: self.buffer.join(""),
-        };
         let total_usage = usage.prompt_tokens + usage.completion_tokens;
         self.size = self
             .size
             .checked_add_signed(total_usage)
             .expect("shouldn't overflow from adding token usage");
-        self.messages.push_back(message);
+        self.messages.push_assistant(self.buffer.join(""));
         self.usages.push_back(usage);
         self.buffer.clear();
+        self.prune();
+    }
 
+    /// Drop the oldest exchanges until the history fits within its limit.
+    fn prune(&mut self) {
         while self.size > self.limit {
             if let Some(usage) = self.usages.pop_front() {
                 let total_usage = usage.prompt_tokens + usage.completion_tokens;
@@ -266,36 +401,170 @@ impl History {
                     .size
                     .checked_add_signed(-total_usage)
                     .expect("shouldn't overflow from subbing token usage");
-                self.messages = self.messages.split_off(2);
+                let turns = self.messages.turns();
+                self.messages
+                    .truncate_to_last_n_turns(turns.saturating_sub(1));
             }
         }
     }
 
+    pub fn print(&self) {
+        // Each exchange is a user message followed by an assistant message,
+        // and each assistant message has its own token usage.
+        for (i, message) in self.messages.messages().iter().enumerate() {
+            match message.role {
+                MessageRole::Assistant => {
+                    let usage = self.usages.get(i / 2);
+                    match usage {
+                        Some(usage) => println!(
+                            "[assistant] ({} prompt + {} completion tokens) {}",
+                            usage.prompt_tokens, usage.completion_tokens, message.content
+                        ),
+                        None => println!("[assistant] {}", message.content),
+                    }
+                }
+                MessageRole::System => println!("[system] {}", message.content),
+                MessageRole::User => println!("[user] {}", message.content),
+            }
+        }
+        println!("{} of {} context tokens used", self.size, self.limit);
+    }
+
     pub fn push_assistant_chunk(&mut self, content: String) {
         self.buffer.push(content);
     }
 
+    /// Take whatever was streamed of a response that didn't finish so it
+    /// isn't mixed into the next response.
+    pub fn take_partial(&mut self) -> String {
+        let content = self.buffer.join("");
+        self.buffer.clear();
+        content
+    }
+
     pub fn push_user(&mut self, content: String) -> GenerationRequest {
-        let message = Message {
-            role: MessageRole::User,
-            content,
-        };
-        self.messages.push_back(message);
+        self.messages.push_user(content);
+        self.take_request()
+    }
+
+    /// Drop the last assistant response so the last user message can be
+    /// resent. Returns `None` if there's no complete exchange to retry.
+    pub fn retry(&mut self) -> Option<GenerationRequest> {
+        if self.len() < 2 || self.len() % 2 == 1 {
+            return None;
+        }
+        self.pop_back();
+        if let Some(usage) = self.usages.pop_back() {
+            let total_usage = usage.prompt_tokens + usage.completion_tokens;
+            self.size = self
+                .size
+                .checked_add_signed(-total_usage)
+                .expect("shouldn't overflow from subbing token usage");
+        }
+        Some(self.take_request())
+    }
+
+    pub fn set_limit(&mut self, limit: u32) {
+        self.limit = limit;
+        self.prune();
+    }
+
+    pub fn set_style_instructions(&mut self, style_instructions: Option<String>) {
+        self.style_instructions = style_instructions;
+    }
+
+    pub fn set_endpoint_hint(&mut self, endpoint_hint: String) {
+        self.endpoint_hint = Some(endpoint_hint);
+    }
+
+    pub fn set_sampling(&mut self, temperature: Option<f32>, max_tokens: Option<u32>) {
+        self.temperature = temperature;
+        self.max_tokens = max_tokens;
+    }
+
+    /// Make a request from the history, using up the endpoint hint since
+    /// it's only for the next request.
+    fn take_request(&mut self) -> GenerationRequest {
         GenerationRequest::builder()
             .messages(self.messages.clone().into())
+            .maybe_style_instructions(self.style_instructions.clone())
+            .maybe_endpoint_hint(self.endpoint_hint.take())
+            .maybe_temperature(self.temperature)
+            .maybe_max_tokens(self.max_tokens)
             .build()
     }
 }
 
+/// Handle a command locally, only returning a request if the command
+/// needs a new response from the server.
+fn handle_slash_command(history: &mut History, command: SlashCommand) -> Option<ServerRequest> {
+    match command {
+        SlashCommand::Capabilities => return Some(ServerRequest::Capabilities),
+        SlashCommand::Clear => history.clear(),
+        SlashCommand::Help => println!("{SLASH_COMMAND_HELP}"),
+        SlashCommand::History => history.print(),
+        SlashCommand::Limit(limit) => history.set_limit(limit),
+        SlashCommand::Retry => {
+            let request = history.retry();
+            if request.is_none() {
+                println!("Nothing to retry");
+            }
+            return request.map(ServerRequest::Start);
+        }
+        SlashCommand::System(style_instructions) => {
+            history.set_style_instructions(style_instructions);
+        }
+        SlashCommand::Use(endpoint_hint) => history.set_endpoint_hint(endpoint_hint),
+    }
+    None
+}
+
+/// Record a message in the transcript if there is one. Failing to write
+/// the transcript isn't worth ending the chat over, so errors are only
+/// printed.
+fn record(transcript: Option<&mut Transcript>, role: &MessageRole, content: &str, partial: bool) {
+    let Some(transcript) = transcript else {
+        return;
+    };
+    if let Err(err) = transcript.write(role, content, partial) {
+        eprintln!("Couldn't write to transcript: {err}");
+    }
+}
+
+/// Handle the end of a response stream, whether it finished, was cancelled
+/// by the user, or failed. If there's an odd number of messages, then the
+/// response never finished, so the user's message is dropped from the
+/// history and whatever was streamed is recorded as partial. Otherwise,
+/// the finished response is recorded.
+fn end_response(history: &mut History, transcript: Option<&mut Transcript>) {
+    let partial = history.take_partial();
+    if history.len() % 2 == 1 {
+        history.pop_back();
+        record(transcript, &MessageRole::Assistant, &partial, true);
+    } else if let Some(message) = history.last() {
+        record(transcript, &message.role, &message.content, false);
+    }
+}
+
 struct Args {
     url: String,
-    timeout: Duration,
+    token: Option<String>,
+    connect_timeout: Duration,
+    idle_timeout: Duration,
     context_limit: u32,
+    history_file: PathBuf,
+    transcript: Option<PathBuf>,
+    output: OutputFormat,
+    system: Option<String>,
+    temperature: Option<f32>,
+    max_tokens: Option<u32>,
 }
 
 const DEFAULT_SERVER_ASSISTANT_URL: &str = "http://127.0.0.1:6969/assistant";
-const DEFAULT_RESPONSE_TIMEOUT: u64 = 10;
+const DEFAULT_CONNECT_TIMEOUT: u64 = 10;
+const DEFAULT_IDLE_TIMEOUT: u64 = 60;
 const DEFAULT_CONTEXT_LIMIT: u32 = 4000;
+const DEFAULT_HISTORY_FILE: &str = ".toi_history";
 
 /// Minimal REPL
 #[tokio::main]
@@ -309,9 +578,21 @@ USAGE:
     toi_client [OPTIONS]
 
 OPTIONS:
-    --url       Server assistant URL    [default: {DEFAULT_SERVER_ASSISTANT_URL}]
-    --timeout   Server response timeout [default: {DEFAULT_RESPONSE_TIMEOUT}]
-    --limit     Chat context limit      [default: {DEFAULT_CONTEXT_LIMIT}]
+    --url              Server assistant URL            [default: {DEFAULT_SERVER_ASSISTANT_URL}]
+    --token            Server bearer token
+    --connect-timeout  Server first response timeout   [default: {DEFAULT_CONNECT_TIMEOUT}]
+    --idle-timeout     Server response chunk timeout   [default: {DEFAULT_IDLE_TIMEOUT}]
+    --limit            Chat context limit              [default: {DEFAULT_CONTEXT_LIMIT}]
+    --history-file     Input history file              [default: ~/{DEFAULT_HISTORY_FILE}]
+    --transcript       Chat transcript file, written as JSON lines if it ends
+                       in .jsonl and as plain text otherwise
+    --output           Response output format          [default: plain]
+                       (plain, markdown, or json)
+    --system           Style instructions for responses (e.g., be brief)
+    --temperature      Response sampling temperature from 0 to 2
+                       [default: the server's]
+    --max-tokens       Max number of tokens per response
+                       [default: the server's]
 
 FLAGS:
     -h, --help    Print help information"
@@ -326,19 +607,44 @@ FLAGS:
         url: pargs
             .value_from_str("--url")
             .unwrap_or(DEFAULT_SERVER_ASSISTANT_URL.into()),
-        timeout: pargs
-            .value_from_str("--timeout")
+        token: pargs.opt_value_from_str("--token")?,
+        connect_timeout: pargs
+            .value_from_str("--connect-timeout")
             .map(Duration::from_secs)
-            .unwrap_or(Duration::from_secs(DEFAULT_RESPONSE_TIMEOUT)),
+            .unwrap_or(Duration::from_secs(DEFAULT_CONNECT_TIMEOUT)),
+        idle_timeout: pargs
+            .value_from_str("--idle-timeout")
+            .map(Duration::from_secs)
+            .unwrap_or(Duration::from_secs(DEFAULT_IDLE_TIMEOUT)),
         context_limit: pargs
             .value_from_str("--limit")
             .unwrap_or(DEFAULT_CONTEXT_LIMIT),
+        history_file: pargs.value_from_str("--history-file").unwrap_or_else(|_| {
+            match dirs_next::home_dir() {
+                Some(dir) => dir.join(DEFAULT_HISTORY_FILE),
+                None => PathBuf::from(DEFAULT_HISTORY_FILE),
+            }
+        }),
+        transcript: pargs.opt_value_from_str("--transcript")?,
+        output: pargs.opt_value_from_str("--output")?.unwrap_or_default(),
+        system: pargs.opt_value_from_str("--system")?,
+        temperature: pargs.opt_value_from_str("--temperature")?,
+        max_tokens: pargs.opt_value_from_str("--max-tokens")?,
     };
     let Args {
         url,
-        timeout,
+        token,
+        connect_timeout,
+        idle_timeout,
         context_limit,
+        history_file,
+        transcript,
+        output,
+        system,
+        temperature,
+        max_tokens,
     } = args;
+    let mut transcript = transcript.map(|path| Transcript::open(&path)).transpose()?;
 
     // Channels for all the IPC going on.
     let (start_repl_sender, start_repl_receiver) = tokio::sync::mpsc::channel(1);
@@ -357,10 +663,12 @@ FLAGS:
     ) = tokio::sync::mpsc::channel(1);
 
     // Begin background processes.
-    thread::spawn(move || repl(start_repl_receiver, &user_request_sender));
+    thread::spawn(move || repl(start_repl_receiver, &user_request_sender, &history_file));
     tokio::spawn(client(
         url,
-        timeout,
+        token,
+        connect_timeout,
+        idle_timeout,
         server_request_receiver,
         server_response_sender,
     ));
@@ -372,23 +680,58 @@ FLAGS:
     // Main loop.
     let mut stdout = io::stdout();
     let mut history = History::new(context_limit);
+    history.set_style_instructions(system);
+    history.set_sampling(temperature, max_tokens);
+    let mut renderer = Renderer::new(output);
+
+    // A spinner fills the silence before a response starts, but it's only
+    // drawn for people watching a terminal.
+    let mut spinner = Spinner::new(stdout.is_terminal() && output != OutputFormat::Json);
+    let mut ticker = tokio::time::interval(spinner::TICK_INTERVAL);
+    ticker.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Skip);
     loop {
         tokio::select! {
             Some(user_request) = user_request_receiver.recv() => {
                 let server_request = match user_request {
-                    UserRequest::Prompt(input) => {
-                        let request = history.push_user(input);
-                        ServerRequest::Start(request)
-                    }
-                    UserRequest::Cancel => ServerRequest::Cancel
+                    UserRequest::Prompt(input) => match SlashCommand::parse(&input) {
+                        Some(command) => handle_slash_command(&mut history, command),
+                        None => {
+                            record(transcript.as_mut(), &MessageRole::User, &input, false);
+                            let request = history.push_user(input);
+                            Some(ServerRequest::Start(request))
+                        }
+                    },
+                    UserRequest::Cancel => Some(ServerRequest::Cancel)
                 };
-                server_request_sender.send(server_request).await?;
+                match &server_request {
+                    Some(ServerRequest::Start(_)) => spinner.start(Instant::now()),
+                    Some(ServerRequest::Cancel) => {
+                        print!("{}", spinner.stop());
+                        stdout.flush()?;
+                    }
+                    Some(ServerRequest::Capabilities) | None => {}
+                }
+                // Commands are handled locally, so the user is prompted again
+                // rather than waiting on the server.
+                match server_request {
+                    Some(server_request) => server_request_sender.send(server_request).await?,
+                    None => start_repl_sender.send(()).await?,
+                }
+            }
+            _ = ticker.tick(), if spinner.is_active() => {
+                if let Some(frame) = spinner.tick(Instant::now()) {
+                    print!("{frame}");
+                    stdout.flush()?;
+                }
             }
             Some(server_response) = server_response_receiver.recv() => {
+                // Anything from the server means the wait is over, so the
+                // spinner is erased before the response is printed over it.
+                print!("{}", spinner.stop());
                 match server_response {
                     ServerResponse::Chunk(chunk) => {
                         if let Some(choice) = chunk.choices.into_iter().next() {
-                            print!("{}", choice.delta.content);
+                            print!("{}", renderer.render(&choice.delta.content));
                             stdout.flush()?;
                             history.push_assistant_chunk(choice.delta.content);
                         }
@@ -402,10 +745,8 @@ FLAGS:
                         // an odd number of messages, then we know this edge case
                         // didn't occur, and that the user's message can be ignored
                         // from the history. Otherwise, keep the latest message.
-                        if history.len() % 2 == 1 {
-                            history.pop_back();
-                        }
-                        println!();
+                        end_response(&mut history, transcript.as_mut());
+                        println!("{}", renderer.finish());
                         start_repl_sender.send(()).await?;
                     },
                     ServerResponse::Error(err) => {
@@ -414,10 +755,12 @@ FLAGS:
                         // an odd number of messages, then we know this edge case
                         // occurred, and we don't want to pop the assistant's
                         // message.
-                        if history.len() % 2 == 1 {
-                            history.pop_back();
-                        }
-                        println!("{err}");
+                        end_response(&mut history, transcript.as_mut());
+                        println!("{}", renderer.error(&err));
+                        start_repl_sender.send(()).await?;
+                    }
+                    ServerResponse::Capabilities(description) => {
+                        println!("{description}");
                         start_repl_sender.send(()).await?;
                     }
                 }
@@ -428,8 +771,10 @@ FLAGS:
 
 #[cfg(test)]
 mod tests {
-    use super::History;
+    use toi::{Capabilities, Capability};
+
     use super::models::client::TokenUsage;
+    use super::{History, capabilities_url, describe_capabilities, end_response};
 
     #[test]
     fn pruning_history() {
@@ -468,4 +813,154 @@ mod tests {
         assert_eq!(history.len(), 2);
         assert_eq!(history.size, 3);
     }
+
+    #[test]
+    fn retrying_history() {
+        let mut history = History::new(100);
+
+        // Nothing to retry without a complete exchange.
+        assert!(history.retry().is_none());
+        history.push_user("Tell me a joke".to_string());
+        assert!(history.retry().is_none());
+
+        // Finish the exchange, and then retry it. Only the user message
+        // should remain, and its token usage should be forgotten.
+        history.push_assistant_chunk("No".to_string());
+        history.push_assistant_and_token_usage(TokenUsage {
+            prompt_tokens: 4,
+            completion_tokens: 1,
+        });
+        assert_eq!(history.len(), 2);
+        let request = history.retry().expect("should have an exchange to retry");
+        assert_eq!(request.messages.len(), 1);
+        assert_eq!(request.messages[0].content, "Tell me a joke");
+        assert_eq!(history.len(), 1);
+        assert_eq!(history.size, 0);
+
+        // Cancelling the retried request partway through pops the dangling
+        // user message just like a regular request, and the partial response
+        // isn't carried over to the next one.
+        history.push_assistant_chunk("Knock".to_string());
+        assert_eq!(history.take_partial(), "Knock");
+        assert_eq!(history.take_partial(), "");
+        history.pop_back();
+        assert_eq!(history.len(), 0);
+        assert!(history.retry().is_none());
+    }
+
+    #[test]
+    fn sending_style_instructions() {
+        let mut history = History::new(100);
+        let request = history.push_user("Hi".to_string());
+        assert_eq!(request.style_instructions, None);
+
+        // Style instructions are sent with every request until they're
+        // cleared, but they aren't part of the history.
+        history.set_style_instructions(Some("be brief".to_string()));
+        let request = history.push_user("Hi again".to_string());
+        assert_eq!(request.style_instructions.as_deref(), Some("be brief"));
+        assert_eq!(history.len(), 2);
+        history.set_style_instructions(None);
+        let request = history.push_user("Bye".to_string());
+        assert_eq!(request.style_instructions, None);
+    }
+
+    #[test]
+    fn finding_capabilities() {
+        for url in [
+            "http://127.0.0.1:6969/assistant",
+            "http://127.0.0.1:6969/assistant/",
+        ] {
+            assert_eq!(capabilities_url(url), "http://127.0.0.1:6969/capabilities");
+        }
+    }
+
+    #[test]
+    fn describing_capabilities() {
+        let capabilities = Capabilities {
+            version: "0.1.1".to_string(),
+            domains: vec!["notes".to_string()],
+            embedding_dimensions: Some(3),
+            endpoints: vec![
+                Capability {
+                    path: "/notes".to_string(),
+                    method: "POST".to_string(),
+                    summary: "Add a note".to_string(),
+                    has_params: false,
+                    has_body: true,
+                },
+                Capability {
+                    path: "/notes/search".to_string(),
+                    method: "GET".to_string(),
+                    summary: "Search notes".to_string(),
+                    has_params: true,
+                    has_body: false,
+                },
+            ],
+        };
+        assert_eq!(
+            describe_capabilities(&capabilities),
+            [
+                "Server version 0.1.1 (3-dimensional embeddings)",
+                "Domains: notes",
+                "    POST   /notes         Add a note [body]",
+                "    GET    /notes/search  Search notes [params]",
+            ]
+            .join("\n")
+        );
+    }
+
+    #[test]
+    fn sending_endpoint_hints() {
+        let mut history = History::new(100);
+        history.set_endpoint_hint("/recipes".to_string());
+        let request = history.push_user("Note down this recipe".to_string());
+        assert_eq!(request.endpoint_hint.as_deref(), Some("/recipes"));
+
+        // The hint is only sent with the next message.
+        let request = history.push_user("Thanks".to_string());
+        assert_eq!(request.endpoint_hint, None);
+    }
+
+    #[test]
+    fn sending_sampling_parameters() {
+        let mut history = History::new(100);
+        let request = history.push_user("Tell me a story".to_string());
+        assert_eq!(request.temperature, None);
+        assert_eq!(request.max_tokens, None);
+
+        // Sampling parameters are sent with every message.
+        history.set_sampling(Some(1.2), Some(256));
+        for content in ["Tell me another story", "And another"] {
+            let request = history.push_user(content.to_string());
+            assert_eq!(request.temperature, Some(1.2));
+            assert_eq!(request.max_tokens, Some(256));
+        }
+    }
+
+    #[test]
+    fn discarding_partial_responses() {
+        let mut history = History::new(10);
+
+        // Simulate a response that stops partway through, like when the idle
+        // timeout fires between chunks.
+        history.push_user("Tell me a story".to_string());
+        history.push_assistant_chunk("Once upon".to_string());
+        end_response(&mut history, None);
+
+        // The unanswered user message and partial response are discarded so
+        // the next response starts clean.
+        assert_eq!(history.len(), 0);
+        assert!(history.buffer.is_empty());
+        history.push_user("Tell me a joke".to_string());
+        history.push_assistant_chunk("Knock knock".to_string());
+        history.push_assistant_and_token_usage(TokenUsage {
+            prompt_tokens: 3,
+            completion_tokens: 2,
+        });
+        assert_eq!(
+            history.last().map(|message| message.content.as_str()),
+            Some("Knock knock")
+        );
+    }
 }
@@ -1,4 +1,5 @@
 use serde::Deserialize;
+use toi::ResumePoint;
 
 #[derive(Deserialize)]
 pub struct TokenUsage {
@@ -21,3 +22,124 @@ pub struct GenerationResponseChunk {
     pub choices: Vec<StreamingChoice>,
     pub usage: Option<TokenUsage>,
 }
+
+/// How far a response stream got, for resuming it from where it left off if
+/// it's interrupted.
+#[derive(Default)]
+pub struct StreamProgress {
+    token: Option<String>,
+    offset: usize,
+}
+
+impl StreamProgress {
+    /// Count a complete line of the response stream (including its line
+    /// ending) as received, remembering the resume token if the line is a
+    /// checkpoint.
+    pub fn consume(&mut self, line: &[u8]) {
+        self.offset += line.len();
+        if let Some(resume_point) = ResumePoint::from_checkpoint(&String::from_utf8_lossy(line)) {
+            self.token = Some(resume_point.token);
+        }
+    }
+
+    /// Where to resume the response stream from, if the server sent a
+    /// checkpoint for it.
+    #[must_use]
+    pub fn resume_point(&self) -> Option<ResumePoint> {
+        self.token.clone().map(|token| ResumePoint {
+            token,
+            offset: self.offset,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use toi::ResumePoint;
+
+    use super::StreamProgress;
+
+    fn content_of(line: &[u8]) -> Option<String> {
+        let line = String::from_utf8_lossy(line);
+        let data = line.trim_end().strip_prefix("data: ")?;
+        let chunk: serde_json::Value = serde_json::from_str(data).ok()?;
+        chunk["choices"][0]["delta"]["content"]
+            .as_str()
+            .map(str::to_string)
+    }
+
+    fn stream() -> Vec<u8> {
+        let checkpoint = ResumePoint {
+            token: "abc".to_string(),
+            offset: 0,
+        }
+        .to_checkpoint();
+        let mut stream = checkpoint.into_bytes();
+        for content in ["Hello", ", ", "world", "!"] {
+            let data =
+                format!("data: {{\"choices\":[{{\"delta\":{{\"content\":\"{content}\"}}}}]}}\n\n");
+            stream.extend_from_slice(data.as_bytes());
+        }
+        stream.extend_from_slice(b"data: [DONE]\n\n");
+        stream
+    }
+
+    #[test]
+    fn splicing_resumed_streams() {
+        let stream = stream();
+
+        // The connection drops partway through a line, so only the complete
+        // lines before it count as received.
+        let cutoff = stream
+            .windows(5)
+            .position(|window| window == b"world")
+            .expect("stream should have content");
+        let mut progress = StreamProgress::default();
+        let mut buffer = String::new();
+        for line in stream[..cutoff].split_inclusive(|byte| *byte == b'\n') {
+            if !line.ends_with(b"\n") {
+                break;
+            }
+            progress.consume(line);
+            buffer.extend(content_of(line));
+        }
+        assert_eq!(buffer, "Hello, ");
+
+        // Resuming replays everything after the offset, which completes the
+        // buffer without repeating anything.
+        let resume_point = progress.resume_point().expect("stream has a checkpoint");
+        assert_eq!(resume_point.token, "abc");
+        for line in stream[resume_point.offset..].split_inclusive(|byte| *byte == b'\n') {
+            progress.consume(line);
+            buffer.extend(content_of(line));
+        }
+        assert_eq!(buffer, "Hello, world!");
+        assert_eq!(
+            progress.resume_point().map(|point| point.offset),
+            Some(stream.len())
+        );
+    }
+
+    #[test]
+    fn parsing_checkpoints() {
+        let resume_point = ResumePoint {
+            token: "abc".to_string(),
+            offset: 42,
+        };
+        let checkpoint = resume_point.to_checkpoint();
+        assert_eq!(checkpoint, ": checkpoint token=abc offset=42\n\n");
+        assert_eq!(
+            ResumePoint::from_checkpoint(checkpoint.lines().next().unwrap_or_default()),
+            Some(resume_point)
+        );
+        assert_eq!(ResumePoint::from_checkpoint("data: [DONE]"), None);
+        assert_eq!(ResumePoint::from_checkpoint(": keep-alive"), None);
+    }
+
+    #[test]
+    fn streams_without_checkpoints() {
+        let mut progress = StreamProgress::default();
+        progress.consume(b"data: [DONE]\n");
+        assert!(progress.resume_point().is_none());
+    }
+}
@@ -9,10 +9,128 @@ pub enum UserRequest {
 pub enum ServerRequest {
     Start(GenerationRequest),
     Cancel,
+    Capabilities,
 }
 
 pub enum ServerResponse {
     Chunk(GenerationResponseChunk),
     Done,
     Error(String),
+    Capabilities(String),
+}
+
+pub const SLASH_COMMAND_HELP: &str = r"Commands:
+    /capabilities
+                Print the endpoints the assistant can use
+    /clear      Clear the chat history
+    /history    Print the chat history and its token usage
+    /limit N    Set the chat context limit to N tokens
+    /retry      Resend the last message for a new response
+    /system     Set style instructions for responses (e.g., /system be
+                brief), or clear them if none are given
+    /use PATH   Only consider endpoints under PATH (e.g., /use /recipes)
+                for the next message
+    /help       Print this help message";
+
+/// Commands handled by the client rather than sent to the server.
+#[derive(Debug, PartialEq)]
+pub enum SlashCommand {
+    Capabilities,
+    Clear,
+    Help,
+    History,
+    Limit(u32),
+    Retry,
+    System(Option<String>),
+    Use(String),
+}
+
+impl SlashCommand {
+    /// Parse user input into a command. Returns `None` if the input isn't
+    /// a command and should be sent to the server instead. Unknown or
+    /// malformed commands parse as `Help`.
+    pub fn parse(input: &str) -> Option<Self> {
+        let input = input.trim();
+        let command = input.strip_prefix('/')?;
+
+        // Style instructions are free-form, so they're kept as-is.
+        if let Some(instructions) = command.strip_prefix("system") {
+            if instructions.is_empty() {
+                return Some(Self::System(None));
+            }
+            if instructions.starts_with(char::is_whitespace) {
+                return Some(Self::System(Some(instructions.trim().to_string())));
+            }
+        }
+
+        let mut parts = command.split_whitespace();
+        let command = match (parts.next(), parts.next(), parts.next()) {
+            (Some("capabilities"), None, None) => Self::Capabilities,
+            (Some("clear"), None, None) => Self::Clear,
+            (Some("history"), None, None) => Self::History,
+            (Some("limit"), Some(limit), None) => match limit.parse() {
+                Ok(limit) => Self::Limit(limit),
+                Err(_) => Self::Help,
+            },
+            (Some("retry"), None, None) => Self::Retry,
+            (Some("use"), Some(endpoint_hint), None) => Self::Use(endpoint_hint.to_string()),
+            _ => Self::Help,
+        };
+        Some(command)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::SlashCommand;
+
+    #[test]
+    fn parsing_slash_commands() {
+        assert_eq!(SlashCommand::parse("hello"), None);
+        assert_eq!(SlashCommand::parse("what's 1/2?"), None);
+        assert_eq!(
+            SlashCommand::parse("/capabilities"),
+            Some(SlashCommand::Capabilities)
+        );
+        assert_eq!(SlashCommand::parse("/clear"), Some(SlashCommand::Clear));
+        assert_eq!(
+            SlashCommand::parse(" /history "),
+            Some(SlashCommand::History)
+        );
+        assert_eq!(
+            SlashCommand::parse("/limit 2000"),
+            Some(SlashCommand::Limit(2000))
+        );
+        assert_eq!(SlashCommand::parse("/retry"), Some(SlashCommand::Retry));
+        assert_eq!(SlashCommand::parse("/help"), Some(SlashCommand::Help));
+        assert_eq!(
+            SlashCommand::parse("/system talk like a pirate"),
+            Some(SlashCommand::System(Some("talk like a pirate".to_string())))
+        );
+        assert_eq!(
+            SlashCommand::parse("/system"),
+            Some(SlashCommand::System(None))
+        );
+        assert_eq!(
+            SlashCommand::parse("/use /recipes"),
+            Some(SlashCommand::Use("/recipes".to_string()))
+        );
+
+        // Unknown and malformed commands fall back to help.
+        assert_eq!(SlashCommand::parse("/"), Some(SlashCommand::Help));
+        assert_eq!(SlashCommand::parse("/foo"), Some(SlashCommand::Help));
+        assert_eq!(SlashCommand::parse("/limit"), Some(SlashCommand::Help));
+        assert_eq!(SlashCommand::parse("/limit -1"), Some(SlashCommand::Help));
+        assert_eq!(SlashCommand::parse("/clear all"), Some(SlashCommand::Help));
+        assert_eq!(
+            SlashCommand::parse("/capabilities notes"),
+            Some(SlashCommand::Help)
+        );
+        assert_eq!(SlashCommand::parse("/systems"), Some(SlashCommand::Help));
+        assert_eq!(SlashCommand::parse("/use"), Some(SlashCommand::Help));
+        assert_eq!(
+            SlashCommand::parse("/use /recipes /notes"),
+            Some(SlashCommand::Help)
+        );
+    }
 }
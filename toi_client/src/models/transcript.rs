@@ -0,0 +1,151 @@
+use chrono::{SecondsFormat, Utc};
+use serde::Serialize;
+use std::fs::{File, OpenOptions};
+use std::io::{self, Write};
+use std::path::Path;
+use toi::MessageRole;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum TranscriptFormat {
+    Jsonl,
+    Text,
+}
+
+impl TranscriptFormat {
+    /// Transcripts are written as JSON lines if their file has a `.jsonl`
+    /// extension, and as plain text otherwise.
+    pub fn from_path(path: &Path) -> Self {
+        match path.extension().and_then(|ext| ext.to_str()) {
+            Some("jsonl") => Self::Jsonl,
+            _ => Self::Text,
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct TranscriptEntry<'a> {
+    timestamp: &'a str,
+    role: &'a MessageRole,
+    content: &'a str,
+    partial: bool,
+}
+
+/// Format a message as a single transcript entry. Partial entries are
+/// assistant responses that were cancelled or failed before finishing.
+fn format_entry(
+    format: TranscriptFormat,
+    timestamp: &str,
+    role: &MessageRole,
+    content: &str,
+    partial: bool,
+) -> Result<String, serde_json::Error> {
+    match format {
+        TranscriptFormat::Jsonl => {
+            let entry = TranscriptEntry {
+                timestamp,
+                role,
+                content,
+                partial,
+            };
+            let mut line = serde_json::to_string(&entry)?;
+            line.push('\n');
+            Ok(line)
+        }
+        TranscriptFormat::Text => {
+            let role = match role {
+                MessageRole::Assistant => "assistant",
+                MessageRole::System => "system",
+                MessageRole::User => "user",
+            };
+            let marker = if partial { " (cancelled)" } else { "" };
+            Ok(format!("[{timestamp}] [{role}]{marker} {content}\n"))
+        }
+    }
+}
+
+/// Appends user prompts and assistant responses to a file so past
+/// conversations can be looked back on.
+pub struct Transcript {
+    file: File,
+    format: TranscriptFormat,
+}
+
+impl Transcript {
+    pub fn open(path: &Path) -> io::Result<Self> {
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        Ok(Self {
+            file,
+            format: TranscriptFormat::from_path(path),
+        })
+    }
+
+    /// Write and flush a whole entry at once so an exit right after never
+    /// leaves a half-written line behind.
+    pub fn write(&mut self, role: &MessageRole, content: &str, partial: bool) -> io::Result<()> {
+        let timestamp = Utc::now().to_rfc3339_opts(SecondsFormat::Secs, true);
+        let entry = format_entry(self.format, &timestamp, role, content, partial)?;
+        self.file.write_all(entry.as_bytes())?;
+        self.file.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{TranscriptFormat, format_entry};
+    use std::path::Path;
+    use toi::MessageRole;
+
+    #[test]
+    fn formatting_transcript_entries() -> Result<(), serde_json::Error> {
+        assert_eq!(
+            TranscriptFormat::from_path(Path::new("chat.jsonl")),
+            TranscriptFormat::Jsonl
+        );
+        assert_eq!(
+            TranscriptFormat::from_path(Path::new("chat.txt")),
+            TranscriptFormat::Text
+        );
+        assert_eq!(
+            TranscriptFormat::from_path(Path::new("chat")),
+            TranscriptFormat::Text
+        );
+
+        let timestamp = "2025-06-01T12:00:00Z";
+        assert_eq!(
+            format_entry(
+                TranscriptFormat::Text,
+                timestamp,
+                &MessageRole::User,
+                "Hello!",
+                false
+            )?,
+            "[2025-06-01T12:00:00Z] [user] Hello!\n"
+        );
+        assert_eq!(
+            format_entry(
+                TranscriptFormat::Text,
+                timestamp,
+                &MessageRole::Assistant,
+                "Hi",
+                true
+            )?,
+            "[2025-06-01T12:00:00Z] [assistant] (cancelled) Hi\n"
+        );
+
+        // Newlines in content are escaped so each entry stays on one line.
+        let line = format_entry(
+            TranscriptFormat::Jsonl,
+            timestamp,
+            &MessageRole::Assistant,
+            "One\nTwo",
+            true,
+        )?;
+        assert_eq!(line.lines().count(), 1);
+        let entry: serde_json::Value = serde_json::from_str(&line)?;
+        assert_eq!(entry["timestamp"], timestamp);
+        assert_eq!(entry["role"], "assistant");
+        assert_eq!(entry["content"], "One\nTwo");
+        assert_eq!(entry["partial"], true);
+        Ok(())
+    }
+}
@@ -0,0 +1,333 @@
+use serde::Serialize;
+use std::str::FromStr;
+
+const RESET: &str = "\x1b[0m";
+
+/// How assistant responses are written to the terminal.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum OutputFormat {
+    /// Responses are printed as-is.
+    #[default]
+    Plain,
+    /// Markdown in responses is styled with ANSI escape codes.
+    Markdown,
+    /// Each response chunk is printed as a JSON object on its own line.
+    Json,
+}
+
+impl FromStr for OutputFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "plain" => Ok(Self::Plain),
+            "markdown" => Ok(Self::Markdown),
+            "json" => Ok(Self::Json),
+            other => Err(format!(
+                "unknown output format '{other}', expected plain, markdown, or json"
+            )),
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct OutputChunk<'a> {
+    content: &'a str,
+}
+
+#[derive(Serialize)]
+struct OutputError<'a> {
+    error: &'a str,
+}
+
+/// Whether `line` could still turn into `token` once more of it streams in.
+fn could_start(line: &[char], token: &str) -> bool {
+    line.len() < token.chars().count() && token.chars().zip(line).all(|(a, b)| a == *b)
+}
+
+fn starts_with(line: &[char], token: &str) -> bool {
+    line.len() >= token.chars().count() && token.chars().zip(line).all(|(a, b)| a == *b)
+}
+
+/// Streaming markdown renderer. Text is written as soon as it can't change
+/// meaning, so only the start of a line (which could be a header, list item,
+/// or code fence) or a lone `*` (which could start bold text) is held back
+/// until the next chunk arrives.
+#[derive(Default)]
+struct MarkdownRenderer {
+    pending: Vec<char>,
+    mid_line: bool,
+    in_fence: bool,
+    in_header: bool,
+    in_bold: bool,
+    in_code: bool,
+}
+
+impl MarkdownRenderer {
+    /// Escape codes for resetting to whatever styles are currently active.
+    fn style(&self) -> String {
+        let mut codes = vec![];
+        if self.in_header || self.in_bold {
+            codes.push("1");
+        }
+        if self.in_header {
+            codes.push("4");
+        }
+        if self.in_fence || self.in_code {
+            codes.push("36");
+        }
+        if codes.is_empty() {
+            RESET.to_string()
+        } else {
+            format!("{RESET}\x1b[{}m", codes.join(";"))
+        }
+    }
+
+    /// Render the start of a line, returning how many characters were
+    /// consumed or `None` if more are needed to tell what the line is.
+    fn render_line_start(&mut self, rest: &[char], output: &mut String) -> Option<usize> {
+        let indent = rest
+            .iter()
+            .take_while(|c| **c == ' ' || **c == '\t')
+            .count();
+        let line = &rest[indent..];
+        if line.is_empty() || could_start(line, "```") {
+            return None;
+        }
+
+        // Fence lines aren't printed, only the code between them.
+        if starts_with(line, "```") {
+            let end = line.iter().position(|c| *c == '\n')?;
+            self.in_fence = !self.in_fence;
+            output.push_str(&self.style());
+            return Some(indent + end + 1);
+        }
+
+        if self.in_fence {
+            output.extend(&rest[..indent]);
+            self.mid_line = true;
+            return Some(indent);
+        }
+
+        // Headers and list items are only told apart from regular text by
+        // the character after their marker.
+        let level = line.iter().take_while(|c| **c == '#').count();
+        let (prefix, consumed) = if level > 0 {
+            match line.get(level) {
+                None => return None,
+                Some(' ') => {
+                    self.in_header = true;
+                    (self.style(), level + 1)
+                }
+                Some(_) => (String::new(), 0),
+            }
+        } else {
+            match (line[0], line.get(1)) {
+                ('-' | '*' | '+', None) => return None,
+                ('-' | '*' | '+', Some(' ')) => ("• ".to_string(), 2),
+                _ => (String::new(), 0),
+            }
+        };
+        output.extend(&rest[..indent]);
+        output.push_str(&prefix);
+        self.mid_line = true;
+        Some(indent + consumed)
+    }
+
+    /// Render the middle of a line, returning how many characters were
+    /// consumed or `None` if more are needed.
+    fn render_inline(&mut self, rest: &[char], output: &mut String) -> Option<usize> {
+        match rest[0] {
+            '\n' => {
+                // Inline styles don't carry over to the next line.
+                if self.in_header || self.in_bold || self.in_code {
+                    self.in_header = false;
+                    self.in_bold = false;
+                    self.in_code = false;
+                    output.push_str(&self.style());
+                }
+                output.push('\n');
+                self.mid_line = false;
+                Some(1)
+            }
+            c if self.in_fence => {
+                output.push(c);
+                Some(1)
+            }
+            '`' => {
+                self.in_code = !self.in_code;
+                output.push_str(&self.style());
+                Some(1)
+            }
+            c if self.in_code => {
+                output.push(c);
+                Some(1)
+            }
+            '*' => match rest.get(1) {
+                None => None,
+                Some('*') => {
+                    self.in_bold = !self.in_bold;
+                    output.push_str(&self.style());
+                    Some(2)
+                }
+                Some(_) => {
+                    output.push('*');
+                    Some(1)
+                }
+            },
+            c => {
+                output.push(c);
+                Some(1)
+            }
+        }
+    }
+
+    fn push(&mut self, chunk: &str) -> String {
+        let mut pending = std::mem::take(&mut self.pending);
+        pending.extend(chunk.chars());
+        let mut output = String::new();
+        let mut i = 0;
+        while i < pending.len() {
+            let rest = &pending[i..];
+            let consumed = if self.mid_line {
+                self.render_inline(rest, &mut output)
+            } else {
+                self.render_line_start(rest, &mut output)
+            };
+            match consumed {
+                Some(consumed) => i += consumed,
+                None => break,
+            }
+        }
+        self.pending = pending.split_off(i);
+        output
+    }
+
+    /// Write whatever's still held back as-is and reset styles for the
+    /// next response.
+    fn finish(&mut self) -> String {
+        let mut output: String = self.pending.drain(..).collect();
+        if self.in_fence || self.in_header || self.in_bold || self.in_code {
+            output.push_str(RESET);
+        }
+        *self = Self::default();
+        output
+    }
+}
+
+/// Formats response chunks for the terminal as they stream in.
+pub struct Renderer {
+    format: OutputFormat,
+    markdown: MarkdownRenderer,
+}
+
+impl Renderer {
+    pub fn new(format: OutputFormat) -> Self {
+        Self {
+            format,
+            markdown: MarkdownRenderer::default(),
+        }
+    }
+
+    /// Format a response chunk.
+    pub fn render(&mut self, content: &str) -> String {
+        match self.format {
+            OutputFormat::Plain => content.to_string(),
+            OutputFormat::Markdown => self.markdown.push(content),
+            OutputFormat::Json => {
+                let chunk = OutputChunk { content };
+                let chunk = serde_json::to_string(&chunk).expect("chunk should serialize");
+                format!("{chunk}\n")
+            }
+        }
+    }
+
+    /// Format whatever's left at the end of a response.
+    pub fn finish(&mut self) -> String {
+        match self.format {
+            OutputFormat::Plain | OutputFormat::Json => String::new(),
+            OutputFormat::Markdown => self.markdown.finish(),
+        }
+    }
+
+    /// Format an error that ended a response.
+    pub fn error(&mut self, err: &str) -> String {
+        match self.format {
+            OutputFormat::Plain | OutputFormat::Markdown => format!("{}{err}", self.finish()),
+            OutputFormat::Json => {
+                let error = OutputError { error: err };
+                serde_json::to_string(&error).expect("error should serialize")
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{OutputFormat, Renderer};
+
+    fn render_markdown(chunks: &[&str]) -> String {
+        let mut renderer = Renderer::new(OutputFormat::Markdown);
+        let mut output: String = chunks.iter().map(|chunk| renderer.render(chunk)).collect();
+        output.push_str(&renderer.finish());
+        output
+    }
+
+    #[test]
+    fn parsing_output_formats() {
+        assert_eq!("plain".parse(), Ok(OutputFormat::Plain));
+        assert_eq!("markdown".parse(), Ok(OutputFormat::Markdown));
+        assert_eq!("json".parse(), Ok(OutputFormat::Json));
+        assert!("yaml".parse::<OutputFormat>().is_err());
+    }
+
+    #[test]
+    fn rendering_fences_split_across_chunks() {
+        // The fence opens and closes across several chunks, and markdown
+        // inside the fence is left alone.
+        let output = render_markdown(&[
+            "Here:\n`",
+            "``rust\nlet x = **1**;\n",
+            "`",
+            "``\nDone **bold**",
+        ]);
+        assert_eq!(
+            output,
+            "Here:\n\x1b[0m\x1b[36mlet x = **1**;\n\x1b[0mDone \x1b[0m\x1b[1mbold\x1b[0m"
+        );
+    }
+
+    #[test]
+    fn rendering_tokens_split_across_chunks() {
+        let output = render_markdown(&["# Ti", "tle\n-", " item **a", "*", "* and `co", "de`\n"]);
+        assert_eq!(
+            output,
+            "\x1b[0m\x1b[1;4mTitle\x1b[0m\n• item \x1b[0m\x1b[1ma\x1b[0m and \x1b[0m\x1b[36mcode\x1b[0m\n"
+        );
+    }
+
+    #[test]
+    fn rendering_unfinished_markdown() {
+        // Anything held back is written as-is, and styles are reset.
+        assert_eq!(render_markdown(&["a *"]), "a *");
+        assert_eq!(render_markdown(&["  #"]), "  #");
+        assert_eq!(
+            render_markdown(&["```", "py\nprint(1)"]),
+            "\x1b[0m\x1b[36mprint(1)\x1b[0m"
+        );
+    }
+
+    #[test]
+    fn rendering_plain_and_json() {
+        let mut renderer = Renderer::new(OutputFormat::Plain);
+        assert_eq!(renderer.render("**hi**"), "**hi**");
+        assert_eq!(renderer.finish(), "");
+
+        let mut renderer = Renderer::new(OutputFormat::Json);
+        assert_eq!(
+            renderer.render("say \"hi\""),
+            "{\"content\":\"say \\\"hi\\\"\"}\n"
+        );
+        assert_eq!(renderer.error("oops"), "{\"error\":\"oops\"}");
+    }
+}
@@ -0,0 +1,120 @@
+use std::time::{Duration, Instant};
+
+/// Escape codes for moving to the start of the line and clearing it.
+const CLEAR_LINE: &str = "\r\x1b[2K";
+const FRAMES: [char; 4] = ['|', '/', '-', '\\'];
+
+/// How often the spinner is redrawn.
+pub const TICK_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Spinner with the number of seconds spent waiting for a response to
+/// start. It's only active between sending a request and getting anything
+/// back (or cancelling), and it's only drawn on the current line so it can
+/// be erased before the response is printed there instead.
+pub struct Spinner {
+    enabled: bool,
+    started_at: Option<Instant>,
+    frame: usize,
+    drawn: bool,
+}
+
+impl Spinner {
+    /// Make a spinner that's only ever drawn if it's enabled (e.g., when
+    /// output goes to a terminal rather than a pipe).
+    pub fn new(enabled: bool) -> Self {
+        Self {
+            enabled,
+            started_at: None,
+            frame: 0,
+            drawn: false,
+        }
+    }
+
+    pub fn is_active(&self) -> bool {
+        self.started_at.is_some()
+    }
+
+    /// Start waiting for a response.
+    pub fn start(&mut self, now: Instant) {
+        if self.enabled {
+            self.started_at = Some(now);
+            self.frame = 0;
+        }
+    }
+
+    /// Text for drawing the next frame over the current line, or `None` if
+    /// nothing is being waited on.
+    pub fn tick(&mut self, now: Instant) -> Option<String> {
+        let started_at = self.started_at?;
+        let frame = FRAMES[self.frame % FRAMES.len()];
+        self.frame += 1;
+        self.drawn = true;
+        let elapsed = now.saturating_duration_since(started_at).as_secs();
+        Some(format!("{CLEAR_LINE}{frame} {elapsed}s"))
+    }
+
+    /// Stop waiting, returning the text for erasing the spinner so whatever
+    /// is printed next starts at the beginning of the line. Nothing needs
+    /// erasing if the spinner was never drawn.
+    pub fn stop(&mut self) -> &'static str {
+        self.started_at = None;
+        if std::mem::take(&mut self.drawn) {
+            CLEAR_LINE
+        } else {
+            ""
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn spinning_while_waiting() {
+        let mut spinner = Spinner::new(true);
+        let now = Instant::now();
+        assert!(!spinner.is_active());
+        assert_eq!(spinner.tick(now), None);
+
+        // Frames cycle and show the number of seconds spent waiting.
+        spinner.start(now);
+        assert!(spinner.is_active());
+        assert_eq!(spinner.tick(now).as_deref(), Some("\r\x1b[2K| 0s"));
+        let later = now + Duration::from_millis(2500);
+        assert_eq!(spinner.tick(later).as_deref(), Some("\r\x1b[2K/ 2s"));
+
+        // The first chunk stops the spinner and erases it.
+        assert_eq!(spinner.stop(), CLEAR_LINE);
+        assert!(!spinner.is_active());
+        assert_eq!(spinner.tick(later), None);
+
+        // Stopping again, like when the response is done, erases nothing.
+        assert_eq!(spinner.stop(), "");
+    }
+
+    #[test]
+    fn stopping_before_drawing() {
+        // Responses that start or are cancelled before the first tick have
+        // nothing to erase.
+        let mut spinner = Spinner::new(true);
+        spinner.start(Instant::now());
+        assert_eq!(spinner.stop(), "");
+        assert!(!spinner.is_active());
+
+        // Restarting begins from the first frame.
+        let now = Instant::now();
+        spinner.start(now);
+        assert_eq!(spinner.tick(now).as_deref(), Some("\r\x1b[2K| 0s"));
+    }
+
+    #[test]
+    fn disabled_spinner() {
+        let mut spinner = Spinner::new(false);
+        let now = Instant::now();
+        spinner.start(now);
+        assert!(!spinner.is_active());
+        assert_eq!(spinner.tick(now), None);
+        assert_eq!(spinner.stop(), "");
+    }
+}
@@ -0,0 +1,5 @@
+-- This file should undo anything in `up.sql`
+ALTER TABLE events
+    DROP COLUMN recurrence_frequency,
+    DROP COLUMN recurrence_interval,
+    DROP COLUMN recurrence_until;
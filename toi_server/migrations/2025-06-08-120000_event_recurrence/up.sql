@@ -0,0 +1,7 @@
+-- Your SQL goes here
+ALTER TABLE events
+    ADD COLUMN IF NOT EXISTS recurrence_frequency TEXT
+        CHECK (recurrence_frequency IN ('daily', 'weekly', 'monthly')),
+    ADD COLUMN IF NOT EXISTS recurrence_interval INT
+        CHECK (recurrence_interval > 0),
+    ADD COLUMN IF NOT EXISTS recurrence_until TIMESTAMPTZ;
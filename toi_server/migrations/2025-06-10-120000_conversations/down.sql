@@ -0,0 +1,4 @@
+-- This file should undo anything in `up.sql`
+DROP TABLE conversation_messages;
+
+DROP TABLE conversations;
@@ -0,0 +1,16 @@
+-- Your SQL goes here
+CREATE TABLE IF NOT EXISTS conversations (
+    id INT PRIMARY KEY GENERATED ALWAYS AS IDENTITY,
+    title TEXT,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT now()
+);
+
+CREATE TABLE IF NOT EXISTS conversation_messages (
+    id INT PRIMARY KEY GENERATED ALWAYS AS IDENTITY,
+    conversation_id INT NOT NULL REFERENCES conversations(id) ON DELETE CASCADE,
+    sequence INT NOT NULL,
+    role TEXT NOT NULL CHECK (role IN ('assistant', 'user')),
+    content TEXT NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT now(),
+    UNIQUE (conversation_id, sequence)
+);
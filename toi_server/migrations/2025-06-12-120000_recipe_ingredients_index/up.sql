@@ -0,0 +1,4 @@
+-- Your SQL goes here
+CREATE EXTENSION IF NOT EXISTS pg_trgm;
+
+CREATE INDEX IF NOT EXISTS recipes_ingredients_trgm_idx ON recipes USING GIN (ingredients gin_trgm_ops);
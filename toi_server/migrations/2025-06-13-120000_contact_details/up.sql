@@ -0,0 +1,18 @@
+-- Your SQL goes here
+CREATE TABLE IF NOT EXISTS contact_emails (
+    id INT PRIMARY KEY GENERATED ALWAYS AS IDENTITY,
+    contact_id INT NOT NULL REFERENCES contacts(id) ON DELETE CASCADE,
+    label TEXT NOT NULL,
+    value TEXT NOT NULL
+);
+
+CREATE INDEX IF NOT EXISTS contact_emails_contact_id_idx ON contact_emails (contact_id);
+
+CREATE TABLE IF NOT EXISTS contact_phones (
+    id INT PRIMARY KEY GENERATED ALWAYS AS IDENTITY,
+    contact_id INT NOT NULL REFERENCES contacts(id) ON DELETE CASCADE,
+    label TEXT NOT NULL,
+    value TEXT NOT NULL
+);
+
+CREATE INDEX IF NOT EXISTS contact_phones_contact_id_idx ON contact_phones (contact_id);
@@ -0,0 +1,3 @@
+-- This file should undo anything in `up.sql`
+ALTER TABLE todos DROP COLUMN last_completed_at;
+ALTER TABLE todos DROP COLUMN recurrence_days;
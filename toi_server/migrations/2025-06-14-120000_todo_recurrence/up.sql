@@ -0,0 +1,3 @@
+-- Your SQL goes here
+ALTER TABLE todos ADD COLUMN IF NOT EXISTS recurrence_days INT CHECK (recurrence_days > 0);
+ALTER TABLE todos ADD COLUMN IF NOT EXISTS last_completed_at TIMESTAMPTZ;
@@ -0,0 +1,3 @@
+-- This file should undo anything in `up.sql`
+ALTER TABLE places DROP COLUMN longitude;
+ALTER TABLE places DROP COLUMN latitude;
@@ -0,0 +1,3 @@
+-- Your SQL goes here
+ALTER TABLE places ADD COLUMN IF NOT EXISTS latitude DOUBLE PRECISION;
+ALTER TABLE places ADD COLUMN IF NOT EXISTS longitude DOUBLE PRECISION;
@@ -0,0 +1,9 @@
+-- Your SQL goes here
+CREATE TABLE IF NOT EXISTS pending_actions (
+    id INT PRIMARY KEY GENERATED ALWAYS AS IDENTITY,
+    conversation_id INT REFERENCES conversations(id) ON DELETE CASCADE,
+    message_hash TEXT NOT NULL,
+    request JSONB NOT NULL,
+    description TEXT NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT now()
+);
@@ -0,0 +1,2 @@
+-- This file should undo anything in `up.sql`
+ALTER TABLE events DROP CONSTRAINT IF EXISTS events_ends_after_starts;
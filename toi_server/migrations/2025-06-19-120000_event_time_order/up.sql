@@ -0,0 +1,7 @@
+-- Your SQL goes here
+UPDATE events
+SET starts_at = ends_at, ends_at = starts_at
+WHERE ends_at < starts_at;
+
+ALTER TABLE events
+ADD CONSTRAINT events_ends_after_starts CHECK (ends_at >= starts_at);
@@ -0,0 +1,2 @@
+-- This file should undo anything in `up.sql`
+ALTER TABLE geocode_cache DROP COLUMN forecast_hourly;
@@ -0,0 +1,2 @@
+-- Your SQL goes here
+ALTER TABLE geocode_cache ADD COLUMN IF NOT EXISTS forecast_hourly TEXT;
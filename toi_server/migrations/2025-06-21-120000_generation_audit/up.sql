@@ -0,0 +1,16 @@
+-- Your SQL goes here
+CREATE TABLE IF NOT EXISTS generation_audit (
+    id INT PRIMARY KEY GENERATED ALWAYS AS IDENTITY,
+    purpose TEXT NOT NULL
+        CHECK (purpose IN ('classification', 'request-generation', 'summary', 'chat')),
+    system_prompt_hash TEXT NOT NULL,
+    api_path TEXT,
+    api_method TEXT,
+    rerank_score DOUBLE PRECISION,
+    output TEXT NOT NULL,
+    prompt_tokens INT,
+    completion_tokens INT,
+    latency_ms INT NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT now()
+);
+CREATE INDEX IF NOT EXISTS generation_audit_created_at_idx ON generation_audit (created_at);
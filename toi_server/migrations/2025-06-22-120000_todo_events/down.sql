@@ -0,0 +1,3 @@
+-- This file should undo anything in `up.sql`
+DROP INDEX IF EXISTS todos_event_id_idx;
+ALTER TABLE todos DROP COLUMN event_id;
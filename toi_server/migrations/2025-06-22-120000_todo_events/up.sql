@@ -0,0 +1,4 @@
+-- Your SQL goes here
+ALTER TABLE todos
+ADD COLUMN IF NOT EXISTS event_id INT REFERENCES events (id) ON DELETE SET NULL;
+CREATE INDEX IF NOT EXISTS todos_event_id_idx ON todos (event_id);
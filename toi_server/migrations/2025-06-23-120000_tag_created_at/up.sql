@@ -0,0 +1,2 @@
+-- Your SQL goes here
+ALTER TABLE tags ADD COLUMN IF NOT EXISTS created_at TIMESTAMPTZ NOT NULL DEFAULT now();
@@ -0,0 +1,3 @@
+-- This file should undo anything in `up.sql`
+ALTER TABLE notes DROP COLUMN archived_at;
+ALTER TABLE notes DROP COLUMN pinned;
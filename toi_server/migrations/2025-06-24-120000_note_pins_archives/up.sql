@@ -0,0 +1,3 @@
+-- Your SQL goes here
+ALTER TABLE notes ADD COLUMN IF NOT EXISTS pinned BOOLEAN NOT NULL DEFAULT false;
+ALTER TABLE notes ADD COLUMN IF NOT EXISTS archived_at TIMESTAMPTZ;
@@ -0,0 +1,3 @@
+-- This file should undo anything in `up.sql`
+DROP INDEX IF EXISTS events_place_id_idx;
+ALTER TABLE events DROP COLUMN place_id;
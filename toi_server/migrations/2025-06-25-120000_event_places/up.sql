@@ -0,0 +1,4 @@
+-- Your SQL goes here
+ALTER TABLE events
+ADD COLUMN IF NOT EXISTS place_id INT REFERENCES places (id) ON DELETE SET NULL;
+CREATE INDEX IF NOT EXISTS events_place_id_idx ON events (place_id);
@@ -0,0 +1,4 @@
+-- This file should undo anything in `up.sql`
+DROP TABLE todo_tags;
+
+DROP TABLE note_tags;
@@ -0,0 +1,8 @@
+-- Your SQL goes here
+CREATE TABLE IF NOT EXISTS note_chunks (
+    note_id INT REFERENCES notes(id) ON DELETE CASCADE,
+    chunk_index INT NOT NULL,
+    content TEXT NOT NULL,
+    embedding VECTOR NOT NULL,
+    PRIMARY KEY (note_id, chunk_index)
+);
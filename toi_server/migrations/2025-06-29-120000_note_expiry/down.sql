@@ -0,0 +1,3 @@
+-- This file should undo anything in `up.sql`
+DROP INDEX IF EXISTS notes_expires_at_idx;
+ALTER TABLE notes DROP COLUMN expires_at;
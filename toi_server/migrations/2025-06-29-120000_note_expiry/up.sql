@@ -0,0 +1,3 @@
+-- Your SQL goes here
+ALTER TABLE notes ADD COLUMN IF NOT EXISTS expires_at TIMESTAMPTZ;
+CREATE INDEX IF NOT EXISTS notes_expires_at_idx ON notes (expires_at);
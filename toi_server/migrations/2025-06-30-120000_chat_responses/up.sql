@@ -0,0 +1,8 @@
+-- Your SQL goes here
+CREATE TABLE IF NOT EXISTS chat_responses (
+    id TEXT PRIMARY KEY,
+    content TEXT NOT NULL,
+    complete BOOLEAN NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT now()
+);
+CREATE INDEX IF NOT EXISTS chat_responses_created_at_idx ON chat_responses (created_at);
@@ -0,0 +1,24 @@
+-- This file should undo anything in `up.sql`
+ALTER TABLE places DROP CONSTRAINT IF EXISTS places_user_id_phone_key;
+ALTER TABLE places DROP CONSTRAINT IF EXISTS places_user_id_address_key;
+ALTER TABLE places ADD CONSTRAINT places_phone_key UNIQUE (phone);
+ALTER TABLE places ADD CONSTRAINT places_address_key UNIQUE (address);
+ALTER TABLE contacts DROP CONSTRAINT IF EXISTS contacts_user_id_phone_key;
+ALTER TABLE contacts DROP CONSTRAINT IF EXISTS contacts_user_id_email_key;
+ALTER TABLE contacts ADD CONSTRAINT contacts_phone_key UNIQUE (phone);
+ALTER TABLE contacts ADD CONSTRAINT contacts_email_key UNIQUE (email);
+
+ALTER TABLE generation_audit DROP COLUMN IF EXISTS user_id;
+ALTER TABLE chat_responses DROP COLUMN IF EXISTS user_id;
+ALTER TABLE pending_actions DROP COLUMN IF EXISTS user_id;
+ALTER TABLE conversations DROP COLUMN IF EXISTS user_id;
+ALTER TABLE tags DROP COLUMN IF EXISTS user_id;
+ALTER TABLE transactions DROP COLUMN IF EXISTS user_id;
+ALTER TABLE bank_accounts DROP COLUMN IF EXISTS user_id;
+ALTER TABLE places DROP COLUMN IF EXISTS user_id;
+ALTER TABLE recipes DROP COLUMN IF EXISTS user_id;
+ALTER TABLE events DROP COLUMN IF EXISTS user_id;
+ALTER TABLE contacts DROP COLUMN IF EXISTS user_id;
+ALTER TABLE todos DROP COLUMN IF EXISTS user_id;
+ALTER TABLE notes DROP COLUMN IF EXISTS user_id;
+DROP TABLE IF EXISTS users;
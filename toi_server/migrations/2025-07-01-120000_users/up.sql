@@ -0,0 +1,54 @@
+-- Your SQL goes here
+CREATE TABLE IF NOT EXISTS users (
+    id INT PRIMARY KEY GENERATED BY DEFAULT AS IDENTITY,
+    name TEXT NOT NULL UNIQUE,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT now()
+);
+
+-- Everything from before there were users belongs to the default user,
+-- which is also who requests belong to if their token isn't tied to a user.
+INSERT INTO users (id, name) OVERRIDING SYSTEM VALUE VALUES (1, 'default')
+ON CONFLICT DO NOTHING;
+SELECT setval(pg_get_serial_sequence('users', 'id'), GREATEST((SELECT MAX(id) FROM users), 1));
+
+ALTER TABLE notes ADD COLUMN IF NOT EXISTS user_id INT NOT NULL DEFAULT 1 REFERENCES users(id) ON DELETE CASCADE;
+ALTER TABLE todos ADD COLUMN IF NOT EXISTS user_id INT NOT NULL DEFAULT 1 REFERENCES users(id) ON DELETE CASCADE;
+ALTER TABLE contacts ADD COLUMN IF NOT EXISTS user_id INT NOT NULL DEFAULT 1 REFERENCES users(id) ON DELETE CASCADE;
+ALTER TABLE events ADD COLUMN IF NOT EXISTS user_id INT NOT NULL DEFAULT 1 REFERENCES users(id) ON DELETE CASCADE;
+ALTER TABLE recipes ADD COLUMN IF NOT EXISTS user_id INT NOT NULL DEFAULT 1 REFERENCES users(id) ON DELETE CASCADE;
+ALTER TABLE places ADD COLUMN IF NOT EXISTS user_id INT NOT NULL DEFAULT 1 REFERENCES users(id) ON DELETE CASCADE;
+ALTER TABLE bank_accounts ADD COLUMN IF NOT EXISTS user_id INT NOT NULL DEFAULT 1 REFERENCES users(id) ON DELETE CASCADE;
+ALTER TABLE transactions ADD COLUMN IF NOT EXISTS user_id INT NOT NULL DEFAULT 1 REFERENCES users(id) ON DELETE CASCADE;
+ALTER TABLE tags ADD COLUMN IF NOT EXISTS user_id INT NOT NULL DEFAULT 1 REFERENCES users(id) ON DELETE CASCADE;
+
+-- So do conversations, what the assistant has replied or is waiting on, and
+-- audited model calls. Conversation messages belong to whoever their
+-- conversation belongs to.
+ALTER TABLE conversations ADD COLUMN IF NOT EXISTS user_id INT NOT NULL DEFAULT 1 REFERENCES users(id) ON DELETE CASCADE;
+ALTER TABLE pending_actions ADD COLUMN IF NOT EXISTS user_id INT NOT NULL DEFAULT 1 REFERENCES users(id) ON DELETE CASCADE;
+ALTER TABLE chat_responses ADD COLUMN IF NOT EXISTS user_id INT NOT NULL DEFAULT 1 REFERENCES users(id) ON DELETE CASCADE;
+ALTER TABLE generation_audit ADD COLUMN IF NOT EXISTS user_id INT NOT NULL DEFAULT 1 REFERENCES users(id) ON DELETE CASCADE;
+
+CREATE INDEX IF NOT EXISTS notes_user_id_idx ON notes (user_id);
+CREATE INDEX IF NOT EXISTS todos_user_id_idx ON todos (user_id);
+CREATE INDEX IF NOT EXISTS contacts_user_id_idx ON contacts (user_id);
+CREATE INDEX IF NOT EXISTS events_user_id_idx ON events (user_id);
+CREATE INDEX IF NOT EXISTS recipes_user_id_idx ON recipes (user_id);
+CREATE INDEX IF NOT EXISTS places_user_id_idx ON places (user_id);
+CREATE INDEX IF NOT EXISTS bank_accounts_user_id_idx ON bank_accounts (user_id);
+CREATE INDEX IF NOT EXISTS transactions_user_id_idx ON transactions (user_id);
+CREATE INDEX IF NOT EXISTS tags_user_id_idx ON tags (user_id);
+CREATE INDEX IF NOT EXISTS conversations_user_id_idx ON conversations (user_id);
+CREATE INDEX IF NOT EXISTS pending_actions_user_id_idx ON pending_actions (user_id);
+CREATE INDEX IF NOT EXISTS chat_responses_user_id_idx ON chat_responses (user_id);
+CREATE INDEX IF NOT EXISTS generation_audit_user_id_idx ON generation_audit (user_id);
+
+-- Contact and place details only need to be unique for each user.
+ALTER TABLE contacts DROP CONSTRAINT IF EXISTS contacts_email_key;
+ALTER TABLE contacts DROP CONSTRAINT IF EXISTS contacts_phone_key;
+ALTER TABLE contacts ADD CONSTRAINT contacts_user_id_email_key UNIQUE (user_id, email);
+ALTER TABLE contacts ADD CONSTRAINT contacts_user_id_phone_key UNIQUE (user_id, phone);
+ALTER TABLE places DROP CONSTRAINT IF EXISTS places_address_key;
+ALTER TABLE places DROP CONSTRAINT IF EXISTS places_phone_key;
+ALTER TABLE places ADD CONSTRAINT places_user_id_address_key UNIQUE (user_id, address);
+ALTER TABLE places ADD CONSTRAINT places_user_id_phone_key UNIQUE (user_id, phone);
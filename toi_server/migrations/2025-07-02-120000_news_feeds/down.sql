@@ -0,0 +1,4 @@
+-- This file should undo anything in `up.sql`
+ALTER TABLE news DROP COLUMN source;
+
+DROP TABLE news_feeds;
@@ -0,0 +1,8 @@
+-- Your SQL goes here
+CREATE TABLE IF NOT EXISTS news_feeds (
+    name TEXT PRIMARY KEY,
+    url TEXT NOT NULL,
+    is_default BOOLEAN NOT NULL DEFAULT FALSE
+);
+
+ALTER TABLE news ADD COLUMN source TEXT;
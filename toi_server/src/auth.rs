@@ -0,0 +1,191 @@
+use axum::{
+    extract::{FromRequestParts, Request, State},
+    http::{HeaderValue, header, request::Parts},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::convert::Infallible;
+use std::sync::Arc;
+
+use crate::{
+    models::{error::ToiError, state::ToiState},
+    schema, utils,
+};
+
+/// Hex-encoded SHA-256 hash of a token. Only hashes are kept in the
+/// configuration file so it doesn't leak working tokens.
+#[must_use]
+pub fn hash_token(token: &str) -> String {
+    format!("{:x}", Sha256::digest(token.as_bytes()))
+}
+
+/// ID of the user that everything belonged to before there were users. It's
+/// also who requests belong to if they aren't made with a token tied to a
+/// user, including when requests aren't authenticated at all.
+pub const DEFAULT_USER_ID: i32 = 1;
+
+/// User a request was authenticated as. Data is only ever searched, added,
+/// or deleted on behalf of this user.
+///
+/// It's added to requests' extensions by [`authenticate`], and it's the
+/// default user for requests that didn't go through it. The request's
+/// `Authorization` header is kept so the assistant endpoint can make
+/// requests to other endpoints as the same user.
+#[derive(Clone, Debug)]
+pub struct CurrentUser {
+    pub id: i32,
+    pub authorization: Option<HeaderValue>,
+}
+
+impl Default for CurrentUser {
+    fn default() -> Self {
+        Self {
+            id: DEFAULT_USER_ID,
+            authorization: None,
+        }
+    }
+}
+
+impl<S: Send + Sync> FromRequestParts<S> for CurrentUser {
+    type Rejection = Infallible;
+
+    async fn from_request_parts(parts: &mut Parts, _: &S) -> Result<Self, Self::Rejection> {
+        Ok(parts.extensions.get::<Self>().cloned().unwrap_or_default())
+    }
+}
+
+/// Get the ID of the user with the given name, adding them if they don't
+/// exist yet.
+pub async fn find_or_add_user(name: &str, conn: &mut utils::Conn<'_>) -> Result<i32, ToiError> {
+    use diesel::{ExpressionMethods, QueryDsl};
+    use diesel_async::RunQueryDsl;
+
+    let name = name.trim();
+    diesel::insert_into(schema::users::table)
+        .values(schema::users::name.eq(name))
+        .on_conflict_do_nothing()
+        .execute(conn)
+        .await
+        .map_err(utils::diesel_error)?;
+    schema::users::table
+        .filter(schema::users::name.eq(name))
+        .select(schema::users::id)
+        .first(conn)
+        .await
+        .map_err(utils::diesel_error)
+}
+
+/// Bearer token authentication against a set of hashed tokens, each of
+/// which belongs to a user.
+#[derive(Clone)]
+pub struct TokenAuth {
+    token_hashes: Arc<HashMap<String, i32>>,
+}
+
+impl TokenAuth {
+    /// Requests aren't authenticated if there aren't any tokens. These tokens
+    /// belong to the default user.
+    #[must_use]
+    pub fn new(token_hashes: Vec<String>) -> Self {
+        Self {
+            token_hashes: Arc::new(HashMap::new()),
+        }
+        .with_user(DEFAULT_USER_ID, token_hashes)
+    }
+
+    /// Also allow tokens that belong to another user.
+    #[must_use]
+    pub fn with_user(self, user_id: i32, token_hashes: Vec<String>) -> Self {
+        let mut all_token_hashes = Arc::unwrap_or_clone(self.token_hashes);
+        all_token_hashes.extend(
+            token_hashes
+                .into_iter()
+                .map(|token_hash| token_hash.trim().to_lowercase())
+                .filter(|token_hash| !token_hash.is_empty())
+                .map(|token_hash| (token_hash, user_id)),
+        );
+        Self {
+            token_hashes: Arc::new(all_token_hashes),
+        }
+    }
+
+    fn is_enabled(&self) -> bool {
+        !self.token_hashes.is_empty()
+    }
+
+    /// Check the value of an `Authorization` header, returning the ID of the
+    /// user its token belongs to.
+    pub fn check(&self, authorization: Option<&str>) -> Result<i32, ToiError> {
+        if !self.is_enabled() {
+            return Ok(DEFAULT_USER_ID);
+        }
+        let authorization = authorization
+            .ok_or_else(|| ToiError::Unauthorized("missing bearer token".to_string()))?;
+        let token = authorization
+            .strip_prefix("Bearer ")
+            .ok_or_else(|| ToiError::Unauthorized("expected a bearer token".to_string()))?;
+        self.token_hashes
+            .get(&hash_token(token.trim()))
+            .copied()
+            .ok_or_else(|| ToiError::Unauthorized("invalid bearer token".to_string()))
+    }
+}
+
+/// Middleware for rejecting requests that don't have a valid bearer token,
+/// and for noting which user the rest do.
+pub async fn authenticate(
+    State(state): State<ToiState>,
+    mut request: Request,
+    next: Next,
+) -> Response {
+    let authorization = request.headers().get(header::AUTHORIZATION).cloned();
+    let checked = state
+        .token_auth
+        .check(authorization.as_ref().and_then(|value| value.to_str().ok()));
+    match checked {
+        Ok(id) => {
+            request
+                .extensions_mut()
+                .insert(CurrentUser { id, authorization });
+            next.run(request).await
+        }
+        Err(err) => err.into_response(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{DEFAULT_USER_ID, TokenAuth, hash_token};
+
+    #[test]
+    fn checking_tokens() {
+        let auth = TokenAuth::new(vec![hash_token("secret")]);
+        assert!(auth.check(Some("Bearer secret")).is_ok());
+        assert!(auth.check(Some("Bearer wrong")).is_err());
+        assert!(auth.check(Some("secret")).is_err());
+        assert!(auth.check(None).is_err());
+    }
+
+    #[test]
+    fn disabled_auth() {
+        let auth = TokenAuth::new(vec![]);
+        assert_eq!(auth.check(None).ok(), Some(DEFAULT_USER_ID));
+        assert!(auth.check(Some("Bearer anything")).is_ok());
+    }
+
+    #[test]
+    fn checking_user_tokens() {
+        let auth = TokenAuth::new(vec![hash_token("shared")])
+            .with_user(2, vec![hash_token("alice")])
+            .with_user(3, vec![hash_token("bob")]);
+        assert_eq!(
+            auth.check(Some("Bearer shared")).ok(),
+            Some(DEFAULT_USER_ID)
+        );
+        assert_eq!(auth.check(Some("Bearer alice")).ok(), Some(2));
+        assert_eq!(auth.check(Some("Bearer bob")).ok(), Some(3));
+        assert!(auth.check(Some("Bearer carol")).is_err());
+    }
+}
@@ -1,14 +1,199 @@
-use axum::{body::Body, http::StatusCode};
+use axum::body::{Body, Bytes};
+use futures::Stream;
 use pgvector::Vector;
 use reqwest::{Client, header::HeaderMap};
 use serde::{Serialize, de::DeserializeOwned};
+use serde_json::{Value, json};
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use std::time::{Duration, Instant};
 use toi::GenerationRequest;
+use tracing::info;
 
-use crate::models::client::{
-    ApiClientError, EmbeddingRequest, EmbeddingResponse, GenerationResponse, HttpClientConfig,
-    RerankRequest, RerankResponse, StreamingGenerationRequest,
+use crate::metrics::Metrics;
+use crate::models::{
+    client::{
+        ApiClientError, BatchEmbeddingRequest, EmbeddingCache, EmbeddingRequest, EmbeddingResponse,
+        GenerationResponse, HttpClientConfig, RerankRequest, RerankResponse,
+        StreamingGenerationRequest, TokenUsage,
+    },
+    error::ToiError,
 };
 
+// SSE comment sent while a response stream is idle. Clients ignore it like
+// any other comment.
+const KEEP_ALIVE_COMMENT: &str = ": keep-alive\n\n";
+
+/// Streamed response from the generation API. Dropping it before it's
+/// finished, like when the client disconnects mid-response, drops the
+/// underlying connection and cancels generation upstream rather than
+/// letting it run to completion.
+///
+/// Token usage from model calls made before the stream started is added to
+/// the usage reported by the stream so clients see the usage for the whole
+/// turn. If the stream doesn't report usage, a usage chunk is added right
+/// before it finishes.
+struct GenerationStream {
+    inner: Pin<Box<dyn Stream<Item = reqwest::Result<Bytes>> + Send>>,
+    buffer: Vec<u8>,
+    prior_usage: TokenUsage,
+    usage_sent: bool,
+    finished: bool,
+}
+
+impl GenerationStream {
+    fn new(
+        stream: impl Stream<Item = reqwest::Result<Bytes>> + Send + 'static,
+        prior_usage: TokenUsage,
+    ) -> Self {
+        Self {
+            inner: Box::pin(stream),
+            buffer: vec![],
+            prior_usage,
+            usage_sent: false,
+            finished: false,
+        }
+    }
+
+    fn rewrite_line(&mut self, line: &str) -> String {
+        let Some(data) = line.strip_prefix("data: ") else {
+            return line.to_string();
+        };
+
+        // Make sure usage is always sent before the stream ends.
+        if data.trim() == "[DONE]" {
+            if self.usage_sent {
+                return line.to_string();
+            }
+            self.usage_sent = true;
+            let chunk = json!({"choices": [], "usage": self.prior_usage});
+            return format!("data: {chunk}\n\n{line}");
+        }
+
+        // Combine usage with the stream's own usage.
+        let Ok(mut chunk) = serde_json::from_str::<Value>(data) else {
+            return line.to_string();
+        };
+        let Some(usage) = chunk.get_mut("usage").filter(|usage| usage.is_object()) else {
+            return line.to_string();
+        };
+        let Ok(mut combined_usage) = serde_json::from_value::<TokenUsage>(usage.clone()) else {
+            return line.to_string();
+        };
+        combined_usage += self.prior_usage;
+        usage["prompt_tokens"] = combined_usage.prompt_tokens.into();
+        usage["completion_tokens"] = combined_usage.completion_tokens.into();
+        if usage.get("total_tokens").is_some() {
+            usage["total_tokens"] =
+                (combined_usage.prompt_tokens + combined_usage.completion_tokens).into();
+        }
+        self.usage_sent = true;
+        format!("data: {chunk}")
+    }
+
+    fn rewrite_buffered_lines(&mut self) -> String {
+        let mut output = String::new();
+        while let Some(i) = self.buffer.iter().position(|b| *b == b'\n') {
+            let line: Vec<u8> = self.buffer.drain(..=i).collect();
+            let line = String::from_utf8_lossy(&line[..i]);
+            output.push_str(&self.rewrite_line(&line));
+            output.push('\n');
+        }
+        output
+    }
+}
+
+impl Stream for GenerationStream {
+    type Item = reqwest::Result<Bytes>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        if self.finished {
+            return Poll::Ready(None);
+        }
+        loop {
+            match self.inner.as_mut().poll_next(cx) {
+                Poll::Ready(Some(Ok(bytes))) => {
+                    // Only complete lines can be rewritten, so partial lines
+                    // are held until the rest of them arrive.
+                    self.buffer.extend_from_slice(&bytes);
+                    let output = self.rewrite_buffered_lines();
+                    if !output.is_empty() {
+                        return Poll::Ready(Some(Ok(Bytes::from(output))));
+                    }
+                }
+                Poll::Ready(None) => {
+                    self.finished = true;
+                    if self.buffer.is_empty() {
+                        return Poll::Ready(None);
+                    }
+                    let buffer = std::mem::take(&mut self.buffer);
+                    let output = self.rewrite_line(&String::from_utf8_lossy(&buffer));
+                    return Poll::Ready(Some(Ok(Bytes::from(output))));
+                }
+                poll => return poll,
+            }
+        }
+    }
+}
+
+impl Drop for GenerationStream {
+    fn drop(&mut self) {
+        if !self.finished {
+            info!("response stream dropped early, cancelling generation");
+        }
+    }
+}
+
+/// Generation stream that sends an SSE comment whenever nothing has been
+/// sent for a while before the generation API starts responding, like when
+/// it takes a long time to work through a big prompt, so proxies and clients
+/// don't close the connection for being idle. Comments stop once data flows
+/// and are only sent before the wrapped stream's first chunk, so they never
+/// split an event.
+struct KeepAliveStream {
+    inner: GenerationStream,
+    interval: Duration,
+    idle: Pin<Box<tokio::time::Sleep>>,
+    started: bool,
+}
+
+impl KeepAliveStream {
+    fn new(inner: GenerationStream, interval: Duration) -> Self {
+        Self {
+            inner,
+            interval,
+            idle: Box::pin(tokio::time::sleep(interval)),
+            started: false,
+        }
+    }
+
+    fn reset_idle(&mut self) {
+        let deadline = tokio::time::Instant::now() + self.interval;
+        self.idle.as_mut().reset(deadline);
+    }
+}
+
+impl Stream for KeepAliveStream {
+    type Item = reqwest::Result<Bytes>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        match Pin::new(&mut self.inner).poll_next(cx) {
+            Poll::Ready(Some(item)) => {
+                self.started = true;
+                Poll::Ready(Some(item))
+            }
+            Poll::Ready(None) => Poll::Ready(None),
+            Poll::Pending => {
+                if self.started || self.idle.as_mut().poll(cx).is_pending() {
+                    return Poll::Pending;
+                }
+                self.reset_idle();
+                Poll::Ready(Some(Ok(Bytes::from_static(KEEP_ALIVE_COMMENT.as_bytes()))))
+            }
+        }
+    }
+}
+
 #[derive(Clone)]
 pub struct ModelClient {
     pub embedding_api_config: HttpClientConfig,
@@ -17,13 +202,14 @@ pub struct ModelClient {
     generation_client: Client,
     pub reranking_api_config: HttpClientConfig,
     reranking_client: Client,
+    pub metrics: Metrics,
 }
 
 impl ModelClient {
     fn build_request_json<Request: Serialize>(
         config: &HttpClientConfig,
         request: Request,
-    ) -> Result<serde_json::Value, (StatusCode, String)> {
+    ) -> Result<serde_json::Value, ToiError> {
         let mut value = serde_json::to_value(request)
             .map_err(|err| ApiClientError::RequestJson.into_response(&err))?;
         let request = value
@@ -39,14 +225,35 @@ impl ModelClient {
         Ok(value)
     }
 
-    pub async fn embed(&self, request: EmbeddingRequest) -> Result<Vector, (StatusCode, String)> {
-        let response: EmbeddingResponse = Self::post(
-            &self.embedding_api_config,
-            "/v1/embeddings".to_string(),
-            &self.embedding_client,
-            request,
-        )
-        .await?;
+    /// Time a call to a model API, recording how long it took and whether
+    /// it succeeded if metrics are enabled.
+    async fn timed<T>(
+        &self,
+        api: &'static str,
+        call: impl Future<Output = Result<T, ToiError>>,
+    ) -> Result<T, ToiError> {
+        if !self.metrics.is_enabled() {
+            return call.await;
+        }
+        let start = Instant::now();
+        let result = call.await;
+        self.metrics
+            .observe_model_request(api, result.is_ok(), start.elapsed());
+        result
+    }
+
+    pub async fn embed(&self, request: EmbeddingRequest) -> Result<Vector, ToiError> {
+        let response: EmbeddingResponse = self
+            .timed(
+                "embedding",
+                Self::post(
+                    &self.embedding_api_config,
+                    "/v1/embeddings".to_string(),
+                    &self.embedding_client,
+                    request,
+                ),
+            )
+            .await?;
         match response.data.into_iter().next() {
             Some(data) => Ok(Vector::from(data.embedding)),
             None => Err(ApiClientError::ResponseJson
@@ -54,17 +261,73 @@ impl ModelClient {
         }
     }
 
+    /// Embed an input, reusing the embedding from earlier in the same request
+    /// if the exact same input was already embedded.
+    pub async fn embed_cached(
+        &self,
+        request: EmbeddingRequest,
+        cache: &mut EmbeddingCache,
+    ) -> Result<Vector, ToiError> {
+        if let Some(embedding) = cache.get(&request.input) {
+            return Ok(embedding.clone());
+        }
+        let input = request.input.clone();
+        let embedding = self.embed(request).await?;
+        cache.insert(input, embedding.clone());
+        Ok(embedding)
+    }
+
+    /// Embed many inputs with a single request, returning embeddings in the
+    /// same order as the inputs.
+    pub async fn embed_batch(
+        &self,
+        request: BatchEmbeddingRequest,
+    ) -> Result<Vec<Vector>, ToiError> {
+        let num_inputs = request.input.len();
+        let response: EmbeddingResponse = self
+            .timed(
+                "embedding",
+                Self::post(
+                    &self.embedding_api_config,
+                    "/v1/embeddings".to_string(),
+                    &self.embedding_client,
+                    request,
+                ),
+            )
+            .await?;
+        let mut data = response.data;
+        if data.len() != num_inputs {
+            return Err(ApiClientError::ResponseJson.into_response(&format!(
+                "expected {num_inputs} embeddings but got {}",
+                data.len()
+            )));
+        }
+        data.sort_by_key(|data| data.index);
+        Ok(data
+            .into_iter()
+            .map(|data| Vector::from(data.embedding))
+            .collect())
+    }
+
     pub async fn generate(
         &self,
         request: GenerationRequest,
-    ) -> Result<String, (StatusCode, String)> {
-        let response: GenerationResponse = Self::post(
-            &self.generation_api_config,
-            "/v1/chat/completions".to_string(),
-            &self.generation_client,
-            request,
-        )
-        .await?;
+        usage: &mut TokenUsage,
+    ) -> Result<String, ToiError> {
+        let response: GenerationResponse = self
+            .timed(
+                "generation",
+                Self::post(
+                    &self.generation_api_config,
+                    "/v1/chat/completions".to_string(),
+                    &self.generation_client,
+                    request,
+                ),
+            )
+            .await?;
+        if let Some(response_usage) = response.usage {
+            *usage += response_usage;
+        }
         match response.choices.into_iter().next() {
             Some(choice) => Ok(choice.message.content),
             None => Err(ApiClientError::ResponseJson
@@ -72,29 +335,85 @@ impl ModelClient {
         }
     }
 
+    /// Stream a generation. Keep-alive comments are sent whenever nothing
+    /// has been sent within the keep-alive interval, unless the interval is
+    /// zero. Only the time until the response starts is recorded in metrics.
     pub async fn generate_stream(
         &self,
         request: StreamingGenerationRequest,
-    ) -> Result<Body, (StatusCode, String)> {
+        prior_usage: TokenUsage,
+        keep_alive_interval: Duration,
+    ) -> Result<Body, ToiError> {
         let base_url = self.generation_api_config.base_url.trim_end_matches('/');
         let url = format!("{base_url}/v1/chat/completions");
         let request = Self::build_request_json(&self.generation_api_config, request)?;
+        let start = Instant::now();
         let response = self
             .generation_client
             .post(&url)
             .query(&self.generation_api_config.params)
             .json(&request)
             .send()
+            .await;
+        self.metrics.observe_model_request(
+            "generation_stream",
+            response
+                .as_ref()
+                .is_ok_and(|response| response.status().is_success()),
+            start.elapsed(),
+        );
+        let response = response.map_err(|err| ApiClientError::ApiConnection.into_response(&err))?;
+        let stream = GenerationStream::new(response.bytes_stream(), prior_usage);
+        if keep_alive_interval.is_zero() {
+            Ok(Body::from_stream(stream))
+        } else {
+            Ok(Body::from_stream(KeepAliveStream::new(
+                stream,
+                keep_alive_interval,
+            )))
+        }
+    }
+
+    async fn ping(
+        config: &HttpClientConfig,
+        client: &Client,
+        timeout: Duration,
+    ) -> Result<(), String> {
+        let base_url = config.base_url.trim_end_matches('/');
+        let url = format!("{base_url}/v1/models");
+        client
+            .get(&url)
+            .query(&config.params)
+            .timeout(timeout)
+            .send()
             .await
-            .map_err(|err| ApiClientError::ApiConnection.into_response(&err))?;
-        let stream = response.bytes_stream();
-        Ok(Body::from_stream(stream))
+            .and_then(reqwest::Response::error_for_status)
+            .map(|_| ())
+            .map_err(|err| err.to_string())
+    }
+
+    pub async fn ping_embedding_api(&self, timeout: Duration) -> Result<(), String> {
+        Self::ping(&self.embedding_api_config, &self.embedding_client, timeout).await
+    }
+
+    pub async fn ping_generation_api(&self, timeout: Duration) -> Result<(), String> {
+        Self::ping(
+            &self.generation_api_config,
+            &self.generation_client,
+            timeout,
+        )
+        .await
+    }
+
+    pub async fn ping_reranking_api(&self, timeout: Duration) -> Result<(), String> {
+        Self::ping(&self.reranking_api_config, &self.reranking_client, timeout).await
     }
 
     pub fn new(
         embedding_api_config: HttpClientConfig,
         generation_api_config: HttpClientConfig,
         reranking_api_config: HttpClientConfig,
+        metrics: Metrics,
     ) -> Result<Self, Box<dyn std::error::Error>> {
         let embedding_header_map = HeaderMap::try_from(&embedding_api_config.headers)?;
         let embedding_client = Client::builder()
@@ -115,6 +434,7 @@ impl ModelClient {
             generation_client,
             reranking_api_config,
             reranking_client,
+            metrics,
         })
     }
 
@@ -123,7 +443,7 @@ impl ModelClient {
         endpoint: String,
         client: &Client,
         request: Request,
-    ) -> Result<ResponseModel, (StatusCode, String)> {
+    ) -> Result<ResponseModel, ToiError> {
         let base_url = config.base_url.trim_end_matches('/');
         let url = format!("{base_url}{endpoint}");
         let request = Self::build_request_json(config, request)?;
@@ -139,17 +459,127 @@ impl ModelClient {
             .map_err(|err| ApiClientError::ResponseJson.into_response(&err))
     }
 
-    pub async fn rerank(
-        &self,
-        request: RerankRequest,
-    ) -> Result<RerankResponse, (StatusCode, String)> {
-        let response: RerankResponse = Self::post(
-            &self.reranking_api_config,
-            "/v1/rerank".to_string(),
-            &self.reranking_client,
-            request,
-        )
-        .await?;
+    pub async fn rerank(&self, request: RerankRequest) -> Result<RerankResponse, ToiError> {
+        let response: RerankResponse = self
+            .timed(
+                "reranking",
+                Self::post(
+                    &self.reranking_api_config,
+                    "/v1/rerank".to_string(),
+                    &self.reranking_client,
+                    request,
+                ),
+            )
+            .await?;
         Ok(response)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures::StreamExt;
+
+    async fn rewrite(chunks: Vec<&'static str>, prior_usage: TokenUsage) -> String {
+        let stream = futures::stream::iter(
+            chunks
+                .into_iter()
+                .map(|chunk| Ok::<_, reqwest::Error>(Bytes::from_static(chunk.as_bytes()))),
+        );
+        let outputs: Vec<Bytes> = GenerationStream::new(stream, prior_usage)
+            .map(|output| output.expect("mock stream shouldn't fail"))
+            .collect()
+            .await;
+        outputs
+            .iter()
+            .map(|output| String::from_utf8_lossy(output).into_owned())
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn combining_stream_usage() {
+        let prior_usage = TokenUsage {
+            prompt_tokens: 10,
+            completion_tokens: 5,
+        };
+        // Lines split across chunks are still rewritten.
+        let output = rewrite(
+            vec![
+                "data: {\"choices\":[{\"delta\":{\"content\":\"Hi\"}}]}\n\ndata: {\"choices\":[],",
+                "\"usage\":{\"prompt_tokens\":3,\"completion_tokens\":1,\"total_tokens\":4}}\n\n",
+                "data: [DONE]\n\n",
+            ],
+            prior_usage,
+        )
+        .await;
+        let usage_line = output
+            .lines()
+            .find(|line| line.contains("usage"))
+            .expect("should have usage");
+        let chunk: Value = serde_json::from_str(&usage_line["data: ".len()..]).unwrap();
+        assert_eq!(chunk["usage"]["prompt_tokens"], 13);
+        assert_eq!(chunk["usage"]["completion_tokens"], 6);
+        assert_eq!(chunk["usage"]["total_tokens"], 19);
+        assert_eq!(output.matches("usage").count(), 1);
+        assert!(output.ends_with("data: [DONE]\n\n"));
+    }
+
+    #[tokio::test]
+    async fn adding_missing_stream_usage() {
+        let prior_usage = TokenUsage {
+            prompt_tokens: 10,
+            completion_tokens: 5,
+        };
+        let output = rewrite(
+            vec![
+                "data: {\"choices\":[{\"delta\":{\"content\":\"Hi\"}}]}\n\n",
+                "data: [DONE]\n\n",
+            ],
+            prior_usage,
+        )
+        .await;
+        let usage_line = output
+            .lines()
+            .find(|line| line.contains("usage"))
+            .expect("should have usage");
+        let chunk: Value = serde_json::from_str(&usage_line["data: ".len()..]).unwrap();
+        assert_eq!(chunk["usage"]["prompt_tokens"], 10);
+        assert_eq!(chunk["usage"]["completion_tokens"], 5);
+        assert!(output.ends_with("data: [DONE]\n\n"));
+    }
+
+    #[tokio::test]
+    async fn sending_keep_alives() {
+        let chunks = [
+            "data: {\"choices\":[{\"delta\":{\"content\":\"Hi\"}}]}\n\n",
+            "data: [DONE]\n\n",
+        ];
+
+        // The stream stalls before its first chunk, like a slow prompt, and
+        // then again between chunks.
+        let stream = futures::stream::iter(chunks).then(|chunk| async move {
+            tokio::time::sleep(Duration::from_millis(100)).await;
+            Ok::<_, reqwest::Error>(Bytes::from_static(chunk.as_bytes()))
+        });
+        let stream = GenerationStream::new(stream, TokenUsage::default());
+        let outputs: Vec<Bytes> = KeepAliveStream::new(stream, Duration::from_millis(20))
+            .map(|output| output.expect("mock stream shouldn't fail"))
+            .collect()
+            .await;
+        let output: String = outputs
+            .iter()
+            .map(|output| String::from_utf8_lossy(output).into_owned())
+            .collect();
+        assert!(output.starts_with(KEEP_ALIVE_COMMENT));
+
+        // Comments stop once data flows.
+        let (_, after_start) = output
+            .split_once("data: ")
+            .expect("stream should have data");
+        assert!(!after_start.contains(KEEP_ALIVE_COMMENT));
+
+        // Removing the comments leaves the stream as it was.
+        let expected = rewrite(chunks.to_vec(), TokenUsage::default()).await;
+        assert_eq!(output.replace(KEEP_ALIVE_COMMENT, ""), expected);
+    }
+}
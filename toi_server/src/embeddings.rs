@@ -0,0 +1,556 @@
+use diesel::{
+    ExpressionMethods, QueryDsl, QueryableByName, SelectableHelper,
+    sql_types::{Array, BigInt, Integer},
+};
+use diesel_async::{
+    AsyncConnection, AsyncPgConnection, RunQueryDsl, scoped_futures::ScopedFutureExt,
+};
+use pgvector::Vector;
+use std::collections::HashMap;
+use std::fmt;
+use std::str::FromStr;
+use std::sync::Arc;
+use tracing::{info, warn};
+
+use crate::{
+    models::{
+        client::{BatchEmbeddingRequest, EmbeddingPromptTemplate, EmbeddingRequest},
+        contacts::{Contact, ContactDetail, ContactWithDetails, NewContactRequest},
+        error::ToiError,
+        places::{NewPlaceRequest, Place},
+        state::ToiState,
+    },
+    routes::{
+        contacts::load_contact_details,
+        notes::{embed_note_chunks, replace_note_chunks},
+    },
+    schema, utils,
+};
+
+// Probe used for checking how many dimensions the embedding API returns.
+const PROBE: &str = "How many dimensions does this embedding have?";
+
+/// Domains whose search queries are embedded with an instruction prefix, by
+/// the names used for configuring their instructions. Chat is the assistant's
+/// search for endpoints relevant to a user's command, and search is the
+/// search across several kinds of items at once.
+pub const EMBEDDING_DOMAINS: [&str; 11] = [
+    "accounts",
+    "chat",
+    "contacts",
+    "events",
+    "notes",
+    "places",
+    "recipes",
+    "search",
+    "tags",
+    "todos",
+    "transactions",
+];
+
+/// Configured instruction prefixes that override the ones each domain's
+/// search queries are embedded with by default, since embedding models are
+/// trained with different (or no) instructions.
+#[derive(Clone, Debug, Default)]
+pub struct EmbeddingInstructions {
+    overrides: Arc<HashMap<String, String>>,
+}
+
+impl EmbeddingInstructions {
+    /// Overrides for unknown domains are ignored with a warning since they're
+    /// likely typos.
+    #[must_use]
+    pub fn new(overrides: HashMap<String, String>) -> Self {
+        let overrides = overrides
+            .into_iter()
+            .filter(|(domain, _)| {
+                let is_known = EMBEDDING_DOMAINS.contains(&domain.as_str());
+                if !is_known {
+                    warn!(
+                        "ignoring embedding instruction for unknown domain '{domain}', expected one of: {}",
+                        EMBEDDING_DOMAINS.join(", ")
+                    );
+                }
+                is_known
+            })
+            .collect();
+        Self {
+            overrides: Arc::new(overrides),
+        }
+    }
+
+    /// Prompt template for embedding a domain's search queries. A domain's
+    /// override replaces its default instruction prefix, and an empty
+    /// override means queries are embedded as-is without any prefixes.
+    #[must_use]
+    pub fn template(
+        &self,
+        domain: &str,
+        instruction_prefix: &str,
+        query_prefix: &str,
+    ) -> EmbeddingPromptTemplate {
+        let instruction_prefix = match self.overrides.get(domain) {
+            Some(instruction_prefix) if instruction_prefix.is_empty() => {
+                return EmbeddingPromptTemplate::builder().build();
+            }
+            Some(instruction_prefix) => instruction_prefix,
+            None => instruction_prefix,
+        };
+        EmbeddingPromptTemplate::builder()
+            .instruction_prefix(instruction_prefix.to_string())
+            .query_prefix(query_prefix.to_string())
+            .build()
+    }
+}
+
+/// Tables with embeddings made from user items. OpenAPI embeddings aren't
+/// included since they're rebuilt every time the server starts.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum EmbeddedTable {
+    BankAccounts,
+    Contacts,
+    Events,
+    Notes,
+    Places,
+    Recipes,
+    Tags,
+    Todos,
+    Transactions,
+}
+
+impl EmbeddedTable {
+    pub const ALL: [Self; 9] = [
+        Self::BankAccounts,
+        Self::Contacts,
+        Self::Events,
+        Self::Notes,
+        Self::Places,
+        Self::Recipes,
+        Self::Tags,
+        Self::Todos,
+        Self::Transactions,
+    ];
+
+    #[must_use]
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::BankAccounts => "bank_accounts",
+            Self::Contacts => "contacts",
+            Self::Events => "events",
+            Self::Notes => "notes",
+            Self::Places => "places",
+            Self::Recipes => "recipes",
+            Self::Tags => "tags",
+            Self::Todos => "todos",
+            Self::Transactions => "transactions",
+        }
+    }
+
+    /// Distinct numbers of dimensions of the table's stored embeddings.
+    /// Embedding columns aren't declared with a fixed number of dimensions,
+    /// so they're read from the stored embeddings themselves. Note chunks
+    /// are checked along with their notes.
+    async fn stored_dimensions(self, conn: &mut utils::Conn<'_>) -> Result<Vec<i32>, ToiError> {
+        let mut query = format!(
+            "SELECT DISTINCT vector_dims(embedding) AS dimensions FROM {}",
+            self.name()
+        );
+        if self == Self::Notes {
+            query.push_str(" UNION SELECT vector_dims(embedding) AS dimensions FROM note_chunks");
+        }
+        let dimensions: Vec<StoredDimensions> = diesel::sql_query(query)
+            .load(conn)
+            .await
+            .map_err(utils::diesel_error)?;
+        Ok(dimensions
+            .into_iter()
+            .map(|dimensions| dimensions.dimensions)
+            .collect())
+    }
+
+    /// IDs of the given items ordered by their embeddings' distance to a
+    /// query embedding, closest first, keeping up to `limit` of them. Notes
+    /// are as close as their closest chunk, just like when they're searched.
+    pub async fn closest_ids(
+        self,
+        ids: &[i32],
+        embedding: Vector,
+        limit: usize,
+        conn: &mut utils::Conn<'_>,
+    ) -> Result<Vec<i32>, ToiError> {
+        let distance = if self == Self::Notes {
+            "LEAST(embedding <=> $2, (SELECT MIN(note_chunks.embedding <=> $2) \
+             FROM note_chunks WHERE note_chunks.note_id = notes.id))"
+        } else {
+            "embedding <=> $2"
+        };
+        let query = format!(
+            "SELECT id FROM {} WHERE id = ANY($1) ORDER BY {distance} LIMIT $3",
+            self.name()
+        );
+        let closest: Vec<ClosestId> = diesel::sql_query(query)
+            .bind::<Array<Integer>, _>(ids)
+            .bind::<pgvector::sql_types::Vector, _>(embedding)
+            .bind::<BigInt, _>(i64::try_from(limit).unwrap_or(i64::MAX))
+            .load(conn)
+            .await
+            .map_err(utils::diesel_error)?;
+        Ok(closest.into_iter().map(|closest| closest.id).collect())
+    }
+
+    /// Load the batch of IDs and embedding inputs after the given ID. Inputs
+    /// are built the same way as when items are added.
+    async fn load_inputs(
+        self,
+        last_id: i32,
+        limit: i64,
+        conn: &mut utils::Conn<'_>,
+    ) -> Result<Vec<(i32, String)>, ToiError> {
+        let inputs = match self {
+            Self::BankAccounts => schema::bank_accounts::table
+                .select((
+                    schema::bank_accounts::id,
+                    schema::bank_accounts::description,
+                ))
+                .filter(schema::bank_accounts::id.gt(last_id))
+                .order(schema::bank_accounts::id)
+                .limit(limit)
+                .load(conn)
+                .await
+                .map_err(utils::diesel_error)?,
+            Self::Contacts => {
+                let contacts: Vec<Contact> = schema::contacts::table
+                    .select(Contact::as_select())
+                    .filter(schema::contacts::id.gt(last_id))
+                    .order(schema::contacts::id)
+                    .limit(limit)
+                    .load(conn)
+                    .await
+                    .map_err(utils::diesel_error)?;
+                load_contact_details(contacts, conn)
+                    .await?
+                    .into_iter()
+                    .map(|contact| {
+                        let ContactWithDetails {
+                            contact,
+                            emails,
+                            phones,
+                        } = contact;
+                        let new_contact_request = NewContactRequest {
+                            first_name: contact.first_name,
+                            last_name: contact.last_name,
+                            email: contact.email,
+                            phone: contact.phone,
+                            birthday: contact.birthday,
+                            relationship: contact.relationship,
+                            emails: Some(emails.iter().map(ContactDetail::from).collect()),
+                            phones: Some(phones.iter().map(ContactDetail::from).collect()),
+                            allow_duplicate: None,
+                        };
+                        (contact.id, new_contact_request.to_string())
+                    })
+                    .collect()
+            }
+            Self::Events => schema::events::table
+                .select((schema::events::id, schema::events::description))
+                .filter(schema::events::id.gt(last_id))
+                .order(schema::events::id)
+                .limit(limit)
+                .load(conn)
+                .await
+                .map_err(utils::diesel_error)?,
+            Self::Notes => schema::notes::table
+                .select((schema::notes::id, schema::notes::content))
+                .filter(schema::notes::id.gt(last_id))
+                .order(schema::notes::id)
+                .limit(limit)
+                .load(conn)
+                .await
+                .map_err(utils::diesel_error)?,
+            Self::Places => {
+                let places: Vec<Place> = schema::places::table
+                    .select(Place::as_select())
+                    .filter(schema::places::id.gt(last_id))
+                    .order(schema::places::id)
+                    .limit(limit)
+                    .load(conn)
+                    .await
+                    .map_err(utils::diesel_error)?;
+                places
+                    .into_iter()
+                    .map(|place| {
+                        let new_place_request = NewPlaceRequest {
+                            name: place.name,
+                            description: place.description,
+                            address: place.address,
+                            phone: place.phone,
+                        };
+                        (place.id, new_place_request.to_string())
+                    })
+                    .collect()
+            }
+            Self::Recipes => schema::recipes::table
+                .select((schema::recipes::id, schema::recipes::description))
+                .filter(schema::recipes::id.gt(last_id))
+                .order(schema::recipes::id)
+                .limit(limit)
+                .load(conn)
+                .await
+                .map_err(utils::diesel_error)?,
+            Self::Tags => schema::tags::table
+                .select((schema::tags::id, schema::tags::name))
+                .filter(schema::tags::id.gt(last_id))
+                .order(schema::tags::id)
+                .limit(limit)
+                .load(conn)
+                .await
+                .map_err(utils::diesel_error)?,
+            Self::Todos => schema::todos::table
+                .select((schema::todos::id, schema::todos::item))
+                .filter(schema::todos::id.gt(last_id))
+                .order(schema::todos::id)
+                .limit(limit)
+                .load(conn)
+                .await
+                .map_err(utils::diesel_error)?,
+            Self::Transactions => schema::transactions::table
+                .select((schema::transactions::id, schema::transactions::description))
+                .filter(schema::transactions::id.gt(last_id))
+                .order(schema::transactions::id)
+                .limit(limit)
+                .load(conn)
+                .await
+                .map_err(utils::diesel_error)?,
+        };
+        Ok(inputs)
+    }
+
+    async fn set_embedding(
+        self,
+        id: i32,
+        embedding: Vector,
+        conn: &mut AsyncPgConnection,
+    ) -> diesel::QueryResult<usize> {
+        match self {
+            Self::BankAccounts => {
+                diesel::update(schema::bank_accounts::table)
+                    .filter(schema::bank_accounts::id.eq(id))
+                    .set(schema::bank_accounts::embedding.eq(embedding))
+                    .execute(conn)
+                    .await
+            }
+            Self::Contacts => {
+                diesel::update(schema::contacts::table)
+                    .filter(schema::contacts::id.eq(id))
+                    .set(schema::contacts::embedding.eq(embedding))
+                    .execute(conn)
+                    .await
+            }
+            Self::Events => {
+                diesel::update(schema::events::table)
+                    .filter(schema::events::id.eq(id))
+                    .set(schema::events::embedding.eq(embedding))
+                    .execute(conn)
+                    .await
+            }
+            Self::Notes => {
+                diesel::update(schema::notes::table)
+                    .filter(schema::notes::id.eq(id))
+                    .set(schema::notes::embedding.eq(embedding))
+                    .execute(conn)
+                    .await
+            }
+            Self::Places => {
+                diesel::update(schema::places::table)
+                    .filter(schema::places::id.eq(id))
+                    .set(schema::places::embedding.eq(embedding))
+                    .execute(conn)
+                    .await
+            }
+            Self::Recipes => {
+                diesel::update(schema::recipes::table)
+                    .filter(schema::recipes::id.eq(id))
+                    .set(schema::recipes::embedding.eq(embedding))
+                    .execute(conn)
+                    .await
+            }
+            Self::Tags => {
+                diesel::update(schema::tags::table)
+                    .filter(schema::tags::id.eq(id))
+                    .set(schema::tags::embedding.eq(embedding))
+                    .execute(conn)
+                    .await
+            }
+            Self::Todos => {
+                diesel::update(schema::todos::table)
+                    .filter(schema::todos::id.eq(id))
+                    .set(schema::todos::embedding.eq(embedding))
+                    .execute(conn)
+                    .await
+            }
+            Self::Transactions => {
+                diesel::update(schema::transactions::table)
+                    .filter(schema::transactions::id.eq(id))
+                    .set(schema::transactions::embedding.eq(embedding))
+                    .execute(conn)
+                    .await
+            }
+        }
+    }
+}
+
+impl fmt::Display for EmbeddedTable {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
+
+impl FromStr for EmbeddedTable {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::ALL
+            .into_iter()
+            .find(|table| table.name() == s)
+            .ok_or_else(|| format!("'{s}' isn't a table with embeddings"))
+    }
+}
+
+#[derive(QueryableByName)]
+struct StoredDimensions {
+    #[diesel(sql_type = Integer)]
+    dimensions: i32,
+}
+
+#[derive(QueryableByName)]
+struct ClosestId {
+    #[diesel(sql_type = Integer)]
+    id: i32,
+}
+
+/// Make sure the embedding API returns as many dimensions as configured,
+/// and that stored embeddings have that many dimensions too. Embeddings
+/// with different dimensions can't be compared, so searches would fail
+/// otherwise. Nothing is checked if the number of dimensions isn't
+/// configured.
+pub async fn check_dimensions(state: &ToiState) -> Result<(), ToiError> {
+    let Some(expected) = state.model_client.embedding_api_config.embedding_dimensions else {
+        return Ok(());
+    };
+    let embedding_request = EmbeddingRequest {
+        input: PROBE.to_string(),
+    };
+    let actual = state
+        .model_client
+        .embed(embedding_request)
+        .await?
+        .as_slice()
+        .len();
+    if actual != expected {
+        return Err(ToiError::Validation(format!(
+            "embedding API returns {actual}-dimensional embeddings, but {expected} dimensions are configured"
+        )));
+    }
+
+    let mut conn = utils::get_conn(&state.pool).await?;
+    for table in EmbeddedTable::ALL {
+        let mismatch = table
+            .stored_dimensions(&mut conn)
+            .await?
+            .into_iter()
+            .find(|dimensions| usize::try_from(*dimensions).ok() != Some(expected));
+        if let Some(dimensions) = mismatch {
+            return Err(ToiError::Validation(format!(
+                "{table} has {dimensions}-dimensional embeddings, but the embedding API returns {expected}; run `toi_server reembed {table}` to re-embed them with the current model"
+            )));
+        }
+    }
+    Ok(())
+}
+
+/// Re-embed every item in a table with the current embedding model,
+/// returning how many items were re-embedded. Items are re-embedded in
+/// batches, and each batch is updated in its own transaction so progress
+/// isn't lost if a later batch fails. Notes' chunks are rebuilt along with
+/// them.
+pub async fn reembed(state: &ToiState, table: EmbeddedTable) -> Result<usize, ToiError> {
+    let mut conn = utils::get_conn(&state.pool).await?;
+    let batch_size = i64::try_from(state.server_config.max_batch_size).unwrap_or(i64::MAX);
+    let mut last_id = 0;
+    let mut num_reembedded = 0;
+    loop {
+        let inputs = table.load_inputs(last_id, batch_size, &mut conn).await?;
+        let Some((id, _)) = inputs.last() else {
+            break;
+        };
+        last_id = *id;
+        let (ids, input): (Vec<i32>, Vec<String>) = inputs.into_iter().unzip();
+        let mut note_chunks = vec![];
+        if table == EmbeddedTable::Notes {
+            for content in &input {
+                note_chunks.push(embed_note_chunks(state, content).await?);
+            }
+        }
+        let embedding_request = BatchEmbeddingRequest { input };
+        let embeddings = state.model_client.embed_batch(embedding_request).await?;
+        let num_batch = ids.len();
+        conn.transaction(|mut conn| {
+            async move {
+                for (id, embedding) in ids.iter().zip(embeddings) {
+                    table.set_embedding(*id, embedding, &mut conn).await?;
+                }
+                for (id, chunks) in ids.into_iter().zip(note_chunks) {
+                    replace_note_chunks(id, chunks, &mut conn).await?;
+                }
+                Ok(())
+            }
+            .scope_boxed()
+        })
+        .await
+        .map_err(utils::diesel_error)?;
+        num_reembedded += num_batch;
+        info!("re-embedded {num_reembedded} items in {table}");
+    }
+    Ok(num_reembedded)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use super::EmbeddingInstructions;
+
+    #[test]
+    fn overriding_embedding_instructions() {
+        let overrides = HashMap::from([
+            ("notes".to_string(), "Instruction: Find notes".to_string()),
+            ("todos".to_string(), String::new()),
+            ("nots".to_string(), "Instruction: Typo".to_string()),
+        ]);
+        let embedding_instructions = EmbeddingInstructions::new(overrides);
+        assert_eq!(
+            embedding_instructions
+                .template("notes", "Instruction: Default", "Query: ")
+                .apply("groceries"),
+            "Instruction: Find notes\nQuery: groceries"
+        );
+        assert_eq!(
+            embedding_instructions
+                .template("todos", "Instruction: Default", "Query: ")
+                .apply("groceries"),
+            "groceries"
+        );
+        assert_eq!(
+            embedding_instructions
+                .template("places", "Instruction: Default", "Query: ")
+                .apply("groceries"),
+            "Instruction: Default\nQuery: groceries"
+        );
+        assert_eq!(
+            embedding_instructions
+                .template("nots", "Instruction: Default", "Query: ")
+                .apply("groceries"),
+            "Instruction: Default\nQuery: groceries"
+        );
+    }
+}
@@ -0,0 +1,164 @@
+use chrono::{DateTime, NaiveDate, Utc};
+
+/// Content type for iCalendar responses.
+pub const CONTENT_TYPE: &str = "text/calendar; charset=utf-8";
+
+// Lines longer than this many octets (excluding the line break) are folded.
+const MAX_LINE_OCTETS: usize = 75;
+
+/// Writes an iCalendar (RFC 5545) document one property at a time, taking
+/// care of line breaks and folding long lines.
+pub struct CalendarWriter {
+    buf: String,
+}
+
+impl CalendarWriter {
+    /// Start a calendar document.
+    #[must_use]
+    pub fn new() -> Self {
+        let mut writer = Self { buf: String::new() };
+        writer.property("BEGIN", "VCALENDAR");
+        writer.property("VERSION", "2.0");
+        writer.property("PRODID", "-//toi//toi_server//EN");
+        writer.property("CALSCALE", "GREGORIAN");
+        writer
+    }
+
+    /// Write a property with a value that's already formatted, like a date
+    /// or recurrence rule. The name may include parameters, like
+    /// `DTSTART;VALUE=DATE`.
+    pub fn property(&mut self, name: &str, value: &str) {
+        self.line(&format!("{name}:{value}"));
+    }
+
+    /// Write a property with a free-form text value, escaping it first.
+    pub fn text(&mut self, name: &str, value: &str) {
+        self.property(name, &escape_text(value));
+    }
+
+    /// Write a property with a UTC datetime value.
+    pub fn datetime(&mut self, name: &str, value: DateTime<Utc>) {
+        self.property(name, &format_datetime(value));
+    }
+
+    /// Write a property with an all-day date value.
+    pub fn date(&mut self, name: &str, value: NaiveDate) {
+        self.property(&format!("{name};VALUE=DATE"), &format_date(value));
+    }
+
+    /// Finish the calendar document and get its text.
+    #[must_use]
+    pub fn finish(mut self) -> String {
+        self.property("END", "VCALENDAR");
+        self.buf
+    }
+
+    /// Write a content line, folding it so no line is longer than 75 octets.
+    /// Continuation lines start with a space that counts toward their length,
+    /// and lines are never split in the middle of a multi-byte character.
+    fn line(&mut self, line: &str) {
+        let mut limit = MAX_LINE_OCTETS;
+        let mut len = 0;
+        for c in line.chars() {
+            if len + c.len_utf8() > limit {
+                self.buf.push_str("\r\n ");
+                limit = MAX_LINE_OCTETS - 1;
+                len = 0;
+            }
+            self.buf.push(c);
+            len += c.len_utf8();
+        }
+        self.buf.push_str("\r\n");
+    }
+}
+
+impl Default for CalendarWriter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Escape backslashes, semicolons, commas, and line breaks in a text value.
+#[must_use]
+pub fn escape_text(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    let mut chars = value.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => escaped.push_str("\\\\"),
+            ';' => escaped.push_str("\\;"),
+            ',' => escaped.push_str("\\,"),
+            '\r' => {
+                chars.next_if_eq(&'\n');
+                escaped.push_str("\\n");
+            }
+            '\n' => escaped.push_str("\\n"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// Format a datetime in the UTC form iCalendar uses, like `20250627T120000Z`.
+#[must_use]
+pub fn format_datetime(value: DateTime<Utc>) -> String {
+    value.format("%Y%m%dT%H%M%SZ").to_string()
+}
+
+/// Format a date in the form iCalendar uses, like `20250627`.
+#[must_use]
+pub fn format_date(value: NaiveDate) -> String {
+    value.format("%Y%m%d").to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::TimeZone;
+
+    use super::*;
+
+    #[test]
+    fn escapes_text() {
+        assert_eq!(
+            escape_text("Dinner; bring wine, cheese\\crackers\r\nand\nplates"),
+            "Dinner\\; bring wine\\, cheese\\\\crackers\\nand\\nplates"
+        );
+        assert_eq!(escape_text("plain text"), "plain text");
+    }
+
+    #[test]
+    fn folds_long_lines() {
+        let mut writer = CalendarWriter::new();
+        let value = "é".repeat(100);
+        writer.text("DESCRIPTION", &value);
+        let calendar = writer.finish();
+        assert!(calendar.ends_with("END:VCALENDAR\r\n"));
+        let lines: Vec<&str> = calendar
+            .strip_suffix("\r\n")
+            .expect("calendar should end with a line break")
+            .split("\r\n")
+            .collect();
+        for line in &lines {
+            assert!(line.len() <= MAX_LINE_OCTETS);
+        }
+        let start = lines
+            .iter()
+            .position(|line| line.starts_with("DESCRIPTION:"))
+            .expect("description should be written");
+        let mut unfolded = lines[start].to_string();
+        for line in &lines[start + 1..] {
+            let Some(continuation) = line.strip_prefix(' ') else {
+                break;
+            };
+            unfolded.push_str(continuation);
+        }
+        assert_eq!(unfolded, format!("DESCRIPTION:{value}"));
+    }
+
+    #[test]
+    fn formats_dates() {
+        let datetime = Utc.with_ymd_and_hms(2025, 6, 27, 8, 5, 0).unwrap();
+        assert_eq!(format_datetime(datetime), "20250627T080500Z");
+        assert_eq!(format_date(datetime.date_naive()), "20250627");
+    }
+}
@@ -0,0 +1,129 @@
+use axum::http::{HeaderMap, HeaderName};
+use chrono::{TimeDelta, Utc};
+use diesel::{ExpressionMethods, OptionalExtension, QueryDsl, SelectableHelper, sql_types::Text};
+use diesel_async::{AsyncPgConnection, RunQueryDsl};
+use serde::{Serialize, de::DeserializeOwned};
+use sha2::{Digest, Sha256};
+
+use crate::{
+    models::{error::ToiError, idempotency::StoredResponse, state::ToiState},
+    schema, utils,
+};
+
+/// Request header with a key that makes repeats of a request to an add
+/// endpoint get the original response instead of adding the item again.
+pub static IDEMPOTENCY_KEY_HEADER: HeaderName = HeaderName::from_static("idempotency-key");
+
+/// Idempotency key sent with a request to an add endpoint, along with a hash
+/// of the request for telling whether a repeat is really the same request.
+/// Keys are scoped to the user that sent them so different users can't
+/// replay each other's responses.
+pub struct IdempotencyKey {
+    key: String,
+    user_id: i32,
+    endpoint: &'static str,
+    request_hash: String,
+    ttl: TimeDelta,
+}
+
+impl IdempotencyKey {
+    /// Get the idempotency key sent with a request to an endpoint, if any.
+    pub fn from_request<T: Serialize>(
+        state: &ToiState,
+        headers: &HeaderMap,
+        user_id: i32,
+        endpoint: &'static str,
+        params: &T,
+    ) -> Result<Option<Self>, ToiError> {
+        let Some(value) = headers.get(&IDEMPOTENCY_KEY_HEADER) else {
+            return Ok(None);
+        };
+        let key = value
+            .to_str()
+            .ok()
+            .map(str::trim)
+            .filter(|key| !key.is_empty())
+            .ok_or_else(|| {
+                ToiError::Validation("idempotency key must be non-empty ASCII text".to_string())
+            })?;
+        let request = serde_json::to_vec(params).map_err(utils::internal_error)?;
+        Ok(Some(Self {
+            key: key.to_string(),
+            user_id,
+            endpoint,
+            request_hash: format!("{:x}", Sha256::digest(request)),
+            ttl: TimeDelta::hours(state.server_config.idempotency_ttl_hours.into()),
+        }))
+    }
+
+    /// Key as it's stored, which includes the user it's scoped to.
+    fn stored_key(&self) -> String {
+        format!("{}:{}", self.user_id, self.key)
+    }
+
+    /// Get the response stored for this key if it was used within the expiry
+    /// window. Reusing a key for a different request is a conflict.
+    ///
+    /// Within a transaction, the key stays locked until the transaction ends,
+    /// so concurrent requests with the same key wait for the first one to
+    /// store its response and then replay it.
+    pub async fn replay<R: DeserializeOwned>(
+        &self,
+        conn: &mut AsyncPgConnection,
+    ) -> Result<Option<R>, ToiError> {
+        diesel::sql_query("SELECT pg_advisory_xact_lock(hashtext($1 || ' ' || $2))")
+            .bind::<Text, _>(self.stored_key())
+            .bind::<Text, _>(self.endpoint)
+            .execute(conn)
+            .await?;
+
+        // Expired keys are deleted along the way so they can be reused.
+        let cutoff = Utc::now() - self.ttl;
+        diesel::delete(
+            schema::idempotency_keys::table.filter(schema::idempotency_keys::created_at.lt(cutoff)),
+        )
+        .execute(conn)
+        .await?;
+
+        let stored = schema::idempotency_keys::table
+            .select(StoredResponse::as_select())
+            .filter(schema::idempotency_keys::key.eq(self.stored_key()))
+            .filter(schema::idempotency_keys::endpoint.eq(self.endpoint))
+            .first(conn)
+            .await
+            .optional()?;
+        let Some(stored) = stored else {
+            return Ok(None);
+        };
+        if stored.request_hash != self.request_hash {
+            return Err(ToiError::Conflict(format!(
+                "idempotency key {:?} was already used for a different request to {}",
+                self.key, self.endpoint
+            )));
+        }
+        serde_json::from_str(&stored.response_body)
+            .map(Some)
+            .map_err(utils::internal_error)
+    }
+
+    /// Store the response for this key so repeats of the request replay it.
+    /// Meant to be called within the same transaction that adds the item.
+    pub async fn store<R: Serialize>(
+        &self,
+        response: &R,
+        conn: &mut AsyncPgConnection,
+    ) -> Result<(), ToiError> {
+        let stored = StoredResponse {
+            key: self.stored_key(),
+            endpoint: self.endpoint.to_string(),
+            request_hash: self.request_hash.clone(),
+            response_body: serde_json::to_string(response).map_err(utils::internal_error)?,
+            created_at: Utc::now(),
+        };
+        diesel::insert_into(schema::idempotency_keys::table)
+            .values(stored)
+            .execute(conn)
+            .await?;
+        Ok(())
+    }
+}
@@ -1,40 +1,152 @@
-use std::fs::File;
+use std::time::Duration;
 
-use ctrlc::set_handler;
 use diesel_async::{AsyncPgConnection, pooled_connection::AsyncDieselConnectionManager};
 use reqwest::header;
 use tracing::info;
 
+pub mod auth;
 mod client;
+pub mod embeddings;
+pub mod ics;
+pub mod idempotency;
+pub mod metrics;
 pub mod models;
+pub mod ndjson;
+pub mod rate_limit;
+pub mod request_id;
+pub mod resume;
 pub mod routes;
 pub mod schema;
+pub mod search;
+pub mod seeding;
+pub mod shutdown;
 mod utils;
+pub mod vcard;
+
+/// Options for initializing the server state.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct InitOptions {
+    /// Don't check that the model APIs are reachable, which is useful when
+    /// they're started after the server.
+    pub skip_api_check: bool,
+}
 
 pub async fn init(
     db_connection_url: String,
 ) -> Result<models::state::ToiState, Box<dyn std::error::Error>> {
-    // Catching signals for exit.
-    set_handler(|| std::process::exit(0))?;
+    init_with_options(db_connection_url, InitOptions::default()).await
+}
+
+pub async fn init_with_options(
+    db_connection_url: String,
+    options: InitOptions,
+) -> Result<models::state::ToiState, Box<dyn std::error::Error>> {
+    let state = init_state(db_connection_url).await?;
+
+    // Unreachable model APIs are reported together rather than failing on
+    // the first request that needs them.
+    if !options.skip_api_check {
+        info!("checking model APIs are reachable");
+        check_apis(&state).await?;
+    }
+
+    // Fail fast if the embedding model doesn't match stored embeddings
+    // rather than failing on every search later.
+    info!("checking embedding dimensions");
+    embeddings::check_dimensions(&state).await?;
+
+    // Seed data is added for the default user once the embedding API is
+    // known to work, so a dev instance starts with something to search.
+    if state.server_config.seed_on_start {
+        info!("seeding data");
+        seeding::seed(&state, auth::DEFAULT_USER_ID).await?;
+    }
+    Ok(state)
+}
+
+/// Initialize the server state without checking model APIs or stored
+/// embeddings, which is needed for re-embedding them after switching
+/// embedding models.
+pub async fn init_without_checks(
+    db_connection_url: String,
+) -> Result<models::state::ToiState, Box<dyn std::error::Error>> {
+    init_state(db_connection_url).await
+}
 
-    // All configuration comes from a required config file.
-    let config_path = dotenvy::var("TOI_CONFIG_PATH")?;
-    let config_file = File::open(config_path)?;
-    let config: models::config::ToiConfig = serde_json::from_reader(config_file)?;
+async fn check_apis(state: &models::state::ToiState) -> Result<(), models::config::ConfigError> {
+    let timeout = Duration::from_secs(state.server_config.readiness_timeout);
+    let (embedding, generation, reranking) = tokio::join!(
+        state.model_client.ping_embedding_api(timeout),
+        state.model_client.ping_generation_api(timeout),
+        state.model_client.ping_reranking_api(timeout),
+    );
+    let problems = [
+        (
+            "embedding",
+            &state.model_client.embedding_api_config,
+            embedding,
+        ),
+        (
+            "generation",
+            &state.model_client.generation_api_config,
+            generation,
+        ),
+        (
+            "reranking",
+            &state.model_client.reranking_api_config,
+            reranking,
+        ),
+    ]
+    .into_iter()
+    .filter_map(|(name, api_config, result)| {
+        result.err().map(|err| {
+            format!(
+                "{name} API at {} isn't reachable (skip this check with --skip-api-check): {err}",
+                api_config.base_url
+            )
+        })
+    })
+    .collect();
+    models::config::ConfigError::check(problems)
+}
+
+async fn init_state(
+    db_connection_url: String,
+) -> Result<models::state::ToiState, Box<dyn std::error::Error>> {
+    // All configuration comes from a config file or variable, and every
+    // problem with it is reported at once.
+    let config = models::config::ToiConfig::from_env()?;
     info!("initializing with {config:?}");
     let models::config::ToiConfig {
         server: server_config,
+        tokens,
+        users,
+        embedding_instructions,
+        database: database_config,
+        api_client: api_client_config,
+        news: news_config,
         embedding: embedding_api_config,
         generation: generation_api_config,
         reranking: reranking_api_config,
     } = config;
 
+    // One client is shared by everything other than the model APIs so
+    // requests to the same host reuse pooled connections.
     let mut headers = header::HeaderMap::new();
     let user_agent = header::HeaderValue::from_str(&server_config.user_agent)?;
     headers.insert("User-Agent", user_agent);
-    let api_client = reqwest::Client::builder()
+    let mut api_client = reqwest::Client::builder()
         .default_headers(headers)
-        .build()?;
+        .connect_timeout(Duration::from_secs(api_client_config.connect_timeout))
+        .pool_max_idle_per_host(api_client_config.pool_max_idle_per_host)
+        .pool_idle_timeout(Duration::from_secs(api_client_config.pool_idle_timeout));
+    if let Some(timeout) = api_client_config.timeout {
+        api_client = api_client.timeout(Duration::from_secs(timeout));
+    }
+    let api_client = api_client.build()?;
+
+    // Request counts and latencies are only kept if metrics are enabled.
+    let metrics = metrics::Metrics::new(server_config.metrics_enabled);
 
     // Shared state components. A client is used for interacting with supporting
     // API services, while a pool is used for interacting with the database.
@@ -42,17 +154,63 @@ pub async fn init(
         embedding_api_config,
         generation_api_config,
         reranking_api_config,
+        metrics.clone(),
     )?;
     let manager = AsyncDieselConnectionManager::<AsyncPgConnection>::new(db_connection_url);
-    let pool = bb8::Pool::builder().build(manager).await?;
+    let pool = bb8::Pool::builder()
+        .max_size(database_config.max_size)
+        .min_idle(database_config.min_idle)
+        .connection_timeout(Duration::from_secs(database_config.connection_timeout))
+        .idle_timeout(database_config.idle_timeout.map(Duration::from_secs))
+        .build(manager)
+        .await?;
+
+    // Rate limits are tracked across all requests, so the limiter is shared
+    // state too.
+    let rate_limiter = rate_limit::RateLimiter::new(
+        server_config.rate_limit_requests_per_minute,
+        server_config.rate_limit_burst,
+    );
+
+    // Partial responses are kept in memory so clients can resume response
+    // streams that were interrupted.
+    let resume_store = resume::ResumeStore::new(
+        server_config.resume_max_streams,
+        server_config.resume_max_bytes,
+        Duration::from_secs(server_config.resume_ttl_minutes * 60),
+    );
+
+    // Tokens are only kept as hashes, so they're checked by hashing
+    // whatever clients send. Users are added the first time their tokens
+    // are configured so their tokens can be tied to their IDs.
+    let mut token_auth = auth::TokenAuth::new(tokens);
+    if !users.is_empty() {
+        let mut conn = pool.get().await?;
+        for (name, token_hashes) in users {
+            let user_id = auth::find_or_add_user(&name, &mut conn).await?;
+            info!("tying tokens to user={name}");
+            token_auth = token_auth.with_user(user_id, token_hashes);
+        }
+    }
+
+    // Instruction prefixes used for embedding search queries can be tuned
+    // for different embedding models.
+    let embedding_instructions = embeddings::EmbeddingInstructions::new(embedding_instructions);
 
     // Build state with empty spec first since only the assistant endpoint uses
     // the OpenAPI spec.
     let state = models::state::ToiState {
         server_config,
         api_client,
+        capabilities: models::capabilities::CapabilitiesCache::default(),
+        embedding_instructions,
+        metrics,
         model_client,
+        news_config,
         pool,
+        rate_limiter,
+        resume_store,
+        token_auth,
     };
     Ok(state)
 }
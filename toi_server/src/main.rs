@@ -1,5 +1,7 @@
 use diesel::{Connection, PgConnection};
 use diesel_migrations::{EmbeddedMigrations, MigrationHarness, embed_migrations};
+use std::time::Duration;
+use toi_server::embeddings::EmbeddedTable;
 use tokio::net::TcpListener;
 use tower_http::trace::TraceLayer;
 use tracing::info;
@@ -32,34 +34,81 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
     conn.run_pending_migrations(MIGRATIONS)
         .expect("shouldn't fail to run migrations");
 
+    // Stored embeddings are re-embedded with the current embedding model
+    // when asked to rather than serving. This skips the embedding checks
+    // since they'd fail until re-embedding is done.
+    let mut args: Vec<String> = std::env::args().skip(1).collect();
+    let skip_api_check = args.iter().any(|arg| arg == "--skip-api-check");
+    args.retain(|arg| arg != "--skip-api-check");
+    let mut args = args.into_iter();
+    if let Some(command) = args.next() {
+        if command != "reembed" {
+            return Err(format!("unknown command '{command}', expected reembed").into());
+        }
+        let tables = args
+            .map(|table| table.parse::<EmbeddedTable>())
+            .collect::<Result<Vec<_>, _>>()?;
+        let tables = if tables.is_empty() {
+            EmbeddedTable::ALL.to_vec()
+        } else {
+            tables
+        };
+        info!("initializing server state");
+        let state = toi_server::init_without_checks(db_connection_url).await?;
+        for table in tables {
+            info!("re-embedding {table}");
+            let num_reembedded = toi_server::embeddings::reembed(&state, table).await?;
+            info!("finished re-embedding {num_reembedded} items in {table}");
+        }
+        return Ok(());
+    }
+
     // Initialize the server state and extract the server binding address.
     info!("initializing server state");
-    let state = toi_server::init(db_connection_url).await?;
+    let options = toi_server::InitOptions { skip_api_check };
+    let state = toi_server::init_with_options(db_connection_url, options).await?;
+
+    // Routes that call model APIs are rate limited to keep runaway clients
+    // from burning through model API quotas.
+    let rate_limited = |router: OpenApiRouter| {
+        router.layer(axum::middleware::from_fn_with_state(
+            state.clone(),
+            toi_server::rate_limit::rate_limit,
+        ))
+    };
 
     // Define base router and OpenAPI spec used for building the system prompt
     // for the main assistant endpoint.
     let mut openapi_router = OpenApiRouter::with_openapi(ApiDoc::openapi())
         .nest(
             "/banking/accounts",
-            toi_server::routes::accounts::accounts_router(state.clone()).nest(
-                "/transactions",
-                toi_server::routes::transactions::bank_account_transactions_router(state.clone()),
+            rate_limited(
+                toi_server::routes::accounts::accounts_router(state.clone()).nest(
+                    "/transactions",
+                    toi_server::routes::transactions::bank_account_transactions_router(
+                        state.clone(),
+                    ),
+                ),
             ),
         )
         .nest(
             "/banking/transactions",
-            toi_server::routes::transactions::transactions_router(state.clone()),
+            rate_limited(toi_server::routes::transactions::transactions_router(
+                state.clone(),
+            )),
         )
         .nest(
             "/contacts",
-            toi_server::routes::contacts::contacts_router(state.clone()),
+            rate_limited(toi_server::routes::contacts::contacts_router(state.clone())),
         )
         .nest("/datetime", toi_server::routes::datetime::datetime_router())
         .nest(
             "/events",
-            toi_server::routes::events::events_router(state.clone()).nest(
-                "/attendees",
-                toi_server::routes::attendees::attendees_router(state.clone()),
+            rate_limited(
+                toi_server::routes::events::events_router(state.clone()).nest(
+                    "/attendees",
+                    toi_server::routes::attendees::attendees_router(state.clone()),
+                ),
             ),
         )
         .nest(
@@ -68,23 +117,31 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
         )
         .nest(
             "/notes",
-            toi_server::routes::notes::notes_router(state.clone()),
+            rate_limited(toi_server::routes::notes::notes_router(state.clone())),
         )
         .nest(
             "/places",
-            toi_server::routes::places::places_router(state.clone()),
+            rate_limited(toi_server::routes::places::places_router(state.clone())),
         )
         .nest(
             "/recipes",
-            toi_server::routes::recipes::recipes_router(state.clone()),
+            rate_limited(toi_server::routes::recipes::recipes_router(state.clone())),
+        )
+        .nest(
+            "/reminders",
+            toi_server::routes::reminders::reminders_router(state.clone()),
+        )
+        .nest(
+            "/search",
+            rate_limited(toi_server::routes::search::search_router(state.clone())),
         )
         .nest(
             "/tags",
-            toi_server::routes::tags::tags_router(state.clone()),
+            rate_limited(toi_server::routes::tags::tags_router(state.clone())),
         )
         .nest(
             "/todos",
-            toi_server::routes::todos::todos_router(state.clone()),
+            rate_limited(toi_server::routes::todos::todos_router(state.clone())),
         )
         .nest(
             "/weather",
@@ -92,19 +149,135 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
         );
     let openapi = openapi_router.get_openapi_mut();
 
-    // Add the main /assistant endpoint to the router so it can be included in
-    // the docs, but excluded from its own system prompt. Then continue building
-    // the API router.
+    // Add the main /assistant endpoints to the router so they can be included
+    // in the docs, but excluded from their own system prompt. Then continue
+    // building the API router.
     let assistant_router =
         toi_server::routes::assistant::assistant_router(openapi, state.clone()).await?;
-    let openapi_router = openapi_router.nest("/assistant", assistant_router);
+    let openapi_router = openapi_router.nest("/assistant", rate_limited(assistant_router));
+
+    // Conversations are also excluded since they only store what's said
+    // to the assistant rather than fulfill user requests.
+    let openapi_router = openapi_router.nest(
+        "/conversations",
+        toi_server::routes::conversations::conversations_router(state.clone()),
+    );
+
+    // Exports are also excluded since they're meant for getting data out of
+    // the server rather than fulfilling user requests.
+    let openapi_router = openapi_router.nest(
+        "/export",
+        toi_server::routes::export::export_router(state.clone()),
+    );
+
+    // Contact avatars are also excluded since they're images rather than
+    // anything the assistant can read or write.
+    let openapi_router = openapi_router.nest(
+        "/contacts",
+        toi_server::routes::contacts::contact_avatars_router(state.clone()),
+    );
+
+    // Generation audits are also excluded since they're only meant for
+    // reviewing how the assistant handled past requests.
+    let openapi_router = openapi_router.nest(
+        "/admin/audit",
+        toi_server::routes::audit::audit_router(state.clone()),
+    );
+
+    // Connection pool status is also excluded since it's only meant for
+    // tuning the database settings.
+    let openapi_router = openapi_router.nest(
+        "/admin/pool",
+        toi_server::routes::pool::pool_router(state.clone()),
+    );
+
+    // Seeding is also excluded since it's only meant for standing up dev
+    // instances.
+    let openapi_router = openapi_router.nest(
+        "/admin/seed",
+        toi_server::routes::seeding::seeding_router(state.clone()),
+    );
+
+    // Capabilities are also excluded since they only describe the other
+    // endpoints.
+    let openapi_router = openapi_router.nest(
+        "/capabilities",
+        toi_server::routes::capabilities::capabilities_router(state.clone()),
+    );
+
+    // Metrics are also excluded since they're only meant for monitoring
+    // the server.
+    let openapi_router = openapi_router.nest(
+        "/metrics",
+        toi_server::routes::metrics::metrics_router(state.clone()),
+    );
+
+    // Everything up to this point requires a bearer token if any are
+    // configured.
+    let openapi_router = openapi_router.layer(axum::middleware::from_fn_with_state(
+        state.clone(),
+        toi_server::auth::authenticate,
+    ));
+
+    // Health checks are also excluded from the system prompt since they're
+    // only meant for supervisors and orchestrators. They're also left
+    // unauthenticated so supervisors don't need a token.
+    let openapi_router =
+        openapi_router.merge(toi_server::routes::health::health_router(state.clone()));
     let (router, api) = openapi_router.split_for_parts();
+
+    // Requests to every route are counted and timed if metrics are enabled.
+    // The layer isn't added at all otherwise.
+    let router = if state.server_config.metrics_enabled {
+        router.route_layer(axum::middleware::from_fn_with_state(
+            state.clone(),
+            toi_server::metrics::track_requests,
+        ))
+    } else {
+        router
+    };
     let router = router
         .merge(SwaggerUi::new("/swagger-ui").url("/api-docs/openapi.json", api))
-        .layer(TraceLayer::new_for_http());
+        .layer(TraceLayer::new_for_http())
+        .layer(axum::middleware::from_fn(
+            toi_server::request_id::request_id,
+        ));
+
+    // Upcoming todos and events are logged in the background so operators
+    // can wire up notifications from the logs.
+    tokio::spawn(toi_server::routes::reminders::log_upcoming_reminders(
+        state.clone(),
+    ));
+
+    // Old generation audits are also purged in the background.
+    tokio::spawn(toi_server::routes::audit::purge_expired_generation_audits(
+        state.clone(),
+    ));
+
+    // So are notes that expired a while ago.
+    tokio::spawn(toi_server::routes::notes::purge_expired_notes(
+        state.clone(),
+    ));
+
+    // And streamed replies that have been kept for long enough.
+    tokio::spawn(toi_server::routes::assistant::purge_expired_chat_responses(
+        state.clone(),
+    ));
 
     info!("serving at {}", state.server_config.bind_addr);
     let listener = TcpListener::bind(state.server_config.bind_addr).await?;
-    axum::serve(listener, router).await?;
+    let drain_timeout = Duration::from_secs(state.server_config.shutdown_timeout);
+    toi_server::shutdown::serve(
+        listener,
+        router,
+        toi_server::shutdown::signal(),
+        drain_timeout,
+    )
+    .await?;
+
+    // Release the pool so its connections are closed before exiting rather
+    // than being cut off by the process exiting.
+    drop(state);
+    info!("shut down");
     Ok(())
 }
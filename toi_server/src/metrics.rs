@@ -0,0 +1,297 @@
+use axum::{
+    extract::{MatchedPath, Request, State},
+    middleware::Next,
+    response::Response,
+};
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::sync::{Arc, Mutex, PoisonError};
+use std::time::{Duration, Instant};
+
+use crate::models::{pool::PoolStatus, state::ToiState};
+
+// Upper bounds in seconds of the latency histogram buckets. They span quick
+// database lookups up to long generations.
+const BUCKETS: [f64; 12] = [
+    0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0, 30.0,
+];
+
+#[derive(Default)]
+struct Histogram {
+    buckets: [u64; BUCKETS.len()],
+    count: u64,
+    sum: f64,
+}
+
+impl Histogram {
+    fn observe(&mut self, elapsed: Duration) {
+        let seconds = elapsed.as_secs_f64();
+        for (bucket, bound) in self.buckets.iter_mut().zip(BUCKETS) {
+            if seconds <= bound {
+                *bucket += 1;
+            }
+        }
+        self.count += 1;
+        self.sum += seconds;
+    }
+
+    /// Write the histogram's series with the given labels, which are
+    /// already formatted.
+    fn render(&self, out: &mut String, name: &str, labels: &str) {
+        for (bucket, bound) in self.buckets.iter().zip(BUCKETS) {
+            let _ = writeln!(out, "{name}_bucket{{{labels},le=\"{bound}\"}} {bucket}");
+        }
+        let _ = writeln!(out, "{name}_bucket{{{labels},le=\"+Inf\"}} {}", self.count);
+        let _ = writeln!(out, "{name}_sum{{{labels}}} {}", self.sum);
+        let _ = writeln!(out, "{name}_count{{{labels}}} {}", self.count);
+    }
+}
+
+#[derive(Default)]
+struct Registry {
+    // Keyed by API and outcome.
+    model_requests: BTreeMap<(&'static str, &'static str), Histogram>,
+    // Keyed by method, route, and status code.
+    http_requests: BTreeMap<(String, String, u16), Histogram>,
+}
+
+/// In-process registry of request counts and latencies for the model APIs
+/// and the server's own routes. Nothing is recorded if metrics are
+/// disabled.
+#[derive(Clone, Default)]
+pub struct Metrics {
+    registry: Option<Arc<Mutex<Registry>>>,
+}
+
+impl Metrics {
+    #[must_use]
+    pub fn new(enabled: bool) -> Self {
+        Self {
+            registry: enabled.then(|| Arc::new(Mutex::new(Registry::default()))),
+        }
+    }
+
+    #[must_use]
+    pub fn is_enabled(&self) -> bool {
+        self.registry.is_some()
+    }
+
+    /// Record a call to a model API, like "embedding" or "reranking".
+    pub fn observe_model_request(&self, api: &'static str, success: bool, elapsed: Duration) {
+        if let Some(registry) = &self.registry {
+            let outcome = if success { "success" } else { "error" };
+            registry
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner)
+                .model_requests
+                .entry((api, outcome))
+                .or_default()
+                .observe(elapsed);
+        }
+    }
+
+    /// Record a request to one of the server's routes.
+    pub fn observe_http_request(&self, method: &str, route: &str, status: u16, elapsed: Duration) {
+        if let Some(registry) = &self.registry {
+            registry
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner)
+                .http_requests
+                .entry((method.to_string(), route.to_string(), status))
+                .or_default()
+                .observe(elapsed);
+        }
+    }
+
+    /// Render everything recorded so far along with the database connection
+    /// pool's status in the Prometheus text format. Returns `None` if metrics
+    /// are disabled.
+    #[must_use]
+    pub fn render(&self, pool_status: &PoolStatus) -> Option<String> {
+        let registry = self
+            .registry
+            .as_ref()?
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner);
+        let mut out = String::new();
+
+        out.push_str("# HELP toi_model_requests_total Number of requests made to model APIs.\n");
+        out.push_str("# TYPE toi_model_requests_total counter\n");
+        for ((api, outcome), histogram) in &registry.model_requests {
+            let _ = writeln!(
+                out,
+                "toi_model_requests_total{{api=\"{api}\",outcome=\"{outcome}\"}} {}",
+                histogram.count
+            );
+        }
+        out.push_str(
+            "# HELP toi_model_request_duration_seconds Seconds until model APIs responded.\n",
+        );
+        out.push_str("# TYPE toi_model_request_duration_seconds histogram\n");
+        for ((api, outcome), histogram) in &registry.model_requests {
+            let labels = format!("api=\"{api}\",outcome=\"{outcome}\"");
+            histogram.render(&mut out, "toi_model_request_duration_seconds", &labels);
+        }
+
+        out.push_str("# HELP toi_http_requests_total Number of requests made to the server.\n");
+        out.push_str("# TYPE toi_http_requests_total counter\n");
+        for ((method, route, status), histogram) in &registry.http_requests {
+            let _ = writeln!(
+                out,
+                "toi_http_requests_total{{method=\"{method}\",route=\"{}\",status=\"{status}\"}} {}",
+                escape_label(route),
+                histogram.count
+            );
+        }
+        out.push_str(
+            "# HELP toi_http_request_duration_seconds Seconds until the server responded.\n",
+        );
+        out.push_str("# TYPE toi_http_request_duration_seconds histogram\n");
+        for ((method, route, status), histogram) in &registry.http_requests {
+            let labels = format!(
+                "method=\"{method}\",route=\"{}\",status=\"{status}\"",
+                escape_label(route)
+            );
+            histogram.render(&mut out, "toi_http_request_duration_seconds", &labels);
+        }
+
+        out.push_str("# HELP toi_db_pool_connections Number of open database connections.\n");
+        out.push_str("# TYPE toi_db_pool_connections gauge\n");
+        let _ = writeln!(out, "toi_db_pool_connections {}", pool_status.connections);
+        out.push_str(
+            "# HELP toi_db_pool_idle_connections Number of open database connections that aren't in use.\n",
+        );
+        out.push_str("# TYPE toi_db_pool_idle_connections gauge\n");
+        let _ = writeln!(
+            out,
+            "toi_db_pool_idle_connections {}",
+            pool_status.idle_connections
+        );
+        out.push_str(
+            "# HELP toi_db_pool_gets_total Number of times a database connection was requested.\n",
+        );
+        out.push_str("# TYPE toi_db_pool_gets_total counter\n");
+        let _ = writeln!(
+            out,
+            "toi_db_pool_gets_total{{outcome=\"direct\"}} {}",
+            pool_status.get_direct
+        );
+        let _ = writeln!(
+            out,
+            "toi_db_pool_gets_total{{outcome=\"waited\"}} {}",
+            pool_status.get_waited
+        );
+        let _ = writeln!(
+            out,
+            "toi_db_pool_gets_total{{outcome=\"timed_out\"}} {}",
+            pool_status.get_timed_out
+        );
+        out.push_str(
+            "# HELP toi_db_pool_wait_seconds_total Seconds spent waiting for database connections.\n",
+        );
+        out.push_str("# TYPE toi_db_pool_wait_seconds_total counter\n");
+        let _ = writeln!(
+            out,
+            "toi_db_pool_wait_seconds_total {}",
+            Duration::from_millis(pool_status.get_wait_time_ms).as_secs_f64()
+        );
+        Some(out)
+    }
+}
+
+/// Escape a label value so it's valid in the Prometheus text format.
+fn escape_label(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+/// Middleware for recording how many requests each route gets and how long
+/// they take. For streamed responses, that's how long until the response
+/// started.
+pub async fn track_requests(
+    State(state): State<ToiState>,
+    request: Request,
+    next: Next,
+) -> Response {
+    let method = request.method().to_string();
+    let route = request
+        .extensions()
+        .get::<MatchedPath>()
+        .map_or_else(|| "unmatched".to_string(), |path| path.as_str().to_string());
+    let start = Instant::now();
+    let response = next.run(request).await;
+    state.metrics.observe_http_request(
+        &method,
+        &route,
+        response.status().as_u16(),
+        start.elapsed(),
+    );
+    response
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::Metrics;
+    use crate::models::pool::PoolStatus;
+
+    fn pool_status() -> PoolStatus {
+        PoolStatus {
+            connections: 2,
+            idle_connections: 1,
+            get_direct: 5,
+            get_waited: 3,
+            get_timed_out: 1,
+            get_wait_time_ms: 1500,
+            connections_created: 2,
+        }
+    }
+
+    #[test]
+    fn rendering_metrics() {
+        let metrics = Metrics::new(true);
+        metrics.observe_model_request("embedding", true, Duration::from_millis(20));
+        metrics.observe_model_request("embedding", true, Duration::from_secs(2));
+        metrics.observe_model_request("reranking", false, Duration::from_millis(1));
+        metrics.observe_http_request("POST", "/notes", 201, Duration::from_millis(30));
+        let rendered = metrics
+            .render(&pool_status())
+            .expect("metrics should be enabled");
+        let lines: Vec<&str> = rendered.lines().collect();
+
+        // Counters are kept per outcome.
+        assert!(
+            lines.contains(&"toi_model_requests_total{api=\"embedding\",outcome=\"success\"} 2")
+        );
+        assert!(lines.contains(&"toi_model_requests_total{api=\"reranking\",outcome=\"error\"} 1"));
+
+        // Buckets are cumulative.
+        assert!(lines.contains(
+            &"toi_model_request_duration_seconds_bucket{api=\"embedding\",outcome=\"success\",le=\"0.025\"} 1"
+        ));
+        assert!(lines.contains(
+            &"toi_model_request_duration_seconds_bucket{api=\"embedding\",outcome=\"success\",le=\"2.5\"} 2"
+        ));
+        assert!(lines.contains(
+            &"toi_model_request_duration_seconds_bucket{api=\"embedding\",outcome=\"success\",le=\"+Inf\"} 2"
+        ));
+        assert!(lines.contains(
+            &"toi_http_requests_total{method=\"POST\",route=\"/notes\",status=\"201\"} 1"
+        ));
+
+        // Pool waits come from the pool's status.
+        assert!(lines.contains(&"toi_db_pool_gets_total{outcome=\"waited\"} 3"));
+        assert!(lines.contains(&"toi_db_pool_wait_seconds_total 1.5"));
+    }
+
+    #[test]
+    fn disabled_metrics() {
+        let metrics = Metrics::new(false);
+        metrics.observe_model_request("embedding", true, Duration::from_millis(20));
+        assert!(!metrics.is_enabled());
+        assert!(metrics.render(&pool_status()).is_none());
+    }
+}
@@ -1,19 +1,35 @@
 pub mod accounts;
 pub mod assistant;
 pub mod attendees;
+pub mod audit;
+pub mod capabilities;
 pub mod client;
 pub mod config;
 pub mod contacts;
+pub mod conversations;
 pub mod datetime;
+pub mod deletion;
+pub mod error;
 pub mod events;
+pub mod export;
+pub mod health;
+pub mod idempotency;
 pub mod news;
 pub mod notes;
 pub mod openapi;
+pub mod pagination;
+pub mod pending_actions;
 pub mod places;
+pub mod pool;
 pub mod prompts;
 pub mod recipes;
+pub mod reminders;
+pub mod sampling;
+pub mod search;
+pub mod seeding;
 pub mod state;
 pub mod tags;
 pub mod todos;
 pub mod transactions;
+pub mod validation;
 pub mod weather;
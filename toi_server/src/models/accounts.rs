@@ -4,9 +4,9 @@ use diesel::{Insertable, Queryable, Selectable};
 use pgvector::Vector;
 use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
-use utoipa::ToSchema;
+use utoipa::{IntoParams, ToSchema};
 
-use crate::utils;
+use crate::{models::deletion::DeleteFilters, utils};
 
 #[derive(Debug, Deserialize, PartialEq, Queryable, Selectable, Serialize, ToSchema)]
 #[diesel(table_name = crate::schema::bank_accounts)]
@@ -24,6 +24,7 @@ pub struct BankAccount {
 #[diesel(table_name = crate::schema::bank_accounts)]
 #[diesel(check_for_backend(diesel::pg::Pg))]
 pub struct NewBankAccount {
+    pub user_id: i32,
     pub description: String,
     pub embedding: Vector,
 }
@@ -34,7 +35,7 @@ pub struct NewBankAccountRequest {
     pub description: String,
 }
 
-#[derive(Builder, Deserialize, JsonSchema, Serialize, ToSchema)]
+#[derive(Builder, Clone, Deserialize, JsonSchema, Serialize, ToSchema)]
 pub struct BankAccountSearchParams {
     /// Select bank accounts using their database-generated IDs rather than searching
     /// for them.
@@ -59,4 +60,116 @@ pub struct BankAccountSearchParams {
     pub order_by: Option<utils::OrderBy>,
     /// Limit the max number of bank accounts to return from the search.
     pub limit: Option<i64>,
+    /// Skip this many matching bank accounts before returning results. Use with
+    /// the limit to page through many bank accounts.
+    pub offset: Option<i64>,
+}
+
+impl DeleteFilters for BankAccountSearchParams {
+    fn narrows(&self) -> bool {
+        self.ids.is_some()
+            || self.query.is_some()
+            || self.created_from.is_some()
+            || self.created_to.is_some()
+            || self.limit.is_some()
+    }
+}
+
+#[derive(Builder, Deserialize, JsonSchema, Serialize, ToSchema)]
+pub struct BankAccountMergeTarget {
+    /// Select the bank account to merge into using its database-generated
+    /// ID rather than searching for it.
+    pub id: Option<i32>,
+    /// User query string to compare embeddings against for finding the bank
+    /// account to merge into (e.g., "joint checking").
+    pub query: Option<String>,
+    /// Whether to match the query string more closely using a reranking -based
+    /// approach. `true` is useful for cases where the user is looking to match
+    /// to specific words or phrases, whereas `false` is useful for more broad
+    /// matching.
+    pub use_reranking_filter: Option<bool>,
+}
+
+#[derive(Builder, Deserialize, JsonSchema, Serialize, ToSchema)]
+pub struct UpdateBankAccountRequest {
+    /// Update a bank account using its database-generated ID rather than
+    /// searching for it.
+    pub id: Option<i32>,
+    /// New bank account description. Either this or `merge_into` is
+    /// required.
+    pub description: Option<String>,
+    /// Another bank account to move all of this bank account's transactions
+    /// into before deleting this one, which is useful for getting rid of
+    /// duplicate bank accounts. Either this or `description` is required.
+    pub merge_into: Option<BankAccountMergeTarget>,
+    /// User query string to compare embeddings against. Basically,
+    /// if the user is asking something like "what color is my jacket?",
+    /// then the query string should be something like "jacket color" or
+    /// the user's original question. This can be left empty to ignore
+    /// similarity search in cases where the user wants to filter by
+    /// other means or get all items.
+    pub query: Option<String>,
+    /// Whether to match the query string more closely using a reranking -based
+    /// approach. `true` is useful for cases where the user is looking to match
+    /// to specific words or phrases, whereas `false` is useful for more broad
+    /// matching.
+    pub use_reranking_filter: Option<bool>,
+    /// Filter on bank accounts created after this ISO formatted datetime.
+    pub created_from: Option<DateTime<Utc>>,
+    /// Filter on bank accounts created before this ISO formatted datetime.
+    pub created_to: Option<DateTime<Utc>>,
+    /// How to order results for retrieved bank accounts.
+    pub order_by: Option<utils::OrderBy>,
+}
+
+#[derive(Debug, Deserialize, PartialEq, Serialize, ToSchema)]
+pub struct UpdatedBankAccount {
+    /// Updated bank account, which is the one merged into for merges.
+    pub bank_account: BankAccount,
+    /// Number of transactions moved into the bank account from the one
+    /// merged into it.
+    pub moved_transaction_count: usize,
+}
+
+#[derive(Builder, Deserialize, IntoParams, JsonSchema, Serialize)]
+pub struct BankAccountBalanceParams {
+    /// Select a bank account using its database-generated ID rather than
+    /// searching for it first.
+    pub bank_account_id: Option<i32>,
+    /// User query string to compare embeddings against. Basically,
+    /// if the user is asking something like "what color is my jacket?",
+    /// then the query string should be something like "jacket color" or
+    /// the user's original question.
+    /// This can be left empty or null to ignore similarity search
+    /// in cases where the user wants to filter by other params
+    /// (e.g., get items by date or get all items).
+    pub bank_account_query: Option<String>,
+    /// Whether to match the query string more closely using a reranking -based
+    /// approach. `true` is useful for cases where the user is looking to match
+    /// to a specific phrase, name, or words.
+    pub bank_account_use_reranking_filter: Option<bool>,
+    /// Filter on bank accounts created after this ISO formatted datetime.
+    pub bank_account_created_from: Option<DateTime<Utc>>,
+    /// Filter on bank accounts created before this ISO formatted datetime.
+    pub bank_account_created_to: Option<DateTime<Utc>>,
+    /// How to order results for retrieved bank accounts.
+    pub bank_account_order_by: Option<utils::OrderBy>,
+    /// Only include transactions posted after this ISO formatted datetime.
+    pub posted_from: Option<DateTime<Utc>>,
+    /// Only include transactions posted before this ISO formatted datetime.
+    pub posted_to: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Deserialize, PartialEq, Serialize, ToSchema)]
+pub struct BankAccountBalance {
+    /// Matching bank account.
+    pub bank_account: BankAccount,
+    /// Sum of all transaction amounts within the window.
+    pub balance: f32,
+    /// Number of transactions within the window.
+    pub transaction_count: i64,
+    /// Datetime of the earliest transaction within the window in ISO format.
+    pub earliest_posted_at: Option<DateTime<Utc>>,
+    /// Datetime of the latest transaction within the window in ISO format.
+    pub latest_posted_at: Option<DateTime<Utc>>,
 }
@@ -1,15 +1,162 @@
-use axum::http::StatusCode;
-use reqwest::{Client, Method, Request};
+use chrono::{DateTime, Utc};
+use diesel::{Insertable, Queryable, Selectable};
+use reqwest::{Client, Method, Request, header};
 use serde::{Deserialize, Serialize, de::DeserializeOwned};
 use serde_json::Value;
+use std::fmt;
 use toi::{Message, MessageRole};
+use utoipa::ToSchema;
 
-use crate::models::client::ApiClientError;
+use crate::{
+    idempotency::IDEMPOTENCY_KEY_HEADER,
+    models::{
+        client::{ApiClientError, TokenUsage},
+        error::ToiError,
+    },
+    request_id::{PARENT_REQUEST_HEADER, RequestId},
+};
+
+/// Reply from the assistant returned all at once rather than streamed.
+#[derive(Debug, Deserialize, Serialize)]
+pub struct AssistantCompletion {
+    /// The assistant's reply.
+    pub content: String,
+    /// Token usage of all the model calls made for the reply.
+    pub token_usage: TokenUsage,
+    /// Last request the assistant made to the server's own endpoints to
+    /// fulfill the user's message, if any.
+    pub executed_request: Option<GeneratedRequest>,
+}
+
+/// Streamed reply from the assistant, kept so it can be fetched again if
+/// the client lost the response stream.
+#[derive(Debug, Deserialize, PartialEq, Queryable, Selectable, Serialize, ToSchema)]
+#[diesel(table_name = crate::schema::chat_responses)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+pub struct ChatResponse {
+    /// Response ID from the response stream's `x-toi-response-id` header.
+    pub id: String,
+    /// Whatever was streamed of the reply.
+    pub content: String,
+    /// Whether the whole reply was streamed. The client disconnecting or
+    /// the stream failing partway through leaves it incomplete.
+    pub complete: bool,
+    /// Datetime the response stream ended in ISO format.
+    pub created_at: DateTime<Utc>,
+}
+
+#[derive(Insertable)]
+#[diesel(table_name = crate::schema::chat_responses)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+pub struct NewChatResponse {
+    pub user_id: i32,
+    pub id: String,
+    pub content: String,
+    pub complete: bool,
+}
 
 #[derive(Debug, Deserialize)]
 pub struct GeneratedCommandExtraction {
     pub command: Option<String>,
     pub target: Option<String>,
+    /// Separate actions the user asks for in the order they should be done.
+    /// Messages with more than one step are fulfilled with a plan.
+    #[serde(default)]
+    pub steps: Vec<String>,
+}
+
+/// How a step of a plan went.
+#[derive(Debug, PartialEq)]
+pub enum StepStatus {
+    /// The step's request succeeded.
+    Succeeded,
+    /// The step stopped the plan for the given reason.
+    Failed(String),
+    /// The step deletes things, so it's waiting for the user to confirm it.
+    Pending,
+    /// The step wasn't tried because an earlier step stopped the plan or
+    /// the plan had too many steps.
+    Skipped,
+}
+
+/// How every step of a plan went, in order. It's added to the context so
+/// the summary covers what succeeded, what failed, and what was skipped
+/// rather than just the last response.
+#[derive(Debug, Default)]
+pub struct PlanReport {
+    pub steps: Vec<(String, StepStatus)>,
+}
+
+impl PlanReport {
+    pub fn push(&mut self, step: String, status: StepStatus) {
+        self.steps.push((step, status));
+    }
+
+    /// Mark the remaining steps as skipped.
+    pub fn skip(&mut self, steps: impl IntoIterator<Item = String>) {
+        self.steps
+            .extend(steps.into_iter().map(|step| (step, StepStatus::Skipped)));
+    }
+
+    /// The step that stopped the plan along with its number and why it
+    /// stopped the plan, if any did.
+    fn stopped_at(&self) -> Option<(usize, &str, &str)> {
+        self.steps
+            .iter()
+            .enumerate()
+            .find_map(|(i, (step, status))| match status {
+                StepStatus::Failed(reason) => Some((i + 1, step.as_str(), reason.as_str())),
+                StepStatus::Pending => Some((
+                    i + 1,
+                    step.as_str(),
+                    "it deletes things and is waiting for the user to confirm it",
+                )),
+                StepStatus::Succeeded | StepStatus::Skipped => None,
+            })
+    }
+}
+
+impl fmt::Display for PlanReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let num_steps = self.steps.len();
+        let num_skipped = self
+            .steps
+            .iter()
+            .filter(|(_, status)| *status == StepStatus::Skipped)
+            .count();
+        if let Some((step_number, step, reason)) = self.stopped_at() {
+            write!(
+                f,
+                "The plan stopped at step {step_number} of {num_steps} (`{step}`) because \
+                {reason}. None of the steps after it were done."
+            )?;
+        } else if num_skipped > 0 {
+            let num_done = num_steps - num_skipped;
+            write!(
+                f,
+                "Only the first {num_done} of {num_steps} steps were done because plans are \
+                limited to {num_done} steps."
+            )?;
+        } else {
+            write!(f, "All {num_steps} steps of the plan were done.")?;
+        }
+        write!(f, "\n\nHere's how each step went:")?;
+        for (i, (step, status)) in self.steps.iter().enumerate() {
+            let status = match status {
+                StepStatus::Succeeded => "succeeded".to_string(),
+                StepStatus::Failed(reason) => format!("failed because {reason}"),
+                StepStatus::Pending => "waiting for the user to confirm it".to_string(),
+                StepStatus::Skipped => "skipped".to_string(),
+            };
+            write!(f, "\n{}. `{step}`: {status}", i + 1)?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct GeneratedConfirmation {
+    pub confirmed: bool,
 }
 
 #[derive(Clone, Debug, Deserialize, Serialize)]
@@ -21,6 +168,18 @@ enum GeneratedMethod {
     Put,
 }
 
+impl GeneratedMethod {
+    /// Method as it's stored with the server's endpoints.
+    fn as_str(&self) -> &'static str {
+        match self {
+            GeneratedMethod::Delete => "DELETE",
+            GeneratedMethod::Get => "GET",
+            GeneratedMethod::Post => "POST",
+            GeneratedMethod::Put => "PUT",
+        }
+    }
+}
+
 impl From<GeneratedMethod> for Method {
     fn from(val: GeneratedMethod) -> Self {
         match val {
@@ -41,6 +200,28 @@ pub struct GeneratedRequest {
 }
 
 impl GeneratedRequest {
+    /// Whether the request deletes or purges items.
+    #[must_use]
+    pub fn is_destructive(&self) -> bool {
+        matches!(self.method, GeneratedMethod::Delete)
+            || self.path.ends_with("/delete")
+            || self.path.ends_with("/purge")
+    }
+
+    /// Search request for the items a deleting request would delete. Deleting
+    /// endpoints take the same parameters as their sibling search endpoints,
+    /// so the request is the same other than its path.
+    #[must_use]
+    pub fn to_preview(&self) -> Option<Self> {
+        let base_path = self.path.strip_suffix("/delete")?;
+        Some(Self {
+            method: GeneratedMethod::Post,
+            path: format!("{base_path}/search"),
+            params: self.params.clone(),
+            body: self.body.clone(),
+        })
+    }
+
     #[must_use]
     pub fn into_assistant_message(self) -> Message {
         Message {
@@ -49,13 +230,58 @@ impl GeneratedRequest {
         }
     }
 
+    /// Path and method of the endpoint the request is for.
     #[must_use]
-    pub fn to_localhost_http_request(&self, api_client: &Client, server_port: &u16) -> Request {
+    pub fn endpoint(&self) -> (&str, &'static str) {
+        (&self.path, self.method.as_str())
+    }
+
+    /// Build the request against the server itself. The ID of the request
+    /// that generated it is passed along so their logs can be tied together.
+    /// Paths that could point the request somewhere other than one of the
+    /// server's own endpoints are rejected.
+    ///
+    /// An idempotency key makes the add endpoints return what they added the
+    /// first time if the same request is sent again (e.g., when a turn is
+    /// retried).
+    ///
+    /// The `Authorization` header of the request that generated it is passed
+    /// along so it's made as the same user.
+    pub fn to_localhost_http_request(
+        &self,
+        api_client: &Client,
+        server_port: &u16,
+        parent_request_id: Option<RequestId>,
+        idempotency_key: Option<&str>,
+        authorization: Option<&header::HeaderValue>,
+    ) -> Result<Request, ToiError> {
+        check_path(&self.path).map_err(|reason| {
+            ToiError::ModelApi(format!(
+                "generated request path {:?} is invalid because {reason}",
+                self.path
+            ))
+        })?;
         let mut request_builder = api_client.request(
             self.method.clone().into(),
             format!("http://127.0.0.1:{server_port}{}", self.path),
         );
 
+        if let Some(parent_request_id) = parent_request_id {
+            request_builder = request_builder.header(
+                PARENT_REQUEST_HEADER.clone(),
+                parent_request_id.header_value(),
+            );
+        }
+
+        if let Some(idempotency_key) = idempotency_key {
+            request_builder =
+                request_builder.header(IDEMPOTENCY_KEY_HEADER.clone(), idempotency_key);
+        }
+
+        if let Some(authorization) = authorization {
+            request_builder = request_builder.header(header::AUTHORIZATION, authorization);
+        }
+
         if let Some(params) = &self.params {
             request_builder = request_builder.query(params);
         }
@@ -64,10 +290,272 @@ impl GeneratedRequest {
             request_builder = request_builder.json(&body);
         }
 
-        request_builder.build().expect("request should be valid")
+        let request = request_builder
+            .build()
+            .map_err(|err| ApiClientError::ResponseJson.into_response(&err))?;
+        if request.url().host_str() != Some("127.0.0.1") {
+            return Err(ToiError::ModelApi(format!(
+                "generated request path {:?} doesn't target the server",
+                self.path
+            )));
+        }
+        Ok(request)
     }
 }
 
-pub fn parse_generated_response<T: DeserializeOwned>(s: &str) -> Result<T, (StatusCode, String)> {
-    serde_json::from_str::<T>(s).map_err(|err| ApiClientError::ResponseJson.into_response(&err))
+/// A generated request along with a question for the user, which is only
+/// asked for when another API scored too close to tell which the user means.
+#[derive(Debug, Deserialize)]
+pub struct GeneratedClarifiableRequest {
+    #[serde(flatten)]
+    pub request: GeneratedRequest,
+    clarification: Option<String>,
+}
+
+impl GeneratedClarifiableRequest {
+    /// The question for the user, if one was asked instead of making the
+    /// request.
+    #[must_use]
+    pub fn clarification(&self) -> Option<&str> {
+        self.clarification
+            .as_deref()
+            .map(str::trim)
+            .filter(|clarification| !clarification.is_empty())
+    }
+}
+
+/// Check that a generated path is a plain path on the server itself (e.g.,
+/// "/notes/search"), returning why it isn't otherwise. Anything that could
+/// change the request's host or escape the API, like full URLs, user info,
+/// or "..", is rejected.
+fn check_path(path: &str) -> Result<(), String> {
+    if !path.starts_with('/') || path.starts_with("//") {
+        return Err("it must start with a single / and not be a full URL".to_string());
+    }
+    if path.contains("..") {
+        return Err("it has a ..".to_string());
+    }
+    if let Some(c) = path
+        .chars()
+        .find(|c| !c.is_ascii_alphanumeric() && !matches!(c, '/' | '-' | '_' | '.'))
+    {
+        return Err(format!("it has a character that isn't allowed ({c:?})"));
+    }
+    Ok(())
+}
+
+/// Find the JSON object within a model's output, which is the span from the
+/// first `{` to its matching `}`. Braces within strings are skipped.
+fn extract_json_object(s: &str) -> Option<&str> {
+    let start = s.find('{')?;
+    let mut depth = 0;
+    let mut in_string = false;
+    let mut escaped = false;
+    for (i, c) in s[start..].char_indices() {
+        if in_string {
+            if escaped {
+                escaped = false;
+            } else if c == '\\' {
+                escaped = true;
+            } else if c == '"' {
+                in_string = false;
+            }
+            continue;
+        }
+        match c {
+            '"' => in_string = true,
+            '{' => depth += 1,
+            '}' => {
+                depth -= 1;
+                if depth == 0 {
+                    return Some(&s[start..=start + i]);
+                }
+            }
+            _ => {}
+        }
+    }
+    None
+}
+
+/// Parse a model's structured output. Models sometimes wrap the JSON object
+/// in code fences or surround it with prose despite the response format, so
+/// the JSON object within the output is parsed if the output as a whole
+/// isn't valid.
+pub fn parse_generated_response<T: DeserializeOwned>(s: &str) -> Result<T, ToiError> {
+    serde_json::from_str::<T>(s)
+        .or_else(|err| {
+            extract_json_object(s)
+                .and_then(|json| serde_json::from_str::<T>(json).ok())
+                .ok_or(err)
+        })
+        .map_err(|err| ApiClientError::ResponseJson.into_response(&err))
+}
+
+#[cfg(test)]
+mod tests {
+    use reqwest::{Client, header};
+
+    use super::{
+        GeneratedClarifiableRequest, GeneratedCommandExtraction, GeneratedConfirmation,
+        GeneratedMethod, GeneratedRequest, PlanReport, StepStatus, parse_generated_response,
+    };
+    use crate::models::error::ToiError;
+
+    fn generated_request(path: &str) -> GeneratedRequest {
+        GeneratedRequest {
+            method: GeneratedMethod::Post,
+            path: path.to_string(),
+            params: None,
+            body: Some(serde_json::json!({"query": "groceries"})),
+        }
+    }
+
+    #[test]
+    fn building_localhost_requests() {
+        let client = Client::new();
+        let request = generated_request("/notes/search")
+            .to_localhost_http_request(&client, &6969, None, None, None)
+            .expect("path should be valid");
+        assert_eq!(request.url().as_str(), "http://127.0.0.1:6969/notes/search");
+        assert_eq!(request.method(), reqwest::Method::POST);
+        assert!(!request.headers().contains_key("idempotency-key"));
+
+        let request = generated_request("/notes")
+            .to_localhost_http_request(&client, &6969, None, Some("turn-1"), None)
+            .expect("path should be valid");
+        assert_eq!(
+            request
+                .headers()
+                .get("idempotency-key")
+                .and_then(|value| value.to_str().ok()),
+            Some("turn-1")
+        );
+        assert!(!request.headers().contains_key("authorization"));
+
+        let authorization = header::HeaderValue::from_static("Bearer token");
+        let request = generated_request("/notes/search")
+            .to_localhost_http_request(&client, &6969, None, None, Some(&authorization))
+            .expect("path should be valid");
+        assert_eq!(request.headers().get("authorization"), Some(&authorization));
+
+        let invalid = [
+            "http://evil.example/x",
+            "//evil.example/x",
+            "@evil.example/x",
+            "/notes@evil.example",
+            "notes/search",
+            "/notes/../admin",
+            "/notes/search?query=x",
+            "/notes/search#x",
+            "/notes\\search",
+            "/notes search",
+            "/notes:80/search",
+            "",
+        ];
+        for path in invalid {
+            let result =
+                generated_request(path).to_localhost_http_request(&client, &6969, None, None, None);
+            assert!(matches!(result, Err(ToiError::ModelApi(_))), "{path}");
+        }
+    }
+
+    #[test]
+    fn parsing_generated_responses() {
+        let outputs = [
+            r#"{"confirmed": true}"#,
+            "```json\n{\"confirmed\": true}\n```",
+            "```\n{\"confirmed\": true}\n```",
+            "Sure! Here's the JSON:\n{\"confirmed\": true}",
+            "{\"confirmed\": true}\nThe user confirmed the action.",
+        ];
+        for output in outputs {
+            let confirmation = parse_generated_response::<GeneratedConfirmation>(output)
+                .expect("output should be parseable");
+            assert!(confirmation.confirmed, "{output}");
+        }
+
+        // Braces within strings don't end the JSON object early.
+        let output = r#"Here you go: {"command": "add a note saying {hi}", "target": null} Done!"#;
+        let extraction = parse_generated_response::<GeneratedCommandExtraction>(output)
+            .expect("output should be parseable");
+        assert_eq!(
+            extraction.command.as_deref(),
+            Some("add a note saying {hi}")
+        );
+
+        let outputs = [
+            "I'm not sure what you mean.",
+            "```json\n{\"confirmed\": tru\n```",
+            "{\"confirmed\": true",
+            r#"{"confirmed": "maybe"}"#,
+        ];
+        for output in outputs {
+            let result = parse_generated_response::<GeneratedConfirmation>(output);
+            assert!(matches!(result, Err(ToiError::ModelApi(_))), "{output}");
+        }
+    }
+
+    #[test]
+    fn parsing_clarifications() {
+        let output = r#"{"path": "/notes", "method": "POST", "body": {"content": "milk"}}"#;
+        let generated = parse_generated_response::<GeneratedClarifiableRequest>(output)
+            .expect("output should be parseable");
+        assert_eq!(generated.request.endpoint(), ("/notes", "POST"));
+        assert_eq!(generated.clarification(), None);
+
+        let output = r#"{"path": "/notes", "method": "POST", "clarification": "  "}"#;
+        let generated = parse_generated_response::<GeneratedClarifiableRequest>(output)
+            .expect("output should be parseable");
+        assert_eq!(generated.clarification(), None);
+
+        let output = r#"{
+            "path": "/notes",
+            "method": "POST",
+            "clarification": "Do you want a note or a todo?"
+        }"#;
+        let generated = parse_generated_response::<GeneratedClarifiableRequest>(output)
+            .expect("output should be parseable");
+        assert_eq!(
+            generated.clarification(),
+            Some("Do you want a note or a todo?")
+        );
+    }
+
+    #[test]
+    fn reporting_plans() {
+        let mut report = PlanReport::default();
+        report.push("add a note".to_string(), StepStatus::Succeeded);
+        report.push("add a todo".to_string(), StepStatus::Succeeded);
+        assert_eq!(
+            report.to_string(),
+            "All 2 steps of the plan were done.\n\n\
+            Here's how each step went:\n\
+            1. `add a note`: succeeded\n\
+            2. `add a todo`: succeeded"
+        );
+
+        let mut report = PlanReport::default();
+        report.push("add a note".to_string(), StepStatus::Succeeded);
+        report.push(
+            "add a todo".to_string(),
+            StepStatus::Failed("its request failed with status 422".to_string()),
+        );
+        report.skip(["add an event".to_string()]);
+        assert_eq!(
+            report.to_string(),
+            "The plan stopped at step 2 of 3 (`add a todo`) because its request failed with \
+            status 422. None of the steps after it were done.\n\n\
+            Here's how each step went:\n\
+            1. `add a note`: succeeded\n\
+            2. `add a todo`: failed because its request failed with status 422\n\
+            3. `add an event`: skipped"
+        );
+
+        let mut report = PlanReport::default();
+        report.push("add a note".to_string(), StepStatus::Succeeded);
+        report.skip(["add a todo".to_string()]);
+        assert!(report.to_string().starts_with(
+            "Only the first 1 of 2 steps were done because plans are limited to 1 steps."
+        ));
+    }
 }
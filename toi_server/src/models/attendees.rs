@@ -5,7 +5,11 @@ use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 use utoipa::ToSchema;
 
-use crate::{models::contacts::Contact, models::events::Event, utils};
+use crate::{
+    models::contacts::Contact,
+    models::events::{Event, EventOrderBy},
+    utils,
+};
 
 #[derive(Insertable, Queryable, Selectable)]
 #[diesel(table_name = crate::schema::event_attendees)]
@@ -23,6 +27,35 @@ pub struct Attendees {
     pub contacts: Vec<Contact>,
 }
 
+#[derive(Debug, Deserialize, PartialEq, Serialize, ToSchema)]
+pub struct ContactEvents {
+    /// Matching contact.
+    pub contact: Contact,
+    /// Events the contact is attending, earliest first.
+    pub events: Vec<Event>,
+}
+
+#[derive(Builder, Deserialize, JsonSchema, Serialize, ToSchema)]
+pub struct ContactEventSearchParams {
+    /// Select a contact using their database-generated ID rather than
+    /// searching for them first.
+    pub contact_id: Option<i32>,
+    /// User query string to compare embeddings against. Basically,
+    /// if the user is asking something like "what events is Alice going
+    /// to?", then the query string should be something like "Alice".
+    pub contact_query: Option<String>,
+    /// Whether to match the query string more closely using a reranking -based
+    /// approach. `true` is useful for cases where the user is looking to match
+    /// to a specific phrase, name, or words.
+    pub contact_use_reranking_filter: Option<bool>,
+    /// Filter on events, or repeats of events, still going on at or after
+    /// this ISO formatted datetime.
+    pub event_occurs_from: Option<DateTime<Utc>>,
+    /// Filter on events, or repeats of events, starting at or before this
+    /// ISO formatted datetime.
+    pub event_occurs_to: Option<DateTime<Utc>>,
+}
+
 #[derive(Builder, Deserialize, JsonSchema, Serialize, ToSchema)]
 pub struct AttendeeSearchParams {
     /// Select an event using its database-generated IDs rather than
@@ -52,7 +85,7 @@ pub struct AttendeeSearchParams {
     /// or day of `event_day`.
     pub event_day_falls_on: Option<utils::DateFallsOn>,
     /// How to order results for retrieved events.
-    pub event_order_by: Option<utils::OrderBy>,
+    pub event_order_by: Option<EventOrderBy>,
     /// Search contacts using their database-generated IDs rather than
     /// searching for them first.
     pub contact_ids: Option<Vec<i32>>,
@@ -0,0 +1,131 @@
+use bon::Builder;
+use chrono::{DateTime, Utc};
+use diesel::{
+    AsExpression, FromSqlRow, Insertable, Queryable, Selectable,
+    deserialize::{self, FromSql},
+    pg::{Pg, PgValue},
+    serialize::{self, Output, ToSql},
+    sql_types::Text,
+};
+use serde::{Deserialize, Serialize};
+use utoipa::{IntoParams, ToSchema};
+
+use crate::models::client::TokenUsage;
+
+#[derive(
+    AsExpression, Clone, Copy, Debug, Deserialize, FromSqlRow, PartialEq, Serialize, ToSchema,
+)]
+#[diesel(sql_type = Text)]
+#[serde(rename_all = "kebab-case")]
+pub enum AuditPurpose {
+    /// Extracting commands from the user's message or checking whether the
+    /// user confirmed a pending action.
+    Classification,
+    /// Converting a command into an HTTP request for a matched API.
+    RequestGeneration,
+    /// Summarizing an API's response for the user.
+    Summary,
+    /// Responding like a normal chat assistant when no API matched.
+    Chat,
+}
+
+impl ToSql<Text, Pg> for AuditPurpose {
+    fn to_sql<'b>(&'b self, out: &mut Output<'b, '_, Pg>) -> serialize::Result {
+        let value = match self {
+            Self::Classification => "classification",
+            Self::RequestGeneration => "request-generation",
+            Self::Summary => "summary",
+            Self::Chat => "chat",
+        };
+        <str as ToSql<Text, Pg>>::to_sql(value, &mut out.reborrow())
+    }
+}
+
+impl FromSql<Text, Pg> for AuditPurpose {
+    fn from_sql(bytes: PgValue<'_>) -> deserialize::Result<Self> {
+        match bytes.as_bytes() {
+            b"classification" => Ok(Self::Classification),
+            b"request-generation" => Ok(Self::RequestGeneration),
+            b"summary" => Ok(Self::Summary),
+            b"chat" => Ok(Self::Chat),
+            _ => Err("unrecognized audit purpose".into()),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, PartialEq, Queryable, Selectable, Serialize, ToSchema)]
+#[diesel(table_name = crate::schema::generation_audit)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+pub struct GenerationAudit {
+    /// Unique audit entry ID.
+    pub id: i32,
+    /// What the model call was for.
+    pub purpose: AuditPurpose,
+    /// Hex-encoded SHA-256 hash of the rendered system prompt.
+    pub system_prompt_hash: String,
+    /// Path of the API the call was for, if it was for one.
+    pub api_path: Option<String>,
+    /// Method of the API the call was for, if it was for one.
+    pub api_method: Option<String>,
+    /// Rerank score of the API the call was for, if it was for one.
+    pub rerank_score: Option<f64>,
+    /// Raw output of the model.
+    pub output: String,
+    /// Number of prompt tokens used, if the model API reported it.
+    pub prompt_tokens: Option<i32>,
+    /// Number of completion tokens used, if the model API reported it.
+    pub completion_tokens: Option<i32>,
+    /// How long the call took in milliseconds.
+    pub latency_ms: i32,
+    /// Datetime the call was made in ISO format.
+    pub created_at: DateTime<Utc>,
+}
+
+#[derive(Builder, Clone, Insertable)]
+#[diesel(table_name = crate::schema::generation_audit)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+pub struct NewGenerationAudit {
+    pub user_id: i32,
+    pub purpose: AuditPurpose,
+    pub system_prompt_hash: String,
+    pub api_path: Option<String>,
+    pub api_method: Option<String>,
+    pub rerank_score: Option<f64>,
+    #[builder(default)]
+    pub output: String,
+    pub prompt_tokens: Option<i32>,
+    pub completion_tokens: Option<i32>,
+    #[builder(default)]
+    pub latency_ms: i32,
+}
+
+impl NewGenerationAudit {
+    /// Fill in what's only known once the model call finishes.
+    #[must_use]
+    pub fn finish(
+        self,
+        output: String,
+        usage: Option<TokenUsage>,
+        latency: std::time::Duration,
+    ) -> Self {
+        Self {
+            output,
+            prompt_tokens: usage.and_then(|usage| i32::try_from(usage.prompt_tokens).ok()),
+            completion_tokens: usage.and_then(|usage| i32::try_from(usage.completion_tokens).ok()),
+            latency_ms: i32::try_from(latency.as_millis()).unwrap_or(i32::MAX),
+            ..self
+        }
+    }
+}
+
+#[derive(Builder, Default, Deserialize, IntoParams, Serialize)]
+pub struct AuditQueryParams {
+    /// Only get entries for model calls with this purpose.
+    pub purpose: Option<AuditPurpose>,
+    /// Only get entries created on or after this ISO formatted datetime.
+    pub from: Option<DateTime<Utc>>,
+    /// Only get entries created on or before this ISO formatted datetime.
+    pub to: Option<DateTime<Utc>>,
+    /// Limit the max number of entries to return. Defaults to 100.
+    pub limit: Option<i64>,
+}
@@ -0,0 +1,83 @@
+use axum::body::Bytes;
+use std::collections::BTreeSet;
+use std::sync::{Arc, PoisonError, RwLock};
+use toi::Capability;
+
+/// Serialized capabilities response, kept around so it doesn't need to be
+/// rebuilt for every request. It's cleared whenever the endpoints the
+/// assistant can use are replaced.
+#[derive(Clone, Default)]
+pub struct CapabilitiesCache {
+    body: Arc<RwLock<Option<Bytes>>>,
+}
+
+impl CapabilitiesCache {
+    #[must_use]
+    pub fn get(&self) -> Option<Bytes> {
+        self.body
+            .read()
+            .unwrap_or_else(PoisonError::into_inner)
+            .clone()
+    }
+
+    pub fn set(&self, body: Bytes) {
+        *self.body.write().unwrap_or_else(PoisonError::into_inner) = Some(body);
+    }
+
+    pub fn clear(&self) {
+        *self.body.write().unwrap_or_else(PoisonError::into_inner) = None;
+    }
+}
+
+/// Distinct top-level path segments of the endpoints, in sorted order.
+#[must_use]
+pub fn domains(endpoints: &[Capability]) -> Vec<String> {
+    endpoints
+        .iter()
+        .filter_map(|endpoint| endpoint.path.trim_start_matches('/').split('/').next())
+        .filter(|domain| !domain.is_empty())
+        .map(str::to_string)
+        .collect::<BTreeSet<String>>()
+        .into_iter()
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::body::Bytes;
+    use toi::Capability;
+
+    use super::{CapabilitiesCache, domains};
+
+    fn capability(path: &str) -> Capability {
+        Capability {
+            path: path.to_string(),
+            method: "GET".to_string(),
+            summary: String::new(),
+            has_params: false,
+            has_body: false,
+        }
+    }
+
+    #[test]
+    fn collecting_domains() {
+        let endpoints = [
+            capability("/notes/search"),
+            capability("/events"),
+            capability("/notes"),
+            capability("/banking/accounts"),
+            capability("/"),
+        ];
+        assert_eq!(domains(&endpoints), vec!["banking", "events", "notes"]);
+    }
+
+    #[test]
+    fn caching_capabilities() {
+        let cache = CapabilitiesCache::default();
+        assert_eq!(cache.get(), None);
+        cache.clone().set(Bytes::from_static(b"[]"));
+        assert_eq!(cache.get(), Some(Bytes::from_static(b"[]")));
+        cache.clear();
+        assert_eq!(cache.get(), None);
+    }
+}
@@ -1,9 +1,13 @@
-use crate::utils;
-use axum::http::StatusCode;
+use crate::{
+    models::{error::ToiError, sampling::Sampling},
+    utils,
+};
 use bon::Builder;
+use pgvector::Vector;
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 use std::fmt;
+use std::ops;
 use toi::Message;
 
 #[derive(Builder, Clone, Deserialize)]
@@ -31,9 +35,34 @@ pub struct EmbeddingRequest {
     pub input: String,
 }
 
+/// Embeddings made while handling a single request, keyed by their
+/// prompt-formatted input so nested searches don't embed the same text twice.
+#[derive(Default)]
+pub struct EmbeddingCache {
+    embeddings: HashMap<String, Vector>,
+}
+
+impl EmbeddingCache {
+    #[must_use]
+    pub fn get(&self, input: &str) -> Option<&Vector> {
+        self.embeddings.get(input)
+    }
+
+    pub fn insert(&mut self, input: String, embedding: Vector) {
+        self.embeddings.insert(input, embedding);
+    }
+}
+
+#[derive(Serialize)]
+pub struct BatchEmbeddingRequest {
+    pub input: Vec<String>,
+}
+
 #[derive(Deserialize)]
 pub struct EmbeddingData {
     pub embedding: Vec<f32>,
+    #[serde(default)]
+    pub index: usize,
 }
 
 #[derive(Deserialize)]
@@ -72,6 +101,8 @@ pub struct StreamOptions {
 #[derive(Serialize)]
 pub struct StreamingGenerationRequest {
     pub messages: Vec<Message>,
+    #[serde(flatten)]
+    pub sampling: Sampling,
     stream: bool,
     stream_options: StreamOptions,
 }
@@ -81,12 +112,18 @@ impl StreamingGenerationRequest {
     pub fn new(messages: Vec<Message>) -> Self {
         Self {
             messages,
+            sampling: Sampling::default(),
             stream: true,
             stream_options: StreamOptions {
                 include_usage: true,
             },
         }
     }
+
+    #[must_use]
+    pub fn with_sampling(self, sampling: Sampling) -> Self {
+        Self { sampling, ..self }
+    }
 }
 
 #[derive(Deserialize, Serialize)]
@@ -94,9 +131,47 @@ pub struct Choice {
     pub message: Message,
 }
 
+#[derive(Clone, Copy, Debug, Default, Deserialize, PartialEq, Serialize)]
+pub struct TokenUsage {
+    pub prompt_tokens: u32,
+    pub completion_tokens: u32,
+}
+
+impl ops::AddAssign for TokenUsage {
+    fn add_assign(&mut self, other: Self) {
+        self.prompt_tokens += other.prompt_tokens;
+        self.completion_tokens += other.completion_tokens;
+    }
+}
+
+impl ops::SubAssign for TokenUsage {
+    fn sub_assign(&mut self, other: Self) {
+        self.prompt_tokens = self.prompt_tokens.saturating_sub(other.prompt_tokens);
+        self.completion_tokens = self
+            .completion_tokens
+            .saturating_sub(other.completion_tokens);
+    }
+}
+
 #[derive(Deserialize, Serialize)]
 pub struct GenerationResponse {
     pub choices: Vec<Choice>,
+    pub usage: Option<TokenUsage>,
+}
+
+#[derive(Deserialize)]
+pub struct StreamingDelta {
+    pub content: Option<String>,
+}
+
+#[derive(Deserialize)]
+pub struct StreamingChoice {
+    pub delta: StreamingDelta,
+}
+
+#[derive(Deserialize)]
+pub struct GenerationResponseChunk {
+    pub choices: Vec<StreamingChoice>,
 }
 
 pub enum ApiClientError {
@@ -109,25 +184,19 @@ pub enum ApiClientError {
 
 impl ApiClientError {
     #[must_use]
-    pub fn into_response<T: fmt::Debug>(self, err: &T) -> (StatusCode, String) {
+    pub fn into_response<T: fmt::Debug>(self, err: &T) -> ToiError {
         match self {
-            Self::ApiConnection => (
-                StatusCode::BAD_GATEWAY,
-                format!("connection error when getting response: {err:?}"),
-            ),
-            Self::DefaultJson => (
-                StatusCode::BAD_REQUEST,
-                format!("couldn't serialize default JSON: {err:?}"),
-            ),
-            Self::EmptyResponse => (StatusCode::NOT_FOUND, format!("item not found: {err:?}")),
-            Self::RequestJson => (
-                StatusCode::UNPROCESSABLE_ENTITY,
-                format!("couldn't serialize request: {err:?}"),
-            ),
-            Self::ResponseJson => (
-                StatusCode::UNPROCESSABLE_ENTITY,
-                format!("couldn't deserialize response: {err:?}"),
-            ),
+            Self::ApiConnection => {
+                ToiError::Upstream(format!("connection error when getting response: {err:?}"))
+            }
+            Self::DefaultJson => {
+                ToiError::Validation(format!("couldn't serialize default JSON: {err:?}"))
+            }
+            Self::EmptyResponse => ToiError::NotFound(format!("item not found: {err:?}")),
+            Self::RequestJson => ToiError::ModelApi(format!("couldn't serialize request: {err:?}")),
+            Self::ResponseJson => {
+                ToiError::ModelApi(format!("couldn't deserialize response: {err:?}"))
+            }
         }
     }
 }
@@ -142,4 +211,7 @@ pub struct HttpClientConfig {
     pub params: HashMap<String, String>,
     #[serde(deserialize_with = "utils::deserialize_with_envsubst")]
     pub json: HashMap<String, String>,
+    /// Number of dimensions embeddings are expected to have. Only used for
+    /// the embedding API.
+    pub embedding_dimensions: Option<usize>,
 }
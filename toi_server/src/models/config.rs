@@ -1,6 +1,22 @@
-use crate::{models::client::HttpClientConfig, utils};
+use crate::{
+    models::{client::HttpClientConfig, sampling::Sampling},
+    utils,
+};
+use chrono_tz::Tz;
 use serde::Deserialize;
+use serde_json::Value;
+use std::collections::{HashMap, HashSet};
+use std::fmt;
 use std::net::SocketAddr;
+use std::path::PathBuf;
+
+// Cosine distances range from 0 for identical embeddings to 2 for opposite
+// ones.
+const MAX_DISTANCE: f64 = 2.0;
+
+fn default_audit_retention_days() -> u32 {
+    30
+}
 
 fn default_bind_addr() -> SocketAddr {
     "127.0.0.1:6969"
@@ -8,14 +24,168 @@ fn default_bind_addr() -> SocketAddr {
         .expect("default bind address should be valid")
 }
 
+fn default_confirm_destructive() -> bool {
+    true
+}
+
+fn default_api_candidate_limit() -> usize {
+    16
+}
+
+fn default_api_tie_margin() -> f64 {
+    0.05
+}
+
+fn default_contact_avatar_max_bytes() -> usize {
+    256 * 1024
+}
+
+fn default_contact_duplicate_similarity() -> f64 {
+    0.8
+}
+
+fn default_contact_import_max_bytes() -> usize {
+    1024 * 1024
+}
+
 fn default_distance_threshold() -> f64 {
     0.75
 }
 
+fn default_duplicate_distance_threshold() -> f64 {
+    0.1
+}
+
+fn default_embedding_concurrency() -> usize {
+    8
+}
+
+fn default_exclude_distance_threshold() -> f64 {
+    0.5
+}
+
+fn default_geocode_cache_ttl_days() -> u32 {
+    30
+}
+
+fn default_geocoding_url() -> String {
+    "https://nominatim.openstreetmap.org/search".to_string()
+}
+
+fn default_idempotency_ttl_hours() -> u32 {
+    24
+}
+
+fn default_keep_alive_interval() -> u64 {
+    10
+}
+
+fn default_max_batch_size() -> usize {
+    100
+}
+
+fn default_max_plan_steps() -> usize {
+    5
+}
+
+fn default_max_style_instructions_chars() -> usize {
+    500
+}
+
+fn default_note_chunk_chars() -> usize {
+    1000
+}
+
+fn default_note_chunk_overlap_chars() -> usize {
+    200
+}
+
+fn default_note_expiry_cleanup_interval_minutes() -> u64 {
+    60
+}
+
+fn default_note_expiry_grace_hours() -> u32 {
+    24
+}
+
+fn default_pending_action_ttl_minutes() -> u32 {
+    10
+}
+
+fn default_rate_limit_burst() -> u32 {
+    20
+}
+
+fn default_rate_limit_requests_per_minute() -> u32 {
+    60
+}
+
+fn default_readiness_timeout() -> u64 {
+    2
+}
+
+fn default_reminder_interval_hours() -> u64 {
+    24
+}
+
+fn default_rerank_max_document_chars() -> usize {
+    2000
+}
+
+fn default_rerank_max_documents() -> usize {
+    50
+}
+
+fn default_resume_max_bytes() -> usize {
+    1024 * 1024
+}
+
+fn default_resume_max_streams() -> usize {
+    64
+}
+
+fn default_resume_ttl_minutes() -> u64 {
+    5
+}
+
+fn default_response_retention_hours() -> u32 {
+    24
+}
+
+fn default_response_sampling() -> Sampling {
+    Sampling {
+        temperature: Some(0.7),
+        ..Sampling::default()
+    }
+}
+
+fn default_shutdown_timeout() -> u64 {
+    30
+}
+
 fn default_similarity_threshold() -> f64 {
     0.50
 }
 
+fn default_structured_sampling() -> Sampling {
+    Sampling {
+        temperature: Some(0.0),
+        ..Sampling::default()
+    }
+}
+
+fn default_swap_if_reversed() -> bool {
+    false
+}
+
+fn default_timezone() -> Tz {
+    Tz::UTC
+}
+
+fn default_trash_retention_days() -> u32 {
+    30
+}
+
 fn default_user_agent() -> String {
     "https://github.com/theOGognf/toi".to_string()
 }
@@ -34,14 +204,701 @@ pub struct ServerConfig {
     pub user_agent: String,
     #[serde(default = "default_distance_threshold")]
     pub distance_threshold: f64,
+    #[serde(default = "default_exclude_distance_threshold")]
+    pub exclude_distance_threshold: f64,
     #[serde(default = "default_similarity_threshold")]
     pub similarity_threshold: f64,
+    #[serde(default = "default_api_candidate_limit")]
+    pub api_candidate_limit: usize,
+    #[serde(default = "default_api_tie_margin")]
+    pub api_tie_margin: f64,
+    #[serde(default = "default_rerank_max_documents")]
+    pub rerank_max_documents: usize,
+    #[serde(default = "default_rerank_max_document_chars")]
+    pub rerank_max_document_chars: usize,
+    #[serde(default = "default_note_chunk_chars")]
+    pub note_chunk_chars: usize,
+    #[serde(default = "default_note_chunk_overlap_chars")]
+    pub note_chunk_overlap_chars: usize,
+    #[serde(default = "default_note_expiry_cleanup_interval_minutes")]
+    pub note_expiry_cleanup_interval_minutes: u64,
+    #[serde(default = "default_note_expiry_grace_hours")]
+    pub note_expiry_grace_hours: u32,
+    #[serde(default)]
+    pub duplicate_check_enabled: bool,
+    #[serde(default = "default_duplicate_distance_threshold")]
+    pub duplicate_distance_threshold: f64,
+    #[serde(default = "default_contact_duplicate_similarity")]
+    pub contact_duplicate_similarity: f64,
+    #[serde(default = "default_contact_import_max_bytes")]
+    pub contact_import_max_bytes: usize,
+    #[serde(default = "default_contact_avatar_max_bytes")]
+    pub contact_avatar_max_bytes: usize,
+    #[serde(default = "default_readiness_timeout")]
+    pub readiness_timeout: u64,
+    #[serde(default = "default_shutdown_timeout")]
+    pub shutdown_timeout: u64,
+    #[serde(default = "default_geocode_cache_ttl_days")]
+    pub geocode_cache_ttl_days: u32,
+    #[serde(default = "default_geocoding_url")]
+    pub geocoding_url: String,
+    #[serde(default = "default_embedding_concurrency")]
+    pub embedding_concurrency: usize,
+    #[serde(default = "default_max_batch_size")]
+    pub max_batch_size: usize,
+    #[serde(default = "default_max_plan_steps")]
+    pub max_plan_steps: usize,
+    #[serde(default = "default_max_style_instructions_chars")]
+    pub max_style_instructions_chars: usize,
+    #[serde(default = "default_confirm_destructive")]
+    pub confirm_destructive: bool,
+    #[serde(default = "default_pending_action_ttl_minutes")]
+    pub pending_action_ttl_minutes: u32,
+    #[serde(default = "default_idempotency_ttl_hours")]
+    pub idempotency_ttl_hours: u32,
+    #[serde(default = "default_swap_if_reversed")]
+    pub swap_if_reversed: bool,
+    #[serde(
+        default = "default_timezone",
+        deserialize_with = "utils::deserialize_timezone"
+    )]
+    pub timezone: Tz,
+    #[serde(default = "default_trash_retention_days")]
+    pub trash_retention_days: u32,
+    #[serde(default = "default_reminder_interval_hours")]
+    pub reminder_interval_hours: u64,
+    #[serde(default = "default_rate_limit_requests_per_minute")]
+    pub rate_limit_requests_per_minute: u32,
+    #[serde(default = "default_rate_limit_burst")]
+    pub rate_limit_burst: u32,
+    #[serde(default)]
+    pub rate_limit_by_user_agent: bool,
+    #[serde(default)]
+    pub audit_enabled: bool,
+    #[serde(default)]
+    pub metrics_enabled: bool,
+    #[serde(default = "default_audit_retention_days")]
+    pub audit_retention_days: u32,
+    #[serde(default = "default_resume_max_streams")]
+    pub resume_max_streams: usize,
+    #[serde(default = "default_resume_max_bytes")]
+    pub resume_max_bytes: usize,
+    #[serde(default = "default_resume_ttl_minutes")]
+    pub resume_ttl_minutes: u64,
+    #[serde(default = "default_keep_alive_interval")]
+    pub keep_alive_interval: u64,
+    #[serde(default = "default_response_retention_hours")]
+    pub response_retention_hours: u32,
+    #[serde(default = "default_structured_sampling")]
+    pub structured_sampling: Sampling,
+    #[serde(default = "default_response_sampling")]
+    pub response_sampling: Sampling,
+    #[serde(default)]
+    pub seed_data_path: Option<PathBuf>,
+    #[serde(default)]
+    pub seed_on_start: bool,
+}
+
+/// Settings for the database connection pool.
+#[derive(Debug, Deserialize)]
+#[serde(default)]
+pub struct DatabaseConfig {
+    /// Max number of connections the pool keeps open.
+    pub max_size: u32,
+    /// Number of idle connections the pool tries to keep open. Defaults to
+    /// `max_size`.
+    pub min_idle: Option<u32>,
+    /// Seconds to wait for a connection before giving up.
+    pub connection_timeout: u64,
+    /// Seconds before idle connections are closed. Idle connections are
+    /// never closed if this isn't given.
+    pub idle_timeout: Option<u64>,
+}
+
+impl Default for DatabaseConfig {
+    fn default() -> Self {
+        Self {
+            max_size: 10,
+            min_idle: None,
+            connection_timeout: 30,
+            idle_timeout: Some(600),
+        }
+    }
+}
+
+/// Settings for the HTTP client shared by everything that makes requests
+/// other than the model APIs (e.g., the assistant's requests to other
+/// endpoints and geocoding).
+#[derive(Debug, Deserialize)]
+#[serde(default)]
+pub struct ApiClientConfig {
+    /// Seconds to wait for a connection before giving up.
+    pub connect_timeout: u64,
+    /// Seconds to wait for a whole request before giving up. Requests never
+    /// time out if this isn't given.
+    pub timeout: Option<u64>,
+    /// Max number of idle connections kept open for each host.
+    pub pool_max_idle_per_host: usize,
+    /// Seconds before idle connections are closed.
+    pub pool_idle_timeout: u64,
+}
+
+impl Default for ApiClientConfig {
+    fn default() -> Self {
+        Self {
+            connect_timeout: 10,
+            timeout: Some(120),
+            pool_max_idle_per_host: 16,
+            pool_idle_timeout: 90,
+        }
+    }
+}
+
+/// An RSS feed news can be fetched from by name.
+#[derive(Clone, Debug, Deserialize)]
+pub struct NewsFeedConfig {
+    /// Name the feed is picked by (e.g., "Hacker News"). Requests for news
+    /// from a source are matched against feed names by relevance, so names
+    /// don't have to be given exactly.
+    pub name: String,
+    /// URL of the RSS feed.
+    pub url: String,
+    /// Whether news comes from this feed when requests don't ask for a
+    /// source or anything specific. Google News is used otherwise.
+    #[serde(default)]
+    pub default: bool,
+}
+
+/// Settings for getting news.
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct NewsConfig {
+    /// RSS feeds news can be fetched from in addition to Google News.
+    pub feeds: Vec<NewsFeedConfig>,
 }
 
 #[derive(Debug, Deserialize)]
 pub struct ToiConfig {
     pub server: ServerConfig,
+    /// Hex-encoded SHA-256 hashes of bearer tokens that are allowed to make
+    /// requests. Requests aren't authenticated if this is empty.
+    #[serde(default, deserialize_with = "utils::deserialize_with_envsubst")]
+    pub tokens: Vec<String>,
+    /// Hex-encoded SHA-256 hashes of bearer tokens keyed by the name of the
+    /// user they belong to. Each user only sees their own data, while tokens
+    /// under `tokens` belong to the default user.
+    #[serde(default, deserialize_with = "utils::deserialize_with_envsubst")]
+    pub users: HashMap<String, Vec<String>>,
+    /// Instruction prefixes for embedding search queries keyed by domain
+    /// (e.g., "notes"), overriding the defaults. An empty instruction means
+    /// queries are embedded without any prefixes.
+    #[serde(default)]
+    pub embedding_instructions: HashMap<String, String>,
+    #[serde(default)]
+    pub database: DatabaseConfig,
+    #[serde(default)]
+    pub api_client: ApiClientConfig,
+    #[serde(default)]
+    pub news: NewsConfig,
     pub embedding: HttpClientConfig,
     pub generation: HttpClientConfig,
     pub reranking: HttpClientConfig,
 }
+
+/// Every problem found with a config, so they can all be fixed at once
+/// rather than one restart at a time.
+#[derive(PartialEq)]
+pub struct ConfigError {
+    pub problems: Vec<String>,
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "invalid config:")?;
+        for problem in &self.problems {
+            write!(f, "\n- {problem}")?;
+        }
+        Ok(())
+    }
+}
+
+// Errors returned from `main` are printed with their debug representation,
+// so it's the same readable list.
+impl fmt::Debug for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Display::fmt(self, f)
+    }
+}
+
+impl std::error::Error for ConfigError {}
+
+impl ConfigError {
+    /// Error for any problems found, or nothing if there weren't any.
+    pub fn check(problems: Vec<String>) -> Result<(), Self> {
+        if problems.is_empty() {
+            Ok(())
+        } else {
+            Err(Self { problems })
+        }
+    }
+}
+
+/// Names of environment variables referenced like `${NAME}` that were left
+/// in a value because they aren't set.
+fn unset_env_vars(value: &str) -> Vec<&str> {
+    let mut names = vec![];
+    let mut rest = value;
+    while let Some(start) = rest.find("${") {
+        rest = &rest[start + 2..];
+        let Some(end) = rest.find('}') else {
+            break;
+        };
+        names.push(&rest[..end]);
+        rest = &rest[end + 1..];
+    }
+    names
+}
+
+impl ToiConfig {
+    /// Load config from the JSON in `TOI_CONFIG_JSON` if it's set (e.g., for
+    /// container deployments), or from the file at `TOI_CONFIG_PATH`
+    /// otherwise.
+    pub fn from_env() -> Result<Self, Box<dyn std::error::Error>> {
+        let json = if let Ok(json) = dotenvy::var("TOI_CONFIG_JSON") {
+            json
+        } else {
+            let config_path = dotenvy::var("TOI_CONFIG_PATH")
+                .map_err(|_| "either TOI_CONFIG_JSON or TOI_CONFIG_PATH must be set")?;
+            std::fs::read_to_string(&config_path)
+                .map_err(|err| format!("couldn't read config file '{config_path}': {err}"))?
+        };
+        Ok(Self::from_json(&json)?)
+    }
+
+    /// Parse and validate config, collecting every problem found rather
+    /// than stopping at the first.
+    pub fn from_json(json: &str) -> Result<Self, ConfigError> {
+        let mut value: Value = serde_json::from_str(json).map_err(|err| ConfigError {
+            problems: vec![format!("config isn't valid JSON: {err}")],
+        })?;
+
+        // A bad bind address is replaced with the default after it's
+        // reported so the rest of the config can still be checked.
+        let mut problems = vec![];
+        if let Some(bind_addr) = value.pointer_mut("/server/bind_addr")
+            && let Some(addr) = bind_addr.as_str().map(str::to_string)
+            && addr.parse::<SocketAddr>().is_err()
+        {
+            problems.push(format!(
+                "server.bind_addr '{addr}' isn't a valid address (e.g., 0.0.0.0:6969)"
+            ));
+            *bind_addr = Value::String(default_bind_addr().to_string());
+        }
+        let config: Self = match serde_json::from_value(value) {
+            Ok(config) => config,
+            Err(err) => {
+                problems.push(err.to_string());
+                return Err(ConfigError { problems });
+            }
+        };
+        if let Err(err) = config.validate() {
+            problems.extend(err.problems);
+        }
+        ConfigError::check(problems)?;
+        Ok(config)
+    }
+
+    /// Check for values that would otherwise only surface as confusing
+    /// errors at runtime.
+    pub fn validate(&self) -> Result<(), ConfigError> {
+        let mut problems = vec![];
+        let apis = [
+            ("embedding", &self.embedding),
+            ("generation", &self.generation),
+            ("reranking", &self.reranking),
+        ];
+
+        for (name, api) in apis {
+            if api.base_url.is_empty() {
+                problems.push(format!("{name}.base_url is required"));
+            } else if let Err(err) = reqwest::Url::parse(&api.base_url) {
+                problems.push(format!(
+                    "{name}.base_url '{}' isn't a valid URL: {err}",
+                    api.base_url
+                ));
+            }
+        }
+        if let Err(err) = reqwest::Url::parse(&self.server.geocoding_url) {
+            problems.push(format!(
+                "server.geocoding_url '{}' isn't a valid URL: {err}",
+                self.server.geocoding_url
+            ));
+        }
+        let mut feed_names = HashSet::new();
+        for feed in &self.news.feeds {
+            if feed.name.trim().is_empty() {
+                problems.push("news.feeds can't have empty names".to_string());
+            } else if !feed_names.insert(feed.name.trim().to_lowercase()) {
+                problems.push(format!("news.feeds has more than one '{}' feed", feed.name));
+            }
+            if let Err(err) = reqwest::Url::parse(&feed.url) {
+                problems.push(format!(
+                    "news.feeds.{}.url '{}' isn't a valid URL: {err}",
+                    feed.name, feed.url
+                ));
+            }
+        }
+        if self.news.feeds.iter().filter(|feed| feed.default).count() > 1 {
+            problems.push("news.feeds can only have one default feed".to_string());
+        }
+
+        let distances = [
+            ("server.distance_threshold", self.server.distance_threshold),
+            (
+                "server.exclude_distance_threshold",
+                self.server.exclude_distance_threshold,
+            ),
+            (
+                "server.duplicate_distance_threshold",
+                self.server.duplicate_distance_threshold,
+            ),
+        ];
+        for (name, threshold) in distances {
+            if !(0.0..=MAX_DISTANCE).contains(&threshold) {
+                problems.push(format!(
+                    "{name} must be from 0 to {MAX_DISTANCE}, but it's {threshold}"
+                ));
+            }
+        }
+        let similarities = [
+            (
+                "server.similarity_threshold",
+                self.server.similarity_threshold,
+            ),
+            (
+                "server.contact_duplicate_similarity",
+                self.server.contact_duplicate_similarity,
+            ),
+            ("server.api_tie_margin", self.server.api_tie_margin),
+        ];
+        for (name, threshold) in similarities {
+            if !(0.0..=1.0).contains(&threshold) {
+                problems.push(format!("{name} must be from 0 to 1, but it's {threshold}"));
+            }
+        }
+
+        let rerank_limits = [
+            (
+                "server.api_candidate_limit",
+                self.server.api_candidate_limit,
+            ),
+            (
+                "server.rerank_max_documents",
+                self.server.rerank_max_documents,
+            ),
+            (
+                "server.rerank_max_document_chars",
+                self.server.rerank_max_document_chars,
+            ),
+        ];
+        for (name, limit) in rerank_limits {
+            if limit == 0 {
+                problems.push(format!("{name} must be at least 1"));
+            }
+        }
+        let timeouts = [
+            (
+                "api_client.connect_timeout",
+                Some(self.api_client.connect_timeout),
+            ),
+            ("api_client.timeout", self.api_client.timeout),
+        ];
+        for (name, timeout) in timeouts {
+            if timeout == Some(0) {
+                problems.push(format!("{name} must be at least 1"));
+            }
+        }
+        if self.server.seed_on_start && self.server.seed_data_path.is_none() {
+            problems.push("server.seed_on_start requires server.seed_data_path".to_string());
+        }
+        if self.server.note_chunk_overlap_chars >= self.server.note_chunk_chars {
+            problems.push(format!(
+                "server.note_chunk_overlap_chars must be less than server.note_chunk_chars ({}), but it's {}",
+                self.server.note_chunk_chars, self.server.note_chunk_overlap_chars
+            ));
+        }
+
+        let samplings = [
+            (
+                "server.structured_sampling",
+                self.server.structured_sampling,
+            ),
+            ("server.response_sampling", self.server.response_sampling),
+        ];
+        for (name, sampling) in samplings {
+            if let Err(err) = sampling.validate() {
+                problems.push(format!("{name}: {err}"));
+            }
+        }
+
+        // Unset environment variables are left as they are when they're
+        // substituted, so they'd otherwise be sent to APIs verbatim.
+        let mut values = vec![("server.user_agent".to_string(), &self.server.user_agent)];
+        values.extend(
+            self.tokens
+                .iter()
+                .map(|token| ("tokens".to_string(), token)),
+        );
+        for (user, tokens) in &self.users {
+            if user.trim().is_empty() {
+                problems.push("users can't have empty names".to_string());
+            }
+            values.extend(tokens.iter().map(|token| (format!("users.{user}"), token)));
+        }
+        for (name, api) in apis {
+            for (field, map) in [
+                ("headers", &api.headers),
+                ("params", &api.params),
+                ("json", &api.json),
+            ] {
+                values.extend(
+                    map.iter()
+                        .map(|(key, value)| (format!("{name}.{field}.{key}"), value)),
+                );
+            }
+        }
+        for (name, value) in values {
+            for var in unset_env_vars(value) {
+                problems.push(format!(
+                    "{name} references environment variable {var}, which isn't set"
+                ));
+            }
+        }
+        ConfigError::check(problems)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MINIMAL_CONFIG: &str = r#"{
+        "server": {},
+        "embedding": {"base_url": "http://embedding:8000"},
+        "generation": {"base_url": "http://generation:8000"},
+        "reranking": {"base_url": "http://reranking:8000"}
+    }"#;
+
+    fn config_with(pointer: &str, value: Value) -> String {
+        let mut config: Value =
+            serde_json::from_str(MINIMAL_CONFIG).expect("minimal config should be valid JSON");
+        *config
+            .pointer_mut(pointer)
+            .expect("pointer should be in the minimal config") = value;
+        config.to_string()
+    }
+
+    fn problems(json: &str) -> Vec<String> {
+        ToiConfig::from_json(json)
+            .expect_err("config should be invalid")
+            .problems
+    }
+
+    #[test]
+    fn valid_config() {
+        assert!(ToiConfig::from_json(MINIMAL_CONFIG).is_ok());
+    }
+
+    #[test]
+    fn invalid_json() {
+        let problems = problems("{");
+        assert_eq!(problems.len(), 1);
+        assert!(problems[0].starts_with("config isn't valid JSON"));
+    }
+
+    #[test]
+    fn invalid_bind_addr() {
+        let json = config_with("/server", serde_json::json!({"bind_addr": "localhost"}));
+        assert_eq!(
+            problems(&json),
+            vec!["server.bind_addr 'localhost' isn't a valid address (e.g., 0.0.0.0:6969)"]
+        );
+    }
+
+    #[test]
+    fn invalid_base_urls() {
+        let json = config_with("/embedding/base_url", Value::String(String::new()));
+        assert_eq!(problems(&json), vec!["embedding.base_url is required"]);
+        let json = config_with("/reranking/base_url", Value::String("reranking".into()));
+        let problems = problems(&json);
+        assert_eq!(problems.len(), 1);
+        assert!(problems[0].starts_with("reranking.base_url 'reranking' isn't a valid URL"));
+    }
+
+    #[test]
+    fn thresholds_out_of_range() {
+        let json = config_with(
+            "/server",
+            serde_json::json!({
+                "distance_threshold": 2.5,
+                "exclude_distance_threshold": -0.1,
+                "similarity_threshold": 1.5,
+                "contact_duplicate_similarity": 0.9,
+                "api_tie_margin": 1.1
+            }),
+        );
+        assert_eq!(
+            problems(&json),
+            vec![
+                "server.distance_threshold must be from 0 to 2, but it's 2.5",
+                "server.exclude_distance_threshold must be from 0 to 2, but it's -0.1",
+                "server.similarity_threshold must be from 0 to 1, but it's 1.5",
+                "server.api_tie_margin must be from 0 to 1, but it's 1.1",
+            ]
+        );
+    }
+
+    #[test]
+    fn invalid_search_limits() {
+        let json = config_with(
+            "/server",
+            serde_json::json!({
+                "rerank_max_documents": 0,
+                "api_candidate_limit": 0,
+                "note_chunk_chars": 100,
+                "note_chunk_overlap_chars": 100
+            }),
+        );
+        assert_eq!(
+            problems(&json),
+            vec![
+                "server.api_candidate_limit must be at least 1",
+                "server.rerank_max_documents must be at least 1",
+                "server.note_chunk_overlap_chars must be less than server.note_chunk_chars (100), but it's 100",
+            ]
+        );
+    }
+
+    #[test]
+    fn seeding_on_start_without_seed_data() {
+        let json = config_with("/server", serde_json::json!({"seed_on_start": true}));
+        assert_eq!(
+            problems(&json),
+            vec!["server.seed_on_start requires server.seed_data_path"]
+        );
+        let json = config_with(
+            "/server",
+            serde_json::json!({"seed_on_start": true, "seed_data_path": "seed"}),
+        );
+        assert!(ToiConfig::from_json(&json).is_ok());
+    }
+
+    #[test]
+    fn invalid_api_client_timeouts() {
+        let mut config: Value =
+            serde_json::from_str(MINIMAL_CONFIG).expect("minimal config should be valid JSON");
+        config["api_client"] = serde_json::json!({"connect_timeout": 0, "timeout": 0});
+        assert_eq!(
+            problems(&config.to_string()),
+            vec![
+                "api_client.connect_timeout must be at least 1",
+                "api_client.timeout must be at least 1",
+            ]
+        );
+        config["api_client"] = serde_json::json!({"timeout": null});
+        assert!(ToiConfig::from_json(&config.to_string()).is_ok());
+    }
+
+    #[test]
+    fn invalid_sampling() {
+        let json = config_with(
+            "/server",
+            serde_json::json!({"response_sampling": {"max_tokens": 0}}),
+        );
+        assert_eq!(
+            problems(&json),
+            vec!["server.response_sampling: max_tokens must be at least 1"]
+        );
+    }
+
+    #[test]
+    fn unset_env_vars_referenced() {
+        let json = config_with(
+            "/generation",
+            serde_json::json!({
+                "base_url": "http://generation:8000",
+                "headers": {"api_key": "${TOI_TEST_UNSET_API_KEY}"}
+            }),
+        );
+        assert_eq!(
+            problems(&json),
+            vec![
+                "generation.headers.api_key references environment variable TOI_TEST_UNSET_API_KEY, which isn't set"
+            ]
+        );
+        assert_eq!(unset_env_vars("${A}-${B}-${C"), vec!["A", "B"]);
+    }
+
+    #[test]
+    fn invalid_users() {
+        let mut config: Value =
+            serde_json::from_str(MINIMAL_CONFIG).expect("minimal config should be valid JSON");
+        config["users"] = serde_json::json!({
+            "alice": ["${TOI_TEST_UNSET_ALICE_TOKEN}"],
+            " ": ["abc123"]
+        });
+        let mut problems = problems(&config.to_string());
+        problems.sort();
+        assert_eq!(
+            problems,
+            vec![
+                "users can't have empty names",
+                "users.alice references environment variable TOI_TEST_UNSET_ALICE_TOKEN, which isn't set",
+            ]
+        );
+    }
+
+    #[test]
+    fn invalid_news_feeds() {
+        let mut config: Value =
+            serde_json::from_str(MINIMAL_CONFIG).expect("minimal config should be valid JSON");
+        config["news"] = serde_json::json!({
+            "feeds": [
+                {"name": "Hacker News", "url": "https://news.ycombinator.com/rss", "default": true},
+                {"name": "hacker news ", "url": "hn", "default": true},
+                {"name": " ", "url": "https://example.com/rss"}
+            ]
+        });
+        assert_eq!(
+            problems(&config.to_string()),
+            vec![
+                "news.feeds has more than one 'hacker news ' feed",
+                "news.feeds.hacker news .url 'hn' isn't a valid URL: relative URL without a base",
+                "news.feeds can't have empty names",
+                "news.feeds can only have one default feed",
+            ]
+        );
+        config["news"] = serde_json::json!({
+            "feeds": [{"name": "Hacker News", "url": "https://news.ycombinator.com/rss"}]
+        });
+        assert!(ToiConfig::from_json(&config.to_string()).is_ok());
+    }
+
+    #[test]
+    fn every_problem_is_reported() {
+        let json = r#"{
+            "server": {"bind_addr": "nope", "similarity_threshold": 2.0},
+            "embedding": {"base_url": ""},
+            "generation": {"base_url": "http://generation:8000"},
+            "reranking": {"base_url": "http://reranking:8000"}
+        }"#;
+        let err = ToiConfig::from_json(json).expect_err("config should be invalid");
+        assert_eq!(
+            err.to_string(),
+            "invalid config:
+- server.bind_addr 'nope' isn't a valid address (e.g., 0.0.0.0:6969)
+- embedding.base_url is required
+- server.similarity_threshold must be from 0 to 1, but it's 2"
+        );
+    }
+}
@@ -4,10 +4,17 @@ use diesel::{AsChangeset, Insertable, Queryable, Selectable};
 use pgvector::Vector;
 use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
-use std::fmt;
+use std::{collections::HashSet, fmt};
 use utoipa::ToSchema;
 
-use crate::utils;
+use crate::{
+    models::{
+        deletion::DeleteFilters,
+        error::ToiError,
+        validation::{normalize_phone_number, validate_email},
+    },
+    utils, vcard,
+};
 
 #[derive(Debug, Deserialize, PartialEq, Queryable, Selectable, Serialize, ToSchema)]
 #[diesel(table_name = crate::schema::contacts)]
@@ -54,10 +61,94 @@ impl Contact {
     }
 }
 
+#[derive(Debug, Deserialize, PartialEq, Queryable, Selectable, Serialize, ToSchema)]
+#[diesel(table_name = crate::schema::contact_emails)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+pub struct ContactEmail {
+    /// Unique contact email ID.
+    pub id: i32,
+    /// ID of the contact the email belongs to.
+    pub contact_id: i32,
+    /// What kind of email it is (e.g., "work" or "personal").
+    pub label: String,
+    /// The email address.
+    pub value: String,
+}
+
+#[derive(Insertable)]
+#[diesel(table_name = crate::schema::contact_emails)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+pub struct NewContactEmail {
+    pub contact_id: i32,
+    pub label: String,
+    pub value: String,
+}
+
+#[derive(Debug, Deserialize, PartialEq, Queryable, Selectable, Serialize, ToSchema)]
+#[diesel(table_name = crate::schema::contact_phones)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+pub struct ContactPhone {
+    /// Unique contact phone number ID.
+    pub id: i32,
+    /// ID of the contact the phone number belongs to.
+    pub contact_id: i32,
+    /// What kind of phone number it is (e.g., "work" or "mobile").
+    pub label: String,
+    /// The phone number in XXX-XXX-XXXX format.
+    pub value: String,
+}
+
+#[derive(Insertable)]
+#[diesel(table_name = crate::schema::contact_phones)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+pub struct NewContactPhone {
+    pub contact_id: i32,
+    pub label: String,
+    pub value: String,
+}
+
+#[derive(Builder, Clone, Debug, Deserialize, JsonSchema, PartialEq, Serialize, ToSchema)]
+pub struct ContactDetail {
+    /// What kind of email or phone number it is (e.g., "work" or "personal").
+    pub label: String,
+    /// The email address or phone number.
+    pub value: String,
+}
+
+impl From<&ContactEmail> for ContactDetail {
+    fn from(email: &ContactEmail) -> Self {
+        Self {
+            label: email.label.clone(),
+            value: email.value.clone(),
+        }
+    }
+}
+
+impl From<&ContactPhone> for ContactDetail {
+    fn from(phone: &ContactPhone) -> Self {
+        Self {
+            label: phone.label.clone(),
+            value: phone.value.clone(),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, PartialEq, Serialize, ToSchema)]
+pub struct ContactWithDetails {
+    /// Matching contact.
+    #[serde(flatten)]
+    pub contact: Contact,
+    /// All of the contact's labeled emails.
+    pub emails: Vec<ContactEmail>,
+    /// All of the contact's labeled phone numbers.
+    pub phones: Vec<ContactPhone>,
+}
+
 #[derive(AsChangeset, Insertable)]
 #[diesel(table_name = crate::schema::contacts)]
 #[diesel(check_for_backend(diesel::pg::Pg))]
 pub struct NewContact {
+    pub user_id: i32,
     pub first_name: String,
     pub last_name: Option<String>,
     pub email: Option<String>,
@@ -81,11 +172,273 @@ pub struct NewContactRequest {
     pub birthday: Option<NaiveDate>,
     /// Short description of relationship to the contact.
     pub relationship: Option<String>,
+    /// Additional labeled emails for the contact.
+    pub emails: Option<Vec<ContactDetail>>,
+    /// Additional labeled phone numbers for the contact in XXX-XXX-XXXX format.
+    pub phones: Option<Vec<ContactDetail>>,
+    /// Add the contact even if it looks like a duplicate of an existing
+    /// contact. Only set this if the user explicitly asks for it.
+    pub allow_duplicate: Option<bool>,
+}
+
+/// Lowercased first and last name for comparing contacts' names.
+fn full_name(first_name: &str, last_name: Option<&str>) -> String {
+    match last_name {
+        Some(last_name) => format!("{first_name} {last_name}").to_lowercase(),
+        None => first_name.to_lowercase(),
+    }
+}
+
+/// Lowercase an email and trim any surrounding whitespace so the same
+/// address compares equal however it was typed.
+fn normalize_email(email: &str) -> String {
+    email.trim().to_lowercase()
+}
+
+/// Keep only a phone number's digits so punctuation like dashes,
+/// parentheses, and spaces doesn't matter when comparing phone numbers.
+fn normalize_phone(phone: &str) -> String {
+    phone.chars().filter(char::is_ascii_digit).collect()
+}
+
+/// Normalize a contact's phone numbers into XXX-XXX-XXXX format and check
+/// that their emails look like emails. Every invalid email and phone number
+/// is listed in the error so they can all be fixed at once.
+fn normalize_details(
+    email: &mut Option<String>,
+    phone: &mut Option<String>,
+    emails: &mut Option<Vec<ContactDetail>>,
+    phones: &mut Option<Vec<ContactDetail>>,
+) -> Result<(), ToiError> {
+    let mut problems = vec![];
+    let mut check = |field: String,
+                     value: &mut String,
+                     kind: &str,
+                     normalize: fn(&str) -> Result<String, String>| {
+        match normalize(value) {
+            Ok(normalized) => *value = normalized,
+            Err(reason) => problems.push(format!(
+                "{field} \"{value}\" isn't a valid {kind} because {reason}"
+            )),
+        }
+    };
+    if let Some(email) = email {
+        check("email".to_string(), email, "email", validate_email);
+    }
+    for (i, detail) in emails.iter_mut().flatten().enumerate() {
+        check(
+            format!("emails[{i}]"),
+            &mut detail.value,
+            "email",
+            validate_email,
+        );
+    }
+    if let Some(phone) = phone {
+        check(
+            "phone".to_string(),
+            phone,
+            "phone number",
+            normalize_phone_number,
+        );
+    }
+    for (i, detail) in phones.iter_mut().flatten().enumerate() {
+        check(
+            format!("phones[{i}]"),
+            &mut detail.value,
+            "phone number",
+            normalize_phone_number,
+        );
+    }
+    if problems.is_empty() {
+        Ok(())
+    } else {
+        Err(ToiError::Validation(problems.join("; ")))
+    }
+}
+
+impl NewContactRequest {
+    /// Normalize the new contact's phone numbers into XXX-XXX-XXXX format and
+    /// check that their emails look like emails.
+    pub fn normalize_details(&mut self) -> Result<(), ToiError> {
+        normalize_details(
+            &mut self.email,
+            &mut self.phone,
+            &mut self.emails,
+            &mut self.phones,
+        )
+    }
+
+    /// Whether the new contact looks like the same person as an existing
+    /// contact. Their names have to be at least this similar by normalized
+    /// Damerau-Levenshtein similarity, and they have to share an email or a
+    /// phone number.
+    #[must_use]
+    pub fn is_duplicate_of(&self, contact: &ContactWithDetails, name_similarity: f64) -> bool {
+        self.looks_like(
+            &contact.contact.first_name,
+            contact.contact.last_name.as_deref(),
+            contact
+                .contact
+                .email
+                .iter()
+                .chain(contact.emails.iter().map(|email| &email.value)),
+            contact
+                .contact
+                .phone
+                .iter()
+                .chain(contact.phones.iter().map(|phone| &phone.value)),
+            name_similarity,
+        )
+    }
+
+    /// Whether the new contact looks like the same person as another new
+    /// contact, like when the same person is listed twice in one import.
+    #[must_use]
+    pub fn is_duplicate_of_request(&self, other: &NewContactRequest, name_similarity: f64) -> bool {
+        self.looks_like(
+            &other.first_name,
+            other.last_name.as_deref(),
+            other
+                .email
+                .iter()
+                .chain(other.emails.iter().flatten().map(|detail| &detail.value)),
+            other
+                .phone
+                .iter()
+                .chain(other.phones.iter().flatten().map(|detail| &detail.value)),
+            name_similarity,
+        )
+    }
+
+    fn looks_like<'a>(
+        &self,
+        first_name: &str,
+        last_name: Option<&str>,
+        mut other_emails: impl Iterator<Item = &'a String>,
+        mut other_phones: impl Iterator<Item = &'a String>,
+        name_similarity: f64,
+    ) -> bool {
+        let name = full_name(&self.first_name, self.last_name.as_deref());
+        let other_name = full_name(first_name, last_name);
+        if strsim::normalized_damerau_levenshtein(&name, &other_name) < name_similarity {
+            return false;
+        }
+
+        let emails: HashSet<String> = self
+            .email
+            .iter()
+            .chain(self.emails.iter().flatten().map(|detail| &detail.value))
+            .map(|email| normalize_email(email))
+            .filter(|email| !email.is_empty())
+            .collect();
+        let shares_email = other_emails.any(|email| emails.contains(&normalize_email(email)));
+
+        let phones: HashSet<String> = self
+            .phone
+            .iter()
+            .chain(self.phones.iter().flatten().map(|detail| &detail.value))
+            .map(|phone| normalize_phone(phone))
+            .filter(|phone| !phone.is_empty())
+            .collect();
+        let shares_phone = other_phones.any(|phone| phones.contains(&normalize_phone(phone)));
+
+        shares_email || shares_phone
+    }
+}
+
+impl From<ContactWithDetails> for NewContactRequest {
+    fn from(contact: ContactWithDetails) -> Self {
+        let ContactWithDetails {
+            contact:
+                Contact {
+                    first_name,
+                    last_name,
+                    email,
+                    phone,
+                    birthday,
+                    relationship,
+                    ..
+                },
+            emails,
+            phones,
+        } = contact;
+        Self {
+            first_name,
+            last_name,
+            email,
+            phone,
+            birthday,
+            relationship,
+            emails: Some(emails.iter().map(ContactDetail::from).collect()),
+            phones: Some(phones.iter().map(ContactDetail::from).collect()),
+            allow_duplicate: None,
+        }
+    }
+}
+
+/// Split a vCard's emails or phone numbers into the first one and labeled
+/// details for the rest. Details without a label are labeled "other".
+fn split_vcard_details(
+    details: Vec<vcard::Detail>,
+) -> (Option<String>, Option<Vec<ContactDetail>>) {
+    let mut details = details.into_iter();
+    let first = details.next().map(|detail| detail.value);
+    let rest: Vec<ContactDetail> = details
+        .map(|vcard::Detail { label, value }| ContactDetail {
+            label: label.unwrap_or_else(|| "other".to_string()),
+            value,
+        })
+        .collect();
+    (first, (!rest.is_empty()).then_some(rest))
+}
+
+impl TryFrom<vcard::Card> for NewContactRequest {
+    type Error = String;
+
+    /// Use the vCard's structured name if it has one, or else split its
+    /// formatted name at the first space. The first email and phone number
+    /// become the contact's main ones, and any others are added as labeled
+    /// details. Notes are kept as the relationship.
+    fn try_from(card: vcard::Card) -> Result<Self, Self::Error> {
+        let vcard::Card {
+            formatted_name,
+            given_name,
+            family_name,
+            emails,
+            phones,
+            birthday,
+            note,
+        } = card;
+        let (first_name, last_name) = match (given_name, family_name, formatted_name) {
+            (Some(given_name), family_name, _) => (given_name, family_name),
+            (None, _, Some(formatted_name)) => match formatted_name.split_once(' ') {
+                Some((first_name, last_name)) => {
+                    (first_name.to_string(), Some(last_name.trim().to_string()))
+                }
+                None => (formatted_name, None),
+            },
+            (None, Some(family_name), None) => (family_name, None),
+            (None, None, None) => return Err("vCard doesn't have a name".to_string()),
+        };
+        let (email, emails) = split_vcard_details(emails);
+        let (phone, phones) = split_vcard_details(phones);
+        Ok(Self {
+            first_name,
+            last_name,
+            email,
+            phone,
+            birthday,
+            relationship: note,
+            emails,
+            phones,
+            allow_duplicate: None,
+        })
+    }
 }
 
 impl fmt::Display for NewContactRequest {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        let items: Vec<String> = [
+        let mut items: Vec<String> = [
             ("First Name", Some(&self.first_name)),
             ("Last Name", self.last_name.as_ref()),
             ("Email", self.email.as_ref()),
@@ -95,11 +448,38 @@ impl fmt::Display for NewContactRequest {
         .iter()
         .filter_map(|(k, opt)| opt.map(|v| format!("{k}: {v}")))
         .collect();
+        // Include every labeled email and phone number so contacts can still
+        // be found by any of them.
+        let details = [("Email", &self.emails), ("Phone", &self.phones)];
+        for (k, details) in details {
+            for ContactDetail { label, value } in details.iter().flatten() {
+                items.push(format!("{k} ({label}): {value}"));
+            }
+        }
         let repr = items.join("\n");
         write!(f, "{repr}")
     }
 }
 
+#[derive(Debug, Deserialize, PartialEq, Serialize, ToSchema)]
+pub struct SkippedContact {
+    /// Name of the contact from the vCard.
+    pub name: String,
+    /// Why the contact wasn't imported.
+    pub reason: String,
+    /// ID of the existing contact the skipped contact looks like, if it was
+    /// skipped for being a duplicate of one.
+    pub existing_contact_id: Option<i32>,
+}
+
+#[derive(Debug, Deserialize, PartialEq, Serialize, ToSchema)]
+pub struct ContactImport {
+    /// Contacts that were imported.
+    pub imported: Vec<ContactWithDetails>,
+    /// Contacts that weren't imported, in the order they were listed.
+    pub skipped: Vec<SkippedContact>,
+}
+
 #[derive(Builder, Deserialize, JsonSchema, Serialize, ToSchema)]
 pub struct ContactDeleteParams {
     /// Select contacts according to their database-generated IDs rather
@@ -127,7 +507,17 @@ pub struct ContactDeleteParams {
     pub limit: Option<i64>,
 }
 
-#[derive(Builder, Deserialize, JsonSchema, Serialize, ToSchema)]
+impl DeleteFilters for ContactDeleteParams {
+    fn narrows(&self) -> bool {
+        self.ids.is_some()
+            || self.query.is_some()
+            || self.created_from.is_some()
+            || self.created_to.is_some()
+            || self.limit.is_some()
+    }
+}
+
+#[derive(Builder, Clone, Deserialize, JsonSchema, Serialize, ToSchema)]
 pub struct ContactSearchParams {
     /// Select contacts according to their database-generated IDs rather
     /// than searching for them.
@@ -137,8 +527,17 @@ pub struct ContactSearchParams {
     pub birthday: Option<NaiveDate>,
     /// What kind of calendar object the birthday falls on. Used
     /// to search if a contact's birthday falls on the month of, week of,
-    /// or day of `birthday`.
+    /// or day of `birthday`, ignoring the year the contact was born.
+    /// Leave empty to match the exact date instead.
     pub birthday_falls_on: Option<utils::DateFallsOn>,
+    /// Only return contacts whose birthday comes up within this many days
+    /// from today, including today. Useful for questions like "whose
+    /// birthday is coming up?".
+    pub upcoming_within_days: Option<i32>,
+    /// IANA timezone name (e.g., "America/Chicago") that today is in when
+    /// looking for upcoming birthdays. Leave empty to use the server's
+    /// timezone.
+    pub timezone: Option<String>,
     /// User query string to compare embeddings against. Basically,
     /// if the user is asking something like "what color is my jacket?",
     /// then the query string should be something like "jacket color" or
@@ -159,6 +558,12 @@ pub struct ContactSearchParams {
     pub order_by: Option<utils::OrderBy>,
     /// Limit the max number of contacts to return from the search.
     pub limit: Option<i64>,
+    /// Skip this many matching contacts before returning results. Use with
+    /// the limit to page through many contacts.
+    pub offset: Option<i64>,
+    /// Only count matching contacts instead of returning them. Useful for
+    /// questions like "how many contacts are there".
+    pub count_only: Option<bool>,
 }
 
 #[derive(Builder, Clone, Deserialize, JsonSchema, Serialize, ToSchema)]
@@ -175,6 +580,24 @@ pub struct ContactUpdates {
     pub birthday: Option<NaiveDate>,
     /// Short description of relationship to the contact.
     pub relationship: Option<String>,
+    /// Labeled emails that replace all of the contact's existing ones.
+    pub emails: Option<Vec<ContactDetail>>,
+    /// Labeled phone numbers in XXX-XXX-XXXX format that replace all of the
+    /// contact's existing ones.
+    pub phones: Option<Vec<ContactDetail>>,
+}
+
+impl ContactUpdates {
+    /// Normalize updated phone numbers into XXX-XXX-XXXX format and check
+    /// that updated emails look like emails.
+    pub fn normalize_details(&mut self) -> Result<(), ToiError> {
+        normalize_details(
+            &mut self.email,
+            &mut self.phone,
+            &mut self.emails,
+            &mut self.phones,
+        )
+    }
 }
 
 #[derive(Builder, Clone, Deserialize, JsonSchema, Serialize, ToSchema)]
@@ -203,3 +626,288 @@ pub struct UpdateContactRequest {
     /// How to order results for retrieved contacts.
     pub order_by: Option<utils::OrderBy>,
 }
+
+/// Content types contact avatars can have.
+pub const AVATAR_CONTENT_TYPES: [&str; 3] = ["image/png", "image/jpeg", "image/webp"];
+
+/// Content type of an image detected from the bytes its file starts with, or
+/// `None` if it isn't an image with one of `AVATAR_CONTENT_TYPES`.
+#[must_use]
+pub fn detect_avatar_content_type(data: &[u8]) -> Option<&'static str> {
+    if data.starts_with(b"\x89PNG\r\n\x1a\n") {
+        Some("image/png")
+    } else if data.starts_with(b"\xff\xd8\xff") {
+        Some("image/jpeg")
+    } else if data.starts_with(b"RIFF") && data.get(8..12) == Some(b"WEBP".as_slice()) {
+        Some("image/webp")
+    } else {
+        None
+    }
+}
+
+#[derive(Debug, Deserialize, PartialEq, Queryable, Selectable, Serialize, ToSchema)]
+#[diesel(table_name = crate::schema::contact_avatars)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+pub struct ContactAvatar {
+    /// ID of the contact the avatar belongs to.
+    pub contact_id: i32,
+    /// Avatar image's content type (e.g., "image/png").
+    pub content_type: String,
+    /// Datetime the avatar was last set in ISO format.
+    pub updated_at: DateTime<Utc>,
+}
+
+#[derive(AsChangeset, Insertable)]
+#[diesel(table_name = crate::schema::contact_avatars)]
+#[diesel(primary_key(contact_id))]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+pub struct NewContactAvatar {
+    pub contact_id: i32,
+    pub content_type: String,
+    pub data: Vec<u8>,
+    pub updated_at: DateTime<Utc>,
+}
+
+#[derive(Builder, Deserialize, Serialize, ToSchema)]
+pub struct ContactAvatarRequest {
+    /// Set the avatar of a contact using their database-generated ID rather
+    /// than searching for them.
+    pub id: Option<i32>,
+    /// Avatar image's content type. Must be one of "image/png",
+    /// "image/jpeg", or "image/webp".
+    pub content_type: String,
+    /// Base64-encoded avatar image.
+    pub data: String,
+    /// User query string to compare embeddings against for finding the
+    /// contact, like their name.
+    pub query: Option<String>,
+    /// Whether to match the query string more closely using a reranking -based
+    /// approach. `true` is useful for cases where the user is looking to match
+    /// to specific words or phrases, whereas `false` is useful for more broad
+    /// matching.
+    pub use_reranking_filter: Option<bool>,
+    /// Filter on contacts created after this ISO formatted datetime.
+    pub created_from: Option<DateTime<Utc>>,
+    /// Filter on contacts created before this ISO formatted datetime.
+    pub created_to: Option<DateTime<Utc>>,
+    /// How to order results for retrieved contacts.
+    pub order_by: Option<utils::OrderBy>,
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{NaiveDate, Utc};
+
+    use super::{
+        Contact, ContactDetail, ContactPhone, ContactWithDetails, NewContactRequest,
+        detect_avatar_content_type,
+    };
+    use crate::vcard;
+
+    fn existing_contact() -> ContactWithDetails {
+        ContactWithDetails {
+            contact: Contact {
+                id: 1,
+                first_name: "John".to_string(),
+                last_name: Some("Smith".to_string()),
+                email: Some("John.Smith@example.com".to_string()),
+                phone: None,
+                birthday: None,
+                relationship: None,
+                created_at: Utc::now(),
+            },
+            emails: vec![],
+            phones: vec![ContactPhone {
+                id: 1,
+                contact_id: 1,
+                label: "mobile".to_string(),
+                value: "555-123-4567".to_string(),
+            }],
+        }
+    }
+
+    #[test]
+    fn duplicate_by_email() {
+        let new_contact = NewContactRequest::builder()
+            .first_name("John".to_string())
+            .last_name("Smith".to_string())
+            .email(" john.smith@EXAMPLE.com".to_string())
+            .build();
+        assert!(new_contact.is_duplicate_of(&existing_contact(), 0.8));
+    }
+
+    #[test]
+    fn duplicate_by_similar_name_and_phone() {
+        let new_contact = NewContactRequest::builder()
+            .first_name("Jon".to_string())
+            .last_name("Smith".to_string())
+            .phones(vec![
+                ContactDetail::builder()
+                    .label("cell".to_string())
+                    .value("(555) 123 4567".to_string())
+                    .build(),
+            ])
+            .build();
+        assert!(new_contact.is_duplicate_of(&existing_contact(), 0.8));
+    }
+
+    #[test]
+    fn different_person_with_same_first_name() {
+        let new_contact = NewContactRequest::builder()
+            .first_name("John".to_string())
+            .last_name("Doe".to_string())
+            .phone("555-123-4567".to_string())
+            .build();
+        assert!(!new_contact.is_duplicate_of(&existing_contact(), 0.8));
+    }
+
+    #[test]
+    fn similar_name_without_shared_details() {
+        let new_contact = NewContactRequest::builder()
+            .first_name("John".to_string())
+            .last_name("Smith".to_string())
+            .email("jsmith@other.com".to_string())
+            .build();
+        assert!(!new_contact.is_duplicate_of(&existing_contact(), 0.8));
+    }
+
+    #[test]
+    fn normalizing_details() {
+        let mut new_contact = NewContactRequest::builder()
+            .first_name("John".to_string())
+            .email(" john@example.com ".to_string())
+            .phone("+1 (555) 123-4567".to_string())
+            .build();
+        assert!(new_contact.normalize_details().is_ok());
+        assert_eq!(new_contact.email.as_deref(), Some("john@example.com"));
+        assert_eq!(new_contact.phone.as_deref(), Some("555-123-4567"));
+    }
+
+    #[test]
+    fn listing_invalid_details() {
+        let mut new_contact = NewContactRequest::builder()
+            .first_name("John".to_string())
+            .email("john".to_string())
+            .phone("call him after 5".to_string())
+            .phones(vec![
+                ContactDetail::builder()
+                    .label("work".to_string())
+                    .value("555-123-4567".to_string())
+                    .build(),
+                ContactDetail::builder()
+                    .label("home".to_string())
+                    .value("+44 20 7946 0958".to_string())
+                    .build(),
+            ])
+            .build();
+        let err = new_contact
+            .normalize_details()
+            .expect_err("details should be invalid")
+            .to_string();
+        assert!(err.contains("email \"john\" isn't a valid email"), "{err}");
+        assert!(
+            err.contains("phone \"call him after 5\" isn't a valid phone number"),
+            "{err}"
+        );
+        assert!(!err.contains("phones[0]"), "{err}");
+        assert!(err.contains("phones[1]"), "{err}");
+    }
+
+    #[test]
+    fn contacts_from_vcards() {
+        let card = vcard::Card {
+            formatted_name: Some("Dr. John Smith".to_string()),
+            given_name: Some("John".to_string()),
+            family_name: Some("Smith".to_string()),
+            emails: vec![
+                vcard::Detail {
+                    label: Some("work".to_string()),
+                    value: "john@example.com".to_string(),
+                },
+                vcard::Detail {
+                    label: None,
+                    value: "jsmith@example.com".to_string(),
+                },
+            ],
+            phones: vec![],
+            birthday: NaiveDate::from_ymd_opt(1985, 4, 12),
+            note: Some("Climbing partner".to_string()),
+        };
+        let contact = NewContactRequest::try_from(card).expect("vCard should have a name");
+        assert_eq!(contact.first_name, "John");
+        assert_eq!(contact.last_name.as_deref(), Some("Smith"));
+        assert_eq!(contact.email.as_deref(), Some("john@example.com"));
+        assert_eq!(
+            contact.emails,
+            Some(vec![
+                ContactDetail::builder()
+                    .label("other".to_string())
+                    .value("jsmith@example.com".to_string())
+                    .build()
+            ])
+        );
+        assert_eq!(contact.phone, None);
+        assert_eq!(contact.phones, None);
+        assert_eq!(contact.relationship.as_deref(), Some("Climbing partner"));
+
+        // Formatted names are split when there's no structured name.
+        let card = vcard::Card {
+            formatted_name: Some("Mary Ann Jones".to_string()),
+            ..vcard::Card::default()
+        };
+        let contact = NewContactRequest::try_from(card).expect("vCard should have a name");
+        assert_eq!(contact.first_name, "Mary");
+        assert_eq!(contact.last_name.as_deref(), Some("Ann Jones"));
+
+        assert!(NewContactRequest::try_from(vcard::Card::default()).is_err());
+    }
+
+    #[test]
+    fn duplicate_requests() {
+        let new_contact = NewContactRequest::builder()
+            .first_name("John".to_string())
+            .last_name("Smith".to_string())
+            .phone("555-123-4567".to_string())
+            .build();
+        let other = NewContactRequest::builder()
+            .first_name("Jon".to_string())
+            .last_name("Smith".to_string())
+            .email("jon@example.com".to_string())
+            .phones(vec![
+                ContactDetail::builder()
+                    .label("cell".to_string())
+                    .value("(555) 123-4567".to_string())
+                    .build(),
+            ])
+            .build();
+        assert!(new_contact.is_duplicate_of_request(&other, 0.8));
+        let stranger = NewContactRequest::builder()
+            .first_name("John".to_string())
+            .last_name("Smith".to_string())
+            .email("john@example.com".to_string())
+            .build();
+        assert!(!new_contact.is_duplicate_of_request(&stranger, 0.8));
+    }
+
+    #[test]
+    fn avatar_content_types() {
+        assert_eq!(
+            detect_avatar_content_type(b"\x89PNG\r\n\x1a\n\x00\x00"),
+            Some("image/png")
+        );
+        assert_eq!(
+            detect_avatar_content_type(b"\xff\xd8\xff\xe0\x00\x10JFIF"),
+            Some("image/jpeg")
+        );
+        assert_eq!(
+            detect_avatar_content_type(b"RIFF\x24\x00\x00\x00WEBPVP8 "),
+            Some("image/webp")
+        );
+        assert_eq!(
+            detect_avatar_content_type(b"RIFF\x24\x00\x00\x00WAVE"),
+            None
+        );
+        assert_eq!(detect_avatar_content_type(b"GIF89a"), None);
+        assert_eq!(detect_avatar_content_type(b""), None);
+    }
+}
@@ -0,0 +1,156 @@
+use bon::Builder;
+use chrono::{DateTime, Utc};
+use diesel::{Insertable, Queryable, Selectable};
+use serde::{Deserialize, Serialize};
+use toi::{Message, MessageRole};
+use utoipa::ToSchema;
+
+use crate::models::client::{GenerationResponseChunk, TokenUsage};
+
+#[derive(Debug, Deserialize, PartialEq, Queryable, Selectable, Serialize, ToSchema)]
+#[diesel(table_name = crate::schema::conversations)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+pub struct Conversation {
+    /// Unique conversation ID.
+    pub id: i32,
+    /// Optional conversation title.
+    pub title: Option<String>,
+    /// Datetime the conversation was created in ISO format.
+    pub created_at: DateTime<Utc>,
+}
+
+#[derive(Insertable)]
+#[diesel(table_name = crate::schema::conversations)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+pub struct NewConversation {
+    pub user_id: i32,
+    pub title: Option<String>,
+}
+
+#[derive(Builder, Deserialize, Serialize, ToSchema)]
+pub struct NewConversationRequest {
+    /// Optional conversation title.
+    pub title: Option<String>,
+}
+
+#[derive(Debug, Deserialize, PartialEq, Queryable, Selectable, Serialize, ToSchema)]
+#[diesel(table_name = crate::schema::conversation_messages)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+pub struct ConversationMessage {
+    /// Unique message ID.
+    pub id: i32,
+    /// ID of the conversation the message belongs to.
+    pub conversation_id: i32,
+    /// Position of the message within its conversation, starting at zero.
+    pub sequence: i32,
+    /// Who the message is from, either "assistant" or "user".
+    pub role: String,
+    /// Message content.
+    pub content: String,
+    /// Datetime the message was added in ISO format.
+    pub created_at: DateTime<Utc>,
+}
+
+impl From<ConversationMessage> for Message {
+    fn from(message: ConversationMessage) -> Self {
+        let role = match message.role.as_str() {
+            "assistant" => MessageRole::Assistant,
+            _ => MessageRole::User,
+        };
+        Self {
+            role,
+            content: message.content,
+        }
+    }
+}
+
+#[derive(Insertable)]
+#[diesel(table_name = crate::schema::conversation_messages)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+pub struct NewConversationMessage {
+    pub conversation_id: i32,
+    pub sequence: i32,
+    pub role: String,
+    pub content: String,
+}
+
+impl NewConversationMessage {
+    #[must_use]
+    pub fn new(conversation_id: i32, sequence: i32, message: Message) -> Self {
+        // System messages are never deserialized from requests, so they're
+        // stored as user messages to satisfy the table's constraint.
+        let role = match message.role {
+            MessageRole::Assistant => "assistant",
+            MessageRole::System | MessageRole::User => "user",
+        };
+        Self {
+            conversation_id,
+            sequence,
+            role: role.to_string(),
+            content: message.content,
+        }
+    }
+}
+
+#[derive(Builder, Deserialize, Serialize, ToSchema)]
+pub struct NewConversationMessagesRequest {
+    /// Messages to append to the conversation in order.
+    pub messages: Vec<Message>,
+}
+
+/// Rebuild an assistant reply from the raw server-sent events of a
+/// streamed generation response.
+#[must_use]
+pub fn collect_streamed_content(raw: &[u8]) -> String {
+    String::from_utf8_lossy(raw)
+        .lines()
+        .filter_map(|line| line.strip_prefix("data: "))
+        .filter_map(|data| serde_json::from_str::<GenerationResponseChunk>(data).ok())
+        .flat_map(|chunk| chunk.choices)
+        .filter_map(|choice| choice.delta.content)
+        .collect()
+}
+
+/// Get the token usage reported by the raw server-sent events of a streamed
+/// generation response, if any was reported.
+#[must_use]
+pub fn collect_streamed_usage(raw: &[u8]) -> Option<TokenUsage> {
+    String::from_utf8_lossy(raw)
+        .lines()
+        .filter_map(|line| line.strip_prefix("data: "))
+        .filter_map(|data| serde_json::from_str::<serde_json::Value>(data).ok())
+        .filter_map(|mut chunk| chunk.get_mut("usage").map(serde_json::Value::take))
+        .find_map(|usage| serde_json::from_value::<TokenUsage>(usage).ok())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn collects_streamed_content() {
+        let raw = concat!(
+            "data: {\"choices\":[{\"delta\":{\"role\":\"assistant\"}}]}\n\n",
+            "data: {\"choices\":[{\"delta\":{\"content\":\"Hello\"}}]}\n\n",
+            "data: {\"choices\":[{\"delta\":{\"content\":\", world\"}}]}\n\n",
+            "data: {\"choices\":[],\"usage\":{\"prompt_tokens\":1}}\n\n",
+            "data: [DONE]\n\n",
+        );
+        assert_eq!(collect_streamed_content(raw.as_bytes()), "Hello, world");
+    }
+
+    #[test]
+    fn collects_streamed_usage() {
+        let raw = concat!(
+            "data: {\"choices\":[{\"delta\":{\"content\":\"Hello\"}}]}\n\n",
+            "data: {\"choices\":[],\"usage\":{\"prompt_tokens\":3,\"completion_tokens\":2}}\n\n",
+            "data: [DONE]\n\n",
+        );
+        let usage = TokenUsage {
+            prompt_tokens: 3,
+            completion_tokens: 2,
+        };
+        assert_eq!(collect_streamed_usage(raw.as_bytes()), Some(usage));
+        assert_eq!(collect_streamed_usage(b"data: [DONE]\n\n"), None);
+    }
+}
@@ -1,5 +1,5 @@
 use bon::Builder;
-use chrono::{DateTime, Utc};
+use chrono::{DateTime, FixedOffset, NaiveDateTime, NaiveTime, Utc, Weekday};
 use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 use utoipa::{IntoParams, ToSchema};
@@ -32,3 +32,47 @@ pub struct DateTimeShiftRequest {
     #[builder(default)]
     pub seconds: i64,
 }
+
+#[derive(Builder, Deserialize, IntoParams, JsonSchema, Serialize)]
+pub struct DateTimeConvertParams {
+    /// Local datetime to convert in ISO format without a UTC offset
+    /// (e.g., 2025-06-13T15:00:00).
+    pub datetime: NaiveDateTime,
+    /// IANA timezone name the datetime is in (e.g., "Asia/Tokyo").
+    /// Defaults to UTC.
+    pub from_timezone: Option<String>,
+    /// IANA timezone name to convert the datetime to (e.g., "America/New_York").
+    /// Defaults to UTC.
+    pub to_timezone: Option<String>,
+}
+
+#[derive(Builder, Deserialize, IntoParams, JsonSchema, Serialize)]
+pub struct DateTimeResolveParams {
+    /// Datetime to resolve from in ISO format. Defaults to now.
+    pub datetime: Option<DateTime<Utc>>,
+    /// IANA timezone name (e.g., "Asia/Tokyo") that weekdays and times of day
+    /// are relative to. Defaults to UTC.
+    pub timezone: Option<String>,
+    /// Number of weeks ahead (positive) or behind (negative).
+    pub weeks: Option<i64>,
+    /// Number of days ahead (positive) or behind (negative).
+    pub days: Option<i64>,
+    /// Move forward to the next occurrence of this weekday after shifting
+    /// by weeks and days (e.g., "Fri" for "next Friday"). Never resolves
+    /// to the same day.
+    #[param(value_type = Option<String>)]
+    pub weekday: Option<Weekday>,
+    /// Time of day in 24-hour HH:MM:SS format (e.g., 15:00:00 for 3pm).
+    /// Defaults to the time of the given datetime.
+    pub time: Option<NaiveTime>,
+}
+
+#[derive(Debug, Deserialize, PartialEq, Serialize, ToSchema)]
+pub struct ResolvedDateTime {
+    /// Resolved datetime in UTC ISO format.
+    pub utc: DateTime<Utc>,
+    /// Resolved datetime in ISO format with the local UTC offset.
+    pub local: DateTime<FixedOffset>,
+    /// IANA timezone name the local datetime is in.
+    pub timezone: String,
+}
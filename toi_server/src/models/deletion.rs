@@ -0,0 +1,66 @@
+use bon::Builder;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+use crate::models::error::ToiError;
+
+/// Search parameters that can say whether they narrow down which items are
+/// deleted.
+pub trait DeleteFilters {
+    /// Whether any parameter selects or filters items, so only some of them
+    /// would be deleted.
+    fn narrows(&self) -> bool;
+}
+
+#[derive(Builder, Deserialize, JsonSchema, Serialize, ToSchema)]
+pub struct DeleteParams<T> {
+    /// Parameters for finding the items to delete.
+    #[serde(flatten)]
+    pub params: T,
+    /// Confirm that every item should be deleted. Only set this to `true`
+    /// when the user clearly asked to delete everything (e.g., "delete all
+    /// notes"). It's required when no IDs, query, date range, limit, or
+    /// other filters are given, and ignored otherwise.
+    pub confirm_delete_all: Option<bool>,
+}
+
+impl<T: DeleteFilters> DeleteParams<T> {
+    /// Search parameters for the items to delete. Parameters that would
+    /// delete every item are rejected unless deleting everything was
+    /// confirmed.
+    pub fn into_checked(self) -> Result<T, ToiError> {
+        if self.params.narrows() || self.confirm_delete_all.unwrap_or_default() {
+            Ok(self.params)
+        } else {
+            Err(ToiError::Validation(
+                "no IDs, query, date range, limit, or other filters were given, so every item would be deleted; set `confirm_delete_all` to `true` to delete everything".to_string(),
+            ))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::notes::NoteSearchParams;
+
+    #[test]
+    fn checking_delete_params() {
+        let unfiltered = || NoteSearchParams::builder().build();
+        let params = DeleteParams::builder().params(unfiltered()).build();
+        assert!(matches!(
+            params.into_checked(),
+            Err(ToiError::Validation(_))
+        ));
+        let params = DeleteParams::builder()
+            .params(unfiltered())
+            .confirm_delete_all(true)
+            .build();
+        assert!(params.into_checked().is_ok());
+        let params = DeleteParams::builder()
+            .params(NoteSearchParams::builder().ids(vec![1]).build())
+            .build();
+        assert!(params.into_checked().is_ok());
+    }
+}
@@ -0,0 +1,175 @@
+use axum::{
+    http::StatusCode,
+    response::{IntoResponse, Json, Response},
+};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use utoipa::ToSchema;
+
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq, Serialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum ErrorCode {
+    NotFound,
+    Unauthorized,
+    Validation,
+    Conflict,
+    PayloadTooLarge,
+    RateLimited,
+    ModelApi,
+    Database,
+    Upstream,
+    Unavailable,
+}
+
+#[derive(Debug, Deserialize, PartialEq, Serialize, ToSchema)]
+pub struct ErrorDetail {
+    /// Machine-readable error category.
+    pub code: ErrorCode,
+    /// Short, human-readable summary of the error category.
+    pub message: String,
+    /// Specifics about what went wrong.
+    pub detail: String,
+}
+
+#[derive(Debug, Deserialize, PartialEq, Serialize, ToSchema)]
+pub struct ErrorResponse {
+    pub error: ErrorDetail,
+}
+
+#[derive(Debug)]
+pub enum ToiError {
+    /// An item the request refers to doesn't exist.
+    NotFound(String),
+    /// The request doesn't have valid credentials.
+    Unauthorized(String),
+    /// The request, or JSON elements configured by the user, are invalid.
+    Validation(String),
+    /// The request conflicts with an earlier one, like when an idempotency
+    /// key is reused with a different request body.
+    Conflict(String),
+    /// The request has more items than the server is configured to accept.
+    PayloadTooLarge(String),
+    /// The client has made too many requests recently.
+    RateLimited(String),
+    /// A request to or response from a model API couldn't be processed.
+    ModelApi(String),
+    /// The database, or something else internal to the server, failed.
+    Database(String),
+    /// A model API or other upstream service couldn't be reached.
+    Upstream(String),
+    /// The server is too busy to handle the request right now, like when
+    /// all database connections are in use.
+    Unavailable(String),
+}
+
+impl ToiError {
+    #[must_use]
+    pub fn code(&self) -> ErrorCode {
+        match self {
+            Self::NotFound(_) => ErrorCode::NotFound,
+            Self::Unauthorized(_) => ErrorCode::Unauthorized,
+            Self::Validation(_) => ErrorCode::Validation,
+            Self::Conflict(_) => ErrorCode::Conflict,
+            Self::PayloadTooLarge(_) => ErrorCode::PayloadTooLarge,
+            Self::RateLimited(_) => ErrorCode::RateLimited,
+            Self::ModelApi(_) => ErrorCode::ModelApi,
+            Self::Database(_) => ErrorCode::Database,
+            Self::Upstream(_) => ErrorCode::Upstream,
+            Self::Unavailable(_) => ErrorCode::Unavailable,
+        }
+    }
+
+    #[must_use]
+    pub fn status(&self) -> StatusCode {
+        match self {
+            Self::NotFound(_) => StatusCode::NOT_FOUND,
+            Self::Unauthorized(_) => StatusCode::UNAUTHORIZED,
+            Self::Validation(_) => StatusCode::BAD_REQUEST,
+            Self::Conflict(_) => StatusCode::CONFLICT,
+            Self::PayloadTooLarge(_) => StatusCode::PAYLOAD_TOO_LARGE,
+            Self::RateLimited(_) => StatusCode::TOO_MANY_REQUESTS,
+            Self::ModelApi(_) => StatusCode::UNPROCESSABLE_ENTITY,
+            Self::Database(_) => StatusCode::INTERNAL_SERVER_ERROR,
+            Self::Upstream(_) => StatusCode::BAD_GATEWAY,
+            Self::Unavailable(_) => StatusCode::SERVICE_UNAVAILABLE,
+        }
+    }
+
+    #[must_use]
+    pub fn message(&self) -> &'static str {
+        match self {
+            Self::NotFound(_) => "item not found",
+            Self::Unauthorized(_) => "unauthorized",
+            Self::Validation(_) => "invalid request",
+            Self::Conflict(_) => "request conflicts with an earlier request",
+            Self::PayloadTooLarge(_) => "request is too large",
+            Self::RateLimited(_) => "too many requests",
+            Self::ModelApi(_) => "couldn't process model API request or response",
+            Self::Database(_) => "internal server error",
+            Self::Upstream(_) => "couldn't reach upstream service",
+            Self::Unavailable(_) => "server is busy",
+        }
+    }
+
+    #[must_use]
+    pub fn detail(&self) -> &str {
+        match self {
+            Self::NotFound(detail)
+            | Self::Unauthorized(detail)
+            | Self::Validation(detail)
+            | Self::Conflict(detail)
+            | Self::PayloadTooLarge(detail)
+            | Self::RateLimited(detail)
+            | Self::ModelApi(detail)
+            | Self::Database(detail)
+            | Self::Upstream(detail)
+            | Self::Unavailable(detail) => detail,
+        }
+    }
+}
+
+impl fmt::Display for ToiError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.detail())
+    }
+}
+
+impl std::error::Error for ToiError {}
+
+impl IntoResponse for ToiError {
+    fn into_response(self) -> Response {
+        let body = ErrorResponse {
+            error: ErrorDetail {
+                code: self.code(),
+                message: self.message().to_string(),
+                detail: self.detail().to_string(),
+            },
+        };
+        (self.status(), Json(body)).into_response()
+    }
+}
+
+// Routes that haven't moved to `ToiError` yet still respond with plain
+// status and text tuples, so errors convert both ways with `?`.
+impl From<ToiError> for (StatusCode, String) {
+    fn from(err: ToiError) -> Self {
+        (err.status(), err.to_string())
+    }
+}
+
+impl From<(StatusCode, String)> for ToiError {
+    fn from((status, detail): (StatusCode, String)) -> Self {
+        match status {
+            StatusCode::NOT_FOUND => Self::NotFound(detail),
+            StatusCode::UNAUTHORIZED => Self::Unauthorized(detail),
+            StatusCode::BAD_REQUEST => Self::Validation(detail),
+            StatusCode::CONFLICT => Self::Conflict(detail),
+            StatusCode::PAYLOAD_TOO_LARGE => Self::PayloadTooLarge(detail),
+            StatusCode::TOO_MANY_REQUESTS => Self::RateLimited(detail),
+            StatusCode::UNPROCESSABLE_ENTITY => Self::ModelApi(detail),
+            StatusCode::BAD_GATEWAY => Self::Upstream(detail),
+            StatusCode::SERVICE_UNAVAILABLE => Self::Unavailable(detail),
+            _ => Self::Database(detail),
+        }
+    }
+}
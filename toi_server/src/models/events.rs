@@ -1,14 +1,78 @@
 use bon::Builder;
-use chrono::{DateTime, NaiveDate, Utc};
-use diesel::{Insertable, Queryable, Selectable};
+use chrono::{DateTime, Datelike, Days, Months, NaiveDate, NaiveTime, TimeDelta, TimeZone, Utc};
+use chrono_tz::Tz;
+use diesel::{
+    AsExpression, FromSqlRow, Insertable, Queryable, Selectable,
+    deserialize::{self, FromSql},
+    pg::{Pg, PgValue},
+    serialize::{self, Output, ToSql},
+    sql_types::Text,
+};
 use pgvector::Vector;
 use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
-use utoipa::ToSchema;
+use utoipa::{IntoParams, ToSchema};
 
-use crate::utils;
+use crate::{
+    models::{deletion::DeleteFilters, places::Place},
+    utils,
+};
 
-#[derive(Debug, Deserialize, PartialEq, Queryable, Selectable, Serialize, ToSchema)]
+#[derive(
+    AsExpression,
+    Clone,
+    Copy,
+    Debug,
+    Deserialize,
+    FromSqlRow,
+    JsonSchema,
+    PartialEq,
+    Serialize,
+    ToSchema,
+)]
+#[diesel(sql_type = Text)]
+#[serde(rename_all = "lowercase")]
+pub enum RecurrenceFrequency {
+    Daily,
+    Weekly,
+    Monthly,
+}
+
+impl RecurrenceFrequency {
+    /// Shift a datetime forward by a number of recurrence periods. Monthly
+    /// shifts are clamped to the last day of shorter months.
+    fn shift(self, datetime: DateTime<Utc>, periods: u32) -> Option<DateTime<Utc>> {
+        match self {
+            Self::Daily => datetime.checked_add_days(Days::new(periods.into())),
+            Self::Weekly => datetime.checked_add_days(Days::new(7 * u64::from(periods))),
+            Self::Monthly => datetime.checked_add_months(Months::new(periods)),
+        }
+    }
+}
+
+impl ToSql<Text, Pg> for RecurrenceFrequency {
+    fn to_sql<'b>(&'b self, out: &mut Output<'b, '_, Pg>) -> serialize::Result {
+        let value = match self {
+            Self::Daily => "daily",
+            Self::Weekly => "weekly",
+            Self::Monthly => "monthly",
+        };
+        <str as ToSql<Text, Pg>>::to_sql(value, &mut out.reborrow())
+    }
+}
+
+impl FromSql<Text, Pg> for RecurrenceFrequency {
+    fn from_sql(bytes: PgValue<'_>) -> deserialize::Result<Self> {
+        match bytes.as_bytes() {
+            b"daily" => Ok(Self::Daily),
+            b"weekly" => Ok(Self::Weekly),
+            b"monthly" => Ok(Self::Monthly),
+            _ => Err("unrecognized recurrence frequency".into()),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Deserialize, PartialEq, Queryable, Selectable, Serialize, ToSchema)]
 #[diesel(table_name = crate::schema::events)]
 #[diesel(check_for_backend(diesel::pg::Pg))]
 pub struct Event {
@@ -22,40 +86,257 @@ pub struct Event {
     pub starts_at: DateTime<Utc>,
     /// Datetime the event ends in ISO format.
     pub ends_at: DateTime<Utc>,
+    /// How often the event repeats, if it repeats at all.
+    pub recurrence_frequency: Option<RecurrenceFrequency>,
+    /// Number of frequency periods between each repeat of the event.
+    pub recurrence_interval: Option<i32>,
+    /// Datetime the event stops repeating in ISO format.
+    pub recurrence_until: Option<DateTime<Utc>>,
+    /// Database-generated ID of the place the event is at. It's cleared if
+    /// the place is deleted.
+    pub place_id: Option<i32>,
+}
+
+#[derive(Debug, Deserialize, PartialEq, Serialize, ToSchema)]
+pub struct EventWithPlace {
+    /// Matching event.
+    #[serde(flatten)]
+    pub event: Event,
+    /// The place the event is at. Only included when asked for.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub place: Option<Place>,
+}
+
+/// An added event along with the existing events it conflicts with.
+#[derive(Debug, Deserialize, PartialEq, Serialize, ToSchema)]
+pub struct EventCreated {
+    /// Added event.
+    #[serde(flatten)]
+    pub event: Event,
+    /// Existing events whose times overlap with the added event's. The event
+    /// is still added, so the user should be told about these conflicts.
+    #[serde(default)]
+    pub conflicts: Vec<Event>,
+}
+
+impl Event {
+    /// Whether the event, or any of its repeats, overlaps with the window
+    /// between the given start and end datetimes.
+    pub fn occurs_within(&self, window_start: DateTime<Utc>, window_end: DateTime<Utc>) -> bool {
+        self.first_occurrence_within(window_start, window_end)
+            .is_some()
+    }
+
+    /// Start of the first occurrence of the event, or any of its repeats,
+    /// that overlaps with the window between the given start and end
+    /// datetimes.
+    pub fn first_occurrence_within(
+        &self,
+        window_start: DateTime<Utc>,
+        window_end: DateTime<Utc>,
+    ) -> Option<DateTime<Utc>> {
+        let Some(frequency) = self.recurrence_frequency else {
+            return (self.starts_at <= window_end && self.ends_at >= window_start)
+                .then_some(self.starts_at);
+        };
+        let duration = self.ends_at - self.starts_at;
+        let interval = self.interval();
+        let mut periods = 0;
+        while let Some(starts_at) = frequency.shift(self.starts_at, periods) {
+            if starts_at > window_end
+                || self.recurrence_until.is_some_and(|until| starts_at > until)
+            {
+                return None;
+            }
+            if starts_at + duration >= window_start {
+                return Some(starts_at);
+            }
+            periods = periods.checked_add(interval)?;
+        }
+        None
+    }
+
+    /// Start of the first occurrence of the event, or any of its repeats,
+    /// that starts at or after the given datetime.
+    #[must_use]
+    pub fn next_start_from(&self, from: DateTime<Utc>) -> Option<DateTime<Utc>> {
+        let Some(frequency) = self.recurrence_frequency else {
+            return (self.starts_at >= from).then_some(self.starts_at);
+        };
+        let interval = self.interval();
+        let mut periods = 0;
+        while let Some(starts_at) = frequency.shift(self.starts_at, periods) {
+            if self.recurrence_until.is_some_and(|until| starts_at > until) {
+                return None;
+            }
+            if starts_at >= from {
+                return Some(starts_at);
+            }
+            periods = periods.checked_add(interval)?;
+        }
+        None
+    }
+
+    /// iCalendar recurrence rule for repeating events, like
+    /// `FREQ=WEEKLY;INTERVAL=2`.
+    #[must_use]
+    pub fn recurrence_rule(&self) -> Option<String> {
+        let frequency = self.recurrence_frequency?;
+        let mut rule = match frequency {
+            RecurrenceFrequency::Daily => "FREQ=DAILY".to_string(),
+            RecurrenceFrequency::Weekly => "FREQ=WEEKLY".to_string(),
+            RecurrenceFrequency::Monthly => "FREQ=MONTHLY".to_string(),
+        };
+        rule.push_str(&format!(";INTERVAL={}", self.interval()));
+        // Monthly repeats are clamped to the last day of shorter months,
+        // whereas calendars skip months without the day unless told to
+        // fall back to the last day.
+        let day = self.starts_at.day();
+        if frequency == RecurrenceFrequency::Monthly && day > 28 {
+            rule.push_str(&format!(";BYMONTHDAY={day},-1;BYSETPOS=1"));
+        }
+        if let Some(until) = self.recurrence_until {
+            rule.push_str(&format!(";UNTIL={}", until.format("%Y%m%dT%H%M%SZ")));
+        }
+        Some(rule)
+    }
+
+    /// Number of frequency periods between each repeat of the event.
+    fn interval(&self) -> u32 {
+        self.recurrence_interval
+            .and_then(|interval| u32::try_from(interval).ok())
+            .unwrap_or(1)
+            .max(1)
+    }
 }
 
 #[derive(Insertable)]
 #[diesel(table_name = crate::schema::events)]
 #[diesel(check_for_backend(diesel::pg::Pg))]
 pub struct NewEvent {
+    pub user_id: i32,
     pub description: String,
     pub embedding: Vector,
     pub starts_at: DateTime<Utc>,
     pub ends_at: DateTime<Utc>,
+    pub recurrence_frequency: Option<RecurrenceFrequency>,
+    pub recurrence_interval: Option<i32>,
+    pub recurrence_until: Option<DateTime<Utc>>,
+    pub place_id: Option<i32>,
+}
+
+/// How long events that end when they start are assumed to last when
+/// checking for conflicts, since they're usually added without an end.
+pub const UNTIMED_EVENT_DURATION: TimeDelta = TimeDelta::hours(1);
+
+/// When an event ends for checking conflicts. Events that end when they
+/// start are assumed to last for `UNTIMED_EVENT_DURATION`.
+#[must_use]
+pub fn conflict_end(starts_at: DateTime<Utc>, ends_at: DateTime<Utc>) -> DateTime<Utc> {
+    if ends_at > starts_at {
+        ends_at
+    } else {
+        starts_at + UNTIMED_EVENT_DURATION
+    }
+}
+
+/// Make sure an event doesn't end before it starts, returning its start and
+/// end. Generated requests sometimes mix up the start and end, so reversed
+/// times are swapped instead of rejected if `swap_if_reversed` is set.
+pub fn order_event_times(
+    starts_at: DateTime<Utc>,
+    ends_at: DateTime<Utc>,
+    swap_if_reversed: bool,
+) -> Result<(DateTime<Utc>, DateTime<Utc>), String> {
+    if ends_at >= starts_at {
+        Ok((starts_at, ends_at))
+    } else if swap_if_reversed {
+        Ok((ends_at, starts_at))
+    } else {
+        Err(format!(
+            "event ends at {ends_at} before it starts at {starts_at}"
+        ))
+    }
 }
 
 #[derive(Builder, Deserialize, JsonSchema, Serialize, ToSchema)]
 pub struct NewEventRequest {
     /// Event description to add.
     pub description: String,
-    /// Datetime the event starts in ISO format.
+    /// Datetime the event starts in ISO format. For repeating events,
+    /// this is when the first occurrence starts.
     pub starts_at: DateTime<Utc>,
-    /// Datetime the event ends in ISO format.
+    /// Datetime the event ends in ISO format. For repeating events,
+    /// this is when the first occurrence ends.
     pub ends_at: DateTime<Utc>,
+    /// How often the event repeats. Leave empty for events that only
+    /// happen once. E.g., "standup every weekday" is a daily event and
+    /// "book club every other Tuesday" is a weekly event.
+    pub recurrence_frequency: Option<RecurrenceFrequency>,
+    /// Number of frequency periods between each repeat of the event.
+    /// E.g., "every other week" is a weekly event with an interval of 2.
+    ///
+    /// Defaults to 1 for repeating events.
+    pub recurrence_interval: Option<i32>,
+    /// Optional datetime the event stops repeating in ISO format.
+    pub recurrence_until: Option<DateTime<Utc>>,
+    /// Link the event to a place using the place's database-generated ID
+    /// rather than searching for it.
+    pub place_id: Option<i32>,
+    /// Query string for finding the place the event is at, like "Luigi's"
+    /// for "dinner at Luigi's on Friday". Leave this empty if the event
+    /// isn't at a saved place.
+    pub place_query: Option<String>,
+    /// Add the event even if it looks like a duplicate of an existing event
+    /// that starts at the same time. Only set this if the user explicitly
+    /// asks for it.
+    pub allow_duplicate: Option<bool>,
+    /// Don't add the event if its time overlaps with any existing events.
+    /// Only set this if the user explicitly asks not to double-book.
+    /// Otherwise, the event is added and the overlapping events are listed
+    /// as conflicts.
+    pub fail_on_conflict: Option<bool>,
 }
 
-#[derive(Builder, Deserialize, JsonSchema, Serialize, ToSchema)]
+#[derive(Clone, Copy, Debug, Deserialize, JsonSchema, PartialEq, Serialize, ToSchema)]
+pub enum EventOrderBy {
+    /// Order by when events were created, oldest first.
+    Oldest,
+    /// Order by when events were created, newest first.
+    Newest,
+    /// Order by when events, or their next repeats, start, soonest first.
+    /// Useful for questions like "what's my next event?".
+    StartsSoonest,
+    /// Order by when events, or their next repeats, start, latest first.
+    StartsLatest,
+}
+
+impl EventOrderBy {
+    /// Whether events are ordered by when they start rather than when they
+    /// were created.
+    #[must_use]
+    pub fn by_start(self) -> bool {
+        matches!(self, Self::StartsSoonest | Self::StartsLatest)
+    }
+}
+
+#[derive(Builder, Clone, Deserialize, JsonSchema, Serialize, ToSchema)]
 pub struct EventSearchParams {
     /// Select events using their database-generated IDs rather than searching
     /// for them.
     pub ids: Option<Vec<i32>>,
     /// Event day search parameter. What kind of search depends on
-    /// the `falls_on` field.
+    /// the `falls_on` field. Repeating events match if any of their
+    /// repeats fall on the event day.
     pub event_day: Option<NaiveDate>,
     /// What kind of calendar object the event falls on. Used
     /// to search if an event falls on the month of, week of,
     /// or day of `event_day`.
     pub event_day_falls_on: Option<utils::DateFallsOn>,
+    /// IANA timezone name (e.g., "America/Chicago") that `event_day` is in,
+    /// so the day, week, or month starts and ends at local midnight. Leave
+    /// empty to use the server's timezone.
+    pub timezone: Option<String>,
     /// User query string to compare embeddings against. Basically,
     /// if the user is asking something like "what color is my jacket?",
     /// then the query string should be something like "jacket color" or
@@ -72,8 +353,445 @@ pub struct EventSearchParams {
     pub created_from: Option<DateTime<Utc>>,
     /// Filter on events created before this ISO formatted datetime.
     pub created_to: Option<DateTime<Utc>>,
+    /// Filter on events, or repeats of events, still going on at or after
+    /// this ISO formatted datetime.
+    pub occurs_from: Option<DateTime<Utc>>,
+    /// Filter on events, or repeats of events, starting at or before this
+    /// ISO formatted datetime.
+    pub occurs_to: Option<DateTime<Utc>>,
+    /// Only get events, or repeats of events, that haven't started yet. Use
+    /// with `StartsSoonest` ordering and a limit of 1 for questions like
+    /// "what's my next event?".
+    pub upcoming_only: Option<bool>,
+    /// Filter on events at a place using the place's database-generated ID.
+    pub place_id: Option<i32>,
+    /// Filter on events at the place that best matches this query string,
+    /// like "Luigi's" for "when am I going to Luigi's".
+    pub place_query: Option<String>,
+    /// Whether to include the place each event is at. Useful for questions
+    /// like "where is Friday's dinner?".
+    pub include_place: Option<bool>,
     /// How to order results for retrieved events.
-    pub order_by: Option<utils::OrderBy>,
+    pub order_by: Option<EventOrderBy>,
     /// Limit the max number of events to return from the search.
     pub limit: Option<i64>,
+    /// Skip this many matching events before returning results. Use with
+    /// the limit to page through many events.
+    pub offset: Option<i64>,
+    /// Only count matching events instead of returning them. Useful for
+    /// questions like "how many events are there".
+    pub count_only: Option<bool>,
+}
+
+impl DeleteFilters for EventSearchParams {
+    fn narrows(&self) -> bool {
+        self.ids.is_some()
+            || self.event_day.is_some()
+            || self.query.is_some()
+            || self.created_from.is_some()
+            || self.created_to.is_some()
+            || self.occurs_from.is_some()
+            || self.occurs_to.is_some()
+            || self.upcoming_only.unwrap_or_default()
+            || self.place_id.is_some()
+            || self.place_query.is_some()
+            || self.limit.is_some()
+    }
+}
+
+#[derive(Clone, Copy, Debug, Deserialize, JsonSchema, PartialEq, Serialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum UpcomingWindow {
+    Today,
+    Tomorrow,
+    ThisWeek,
+    NextWeek,
+    ThisMonth,
+}
+
+/// Start of a local day as a UTC datetime. If midnight is skipped by a
+/// daylight saving time transition, the day starts an hour later instead.
+fn start_of_day(date: NaiveDate, timezone: Tz) -> Option<DateTime<Utc>> {
+    let midnight = date.and_time(NaiveTime::MIN);
+    timezone
+        .from_local_datetime(&midnight)
+        .earliest()
+        .or_else(|| {
+            timezone
+                .from_local_datetime(&(midnight + TimeDelta::hours(1)))
+                .earliest()
+        })
+        .map(|datetime| datetime.with_timezone(&Utc))
+}
+
+/// Start and end datetimes of the local day, week, or month an event day
+/// falls on in a timezone. Weeks start on Sunday, and days shortened or
+/// lengthened by daylight saving time transitions still start and end at
+/// local midnight.
+#[must_use]
+pub fn event_day_window(
+    event_day: NaiveDate,
+    falls_on: Option<&utils::DateFallsOn>,
+    timezone: Tz,
+) -> Option<(DateTime<Utc>, DateTime<Utc>)> {
+    let (start_day, end_day) = match falls_on {
+        Some(utils::DateFallsOn::Month) => {
+            let first_day_of_month = event_day.with_day(1)?;
+            (
+                first_day_of_month,
+                first_day_of_month.checked_add_months(Months::new(1))?,
+            )
+        }
+        Some(utils::DateFallsOn::Week) => {
+            let this_weeks_sunday = event_day
+                .checked_sub_days(Days::new(event_day.weekday().num_days_from_sunday().into()))?;
+            (
+                this_weeks_sunday,
+                this_weeks_sunday.checked_add_days(Days::new(7))?,
+            )
+        }
+        Some(utils::DateFallsOn::Day) | None => {
+            (event_day, event_day.checked_add_days(Days::new(1))?)
+        }
+    };
+    let start = start_of_day(start_day, timezone)?;
+    let end = start_of_day(end_day, timezone)? - TimeDelta::seconds(1);
+    Some((start, end))
+}
+
+impl UpcomingWindow {
+    /// Start and end datetimes of the window relative to the current
+    /// datetime in a timezone. Weeks start on Sunday, and windows that
+    /// include the current datetime start from it rather than from the
+    /// start of the day, week, or month.
+    pub fn range(self, now: DateTime<Utc>, timezone: Tz) -> Option<(DateTime<Utc>, DateTime<Utc>)> {
+        let today = now.with_timezone(&timezone).date_naive();
+        let this_weeks_sunday =
+            today.checked_sub_days(Days::new(today.weekday().num_days_from_sunday().into()))?;
+        let (start_day, end_day) = match self {
+            Self::Today => (today, today.checked_add_days(Days::new(1))?),
+            Self::Tomorrow => (
+                today.checked_add_days(Days::new(1))?,
+                today.checked_add_days(Days::new(2))?,
+            ),
+            Self::ThisWeek => (
+                this_weeks_sunday,
+                this_weeks_sunday.checked_add_days(Days::new(7))?,
+            ),
+            Self::NextWeek => (
+                this_weeks_sunday.checked_add_days(Days::new(7))?,
+                this_weeks_sunday.checked_add_days(Days::new(14))?,
+            ),
+            Self::ThisMonth => {
+                let first_day_of_month = today.with_day(1)?;
+                (
+                    first_day_of_month,
+                    first_day_of_month.checked_add_months(Months::new(1))?,
+                )
+            }
+        };
+        let start = start_of_day(start_day, timezone)?.max(now);
+        let end = start_of_day(end_day, timezone)? - TimeDelta::seconds(1);
+        Some((start, end))
+    }
+}
+
+#[derive(Builder, Deserialize, JsonSchema, Serialize, ToSchema)]
+pub struct UpcomingEventsRequest {
+    /// Window of time to get upcoming events for relative to now. E.g.,
+    /// "what's coming up this week" is "this_week" and "what do I have
+    /// going on tomorrow" is "tomorrow".
+    pub window: UpcomingWindow,
+    /// Limit the max number of upcoming events to return.
+    pub limit: Option<usize>,
+}
+
+#[derive(Debug, Deserialize, PartialEq, Serialize, ToSchema)]
+pub struct UpcomingEvent {
+    #[serde(flatten)]
+    pub event: Event,
+    /// Datetime the next occurrence of the event within the window starts
+    /// in ISO format.
+    pub occurs_at: DateTime<Utc>,
+    /// Names of contacts attending the event.
+    pub attendees: Vec<String>,
+}
+
+#[derive(Builder, Default, Deserialize, IntoParams, JsonSchema, Serialize)]
+pub struct EventCalendarParams {
+    /// Only include events, or repeats of events, still going on at or after
+    /// this ISO formatted datetime.
+    pub from: Option<DateTime<Utc>>,
+    /// Only include events, or repeats of events, starting at or before this
+    /// ISO formatted datetime.
+    pub to: Option<DateTime<Utc>>,
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{DateTime, Utc};
+    use chrono_tz::Tz;
+
+    use super::{
+        Event, RecurrenceFrequency, UpcomingWindow, conflict_end, event_day_window,
+        order_event_times,
+    };
+    use crate::utils::DateFallsOn;
+
+    fn datetime(value: &str) -> DateTime<Utc> {
+        value.parse().expect("datetime should be valid")
+    }
+
+    fn event(
+        starts_at: &str,
+        recurrence_frequency: RecurrenceFrequency,
+        recurrence_interval: Option<i32>,
+        recurrence_until: Option<&str>,
+    ) -> Event {
+        let starts_at = datetime(starts_at);
+        Event {
+            id: 1,
+            description: "standup".to_string(),
+            created_at: starts_at,
+            starts_at,
+            ends_at: starts_at + chrono::Duration::minutes(30),
+            recurrence_frequency: Some(recurrence_frequency),
+            recurrence_interval,
+            recurrence_until: recurrence_until.map(datetime),
+            place_id: None,
+        }
+    }
+
+    fn occurs_on(event: &Event, day: &str) -> bool {
+        let window_start = datetime(&format!("{day}T00:00:00Z"));
+        let window_end = datetime(&format!("{day}T23:59:59Z"));
+        event.occurs_within(window_start, window_end)
+    }
+
+    #[test]
+    fn weekly_recurrence() {
+        let event = event(
+            "2025-05-06T09:00:00Z",
+            RecurrenceFrequency::Weekly,
+            None,
+            None,
+        );
+        assert!(occurs_on(&event, "2025-05-06"));
+        assert!(occurs_on(&event, "2025-06-03"));
+        assert!(!occurs_on(&event, "2025-06-04"));
+        assert!(!occurs_on(&event, "2025-04-29"));
+    }
+
+    #[test]
+    fn biweekly_recurrence() {
+        let event = event(
+            "2025-05-06T09:00:00Z",
+            RecurrenceFrequency::Weekly,
+            Some(2),
+            None,
+        );
+        assert!(occurs_on(&event, "2025-05-20"));
+        assert!(!occurs_on(&event, "2025-05-13"));
+    }
+
+    #[test]
+    fn monthly_recurrence_across_month_boundaries() {
+        let event = event(
+            "2025-01-31T09:00:00Z",
+            RecurrenceFrequency::Monthly,
+            None,
+            None,
+        );
+        assert!(occurs_on(&event, "2025-02-28"));
+        assert!(occurs_on(&event, "2025-03-31"));
+        assert!(occurs_on(&event, "2025-04-30"));
+        assert!(!occurs_on(&event, "2025-03-28"));
+        assert!(occurs_on(&event, "2028-02-29"));
+    }
+
+    #[test]
+    fn recurrence_rules() {
+        let biweekly = event(
+            "2025-05-06T09:00:00Z",
+            RecurrenceFrequency::Weekly,
+            Some(2),
+            Some("2025-07-01T09:00:00Z"),
+        );
+        assert_eq!(
+            biweekly.recurrence_rule().as_deref(),
+            Some("FREQ=WEEKLY;INTERVAL=2;UNTIL=20250701T090000Z")
+        );
+        let end_of_month = event(
+            "2025-01-31T09:00:00Z",
+            RecurrenceFrequency::Monthly,
+            None,
+            None,
+        );
+        assert_eq!(
+            end_of_month.recurrence_rule().as_deref(),
+            Some("FREQ=MONTHLY;INTERVAL=1;BYMONTHDAY=31,-1;BYSETPOS=1")
+        );
+        let one_off = Event {
+            recurrence_frequency: None,
+            ..biweekly
+        };
+        assert_eq!(one_off.recurrence_rule(), None);
+    }
+
+    #[test]
+    fn daily_recurrence_across_year_boundary() {
+        let event = event(
+            "2024-12-30T23:45:00Z",
+            RecurrenceFrequency::Daily,
+            None,
+            None,
+        );
+        assert!(occurs_on(&event, "2025-01-01"));
+        assert!(occurs_on(&event, "2025-01-02"));
+    }
+
+    #[test]
+    fn recurrence_until_cutoff() {
+        let event = event(
+            "2025-05-01T09:00:00Z",
+            RecurrenceFrequency::Daily,
+            None,
+            Some("2025-05-10T09:00:00Z"),
+        );
+        assert!(occurs_on(&event, "2025-05-10"));
+        assert!(!occurs_on(&event, "2025-05-11"));
+    }
+
+    #[test]
+    fn next_start_of_repeating_events() {
+        let event = event(
+            "2025-05-06T09:00:00Z",
+            RecurrenceFrequency::Weekly,
+            None,
+            Some("2025-05-20T09:00:00Z"),
+        );
+        let next_start = |from: &str| event.next_start_from(datetime(from));
+        assert_eq!(
+            next_start("2025-05-01T00:00:00Z"),
+            Some(datetime("2025-05-06T09:00:00Z"))
+        );
+        assert_eq!(
+            next_start("2025-05-06T09:15:00Z"),
+            Some(datetime("2025-05-13T09:00:00Z"))
+        );
+        assert_eq!(next_start("2025-05-20T09:15:00Z"), None);
+    }
+
+    #[test]
+    fn upcoming_windows_across_month_boundary() {
+        // Saturday evening in New York is already Sunday in UTC, and the last
+        // day of the month.
+        let now = datetime("2025-06-01T01:30:00Z");
+        let timezone = Tz::America__New_York;
+        let range =
+            |window: UpcomingWindow| window.range(now, timezone).expect("window should be valid");
+
+        let (start, end) = range(UpcomingWindow::Today);
+        assert_eq!(start, now);
+        assert_eq!(end, datetime("2025-06-01T03:59:59Z"));
+
+        let (start, end) = range(UpcomingWindow::Tomorrow);
+        assert_eq!(start, datetime("2025-06-01T04:00:00Z"));
+        assert_eq!(end, datetime("2025-06-02T03:59:59Z"));
+
+        let (start, end) = range(UpcomingWindow::ThisWeek);
+        assert_eq!(start, now);
+        assert_eq!(end, datetime("2025-06-01T03:59:59Z"));
+
+        let (start, end) = range(UpcomingWindow::NextWeek);
+        assert_eq!(start, datetime("2025-06-01T04:00:00Z"));
+        assert_eq!(end, datetime("2025-06-08T03:59:59Z"));
+
+        let (start, end) = range(UpcomingWindow::ThisMonth);
+        assert_eq!(start, now);
+        assert_eq!(end, datetime("2025-06-01T03:59:59Z"));
+    }
+
+    #[test]
+    fn ordering_event_times() {
+        let starts_at = datetime("2025-05-06T09:00:00Z");
+        let ends_at = datetime("2025-05-06T10:00:00Z");
+        assert_eq!(
+            order_event_times(starts_at, ends_at, false),
+            Ok((starts_at, ends_at))
+        );
+        assert_eq!(
+            order_event_times(starts_at, starts_at, false),
+            Ok((starts_at, starts_at))
+        );
+
+        // Reversed times are rejected unless they're swapped.
+        assert!(order_event_times(ends_at, starts_at, false).is_err());
+        assert_eq!(
+            order_event_times(ends_at, starts_at, true),
+            Ok((starts_at, ends_at))
+        );
+    }
+
+    #[test]
+    fn ending_events_for_conflicts() {
+        let starts_at = datetime("2025-05-06T09:00:00Z");
+        let ends_at = datetime("2025-05-06T09:30:00Z");
+        assert_eq!(conflict_end(starts_at, ends_at), ends_at);
+
+        // Events that end when they start are assumed to last an hour.
+        assert_eq!(
+            conflict_end(starts_at, starts_at),
+            datetime("2025-05-06T10:00:00Z")
+        );
+    }
+
+    #[test]
+    fn event_day_windows() {
+        let day = |value: &str| value.parse().expect("date should be valid");
+
+        // Late evening in Chicago is already the next day in UTC, so the
+        // day is bounded by Chicago's midnights.
+        let chicago = Tz::America__Chicago;
+        assert_eq!(
+            event_day_window(day("2025-06-01"), None, chicago),
+            Some((
+                datetime("2025-06-01T05:00:00Z"),
+                datetime("2025-06-02T04:59:59Z")
+            ))
+        );
+        assert_eq!(
+            event_day_window(day("2025-06-01"), None, Tz::UTC),
+            Some((
+                datetime("2025-06-01T00:00:00Z"),
+                datetime("2025-06-01T23:59:59Z")
+            ))
+        );
+
+        // Clocks spring forward on 2025-03-09 in New York, so the day is
+        // only 23 hours long.
+        let new_york = Tz::America__New_York;
+        let (start, end) = event_day_window(day("2025-03-09"), Some(&DateFallsOn::Day), new_york)
+            .expect("window should be valid");
+        assert_eq!(start, datetime("2025-03-09T05:00:00Z"));
+        assert_eq!(end, datetime("2025-03-10T03:59:59Z"));
+        assert_eq!((end - start).num_seconds(), 23 * 60 * 60 - 1);
+
+        // Weeks start on Sunday, and months end at the start of the next
+        // month.
+        assert_eq!(
+            event_day_window(day("2025-03-12"), Some(&DateFallsOn::Week), new_york),
+            Some((
+                datetime("2025-03-09T05:00:00Z"),
+                datetime("2025-03-16T03:59:59Z")
+            ))
+        );
+        assert_eq!(
+            event_day_window(day("2025-03-12"), Some(&DateFallsOn::Month), new_york),
+            Some((
+                datetime("2025-03-01T05:00:00Z"),
+                datetime("2025-04-01T03:59:59Z")
+            ))
+        );
+    }
 }
@@ -0,0 +1,75 @@
+use bon::Builder;
+use chrono::{DateTime, NaiveDate, Utc};
+use serde::{Deserialize, Serialize};
+use utoipa::{IntoParams, ToSchema};
+
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq, Serialize, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum ExportDomain {
+    Contacts,
+    Events,
+    Notes,
+    Recipes,
+    Todos,
+    Transactions,
+}
+
+#[derive(Clone, Copy, Debug, Default, Deserialize, PartialEq, Serialize, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum ExportFormat {
+    /// One JSON object per line.
+    #[default]
+    Jsonl,
+    /// Comma-separated values with a header row.
+    Csv,
+}
+
+#[derive(Builder, Default, Deserialize, IntoParams, Serialize)]
+#[serde(default)]
+pub struct ExportParams {
+    /// Format to export items in. Defaults to JSON lines.
+    #[builder(default)]
+    pub format: ExportFormat,
+}
+
+#[derive(Debug, Deserialize, PartialEq, Serialize, ToSchema)]
+pub struct ContactExport {
+    /// Unique contact ID.
+    pub id: i32,
+    /// Contact's first name.
+    pub first_name: String,
+    /// Contact's last name.
+    pub last_name: Option<String>,
+    /// Contact's email.
+    pub email: Option<String>,
+    /// Contact's phone number in XXX-XXX-XXXX format.
+    pub phone: Option<String>,
+    /// Contact's birthday.
+    pub birthday: Option<NaiveDate>,
+    /// Short description of relationship to the contact.
+    pub relationship: Option<String>,
+    /// Datetime the contact was created in ISO format.
+    pub created_at: DateTime<Utc>,
+    /// Additional labeled emails formatted like "label: value" and
+    /// separated by semicolons.
+    pub emails: String,
+    /// Additional labeled phone numbers formatted like "label: value" and
+    /// separated by semicolons.
+    pub phones: String,
+}
+
+#[derive(Debug, Deserialize, PartialEq, Serialize, ToSchema)]
+pub struct RecipeExport {
+    /// Unique recipe ID.
+    pub id: i32,
+    /// Recipe title or description.
+    pub description: String,
+    /// Recipe ingredients.
+    pub ingredients: String,
+    /// Recipe instructions.
+    pub instructions: String,
+    /// Datetime the recipe was created in ISO format.
+    pub created_at: DateTime<Utc>,
+    /// Recipe tag names separated by commas.
+    pub tags: String,
+}
@@ -0,0 +1,61 @@
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+#[derive(Debug, Deserialize, PartialEq, Serialize, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum DependencyStatus {
+    Ok,
+    Error,
+}
+
+#[derive(Debug, Deserialize, PartialEq, Serialize, ToSchema)]
+pub struct DependencyHealth {
+    /// Whether the dependency is usable.
+    pub status: DependencyStatus,
+    /// Reason the dependency isn't usable.
+    pub error: Option<String>,
+}
+
+impl DependencyHealth {
+    #[must_use]
+    pub fn is_ok(&self) -> bool {
+        self.status == DependencyStatus::Ok
+    }
+}
+
+impl From<Result<(), String>> for DependencyHealth {
+    fn from(result: Result<(), String>) -> Self {
+        match result {
+            Ok(()) => Self {
+                status: DependencyStatus::Ok,
+                error: None,
+            },
+            Err(err) => Self {
+                status: DependencyStatus::Error,
+                error: Some(err),
+            },
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, PartialEq, Serialize, ToSchema)]
+pub struct Readiness {
+    /// Database health.
+    pub database: DependencyHealth,
+    /// Embedding API health.
+    pub embedding: DependencyHealth,
+    /// Generation API health.
+    pub generation: DependencyHealth,
+    /// Reranking API health.
+    pub reranking: DependencyHealth,
+}
+
+impl Readiness {
+    #[must_use]
+    pub fn is_ready(&self) -> bool {
+        self.database.is_ok()
+            && self.embedding.is_ok()
+            && self.generation.is_ok()
+            && self.reranking.is_ok()
+    }
+}
@@ -0,0 +1,16 @@
+use chrono::{DateTime, Utc};
+use diesel::{Insertable, Queryable, Selectable};
+
+/// Response stored for an idempotency key so repeat requests with the key
+/// get the same response instead of adding another item.
+#[derive(Insertable, Queryable, Selectable)]
+#[diesel(table_name = crate::schema::idempotency_keys)]
+#[diesel(primary_key(key, endpoint))]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+pub struct StoredResponse {
+    pub key: String,
+    pub endpoint: String,
+    pub request_hash: String,
+    pub response_body: String,
+    pub created_at: DateTime<Utc>,
+}
@@ -6,6 +6,8 @@ use serde::{Deserialize, Serialize};
 use serde_json::{Value, json};
 use utoipa::{IntoParams, ToSchema};
 
+use crate::models::config::NewsFeedConfig;
+
 #[derive(Insertable, Queryable, Selectable)]
 #[diesel(table_name = crate::schema::news)]
 #[diesel(check_for_backend(diesel::pg::Pg))]
@@ -15,6 +17,7 @@ pub struct News {
     pub title: Option<String>,
     pub url: Option<String>,
     pub updated_at: Option<DateTime<Utc>>,
+    pub source: Option<String>,
 }
 
 #[derive(Queryable, Selectable)]
@@ -31,6 +34,8 @@ pub struct Alias {
 pub struct NewRedirect {
     pub tinyurl: String,
     pub title: Option<String>,
+    /// Name of the feed or publisher the article is from.
+    pub source: Option<String>,
 }
 
 #[derive(Insertable)]
@@ -61,6 +66,26 @@ pub struct ExpiredRedirect {
     pub title: Option<String>,
     pub url: Option<String>,
     pub updated_at: Option<DateTime<Utc>>,
+    pub source: Option<String>,
+}
+
+#[derive(Insertable, Queryable, Selectable)]
+#[diesel(table_name = crate::schema::news_feeds)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+pub struct NewsFeed {
+    pub name: String,
+    pub url: String,
+    pub is_default: bool,
+}
+
+impl From<NewsFeedConfig> for NewsFeed {
+    fn from(value: NewsFeedConfig) -> Self {
+        Self {
+            name: value.name,
+            url: value.url,
+            is_default: value.default,
+        }
+    }
 }
 
 #[derive(Builder, Deserialize, IntoParams, JsonSchema, Serialize, ToSchema)]
@@ -70,15 +95,26 @@ pub struct GetNewsRequest {
     /// should be "apnews.com". This should be null if the user isn't
     /// requesting something specific.
     pub query: Option<String>,
+    /// Name of the news source to get news from. E.g., if the user says
+    /// "get news from hacker news", then this should be "hacker news".
+    /// This should be null if the user doesn't name a source.
+    pub source: Option<String>,
     /// Limit the search to articles published up to this many hours in the past.
     #[param(minimum = 1, maximum = 24)]
     #[schemars(range(min = 1, max = 24))]
     pub when: Option<u8>,
+    /// Max number of articles to get.
+    #[param(minimum = 1)]
+    #[schemars(range(min = 1))]
+    pub max_items: Option<i64>,
 }
 
 impl From<GetNewsRequest> for (&'static str, Value) {
     fn from(value: GetNewsRequest) -> Self {
         let mut s = vec![];
+        if let Some(source) = value.source {
+            s.push(source);
+        }
         if let Some(search) = value.query {
             s.push(search);
         }
@@ -6,7 +6,13 @@ use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 use utoipa::ToSchema;
 
-use crate::utils;
+use crate::{
+    models::{
+        deletion::DeleteFilters,
+        tags::{Tag, TagMatch},
+    },
+    utils,
+};
 
 #[derive(Debug, Deserialize, PartialEq, Queryable, Selectable, Serialize, ToSchema)]
 #[diesel(table_name = crate::schema::notes)]
@@ -18,24 +24,291 @@ pub struct Note {
     pub content: String,
     /// Datetime the note was created in ISO format.
     pub created_at: DateTime<Utc>,
+    /// Datetime the note was moved to the trash in ISO format.
+    pub deleted_at: Option<DateTime<Utc>>,
+    /// Whether the note is pinned so it's listed before other notes.
+    pub pinned: bool,
+    /// Datetime the note was archived in ISO format. Archived notes are left
+    /// out of searches unless they're asked for.
+    pub archived_at: Option<DateTime<Utc>>,
+    /// Datetime the note expires in ISO format. Expired notes are left out
+    /// of searches unless they're asked for, and they're eventually deleted.
+    pub expires_at: Option<DateTime<Utc>>,
 }
 
 #[derive(Insertable)]
 #[diesel(table_name = crate::schema::notes)]
 #[diesel(check_for_backend(diesel::pg::Pg))]
 pub struct NewNote {
+    pub user_id: i32,
     pub content: String,
     pub embedding: Vector,
+    pub expires_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Insertable)]
+#[diesel(table_name = crate::schema::note_chunks)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+pub struct NewNoteChunk {
+    pub note_id: i32,
+    pub chunk_index: i32,
+    pub content: String,
+    pub embedding: Vector,
+}
+
+/// Split a long note's content into chunks of up to `chunk_chars`
+/// characters, each starting `overlap_chars` characters before the previous
+/// one ends so text that falls on a boundary is still whole in one of them.
+/// Notes that fit in a single chunk aren't split, so they don't get any.
+#[must_use]
+pub fn chunk_content(content: &str, chunk_chars: usize, overlap_chars: usize) -> Vec<String> {
+    let chars: Vec<char> = content.chars().collect();
+    if chars.len() <= chunk_chars {
+        return vec![];
+    }
+    let step = chunk_chars.saturating_sub(overlap_chars).max(1);
+    let mut chunks = vec![];
+    let mut start = 0;
+    loop {
+        let end = (start + chunk_chars).min(chars.len());
+        chunks.push(chars[start..end].iter().collect());
+        if end == chars.len() {
+            break;
+        }
+        start += step;
+    }
+    chunks
+}
+
+#[derive(Debug, Deserialize, PartialEq, Serialize, ToSchema)]
+pub struct NoteTags {
+    /// Matching note.
+    pub note: Note,
+    /// Matching tags.
+    pub tags: Vec<Tag>,
+}
+
+#[derive(Insertable)]
+#[diesel(table_name = crate::schema::note_tags)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+pub struct NewNoteTag {
+    pub note_id: i32,
+    pub tag_id: i32,
 }
 
 #[derive(Builder, Deserialize, JsonSchema, Serialize, ToSchema)]
 pub struct NewNoteRequest {
     /// Note content to add.
     pub content: String,
+    /// Note tags. Each one is matched to the closest existing tag.
+    pub tags: Option<Vec<String>>,
+    /// ISO formatted datetime the note stops being useful, for notes that
+    /// are only meant to be remembered for a while (e.g., "remember for
+    /// today that I parked on level 3" expires at the end of today). Leave
+    /// empty for notes that should be kept.
+    pub expires_at: Option<DateTime<Utc>>,
+    /// Add the note even if it looks like a duplicate of an existing note.
+    /// Only set this if the user explicitly asks for it.
+    pub allow_duplicate: Option<bool>,
 }
 
 #[derive(Builder, Deserialize, JsonSchema, Serialize, ToSchema)]
+pub struct BulkNoteImportRequest {
+    /// Notes to add, in the order they should be created.
+    pub notes: Vec<NewNoteRequest>,
+}
+
+/// How appended content is separated from a note's existing content.
+#[derive(Clone, Copy, Default, Deserialize, JsonSchema, PartialEq, Serialize, ToSchema)]
+pub enum NoteSeparator {
+    /// Put the appended content on a new line.
+    #[default]
+    Newline,
+    /// Put the appended content after a comma, like adding to a list.
+    Comma,
+}
+
+impl NoteSeparator {
+    /// Append content to a note's existing content. Trailing whitespace is
+    /// dropped from the existing content so separators don't pile up, and
+    /// empty notes just get the appended content.
+    #[must_use]
+    pub fn join(self, content: &str, append_content: &str) -> String {
+        let content = content.trim_end();
+        let append_content = append_content.trim();
+        if content.is_empty() {
+            return append_content.to_string();
+        }
+        let separator = match self {
+            Self::Newline => "\n",
+            Self::Comma => ", ",
+        };
+        format!("{content}{separator}{append_content}")
+    }
+}
+
+#[derive(Builder, Deserialize, JsonSchema, Serialize, ToSchema)]
+pub struct AppendNoteRequest {
+    /// Append to a note using its database-generated ID rather than
+    /// searching for it.
+    pub id: Option<i32>,
+    /// Content to add to the end of the note.
+    pub append_content: String,
+    /// How to separate the appended content from the note's existing
+    /// content. Defaults to a new line. Use a comma when adding items to a
+    /// comma-separated list (e.g., "eggs, milk").
+    pub separator: Option<NoteSeparator>,
+    /// User query string to compare embeddings against. Basically,
+    /// if the user is asking something like "add olive oil to my groceries
+    /// note", then the query string should be something like "groceries".
+    pub query: Option<String>,
+    /// Whether to match the query string more closely using a reranking -based
+    /// approach. `true` is useful for cases where the user is looking to match
+    /// to specific words or phrases, whereas `false` is useful for more broad
+    /// matching.
+    pub use_reranking_filter: Option<bool>,
+    /// Filter on notes created after this ISO formatted datetime.
+    pub created_from: Option<DateTime<Utc>>,
+    /// Filter on notes created before this ISO formatted datetime.
+    pub created_to: Option<DateTime<Utc>>,
+    /// How to order results for retrieved notes.
+    pub order_by: Option<utils::OrderBy>,
+}
+
+#[derive(Builder, Clone, Deserialize, JsonSchema, Serialize, ToSchema)]
 pub struct NoteSearchParams {
+    /// Select notes using their database-generated IDs rather than searching
+    /// for them.
+    pub ids: Option<Vec<i32>>,
+    /// User query string to compare embeddings against. Basically,
+    /// if the user is asking something like "what color is my jacket?",
+    /// then the query string should be something like "jacket color" or
+    /// the user's original question. This can be left empty to ignore
+    /// similarity search in cases where the user wants to filter by
+    /// other means or get all items.
+    pub query: Option<String>,
+    /// Whether to match the query string more closely using a reranking -based
+    /// approach. `true` is useful for cases where the user is looking to match
+    /// to specific words or phrases, whereas `false` is useful for more broad
+    /// matching.
+    pub use_reranking_filter: Option<bool>,
+    /// Query string for notes to leave out of the results. It's compared
+    /// against notes like the query string, and notes that closely match it
+    /// are removed even if they match every other filter or are selected by
+    /// their IDs. Use this when the user asks for notes that aren't about
+    /// something (e.g., "notes not about work" should have an exclude query of
+    /// "work"). Notes are excluded before the limit and offset are applied, so
+    /// excluded notes don't take up room in the results.
+    pub exclude_query: Option<String>,
+    /// Leave notes with these database-generated IDs out of the results, even
+    /// if they match every other filter or are selected by their IDs.
+    pub exclude_ids: Option<Vec<i32>>,
+    /// Filter on notes created after this ISO formatted datetime.
+    pub created_from: Option<DateTime<Utc>>,
+    /// Filter on notes created before this ISO formatted datetime.
+    pub created_to: Option<DateTime<Utc>>,
+    /// How to order results for retrieved notes.
+    pub order_by: Option<utils::OrderBy>,
+    /// Note tags to search with.
+    pub tags: Option<Vec<String>>,
+    /// Whether notes need any or all of the tags to match. Defaults to any.
+    pub tags_match: Option<TagMatch>,
+    /// Limit the max number of notes to return from the search.
+    pub limit: Option<i64>,
+    /// Skip this many matching notes before returning results. Use with
+    /// the limit to page through many notes.
+    pub offset: Option<i64>,
+    /// Only count matching notes instead of returning them. Useful for
+    /// questions like "how many notes are there".
+    pub count_only: Option<bool>,
+    /// Whether to also search archived notes. Archived notes are left out
+    /// unless the user asks for them (e.g., "including archived notes" or
+    /// "what's in my archive").
+    pub include_archived: Option<bool>,
+    /// Whether to also search expired notes. Expired notes are left out
+    /// unless the user asks for them (e.g., "including expired notes" or
+    /// "where did I park yesterday").
+    pub include_expired: Option<bool>,
+}
+
+#[derive(Builder, Deserialize, JsonSchema, Serialize, ToSchema)]
+pub struct PinNotesRequest {
+    /// Parameters for finding the notes to pin or unpin.
+    #[serde(flatten)]
+    pub params: NoteSearchParams,
+    /// `true` to pin the notes so they're listed first, or `false` to unpin
+    /// them.
+    pub pinned: bool,
+}
+
+#[derive(Builder, Deserialize, JsonSchema, Serialize, ToSchema)]
+pub struct ArchiveNotesRequest {
+    /// Parameters for finding the notes to archive or unarchive. Archived
+    /// notes are always searched when unarchiving.
+    #[serde(flatten)]
+    pub params: NoteSearchParams,
+    /// `true` to archive the notes, or `false` to bring them back out of the
+    /// archive.
+    pub archived: bool,
+}
+
+impl DeleteFilters for NoteSearchParams {
+    fn narrows(&self) -> bool {
+        self.ids.is_some()
+            || self.query.is_some()
+            || self.created_from.is_some()
+            || self.created_to.is_some()
+            || self.tags.is_some()
+            || self.limit.is_some()
+    }
+}
+
+#[derive(Builder, Deserialize, JsonSchema, Serialize, ToSchema)]
+pub struct NoteTagSearchParams {
+    /// Select a note using its database-generated ID rather than searching
+    /// for it first.
+    pub note_id: Option<i32>,
+    /// User query string to compare embeddings against. Basically,
+    /// if the user is asking something like "what color is my jacket?",
+    /// then the query string should be something like "jacket color" or
+    /// the user's original question.
+    /// This can be left empty or null to ignore similarity search
+    /// in cases where the user wants to filter by other params
+    /// (e.g., get items by date or get all items).
+    pub note_query: Option<String>,
+    /// Whether to match the query string more closely using a reranking -based
+    /// approach. `true` is useful for cases where the user is looking to match
+    /// to a specific phrase, name, or words.
+    pub note_use_reranking_filter: Option<bool>,
+    /// Filter on notes created after this ISO formatted datetime.
+    pub note_created_from: Option<DateTime<Utc>>,
+    /// Filter on notes created before this ISO formatted datetime.
+    pub note_created_to: Option<DateTime<Utc>>,
+    /// How to order results for retrieved notes.
+    pub note_order_by: Option<utils::OrderBy>,
+    /// Search tags using their database-generated IDs rather than
+    /// searching for them first.
+    pub tag_ids: Option<Vec<i32>>,
+    /// User query string to compare embeddings against. Basically,
+    /// if the user is asking something like "what color is my jacket?",
+    /// then the query string should be something like "jacket color" or
+    /// the user's original question.
+    /// This can be left empty or null to ignore similarity search
+    /// in cases where the user wants to filter by other params
+    /// (e.g., get items by date or get all items).
+    pub tag_query: Option<String>,
+    /// Whether to match the query string more closely using a reranking -based
+    /// approach. `true` is useful for cases where the user is looking to match
+    /// to a specific phrase, name, or words.
+    pub tag_use_reranking_filter: Option<bool>,
+    /// Whether to match the query string more closely, character-for-character.
+    pub tag_use_edit_distance_filter: Option<bool>,
+    /// Limit the max number of tags to return from the search.
+    pub tag_limit: Option<i64>,
+}
+
+#[derive(Builder, Deserialize, JsonSchema, Serialize, ToSchema)]
+pub struct NewNoteTagsRequest {
     /// Select notes using their database-generated IDs rather than searching
     /// for them.
     pub ids: Option<Vec<i32>>,
@@ -57,6 +330,40 @@ pub struct NoteSearchParams {
     pub created_to: Option<DateTime<Utc>>,
     /// How to order results for retrieved notes.
     pub order_by: Option<utils::OrderBy>,
+    /// Note tags to add.
+    pub tags: Vec<String>,
     /// Limit the max number of notes to return from the search.
     pub limit: Option<i64>,
 }
+
+#[cfg(test)]
+mod tests {
+    use super::{NoteSeparator, chunk_content};
+
+    #[test]
+    fn joining_appended_content() {
+        assert_eq!(
+            NoteSeparator::Newline.join("Groceries:\neggs", "olive oil"),
+            "Groceries:\neggs\nolive oil"
+        );
+        assert_eq!(
+            NoteSeparator::Comma.join("eggs, milk\n", " olive oil "),
+            "eggs, milk, olive oil"
+        );
+        assert_eq!(
+            NoteSeparator::default().join("  ", "olive oil"),
+            "olive oil"
+        );
+    }
+
+    #[test]
+    fn chunking_long_content() {
+        assert!(chunk_content("short note", 10, 2).is_empty());
+        assert_eq!(
+            chunk_content("abcdefghijkl", 5, 2),
+            vec!["abcde", "defgh", "ghijk", "jkl"]
+        );
+        // Chunks are split by characters rather than bytes.
+        assert_eq!(chunk_content("ééééé", 3, 1), vec!["ééé", "ééé"]);
+    }
+}
@@ -2,6 +2,7 @@ use diesel::{Insertable, Queryable, Selectable};
 use pgvector::Vector;
 use serde::Serialize;
 use serde_json::Value;
+use toi::Capability;
 
 #[derive(Insertable, Queryable, Selectable, Serialize)]
 #[diesel(table_name = crate::schema::openapi)]
@@ -14,6 +15,27 @@ pub struct OpenApiPathItem {
     pub body: Option<Value>,
 }
 
+impl OpenApiPathItem {
+    /// Compact description of the endpoint that's summarized by the first
+    /// line of its description.
+    #[must_use]
+    pub fn to_capability(&self) -> Capability {
+        let summary = self
+            .description
+            .lines()
+            .find(|line| !line.trim().is_empty())
+            .unwrap_or_default()
+            .to_string();
+        Capability {
+            path: self.path.clone(),
+            method: self.method.clone(),
+            summary,
+            has_params: self.params.is_some(),
+            has_body: self.body.is_some(),
+        }
+    }
+}
+
 #[derive(Queryable, Selectable, Serialize)]
 #[diesel(table_name = crate::schema::searchable_openapi)]
 #[diesel(check_for_backend(diesel::pg::Pg))]
@@ -0,0 +1,45 @@
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+#[derive(Debug, Deserialize, PartialEq, Serialize, ToSchema)]
+pub struct Page<T> {
+    /// Items within this page of results.
+    pub items: Vec<T>,
+    /// Total number of items matching the search filters, regardless of
+    /// the offset and limit. Items within a page may be further filtered
+    /// using reranking, so this total is an upper bound.
+    pub total: i64,
+    /// Number of matching items skipped before this page.
+    pub offset: Option<i64>,
+    /// Max number of items within this page.
+    pub limit: Option<i64>,
+}
+
+impl<T> Page<T> {
+    /// Page without any items for when only the number of matching items is
+    /// needed. The total is exact in this case.
+    #[must_use]
+    pub fn count(total: i64) -> Self {
+        Self {
+            items: vec![],
+            total,
+            offset: None,
+            limit: None,
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, PartialEq, Serialize, ToSchema)]
+pub struct Count {
+    /// Number of items matching the search filters.
+    pub count: i64,
+}
+
+/// Search results, or only the number of results if that's all that was
+/// asked for.
+#[derive(Debug, Deserialize, PartialEq, Serialize, ToSchema)]
+#[serde(untagged)]
+pub enum SearchResponse<T> {
+    Page(Page<T>),
+    Count(Count),
+}
@@ -0,0 +1,30 @@
+use chrono::{DateTime, Utc};
+use diesel::{Insertable, Queryable, Selectable};
+use serde_json::Value;
+
+/// Destructive request generated by the assistant that's waiting for the
+/// user to confirm it.
+#[derive(Debug, Queryable, Selectable)]
+#[diesel(table_name = crate::schema::pending_actions)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+pub struct PendingAction {
+    pub id: i32,
+    pub conversation_id: Option<i32>,
+    /// Hash of the user message the request was generated for.
+    pub message_hash: String,
+    pub request: Value,
+    /// Description of the API the request is for.
+    pub description: String,
+    pub created_at: DateTime<Utc>,
+}
+
+#[derive(Insertable)]
+#[diesel(table_name = crate::schema::pending_actions)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+pub struct NewPendingAction {
+    pub user_id: i32,
+    pub conversation_id: Option<i32>,
+    pub message_hash: String,
+    pub request: Value,
+    pub description: String,
+}
@@ -9,7 +9,7 @@ use utoipa::ToSchema;
 
 use crate::utils;
 
-#[derive(Debug, Deserialize, PartialEq, Queryable, Selectable, Serialize, ToSchema)]
+#[derive(Clone, Debug, Deserialize, PartialEq, Queryable, Selectable, Serialize, ToSchema)]
 #[diesel(table_name = crate::schema::places)]
 #[diesel(check_for_backend(diesel::pg::Pg))]
 pub struct Place {
@@ -25,6 +25,10 @@ pub struct Place {
     pub phone: Option<String>,
     /// Datetime the place was created in ISO format.
     pub created_at: DateTime<Utc>,
+    /// Latitude geocoded from the place's address.
+    pub latitude: Option<f64>,
+    /// Longitude geocoded from the place's address.
+    pub longitude: Option<f64>,
 }
 
 impl Place {
@@ -47,12 +51,16 @@ impl Place {
 #[derive(AsChangeset, Insertable)]
 #[diesel(table_name = crate::schema::places)]
 #[diesel(check_for_backend(diesel::pg::Pg))]
+#[diesel(treat_none_as_null = true)]
 pub struct NewPlace {
+    pub user_id: i32,
     pub name: String,
     pub description: String,
     pub address: Option<String>,
     pub phone: Option<String>,
     pub embedding: Vector,
+    pub latitude: Option<f64>,
+    pub longitude: Option<f64>,
 }
 
 #[derive(Builder, Deserialize, JsonSchema, Serialize, ToSchema)]
@@ -83,7 +91,7 @@ impl fmt::Display for NewPlaceRequest {
     }
 }
 
-#[derive(Builder, Deserialize, JsonSchema, Serialize, ToSchema)]
+#[derive(Builder, Clone, Deserialize, JsonSchema, Serialize, ToSchema)]
 pub struct PlaceSearchParams {
     /// Select places according to their database-generated IDs rather
     /// than searching for them.
@@ -104,10 +112,19 @@ pub struct PlaceSearchParams {
     pub created_from: Option<DateTime<Utc>>,
     /// Filter on places created before this ISO formatted datetime.
     pub created_to: Option<DateTime<Utc>>,
+    /// Area, address, or landmark to find places near, like "downtown
+    /// Austin". Only places with known coordinates are matched, and they're
+    /// ordered from nearest to farthest unless another order is given.
+    pub near_query: Option<String>,
+    /// Only match places within this many kilometers of the near query.
+    pub radius_km: Option<f64>,
     /// How to order results for retrieved places.
     pub order_by: Option<utils::OrderBy>,
     /// Limit the max number of places to return from the search.
     pub limit: Option<i64>,
+    /// Skip this many matching places before returning results. Use with
+    /// the limit to page through many places.
+    pub offset: Option<i64>,
 }
 
 #[derive(Builder, Clone, Deserialize, JsonSchema, Serialize, ToSchema)]
@@ -0,0 +1,36 @@
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+#[derive(Debug, Deserialize, PartialEq, Serialize, ToSchema)]
+pub struct PoolStatus {
+    /// Number of open database connections, whether they're in use or not.
+    pub connections: u32,
+    /// Number of open database connections that aren't in use.
+    pub idle_connections: u32,
+    /// Number of times a connection was handed out without waiting.
+    pub get_direct: u64,
+    /// Number of times a connection was handed out after waiting for one.
+    pub get_waited: u64,
+    /// Number of times waiting for a connection timed out.
+    pub get_timed_out: u64,
+    /// Total milliseconds spent waiting for connections.
+    pub get_wait_time_ms: u64,
+    /// Number of connections opened since the server started.
+    pub connections_created: u64,
+}
+
+impl From<bb8::State> for PoolStatus {
+    fn from(state: bb8::State) -> Self {
+        let statistics = state.statistics;
+        Self {
+            connections: state.connections,
+            idle_connections: state.idle_connections,
+            get_direct: statistics.get_direct,
+            get_waited: statistics.get_waited,
+            get_timed_out: statistics.get_timed_out,
+            get_wait_time_ms: u64::try_from(statistics.get_wait_time.as_millis())
+                .unwrap_or(u64::MAX),
+            connections_created: statistics.connections_created,
+        }
+    }
+}
@@ -1,3 +1,6 @@
+use bon::Builder;
+use chrono::{DateTime, Utc};
+use chrono_tz::Tz;
 use serde_json::{Value, json};
 use std::fmt;
 use toi::{Message, MessageRole};
@@ -29,43 +32,156 @@ pub trait SystemPrompt: fmt::Display {
 
 impl<T: fmt::Display> SystemPrompt for T {}
 
+/// Append the user's style instructions (if any) after a prompt's rules.
+/// They're placed last and framed as secondary so they can't displace the
+/// rules before them.
+fn write_style_instructions(
+    f: &mut fmt::Formatter<'_>,
+    style_instructions: Option<&str>,
+) -> fmt::Result {
+    match style_instructions {
+        Some(style_instructions) => write!(
+            f,
+            r"
+
+Also follow these style instructions from the user, but ONLY where they don't conflict with the rules above:
+{style_instructions}"
+        ),
+        None => Ok(()),
+    }
+}
+
+/// Append the current datetime in the user's timezone after a prompt's rules
+/// so relative dates like "tomorrow" can be resolved.
+fn write_current_datetime(
+    f: &mut fmt::Formatter<'_>,
+    now: DateTime<Utc>,
+    timezone: Tz,
+) -> fmt::Result {
+    write!(
+        f,
+        r"
+
+{}",
+        current_datetime(now, timezone)
+    )
+}
+
+/// Current datetime in the user's timezone in ISO format, along with its
+/// weekday and the timezone's name.
+fn current_datetime(now: DateTime<Utc>, timezone: Tz) -> String {
+    let now = now.with_timezone(&timezone);
+    format!(
+        "Current datetime: {} ({}); Timezone: {timezone}",
+        now.to_rfc3339_opts(chrono::SecondsFormat::Secs, false),
+        now.format("%A")
+    )
+}
+
+/// Add the current datetime to the descriptions of date and datetime fields
+/// in a JSON schema so the model fills them relative to now in ISO format.
+fn describe_date_fields(schema: &mut Value, current_datetime: &str) {
+    match schema {
+        Value::Object(obj) => {
+            let is_date = obj
+                .get("format")
+                .and_then(Value::as_str)
+                .is_some_and(|format| matches!(format, "date" | "date-time"));
+            if is_date {
+                let description = match obj.get("description").and_then(Value::as_str) {
+                    Some(description) => format!("{description} {current_datetime}"),
+                    None => current_datetime.to_string(),
+                };
+                obj.insert("description".to_string(), description.into());
+            }
+            for value in obj.values_mut() {
+                describe_date_fields(value, current_datetime);
+            }
+        }
+        Value::Array(values) => {
+            for value in values {
+                describe_date_fields(value, current_datetime);
+            }
+        }
+        _ => {}
+    }
+}
+
+/// Prompt for asking the user which of two similar APIs they mean when the
+/// request generated for their command asks instead of making a request.
+#[derive(Builder)]
+pub struct ClarificationPrompt {
+    pub question: String,
+    pub style_instructions: Option<String>,
+}
+
+impl fmt::Display for ClarificationPrompt {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let question = &self.question;
+        write!(
+            f,
+            r"Your job is to ask the user the question below so it's clear what they want while following these rules:
+- NEVER say that anything the user asked for was done
+- Answer as concisely as possible
+- Only use layman's terms
+- NEVER use emojis
+
+**Question**
+{question}"
+        )?;
+        write_style_instructions(f, self.style_instructions.as_deref())
+    }
+}
+
 pub struct CommandPrompt {}
 
 impl fmt::Display for CommandPrompt {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         write!(
             f,
-            r"Your job is to extract the command and target of that command from a user's message using the following examples as guidance:
+            r"Your job is to extract the command and target of that command from a user's message, along with each separate action the user asks for in the order they should be done, using the following examples as guidance:
 
 Example 1:
 User message: `what's the weather like in nyc?`
 Extracted command: `what's the weather`
 Extracted target: `new york city`
+Extracted steps: [`what's the weather in new york city`]
 
 Example 2:
 User message: `what's up?`
 Extracted command: `what's up`
 Extracted target: null
+Extracted steps: []
 
 Example 3:
 User message: `remember to take out the trash tomorrow`
 Extracted command: `remember`
 Extracted target: `take out the trash tomorrow`
+Extracted steps: [`remember to take out the trash tomorrow`]
 
 Example 4:
 User message: `add joe schmoe to my contacts`
 Extracted command: `add a contact`
 Extracted target: `joe schmoe`
+Extracted steps: [`add joe schmoe to my contacts`]
 
 Example 5:
 User message: `'1:15 coffee:water ratio' add that as a note
 Extracted command: `add a note`
 Extracted target: `'1:15 coffee:water ratio'
+Extracted steps: [`add '1:15 coffee:water ratio' as a note`]
 
 Example 6:
 User message: 'hiiiiii'
 Extracted command: null
 Extracted target: null
+Extracted steps: []
+
+Example 7:
+User message: `add bob to my contacts and invite him to friday's dinner`
+Extracted command: `add a contact`
+Extracted target: `bob`
+Extracted steps: [`add bob to my contacts`, `invite bob to friday's dinner`]
 
 Respond concisely in JSON format."
         )
@@ -90,10 +206,196 @@ impl CommandPrompt {
                             "target": {
                                 "type": ["string", "null"],
                                 "description": "Target of the user's command, null if no target found"
+                            },
+                            "steps": {
+                                "type": "array",
+                                "items": {
+                                    "type": "string"
+                                },
+                                "description": "Each separate action the user asks for in the order they should be done, with pronouns replaced by who or what they refer to"
+                            }
+                        },
+                        "additionalProperties": false,
+                        "required": ["command", "target", "steps"]
+                    }
+                }
+            }
+        )
+    }
+}
+
+pub struct ConfirmationPrompt {}
+
+impl fmt::Display for ConfirmationPrompt {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            r"Your job is to decide whether the user's latest message confirms that they want to go ahead with the request the assistant last asked them about while following these rules:
+- Messages like 'yes', 'do it', or 'go ahead' confirm the request
+- Messages that say no, ask for changes, or ask for something else DO NOT confirm the request
+- NEVER assume anything
+
+Respond concisely in JSON format."
+        )
+    }
+}
+
+impl ConfirmationPrompt {
+    #[must_use]
+    pub fn into_response_format(self) -> Value {
+        json!(
+            {
+                "type": "json_schema",
+                "json_schema": {
+                    "name": "confirmation",
+                    "schema": {
+                        "type": "object",
+                        "properties": {
+                            "confirmed": {
+                                "type": "boolean",
+                                "description": "Whether the user confirms the request"
+                            }
+                        },
+                        "additionalProperties": false,
+                        "required": ["confirmed"]
+                    }
+                }
+            }
+        )
+    }
+}
+
+pub struct PendingActionPrompt {
+    pub description: String,
+    pub request: String,
+}
+
+impl fmt::Display for PendingActionPrompt {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let PendingActionPrompt {
+            description,
+            request,
+        } = self;
+        write!(
+            f,
+            r"Your job is to ask the user to confirm an HTTP request that would delete things before it's sent while following these rules:
+- List the items from the user's latest message that would be affected, or say that nothing matched
+- Show the exact HTTP request below
+- Ask the user to reply with something like 'yes, do it' to send the request
+- NEVER say the request was already sent
+- Answer as concisely as possible
+- Only use layman's terms
+- NEVER use emojis
+
+Here's the HTTP request waiting to be sent:
+
+{request}
+
+Here's a description of the API used for the HTTP request as context:
+
+**Description**
+{description}"
+        )
+    }
+}
+
+pub struct RecipeScalePrompt {
+    pub factor: f32,
+}
+
+impl fmt::Display for RecipeScalePrompt {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let factor = self.factor;
+        write!(
+            f,
+            r"Your job is to scale the recipe ingredients the user provides by a factor of {factor} while following these rules:
+- Multiply every ingredient quantity by {factor}
+- Convert to larger or smaller units when it makes quantities easier to measure (e.g., 12 tsp becomes 1/4 cup)
+- Keep ingredients without quantities (e.g., salt to taste) as they are
+- Keep the original order, formatting, and wording of the ingredients
+- NEVER add or remove ingredients
+
+Respond concisely in JSON format."
+        )
+    }
+}
+
+impl RecipeScalePrompt {
+    #[must_use]
+    pub fn into_response_format(self) -> Value {
+        json!(
+            {
+                "type": "json_schema",
+                "json_schema": {
+                    "name": "scaled_ingredients",
+                    "schema": {
+                        "type": "object",
+                        "properties": {
+                            "ingredients": {
+                                "type": "string",
+                                "description": "Recipe ingredients with scaled quantities"
+                            }
+                        },
+                        "additionalProperties": false,
+                        "required": ["ingredients"]
+                    }
+                }
+            }
+        )
+    }
+}
+
+pub struct ShoppingListPrompt;
+
+impl fmt::Display for ShoppingListPrompt {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            r"Your job is to turn the recipe ingredients the user provides into a single shopping list while following these rules:
+- Multiply each recipe's ingredient quantities by the factor given with it
+- Combine ingredients shared between recipes into one item with their total quantity (e.g., 1 cup milk and 2 cups milk become 3 cups milk)
+- Convert to larger or smaller units when it makes quantities easier to buy (e.g., 12 tsp becomes 1/4 cup)
+- Put each item in the store section it's usually found in
+- NEVER add ingredients the recipes don't use
+
+Respond concisely in JSON format."
+        )
+    }
+}
+
+impl ShoppingListPrompt {
+    #[must_use]
+    pub fn into_response_format(self) -> Value {
+        json!(
+            {
+                "type": "json_schema",
+                "json_schema": {
+                    "name": "shopping_list",
+                    "schema": {
+                        "type": "object",
+                        "properties": {
+                            "items": {
+                                "type": "array",
+                                "items": {
+                                    "type": "object",
+                                    "properties": {
+                                        "category": {
+                                            "type": "string",
+                                            "enum": ["produce", "dairy", "meat_and_seafood", "bakery", "pantry", "frozen", "other"],
+                                            "description": "Store section the item is found in"
+                                        },
+                                        "item": {
+                                            "type": "string",
+                                            "description": "Item to buy with its combined quantity"
+                                        }
+                                    },
+                                    "additionalProperties": false,
+                                    "required": ["category", "item"]
+                                }
                             }
                         },
                         "additionalProperties": false,
-                        "required": ["command", "target"]
+                        "required": ["items"]
                     }
                 }
             }
@@ -101,7 +403,12 @@ impl CommandPrompt {
     }
 }
 
-pub struct SimplePrompt {}
+#[derive(Builder)]
+pub struct SimplePrompt {
+    pub now: DateTime<Utc>,
+    pub timezone: Tz,
+    pub style_instructions: Option<String>,
+}
 
 impl fmt::Display for SimplePrompt {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
@@ -115,12 +422,22 @@ impl fmt::Display for SimplePrompt {
 - Only use layman's terms
 - NEVER use emojis
 - NEVER say phrases like 'Let me know if...'"
-        )
+        )?;
+        write_current_datetime(f, self.now, self.timezone)?;
+        write_style_instructions(f, self.style_instructions.as_deref())
     }
 }
 
+#[derive(Builder)]
 pub struct SummaryPrompt {
     pub description: String,
+    pub now: DateTime<Utc>,
+    pub timezone: Tz,
+    pub style_instructions: Option<String>,
+    /// Whether the responses are from the steps of a plan, which are
+    /// followed by a report of how each step went.
+    #[builder(default)]
+    pub plan: bool,
 }
 
 impl fmt::Display for SummaryPrompt {
@@ -136,21 +453,41 @@ impl fmt::Display for SummaryPrompt {
 - Answer as concisely as possible
 - Only use layman's terms
 - NEVER use emojis
-- NEVER say phrases like 'Let me know if...'
+- NEVER say phrases like 'Let me know if...'"
+        )?;
+        if self.plan {
+            write!(
+                f,
+                r"
+- The user asked for several things at once, so use the report of how each step went to say which steps succeeded, which step failed and why, and which steps were skipped
+- NEVER say a step that failed or was skipped was done"
+            )?;
+        }
+        write!(
+            f,
+            r"
 
 Here's a description of the API used for the HTTP request/response as context:
 
 **Description**
 {description}"
-        )
+        )?;
+        write_current_datetime(f, self.now, self.timezone)?;
+        write_style_instructions(f, self.style_instructions.as_deref())
     }
 }
 
+#[derive(Builder)]
 pub struct HttpRequestPrompt {
     pub path: String,
     pub method: String,
     pub params: Option<Value>,
     pub body: Option<Value>,
+    /// Description of another API that scored too close to this one to
+    /// tell which the user means.
+    pub alternative: Option<String>,
+    pub now: DateTime<Utc>,
+    pub timezone: Tz,
 }
 
 impl HttpRequestPrompt {
@@ -213,6 +550,23 @@ impl HttpRequestPrompt {
             response_format["json_schema"]["schema"]["definitions"] = definitions;
         }
 
+        // A question can be asked instead if it's unclear whether the user
+        // means the other API.
+        if self.alternative.is_some() {
+            response_format["json_schema"]["schema"]["properties"]["clarification"] = json!(
+                {
+                    "type": ["string", "null"],
+                    "description": "A question asking the user which of the two APIs they mean, only if it's unclear"
+                }
+            );
+        }
+
+        // Date fields are filled relative to now.
+        describe_date_fields(
+            &mut response_format["json_schema"]["schema"],
+            &current_datetime(self.now, self.timezone),
+        );
+
         response_format
     }
 }
@@ -224,7 +578,152 @@ impl fmt::Display for HttpRequestPrompt {
             r"Your job is to construct an HTTP request while following these rules:
 - Always replace all pronouns/abbreviations with proper nouns
 - Only fill parameters that you explicitly know from the chat context
+- Resolve relative dates and times (e.g., tomorrow, next week) using the current datetime below
 - Respond concisely in JSON format"
-        )
+        )?;
+        if let Some(alternative) = &self.alternative {
+            write!(
+                f,
+                r"
+- If it's unclear whether the user means this API or the other API described below, fill the clarification field with a question asking which one they mean
+
+Here's a description of the other API as context:
+
+**Description**
+{alternative}"
+            )?;
+        }
+        write_current_datetime(f, self.now, self.timezone)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn now() -> DateTime<Utc> {
+        "2025-06-27T16:30:00Z"
+            .parse()
+            .expect("datetime should be valid")
+    }
+
+    #[test]
+    fn appending_style_instructions() {
+        let style_instructions = "Talk like a pirate.".to_string();
+        let prompt = SimplePrompt::builder()
+            .now(now())
+            .timezone(Tz::UTC)
+            .style_instructions(style_instructions.clone())
+            .build();
+        let messages = prompt.to_messages(&[]);
+        assert!(messages[0].content.ends_with(&style_instructions));
+        let prompt = SummaryPrompt::builder()
+            .description("Add a note.".to_string())
+            .now(now())
+            .timezone(Tz::UTC)
+            .style_instructions(style_instructions.clone())
+            .build();
+        let messages = prompt.to_messages(&[]);
+        assert!(messages[0].content.ends_with(&style_instructions));
+    }
+
+    #[test]
+    fn omitting_missing_style_instructions() {
+        let prompt = SimplePrompt::builder().now(now()).timezone(Tz::UTC).build();
+        assert!(!prompt.to_string().contains("style instructions"));
+    }
+
+    #[test]
+    fn rendering_current_datetime() {
+        let expected =
+            "Current datetime: 2025-06-27T12:30:00-04:00 (Friday); Timezone: America/New_York";
+        let prompt = SimplePrompt::builder()
+            .now(now())
+            .timezone(Tz::America__New_York)
+            .build();
+        assert!(prompt.to_string().ends_with(expected));
+        let prompt = SummaryPrompt::builder()
+            .description("Add a note.".to_string())
+            .now(now())
+            .timezone(Tz::America__New_York)
+            .build();
+        assert!(prompt.to_string().ends_with(expected));
+        let prompt = HttpRequestPrompt::builder()
+            .path("/todos".to_string())
+            .method("POST".to_string())
+            .now(now())
+            .timezone(Tz::America__New_York)
+            .build();
+        assert!(prompt.to_string().ends_with(expected));
+    }
+
+    #[test]
+    fn describing_date_fields() {
+        let body = json!({
+            "$schema": "http://json-schema.org/draft-07/schema#",
+            "type": "object",
+            "properties": {
+                "item": {"type": "string", "description": "Todo item"},
+                "due_at": {
+                    "type": ["string", "null"],
+                    "format": "date-time",
+                    "description": "Due datetime in ISO format."
+                }
+            },
+            "definitions": {
+                "Window": {
+                    "type": "object",
+                    "properties": {"day": {"type": "string", "format": "date"}}
+                }
+            }
+        });
+        let prompt = HttpRequestPrompt::builder()
+            .path("/todos".to_string())
+            .method("POST".to_string())
+            .body(body)
+            .now(now())
+            .timezone(Tz::UTC)
+            .build();
+        let response_format = prompt.into_response_format();
+        let schema = &response_format["json_schema"]["schema"];
+        assert_eq!(
+            schema["properties"]["body"]["properties"]["due_at"]["description"],
+            "Due datetime in ISO format. Current datetime: 2025-06-27T16:30:00+00:00 (Friday); Timezone: UTC"
+        );
+        assert_eq!(
+            schema["definitions"]["Window"]["properties"]["day"]["description"],
+            "Current datetime: 2025-06-27T16:30:00+00:00 (Friday); Timezone: UTC"
+        );
+        assert_eq!(
+            schema["properties"]["body"]["properties"]["item"]["description"],
+            "Todo item"
+        );
+    }
+
+    #[test]
+    fn offering_clarification_for_alternative() {
+        let prompt = HttpRequestPrompt::builder()
+            .path("/todos".to_string())
+            .method("POST".to_string())
+            .now(now())
+            .timezone(Tz::UTC)
+            .build();
+        assert!(!prompt.to_string().contains("clarification"));
+        let response_format = prompt.into_response_format();
+        let schema = &response_format["json_schema"]["schema"];
+        assert!(schema["properties"].get("clarification").is_none());
+
+        let prompt = HttpRequestPrompt::builder()
+            .path("/todos".to_string())
+            .method("POST".to_string())
+            .alternative("Add a note.".to_string())
+            .now(now())
+            .timezone(Tz::UTC)
+            .build();
+        assert!(prompt.to_string().contains("**Description**\nAdd a note."));
+        let response_format = prompt.into_response_format();
+        let schema = &response_format["json_schema"]["schema"];
+        assert!(schema["properties"].get("clarification").is_some());
+        assert_eq!(schema["required"], json!(["path", "method"]));
     }
 }
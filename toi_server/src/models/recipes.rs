@@ -6,7 +6,14 @@ use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 use utoipa::ToSchema;
 
-use crate::{models::tags::Tag, utils};
+use crate::{
+    models::{
+        deletion::DeleteFilters,
+        tags::{Tag, TagMatch},
+        todos::Todo,
+    },
+    utils,
+};
 
 #[derive(Debug, Deserialize, PartialEq, Queryable, Selectable, Serialize, ToSchema)]
 #[diesel(table_name = crate::schema::recipes)]
@@ -36,6 +43,45 @@ pub struct RecipePreview {
     pub created_at: DateTime<Utc>,
 }
 
+#[derive(Debug, Deserialize, PartialEq, Serialize, ToSchema)]
+pub struct RecipeWithTags {
+    /// Matching recipe.
+    #[serde(flatten)]
+    pub recipe: Recipe,
+    /// The recipe's tags. Only included if they're asked for.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tags: Option<Vec<Tag>>,
+}
+
+#[derive(Debug, Deserialize, PartialEq, Serialize, ToSchema)]
+pub struct RecipePreviewWithTags {
+    /// Matching recipe preview.
+    #[serde(flatten)]
+    pub recipe_preview: RecipePreview,
+    /// The recipe's tags. Only included if they're asked for.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tags: Option<Vec<Tag>>,
+}
+
+#[derive(Debug, Deserialize, PartialEq, Serialize, ToSchema)]
+pub struct RecipePreviewDetailed {
+    /// Matching recipe preview.
+    #[serde(flatten)]
+    pub recipe_preview: RecipePreview,
+    /// Names of the recipe's tags in alphabetical order.
+    pub tag_names: Vec<String>,
+    /// Number of non-empty lines in the recipe's ingredients.
+    pub ingredient_count: i64,
+}
+
+/// Recipe preview in whichever shape was asked for.
+#[derive(Debug, Deserialize, PartialEq, Serialize, ToSchema)]
+#[serde(untagged)]
+pub enum RecipePreviewResult {
+    Detailed(RecipePreviewDetailed),
+    Lean(RecipePreviewWithTags),
+}
+
 #[derive(Debug, Deserialize, PartialEq, Serialize, ToSchema)]
 pub struct RecipeTags {
     /// Matching recipe preview.
@@ -48,6 +94,7 @@ pub struct RecipeTags {
 #[diesel(table_name = crate::schema::recipes)]
 #[diesel(check_for_backend(diesel::pg::Pg))]
 pub struct NewRecipe {
+    pub user_id: i32,
     pub description: String,
     pub ingredients: String,
     pub instructions: String,
@@ -74,7 +121,7 @@ pub struct NewRecipeRequest {
     pub tags: Vec<String>,
 }
 
-#[derive(Builder, Deserialize, JsonSchema, Serialize, ToSchema)]
+#[derive(Builder, Clone, Deserialize, JsonSchema, Serialize, ToSchema)]
 pub struct RecipeSearchParams {
     /// Update a recipe using their database-generated ID rather than
     /// searching for them.
@@ -91,6 +138,13 @@ pub struct RecipeSearchParams {
     /// to specific words or phrases, whereas `false` is useful for more broad
     /// matching.
     pub use_reranking_filter: Option<bool>,
+    /// Ingredients to look for in recipes' ingredient lists, separated by
+    /// spaces. Every word must appear in a recipe's ingredients for it to
+    /// match. Use this when the user asks about recipes that use or contain
+    /// a specific ingredient (e.g., "what recipes use miso?" should have an
+    /// ingredients query of "miso"). This can be combined with the query
+    /// string, in which case recipes must match both.
+    pub ingredients_query: Option<String>,
     /// Filter on recipes created after this ISO formatted datetime.
     pub created_from: Option<DateTime<Utc>>,
     /// Filter on recipes created before this ISO formatted datetime.
@@ -99,8 +153,157 @@ pub struct RecipeSearchParams {
     pub order_by: Option<utils::OrderBy>,
     /// Recipe tags to search with.
     pub tags: Option<Vec<String>>,
+    /// Whether recipes need any or all of the tags to match. Defaults to
+    /// any.
+    pub tags_match: Option<TagMatch>,
     /// Limit the max number of recipes to return from the search.
     pub limit: Option<i64>,
+    /// Skip this many matching recipes before returning results. Use with
+    /// the limit to page through many recipes.
+    pub offset: Option<i64>,
+    /// Only count matching recipes instead of returning them. Useful for
+    /// questions like "how many recipes are there".
+    pub count_only: Option<bool>,
+    /// Include each recipe's tags with the recipe. Useful for questions like
+    /// "what recipes do I have and how are they tagged".
+    pub include_tags: Option<bool>,
+    /// Include each recipe preview's tag names and how many ingredients it
+    /// has instead of its full tags. Useful for listing many recipes so
+    /// similar ones can be told apart. Only used when getting recipe
+    /// previews.
+    pub detailed: Option<bool>,
+}
+
+impl DeleteFilters for RecipeSearchParams {
+    fn narrows(&self) -> bool {
+        self.ids.is_some()
+            || self.query.is_some()
+            || self.ingredients_query.is_some()
+            || self.created_from.is_some()
+            || self.created_to.is_some()
+            || self.tags.is_some()
+            || self.limit.is_some()
+    }
+}
+
+#[derive(Builder, Deserialize, JsonSchema, Serialize, ToSchema)]
+pub struct RecipeScaleRequest {
+    /// Select a recipe using its database-generated ID rather than
+    /// searching for it first.
+    pub recipe_id: Option<i32>,
+    /// User query string to compare embeddings against. Basically,
+    /// if the user is asking something like "double the lasagna recipe",
+    /// then the query string should be something like "lasagna".
+    pub recipe_query: Option<String>,
+    /// Whether to match the query string more closely using a reranking -based
+    /// approach. `true` is useful for cases where the user is looking to match
+    /// to a specific phrase, name, or words.
+    pub recipe_use_reranking_filter: Option<bool>,
+    /// Filter on recipes created after this ISO formatted datetime.
+    pub recipe_created_from: Option<DateTime<Utc>>,
+    /// Filter on recipes created before this ISO formatted datetime.
+    pub recipe_created_to: Option<DateTime<Utc>>,
+    /// How to order results for retrieved recipes.
+    pub recipe_order_by: Option<utils::OrderBy>,
+    /// How much to multiply ingredient quantities by (e.g., 2 to double the
+    /// recipe, or 0.5 to halve it). To scale for a number of people, divide
+    /// the number of people by how many the recipe serves.
+    pub factor: f32,
+    /// Whether to replace the stored recipe's ingredients with the scaled
+    /// ingredients. Only set this if the user explicitly asks to save or
+    /// update the recipe.
+    pub save: Option<bool>,
+}
+
+#[derive(Debug, Deserialize, PartialEq, Serialize, ToSchema)]
+pub struct ScaledRecipe {
+    /// Matching recipe, with scaled ingredients if they were saved.
+    pub recipe: Recipe,
+    /// Factor ingredient quantities were multiplied by.
+    pub factor: f32,
+    /// Recipe ingredients before scaling.
+    pub original_ingredients: String,
+    /// Recipe ingredients after scaling.
+    pub scaled_ingredients: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct GeneratedScaledIngredients {
+    pub ingredients: String,
+}
+
+#[derive(Builder, Clone, Deserialize, JsonSchema, Serialize, ToSchema)]
+pub struct RecipeServings {
+    /// Database-generated ID of a matching recipe.
+    pub recipe_id: i32,
+    /// How much to multiply the recipe's ingredient quantities by (e.g., 2
+    /// to make the recipe twice, or 0.5 to make half of it).
+    pub factor: f32,
+}
+
+#[derive(Builder, Deserialize, JsonSchema, Serialize, ToSchema)]
+pub struct ShoppingListRequest {
+    /// Parameters for finding the recipes to shop for.
+    #[serde(flatten)]
+    pub params: RecipeSearchParams,
+    /// How much to make of specific recipes. Recipes that aren't listed
+    /// are made as written.
+    pub servings: Option<Vec<RecipeServings>>,
+    /// Whether to add each item on the shopping list as a todo. Only set
+    /// this if the user explicitly asks to add the list to their todos.
+    pub create_todos: Option<bool>,
+    /// When the created todos are due in ISO format.
+    pub due_at: Option<DateTime<Utc>>,
+}
+
+#[derive(
+    Clone, Copy, Debug, Deserialize, Eq, JsonSchema, Ord, PartialEq, PartialOrd, Serialize, ToSchema,
+)]
+#[serde(rename_all = "snake_case")]
+pub enum ShoppingCategory {
+    /// Fruits, vegetables, and fresh herbs.
+    Produce,
+    /// Milk, cheese, eggs, and butter.
+    Dairy,
+    /// Meat, poultry, and seafood.
+    MeatAndSeafood,
+    /// Bread and baked goods.
+    Bakery,
+    /// Dry goods, canned goods, spices, oils, and condiments.
+    Pantry,
+    /// Frozen foods.
+    Frozen,
+    /// Anything else.
+    Other,
+}
+
+#[derive(Debug, Deserialize, PartialEq, Serialize, ToSchema)]
+pub struct ShoppingListCategory {
+    /// Store section the items are found in.
+    pub category: ShoppingCategory,
+    /// Items to buy, with their combined quantities.
+    pub items: Vec<String>,
+}
+
+#[derive(Debug, Deserialize, PartialEq, Serialize, ToSchema)]
+pub struct ShoppingList {
+    /// Recipes the shopping list is for.
+    pub recipes: Vec<RecipePreview>,
+    /// Items to buy grouped by store section.
+    pub categories: Vec<ShoppingListCategory>,
+    /// Todos created for each item. Empty unless todos were requested.
+    pub todos: Vec<Todo>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct GeneratedShoppingItem {
+    pub category: ShoppingCategory,
+    pub item: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct GeneratedShoppingList {
+    pub items: Vec<GeneratedShoppingItem>,
 }
 
 #[derive(Builder, Deserialize, JsonSchema, Serialize, ToSchema)]
@@ -0,0 +1,31 @@
+use bon::Builder;
+use chrono::{DateTime, Utc};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use utoipa::{IntoParams, ToSchema};
+
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq, Serialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum ReminderKind {
+    Todo,
+    Event,
+}
+
+#[derive(Builder, Deserialize, IntoParams, JsonSchema, Serialize)]
+pub struct ReminderParams {
+    /// Only include todos due and events starting within this many hours
+    /// from now. Defaults to 24 hours.
+    pub within_hours: Option<u32>,
+}
+
+#[derive(Debug, Deserialize, PartialEq, Serialize, ToSchema)]
+pub struct Reminder {
+    /// Whether the reminder is for a todo or an event.
+    pub kind: ReminderKind,
+    /// Database-generated ID of the todo or event.
+    pub id: i32,
+    /// Todo item or event description.
+    pub description: String,
+    /// Datetime the todo is due or the event starts in ISO format.
+    pub occurs_at: DateTime<Utc>,
+}
@@ -0,0 +1,181 @@
+use serde::{Deserialize, Serialize};
+use toi::GenerationRequest;
+
+// Highest temperature OpenAI-compatible generation APIs accept.
+const MAX_TEMPERATURE: f32 = 2.0;
+
+/// Sampling parameters for a generation. Parameters that aren't given are
+/// left up to the generation API.
+#[derive(Clone, Copy, Debug, Default, Deserialize, PartialEq, Serialize)]
+pub struct Sampling {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub temperature: Option<f32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub top_p: Option<f32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_tokens: Option<u32>,
+}
+
+impl Sampling {
+    /// Sampling parameters requested by a client.
+    #[must_use]
+    pub fn from_request(request: &GenerationRequest) -> Self {
+        Self {
+            temperature: request.temperature,
+            top_p: request.top_p,
+            max_tokens: request.max_tokens,
+        }
+    }
+
+    /// Fill in parameters that aren't given with defaults.
+    #[must_use]
+    pub fn or(self, defaults: Self) -> Self {
+        Self {
+            temperature: self.temperature.or(defaults.temperature),
+            top_p: self.top_p.or(defaults.top_p),
+            max_tokens: self.max_tokens.or(defaults.max_tokens),
+        }
+    }
+
+    /// Set a generation request's sampling parameters.
+    #[must_use]
+    pub fn apply(self, mut request: GenerationRequest) -> GenerationRequest {
+        request.temperature = self.temperature;
+        request.top_p = self.top_p;
+        request.max_tokens = self.max_tokens;
+        request
+    }
+
+    /// Check that parameters are within the ranges generation APIs accept,
+    /// returning which one isn't otherwise.
+    pub fn validate(&self) -> Result<(), String> {
+        let invalid_temperature = self
+            .temperature
+            .filter(|temperature| !(0.0..=MAX_TEMPERATURE).contains(temperature));
+        if let Some(temperature) = invalid_temperature {
+            return Err(format!(
+                "temperature must be from 0 to {MAX_TEMPERATURE}, but it's {temperature}"
+            ));
+        }
+        let invalid_top_p = self
+            .top_p
+            .filter(|top_p| top_p.is_nan() || *top_p <= 0.0 || *top_p > 1.0);
+        if let Some(top_p) = invalid_top_p {
+            return Err(format!(
+                "top_p must be greater than 0 and at most 1, but it's {top_p}"
+            ));
+        }
+        if self.max_tokens == Some(0) {
+            return Err("max_tokens must be at least 1".to_string());
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+    use toi::{Message, MessageRole};
+
+    use super::*;
+    use crate::models::client::StreamingGenerationRequest;
+
+    fn messages() -> Vec<Message> {
+        vec![Message {
+            role: MessageRole::User,
+            content: "Hi".to_string(),
+        }]
+    }
+
+    #[test]
+    fn serializing_sampling() -> Result<(), serde_json::Error> {
+        // Parameters that aren't given aren't sent at all.
+        let request = GenerationRequest::builder().messages(messages()).build();
+        let value = serde_json::to_value(&request)?;
+        assert!(value.get("temperature").is_none());
+        assert!(value.get("max_tokens").is_none());
+
+        // Given parameters are sent as-is.
+        let sampling = Sampling {
+            temperature: Some(0.5),
+            top_p: None,
+            max_tokens: Some(256),
+        };
+        let value = serde_json::to_value(sampling.apply(request))?;
+        assert_eq!(value["temperature"], json!(0.5));
+        assert!(value.get("top_p").is_none());
+        assert_eq!(value["max_tokens"], json!(256));
+        let request = StreamingGenerationRequest::new(messages()).with_sampling(sampling);
+        let value = serde_json::to_value(request)?;
+        assert_eq!(value["temperature"], json!(0.5));
+        assert!(value.get("top_p").is_none());
+        assert_eq!(value["max_tokens"], json!(256));
+        assert_eq!(value["stream"], json!(true));
+        Ok(())
+    }
+
+    #[test]
+    fn defaulting_sampling() {
+        let requested = Sampling {
+            temperature: Some(1.0),
+            ..Sampling::default()
+        };
+        let defaults = Sampling {
+            temperature: Some(0.7),
+            top_p: Some(0.9),
+            max_tokens: None,
+        };
+        assert_eq!(
+            requested.or(defaults),
+            Sampling {
+                temperature: Some(1.0),
+                top_p: Some(0.9),
+                max_tokens: None,
+            }
+        );
+    }
+
+    #[test]
+    fn validating_sampling() {
+        let valid = [
+            Sampling::default(),
+            Sampling {
+                temperature: Some(0.0),
+                top_p: Some(1.0),
+                max_tokens: Some(1),
+            },
+            Sampling {
+                temperature: Some(2.0),
+                ..Sampling::default()
+            },
+        ];
+        for sampling in valid {
+            assert!(sampling.validate().is_ok());
+        }
+        let invalid = [
+            Sampling {
+                temperature: Some(2.5),
+                ..Sampling::default()
+            },
+            Sampling {
+                temperature: Some(-0.1),
+                ..Sampling::default()
+            },
+            Sampling {
+                top_p: Some(0.0),
+                ..Sampling::default()
+            },
+            Sampling {
+                top_p: Some(1.5),
+                ..Sampling::default()
+            },
+            Sampling {
+                max_tokens: Some(0),
+                ..Sampling::default()
+            },
+        ];
+        for sampling in invalid {
+            assert!(sampling.validate().is_err());
+        }
+    }
+}
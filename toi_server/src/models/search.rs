@@ -0,0 +1,55 @@
+use bon::Builder;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+/// Kinds of items that can be searched all at once.
+#[derive(
+    Clone, Copy, Debug, Deserialize, Eq, JsonSchema, Ord, PartialEq, PartialOrd, Serialize, ToSchema,
+)]
+pub enum SearchDomain {
+    Contacts,
+    Events,
+    Notes,
+    Places,
+    Recipes,
+    Todos,
+}
+
+impl SearchDomain {
+    pub const ALL: [Self; 6] = [
+        Self::Contacts,
+        Self::Events,
+        Self::Notes,
+        Self::Places,
+        Self::Recipes,
+        Self::Todos,
+    ];
+}
+
+#[derive(Builder, Deserialize, JsonSchema, Serialize, ToSchema)]
+pub struct SearchAllRequest {
+    /// User query string to compare items against. Basically, if the user
+    /// is asking something like "find anything about the cabin trip", then
+    /// the query string should be something like "cabin trip".
+    pub query: String,
+    /// Max number of items to consider from each kind of item before they're
+    /// ranked together. Defaults to 5.
+    pub limit_per_domain: Option<i64>,
+    /// Only search these kinds of items. Leave this empty to search
+    /// everything.
+    pub domains: Option<Vec<SearchDomain>>,
+}
+
+#[derive(Debug, Deserialize, PartialEq, Serialize, ToSchema)]
+pub struct SearchAllResult {
+    /// Kind of item that matched.
+    pub domain: SearchDomain,
+    /// Database-generated ID of the item within its kind.
+    pub id: i32,
+    /// Start of the item's text.
+    pub snippet: String,
+    /// How relevant the item is to the query, according to the reranking
+    /// model.
+    pub score: f64,
+}
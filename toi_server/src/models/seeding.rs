@@ -0,0 +1,20 @@
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+#[derive(Clone, Copy, Debug, Default, Deserialize, PartialEq, Serialize, ToSchema)]
+pub struct SeedCount {
+    /// Number of items added from the domain's seed file.
+    pub added: usize,
+    /// Number of items in the domain's seed file that were skipped because
+    /// they already exist or are repeated in the file.
+    pub skipped: usize,
+}
+
+#[derive(Clone, Copy, Debug, Default, Deserialize, PartialEq, Serialize, ToSchema)]
+pub struct SeedReport {
+    pub contacts: SeedCount,
+    pub events: SeedCount,
+    pub notes: SeedCount,
+    pub recipes: SeedCount,
+    pub todos: SeedCount,
+}
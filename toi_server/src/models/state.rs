@@ -1,12 +1,31 @@
-use crate::{client::ModelClient, models::config::ServerConfig, utils};
+use crate::{
+    auth::TokenAuth,
+    client::ModelClient,
+    embeddings::EmbeddingInstructions,
+    metrics::Metrics,
+    models::{
+        capabilities::CapabilitiesCache,
+        config::{NewsConfig, ServerConfig},
+    },
+    rate_limit::RateLimiter,
+    resume::ResumeStore,
+    utils,
+};
 use axum::extract::FromRef;
 
 #[derive(Clone)]
 pub struct ToiState {
     pub server_config: ServerConfig,
     pub api_client: reqwest::Client,
+    pub capabilities: CapabilitiesCache,
+    pub embedding_instructions: EmbeddingInstructions,
+    pub metrics: Metrics,
     pub model_client: ModelClient,
+    pub news_config: NewsConfig,
     pub pool: utils::Pool,
+    pub rate_limiter: RateLimiter,
+    pub resume_store: ResumeStore,
+    pub token_auth: TokenAuth,
 }
 
 impl FromRef<ToiState> for reqwest::Client {
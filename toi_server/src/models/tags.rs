@@ -1,8 +1,10 @@
 use bon::Builder;
+use chrono::{DateTime, Utc};
 use diesel::{Insertable, Queryable, Selectable};
 use pgvector::Vector;
 use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
 use utoipa::ToSchema;
 
 #[derive(Debug, Deserialize, PartialEq, Queryable, Selectable, Serialize, ToSchema)]
@@ -13,12 +15,24 @@ pub struct Tag {
     pub id: i32,
     /// Tag name.
     pub name: String,
+    /// Datetime the tag was created in ISO format.
+    pub created_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Deserialize, PartialEq, Serialize, ToSchema)]
+pub struct TagWithUsage {
+    /// Matching tag.
+    #[serde(flatten)]
+    pub tag: Tag,
+    /// Number of recipes, notes, and todos with the tag.
+    pub usage_count: i64,
 }
 
 #[derive(Insertable)]
 #[diesel(table_name = crate::schema::tags)]
 #[diesel(check_for_backend(diesel::pg::Pg))]
 pub struct NewTag {
+    pub user_id: i32,
     pub name: String,
     pub embedding: Vector,
 }
@@ -29,6 +43,45 @@ pub struct NewTagRequest {
     pub name: String,
 }
 
+/// How items are matched when searching them with several tags.
+#[derive(Clone, Copy, Default, Deserialize, JsonSchema, PartialEq, Serialize, ToSchema)]
+pub enum TagMatch {
+    /// Match items with any of the tags.
+    #[default]
+    Any,
+    /// Only match items with every one of the tags (e.g., "notes tagged
+    /// both work and urgent").
+    All,
+}
+
+impl TagMatch {
+    /// IDs of the items that match, given pairs of item IDs and the IDs of
+    /// their tags that are being searched with. Items are in ID order.
+    #[must_use]
+    pub fn matching_ids(self, item_tags: Vec<(i32, i32)>, num_tags: usize) -> Vec<i32> {
+        let mut num_tags_by_item: BTreeMap<i32, usize> = BTreeMap::new();
+        for (item_id, _) in item_tags {
+            *num_tags_by_item.entry(item_id).or_default() += 1;
+        }
+        num_tags_by_item
+            .into_iter()
+            .filter(|(_, num_item_tags)| match self {
+                Self::Any => true,
+                Self::All => *num_item_tags >= num_tags,
+            })
+            .map(|(item_id, _)| item_id)
+            .collect()
+    }
+}
+
+#[derive(Clone, Deserialize, JsonSchema, PartialEq, Serialize, ToSchema)]
+pub enum TagOrderBy {
+    /// Order tags by name, alphabetically.
+    Alphabetical,
+    /// Order tags by when they were created, newest first.
+    Newest,
+}
+
 #[derive(Builder, Deserialize, JsonSchema, Serialize, ToSchema)]
 pub struct TagSearchParams {
     /// Select tags using their database-generated IDs rather than searching
@@ -47,7 +100,54 @@ pub struct TagSearchParams {
     /// matching.
     pub use_reranking_filter: Option<bool>,
     /// Whether to match the query string more closely, character-for-character.
+    /// Applies with or without the reranking filter.
     pub use_edit_distance_filter: Option<bool>,
+    /// How to order results for retrieved tags. Tags are ordered by how
+    /// similar they are to the query string if there is one, and
+    /// alphabetically otherwise.
+    pub order_by: Option<TagOrderBy>,
     /// Limit the max number of tags to return from the search.
     pub limit: Option<i64>,
 }
+
+#[derive(Builder, Deserialize, JsonSchema, Serialize, ToSchema)]
+pub struct UpdateTagRequest {
+    /// Update a tag using its database-generated ID rather than searching
+    /// for it.
+    pub id: Option<i32>,
+    /// User query string to compare embeddings against. Basically,
+    /// if the user is asking something like "what color is my jacket?",
+    /// then the query string should be something like "jacket color" or
+    /// the user's original question. This can be left empty to ignore
+    /// similarity search in cases where the user wants to filter by
+    /// other means or get all items.
+    pub query: Option<String>,
+    /// Whether to match the query string more closely using a reranking -based
+    /// approach. `true` is useful for cases where the user is looking to match
+    /// to specific words or phrases, whereas `false` is useful for more broad
+    /// matching.
+    pub use_reranking_filter: Option<bool>,
+    /// New tag name.
+    pub new_name: String,
+    /// Whether to merge the tag into an existing tag if one already has the
+    /// new name. Recipes, notes, and todos with the old tag are given the
+    /// existing tag instead, and the old tag is deleted.
+    pub merge: Option<bool>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::TagMatch;
+
+    #[test]
+    fn matching_tagged_items() {
+        // Items 1 and 3 have both tags, and item 2 has only one of them.
+        let item_tags = vec![(3, 10), (1, 10), (2, 20), (1, 20), (3, 20)];
+        assert_eq!(
+            TagMatch::Any.matching_ids(item_tags.clone(), 2),
+            vec![1, 2, 3]
+        );
+        assert_eq!(TagMatch::All.matching_ids(item_tags, 2), vec![1, 3]);
+        assert!(TagMatch::All.matching_ids(vec![], 1).is_empty());
+    }
+}
@@ -6,7 +6,28 @@ use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 use utoipa::ToSchema;
 
-use crate::utils;
+use crate::{
+    models::{
+        deletion::DeleteFilters,
+        events::Event,
+        tags::{Tag, TagMatch},
+    },
+    utils,
+};
+
+#[derive(Clone, Deserialize, JsonSchema, PartialEq, Serialize, ToSchema)]
+pub enum TodoOrderBy {
+    /// Order by when todos were created, oldest first.
+    Oldest,
+    /// Order by when todos were created, newest first.
+    Newest,
+    /// Order by when todos are due, soonest first. Todos that are never
+    /// due come last.
+    DueSoonest,
+    /// Order by priority, highest first. Todos without a priority come
+    /// last, and ties are broken by due date.
+    HighestPriority,
+}
 
 #[derive(Debug, Deserialize, PartialEq, Queryable, Selectable, Serialize, ToSchema)]
 #[diesel(table_name = crate::schema::todos)]
@@ -22,16 +43,58 @@ pub struct Todo {
     pub due_at: Option<DateTime<Utc>>,
     /// Datetime the todo was completed in ISO format.
     pub completed_at: Option<DateTime<Utc>>,
+    /// Todo priority. Higher values are more urgent.
+    pub priority: Option<i16>,
+    /// Number of days between each time a recurring todo is due.
+    pub recurrence_days: Option<i32>,
+    /// Datetime a recurring todo was last completed in ISO format.
+    pub last_completed_at: Option<DateTime<Utc>>,
+    /// Datetime the todo was moved to the trash in ISO format.
+    pub deleted_at: Option<DateTime<Utc>>,
+    /// Database-generated ID of the event the todo is for. It's cleared if
+    /// the event is deleted.
+    pub event_id: Option<i32>,
+}
+
+#[derive(Debug, Deserialize, PartialEq, Serialize, ToSchema)]
+pub struct TodoWithEvent {
+    /// Matching todo.
+    #[serde(flatten)]
+    pub todo: Todo,
+    /// The event the todo is for. Only included when filtering todos by
+    /// event.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub event: Option<Event>,
+}
+
+#[derive(Debug, Deserialize, PartialEq, Serialize, ToSchema)]
+pub struct TodoTags {
+    /// Matching todo.
+    pub todo: Todo,
+    /// Matching tags.
+    pub tags: Vec<Tag>,
+}
+
+#[derive(Insertable)]
+#[diesel(table_name = crate::schema::todo_tags)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+pub struct NewTodoTag {
+    pub todo_id: i32,
+    pub tag_id: i32,
 }
 
 #[derive(Insertable)]
 #[diesel(table_name = crate::schema::todos)]
 #[diesel(check_for_backend(diesel::pg::Pg))]
 pub struct NewTodo {
+    pub user_id: i32,
     pub item: String,
     pub embedding: Vector,
     pub due_at: Option<DateTime<Utc>>,
     pub completed_at: Option<DateTime<Utc>>,
+    pub priority: Option<i16>,
+    pub recurrence_days: Option<i32>,
+    pub event_id: Option<i32>,
 }
 
 #[derive(Builder, Deserialize, JsonSchema, Serialize, ToSchema)]
@@ -42,6 +105,28 @@ pub struct NewTodoRequest {
     pub due_at: Option<DateTime<Utc>>,
     /// Optional datetime the todo was completed in ISO format.
     pub completed_at: Option<DateTime<Utc>>,
+    /// Optional todo priority. Higher values are more urgent, so
+    /// something like "urgent" or "high priority" should be a higher
+    /// value than "whenever" or "low priority".
+    pub priority: Option<i16>,
+    /// Optional number of days between each time the todo is due for
+    /// recurring todos like "water the plants every 3 days". Completing
+    /// a recurring todo pushes its due date back by this many days rather
+    /// than marking it as complete.
+    pub recurrence_days: Option<i32>,
+    /// Link the todo to an event using the event's database-generated ID
+    /// rather than searching for it.
+    pub event_id: Option<i32>,
+    /// Query string for finding the event the todo is for, like "dinner
+    /// party" for "prep for Friday's dinner party". Leave this empty if the
+    /// todo isn't for an event.
+    pub event_query: Option<String>,
+    /// Todo tags. Each one is matched to the closest existing tag.
+    pub tags: Option<Vec<String>>,
+    /// Add the todo even if it looks like a duplicate of an existing todo
+    /// that isn't complete. Only set this if the user explicitly asks for
+    /// it.
+    pub allow_duplicate: Option<bool>,
 }
 
 #[derive(Builder, Deserialize, JsonSchema, Serialize, ToSchema)]
@@ -78,13 +163,76 @@ pub struct CompleteTodoRequest {
     pub incomplete: Option<utils::Scope>,
     /// Whether to include or exclude todos that are never due.
     pub never_due: Option<utils::Scope>,
-    /// How to order results for retrieved todos.
-    pub order_by: Option<utils::OrderBy>,
+    /// Whether to include or exclude recurring todos.
+    pub is_recurring: Option<utils::Scope>,
+    /// Filter on todos with at least this priority.
+    pub min_priority: Option<i16>,
+    /// Filter on todos with at most this priority.
+    pub max_priority: Option<i16>,
+    /// How to order results for retrieved todos. Use `DueSoonest` or
+    /// `HighestPriority` for questions about urgent todos.
+    pub order_by: Option<TodoOrderBy>,
     /// Limit the max number of todos to return from the search.
     pub limit: Option<i64>,
 }
 
 #[derive(Builder, Deserialize, JsonSchema, Serialize, ToSchema)]
+pub struct SnoozeTodoRequest {
+    /// Snooze todos using their database-generated IDs rather than
+    /// searching for them first.
+    pub ids: Option<Vec<i32>>,
+    /// Datetime to push todos back to in ISO format, for snoozing until a
+    /// specific time like "snooze the dentist todo until next Monday" or
+    /// "remind me about the taxes todo on April 1st". Either this or
+    /// `snooze_for_days` is required.
+    pub snooze_until: Option<DateTime<Utc>>,
+    /// Number of days to push todos back by, for snoozing for a while like
+    /// "snooze the laundry todo for 2 days" or "push back my todos by a
+    /// week". Todos are pushed back from when they're due or, if they're
+    /// never due, from now. Either this or `snooze_until` is required.
+    pub snooze_for_days: Option<i32>,
+    /// Whether to also reopen completed todos that are snoozed, like
+    /// "I didn't actually finish the dentist todo, snooze it until
+    /// tomorrow". Completed todos can't be snoozed otherwise.
+    pub reopen: Option<bool>,
+    /// User query string to compare embeddings against. Basically,
+    /// if the user is asking something like "what color is my jacket?",
+    /// then the query string should be something like "jacket color" or
+    /// the user's original question. This can be left empty to ignore
+    /// similarity search in cases where the user wants to filter by
+    /// other means or get all items.
+    pub query: Option<String>,
+    /// Whether to match the query string more closely using a reranking -based
+    /// approach. `true` is useful for cases where the user is looking to match
+    /// to specific words or phrases, whereas `false` is useful for more broad
+    /// matching.
+    pub use_reranking_filter: Option<bool>,
+    /// Filter on todos created after this ISO formatted datetime.
+    pub created_from: Option<DateTime<Utc>>,
+    /// Filter on todos created before this ISO formatted datetime.
+    pub created_to: Option<DateTime<Utc>>,
+    /// Filter on todos due after this ISO formatted datetime.
+    pub due_from: Option<DateTime<Utc>>,
+    /// Filter on todos due before this ISO formatted datetime.
+    pub due_to: Option<DateTime<Utc>>,
+    /// Whether to include or exclude todos that are incomplete.
+    pub incomplete: Option<utils::Scope>,
+    /// Whether to include or exclude todos that are never due.
+    pub never_due: Option<utils::Scope>,
+    /// Whether to include or exclude recurring todos.
+    pub is_recurring: Option<utils::Scope>,
+    /// Filter on todos with at least this priority.
+    pub min_priority: Option<i16>,
+    /// Filter on todos with at most this priority.
+    pub max_priority: Option<i16>,
+    /// How to order results for retrieved todos. Use `DueSoonest` or
+    /// `HighestPriority` for questions about urgent todos.
+    pub order_by: Option<TodoOrderBy>,
+    /// Limit the max number of todos to return from the search.
+    pub limit: Option<i64>,
+}
+
+#[derive(Builder, Clone, Deserialize, JsonSchema, Serialize, ToSchema)]
 pub struct TodoSearchParams {
     /// Select todos using their database-generated IDs rather than
     /// searching for them.
@@ -101,6 +249,17 @@ pub struct TodoSearchParams {
     /// to specific words or phrases, whereas `false` is useful for more broad
     /// matching.
     pub use_reranking_filter: Option<bool>,
+    /// Query string for todos to leave out of the results. It's compared
+    /// against todos like the query string, and todos that closely match it
+    /// are removed even if they match every other filter or are selected by
+    /// their IDs. Use this when the user asks for todos that aren't about
+    /// something (e.g., "todos not about work" should have an exclude query of
+    /// "work"). Todos are excluded before the limit and offset are applied, so
+    /// excluded todos don't take up room in the results.
+    pub exclude_query: Option<String>,
+    /// Leave todos with these database-generated IDs out of the results, even
+    /// if they match every other filter or are selected by their IDs.
+    pub exclude_ids: Option<Vec<i32>>,
     /// Filter on todos created after this ISO formatted datetime.
     pub created_from: Option<DateTime<Utc>>,
     /// Filter on todos created before this ISO formatted datetime.
@@ -117,8 +276,127 @@ pub struct TodoSearchParams {
     pub incomplete: Option<utils::Scope>,
     /// Whether to include or exclude todos that are never due.
     pub never_due: Option<utils::Scope>,
+    /// Whether to include or exclude recurring todos.
+    pub is_recurring: Option<utils::Scope>,
+    /// Filter on todos with at least this priority.
+    pub min_priority: Option<i16>,
+    /// Filter on todos with at most this priority.
+    pub max_priority: Option<i16>,
+    /// Filter on todos for an event using the event's database-generated
+    /// ID. Matching todos are returned with their event.
+    pub event_id: Option<i32>,
+    /// Filter on todos for the event that best matches this query string,
+    /// like "party" for "what do I still need to do before the party".
+    /// Matching todos are returned with their event.
+    pub event_query: Option<String>,
+    /// Todo tags to search with.
+    pub tags: Option<Vec<String>>,
+    /// Whether todos need any or all of the tags to match. Defaults to any.
+    pub tags_match: Option<TagMatch>,
+    /// How to order results for retrieved todos. Use `DueSoonest` or
+    /// `HighestPriority` for questions about urgent todos.
+    pub order_by: Option<TodoOrderBy>,
+    /// Limit the max number of todos to return from the search.
+    pub limit: Option<i64>,
+    /// Skip this many matching todos before returning results. Use with
+    /// the limit to page through many todos.
+    pub offset: Option<i64>,
+    /// Only count matching todos instead of returning them. Useful for
+    /// questions like "how many todos are there".
+    pub count_only: Option<bool>,
+}
+
+impl DeleteFilters for TodoSearchParams {
+    fn narrows(&self) -> bool {
+        self.ids.is_some()
+            || self.query.is_some()
+            || self.created_from.is_some()
+            || self.created_to.is_some()
+            || self.due_from.is_some()
+            || self.due_to.is_some()
+            || self.completed_from.is_some()
+            || self.completed_to.is_some()
+            || self.incomplete.is_some()
+            || self.never_due.is_some()
+            || self.is_recurring.is_some()
+            || self.min_priority.is_some()
+            || self.max_priority.is_some()
+            || self.event_id.is_some()
+            || self.event_query.is_some()
+            || self.tags.is_some()
+            || self.limit.is_some()
+    }
+}
+
+#[derive(Builder, Deserialize, JsonSchema, Serialize, ToSchema)]
+pub struct TodoTagSearchParams {
+    /// Select a todo using its database-generated ID rather than searching
+    /// for it first.
+    pub todo_id: Option<i32>,
+    /// User query string to compare embeddings against. Basically,
+    /// if the user is asking something like "what color is my jacket?",
+    /// then the query string should be something like "jacket color" or
+    /// the user's original question.
+    /// This can be left empty or null to ignore similarity search
+    /// in cases where the user wants to filter by other params
+    /// (e.g., get items by date or get all items).
+    pub todo_query: Option<String>,
+    /// Whether to match the query string more closely using a reranking -based
+    /// approach. `true` is useful for cases where the user is looking to match
+    /// to a specific phrase, name, or words.
+    pub todo_use_reranking_filter: Option<bool>,
+    /// Filter on todos created after this ISO formatted datetime.
+    pub todo_created_from: Option<DateTime<Utc>>,
+    /// Filter on todos created before this ISO formatted datetime.
+    pub todo_created_to: Option<DateTime<Utc>>,
+    /// How to order results for retrieved todos.
+    pub todo_order_by: Option<TodoOrderBy>,
+    /// Search tags using their database-generated IDs rather than
+    /// searching for them first.
+    pub tag_ids: Option<Vec<i32>>,
+    /// User query string to compare embeddings against. Basically,
+    /// if the user is asking something like "what color is my jacket?",
+    /// then the query string should be something like "jacket color" or
+    /// the user's original question.
+    /// This can be left empty or null to ignore similarity search
+    /// in cases where the user wants to filter by other params
+    /// (e.g., get items by date or get all items).
+    pub tag_query: Option<String>,
+    /// Whether to match the query string more closely using a reranking -based
+    /// approach. `true` is useful for cases where the user is looking to match
+    /// to a specific phrase, name, or words.
+    pub tag_use_reranking_filter: Option<bool>,
+    /// Whether to match the query string more closely, character-for-character.
+    pub tag_use_edit_distance_filter: Option<bool>,
+    /// Limit the max number of tags to return from the search.
+    pub tag_limit: Option<i64>,
+}
+
+#[derive(Builder, Deserialize, JsonSchema, Serialize, ToSchema)]
+pub struct NewTodoTagsRequest {
+    /// Select todos using their database-generated IDs rather than
+    /// searching for them.
+    pub ids: Option<Vec<i32>>,
+    /// User query string to compare embeddings against. Basically,
+    /// if the user is asking something like "what color is my jacket?",
+    /// then the query string should be something like "jacket color" or
+    /// the user's original question. This can be left empty to ignore
+    /// similarity search in cases where the user wants to filter by
+    /// other means or get all items.
+    pub query: Option<String>,
+    /// Whether to match the query string more closely using a reranking -based
+    /// approach. `true` is useful for cases where the user is looking to match
+    /// to specific words or phrases, whereas `false` is useful for more broad
+    /// matching.
+    pub use_reranking_filter: Option<bool>,
+    /// Filter on todos created after this ISO formatted datetime.
+    pub created_from: Option<DateTime<Utc>>,
+    /// Filter on todos created before this ISO formatted datetime.
+    pub created_to: Option<DateTime<Utc>>,
     /// How to order results for retrieved todos.
-    pub order_by: Option<utils::OrderBy>,
+    pub order_by: Option<TodoOrderBy>,
+    /// Todo tags to add.
+    pub tags: Vec<String>,
     /// Limit the max number of todos to return from the search.
     pub limit: Option<i64>,
 }
@@ -4,7 +4,7 @@ use diesel::{Insertable, Queryable, Selectable};
 use pgvector::Vector;
 use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
-use utoipa::ToSchema;
+use utoipa::{IntoParams, ToSchema};
 
 use crate::{models::accounts::BankAccount, utils};
 
@@ -17,17 +17,20 @@ pub struct LinkedTransaction {
     pub description: String,
     pub amount: f32,
     pub posted_at: DateTime<Utc>,
+    pub category: Option<String>,
 }
 
 #[derive(Insertable)]
 #[diesel(table_name = crate::schema::transactions)]
 #[diesel(check_for_backend(diesel::pg::Pg))]
 pub struct NewLinkedTransaction {
+    pub user_id: i32,
     pub bank_account_id: i32,
     pub description: String,
     pub amount: f32,
     pub embedding: Vector,
     pub posted_at: Option<DateTime<Utc>>,
+    pub category: Option<String>,
 }
 
 #[derive(Debug, Deserialize, PartialEq, Queryable, Selectable, Serialize, ToSchema)]
@@ -38,9 +41,10 @@ pub struct Transaction {
     pub description: String,
     pub amount: f32,
     pub posted_at: DateTime<Utc>,
+    pub category: Option<String>,
 }
 
-#[derive(Builder, Deserialize, JsonSchema, Serialize, ToSchema)]
+#[derive(Builder, Clone, Deserialize, JsonSchema, Serialize, ToSchema)]
 pub struct TransactionSearchParams {
     /// Select a bank account using its database-generated IDs rather than
     /// searching for it first.
@@ -65,10 +69,18 @@ pub struct TransactionSearchParams {
     pub posted_from: Option<DateTime<Utc>>,
     /// Filter on transactions posted before this ISO formatted datetime.
     pub posted_to: Option<DateTime<Utc>>,
+    /// Filter on transactions with this exact category (e.g., "groceries").
+    pub category: Option<String>,
     /// How to order results for retrieved transactions.
     pub order_by: Option<utils::OrderBy>,
     /// Limit the max number of transactions to return from the search.
     pub limit: Option<i64>,
+    /// Skip this many matching transactions before returning results. Use with
+    /// the limit to page through many transactions.
+    pub offset: Option<i64>,
+    /// Only count matching transactions instead of returning them. Useful for
+    /// questions like "how many transactions are there".
+    pub count_only: Option<bool>,
 }
 
 #[derive(Builder, Deserialize, JsonSchema, Serialize, ToSchema)]
@@ -100,6 +112,10 @@ pub struct NewBankAccountTransactionRequest {
     pub transaction_amount: f32,
     /// Time the transaction was made/posted in ISO format.
     pub transaction_posted_at: Option<DateTime<Utc>>,
+    /// Short, lowercase spending category for the transaction (e.g.,
+    /// "groceries", "rent", or "dining"). Leave empty if there isn't an
+    /// obvious category.
+    pub transaction_category: Option<String>,
 }
 
 #[derive(Debug, Deserialize, PartialEq, Serialize, ToSchema)]
@@ -164,4 +180,29 @@ pub struct BankAccountTransactionSearchParams {
     pub transaction_order_by: Option<utils::OrderBy>,
     /// Limit the max number of transactions to return from the search.
     pub transaction_limit: Option<i64>,
+    /// Skip this many matching transactions before returning results. Use with
+    /// the limit to page through many transactions.
+    pub transaction_offset: Option<i64>,
+}
+
+#[derive(Builder, Deserialize, IntoParams, JsonSchema, Serialize)]
+pub struct TransactionSummaryParams {
+    /// Only summarize transactions with this exact category (e.g.,
+    /// "groceries"). Leave empty to summarize all categories.
+    pub category: Option<String>,
+    /// Only include transactions posted after this ISO formatted datetime.
+    pub posted_from: Option<DateTime<Utc>>,
+    /// Only include transactions posted before this ISO formatted datetime.
+    pub posted_to: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Deserialize, PartialEq, Serialize, ToSchema)]
+pub struct TransactionCategorySummary {
+    /// Transaction category. Transactions without a category are grouped
+    /// as "uncategorized".
+    pub category: String,
+    /// Sum of all transaction amounts in the category within the window.
+    pub total: f32,
+    /// Number of transactions in the category within the window.
+    pub transaction_count: i64,
 }
@@ -0,0 +1,181 @@
+// Example phone number included in phone number errors so the assistant can
+// tell the user what's expected.
+const PHONE_EXAMPLE: &str = "555-123-4567";
+
+/// Whether a phone number has an extension, like "ext. 89", "x89", or "#89".
+fn has_extension(phone: &str) -> bool {
+    let phone = phone.to_lowercase();
+    ["ext", "x", "#"].iter().any(|marker| {
+        phone.split(marker).skip(1).any(|rest| {
+            rest.trim_start_matches(['.', ':', ' '])
+                .starts_with(|c: char| c.is_ascii_digit())
+        })
+    })
+}
+
+/// Normalize a US phone number written in a common format (e.g., with
+/// parentheses, dots, spaces, or a leading +1) into XXX-XXX-XXXX format.
+/// Returns why the phone number is invalid otherwise.
+pub fn normalize_phone_number(phone: &str) -> Result<String, String> {
+    let phone = phone.trim();
+    if has_extension(phone) {
+        return Err(format!(
+            "it has an extension, and only the 10-digit number (like {PHONE_EXAMPLE}) can be stored"
+        ));
+    }
+    if phone
+        .chars()
+        .any(|c| !c.is_ascii_digit() && !matches!(c, ' ' | '-' | '.' | '(' | ')' | '+'))
+    {
+        return Err(format!(
+            "it has letters or symbols that don't belong in a phone number, and phone numbers need 10 digits (like {PHONE_EXAMPLE})"
+        ));
+    }
+    if phone.rfind('+').is_some_and(|index| index > 0) {
+        return Err(format!(
+            "a + can only start a phone number, and phone numbers need 10 digits (like {PHONE_EXAMPLE})"
+        ));
+    }
+    let digits: String = phone.chars().filter(char::is_ascii_digit).collect();
+    let digits = match (phone.starts_with('+'), digits.len()) {
+        (true, 11) if digits.starts_with('1') => &digits[1..],
+        (true, _) => {
+            return Err(
+                "it's an international phone number, and only US phone numbers (+1) are supported"
+                    .to_string(),
+            );
+        }
+        (false, 11) if digits.starts_with('1') => &digits[1..],
+        (false, 10) => &digits,
+        (false, num_digits) => {
+            return Err(format!(
+                "it has {num_digits} digits, and phone numbers need 10 digits (like {PHONE_EXAMPLE})"
+            ));
+        }
+    };
+    Ok(format!(
+        "{}-{}-{}",
+        &digits[..3],
+        &digits[3..6],
+        &digits[6..]
+    ))
+}
+
+/// Check that an email looks like an email address (i.e., something like
+/// name@example.com), returning it without surrounding whitespace. This is
+/// only a lightweight check, so some invalid addresses still pass. Returns
+/// why the email is invalid otherwise.
+pub fn validate_email(email: &str) -> Result<String, String> {
+    let email = email.trim();
+    let Some((local, domain)) = email.split_once('@') else {
+        return Err("it's missing an @, and emails look like name@example.com".to_string());
+    };
+    if domain.contains('@') {
+        return Err("it has more than one @".to_string());
+    }
+    if local.is_empty() {
+        return Err("it's missing the name before the @".to_string());
+    }
+    if local
+        .chars()
+        .any(|c| c.is_whitespace() || c.is_control() || matches!(c, '"' | ',' | ';' | '<' | '>'))
+    {
+        return Err(
+            "the name before the @ has spaces or characters that aren't allowed".to_string(),
+        );
+    }
+    let labels: Vec<&str> = domain.split('.').collect();
+    if labels.len() < 2 {
+        return Err(
+            "the domain after the @ is missing a dot, and emails look like name@example.com"
+                .to_string(),
+        );
+    }
+    let is_valid_label = |label: &&str| {
+        !label.is_empty()
+            && !label.starts_with('-')
+            && !label.ends_with('-')
+            && label.chars().all(|c| c.is_alphanumeric() || c == '-')
+    };
+    if !labels.iter().all(is_valid_label) {
+        return Err(format!(
+            "the domain after the @ ({domain}) isn't a valid domain"
+        ));
+    }
+    Ok(email.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{normalize_phone_number, validate_email};
+
+    #[test]
+    fn normalizing_phone_numbers() {
+        let valid = [
+            ("555-123-4567", "555-123-4567"),
+            ("5551234567", "555-123-4567"),
+            ("(555) 123-4567", "555-123-4567"),
+            ("555.123.4567", "555-123-4567"),
+            (" 555 123 4567 ", "555-123-4567"),
+            ("+1 555 123 4567", "555-123-4567"),
+            ("+1 (555) 123-4567", "555-123-4567"),
+            ("1-555-123-4567", "555-123-4567"),
+        ];
+        for (phone, expected) in valid {
+            assert_eq!(
+                normalize_phone_number(phone).as_deref(),
+                Ok(expected),
+                "{phone}"
+            );
+        }
+
+        let invalid = [
+            ("call him after 5", "letters or symbols"),
+            ("text 555-123-4567", "letters or symbols"),
+            ("555-123-4567 ext. 89", "extension"),
+            ("555-123-4567 x89", "extension"),
+            ("555-123-4567#89", "extension"),
+            ("+44 20 7946 0958", "international"),
+            ("+52 55 1234 5678", "international"),
+            ("123-4567", "7 digits"),
+            ("555-123-45678", "11 digits"),
+            ("555+123-4567", "+ can only start"),
+            ("", "0 digits"),
+        ];
+        for (phone, reason) in invalid {
+            let err = normalize_phone_number(phone).expect_err(phone);
+            assert!(err.contains(reason), "{phone}: {err}");
+        }
+    }
+
+    #[test]
+    fn validating_emails() {
+        let valid = [
+            ("bob@example.com", "bob@example.com"),
+            (
+                " bob.smith+work@mail.example.co ",
+                "bob.smith+work@mail.example.co",
+            ),
+            ("o'brien@my-domain.org", "o'brien@my-domain.org"),
+        ];
+        for (email, expected) in valid {
+            assert_eq!(validate_email(email).as_deref(), Ok(expected), "{email}");
+        }
+
+        let invalid = [
+            ("bob", "missing an @"),
+            ("bob@@example.com", "more than one @"),
+            ("@example.com", "missing the name"),
+            ("bob smith@example.com", "spaces"),
+            ("bob@example", "missing a dot"),
+            ("bob@example..com", "isn't a valid domain"),
+            ("bob@-example.com", "isn't a valid domain"),
+            ("bob@example.com.", "isn't a valid domain"),
+            ("bob@exa_mple.com", "isn't a valid domain"),
+        ];
+        for (email, reason) in invalid {
+            let err = validate_email(email).expect_err(email);
+            assert!(err.contains(reason), "{email}: {err}");
+        }
+    }
+}
@@ -1,5 +1,6 @@
 use bon::Builder;
 use chrono::{DateTime, Utc};
+use diesel::{AsChangeset, Insertable, Queryable, Selectable};
 use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 use std::fmt;
@@ -9,12 +10,27 @@ use utoipa::{IntoParams, ToSchema};
 pub struct WeatherQueryParams {
     /// Free-form query of where to get weather for. Can be a city, county, zip code, state, or any combination thereof.
     pub query: String,
+    /// Whether to skip previously geocoded results and look up the area
+    /// again. Only useful when a previous weather request got the wrong
+    /// area.
+    pub bypass_cache: Option<bool>,
+    /// Only get hourly forecasts for hours ending after this ISO formatted
+    /// datetime. Only used for hourly forecasts.
+    pub from: Option<DateTime<Utc>>,
+    /// Only get hourly forecasts for hours starting before this ISO
+    /// formatted datetime. Only used for hourly forecasts.
+    pub to: Option<DateTime<Utc>>,
+    /// Whether to return the full National Weather Service response rather
+    /// than a summary of it. Leave this empty when answering users'
+    /// questions since the summary has everything they'd ask about.
+    pub verbose: Option<bool>,
 }
 
 #[derive(Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct PointProperties {
     pub forecast: String,
+    pub forecast_hourly: Option<String>,
     pub forecast_zone: String,
 }
 
@@ -23,6 +39,32 @@ pub struct Point {
     pub properties: PointProperties,
 }
 
+#[derive(AsChangeset, Insertable, Queryable, Selectable)]
+#[diesel(table_name = crate::schema::geocode_cache)]
+#[diesel(primary_key(query))]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+pub struct GeocodeCacheEntry {
+    pub query: String,
+    pub latitude: f64,
+    pub longitude: f64,
+    pub forecast: String,
+    pub forecast_zone: String,
+    pub updated_at: DateTime<Utc>,
+    pub forecast_hourly: Option<String>,
+}
+
+impl From<GeocodeCacheEntry> for Point {
+    fn from(entry: GeocodeCacheEntry) -> Self {
+        Self {
+            properties: PointProperties {
+                forecast: entry.forecast,
+                forecast_hourly: entry.forecast_hourly,
+                forecast_zone: entry.forecast_zone,
+            },
+        }
+    }
+}
+
 #[derive(Deserialize)]
 pub struct GeocodingResult {
     pub name: String,
@@ -73,11 +115,99 @@ pub struct GridpointForecast {
     properties: GridpointForecastProperties,
 }
 
+impl GridpointForecast {
+    /// Only keep forecast periods that overlap with the given window.
+    pub fn retain_within(&mut self, from: Option<DateTime<Utc>>, to: Option<DateTime<Utc>>) {
+        self.properties.periods.retain(|period| {
+            from.is_none_or(|from| period.end_time > from)
+                && to.is_none_or(|to| period.start_time < to)
+        });
+    }
+}
+
+/// Condensed forecast period with only what's needed to answer questions
+/// about the weather.
+#[derive(Debug, Deserialize, PartialEq, Serialize, ToSchema)]
+pub struct ForecastSummary {
+    /// Name of the forecast period (e.g., "Tonight"). Hourly forecast periods
+    /// don't have names.
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub name: String,
+    /// Datetime the forecast period starts in ISO format.
+    pub start_time: DateTime<Utc>,
+    /// Datetime the forecast period ends in ISO format.
+    pub end_time: DateTime<Utc>,
+    /// Temperature and its unit (e.g., "72 F").
+    pub temperature: String,
+    /// Wind speed and direction (e.g., "5 to 10 mph S").
+    pub wind: String,
+    /// Short description of the forecast (e.g., "Mostly Sunny").
+    pub short_forecast: String,
+    /// Percent chance of precipitation.
+    pub precipitation_chance: Option<u16>,
+}
+
+impl From<GridpointForecastPeriod> for ForecastSummary {
+    fn from(period: GridpointForecastPeriod) -> Self {
+        Self {
+            name: period.name,
+            start_time: period.start_time,
+            end_time: period.end_time,
+            temperature: format!("{} {}", period.temperature, period.temperature_unit),
+            wind: format!("{} {}", period.wind_speed, period.wind_direction),
+            short_forecast: period.short_forecast,
+            precipitation_chance: period.probability_of_precipitation.value,
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, PartialEq, Serialize, ToSchema)]
+pub struct GridpointForecastSummary {
+    /// Note about the forecast, like when the hourly forecast isn't
+    /// available and the regular forecast is given instead.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub note: Option<String>,
+    pub periods: Vec<ForecastSummary>,
+}
+
+impl From<GridpointForecast> for GridpointForecastSummary {
+    fn from(forecast: GridpointForecast) -> Self {
+        Self {
+            note: None,
+            periods: forecast
+                .properties
+                .periods
+                .into_iter()
+                .map(ForecastSummary::from)
+                .collect(),
+        }
+    }
+}
+
 #[derive(Deserialize, Serialize, ToSchema)]
+pub struct HourlyForecast {
+    /// Note about the forecast, like when the hourly forecast isn't
+    /// available and the regular forecast is given instead.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub note: Option<String>,
+    #[serde(flatten)]
+    pub forecast: GridpointForecast,
+}
+
+impl From<HourlyForecast> for GridpointForecastSummary {
+    fn from(forecast: HourlyForecast) -> Self {
+        Self {
+            note: forecast.note,
+            ..Self::from(forecast.forecast)
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, PartialEq, Serialize, ToSchema)]
 #[serde(rename_all = "camelCase")]
 pub struct ZoneForecastPeriod {
-    name: String,
-    detailed_forecast: String,
+    pub name: String,
+    pub detailed_forecast: String,
 }
 
 #[derive(Deserialize, Serialize, ToSchema)]
@@ -91,15 +221,28 @@ pub struct ZoneForecast {
     properties: ZoneForecastProperties,
 }
 
+#[derive(Debug, Deserialize, PartialEq, Serialize, ToSchema)]
+pub struct ZoneForecastSummary {
+    pub periods: Vec<ZoneForecastPeriod>,
+}
+
+impl From<ZoneForecast> for ZoneForecastSummary {
+    fn from(forecast: ZoneForecast) -> Self {
+        Self {
+            periods: forecast.properties.periods,
+        }
+    }
+}
+
 #[derive(Deserialize, Serialize, ToSchema)]
 #[serde(rename_all = "camelCase")]
 pub struct AlertProperties {
     area_desc: String,
     sent: DateTime<Utc>,
     effective: DateTime<Utc>,
-    onset: DateTime<Utc>,
+    onset: Option<DateTime<Utc>>,
     expires: DateTime<Utc>,
-    ends: DateTime<Utc>,
+    ends: Option<DateTime<Utc>>,
     status: String,
     message_type: String,
     category: String,
@@ -108,7 +251,7 @@ pub struct AlertProperties {
     event: String,
     headline: String,
     description: String,
-    instruction: String,
+    instruction: Option<String>,
 }
 
 #[derive(Deserialize, Serialize, ToSchema)]
@@ -120,3 +263,134 @@ pub struct AlertFeatures {
 pub struct WeatherAlerts {
     features: Vec<AlertFeatures>,
 }
+
+/// Condensed weather alert with only what's needed to tell users about it.
+#[derive(Debug, Deserialize, PartialEq, Serialize, ToSchema)]
+pub struct AlertSummary {
+    /// Kind of alert (e.g., "Heat Advisory").
+    pub event: String,
+    /// How severe the alert is (e.g., "Moderate").
+    pub severity: String,
+    /// Short summary of the alert.
+    pub headline: String,
+    /// Datetime the alert takes effect in ISO format.
+    pub effective: DateTime<Utc>,
+    /// Datetime the alerted conditions end in ISO format, if known.
+    pub ends: Option<DateTime<Utc>>,
+}
+
+impl From<AlertProperties> for AlertSummary {
+    fn from(properties: AlertProperties) -> Self {
+        Self {
+            event: properties.event,
+            severity: properties.severity,
+            headline: properties.headline,
+            effective: properties.effective,
+            ends: properties.ends,
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, PartialEq, Serialize, ToSchema)]
+pub struct WeatherAlertsSummary {
+    pub alerts: Vec<AlertSummary>,
+}
+
+impl From<WeatherAlerts> for WeatherAlertsSummary {
+    fn from(alerts: WeatherAlerts) -> Self {
+        Self {
+            alerts: alerts
+                .features
+                .into_iter()
+                .map(|feature| AlertSummary::from(feature.properties))
+                .collect(),
+        }
+    }
+}
+
+/// Summary of a National Weather Service response, or the full response if
+/// it was asked for.
+#[derive(Serialize, ToSchema)]
+#[serde(untagged)]
+pub enum WeatherResponse<S, F> {
+    Summary(S),
+    Verbose(F),
+}
+
+impl<S: From<F>, F> WeatherResponse<S, F> {
+    /// Summarize a response unless the full response is wanted.
+    pub fn new(response: F, verbose: Option<bool>) -> Self {
+        if verbose.unwrap_or_default() {
+            Self::Verbose(response)
+        } else {
+            Self::Summary(S::from(response))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{DateTime, TimeDelta, Utc};
+
+    use super::{
+        GridpointForecast, GridpointForecastPeriod, GridpointForecastProperties, PrecipitationData,
+    };
+
+    fn datetime(value: &str) -> DateTime<Utc> {
+        value.parse().expect("datetime should be valid")
+    }
+
+    fn hourly_forecast(starts_at: DateTime<Utc>, num_hours: i64) -> GridpointForecast {
+        let periods = (0..num_hours)
+            .map(|hour| GridpointForecastPeriod {
+                name: String::new(),
+                start_time: starts_at + TimeDelta::hours(hour),
+                end_time: starts_at + TimeDelta::hours(hour + 1),
+                temperature: 70,
+                temperature_unit: "F".to_string(),
+                probability_of_precipitation: PrecipitationData {
+                    unit_code: "wmoUnit:percent".to_string(),
+                    value: Some(10),
+                },
+                wind_speed: "5 mph".to_string(),
+                wind_direction: "S".to_string(),
+                short_forecast: "Sunny".to_string(),
+                detailed_forecast: String::new(),
+            })
+            .collect();
+        GridpointForecast {
+            properties: GridpointForecastProperties {
+                generated_at: starts_at,
+                update_time: starts_at,
+                periods,
+            },
+        }
+    }
+
+    #[test]
+    fn filtering_forecast_periods() {
+        let starts_at = datetime("2025-06-01T00:00:00Z");
+        let mut forecast = hourly_forecast(starts_at, 24);
+        forecast.retain_within(None, None);
+        assert_eq!(forecast.properties.periods.len(), 24);
+
+        // Periods that partially overlap with the window are kept.
+        forecast.retain_within(
+            Some(datetime("2025-06-01T17:30:00Z")),
+            Some(datetime("2025-06-01T19:00:00Z")),
+        );
+        let start_times: Vec<DateTime<Utc>> = forecast
+            .properties
+            .periods
+            .iter()
+            .map(|period| period.start_time)
+            .collect();
+        assert_eq!(
+            start_times,
+            vec![
+                datetime("2025-06-01T17:00:00Z"),
+                datetime("2025-06-01T18:00:00Z")
+            ]
+        );
+    }
+}
@@ -0,0 +1,67 @@
+use axum::{
+    body::{Body, Bytes},
+    http::{HeaderMap, header},
+    response::{IntoResponse, Response},
+};
+use futures::{StreamExt, stream};
+use serde::Serialize;
+
+use crate::{models::error::ToiError, utils};
+
+/// Content type for newline-delimited JSON responses.
+pub const CONTENT_TYPE: &str = "application/x-ndjson";
+
+// Items are loaded and written in batches of this many IDs so large search
+// results aren't buffered in memory.
+const BATCH_SIZE: usize = 200;
+
+/// Whether a request's `Accept` header asks for newline-delimited JSON.
+#[must_use]
+pub fn is_accepted(headers: &HeaderMap) -> bool {
+    headers
+        .get_all(header::ACCEPT)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .filter_map(|media_range| media_range.split(';').next())
+        .any(|media_type| media_type.trim().eq_ignore_ascii_case(CONTENT_TYPE))
+}
+
+/// Stream items as newline-delimited JSON, one item per line, in the same
+/// order as their IDs. Items are loaded a batch of IDs at a time, so the
+/// first items are sent before the rest are loaded.
+pub fn stream_items<T, F, Fut>(
+    pool: utils::Pool,
+    ids: Vec<i32>,
+    load_batch: F,
+    item_id: fn(&T) -> i32,
+) -> Response
+where
+    T: Serialize + Send + 'static,
+    F: Fn(utils::Pool, Vec<i32>) -> Fut + Send + 'static,
+    Fut: Future<Output = Result<Vec<T>, ToiError>> + Send + 'static,
+{
+    let batches: Vec<Vec<i32>> = ids.chunks(BATCH_SIZE).map(<[i32]>::to_vec).collect();
+    let stream = stream::iter(batches).then(move |batch_ids| {
+        let batch = load_batch(pool.clone(), batch_ids.clone());
+        async move {
+            let mut items = batch.await?;
+
+            // Batches are loaded without an order, so they're put back in
+            // the order they were searched in.
+            items.sort_by_key(|item| batch_ids.iter().position(|id| *id == item_id(item)));
+            let mut buffer = vec![];
+            for item in &items {
+                serde_json::to_writer(&mut buffer, item).map_err(utils::internal_error)?;
+                buffer.push(b'\n');
+            }
+            Ok::<_, ToiError>(Bytes::from(buffer))
+        }
+    });
+    let stream = stream.map(|result| result.map_err(|err| std::io::Error::other(err.to_string())));
+    (
+        [(header::CONTENT_TYPE, CONTENT_TYPE)],
+        Body::from_stream(stream),
+    )
+        .into_response()
+}
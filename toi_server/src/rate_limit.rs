@@ -0,0 +1,186 @@
+use axum::{
+    extract::{ConnectInfo, Request, State},
+    http::{HeaderValue, header},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use std::collections::HashMap;
+use std::net::{IpAddr, SocketAddr};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use crate::models::{error::ToiError, state::ToiState};
+use crate::request_id::PARENT_REQUEST_HEADER;
+
+// Buckets that have refilled completely are forgotten once there are this
+// many clients being tracked.
+const MAX_TRACKED_CLIENTS: usize = 1024;
+
+struct TokenBucket {
+    tokens: f64,
+    updated_at: Instant,
+}
+
+/// Token bucket rate limiter keyed by client. Each client can make a burst
+/// of requests at once, and then their requests are limited to a steady
+/// rate as their bucket refills.
+#[derive(Clone)]
+pub struct RateLimiter {
+    capacity: f64,
+    refill_per_second: f64,
+    buckets: Arc<Mutex<HashMap<String, TokenBucket>>>,
+}
+
+impl RateLimiter {
+    /// Requests aren't limited if the rate or burst is zero.
+    #[must_use]
+    pub fn new(requests_per_minute: u32, burst: u32) -> Self {
+        Self {
+            capacity: burst.into(),
+            refill_per_second: f64::from(requests_per_minute) / 60.0,
+            buckets: Arc::new(Mutex::new(HashMap::new())),
+        }
+    }
+
+    fn is_enabled(&self) -> bool {
+        self.capacity > 0.0 && self.refill_per_second > 0.0
+    }
+
+    /// Take a token from a client's bucket as of the given instant. Returns
+    /// how long the client has to wait for a token if their bucket is empty.
+    pub fn check(&self, client: &str, now: Instant) -> Result<(), Duration> {
+        if !self.is_enabled() {
+            return Ok(());
+        }
+        let mut buckets = self
+            .buckets
+            .lock()
+            .unwrap_or_else(std::sync::PoisonError::into_inner);
+        if buckets.len() >= MAX_TRACKED_CLIENTS && !buckets.contains_key(client) {
+            buckets.retain(|_, bucket| {
+                let elapsed = now.saturating_duration_since(bucket.updated_at);
+                bucket.tokens + elapsed.as_secs_f64() * self.refill_per_second < self.capacity
+            });
+        }
+        let bucket = buckets.entry(client.to_string()).or_insert(TokenBucket {
+            tokens: self.capacity,
+            updated_at: now,
+        });
+        let elapsed = now.saturating_duration_since(bucket.updated_at);
+        bucket.tokens =
+            (bucket.tokens + elapsed.as_secs_f64() * self.refill_per_second).min(self.capacity);
+        bucket.updated_at = now;
+        if bucket.tokens >= 1.0 {
+            bucket.tokens -= 1.0;
+            Ok(())
+        } else {
+            let wait = (1.0 - bucket.tokens) / self.refill_per_second;
+            Err(Duration::from_secs_f64(wait))
+        }
+    }
+}
+
+/// Get the IP address of the client making a request.
+fn client_ip(request: &Request) -> Option<IpAddr> {
+    request
+        .extensions()
+        .get::<ConnectInfo<SocketAddr>>()
+        .map(|info| info.0.ip())
+}
+
+/// Check whether a request was proxied by the assistant endpoint to
+/// another endpoint. The request that made it was already limited, so
+/// limiting it again would charge the user twice, and every user's proxied
+/// requests would share one bucket since they all come from the server.
+fn is_proxied(request: &Request) -> bool {
+    request.headers().contains_key(&PARENT_REQUEST_HEADER)
+        && client_ip(request).is_some_and(|ip| ip.is_loopback())
+}
+
+/// Identify the client making a request by their IP address and, if
+/// configured, their user agent.
+fn client_key(state: &ToiState, request: &Request) -> String {
+    let ip = client_ip(request).map_or_else(|| "unknown".to_string(), |ip| ip.to_string());
+    if !state.server_config.rate_limit_by_user_agent {
+        return ip;
+    }
+    let user_agent = request
+        .headers()
+        .get(header::USER_AGENT)
+        .and_then(|value| value.to_str().ok())
+        .unwrap_or_default();
+    format!("{ip} {user_agent}")
+}
+
+/// Middleware for limiting the rate clients can make requests. Requests
+/// over the limit are rejected with a hint of when to retry.
+pub async fn rate_limit(State(state): State<ToiState>, request: Request, next: Next) -> Response {
+    if is_proxied(&request) {
+        return next.run(request).await;
+    }
+    let client = client_key(&state, &request);
+    match state.rate_limiter.check(&client, Instant::now()) {
+        Ok(()) => next.run(request).await,
+        Err(wait) => {
+            // Round up to whole seconds so clients don't retry too early.
+            let retry_after = (wait.as_secs() + u64::from(wait.subsec_nanos() > 0)).max(1);
+            let mut response = ToiError::RateLimited(format!(
+                "rate limit exceeded, retry after {retry_after} seconds"
+            ))
+            .into_response();
+            response
+                .headers_mut()
+                .insert(header::RETRY_AFTER, HeaderValue::from(retry_after));
+            response
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, Instant};
+
+    use super::RateLimiter;
+
+    #[test]
+    fn limiting_bursts() {
+        let limiter = RateLimiter::new(60, 3);
+        let now = Instant::now();
+
+        // A burst of requests is allowed at once, and then the next request
+        // has to wait for the bucket to refill.
+        for _ in 0..3 {
+            assert!(limiter.check("127.0.0.1", now).is_ok());
+        }
+        let wait = limiter
+            .check("127.0.0.1", now)
+            .expect_err("burst should be exhausted");
+        assert_eq!(wait, Duration::from_secs(1));
+
+        // Other clients have their own buckets.
+        assert!(limiter.check("127.0.0.2", now).is_ok());
+
+        // Waiting half the time isn't enough, but waiting the rest is.
+        let now = now + Duration::from_millis(500);
+        assert!(limiter.check("127.0.0.1", now).is_err());
+        let now = now + Duration::from_millis(500);
+        assert!(limiter.check("127.0.0.1", now).is_ok());
+        assert!(limiter.check("127.0.0.1", now).is_err());
+
+        // Buckets only refill up to the burst.
+        let now = now + Duration::from_secs(60);
+        for _ in 0..3 {
+            assert!(limiter.check("127.0.0.1", now).is_ok());
+        }
+        assert!(limiter.check("127.0.0.1", now).is_err());
+    }
+
+    #[test]
+    fn disabled_limits() {
+        let limiter = RateLimiter::new(0, 3);
+        let now = Instant::now();
+        for _ in 0..10 {
+            assert!(limiter.check("127.0.0.1", now).is_ok());
+        }
+    }
+}
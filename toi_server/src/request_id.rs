@@ -0,0 +1,67 @@
+use axum::{
+    extract::Request,
+    http::{HeaderName, HeaderValue},
+    middleware::Next,
+    response::Response,
+};
+use tracing::{Instrument, field, info, info_span};
+use uuid::Uuid;
+
+/// Response header with the ID assigned to a request.
+pub static REQUEST_ID_HEADER: HeaderName = HeaderName::from_static("x-toi-request-id");
+
+/// Request header with the ID of the request that made a request, like when
+/// the assistant endpoint proxies a user's request to another endpoint.
+pub static PARENT_REQUEST_HEADER: HeaderName = HeaderName::from_static("x-toi-parent-request");
+
+/// ID assigned to a request. It's added to requests' extensions so handlers
+/// can pass it along to requests they make.
+#[derive(Clone, Copy, Debug)]
+pub struct RequestId(pub Uuid);
+
+impl RequestId {
+    #[must_use]
+    pub fn header_value(&self) -> HeaderValue {
+        HeaderValue::from_str(&self.0.to_string()).expect("UUIDs should be valid header values")
+    }
+}
+
+/// Assign an ID to each request and log everything about the request within
+/// a span with that ID. Requests made by other requests record their
+/// parent's ID so one user message can be followed end-to-end in the logs.
+///
+/// The assistant endpoint also records the API it matched and its rerank
+/// score on the span.
+pub async fn request_id(mut request: Request, next: Next) -> Response {
+    let request_id = RequestId(Uuid::new_v4());
+    let span = info_span!(
+        "request",
+        request_id = %request_id.0,
+        parent = field::Empty,
+        method = %request.method(),
+        uri = %request.uri().path(),
+        api_method = field::Empty,
+        api_path = field::Empty,
+        rerank_score = field::Empty,
+    );
+    if let Some(parent) = request
+        .headers()
+        .get(&PARENT_REQUEST_HEADER)
+        .and_then(|parent| parent.to_str().ok())
+    {
+        span.record("parent", parent);
+    }
+    request.extensions_mut().insert(request_id);
+
+    async move {
+        info!("started request");
+        let mut response = next.run(request).await;
+        info!("finished request with status {}", response.status());
+        response
+            .headers_mut()
+            .insert(REQUEST_ID_HEADER.clone(), request_id.header_value());
+        response
+    }
+    .instrument(span)
+    .await
+}
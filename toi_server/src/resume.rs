@@ -0,0 +1,384 @@
+use axum::body::{Body, Bytes};
+use futures::StreamExt;
+use std::collections::HashMap;
+use std::convert::Infallible;
+use std::sync::{Arc, Mutex, PoisonError};
+use std::time::{Duration, Instant};
+use toi::ResumePoint;
+use tokio::sync::{mpsc, watch};
+use tracing::{info, warn};
+use uuid::Uuid;
+
+use crate::models::error::ToiError;
+
+// A checkpoint is sent after about this many chunks of a resumable response
+// stream, in addition to the one sent at the start.
+const CHECKPOINT_INTERVAL: usize = 8;
+
+/// What's been sent of a resumable response stream so far and who it's
+/// being sent to. Checkpoints are kept too so offsets line up with what
+/// clients received.
+struct PartialStream {
+    user_id: i32,
+    bytes: Vec<u8>,
+    finished: bool,
+    used_at: Instant,
+}
+
+/// Bounded in-memory store of resumable response streams keyed by resume
+/// token. Streams are forgotten once they haven't been used for a while or
+/// once they get too big, and the least recently used streams are evicted to
+/// make room for new ones.
+#[derive(Clone)]
+pub struct ResumeStore {
+    max_streams: usize,
+    max_bytes: usize,
+    ttl: Duration,
+    streams: Arc<Mutex<HashMap<String, Arc<watch::Sender<PartialStream>>>>>,
+}
+
+impl ResumeStore {
+    /// Response streams aren't resumable if the max number of streams or
+    /// bytes per stream is zero.
+    #[must_use]
+    pub fn new(max_streams: usize, max_bytes: usize, ttl: Duration) -> Self {
+        Self {
+            max_streams,
+            max_bytes,
+            ttl,
+            streams: Arc::new(Mutex::new(HashMap::new())),
+        }
+    }
+
+    fn is_enabled(&self) -> bool {
+        self.max_streams > 0 && self.max_bytes > 0
+    }
+
+    /// Start keeping a new response stream for a user as of the given
+    /// instant, returning its resume token.
+    fn insert(&self, user_id: i32, now: Instant) -> (String, Arc<watch::Sender<PartialStream>>) {
+        let mut streams = self.streams.lock().unwrap_or_else(PoisonError::into_inner);
+        streams.retain(|_, partial| {
+            now.saturating_duration_since(partial.borrow().used_at) < self.ttl
+        });
+        while streams.len() >= self.max_streams {
+            let Some(token) = streams
+                .iter()
+                .min_by_key(|(_, partial)| partial.borrow().used_at)
+                .map(|(token, _)| token.clone())
+            else {
+                break;
+            };
+            streams.remove(&token);
+        }
+        let token = Uuid::new_v4().to_string();
+        let (partial, _) = watch::channel(PartialStream {
+            user_id,
+            bytes: vec![],
+            finished: false,
+            used_at: now,
+        });
+        let partial = Arc::new(partial);
+        streams.insert(token.clone(), partial.clone());
+        (token, partial)
+    }
+
+    /// Get a user's response stream that's still being kept as of the given
+    /// instant. Other users' streams aren't found.
+    fn get(
+        &self,
+        user_id: i32,
+        token: &str,
+        now: Instant,
+    ) -> Option<Arc<watch::Sender<PartialStream>>> {
+        let mut streams = self.streams.lock().unwrap_or_else(PoisonError::into_inner);
+        let partial = streams.get(token)?.clone();
+        if partial.borrow().user_id != user_id {
+            return None;
+        }
+        if now.saturating_duration_since(partial.borrow().used_at) >= self.ttl {
+            streams.remove(token);
+            return None;
+        }
+        partial.send_if_modified(|partial| {
+            partial.used_at = now;
+            false
+        });
+        Some(partial)
+    }
+
+    fn remove(&self, token: &str) {
+        self.streams
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .remove(token);
+    }
+
+    /// Make a response stream resumable by keeping what's sent of it and
+    /// sending checkpoints within it that clients can resume it from. The
+    /// stream keeps being read even if the client disconnects so it can be
+    /// resumed, unless it gets too big to keep. Only the user it's sent to
+    /// can resume it.
+    pub fn track(&self, user_id: i32, body: Body) -> Body {
+        if !self.is_enabled() {
+            return body;
+        }
+        let (token, partial) = self.insert(user_id, Instant::now());
+        let (tx, rx) = mpsc::channel::<Result<Bytes, axum::Error>>(32);
+        let mut tracker = Tracker {
+            store: self.clone(),
+            token,
+            partial,
+            tx,
+            connected: true,
+            forgotten: false,
+        };
+        tokio::spawn(async move {
+            let mut stream = body.into_data_stream();
+
+            // A checkpoint is sent up front so clients can resume even if the
+            // first chunk never makes it to them.
+            let mut chunks_since_checkpoint = CHECKPOINT_INTERVAL;
+            loop {
+                if chunks_since_checkpoint >= CHECKPOINT_INTERVAL && tracker.is_between_lines() {
+                    chunks_since_checkpoint = 0;
+                    let checkpoint = tracker.checkpoint();
+                    if !tracker.send(checkpoint).await {
+                        return;
+                    }
+                }
+                let Some(chunk) = stream.next().await else {
+                    break;
+                };
+                chunks_since_checkpoint += 1;
+                match chunk {
+                    Ok(bytes) => {
+                        if !tracker.send(bytes).await {
+                            return;
+                        }
+                    }
+                    Err(err) => {
+                        warn!("resumable response stream failed: {err}");
+                        if tracker.connected {
+                            let _ = tracker.tx.send(Err(err)).await;
+                        }
+                        break;
+                    }
+                }
+            }
+            tracker.finish();
+        });
+        let stream = futures::stream::unfold(rx, |mut rx| async move {
+            rx.recv().await.map(|chunk| (chunk, rx))
+        });
+        Body::from_stream(stream)
+    }
+
+    /// Resume a user's response stream from a byte offset, replaying what was
+    /// sent after the offset and then following the rest of the stream as
+    /// it's sent.
+    pub fn resume(&self, user_id: i32, resume_point: &ResumePoint) -> Result<Body, ToiError> {
+        let ResumePoint { token, offset } = resume_point;
+        let Some(partial) = self.get(user_id, token, Instant::now()) else {
+            return Err(ToiError::NotFound(format!(
+                "no resumable response stream for token {token}"
+            )));
+        };
+        let rx = partial.subscribe();
+        let len = rx.borrow().bytes.len();
+        if *offset > len {
+            return Err(ToiError::Validation(format!(
+                "resume offset {offset} is past the {len} bytes sent so far"
+            )));
+        }
+        let stream = futures::stream::unfold((rx, *offset), |(mut rx, mut offset)| async move {
+            loop {
+                let bytes = {
+                    let partial = rx.borrow_and_update();
+                    if offset < partial.bytes.len() {
+                        let bytes = Bytes::copy_from_slice(&partial.bytes[offset..]);
+                        offset = partial.bytes.len();
+                        Some(bytes)
+                    } else if partial.finished {
+                        return None;
+                    } else {
+                        None
+                    }
+                };
+                if let Some(bytes) = bytes {
+                    return Some((Ok::<_, Infallible>(bytes), (rx, offset)));
+                }
+                rx.changed().await.ok()?;
+            }
+        });
+        Ok(Body::from_stream(stream))
+    }
+}
+
+/// Forwards a resumable response stream to its client while keeping it in
+/// the store.
+struct Tracker {
+    store: ResumeStore,
+    token: String,
+    partial: Arc<watch::Sender<PartialStream>>,
+    tx: mpsc::Sender<Result<Bytes, axum::Error>>,
+    connected: bool,
+    forgotten: bool,
+}
+
+impl Tracker {
+    /// Checkpoints only go between lines so they don't split any of the
+    /// stream's events.
+    fn is_between_lines(&self) -> bool {
+        self.partial
+            .borrow()
+            .bytes
+            .last()
+            .is_none_or(|byte| *byte == b'\n')
+    }
+
+    fn checkpoint(&self) -> Bytes {
+        let resume_point = ResumePoint {
+            token: self.token.clone(),
+            offset: self.partial.borrow().bytes.len(),
+        };
+        Bytes::from(resume_point.to_checkpoint())
+    }
+
+    /// Keep and forward a chunk of the stream, returning whether the stream
+    /// is still worth reading.
+    async fn send(&mut self, bytes: Bytes) -> bool {
+        if !self.forgotten {
+            let max_bytes = self.store.max_bytes;
+            let kept = self.partial.send_if_modified(|partial| {
+                if partial.bytes.len() + bytes.len() > max_bytes {
+                    return false;
+                }
+                partial.bytes.extend_from_slice(&bytes);
+                partial.used_at = Instant::now();
+                true
+            });
+            if !kept {
+                warn!("response stream is too big to keep for resuming");
+                self.forgotten = true;
+                self.store.remove(&self.token);
+                self.finish();
+            }
+        }
+        if self.connected && self.tx.send(Ok(bytes)).await.is_err() {
+            info!("client disconnected from a resumable response stream");
+            self.connected = false;
+        }
+        self.connected || !self.forgotten
+    }
+
+    fn finish(&self) {
+        self.partial.send_modify(|partial| partial.finished = true);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::body::Body;
+    use std::time::{Duration, Instant};
+    use toi::ResumePoint;
+
+    use super::ResumeStore;
+
+    async fn collect(body: Body) -> String {
+        let bytes = axum::body::to_bytes(body, usize::MAX)
+            .await
+            .expect("body should be readable");
+        String::from_utf8_lossy(&bytes).into_owned()
+    }
+
+    fn body() -> Body {
+        Body::from_stream(futures::stream::iter(
+            ["data: Hello\n\n", "data: world\n\n", "data: [DONE]\n\n"]
+                .map(Ok::<_, std::convert::Infallible>),
+        ))
+    }
+
+    #[tokio::test]
+    async fn resuming_streams() {
+        let store = ResumeStore::new(4, 1024, Duration::from_secs(60));
+        let content = collect(store.track(1, body())).await;
+        let (checkpoint, rest) = content
+            .split_once("\n\n")
+            .expect("stream should start with a checkpoint");
+        assert_eq!(rest, "data: Hello\n\ndata: world\n\ndata: [DONE]\n\n");
+        let resume_point =
+            ResumePoint::from_checkpoint(checkpoint).expect("checkpoint should be parseable");
+        assert_eq!(resume_point.offset, 0);
+
+        // Resuming replays everything after the offset.
+        let offset = content
+            .find("data: world")
+            .expect("stream should have content");
+        let resumed = store
+            .resume(
+                1,
+                &ResumePoint {
+                    offset,
+                    ..resume_point.clone()
+                },
+            )
+            .expect("stream should be resumable");
+        assert_eq!(collect(resumed).await, "data: world\n\ndata: [DONE]\n\n");
+
+        // Other users can't resume the stream.
+        assert!(store.resume(2, &resume_point).is_err());
+
+        // Offsets past what was sent and unknown tokens can't be resumed.
+        let past_end = ResumePoint {
+            offset: content.len() + 1,
+            ..resume_point
+        };
+        assert!(store.resume(1, &past_end).is_err());
+        let unknown = ResumePoint {
+            token: "unknown".to_string(),
+            offset: 0,
+        };
+        assert!(store.resume(1, &unknown).is_err());
+    }
+
+    #[tokio::test]
+    async fn forgetting_big_streams() {
+        let store = ResumeStore::new(4, 16, Duration::from_secs(60));
+        let content = collect(store.track(1, body())).await;
+        let checkpoint = content.lines().next().unwrap_or_default();
+        let resume_point =
+            ResumePoint::from_checkpoint(checkpoint).expect("checkpoint should be parseable");
+
+        // The client still gets the whole stream even though it's too big to
+        // be resumed.
+        assert!(content.ends_with("data: [DONE]\n\n"));
+        assert!(store.resume(1, &resume_point).is_err());
+    }
+
+    #[test]
+    fn evicting_streams() {
+        let store = ResumeStore::new(2, 1024, Duration::from_secs(60));
+        let now = Instant::now();
+        let (first, _) = store.insert(1, now);
+        let (second, _) = store.insert(1, now + Duration::from_secs(1));
+
+        // Using a stream keeps it from being evicted for the longest.
+        assert!(store.get(1, &first, now + Duration::from_secs(2)).is_some());
+        let (third, _) = store.insert(1, now + Duration::from_secs(3));
+        assert!(
+            store
+                .get(1, &second, now + Duration::from_secs(3))
+                .is_none()
+        );
+        assert!(store.get(1, &first, now + Duration::from_secs(3)).is_some());
+        assert!(store.get(1, &third, now + Duration::from_secs(3)).is_some());
+
+        // Streams that haven't been used for a while expire.
+        assert!(
+            store
+                .get(1, &third, now + Duration::from_secs(63))
+                .is_none()
+        );
+    }
+}
@@ -1,13 +1,23 @@
 pub mod accounts;
 pub mod assistant;
 pub mod attendees;
+pub mod audit;
+pub mod capabilities;
 pub mod contacts;
+pub mod conversations;
 pub mod datetime;
 pub mod events;
+pub mod export;
+pub mod health;
+pub mod metrics;
 pub mod news;
 pub mod notes;
 pub mod places;
+pub mod pool;
 pub mod recipes;
+pub mod reminders;
+pub mod search;
+pub mod seeding;
 pub mod tags;
 pub mod todos;
 pub mod transactions;
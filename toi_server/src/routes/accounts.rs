@@ -1,17 +1,32 @@
-use axum::{extract::State, http::StatusCode, response::Json};
+use axum::{
+    extract::{Path, Query, State},
+    http::StatusCode,
+    response::Json,
+};
+use chrono::{DateTime, Utc};
 use diesel::{ExpressionMethods, QueryDsl, SelectableHelper};
-use diesel_async::RunQueryDsl;
+use diesel_async::{AsyncConnection, RunQueryDsl, scoped_futures::ScopedFutureExt};
 use pgvector::VectorExpressionMethods;
 use schemars::schema_for;
 use utoipa_axum::{router::OpenApiRouter, routes};
 
 use crate::{
+    auth::CurrentUser,
+    embeddings::EmbeddedTable,
     models::{
-        accounts::{BankAccount, BankAccountSearchParams, NewBankAccount, NewBankAccountRequest},
-        client::{EmbeddingPromptTemplate, EmbeddingRequest, RerankRequest},
+        accounts::{
+            BankAccount, BankAccountBalance, BankAccountBalanceParams, BankAccountMergeTarget,
+            BankAccountSearchParams, NewBankAccount, NewBankAccountRequest,
+            UpdateBankAccountRequest, UpdatedBankAccount,
+        },
+        client::{EmbeddingCache, EmbeddingRequest},
+        deletion::DeleteParams,
+        pagination::Page,
         state::ToiState,
     },
-    schema, utils,
+    schema,
+    search::{self, RerankOptions},
+    utils,
 };
 
 // Prefixes are used for embedding instructions.
@@ -20,17 +35,51 @@ const QUERY_PREFIX: &str = "Query: ";
 
 pub fn accounts_router(state: ToiState) -> OpenApiRouter {
     OpenApiRouter::new()
-        .routes(routes!(add_bank_account))
+        .routes(routes!(add_bank_account, update_matching_bank_account))
+        .routes(routes!(get_bank_account_balance))
         .routes(routes!(delete_matching_bank_accounts))
         .routes(routes!(get_matching_bank_accounts))
+        .routes(routes!(get_bank_account))
         .with_state(state)
 }
 
 pub async fn search_bank_accounts(
     state: &ToiState,
+    user_id: i32,
+    params: BankAccountSearchParams,
+    embeddings: &mut EmbeddingCache,
+    conn: &mut utils::Conn<'_>,
+) -> Result<Page<i32>, (StatusCode, String)> {
+    // The total is counted alongside a page's items, so it's counted on
+    // its own when the offset is past the end and there aren't any.
+    let count_params =
+        params
+            .offset
+            .is_some_and(|offset| offset > 0)
+            .then(|| BankAccountSearchParams {
+                use_reranking_filter: None,
+                limit: Some(1),
+                offset: None,
+                ..params.clone()
+            });
+    let mut page = search_bank_accounts_page(state, user_id, params, embeddings, conn).await?;
+    if page.total == 0
+        && let Some(count_params) = count_params
+    {
+        page.total = search_bank_accounts_page(state, user_id, count_params, embeddings, conn)
+            .await?
+            .total;
+    }
+    Ok(page)
+}
+
+async fn search_bank_accounts_page(
+    state: &ToiState,
+    user_id: i32,
     params: BankAccountSearchParams,
+    embeddings: &mut EmbeddingCache,
     conn: &mut utils::Conn<'_>,
-) -> Result<Vec<i32>, (StatusCode, String)> {
+) -> Result<Page<i32>, (StatusCode, String)> {
     let BankAccountSearchParams {
         ids,
         query,
@@ -39,10 +88,11 @@ pub async fn search_bank_accounts(
         created_to,
         order_by,
         limit,
+        offset,
     } = params;
 
     let mut sql_query = schema::bank_accounts::table
-        .select(BankAccount::as_select())
+        .select((BankAccount::as_select(), utils::total_count()))
         .into_boxed();
 
     // Filter items created on or after date.
@@ -55,6 +105,10 @@ pub async fn search_bank_accounts(
         sql_query = sql_query.filter(schema::bank_accounts::created_at.le(created_to));
     }
 
+    // Items are only ordered by their distance to the query if they aren't
+    // ordered some other way.
+    let ordered_by_distance = order_by.is_none();
+
     // Order items.
     match order_by {
         Some(utils::OrderBy::Oldest) => {
@@ -66,13 +120,15 @@ pub async fn search_bank_accounts(
         None => {
             // By default, filter items similar to a given query.
             if let Some(ref query) = query {
-                let input = EmbeddingPromptTemplate::builder()
-                    .instruction_prefix(INSTRUCTION_PREFIX.to_string())
-                    .query_prefix(QUERY_PREFIX.to_string())
-                    .build()
+                let input = state
+                    .embedding_instructions
+                    .template("accounts", INSTRUCTION_PREFIX, QUERY_PREFIX)
                     .apply(query);
                 let embedding_request = EmbeddingRequest { input };
-                let embedding = state.model_client.embed(embedding_request).await?;
+                let embedding = state
+                    .model_client
+                    .embed_cached(embedding_request, embeddings)
+                    .await?;
                 sql_query = sql_query
                     .filter(
                         schema::bank_accounts::embedding
@@ -89,38 +145,62 @@ pub async fn search_bank_accounts(
         sql_query = sql_query.or_filter(schema::bank_accounts::id.eq_any(ids));
     }
 
+    // Only the user's own items are searched. This comes after the other
+    // filters so items selected by their ids are still only the user's.
+    sql_query = sql_query.filter(schema::bank_accounts::user_id.eq(user_id));
+
     // Limit number of items.
     if let Some(limit) = limit {
         sql_query = sql_query.limit(limit);
     }
 
+    // Skip a number of items.
+    if let Some(offset) = offset {
+        sql_query = sql_query.offset(offset);
+    }
+
     // Get all the items that match the query.
-    let bank_accounts: Vec<BankAccount> =
+    let bank_accounts: Vec<(BankAccount, i64)> =
         sql_query.load(conn).await.map_err(utils::diesel_error)?;
-    let (ids, documents): (Vec<i32>, Vec<String>) = bank_accounts
+    let total = bank_accounts
+        .as_slice()
+        .first()
+        .map_or(0, |(_, total)| *total);
+    let mut ids_docs: Vec<(i32, String)> = bank_accounts
         .into_iter()
-        .map(|bank_account| (bank_account.id, bank_account.description))
-        .unzip();
-    if ids.is_empty() {
-        return Ok(ids);
+        .map(|(bank_account, _)| (bank_account.id, bank_account.description))
+        .collect();
+
+    // Items that aren't ordered by their distance to the query are put in
+    // that order so only the furthest ones are left out of reranking.
+    if !ordered_by_distance
+        && search::needs_reranking(query.as_deref(), use_reranking_filter)
+        && let Some(ref query) = query
+    {
+        let input = state
+            .embedding_instructions
+            .template("accounts", INSTRUCTION_PREFIX, QUERY_PREFIX)
+            .apply(query);
+        ids_docs = search::closest_first(state, EmbeddedTable::BankAccounts, input, ids_docs, conn)
+            .await?;
     }
 
     // Rerank and filter items once more.
-    let ids = match (query, use_reranking_filter) {
-        (Some(query), Some(true)) => {
-            let rerank_request = RerankRequest { query, documents };
-            let rerank_response = state.model_client.rerank(rerank_request).await?;
-            rerank_response
-                .results
-                .into_iter()
-                .filter(|item| item.relevance_score >= state.server_config.similarity_threshold)
-                .map(|item| ids[item.index])
-                .collect()
-        }
-        _ => ids,
-    };
+    let ids = search::rerank_filter(
+        state,
+        query,
+        use_reranking_filter,
+        ids_docs,
+        &RerankOptions::default(),
+    )
+    .await?;
 
-    Ok(ids)
+    Ok(Page {
+        items: ids,
+        total,
+        offset,
+        limit,
+    })
 }
 
 /// Add and return a bank account.
@@ -146,15 +226,17 @@ pub async fn search_bank_accounts(
 #[axum::debug_handler]
 async fn add_bank_account(
     State(state): State<ToiState>,
+    user: CurrentUser,
     Json(params): Json<NewBankAccountRequest>,
 ) -> Result<Json<BankAccount>, (StatusCode, String)> {
-    let mut conn = state.pool.get().await.map_err(utils::internal_error)?;
+    let mut conn = utils::get_conn(&state.pool).await?;
     let NewBankAccountRequest { description } = params;
     let embedding_request = EmbeddingRequest {
         input: description.clone(),
     };
     let embedding = state.model_client.embed(embedding_request).await?;
     let new_bank_account = NewBankAccount {
+        user_id: user.id,
         description,
         embedding,
     };
@@ -167,6 +249,156 @@ async fn add_bank_account(
     Ok(Json(result))
 }
 
+/// Update and return a bank account, either by renaming it or by merging it
+/// into another bank account.
+///
+/// Example queries for updating a bank account using this endpoint:
+/// - Rename my bank account
+/// - Change the description of my account
+/// - Merge this bank account into
+/// - Move all transactions from this account into
+#[utoipa::path(
+    put,
+    path = "",
+    extensions(
+        ("x-json-schema-body" = json!(schema_for!(UpdateBankAccountRequest)))
+    ),
+    request_body = UpdateBankAccountRequest,
+    responses(
+        (status = 200, description = "Successfully updated bank account", body = UpdatedBankAccount),
+        (status = 400, description = "Neither or both of a new description and a bank account to merge into were given"),
+        (status = 404, description = "Bank account or bank account to merge into not found"),
+        (status = 422, description = "Error when parsing a response from a model API"),
+        (status = 502, description = "Error when forwarding request to model APIs")
+    )
+)]
+#[axum::debug_handler]
+async fn update_matching_bank_account(
+    State(state): State<ToiState>,
+    user: CurrentUser,
+    Json(params): Json<UpdateBankAccountRequest>,
+) -> Result<Json<UpdatedBankAccount>, (StatusCode, String)> {
+    let UpdateBankAccountRequest {
+        id,
+        description,
+        merge_into,
+        query,
+        use_reranking_filter,
+        created_from,
+        created_to,
+        order_by,
+    } = params;
+    if description.is_some() == merge_into.is_some() {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            "exactly one of description or merge_into is required".to_string(),
+        ));
+    }
+    let mut conn = utils::get_conn(&state.pool).await?;
+    let mut embeddings = EmbeddingCache::default();
+    let params = BankAccountSearchParams {
+        ids: id.map(|i| vec![i]),
+        query,
+        use_reranking_filter,
+        created_from,
+        created_to,
+        order_by,
+        limit: Some(1),
+        offset: None,
+    };
+    let id = search_bank_accounts(&state, user.id, params, &mut embeddings, &mut conn)
+        .await?
+        .items
+        .into_iter()
+        .next()
+        .ok_or((StatusCode::NOT_FOUND, "bank account not found".to_string()))?;
+
+    // Renaming only changes the bank account itself, so its transactions are
+    // left alone.
+    let Some(merge_into) = merge_into else {
+        let description = description.unwrap_or_default();
+        let embedding_request = EmbeddingRequest {
+            input: description.clone(),
+        };
+        let embedding = state.model_client.embed(embedding_request).await?;
+        let bank_account =
+            diesel::update(schema::bank_accounts::table.filter(schema::bank_accounts::id.eq(id)))
+                .set((
+                    schema::bank_accounts::description.eq(description),
+                    schema::bank_accounts::embedding.eq(embedding),
+                ))
+                .returning(BankAccount::as_returning())
+                .get_result(&mut conn)
+                .await
+                .map_err(utils::diesel_error)?;
+        return Ok(Json(UpdatedBankAccount {
+            bank_account,
+            moved_transaction_count: 0,
+        }));
+    };
+
+    // The bank account to merge into is the closest match that isn't the
+    // bank account being merged, so a query that also matches it still
+    // finds the duplicate.
+    let BankAccountMergeTarget {
+        id: target_id,
+        query: target_query,
+        use_reranking_filter: target_use_reranking_filter,
+    } = merge_into;
+    let params = BankAccountSearchParams {
+        ids: target_id.map(|i| vec![i]),
+        query: target_query,
+        use_reranking_filter: target_use_reranking_filter,
+        created_from: None,
+        created_to: None,
+        order_by: None,
+        limit: Some(2),
+        offset: None,
+    };
+    let target_id = search_bank_accounts(&state, user.id, params, &mut embeddings, &mut conn)
+        .await?
+        .items
+        .into_iter()
+        .find(|target_id| *target_id != id)
+        .ok_or((
+            StatusCode::NOT_FOUND,
+            "bank account to merge into not found".to_string(),
+        ))?;
+
+    // Transactions are moved before the bank account is deleted, all in one
+    // transaction, so none of them are lost along with it.
+    let (bank_account, moved_transaction_count) = conn
+        .transaction::<_, diesel::result::Error, _>(|conn| {
+            async move {
+                let moved_transaction_count = diesel::update(
+                    schema::transactions::table
+                        .filter(schema::transactions::bank_account_id.eq(id)),
+                )
+                .set(schema::transactions::bank_account_id.eq(target_id))
+                .execute(conn)
+                .await?;
+                diesel::delete(
+                    schema::bank_accounts::table.filter(schema::bank_accounts::id.eq(id)),
+                )
+                .execute(conn)
+                .await?;
+                let bank_account = schema::bank_accounts::table
+                    .select(BankAccount::as_select())
+                    .filter(schema::bank_accounts::id.eq(target_id))
+                    .first(conn)
+                    .await?;
+                Ok((bank_account, moved_transaction_count))
+            }
+            .scope_boxed()
+        })
+        .await
+        .map_err(utils::diesel_error)?;
+    Ok(Json(UpdatedBankAccount {
+        bank_account,
+        moved_transaction_count,
+    }))
+}
+
 /// Delete and return bank accounts.
 ///
 /// Example queries for deleting bank accounts using this endpoint:
@@ -178,13 +410,13 @@ async fn add_bank_account(
     post,
     path = "/delete",
     extensions(
-        ("x-json-schema-body" = json!(schema_for!(BankAccountSearchParams)))
+        ("x-json-schema-body" = json!(schema_for!(DeleteParams<BankAccountSearchParams>)))
     ),
-    request_body = BankAccountSearchParams,
+    request_body = DeleteParams<BankAccountSearchParams>,
     responses(
         (status = 200, description = "Successfully deleted bank accounts", body = [BankAccount]),
-        (status = 400, description = "Default JSON elements configured by the user are invalid"),
-        (status = 404, description = "No bank accounts found"),
+        (status = 400, description = "Default JSON elements configured by the user are invalid, or deleting every item wasn't confirmed"),
+        (status = 404, description = "No bank accounts found with the given IDs"),
         (status = 422, description = "Error when parsing a response from a model API"),
         (status = 502, description = "Error when forwarding request to model APIs")
     )
@@ -192,16 +424,23 @@ async fn add_bank_account(
 #[axum::debug_handler]
 async fn delete_matching_bank_accounts(
     State(state): State<ToiState>,
-    Json(params): Json<BankAccountSearchParams>,
+    user: CurrentUser,
+    Json(params): Json<DeleteParams<BankAccountSearchParams>>,
 ) -> Result<Json<Vec<BankAccount>>, (StatusCode, String)> {
-    let mut conn = state.pool.get().await.map_err(utils::internal_error)?;
-    let ids = search_bank_accounts(&state, params, &mut conn).await?;
+    let params = params.into_checked()?;
+    let mut conn = utils::get_conn(&state.pool).await?;
+    let mut embeddings = EmbeddingCache::default();
+    let requested_ids = params.ids.clone();
+    let ids = search_bank_accounts(&state, user.id, params, &mut embeddings, &mut conn)
+        .await?
+        .items;
     let bank_accounts =
         diesel::delete(schema::bank_accounts::table.filter(schema::bank_accounts::id.eq_any(ids)))
             .returning(BankAccount::as_returning())
             .load(&mut conn)
             .await
             .map_err(utils::diesel_error)?;
+    utils::check_ids_found(requested_ids.as_deref(), &bank_accounts, "bank accounts")?;
     Ok(Json(bank_accounts))
 }
 
@@ -220,9 +459,8 @@ async fn delete_matching_bank_accounts(
     ),
     request_body = BankAccountSearchParams,
     responses(
-        (status = 200, description = "Successfully got bank accounts", body = [BankAccount]),
+        (status = 200, description = "Successfully got bank accounts", body = Page<BankAccount>),
         (status = 400, description = "Default JSON elements configured by the user are invalid"),
-        (status = 404, description = "No bank accounts found"),
         (status = 422, description = "Error when parsing a response from a model API"),
         (status = 502, description = "Error when forwarding request to model APIs")
     )
@@ -230,15 +468,165 @@ async fn delete_matching_bank_accounts(
 #[axum::debug_handler]
 async fn get_matching_bank_accounts(
     State(state): State<ToiState>,
+    user: CurrentUser,
     Json(params): Json<BankAccountSearchParams>,
-) -> Result<Json<Vec<BankAccount>>, (StatusCode, String)> {
-    let mut conn = state.pool.get().await.map_err(utils::internal_error)?;
-    let ids = search_bank_accounts(&state, params, &mut conn).await?;
+) -> Result<Json<Page<BankAccount>>, (StatusCode, String)> {
+    let mut conn = utils::get_conn(&state.pool).await?;
+    let mut embeddings = EmbeddingCache::default();
+    let Page {
+        items: ids,
+        total,
+        offset,
+        limit,
+    } = search_bank_accounts(&state, user.id, params, &mut embeddings, &mut conn).await?;
     let bank_accounts = schema::bank_accounts::table
         .select(BankAccount::as_select())
         .filter(schema::bank_accounts::id.eq_any(ids))
         .load(&mut conn)
         .await
         .map_err(utils::diesel_error)?;
-    Ok(Json(bank_accounts))
+    Ok(Json(Page {
+        items: bank_accounts,
+        total,
+        offset,
+        limit,
+    }))
+}
+
+/// Get a bank account's balance.
+///
+/// Example queries for getting a bank account balance using this endpoint:
+/// - What's my checking account balance?
+/// - How much money is in my savings account?
+/// - How much did I spend from my account last month?
+/// - What's the net change in my account this year?
+#[utoipa::path(
+    get,
+    path = "/balance",
+    extensions(
+        ("x-json-schema-params" = json!(schema_for!(BankAccountBalanceParams)))
+    ),
+    params(BankAccountBalanceParams),
+    responses(
+        (status = 200, description = "Successfully got bank account balance", body = BankAccountBalance),
+        (status = 400, description = "Default JSON elements configured by the user are invalid"),
+        (status = 404, description = "Bank account not found"),
+        (status = 422, description = "Error when parsing a response from a model API"),
+        (status = 502, description = "Error when forwarding request to model APIs")
+    )
+)]
+#[axum::debug_handler]
+async fn get_bank_account_balance(
+    State(state): State<ToiState>,
+    user: CurrentUser,
+    Query(params): Query<BankAccountBalanceParams>,
+) -> Result<Json<BankAccountBalance>, (StatusCode, String)> {
+    let mut conn = utils::get_conn(&state.pool).await?;
+    let mut embeddings = EmbeddingCache::default();
+    let BankAccountBalanceParams {
+        bank_account_id,
+        bank_account_query,
+        bank_account_use_reranking_filter,
+        bank_account_created_from,
+        bank_account_created_to,
+        bank_account_order_by,
+        posted_from,
+        posted_to,
+    } = params;
+    let bank_account_query_params = BankAccountSearchParams {
+        ids: bank_account_id.map(|i| vec![i]),
+        query: bank_account_query,
+        use_reranking_filter: bank_account_use_reranking_filter,
+        created_from: bank_account_created_from,
+        created_to: bank_account_created_to,
+        order_by: bank_account_order_by,
+        limit: Some(1),
+        offset: None,
+    };
+    let bank_account_id = search_bank_accounts(
+        &state,
+        user.id,
+        bank_account_query_params,
+        &mut embeddings,
+        &mut conn,
+    )
+    .await?
+    .items
+    .into_iter()
+    .next()
+    .ok_or((StatusCode::NOT_FOUND, "bank account not found".to_string()))?;
+    let bank_account = schema::bank_accounts::table
+        .select(BankAccount::as_select())
+        .filter(schema::bank_accounts::id.eq(bank_account_id))
+        .first(&mut conn)
+        .await
+        .map_err(utils::diesel_error)?;
+
+    // Aggregate transactions in SQL rather than loading them all.
+    let mut sql_query = schema::transactions::table
+        .select((
+            diesel::dsl::sum(schema::transactions::amount),
+            diesel::dsl::count(schema::transactions::id),
+            diesel::dsl::min(schema::transactions::posted_at),
+            diesel::dsl::max(schema::transactions::posted_at),
+        ))
+        .filter(schema::transactions::bank_account_id.eq(bank_account.id))
+        .into_boxed();
+
+    // Filter items posted on or after date.
+    if let Some(posted_from) = posted_from {
+        sql_query = sql_query.filter(schema::transactions::posted_at.ge(posted_from));
+    }
+
+    // Filter items posted on or before date.
+    if let Some(posted_to) = posted_to {
+        sql_query = sql_query.filter(schema::transactions::posted_at.le(posted_to));
+    }
+
+    let (balance, transaction_count, earliest_posted_at, latest_posted_at): (
+        Option<f32>,
+        i64,
+        Option<DateTime<Utc>>,
+        Option<DateTime<Utc>>,
+    ) = sql_query
+        .first(&mut conn)
+        .await
+        .map_err(utils::diesel_error)?;
+    let bank_account_balance = BankAccountBalance {
+        bank_account,
+        balance: balance.unwrap_or_default(),
+        transaction_count,
+        earliest_posted_at,
+        latest_posted_at,
+    };
+    Ok(Json(bank_account_balance))
+}
+
+/// Get a bank account using its database-generated ID.
+#[utoipa::path(
+    get,
+    path = "/{id}",
+    params(
+        ("id" = i32, Path, description = "Database-generated bank account ID")
+    ),
+    responses(
+        (status = 200, description = "Successfully got a bank account", body = BankAccount),
+        (status = 404, description = "Bank account not found")
+    )
+)]
+#[axum::debug_handler]
+async fn get_bank_account(
+    State(state): State<ToiState>,
+    user: CurrentUser,
+    Path(id): Path<i32>,
+) -> Result<Json<BankAccount>, (StatusCode, String)> {
+    let mut conn = utils::get_conn(&state.pool).await?;
+    let bank_account = schema::bank_accounts::table
+        .select(BankAccount::as_select())
+        .filter(schema::bank_accounts::id.eq(id))
+        .filter(schema::bank_accounts::user_id.eq(user.id))
+        .first(&mut conn)
+        .await
+        .map_err(utils::diesel_error)?;
+    Ok(Json(bank_account))
 }
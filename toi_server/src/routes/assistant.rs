@@ -1,17 +1,50 @@
-use axum::{body::Body, extract::State, http::StatusCode, response::Json};
+use axum::{
+    body::{Body, Bytes},
+    extract::{Extension, Path, State},
+    http::{HeaderName, StatusCode},
+    response::{IntoResponse, Json, Response},
+};
+use chrono::{DateTime, TimeDelta, Utc};
+use futures::{StreamExt, TryStreamExt};
+use pgvector::Vector;
+use serde::de::DeserializeOwned;
+use sha2::{Digest, Sha256};
+use std::time::{Duration, Instant};
 use toi::{GenerationRequest, Message, MessageRole};
-use tracing::{debug, info, warn};
+use tokio::sync::mpsc;
+use tracing::{Instrument, debug, field, info, info_span, warn};
 use utoipa::openapi::OpenApi;
 use utoipa_axum::{router::OpenApiRouter, routes};
+use uuid::Uuid;
 
 use crate::{
+    auth::CurrentUser,
     models::{
-        assistant::{GeneratedCommandExtraction, GeneratedRequest, parse_generated_response},
-        client::{ApiClientError, EmbeddingPromptTemplate, EmbeddingRequest, RerankRequest},
+        assistant::{
+            AssistantCompletion, ChatResponse, GeneratedClarifiableRequest,
+            GeneratedCommandExtraction, GeneratedConfirmation, GeneratedRequest, NewChatResponse,
+            PlanReport, StepStatus, parse_generated_response,
+        },
+        audit::{AuditPurpose, NewGenerationAudit},
+        client::{
+            ApiClientError, EmbeddingRequest, RerankRequest, StreamingGenerationRequest, TokenUsage,
+        },
+        conversations::{collect_streamed_content, collect_streamed_usage},
+        error::ToiError,
         openapi::{NewSearchableOpenApiPathItem, OpenApiPathItem, SearchableOpenApiPathItem},
-        prompts::{CommandPrompt, HttpRequestPrompt, SimplePrompt, SummaryPrompt, SystemPrompt},
+        pending_actions::{NewPendingAction, PendingAction},
+        prompts::{
+            ClarificationPrompt, CommandPrompt, ConfirmationPrompt, HttpRequestPrompt,
+            PendingActionPrompt, SimplePrompt, SummaryPrompt, SystemPrompt,
+        },
+        sampling::Sampling,
         state::ToiState,
     },
+    request_id::RequestId,
+    routes::{
+        audit::record_generation,
+        conversations::{append_messages, load_messages},
+    },
     schema, utils,
 };
 
@@ -19,22 +52,25 @@ use crate::{
 const INSTRUCTION_PREFIX: &str = "Instruction: Given a user query, retrieve RESTful API descriptions based on the command within the user's query";
 const QUERY_PREFIX: &str = "Query: ";
 
+// Reminder added when retrying model calls whose structured output couldn't
+// be parsed.
+const JSON_REMINDER: &str = "Your last response couldn't be parsed. Respond with only the JSON object, without code fences or any other text.";
+
+/// Response header with the ID a streamed reply is kept under, so the reply
+/// can be fetched again if the response stream is lost.
+pub static RESPONSE_ID_HEADER: HeaderName = HeaderName::from_static("x-toi-response-id");
+
 pub async fn assistant_router(
     openapi: &mut OpenApi,
     state: ToiState,
 ) -> Result<OpenApiRouter, Box<dyn std::error::Error>> {
-    use diesel_async::RunQueryDsl;
-    let mut conn = state.pool.get().await?;
+    use diesel_async::{AsyncConnection, RunQueryDsl, scoped_futures::ScopedFutureExt};
 
-    // Go through and embed all OpenAPI path specs so they can be used as
+    // Go through and collect all OpenAPI path specs so they can be used as
     // context for generating HTTP requests within the /assistant endpoint.
-    // Start by deleting all the pre-existing OpenAPI path specs just in
-    // case there are any updates.
     info!("preparing OpenAPI endpoints for automation");
-    let mut new_searchable_openapi_path_items = vec![];
-    diesel::delete(schema::openapi::table)
-        .execute(&mut conn)
-        .await?;
+    let start = Instant::now();
+    let mut openapi_path_items = vec![];
     for (path, item) in &mut openapi.paths.paths {
         // Parameterized paths are not supported by the /assistant endpoint.
         if !path.contains('{') {
@@ -107,10 +143,6 @@ pub async fn assistant_router(
                         }
                     }
 
-                    // Assuming that each line in the endpoint's docstring has
-                    // a more unique string that might better match up to a user's
-                    // query, each line in an endpoint's docstring is used as a
-                    // separate embedding.
                     info!("adding uri={path} method={method}");
                     let new_openapi_path_item = OpenApiPathItem {
                         path: path.to_string(),
@@ -119,206 +151,1381 @@ pub async fn assistant_router(
                         params,
                         body,
                     };
-                    let parent_id = diesel::insert_into(schema::openapi::table)
-                        .values(&new_openapi_path_item)
-                        .returning(schema::openapi::id)
-                        .get_result(&mut conn)
-                        .await?;
-                    for description in descriptions {
-                        debug!("processing line='{description}'");
-                        let embedding_request = EmbeddingRequest {
-                            input: description.clone(),
-                        };
-                        let embedding = state
-                            .model_client
-                            .embed(embedding_request)
-                            .await
-                            .map_err(|(_, err)| err)?;
-                        new_searchable_openapi_path_items.push(NewSearchableOpenApiPathItem {
-                            parent_id,
-                            description,
-                            embedding,
-                        });
-                    }
+                    openapi_path_items.push((new_openapi_path_item, descriptions));
                 }
             }
         }
     }
-    diesel::insert_into(schema::searchable_openapi::table)
-        .values(&new_searchable_openapi_path_items)
-        .execute(&mut conn)
+
+    // Assuming that each line in an endpoint's docstring has a more unique
+    // string that might better match up to a user's query, each line in an
+    // endpoint's docstring is used as a separate embedding. Lines are
+    // embedded concurrently since there are a lot of them.
+    let lines: Vec<(usize, String)> = openapi_path_items
+        .iter()
+        .enumerate()
+        .flat_map(|(i, (_, descriptions))| {
+            descriptions
+                .iter()
+                .map(move |description| (i, description.clone()))
+        })
+        .collect();
+    let embedded_lines: Vec<(usize, String, Vector)> = futures::stream::iter(lines)
+        .map(|(i, description)| {
+            let state = &state;
+            async move {
+                debug!("processing line='{description}'");
+                let embedding_request = EmbeddingRequest {
+                    input: description.clone(),
+                };
+                let embedding = state.model_client.embed(embedding_request).await?;
+                Ok::<_, ToiError>((i, description, embedding))
+            }
+        })
+        .buffer_unordered(state.server_config.embedding_concurrency.max(1))
+        .try_collect()
         .await?;
 
+    // Replace all the pre-existing OpenAPI path specs just in case there are
+    // any updates. This is done in one transaction so a failure partway
+    // through doesn't leave only some of the endpoints searchable.
+    let num_endpoints = openapi_path_items.len();
+    let num_lines = embedded_lines.len();
+    let mut conn = state.pool.get().await?;
+    conn.transaction(|conn| {
+        async move {
+            diesel::delete(schema::openapi::table).execute(conn).await?;
+            let mut parent_ids = Vec::with_capacity(openapi_path_items.len());
+            for (new_openapi_path_item, _) in &openapi_path_items {
+                let parent_id: i32 = diesel::insert_into(schema::openapi::table)
+                    .values(new_openapi_path_item)
+                    .returning(schema::openapi::id)
+                    .get_result(conn)
+                    .await?;
+                parent_ids.push(parent_id);
+            }
+            let new_searchable_openapi_path_items: Vec<NewSearchableOpenApiPathItem> =
+                embedded_lines
+                    .into_iter()
+                    .map(|(i, description, embedding)| NewSearchableOpenApiPathItem {
+                        parent_id: parent_ids[i],
+                        description,
+                        embedding,
+                    })
+                    .collect();
+            diesel::insert_into(schema::searchable_openapi::table)
+                .values(&new_searchable_openapi_path_items)
+                .execute(conn)
+                .await?;
+            Ok::<_, diesel::result::Error>(())
+        }
+        .scope_boxed()
+    })
+    .await?;
     drop(conn);
+    info!(
+        "embedded {num_lines} descriptions of {num_endpoints} endpoints in {:.2?}",
+        start.elapsed()
+    );
+
+    // Capabilities are rebuilt from the replaced endpoints the next time
+    // they're asked for.
+    state.capabilities.clear();
 
     let router = OpenApiRouter::new()
         .routes(routes!(assist))
+        .routes(routes!(complete))
+        .routes(routes!(get_chat_response))
         .with_state(state);
 
     Ok(router)
 }
 
-#[utoipa::path(
-    post,
-    path = "",
-    request_body = GenerationRequest,
-    responses(
-        (status = 200, description = "Successfully got a response"),
-        (status = 400, description = "Default JSON elements configured by the user are invalid"),
-        (status = 422, description = "Error when parsing a response from a model API"),
-        (status = 502, description = "Error when forwarding request to model APIs")
+/// Streamed generation that's audited once its response stream finishes.
+struct StreamedGeneration {
+    new_generation_audit: NewGenerationAudit,
+    start: Instant,
+    /// Usage from model calls made before the stream started, which the
+    /// stream's reported usage includes.
+    prior_usage: TokenUsage,
+}
+
+/// Forward a streamed response while also collecting it so the assistant's
+/// reply can be kept under its response ID, added to the conversation, and
+/// audited once the stream ends. Whatever was streamed is kept under the
+/// response ID even if the stream fails or the client disconnects early, but
+/// it's only added to the conversation and audited if the stream finished.
+fn persist_streamed_reply(
+    state: ToiState,
+    user_id: i32,
+    response_id: Option<String>,
+    conversation: Option<(i32, Vec<Message>)>,
+    streamed_generation: Option<StreamedGeneration>,
+    body: Body,
+) -> Body {
+    if response_id.is_none() && conversation.is_none() && streamed_generation.is_none() {
+        return body;
+    }
+    let reply = match &conversation {
+        Some((conversation_id, _)) => format!("conversation={conversation_id} reply"),
+        None => "reply".to_string(),
+    };
+    let (tx, rx) = mpsc::channel::<Result<Bytes, axum::Error>>(32);
+    tokio::spawn(async move {
+        let mut stream = body.into_data_stream();
+        let mut raw = vec![];
+        let mut complete = true;
+        loop {
+            // Stop reading as soon as the client goes away so the upstream
+            // generation is cancelled instead of waiting on the next chunk.
+            let chunk = tokio::select! {
+                chunk = stream.next() => chunk,
+                () = tx.closed() => {
+                    warn!("client disconnected before {reply} finished");
+                    complete = false;
+                    break;
+                }
+            };
+            let Some(chunk) = chunk else {
+                break;
+            };
+            match chunk {
+                Ok(bytes) => {
+                    raw.extend_from_slice(&bytes);
+                    if tx.send(Ok(bytes)).await.is_err() {
+                        warn!("client disconnected before {reply} finished");
+                        complete = false;
+                        break;
+                    }
+                }
+                Err(err) => {
+                    warn!("response stream for {reply} failed: {err}");
+                    let _ = tx.send(Err(err)).await;
+                    complete = false;
+                    break;
+                }
+            }
+        }
+        drop(tx);
+
+        let content = collect_streamed_content(&raw);
+        if let Some(id) = response_id {
+            let new_chat_response = NewChatResponse {
+                user_id,
+                id: id.clone(),
+                content: content.clone(),
+                complete,
+            };
+            let result = match state.pool.get().await {
+                Ok(mut conn) => insert_chat_response(new_chat_response, &mut conn).await,
+                Err(err) => Err(utils::internal_error(err)),
+            };
+            if let Err(err) = result {
+                warn!("couldn't store response={id}: {err}");
+            }
+        }
+        if !complete {
+            return;
+        }
+        if let Some(StreamedGeneration {
+            new_generation_audit,
+            start,
+            prior_usage,
+        }) = streamed_generation
+        {
+            let usage = collect_streamed_usage(&raw).map(|mut usage| {
+                usage -= prior_usage;
+                usage
+            });
+            let new_generation_audit =
+                new_generation_audit.finish(content.clone(), usage, start.elapsed());
+            record_generation(&state, new_generation_audit);
+        }
+        if let Some((conversation_id, mut messages)) = conversation {
+            messages.push(Message {
+                role: MessageRole::Assistant,
+                content,
+            });
+            let result = match state.pool.get().await {
+                Ok(mut conn) => append_messages(user_id, conversation_id, messages, &mut conn)
+                    .await
+                    .map(|_| ()),
+                Err(err) => Err(utils::internal_error(err)),
+            };
+            if let Err(err) = result {
+                warn!("couldn't store reply for conversation={conversation_id}: {err}");
+            }
+        }
+    });
+    let stream = futures::stream::unfold(rx, |mut rx| async move {
+        rx.recv().await.map(|chunk| (chunk, rx))
+    });
+    Body::from_stream(stream)
+}
+
+pub async fn insert_chat_response(
+    new_chat_response: NewChatResponse,
+    conn: &mut utils::Conn<'_>,
+) -> Result<(), ToiError> {
+    use diesel_async::RunQueryDsl;
+
+    diesel::insert_into(schema::chat_responses::table)
+        .values(new_chat_response)
+        .execute(conn)
+        .await
+        .map_err(utils::diesel_error)?;
+    Ok(())
+}
+
+/// Delete kept replies whose response streams ended before a cutoff,
+/// returning how many were deleted.
+pub async fn purge_chat_responses(
+    cutoff: DateTime<Utc>,
+    conn: &mut utils::Conn<'_>,
+) -> Result<usize, ToiError> {
+    use diesel::{ExpressionMethods, QueryDsl};
+    use diesel_async::RunQueryDsl;
+
+    diesel::delete(schema::chat_responses::table)
+        .filter(schema::chat_responses::created_at.lt(cutoff))
+        .execute(conn)
+        .await
+        .map_err(utils::diesel_error)
+}
+
+/// Periodically delete kept replies older than the retention period.
+pub async fn purge_expired_chat_responses(state: ToiState) {
+    let retention_hours = state.server_config.response_retention_hours;
+    if retention_hours == 0 {
+        return;
+    }
+    let mut interval = tokio::time::interval(Duration::from_secs(3600));
+    loop {
+        interval.tick().await;
+        let cutoff = Utc::now() - TimeDelta::hours(retention_hours.into());
+        let result = match state.pool.get().await {
+            Ok(mut conn) => purge_chat_responses(cutoff, &mut conn).await,
+            Err(err) => Err(utils::internal_error(err)),
+        };
+        match result {
+            Ok(num_purged) => info!("purged {num_purged} expired chat responses"),
+            Err(err) => warn!("couldn't purge expired chat responses: {err}"),
+        }
+    }
+}
+
+/// A destructive request waiting for the user to confirm it.
+pub struct PendingStep {
+    /// Description of the API the request is for.
+    pub description: String,
+    pub request: GeneratedRequest,
+}
+
+/// What happened when trying to fulfill a command.
+enum StepOutcome {
+    /// No API is relevant enough.
+    Unmatched,
+    /// An API request was made.
+    Executed {
+        /// Description of the API the request was made to.
+        description: String,
+        /// Status of the API's response.
+        status: StatusCode,
+        /// The request that was made. Plans that stopped before their first
+        /// step don't have one.
+        request: Option<GeneratedRequest>,
+    },
+    /// The request deletes things, so it wasn't sent.
+    Pending(PendingStep),
+    /// Another API scored too close to tell which the user means, so a
+    /// question for the user was asked instead of making a request.
+    Unclear(String),
+}
+
+/// The API most relevant to a command according to the reranking API.
+struct RelevantApi {
+    id: i32,
+    relevance_score: f64,
+    /// ID of the next most relevant API if its score is within the tie
+    /// margin of the most relevant one's.
+    runner_up_id: Option<i32>,
+}
+
+/// Send a generated request to the server itself as the user, returning the
+/// response's status and content.
+async fn send_generated_request(
+    state: &ToiState,
+    user: &CurrentUser,
+    generated_request: &GeneratedRequest,
+    request_id: Option<RequestId>,
+    idempotency_key: Option<&str>,
+) -> Result<(StatusCode, String), ToiError> {
+    // Only requests for the server's own endpoints are sent.
+    let (path, method) = generated_request.endpoint();
+    let mut conn = utils::get_conn(&state.pool).await?;
+    let is_known_endpoint = {
+        use diesel::{ExpressionMethods, QueryDsl};
+        use diesel_async::RunQueryDsl;
+
+        diesel::select(diesel::dsl::exists(
+            schema::openapi::table
+                .filter(schema::openapi::path.eq(path))
+                .filter(schema::openapi::method.eq(method)),
+        ))
+        .get_result::<bool>(&mut conn)
+        .await
+        .map_err(utils::diesel_error)?
+    };
+    drop(conn);
+    if !is_known_endpoint {
+        return Err(ToiError::ModelApi(format!(
+            "generated request is for an unknown endpoint (uri={path} method={method})"
+        )));
+    }
+
+    let http_request = generated_request.to_localhost_http_request(
+        &state.api_client,
+        &state.server_config.bind_addr.port(),
+        request_id,
+        idempotency_key,
+        user.authorization.as_ref(),
+    )?;
+    debug!("sending proxy API request");
+    let response = state
+        .api_client
+        .execute(http_request)
+        .await
+        .map_err(|err| ApiClientError::ApiConnection.into_response(&err))?;
+    debug!("receiving proxy API response");
+    let status = response.status();
+    let content = response
+        .text()
+        .await
+        .unwrap_or_else(|err| format!("{err:?}"));
+    Ok((status, content))
+}
+
+/// Search for the APIs most similar to a command, only considering APIs
+/// under a path prefix if one is given, and rerank them. Returns the most
+/// relevant API along with the runner-up if they're nearly tied, or `None`
+/// if there aren't any APIs to consider.
+async fn most_relevant_api(
+    state: &ToiState,
+    command: &str,
+    embedding: Vector,
+    path_prefix: Option<&str>,
+) -> Result<Option<RelevantApi>, ToiError> {
+    let mut conn = utils::get_conn(&state.pool).await?;
+    let mut items: Vec<(SearchableOpenApiPathItem, f64)> = {
+        use diesel::{
+            BoolExpressionMethods, ExpressionMethods, QueryDsl, SelectableHelper,
+            TextExpressionMethods,
+        };
+        use diesel_async::RunQueryDsl;
+        use pgvector::VectorExpressionMethods;
+
+        // Each line of an API's description is embedded separately, so only
+        // the closest line of each API is kept so one API's lines can't
+        // crowd out other APIs.
+        let distance = || schema::searchable_openapi::embedding.cosine_distance(embedding.clone());
+        let mut query = schema::searchable_openapi::table
+            .inner_join(schema::openapi::table)
+            .select((SearchableOpenApiPathItem::as_select(), distance()))
+            .distinct_on(schema::searchable_openapi::parent_id)
+            .order((schema::searchable_openapi::parent_id, distance()))
+            .into_boxed();
+        if let Some(path_prefix) = path_prefix {
+            let pattern = format!(
+                "{}/%",
+                path_prefix
+                    .replace('\\', "\\\\")
+                    .replace('%', "\\%")
+                    .replace('_', "\\_")
+            );
+            query = query.filter(
+                schema::openapi::path
+                    .eq(path_prefix)
+                    .or(schema::openapi::path.like(pattern)),
+            );
+        }
+        query.load(&mut conn).await.map_err(utils::diesel_error)?
+    };
+    drop(conn);
+    if items.is_empty() {
+        return Ok(None);
+    }
+    items.sort_by(|(_, distance), (_, other_distance)| distance.total_cmp(other_distance));
+    items.truncate(state.server_config.api_candidate_limit);
+
+    // Rerank the results and reevaluate to see if they're relevant.
+    debug!("reranking API search results for relevance");
+    let (ids, documents): (Vec<i32>, Vec<String>) = items
+        .into_iter()
+        .map(|(item, _)| (item.parent_id, item.description))
+        .unzip();
+    let rerank_request = RerankRequest {
+        query: command.to_string(),
+        documents,
+    };
+    let rerank_response = state.model_client.rerank(rerank_request).await?;
+    let mut results = rerank_response
+        .results
+        .iter()
+        .filter(|result| result.index < ids.len());
+    let most_relevant_result = results.next().ok_or_else(|| {
+        let err = "rerank results are empty or out of range";
+        ApiClientError::ResponseJson.into_response(&err)
+    })?;
+
+    // The runner-up is only kept if it's too close to call so the request
+    // can ask the user which one they mean.
+    let runner_up_id = results
+        .next()
+        .filter(|result| {
+            most_relevant_result.relevance_score - result.relevance_score
+                <= state.server_config.api_tie_margin
+        })
+        .map(|result| ids[result.index]);
+    Ok(Some(RelevantApi {
+        id: ids[most_relevant_result.index],
+        relevance_score: most_relevant_result.relevance_score,
+        runner_up_id,
+    }))
+}
+
+/// Find the API most relevant to a command and, if it's relevant enough,
+/// generate and execute a request to it. The generated request and its
+/// response are added to the context so they can be summarized (or used to
+/// generate the next request of a plan).
+///
+/// If another API is nearly as relevant, the model can ask the user which
+/// one they mean rather than generating a request.
+///
+/// Requests that delete things aren't sent if destructive requests need to
+/// be confirmed. The items they'd delete are searched for and added to the
+/// context instead so the user can see what they're confirming.
+///
+/// The request is sent with the step's idempotency key so retrying the turn
+/// doesn't add the same items again.
+async fn execute_step(
+    state: &ToiState,
+    user: &CurrentUser,
+    command: String,
+    endpoint_hint: Option<&str>,
+    messages: &mut Vec<Message>,
+    usage: &mut TokenUsage,
+    request_id: Option<RequestId>,
+    idempotency_key: &str,
+) -> Result<StepOutcome, ToiError> {
+    debug!("embedding message for API search");
+    let input = state
+        .embedding_instructions
+        .template("chat", INSTRUCTION_PREFIX, QUERY_PREFIX)
+        .apply(&command);
+    let embedding_request = EmbeddingRequest { input };
+    let embedding = state.model_client.embed(embedding_request).await?;
+
+    // Only APIs under the hinted path are considered unless none of them are
+    // relevant enough.
+    let hinted_api = match endpoint_hint {
+        Some(path_prefix) => {
+            debug!("searching APIs under {path_prefix}");
+            most_relevant_api(state, &command, embedding.clone(), Some(path_prefix))
+                .await?
+                .filter(|api| api.relevance_score >= state.server_config.similarity_threshold)
+        }
+        None => None,
+    };
+    if endpoint_hint.is_some() && hinted_api.is_none() {
+        info!("no APIs under the endpoint hint are relevant, searching all APIs");
+    }
+    let RelevantApi {
+        id: parent_id,
+        relevance_score,
+        runner_up_id,
+    } = match hinted_api {
+        Some(api) => api,
+        None => most_relevant_api(state, &command, embedding, None)
+            .await?
+            .ok_or_else(|| ToiError::NotFound("no APIs to search".to_string()))?,
+    };
+
+    let mut conn = utils::get_conn(&state.pool).await?;
+    let item: OpenApiPathItem = {
+        use diesel::{ExpressionMethods, QueryDsl, SelectableHelper};
+        use diesel_async::RunQueryDsl;
+
+        schema::openapi::table
+            .select(OpenApiPathItem::as_select())
+            .filter(schema::openapi::id.eq(parent_id))
+            .first(&mut conn)
+            .await
+            .map_err(utils::diesel_error)?
+    };
+    let alternative: Option<String> = match runner_up_id {
+        Some(runner_up_id) => {
+            use diesel::{ExpressionMethods, QueryDsl};
+            use diesel_async::RunQueryDsl;
+
+            schema::openapi::table
+                .select(schema::openapi::description)
+                .filter(schema::openapi::id.eq(runner_up_id))
+                .first(&mut conn)
+                .await
+                .map(Some)
+                .map_err(utils::diesel_error)?
+        }
+        None => None,
+    };
+    drop(conn);
+
+    info!(
+        "most relevant API (uri={} method={}) scored at {:.3}",
+        item.path, item.method, relevance_score
+    );
+    tracing::Span::current()
+        .record("api_method", item.method.as_str())
+        .record("api_path", item.path.as_str())
+        .record("rerank_score", relevance_score);
+    if relevance_score < state.server_config.similarity_threshold {
+        return Ok(StepOutcome::Unmatched);
+    }
+    debug!("API passes similarity threshold");
+
+    // Convert user request into HTTP request.
+    let OpenApiPathItem {
+        path,
+        method,
+        description,
+        params,
+        body,
+    } = item;
+    let new_generation_audit = NewGenerationAudit::builder()
+        .user_id(user.id)
+        .purpose(AuditPurpose::RequestGeneration)
+        .api_path(path.clone())
+        .api_method(method.clone())
+        .rerank_score(relevance_score);
+    let system_prompt = HttpRequestPrompt::builder()
+        .path(path)
+        .method(method)
+        .maybe_params(params)
+        .maybe_body(body)
+        .maybe_alternative(alternative)
+        .now(Utc::now())
+        .timezone(state.server_config.timezone)
+        .build();
+    let generation_request = state.server_config.structured_sampling.apply(
+        GenerationRequest::builder()
+            .messages(system_prompt.to_messages(messages))
+            .response_format(system_prompt.into_response_format())
+            .build(),
+    );
+    let new_generation_audit = new_generation_audit
+        .system_prompt_hash(system_prompt_hash(&generation_request.messages))
+        .build();
+    debug!("preparing proxy API request");
+    let generated: GeneratedClarifiableRequest =
+        generate_parsed(state, generation_request, new_generation_audit, usage).await?;
+    debug!("proxy API request={:?}", generated);
+    if let Some(clarification) = generated.clarification() {
+        info!("asking which API is meant instead of making a request");
+        return Ok(StepOutcome::Unclear(clarification.to_string()));
+    }
+    let generated_request = generated.request;
+
+    // Add the HTTP request to the context as an assistant message.
+    let assistant_message = generated_request.clone().into_assistant_message();
+    messages.push(assistant_message);
+
+    // Hold onto destructive requests until the user confirms them.
+    if state.server_config.confirm_destructive && generated_request.is_destructive() {
+        info!("holding destructive request for confirmation");
+        let content = match generated_request.to_preview() {
+            Some(preview) => {
+                let (status, content) =
+                    send_generated_request(state, user, &preview, request_id, None).await?;
+                if status.is_success() {
+                    format!(
+                        "The request hasn't been sent yet. These items would be affected:\n{content}"
+                    )
+                } else {
+                    format!(
+                        "The request hasn't been sent yet. The affected items couldn't be found:\n{content}"
+                    )
+                }
+            }
+            None => "The request hasn't been sent yet.".to_string(),
+        };
+        messages.push(Message {
+            role: MessageRole::User,
+            content,
+        });
+        return Ok(StepOutcome::Pending(PendingStep {
+            description,
+            request: generated_request,
+        }));
+    }
+
+    // Execute the HTTP request and add the HTTP response as a pseudo user
+    // response.
+    let (status, content) = send_generated_request(
+        state,
+        user,
+        &generated_request,
+        request_id,
+        Some(idempotency_key),
     )
-)]
-#[axum::debug_handler]
-async fn assist(
-    State(state): State<ToiState>,
-    Json(mut request): Json<GenerationRequest>,
-) -> Result<Body, (StatusCode, String)> {
+    .await?;
+    messages.push(Message {
+        role: MessageRole::User,
+        content,
+    });
+    Ok(StepOutcome::Executed {
+        description,
+        status,
+        request: Some(generated_request),
+    })
+}
+
+/// Execute each step of a plan in order, stopping at the first step that
+/// fails or that needs to be confirmed. Each step's request and response
+/// are added to the context so later steps can use them (e.g., the ID of a
+/// contact added by an earlier step), and a report of how each step went,
+/// including the steps that were skipped, is added at the end. Returns the
+/// descriptions of all the APIs used for summarizing the plan along with the
+/// last request that was made and the step waiting to be confirmed, if any.
+async fn execute_plan(
+    state: &ToiState,
+    user: &CurrentUser,
+    steps: Vec<String>,
+    endpoint_hint: Option<&str>,
+    messages: &mut Vec<Message>,
+    usage: &mut TokenUsage,
+    request_id: Option<RequestId>,
+    turn_hash: &str,
+) -> (String, Option<GeneratedRequest>, Option<PendingStep>) {
+    let num_steps = steps.len();
+    let max_steps = state.server_config.max_plan_steps;
+    if num_steps > max_steps {
+        warn!("only executing the first {max_steps} of {num_steps} steps");
+    }
+    let mut descriptions = vec![];
+    let mut executed_request = None;
+    let mut pending_step = None;
+    let mut report = PlanReport::default();
+    let mut steps = steps.into_iter();
+    for (i, step) in steps.by_ref().take(max_steps).enumerate() {
+        let step_number = i + 1;
+        let span = info_span!(
+            "step",
+            step = step_number,
+            api_method = field::Empty,
+            api_path = field::Empty,
+            rerank_score = field::Empty,
+        );
+        info!(parent: &span, "executing step {step_number} of {num_steps}: {step}");
+        let result = execute_step(
+            state,
+            user,
+            step.clone(),
+            endpoint_hint,
+            messages,
+            usage,
+            request_id,
+            &step_idempotency_key(turn_hash, step_number),
+        )
+        .instrument(span)
+        .await;
+        let status = match result {
+            Ok(StepOutcome::Executed {
+                description,
+                status,
+                request,
+            }) => {
+                descriptions.push(description);
+                executed_request = request;
+                if status.is_success() {
+                    report.push(step, StepStatus::Succeeded);
+                    continue;
+                }
+                StepStatus::Failed(format!("its request failed with status {status}"))
+            }
+            Ok(StepOutcome::Pending(step)) => {
+                descriptions.push(step.description.clone());
+                pending_step = Some(step);
+                StepStatus::Pending
+            }
+            Ok(StepOutcome::Unmatched) => StepStatus::Failed("no API could do it".to_string()),
+            Ok(StepOutcome::Unclear(question)) => StepStatus::Failed(format!(
+                "it's unclear what it's asking for, so the user needs to answer this: {question}"
+            )),
+            Err(err) => StepStatus::Failed(err.to_string()),
+        };
+        warn!("step {step_number} of {num_steps} stopped the plan: {status:?}");
+        report.push(step, status);
+        break;
+    }
+
+    // Steps after the one that stopped the plan and steps past the limit
+    // aren't tried, so the summary is told they were skipped rather than
+    // left to guess from the responses.
+    report.skip(steps);
+    messages.push(Message {
+        role: MessageRole::User,
+        content: report.to_string(),
+    });
+    (descriptions.join("\n\n"), executed_request, pending_step)
+}
+
+/// Hash of the system prompt at the start of a model call's messages so
+/// audited calls can be grouped by the prompt they used.
+fn system_prompt_hash(messages: &[Message]) -> String {
+    let system_prompt = messages
+        .first()
+        .filter(|message| message.role == MessageRole::System)
+        .map_or("", |message| message.content.as_str());
+    hash_message(system_prompt)
+}
+
+/// Make a model call, adding its token usage to the turn's usage and
+/// recording it for auditing.
+async fn generate_audited(
+    state: &ToiState,
+    generation_request: GenerationRequest,
+    new_generation_audit: NewGenerationAudit,
+    usage: &mut TokenUsage,
+) -> Result<String, ToiError> {
+    let start = Instant::now();
+    let mut call_usage = TokenUsage::default();
+    let output = state
+        .model_client
+        .generate(generation_request, &mut call_usage)
+        .await?;
+    *usage += call_usage;
+    let new_generation_audit =
+        new_generation_audit.finish(output.clone(), Some(call_usage), start.elapsed());
+    record_generation(state, new_generation_audit);
+    Ok(output)
+}
+
+/// Make a model call for structured output and parse it. Models sometimes
+/// respond with more than the JSON object they're asked for, so if the output
+/// can't be parsed, the call is retried once with a reminder to only respond
+/// with the JSON object.
+async fn generate_parsed<T: DeserializeOwned>(
+    state: &ToiState,
+    mut generation_request: GenerationRequest,
+    new_generation_audit: NewGenerationAudit,
+    usage: &mut TokenUsage,
+) -> Result<T, ToiError> {
+    let output = generate_audited(
+        state,
+        generation_request.clone(),
+        new_generation_audit.clone(),
+        usage,
+    )
+    .await?;
+    match parse_generated_response(&output) {
+        Ok(parsed) => return Ok(parsed),
+        Err(err) => warn!("retrying generation with unparseable output: {err}"),
+    }
+    generation_request.messages.push(Message {
+        role: MessageRole::System,
+        content: JSON_REMINDER.to_string(),
+    });
+    let output = generate_audited(state, generation_request, new_generation_audit, usage).await?;
+    parse_generated_response(&output)
+}
+
+/// Hex-encoded SHA-256 hash of a message. Pending actions are looked up by
+/// the hash of the message they were generated for so the message itself
+/// doesn't need to be stored.
+#[must_use]
+pub fn hash_message(content: &str) -> String {
+    format!("{:x}", Sha256::digest(content.as_bytes()))
+}
+
+/// Hash every message of a turn, so a retry of the turn (the same messages
+/// sent again) hashes the same.
+fn hash_turn(messages: &[Message]) -> String {
+    hash_message(&serde_json::to_string(messages).expect("messages should be serializable"))
+}
+
+/// Idempotency key for the request made by a step of a turn. Steps are
+/// numbered from 1, and a turn with a single command is a single step.
+fn step_idempotency_key(turn_hash: &str, step_number: usize) -> String {
+    format!("{turn_hash}-{step_number}")
+}
+
+/// Store a destructive request until the user confirms it, replacing any
+/// request already waiting on the same message.
+pub async fn store_pending_action(
+    user_id: i32,
+    conversation_id: Option<i32>,
+    message_hash: String,
+    pending_step: &PendingStep,
+    conn: &mut utils::Conn<'_>,
+) -> Result<(), ToiError> {
+    use diesel::{ExpressionMethods, PgExpressionMethods, QueryDsl};
+    use diesel_async::RunQueryDsl;
+
+    diesel::delete(schema::pending_actions::table)
+        .filter(schema::pending_actions::user_id.eq(user_id))
+        .filter(schema::pending_actions::conversation_id.is_not_distinct_from(conversation_id))
+        .filter(schema::pending_actions::message_hash.eq(&message_hash))
+        .execute(conn)
+        .await
+        .map_err(utils::diesel_error)?;
+    let new_pending_action = NewPendingAction {
+        user_id,
+        conversation_id,
+        message_hash,
+        request: serde_json::to_value(&pending_step.request)
+            .expect("request should be serializable"),
+        description: pending_step.description.clone(),
+    };
+    diesel::insert_into(schema::pending_actions::table)
+        .values(new_pending_action)
+        .execute(conn)
+        .await
+        .map_err(utils::diesel_error)?;
+    Ok(())
+}
+
+/// Remove and return the request waiting on a user's message, if there is
+/// one. Requests older than the TTL are discarded first so stale requests
+/// can't be confirmed, and other users' requests are never taken.
+pub async fn take_pending_action(
+    user_id: i32,
+    conversation_id: Option<i32>,
+    message_hash: &str,
+    ttl: TimeDelta,
+    conn: &mut utils::Conn<'_>,
+) -> Result<Option<PendingAction>, ToiError> {
+    use diesel::{ExpressionMethods, OptionalExtension, PgExpressionMethods, SelectableHelper};
+    use diesel_async::RunQueryDsl;
+
+    diesel::delete(schema::pending_actions::table)
+        .filter(schema::pending_actions::created_at.lt(Utc::now() - ttl))
+        .execute(conn)
+        .await
+        .map_err(utils::diesel_error)?;
+    diesel::delete(schema::pending_actions::table)
+        .filter(schema::pending_actions::user_id.eq(user_id))
+        .filter(schema::pending_actions::conversation_id.is_not_distinct_from(conversation_id))
+        .filter(schema::pending_actions::message_hash.eq(message_hash))
+        .returning(PendingAction::as_returning())
+        .get_result(conn)
+        .await
+        .optional()
+        .map_err(utils::diesel_error)
+}
+
+/// Find the request waiting on the user's previous message and check
+/// whether the user's latest message confirms it.
+async fn confirmed_pending_action(
+    state: &ToiState,
+    user: &CurrentUser,
+    conversation_id: Option<i32>,
+    messages: &[Message],
+    usage: &mut TokenUsage,
+) -> Result<Option<PendingStep>, ToiError> {
+    let Some(previous_message) = messages
+        .iter()
+        .rev()
+        .filter(|message| message.role == MessageRole::User)
+        .nth(1)
+    else {
+        return Ok(None);
+    };
+    let message_hash = hash_message(&previous_message.content);
+    let ttl = TimeDelta::minutes(state.server_config.pending_action_ttl_minutes.into());
+    let mut conn = utils::get_conn(&state.pool).await?;
+    let Some(pending_action) =
+        take_pending_action(user.id, conversation_id, &message_hash, ttl, &mut conn).await?
+    else {
+        return Ok(None);
+    };
+    drop(conn);
+
+    debug!("checking confirmation of pending action");
+    let system_prompt = ConfirmationPrompt {};
+    let generation_request = state.server_config.structured_sampling.apply(
+        GenerationRequest::builder()
+            .messages(system_prompt.to_messages(messages))
+            .response_format(system_prompt.into_response_format())
+            .build(),
+    );
+    let new_generation_audit = NewGenerationAudit::builder()
+        .user_id(user.id)
+        .purpose(AuditPurpose::Classification)
+        .system_prompt_hash(system_prompt_hash(&generation_request.messages))
+        .build();
+    let GeneratedConfirmation { confirmed } =
+        generate_parsed(state, generation_request, new_generation_audit, usage).await?;
+    if !confirmed {
+        info!("pending action wasn't confirmed");
+        return Ok(None);
+    }
+    let request = serde_json::from_value(pending_action.request)
+        .map_err(|err| ApiClientError::ResponseJson.into_response(&err))?;
+    Ok(Some(PendingStep {
+        description: pending_action.description,
+        request,
+    }))
+}
+
+/// What the reply to a turn is generated from once everything the turn asked
+/// for has been done.
+struct PreparedReply {
+    purpose: AuditPurpose,
+    /// Messages to generate the reply from, starting with the reply's system
+    /// prompt.
+    messages: Vec<Message>,
+    sampling: Sampling,
+    /// ID of the stored conversation the turn continues, along with the
+    /// turn's incoming messages so they can be stored with the reply.
+    conversation: Option<(i32, Vec<Message>)>,
+    /// Token usage of the model calls made so far.
+    usage: TokenUsage,
+    /// Last request made to fulfill the turn, if any.
+    executed_request: Option<GeneratedRequest>,
+}
+
+/// Do everything a turn asks for before its reply is generated: search for
+/// relevant APIs, make (or hold onto) requests to them, and pick the prompt
+/// for the reply. Shared by the streaming and non-streaming endpoints so they
+/// handle turns the same way.
+async fn prepare_reply(
+    state: &ToiState,
+    user: &CurrentUser,
+    mut request: GenerationRequest,
+    request_id: Option<RequestId>,
+) -> Result<PreparedReply, ToiError> {
+    // Style instructions are limited so they can't drown out the rules of
+    // the prompts they're added to.
+    let style_instructions = request.style_instructions.take();
+    if let Some(ref style_instructions) = style_instructions {
+        let max_chars = state.server_config.max_style_instructions_chars;
+        if style_instructions.chars().count() > max_chars {
+            return Err(ToiError::Validation(format!(
+                "style instructions are longer than {max_chars} characters"
+            )));
+        }
+    }
+
+    // Sampling parameters only apply to the reply, so they're checked and
+    // set aside before any model calls.
+    let sampling = Sampling::from_request(&request);
+    sampling.validate().map_err(ToiError::Validation)?;
+    let sampling = sampling.or(state.server_config.response_sampling);
+
+    // Endpoint hints are path prefixes, so they're normalized to have a
+    // leading slash and no trailing slash.
+    let endpoint_hint = request
+        .endpoint_hint
+        .take()
+        .map(|endpoint_hint| format!("/{}", endpoint_hint.trim().trim_matches('/')));
+
+    // Replies are given the current datetime so relative dates like
+    // "tomorrow" are resolved correctly.
+    let now = Utc::now();
+    let timezone = state.server_config.timezone;
+
+    // Continue a stored conversation by putting its prior messages before
+    // the incoming ones. The incoming messages are kept aside so they can be
+    // stored along with the reply.
+    let conversation = match request.conversation_id {
+        Some(conversation_id) => {
+            let mut conn = utils::get_conn(&state.pool).await?;
+            let mut messages: Vec<Message> = load_messages(user.id, conversation_id, &mut conn)
+                .await?
+                .into_iter()
+                .map(Message::from)
+                .collect();
+            let incoming_messages = std::mem::take(&mut request.messages);
+            messages.extend(incoming_messages.iter().cloned());
+            request.messages = messages;
+            Some((conversation_id, incoming_messages))
+        }
+        None => None,
+    };
+
+    // Token usage is tracked across all model calls for this turn and
+    // reported along with the reply.
+    let mut usage = TokenUsage::default();
+
+    // A destructive request from the previous turn is only sent if the
+    // user's latest message confirms it.
+    let conversation_id = conversation
+        .as_ref()
+        .map(|(conversation_id, _)| *conversation_id);
+    let confirmed_step = if state.server_config.confirm_destructive {
+        confirmed_pending_action(state, user, conversation_id, &request.messages, &mut usage)
+            .await?
+    } else {
+        None
+    };
+
     // Search across OpenAPI spec paths for relevant endpoints. If none are
     // found, respond like a normal chat assistant. Otherwise, execute an
     // HTTP request to fulfill the user's request.
-    let streaming_generation_request = if let Some(message) = request.messages.last() {
+    let mut pending_step = None;
+    let mut executed_request = None;
+    let (purpose, messages) = if let Some(PendingStep {
+        description,
+        request: generated_request,
+    }) = confirmed_step
+    {
+        info!("executing confirmed pending action");
+        let assistant_message = generated_request.clone().into_assistant_message();
+        request.messages.push(assistant_message);
+        let (_, content) =
+            send_generated_request(state, user, &generated_request, request_id, None).await?;
+        request.messages.push(Message {
+            role: MessageRole::User,
+            content,
+        });
+        executed_request = Some(generated_request);
+        debug!("summarizing API response");
+        let messages = SummaryPrompt::builder()
+            .description(description)
+            .now(now)
+            .timezone(timezone)
+            .maybe_style_instructions(style_instructions.clone())
+            .build()
+            .to_messages(&request.messages);
+        (AuditPurpose::Summary, messages)
+    } else if let Some(message) = request.messages.last() {
         debug!(">> {}", message.content);
+        let message_hash = hash_message(&message.content);
+        let turn_hash = hash_turn(&request.messages);
         let system_prompt = CommandPrompt {};
-        let generation_request = GenerationRequest::builder()
-            .messages(system_prompt.to_messages(&request.messages))
-            .response_format(system_prompt.into_response_format())
+        let generation_request = state.server_config.structured_sampling.apply(
+            GenerationRequest::builder()
+                .messages(system_prompt.to_messages(&request.messages))
+                .response_format(system_prompt.into_response_format())
+                .build(),
+        );
+        let new_generation_audit = NewGenerationAudit::builder()
+            .user_id(user.id)
+            .purpose(AuditPurpose::Classification)
+            .system_prompt_hash(system_prompt_hash(&generation_request.messages))
             .build();
         debug!("preparing extraction request");
-        let generated_command_extraction = state.model_client.generate(generation_request).await?;
-        debug!("parsing extraction request");
-        let generated_command_extraction =
-            parse_generated_response::<GeneratedCommandExtraction>(&generated_command_extraction)?;
+        let generated_command_extraction: GeneratedCommandExtraction =
+            generate_parsed(state, generation_request, new_generation_audit, &mut usage).await?;
         debug!("extraction={:?}", generated_command_extraction);
-        let GeneratedCommandExtraction { command, .. } = generated_command_extraction;
-        if let Some(command) = command {
-            debug!("embedding message for API search");
-            let input = EmbeddingPromptTemplate::builder()
-                .instruction_prefix(INSTRUCTION_PREFIX.to_string())
-                .query_prefix(QUERY_PREFIX.to_string())
-                .build()
-                .apply(&command);
-            let embedding_request = EmbeddingRequest { input };
-            let embedding = state.model_client.embed(embedding_request).await?;
-
-            let mut conn = state.pool.get().await.map_err(utils::internal_error)?;
-            let items: Vec<SearchableOpenApiPathItem> = {
-                use diesel::{QueryDsl, SelectableHelper};
-                use diesel_async::RunQueryDsl;
-                use pgvector::VectorExpressionMethods;
-
-                // There should always be some items returned here.
-                schema::searchable_openapi::table
-                    .select(SearchableOpenApiPathItem::as_select())
-                    .order(schema::searchable_openapi::embedding.cosine_distance(embedding))
-                    .limit(16)
-                    .load(&mut conn)
-                    .await
-                    .expect("should have some API items")
-            };
-            // Rerank the results and reevaluate to see if they're relevant.
-            debug!("reranking API search results for relevance");
-            let (mut ids, documents): (Vec<i32>, Vec<String>) = items
-                .into_iter()
-                .map(|item| (item.parent_id, item.description))
-                .unzip();
-            let rerank_request = RerankRequest {
-                query: command,
-                documents,
-            };
-            let rerank_response = state.model_client.rerank(rerank_request).await?;
-            let most_relevant_result = &rerank_response.results[0];
-            let parent_id = ids.swap_remove(most_relevant_result.index);
-            let item: OpenApiPathItem = {
-                use diesel::{ExpressionMethods, QueryDsl, SelectableHelper};
-                use diesel_async::RunQueryDsl;
-
-                // There should always be some items returned here.
-                schema::openapi::table
-                    .select(OpenApiPathItem::as_select())
-                    .filter(schema::openapi::id.eq(parent_id))
-                    .first(&mut conn)
-                    .await
-                    .expect("should find API item")
-            };
-
-            info!(
-                "most relevant API (uri={} method={}) scored at {:.3}",
-                item.path, item.method, most_relevant_result.relevance_score
-            );
-            if most_relevant_result.relevance_score >= state.server_config.similarity_threshold {
-                debug!("API passes similarity threshold");
-
-                // Convert user request into HTTP request.
-                let OpenApiPathItem {
-                    path,
-                    method,
+        let GeneratedCommandExtraction { command, steps, .. } = generated_command_extraction;
+        let is_plan = steps.len() > 1;
+        let outcome = if is_plan {
+            debug!("executing plan with {} steps", steps.len());
+            let (description, plan_request, step) = execute_plan(
+                state,
+                user,
+                steps,
+                endpoint_hint.as_deref(),
+                &mut request.messages,
+                &mut usage,
+                request_id,
+                &turn_hash,
+            )
+            .await;
+            match step {
+                Some(step) => {
+                    executed_request = plan_request;
+                    StepOutcome::Pending(step)
+                }
+                None => StepOutcome::Executed {
                     description,
-                    params,
-                    body,
-                } = item;
-                let system_prompt = HttpRequestPrompt {
-                    path,
-                    method,
-                    params,
-                    body,
-                };
-                let generation_request = GenerationRequest::builder()
-                    .messages(system_prompt.to_messages(&request.messages))
-                    .response_format(system_prompt.into_response_format())
-                    .build();
-                debug!("preparing proxy API request");
-                let generated_request = state.model_client.generate(generation_request).await?;
-                debug!("parsing proxy API request");
-                let generated_request =
-                    parse_generated_response::<GeneratedRequest>(&generated_request)?;
-                debug!("proxy API request={:?}", generated_request);
-
-                // Add the HTTP request to the context as an assistant message.
-                let http_request = generated_request.to_localhost_http_request(
-                    &state.api_client,
-                    &state.server_config.bind_addr.port(),
-                );
-                let assistant_message = generated_request.into_assistant_message();
-                request.messages.push(assistant_message);
-
-                // Execute the HTTP request.
-                debug!("sending proxy API request");
-                let response = state
-                    .api_client
-                    .execute(http_request)
-                    .await
-                    .map_err(|err| ApiClientError::ApiConnection.into_response(&err))?;
-                debug!("receiving proxy API response");
-                let content = response
-                    .text()
-                    .await
-                    .unwrap_or_else(|err| format!("{err:?}"));
-
-                // Add the HTTP response as a pseudo user response.
-                request.messages.push(Message {
-                    role: MessageRole::User,
-                    content,
-                });
-                debug!("summarizing API response");
-                SummaryPrompt { description }.to_streaming_generation_request(&request.messages)
-            } else {
-                debug!("no APIs pass similarity threshold");
-                SimplePrompt {}.to_streaming_generation_request(&request.messages)
+                    status: StatusCode::OK,
+                    request: plan_request,
+                },
             }
+        } else if let Some(command) = command {
+            execute_step(
+                state,
+                user,
+                command,
+                endpoint_hint.as_deref(),
+                &mut request.messages,
+                &mut usage,
+                request_id,
+                &step_idempotency_key(&turn_hash, 1),
+            )
+            .await?
         } else {
             warn!("no command found in request");
-            SimplePrompt {}.to_streaming_generation_request(&request.messages)
+            StepOutcome::Unmatched
+        };
+        match outcome {
+            StepOutcome::Executed {
+                description,
+                request: step_request,
+                ..
+            } => {
+                executed_request = step_request;
+                debug!("summarizing API response");
+                let messages = SummaryPrompt::builder()
+                    .description(description)
+                    .now(now)
+                    .timezone(timezone)
+                    .maybe_style_instructions(style_instructions.clone())
+                    .plan(is_plan)
+                    .build()
+                    .to_messages(&request.messages);
+                (AuditPurpose::Summary, messages)
+            }
+            StepOutcome::Pending(step) => {
+                debug!("asking for confirmation of pending action");
+                let messages = PendingActionPrompt {
+                    description: step.description.clone(),
+                    request: serde_json::to_string_pretty(&step.request)
+                        .expect("request should be serializable"),
+                }
+                .to_messages(&request.messages);
+                pending_step = Some((message_hash, step));
+                (AuditPurpose::Summary, messages)
+            }
+            StepOutcome::Unclear(question) => {
+                debug!("asking which API is meant");
+                let messages = ClarificationPrompt::builder()
+                    .question(question)
+                    .maybe_style_instructions(style_instructions.clone())
+                    .build()
+                    .to_messages(&request.messages);
+                (AuditPurpose::Chat, messages)
+            }
+            StepOutcome::Unmatched => {
+                debug!("no APIs pass similarity threshold");
+                let messages = SimplePrompt::builder()
+                    .now(now)
+                    .timezone(timezone)
+                    .maybe_style_instructions(style_instructions.clone())
+                    .build()
+                    .to_messages(&request.messages);
+                (AuditPurpose::Chat, messages)
+            }
         }
     } else {
         warn!("no message found in request");
-        SimplePrompt {}.to_streaming_generation_request(&request.messages)
+        let messages = SimplePrompt::builder()
+            .now(now)
+            .timezone(timezone)
+            .maybe_style_instructions(style_instructions.clone())
+            .build()
+            .to_messages(&request.messages);
+        (AuditPurpose::Chat, messages)
     };
 
+    if let Some((message_hash, step)) = pending_step {
+        let mut conn = utils::get_conn(&state.pool).await?;
+        store_pending_action(user.id, conversation_id, message_hash, &step, &mut conn).await?;
+    }
+
+    Ok(PreparedReply {
+        purpose,
+        messages,
+        sampling,
+        conversation,
+        usage,
+        executed_request,
+    })
+}
+
+#[utoipa::path(
+    post,
+    path = "",
+    request_body = GenerationRequest,
+    responses(
+        (status = 200, description = "Successfully got a response", headers(
+            ("x-toi-response-id" = String, description = "ID the reply is kept under, which isn't sent when resuming a response stream or when replies aren't kept")
+        )),
+        (status = 400, description = "Style instructions are too long, sampling parameters are out of range, the resume offset is past what's been sent, or default JSON elements configured by the user are invalid"),
+        (status = 404, description = "Conversation or resumable response stream not found"),
+        (status = 422, description = "Error when parsing a response from a model API"),
+        (status = 502, description = "Error when forwarding request to model APIs")
+    )
+)]
+#[axum::debug_handler]
+async fn assist(
+    State(state): State<ToiState>,
+    user: CurrentUser,
+    request_id: Option<Extension<RequestId>>,
+    Json(request): Json<GenerationRequest>,
+) -> Result<Response, (StatusCode, String)> {
+    let request_id = request_id.map(|Extension(request_id)| request_id);
+
+    // Pick an interrupted response stream back up rather than generating a
+    // new response. The original stream still stores the reply and audits
+    // the generation. Only the user it was sent to can pick it back up.
+    if let Some(ref resume_point) = request.resume {
+        info!("resuming response stream");
+        return Ok(state
+            .resume_store
+            .resume(user.id, resume_point)?
+            .into_response());
+    }
+
+    let resumable = request.resumable == Some(true);
+    let PreparedReply {
+        purpose,
+        messages,
+        sampling,
+        conversation,
+        usage,
+        ..
+    } = prepare_reply(&state, &user, request, request_id).await?;
+
+    // The response stream is only audited if auditing is enabled since its
+    // output has to be collected as it's forwarded.
+    let streamed_generation = state
+        .server_config
+        .audit_enabled
+        .then(|| StreamedGeneration {
+            new_generation_audit: NewGenerationAudit::builder()
+                .user_id(user.id)
+                .purpose(purpose)
+                .system_prompt_hash(system_prompt_hash(&messages))
+                .build(),
+            start: Instant::now(),
+            prior_usage: usage,
+        });
+
     debug!("beginning response stream");
     let stream = state
         .model_client
-        .generate_stream(streaming_generation_request)
+        .generate_stream(
+            StreamingGenerationRequest::new(messages).with_sampling(sampling),
+            usage,
+            Duration::from_secs(state.server_config.keep_alive_interval),
+        )
         .await?;
-    Ok(stream)
+    // Replies are kept under a response ID so they can be fetched again if
+    // the response stream is lost, unless they're never kept.
+    let response_id =
+        (state.server_config.response_retention_hours > 0).then(|| Uuid::new_v4().to_string());
+    let resume_store = state.resume_store.clone();
+    let body = persist_streamed_reply(
+        state,
+        user.id,
+        response_id.clone(),
+        conversation,
+        streamed_generation,
+        stream,
+    );
+    let body = if resumable {
+        resume_store.track(user.id, body)
+    } else {
+        body
+    };
+    match response_id {
+        Some(response_id) => {
+            Ok(([(RESPONSE_ID_HEADER.clone(), response_id)], body).into_response())
+        }
+        None => Ok(body.into_response()),
+    }
+}
+
+/// Same as the main assistant endpoint, except the reply is returned all at
+/// once as JSON instead of being streamed. Meant for scripts and other
+/// programmatic callers.
+#[utoipa::path(
+    post,
+    path = "/completion",
+    request_body = GenerationRequest,
+    responses(
+        (status = 200, description = "Successfully got a response"),
+        (status = 400, description = "Style instructions are too long, sampling parameters are out of range, the response was asked to be resumable, or default JSON elements configured by the user are invalid"),
+        (status = 404, description = "Conversation not found"),
+        (status = 422, description = "Error when parsing a response from a model API"),
+        (status = 502, description = "Error when forwarding request to model APIs")
+    )
+)]
+#[axum::debug_handler]
+async fn complete(
+    State(state): State<ToiState>,
+    user: CurrentUser,
+    request_id: Option<Extension<RequestId>>,
+    Json(request): Json<GenerationRequest>,
+) -> Result<Json<AssistantCompletion>, ToiError> {
+    let request_id = request_id.map(|Extension(request_id)| request_id);
+
+    // Only response streams can be resumed.
+    if request.resume.is_some() || request.resumable == Some(true) {
+        return Err(ToiError::Validation(
+            "only streamed responses can be resumed".to_string(),
+        ));
+    }
+
+    let PreparedReply {
+        purpose,
+        messages,
+        sampling,
+        conversation,
+        mut usage,
+        executed_request,
+    } = prepare_reply(&state, &user, request, request_id).await?;
+
+    debug!("generating response");
+    let generation_request =
+        sampling.apply(GenerationRequest::builder().messages(messages).build());
+    let new_generation_audit = NewGenerationAudit::builder()
+        .user_id(user.id)
+        .purpose(purpose)
+        .system_prompt_hash(system_prompt_hash(&generation_request.messages))
+        .build();
+    let content =
+        generate_audited(&state, generation_request, new_generation_audit, &mut usage).await?;
+
+    if let Some((conversation_id, mut messages)) = conversation {
+        messages.push(Message {
+            role: MessageRole::Assistant,
+            content: content.clone(),
+        });
+        let mut conn = utils::get_conn(&state.pool).await?;
+        append_messages(user.id, conversation_id, messages, &mut conn).await?;
+    }
+
+    Ok(Json(AssistantCompletion {
+        content,
+        token_usage: usage,
+        executed_request,
+    }))
+}
+
+/// Get a streamed reply by the response ID it was sent with, like when the
+/// client lost the response stream partway through and wants to recover it
+/// without generating it again. Replies are only kept once their response
+/// streams end.
+#[utoipa::path(
+    get,
+    path = "/responses/{id}",
+    params(
+        ("id" = String, Path, description = "Response ID from the `x-toi-response-id` header")
+    ),
+    responses(
+        (status = 200, description = "Successfully got the reply", body = ChatResponse),
+        (status = 404, description = "Reply not found, expired, or its response stream hasn't ended yet")
+    )
+)]
+#[axum::debug_handler]
+async fn get_chat_response(
+    State(state): State<ToiState>,
+    user: CurrentUser,
+    Path(id): Path<String>,
+) -> Result<Json<ChatResponse>, ToiError> {
+    use diesel::{ExpressionMethods, QueryDsl, SelectableHelper};
+    use diesel_async::RunQueryDsl;
+
+    let mut conn = utils::get_conn(&state.pool).await?;
+    let chat_response = schema::chat_responses::table
+        .find(id)
+        .filter(schema::chat_responses::user_id.eq(user.id))
+        .select(ChatResponse::as_select())
+        .first(&mut conn)
+        .await
+        .map_err(utils::diesel_error)?;
+    Ok(Json(chat_response))
 }
@@ -1,12 +1,17 @@
 use axum::{extract::State, http::StatusCode, response::Json};
+use chrono::{DateTime, Utc};
 use diesel::{BoolExpressionMethods, ExpressionMethods, JoinOnDsl, QueryDsl, SelectableHelper};
 use diesel_async::RunQueryDsl;
 use schemars::schema_for;
 use utoipa_axum::{router::OpenApiRouter, routes};
 
 use crate::{
+    auth::CurrentUser,
     models::{
-        attendees::{Attendee, AttendeeSearchParams, Attendees},
+        attendees::{
+            Attendee, AttendeeSearchParams, Attendees, ContactEventSearchParams, ContactEvents,
+        },
+        client::EmbeddingCache,
         contacts::{Contact, ContactSearchParams},
         events::{Event, EventSearchParams},
         state::ToiState,
@@ -20,12 +25,15 @@ pub fn attendees_router(state: ToiState) -> OpenApiRouter {
         .routes(routes!(add_attendees))
         .routes(routes!(delete_matching_attendees))
         .routes(routes!(get_matching_attendees))
+        .routes(routes!(get_contact_events))
         .with_state(state)
 }
 
 pub async fn search_attendees(
     state: &ToiState,
+    user_id: i32,
     params: AttendeeSearchParams,
+    embeddings: &mut EmbeddingCache,
     conn: &mut utils::Conn<'_>,
 ) -> Result<(Event, Vec<i32>), (StatusCode, String)> {
     let AttendeeSearchParams {
@@ -46,15 +54,25 @@ pub async fn search_attendees(
         ids: event_id.map(|i| vec![i]),
         event_day,
         event_day_falls_on,
+        timezone: None,
         query: event_query,
         use_reranking_filter: event_use_reranking_filter,
         created_from: event_created_from,
         created_to: event_created_to,
+        occurs_from: None,
+        occurs_to: None,
+        upcoming_only: None,
+        place_id: None,
+        place_query: None,
+        include_place: None,
         order_by: event_order_by,
         limit: Some(1),
+        offset: None,
+        count_only: None,
     };
-    let event_id = search_events(state, event_query_params, conn)
+    let event_id = search_events(state, user_id, event_query_params, embeddings, conn)
         .await?
+        .items
         .into_iter()
         .next()
         .ok_or((StatusCode::NOT_FOUND, "event not found".to_string()))?;
@@ -68,14 +86,20 @@ pub async fn search_attendees(
         ids: contact_ids,
         birthday: None,
         birthday_falls_on: None,
+        upcoming_within_days: None,
+        timezone: None,
         query: contact_query,
         use_reranking_filter: contact_use_reranking_filter,
         created_from: None,
         created_to: None,
         order_by: None,
         limit: contact_limit,
+        offset: None,
+        count_only: None,
     };
-    let contact_ids = search_contacts(state, contact_query_params, conn).await?;
+    let contact_ids = search_contacts(state, user_id, contact_query_params, embeddings, conn)
+        .await?
+        .items;
     Ok((event, contact_ids))
 }
 
@@ -102,10 +126,13 @@ pub async fn search_attendees(
 #[axum::debug_handler]
 async fn add_attendees(
     State(state): State<ToiState>,
+    user: CurrentUser,
     Json(params): Json<AttendeeSearchParams>,
 ) -> Result<Json<Attendees>, (StatusCode, String)> {
-    let mut conn = state.pool.get().await.map_err(utils::internal_error)?;
-    let (event, contact_ids) = search_attendees(&state, params, &mut conn).await?;
+    let mut conn = utils::get_conn(&state.pool).await?;
+    let mut embeddings = EmbeddingCache::default();
+    let (event, contact_ids) =
+        search_attendees(&state, user.id, params, &mut embeddings, &mut conn).await?;
     let contacts = schema::contacts::table
         .select(Contact::as_select())
         .filter(schema::contacts::id.eq_any(&contact_ids))
@@ -153,10 +180,13 @@ async fn add_attendees(
 #[axum::debug_handler]
 async fn delete_matching_attendees(
     State(state): State<ToiState>,
+    user: CurrentUser,
     Json(params): Json<AttendeeSearchParams>,
 ) -> Result<Json<Attendees>, (StatusCode, String)> {
-    let mut conn = state.pool.get().await.map_err(utils::internal_error)?;
-    let (event, contact_ids) = search_attendees(&state, params, &mut conn).await?;
+    let mut conn = utils::get_conn(&state.pool).await?;
+    let mut embeddings = EmbeddingCache::default();
+    let (event, contact_ids) =
+        search_attendees(&state, user.id, params, &mut embeddings, &mut conn).await?;
     let contacts = schema::contacts::table
         .select(Contact::as_select())
         .inner_join(
@@ -208,10 +238,13 @@ async fn delete_matching_attendees(
 #[axum::debug_handler]
 async fn get_matching_attendees(
     State(state): State<ToiState>,
+    user: CurrentUser,
     Json(params): Json<AttendeeSearchParams>,
 ) -> Result<Json<Attendees>, (StatusCode, String)> {
-    let mut conn = state.pool.get().await.map_err(utils::internal_error)?;
-    let (event, contact_ids) = search_attendees(&state, params, &mut conn).await?;
+    let mut conn = utils::get_conn(&state.pool).await?;
+    let mut embeddings = EmbeddingCache::default();
+    let (event, contact_ids) =
+        search_attendees(&state, user.id, params, &mut embeddings, &mut conn).await?;
     let contacts = schema::contacts::table
         .select(Contact::as_select())
         .inner_join(
@@ -226,3 +259,105 @@ async fn get_matching_attendees(
     let attendees = Attendees { event, contacts };
     Ok(Json(attendees))
 }
+
+/// Get events a contact is attending.
+///
+/// Example queries for getting a contact's events using this endpoint:
+/// - What events is Alice going to?
+/// - What is Bob attending this week?
+/// - Which events will Carol be at?
+/// - Where am I seeing Dave next?
+#[utoipa::path(
+    post,
+    path = "/by-contact",
+    extensions(
+        ("x-json-schema-body" = json!(schema_for!(ContactEventSearchParams)))
+    ),
+    request_body = ContactEventSearchParams,
+    responses(
+        (status = 200, description = "Successfully got a contact's events", body = ContactEvents),
+        (status = 400, description = "Default JSON elements configured by the user are invalid"),
+        (status = 404, description = "No contact found"),
+        (status = 422, description = "Error when parsing a response from a model API"),
+        (status = 502, description = "Error when forwarding request to model APIs")
+    )
+)]
+#[axum::debug_handler]
+async fn get_contact_events(
+    State(state): State<ToiState>,
+    user: CurrentUser,
+    Json(params): Json<ContactEventSearchParams>,
+) -> Result<Json<ContactEvents>, (StatusCode, String)> {
+    let mut conn = utils::get_conn(&state.pool).await?;
+    let mut embeddings = EmbeddingCache::default();
+    let ContactEventSearchParams {
+        contact_id,
+        contact_query,
+        contact_use_reranking_filter,
+        event_occurs_from,
+        event_occurs_to,
+    } = params;
+    let contact_query_params = ContactSearchParams {
+        ids: contact_id.map(|i| vec![i]),
+        birthday: None,
+        birthday_falls_on: None,
+        upcoming_within_days: None,
+        timezone: None,
+        query: contact_query,
+        use_reranking_filter: contact_use_reranking_filter,
+        created_from: None,
+        created_to: None,
+        order_by: None,
+        limit: Some(1),
+        offset: None,
+        count_only: None,
+    };
+    let contact_id = search_contacts(
+        &state,
+        user.id,
+        contact_query_params,
+        &mut embeddings,
+        &mut conn,
+    )
+    .await?
+    .items
+    .into_iter()
+    .next()
+    .ok_or((StatusCode::NOT_FOUND, "contact not found".to_string()))?;
+    let contact = schema::contacts::table
+        .select(Contact::as_select())
+        .filter(schema::contacts::id.eq(contact_id))
+        .first(&mut conn)
+        .await
+        .map_err(utils::diesel_error)?;
+    let mut sql_query = schema::events::table
+        .select(Event::as_select())
+        .inner_join(
+            schema::event_attendees::table
+                .on(schema::event_attendees::event_id.eq(schema::events::id)),
+        )
+        .filter(schema::event_attendees::contact_id.eq(contact_id))
+        .order(schema::events::starts_at)
+        .into_boxed();
+
+    // Repeating events are matched loosely here and then expanded once
+    // they're loaded.
+    if let Some(event_occurs_to) = event_occurs_to {
+        sql_query = sql_query.filter(schema::events::starts_at.le(event_occurs_to));
+    }
+    let events: Vec<Event> = sql_query
+        .load(&mut conn)
+        .await
+        .map_err(utils::diesel_error)?;
+    let events = if event_occurs_from.is_some() || event_occurs_to.is_some() {
+        let window_start = event_occurs_from.unwrap_or(DateTime::<Utc>::MIN_UTC);
+        let window_end = event_occurs_to.unwrap_or(DateTime::<Utc>::MAX_UTC);
+        events
+            .into_iter()
+            .filter(|event| event.occurs_within(window_start, window_end))
+            .collect()
+    } else {
+        events
+    };
+    Ok(Json(ContactEvents { contact, events }))
+}
@@ -0,0 +1,145 @@
+use axum::{
+    extract::{Query, State},
+    response::Json,
+};
+use chrono::{DateTime, Duration, Utc};
+use diesel::{ExpressionMethods, QueryDsl, SelectableHelper};
+use diesel_async::RunQueryDsl;
+use tracing::{info, warn};
+use utoipa_axum::{router::OpenApiRouter, routes};
+
+use crate::{
+    auth::CurrentUser,
+    models::{
+        audit::{AuditQueryParams, GenerationAudit, NewGenerationAudit},
+        error::ToiError,
+        state::ToiState,
+    },
+    schema, utils,
+};
+
+// Max number of entries returned when no limit is given.
+const DEFAULT_LIMIT: i64 = 100;
+
+pub fn audit_router(state: ToiState) -> OpenApiRouter {
+    OpenApiRouter::new()
+        .routes(routes!(get_generation_audits))
+        .with_state(state)
+}
+
+pub async fn insert_generation_audit(
+    new_generation_audit: NewGenerationAudit,
+    conn: &mut utils::Conn<'_>,
+) -> Result<(), ToiError> {
+    diesel::insert_into(schema::generation_audit::table)
+        .values(new_generation_audit)
+        .execute(conn)
+        .await
+        .map_err(utils::diesel_error)?;
+    Ok(())
+}
+
+/// Record a model call in the background if auditing is enabled so the
+/// user's response isn't held up by it.
+pub fn record_generation(state: &ToiState, new_generation_audit: NewGenerationAudit) {
+    if !state.server_config.audit_enabled {
+        return;
+    }
+    let pool = state.pool.clone();
+    tokio::spawn(async move {
+        let result = match pool.get().await {
+            Ok(mut conn) => insert_generation_audit(new_generation_audit, &mut conn).await,
+            Err(err) => Err(utils::internal_error(err)),
+        };
+        if let Err(err) = result {
+            warn!("couldn't record generation audit: {err}");
+        }
+    });
+}
+
+/// Delete audit entries created before a cutoff, returning how many were
+/// deleted.
+pub async fn purge_generation_audits(
+    cutoff: DateTime<Utc>,
+    conn: &mut utils::Conn<'_>,
+) -> Result<usize, ToiError> {
+    diesel::delete(schema::generation_audit::table)
+        .filter(schema::generation_audit::created_at.lt(cutoff))
+        .execute(conn)
+        .await
+        .map_err(utils::diesel_error)
+}
+
+/// Periodically delete audit entries older than the retention period. This
+/// runs even if auditing is disabled so entries from when it was enabled
+/// don't stick around forever.
+pub async fn purge_expired_generation_audits(state: ToiState) {
+    let retention_days = state.server_config.audit_retention_days;
+    if retention_days == 0 {
+        return;
+    }
+    let mut interval = tokio::time::interval(std::time::Duration::from_secs(24 * 3600));
+    loop {
+        interval.tick().await;
+        let cutoff = Utc::now() - Duration::days(retention_days.into());
+        let result = match state.pool.get().await {
+            Ok(mut conn) => purge_generation_audits(cutoff, &mut conn).await,
+            Err(err) => Err(utils::internal_error(err)),
+        };
+        match result {
+            Ok(num_purged) => info!("purged {num_purged} expired generation audits"),
+            Err(err) => warn!("couldn't purge expired generation audits: {err}"),
+        }
+    }
+}
+
+/// Get recent model calls made by the assistant for the user, newest first.
+#[utoipa::path(
+    get,
+    path = "",
+    params(AuditQueryParams),
+    responses(
+        (status = 200, description = "Successfully got generation audits", body = [GenerationAudit])
+    )
+)]
+#[axum::debug_handler]
+async fn get_generation_audits(
+    State(state): State<ToiState>,
+    user: CurrentUser,
+    Query(params): Query<AuditQueryParams>,
+) -> Result<Json<Vec<GenerationAudit>>, ToiError> {
+    let AuditQueryParams {
+        purpose,
+        from,
+        to,
+        limit,
+    } = params;
+    let mut conn = utils::get_conn(&state.pool).await?;
+    let mut sql_query = schema::generation_audit::table
+        .select(GenerationAudit::as_select())
+        .filter(schema::generation_audit::user_id.eq(user.id))
+        .into_boxed();
+
+    // Filter entries by what the model call was for.
+    if let Some(purpose) = purpose {
+        sql_query = sql_query.filter(schema::generation_audit::purpose.eq(purpose));
+    }
+
+    // Filter entries created on or after date.
+    if let Some(from) = from {
+        sql_query = sql_query.filter(schema::generation_audit::created_at.ge(from));
+    }
+
+    // Filter entries created on or before date.
+    if let Some(to) = to {
+        sql_query = sql_query.filter(schema::generation_audit::created_at.le(to));
+    }
+
+    let generation_audits = sql_query
+        .order(schema::generation_audit::created_at.desc())
+        .limit(limit.unwrap_or(DEFAULT_LIMIT))
+        .load(&mut conn)
+        .await
+        .map_err(utils::diesel_error)?;
+    Ok(Json(generation_audits))
+}
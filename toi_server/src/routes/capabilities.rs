@@ -0,0 +1,78 @@
+use axum::{
+    body::Bytes,
+    extract::State,
+    http::header,
+    response::{IntoResponse, Response},
+};
+use diesel::{ExpressionMethods, OptionalExtension, QueryDsl, SelectableHelper};
+use diesel_async::{AsyncPgConnection, RunQueryDsl};
+use pgvector::Vector;
+use toi::{Capabilities, Capability};
+use utoipa_axum::{router::OpenApiRouter, routes};
+
+use crate::{
+    models::{capabilities::domains, error::ToiError, openapi::OpenApiPathItem, state::ToiState},
+    schema, utils,
+};
+
+pub fn capabilities_router(state: ToiState) -> OpenApiRouter {
+    OpenApiRouter::new()
+        .routes(routes!(get_capabilities))
+        .with_state(state)
+}
+
+/// Load the endpoints the assistant can use, which are the ones with
+/// searchable descriptions.
+async fn load_capabilities(conn: &mut AsyncPgConnection) -> Result<Capabilities, ToiError> {
+    let openapi_path_items: Vec<OpenApiPathItem> = schema::openapi::table
+        .select(OpenApiPathItem::as_select())
+        .filter(schema::openapi::id.eq_any(
+            schema::searchable_openapi::table.select(schema::searchable_openapi::parent_id),
+        ))
+        .order((schema::openapi::path, schema::openapi::method))
+        .load(conn)
+        .await?;
+    let embedding: Option<Vector> = schema::searchable_openapi::table
+        .select(schema::searchable_openapi::embedding)
+        .first(conn)
+        .await
+        .optional()?;
+    let endpoints: Vec<Capability> = openapi_path_items
+        .iter()
+        .map(OpenApiPathItem::to_capability)
+        .collect();
+    Ok(Capabilities {
+        version: env!("CARGO_PKG_VERSION").to_string(),
+        domains: domains(&endpoints),
+        embedding_dimensions: embedding.map(|embedding| embedding.as_slice().len()),
+        endpoints,
+    })
+}
+
+/// Get a compact list of the endpoints the assistant can use along with the
+/// server's version and embedding dimensions, for clients that don't need
+/// the full OpenAPI spec.
+#[utoipa::path(
+    get,
+    path = "",
+    responses(
+        (status = 200, description = "Successfully got capabilities", body = Capabilities)
+    )
+)]
+#[axum::debug_handler]
+async fn get_capabilities(State(state): State<ToiState>) -> Result<Response, ToiError> {
+    let body = match state.capabilities.get() {
+        Some(body) => body,
+        None => {
+            let mut conn = utils::get_conn(&state.pool).await?;
+            let capabilities = load_capabilities(&mut conn).await?;
+            let body = Bytes::from(
+                serde_json::to_vec(&capabilities)
+                    .expect("capabilities shouldn't fail to serialize"),
+            );
+            state.capabilities.set(body.clone());
+            body
+        }
+    };
+    Ok(([(header::CONTENT_TYPE, "application/json")], body).into_response())
+}
@@ -1,21 +1,46 @@
-use axum::{extract::State, http::StatusCode, response::Json};
-use chrono::{Datelike, Duration, Month, NaiveDate};
-use diesel::{BoolExpressionMethods, ExpressionMethods, QueryDsl, SelectableHelper};
-use diesel_async::RunQueryDsl;
+use axum::{
+    extract::{DefaultBodyLimit, Path, State},
+    http::{HeaderMap, StatusCode, header},
+    response::{IntoResponse, Json, Response},
+};
+use base64::{Engine, prelude::BASE64_STANDARD};
+use chrono::{Datelike, Duration, Month, NaiveDate, Utc};
+use diesel::{
+    ExpressionMethods, OptionalExtension, QueryDsl, SelectableHelper,
+    dsl::sql,
+    expression::SqlLiteral,
+    sql_types::{Nullable, Text},
+};
+use diesel_async::{
+    AsyncConnection, AsyncPgConnection, RunQueryDsl, scoped_futures::ScopedFutureExt,
+};
 use pgvector::VectorExpressionMethods;
 use schemars::schema_for;
+use std::collections::HashMap;
 use utoipa_axum::{router::OpenApiRouter, routes};
 
 use crate::{
+    auth::CurrentUser,
+    embeddings::EmbeddedTable,
+    ics::{self, CalendarWriter},
+    idempotency::IdempotencyKey,
     models::{
-        client::{EmbeddingPromptTemplate, EmbeddingRequest, RerankRequest},
+        client::{BatchEmbeddingRequest, EmbeddingCache, EmbeddingRequest},
         contacts::{
-            Contact, ContactDeleteParams, ContactSearchParams, NewContact, NewContactRequest,
-            UpdateContactRequest,
+            AVATAR_CONTENT_TYPES, Contact, ContactAvatar, ContactAvatarRequest,
+            ContactDeleteParams, ContactDetail, ContactEmail, ContactImport, ContactPhone,
+            ContactSearchParams, ContactWithDetails, NewContact, NewContactAvatar, NewContactEmail,
+            NewContactPhone, NewContactRequest, SkippedContact, UpdateContactRequest,
+            detect_avatar_content_type,
         },
+        deletion::DeleteParams,
+        error::ToiError,
+        pagination::{Count, Page, SearchResponse},
         state::ToiState,
     },
-    schema, utils,
+    schema,
+    search::{self, RerankOptions},
+    utils, vcard,
 };
 
 // Prefixes are used for embedding instructions.
@@ -23,33 +48,214 @@ const INSTRUCTION_PREFIX: &str =
     "Instruction: Given a user query, find contacts stored with details that the user mentions";
 const QUERY_PREFIX: &str = "Query: ";
 
+// Axum's default request body limit.
+const DEFAULT_BODY_LIMIT: usize = 2 * 1024 * 1024;
+
 pub fn contacts_router(state: ToiState) -> OpenApiRouter {
     OpenApiRouter::new()
         .routes(routes!(add_contact, update_matching_contact))
         .routes(routes!(delete_matching_contacts))
         .routes(routes!(get_matching_contacts))
+        .routes(routes!(get_birthday_calendar))
+        .routes(routes!(import_contacts))
+        .routes(routes!(get_contact))
+        .with_state(state)
+}
+
+/// Routes for contact avatars, which are kept separate from the other contact
+/// routes since they're for images rather than anything the assistant can
+/// use.
+pub fn contact_avatars_router(state: ToiState) -> OpenApiRouter {
+    // Avatars are base64-encoded in requests, which makes them about a third
+    // larger, so requests can be up to twice as large as avatars can be.
+    let body_limit = state
+        .server_config
+        .contact_avatar_max_bytes
+        .saturating_mul(2)
+        .max(DEFAULT_BODY_LIMIT);
+    OpenApiRouter::new()
+        .routes(routes!(set_matching_contact_avatar))
+        .routes(routes!(get_contact_avatar))
+        .layer(DefaultBodyLimit::max(body_limit))
         .with_state(state)
 }
 
+/// Attach each contact's labeled emails and phone numbers, keeping the
+/// contacts in the same order.
+pub async fn load_contact_details(
+    contacts: Vec<Contact>,
+    conn: &mut utils::Conn<'_>,
+) -> Result<Vec<ContactWithDetails>, ToiError> {
+    let ids: Vec<i32> = contacts.iter().map(|contact| contact.id).collect();
+    let emails: Vec<ContactEmail> = schema::contact_emails::table
+        .select(ContactEmail::as_select())
+        .filter(schema::contact_emails::contact_id.eq_any(&ids))
+        .order(schema::contact_emails::id)
+        .load(conn)
+        .await
+        .map_err(utils::diesel_error)?;
+    let phones: Vec<ContactPhone> = schema::contact_phones::table
+        .select(ContactPhone::as_select())
+        .filter(schema::contact_phones::contact_id.eq_any(&ids))
+        .order(schema::contact_phones::id)
+        .load(conn)
+        .await
+        .map_err(utils::diesel_error)?;
+    let mut emails_by_contact: HashMap<i32, Vec<ContactEmail>> = HashMap::new();
+    for email in emails {
+        emails_by_contact
+            .entry(email.contact_id)
+            .or_default()
+            .push(email);
+    }
+    let mut phones_by_contact: HashMap<i32, Vec<ContactPhone>> = HashMap::new();
+    for phone in phones {
+        phones_by_contact
+            .entry(phone.contact_id)
+            .or_default()
+            .push(phone);
+    }
+    let contacts = contacts
+        .into_iter()
+        .map(|contact| ContactWithDetails {
+            emails: emails_by_contact.remove(&contact.id).unwrap_or_default(),
+            phones: phones_by_contact.remove(&contact.id).unwrap_or_default(),
+            contact,
+        })
+        .collect();
+    Ok(contacts)
+}
+
+/// Replace all of a contact's labeled emails. Meant to be called within the
+/// same transaction that adds or updates the contact.
+pub async fn replace_contact_emails(
+    contact_id: i32,
+    emails: Vec<ContactDetail>,
+    conn: &mut AsyncPgConnection,
+) -> diesel::QueryResult<Vec<ContactEmail>> {
+    diesel::delete(
+        schema::contact_emails::table.filter(schema::contact_emails::contact_id.eq(contact_id)),
+    )
+    .execute(conn)
+    .await?;
+    let new_emails: Vec<NewContactEmail> = emails
+        .into_iter()
+        .map(|ContactDetail { label, value }| NewContactEmail {
+            contact_id,
+            label,
+            value,
+        })
+        .collect();
+    diesel::insert_into(schema::contact_emails::table)
+        .values(new_emails)
+        .returning(ContactEmail::as_returning())
+        .get_results(conn)
+        .await
+}
+
+/// Replace all of a contact's labeled phone numbers. Meant to be called
+/// within the same transaction that adds or updates the contact.
+pub async fn replace_contact_phones(
+    contact_id: i32,
+    phones: Vec<ContactDetail>,
+    conn: &mut AsyncPgConnection,
+) -> diesel::QueryResult<Vec<ContactPhone>> {
+    diesel::delete(
+        schema::contact_phones::table.filter(schema::contact_phones::contact_id.eq(contact_id)),
+    )
+    .execute(conn)
+    .await?;
+    let new_phones: Vec<NewContactPhone> = phones
+        .into_iter()
+        .map(|ContactDetail { label, value }| NewContactPhone {
+            contact_id,
+            label,
+            value,
+        })
+        .collect();
+    diesel::insert_into(schema::contact_phones::table)
+        .values(new_phones)
+        .returning(ContactPhone::as_returning())
+        .get_results(conn)
+        .await
+}
+
+/// Contact birthday as `MMDD` so birthdays can be compared without the
+/// year the contact was born.
+fn birthday_key() -> SqlLiteral<Nullable<Text>> {
+    sql::<Nullable<Text>>("to_char(contacts.birthday, 'MMDD')")
+}
+
+/// Birthday keys for every day from `start` to `end`, inclusive. Feb 29
+/// birthdays are celebrated on Feb 28 in non-leap years, so Feb 28 also
+/// matches them in those years.
+fn birthday_keys(start: NaiveDate, end: NaiveDate) -> Vec<String> {
+    let mut keys = vec![];
+    for date in start.iter_days().take_while(|date| *date <= end) {
+        let key = date.format("%m%d").to_string();
+        let is_leap_year = NaiveDate::from_ymd_opt(date.year(), 2, 29).is_some();
+        if key == "0228" && !is_leap_year {
+            keys.push("0229".to_string());
+        }
+        keys.push(key);
+    }
+    keys
+}
+
 pub async fn search_contacts(
     state: &ToiState,
+    user_id: i32,
+    params: ContactSearchParams,
+    embeddings: &mut EmbeddingCache,
+    conn: &mut utils::Conn<'_>,
+) -> Result<Page<i32>, ToiError> {
+    // The total is counted alongside a page's items, so it's counted on
+    // its own when the offset is past the end and there aren't any.
+    let count_params = (params.offset.is_some_and(|offset| offset > 0)
+        && !params.count_only.unwrap_or_default())
+    .then(|| ContactSearchParams {
+        use_reranking_filter: None,
+        limit: None,
+        offset: None,
+        count_only: Some(true),
+        ..params.clone()
+    });
+    let mut page = search_contacts_page(state, user_id, params, embeddings, conn).await?;
+    if page.total == 0
+        && let Some(count_params) = count_params
+    {
+        page.total = search_contacts_page(state, user_id, count_params, embeddings, conn)
+            .await?
+            .total;
+    }
+    Ok(page)
+}
+
+async fn search_contacts_page(
+    state: &ToiState,
+    user_id: i32,
     params: ContactSearchParams,
+    embeddings: &mut EmbeddingCache,
     conn: &mut utils::Conn<'_>,
-) -> Result<Vec<i32>, (StatusCode, String)> {
+) -> Result<Page<i32>, ToiError> {
     let ContactSearchParams {
         ids,
         birthday,
         birthday_falls_on,
+        upcoming_within_days,
+        timezone,
         query,
         use_reranking_filter,
         created_from,
         created_to,
         order_by,
         limit,
+        offset,
+        count_only,
     } = params;
 
     let mut sql_query = schema::contacts::table
-        .select(Contact::as_select())
+        .select((Contact::as_select(), utils::total_count()))
         .into_boxed();
 
     // Filter items created on or after date.
@@ -70,53 +276,60 @@ pub async fn search_contacts(
                 let month = birthday.month();
                 let num_days_in_month = {
                     let month = u8::try_from(month).map_err(|_| {
-                        (
-                            StatusCode::BAD_REQUEST,
-                            "invalid birthday search month".to_string(),
-                        )
+                        ToiError::Validation("invalid birthday search month".to_string())
                     })?;
                     let month = Month::try_from(month).map_err(|_| {
-                        (
-                            StatusCode::BAD_REQUEST,
-                            "invalid birthday search month".to_string(),
-                        )
+                        ToiError::Validation("invalid birthday search month".to_string())
                     })?;
-                    month.num_days(year).ok_or((
-                        StatusCode::BAD_REQUEST,
+                    month.num_days(year).ok_or(ToiError::Validation(
                         "invalid birthday search year".to_string(),
                     ))?
                 };
-                let first_day_of_month = NaiveDate::from_ymd_opt(year, month, 1).ok_or((
-                    StatusCode::BAD_REQUEST,
-                    "invalid birthday search".to_string(),
-                ))?;
+                let first_day_of_month = NaiveDate::from_ymd_opt(year, month, 1)
+                    .ok_or(ToiError::Validation("invalid birthday search".to_string()))?;
                 let last_day_of_month =
-                    NaiveDate::from_ymd_opt(year, month, num_days_in_month.into()).ok_or((
-                        StatusCode::BAD_REQUEST,
-                        "invalid birthday search".to_string(),
-                    ))?;
-                sql_query = sql_query.filter(
-                    schema::contacts::birthday
-                        .ge(first_day_of_month)
-                        .and(schema::contacts::birthday.le(last_day_of_month)),
-                );
+                    NaiveDate::from_ymd_opt(year, month, num_days_in_month.into())
+                        .ok_or(ToiError::Validation("invalid birthday search".to_string()))?;
+                let keys = birthday_keys(first_day_of_month, last_day_of_month);
+                sql_query = sql_query.filter(birthday_key().eq_any(keys));
             }
             Some(utils::DateFallsOn::Week) => {
                 let num_days_from_sunday = birthday.weekday().num_days_from_sunday();
                 let this_weeks_sunday = birthday - Duration::days(num_days_from_sunday.into());
                 let this_weeks_saturday = this_weeks_sunday + Duration::days(6);
-                sql_query = sql_query.filter(
-                    schema::contacts::birthday
-                        .ge(this_weeks_sunday)
-                        .and(schema::contacts::birthday.le(this_weeks_saturday)),
-                );
+                let keys = birthday_keys(this_weeks_sunday, this_weeks_saturday);
+                sql_query = sql_query.filter(birthday_key().eq_any(keys));
             }
-            Some(utils::DateFallsOn::Day) | None => {
+            Some(utils::DateFallsOn::Day) => {
+                let keys = birthday_keys(birthday, birthday);
+                sql_query = sql_query.filter(birthday_key().eq_any(keys));
+            }
+            None => {
                 sql_query = sql_query.filter(schema::contacts::birthday.eq(birthday));
             }
         }
     }
 
+    // Filter items with birthdays coming up soon.
+    if let Some(upcoming_within_days) = upcoming_within_days {
+        if upcoming_within_days < 0 {
+            return Err(ToiError::Validation(
+                "upcoming birthday search days can't be negative".to_string(),
+            ));
+        }
+        let timezone = utils::parse_timezone(timezone.as_deref(), state.server_config.timezone)?;
+        let today = Utc::now().with_timezone(&timezone).date_naive();
+        let keys = birthday_keys(
+            today,
+            today + Duration::days(upcoming_within_days.min(366).into()),
+        );
+        sql_query = sql_query.filter(birthday_key().eq_any(keys));
+    }
+
+    // Items are only ordered by their distance to the query if they aren't
+    // ordered some other way.
+    let ordered_by_distance = order_by.is_none();
+
     // Order items.
     match order_by {
         Some(utils::OrderBy::Oldest) => sql_query = sql_query.order(schema::contacts::created_at),
@@ -126,13 +339,15 @@ pub async fn search_contacts(
         None => {
             // By default, filter items similar to a given query.
             if let Some(ref query) = query {
-                let input = EmbeddingPromptTemplate::builder()
-                    .instruction_prefix(INSTRUCTION_PREFIX.to_string())
-                    .query_prefix(QUERY_PREFIX.to_string())
-                    .build()
+                let input = state
+                    .embedding_instructions
+                    .template("contacts", INSTRUCTION_PREFIX, QUERY_PREFIX)
                     .apply(query);
                 let embedding_request = EmbeddingRequest { input };
-                let embedding = state.model_client.embed(embedding_request).await?;
+                let embedding = state
+                    .model_client
+                    .embed_cached(embedding_request, embeddings)
+                    .await?;
                 sql_query = sql_query
                     .filter(
                         schema::contacts::embedding
@@ -149,57 +364,124 @@ pub async fn search_contacts(
         sql_query = sql_query.or_filter(schema::contacts::id.eq_any(ids));
     }
 
-    // Limit number of items.
-    if let Some(limit) = limit {
+    // Only the user's own items are searched. This comes after the other
+    // filters so items selected by their ids are still only the user's.
+    sql_query = sql_query.filter(schema::contacts::user_id.eq(user_id));
+
+    // Limit number of items. Only the total is needed when counting items
+    // that don't need to be reranked, so only one item is loaded. Items
+    // counted once they're reranked aren't limited so they're all counted.
+    let count_only = count_only.unwrap_or_default();
+    let count_total =
+        count_only && !search::needs_reranking(query.as_deref(), use_reranking_filter);
+    if count_total {
+        sql_query = sql_query.limit(1);
+    } else if let Some(limit) = limit.filter(|_| !count_only) {
         sql_query = sql_query.limit(limit);
     }
 
+    // Skip a number of items.
+    if let Some(offset) = offset.filter(|_| !count_only) {
+        sql_query = sql_query.offset(offset);
+    }
+
     // Get all the items that match the query.
-    let contacts: Vec<Contact> = sql_query.load(conn).await.map_err(utils::diesel_error)?;
-    let (ids, documents): (Vec<i32>, Vec<String>) = contacts
+    let contacts: Vec<(Contact, i64)> = sql_query.load(conn).await.map_err(utils::diesel_error)?;
+    let total = contacts.as_slice().first().map_or(0, |(_, total)| *total);
+    if count_total {
+        return Ok(Page::count(total));
+    }
+    let contacts: Vec<Contact> = contacts.into_iter().map(|(contact, _)| contact).collect();
+    let contacts = load_contact_details(contacts, conn).await?;
+    let mut ids_docs: Vec<(i32, String)> = contacts
         .into_iter()
         .map(|contact| {
-            let Contact {
-                id,
-                first_name,
-                last_name,
-                email,
-                phone,
-                birthday,
-                relationship,
-                ..
-            } = contact;
-            let new_contact_request = NewContactRequest {
-                first_name,
-                last_name,
-                email,
-                phone,
-                birthday,
-                relationship,
-            };
+            let id = contact.contact.id;
+            let new_contact_request = NewContactRequest::from(contact);
             (id, new_contact_request.to_string())
         })
-        .unzip();
-    if ids.is_empty() {
-        return Ok(ids);
+        .collect();
+
+    // Items that aren't ordered by their distance to the query are put in
+    // that order so only the furthest ones are left out of reranking.
+    if !ordered_by_distance
+        && search::needs_reranking(query.as_deref(), use_reranking_filter)
+        && let Some(ref query) = query
+    {
+        let input = state
+            .embedding_instructions
+            .template("contacts", INSTRUCTION_PREFIX, QUERY_PREFIX)
+            .apply(query);
+        ids_docs =
+            search::closest_first(state, EmbeddedTable::Contacts, input, ids_docs, conn).await?;
     }
 
     // Rerank and filter items once more.
-    let ids = match (query, use_reranking_filter) {
-        (Some(query), Some(true)) => {
-            let rerank_request = RerankRequest { query, documents };
-            let rerank_response = state.model_client.rerank(rerank_request).await?;
-            rerank_response
-                .results
-                .into_iter()
-                .filter(|item| item.relevance_score >= state.server_config.similarity_threshold)
-                .map(|item| ids[item.index])
-                .collect()
-        }
-        _ => ids,
-    };
+    let ids = search::rerank_filter(
+        state,
+        query,
+        use_reranking_filter,
+        ids_docs,
+        &RerankOptions::default(),
+    )
+    .await?;
 
-    Ok(ids)
+    if count_only {
+        return Ok(Page::count(ids.len().try_into().unwrap_or(i64::MAX)));
+    }
+
+    Ok(Page {
+        items: ids,
+        total,
+        offset,
+        limit,
+    })
+}
+
+/// Find an existing contact that looks like the same person as a new
+/// contact. Candidates are found by searching for the new contact's name and
+/// are then compared by name similarity and shared emails or phone numbers.
+async fn find_duplicate_contact(
+    state: &ToiState,
+    user_id: i32,
+    params: &NewContactRequest,
+    conn: &mut utils::Conn<'_>,
+) -> Result<Option<ContactWithDetails>, ToiError> {
+    let query = match &params.last_name {
+        Some(last_name) => format!("{} {last_name}", params.first_name),
+        None => params.first_name.clone(),
+    };
+    let search_params = ContactSearchParams::builder()
+        .query(query)
+        .use_reranking_filter(true)
+        .limit(5)
+        .build();
+    let mut embeddings = EmbeddingCache::default();
+    let Page { items: ids, .. } =
+        search_contacts(state, user_id, search_params, &mut embeddings, conn).await?;
+    if ids.is_empty() {
+        return Ok(None);
+    }
+    let contacts = schema::contacts::table
+        .select(Contact::as_select())
+        .filter(schema::contacts::id.eq_any(&ids))
+        .load(conn)
+        .await
+        .map_err(utils::diesel_error)?;
+    let mut contacts: HashMap<i32, ContactWithDetails> = load_contact_details(contacts, conn)
+        .await?
+        .into_iter()
+        .map(|contact| (contact.contact.id, contact))
+        .collect();
+    // Check candidates in the order they were ranked so the best match is
+    // the one returned.
+    let duplicate = ids
+        .iter()
+        .filter_map(|id| contacts.remove(id))
+        .find(|contact| {
+            params.is_duplicate_of(contact, state.server_config.contact_duplicate_similarity)
+        });
+    Ok(duplicate)
 }
 
 /// Add and return a contact.
@@ -216,8 +498,9 @@ pub async fn search_contacts(
     ),
     request_body = NewContactRequest,
     responses(
-        (status = 201, description = "Successfully added a contact", body = Contact),
-        (status = 400, description = "Default JSON elements configured by the user are invalid"),
+        (status = 201, description = "Successfully added a contact", body = ContactWithDetails),
+        (status = 400, description = "An email or phone number is invalid, or default JSON elements configured by the user are invalid"),
+        (status = 409, description = "A similar contact already exists, or the idempotency key was already used for a different request", body = ContactWithDetails),
         (status = 422, description = "Error when parsing a response from a model API"),
         (status = 502, description = "Error when forwarding request to model APIs")
     )
@@ -225,9 +508,31 @@ pub async fn search_contacts(
 #[axum::debug_handler]
 async fn add_contact(
     State(state): State<ToiState>,
-    Json(params): Json<NewContactRequest>,
-) -> Result<Json<Contact>, (StatusCode, String)> {
-    let mut conn = state.pool.get().await.map_err(utils::internal_error)?;
+    user: CurrentUser,
+    headers: HeaderMap,
+    Json(mut params): Json<NewContactRequest>,
+) -> Result<(StatusCode, Json<ContactWithDetails>), ToiError> {
+    let idempotency_key =
+        IdempotencyKey::from_request(&state, &headers, user.id, "/contacts", &params)?;
+    params.normalize_details()?;
+    let mut conn = utils::get_conn(&state.pool).await?;
+
+    // Replay repeats before the contact they added is mistaken for a
+    // duplicate.
+    if let Some(key) = &idempotency_key
+        && let Some(contact) = key.replay(&mut conn).await?
+    {
+        return Ok((StatusCode::OK, Json(contact)));
+    }
+
+    // Make sure the same person isn't already a contact, returning the
+    // existing contact if they are.
+    if !params.allow_duplicate.unwrap_or_default()
+        && let Some(contact) = find_duplicate_contact(&state, user.id, &params, &mut conn).await?
+    {
+        return Ok((StatusCode::CONFLICT, Json(contact)));
+    }
+
     let embedding_request = EmbeddingRequest {
         input: params.to_string(),
     };
@@ -239,8 +544,12 @@ async fn add_contact(
         phone,
         birthday,
         relationship,
+        emails,
+        phones,
+        ..
     } = params;
     let new_contact = NewContact {
+        user_id: user.id,
         first_name,
         last_name,
         email,
@@ -249,13 +558,186 @@ async fn add_contact(
         relationship,
         embedding,
     };
-    let result = diesel::insert_into(schema::contacts::table)
-        .values(new_contact)
-        .returning(Contact::as_returning())
-        .get_result(&mut conn)
-        .await
-        .map_err(utils::diesel_error)?;
-    Ok(Json(result))
+    // Within a single transaction, add the contact, and then add its labeled
+    // emails and phone numbers.
+    let result = conn
+        .transaction::<_, ToiError, _>(|mut conn| {
+            async move {
+                // Check again in case a request with the same key added the
+                // contact while this one was embedding it.
+                if let Some(key) = &idempotency_key
+                    && let Some(contact) = key.replay(&mut conn).await?
+                {
+                    return Ok(contact);
+                }
+                let contact = diesel::insert_into(schema::contacts::table)
+                    .values(new_contact)
+                    .returning(Contact::as_returning())
+                    .get_result(&mut conn)
+                    .await?;
+                let emails =
+                    replace_contact_emails(contact.id, emails.unwrap_or_default(), &mut conn)
+                        .await?;
+                let phones =
+                    replace_contact_phones(contact.id, phones.unwrap_or_default(), &mut conn)
+                        .await?;
+                let contact = ContactWithDetails {
+                    contact,
+                    emails,
+                    phones,
+                };
+                if let Some(key) = &idempotency_key {
+                    key.store(&contact, &mut conn).await?;
+                }
+                Ok(contact)
+            }
+            .scope_boxed()
+        })
+        .await?;
+    Ok((StatusCode::OK, Json(result)))
+}
+
+/// Import contacts from a vCard document. vCards that can't be imported or
+/// that look like existing contacts are skipped and listed in the response.
+#[utoipa::path(
+    post,
+    path = "/import",
+    request_body(content = String, content_type = "text/vcard"),
+    responses(
+        (status = 200, description = "Successfully imported contacts", body = ContactImport),
+        (status = 400, description = "The vCard document is malformed, or default JSON elements configured by the user are invalid"),
+        (status = 413, description = "The vCard document is too large or has too many vCards"),
+        (status = 422, description = "Error when parsing a response from a model API"),
+        (status = 502, description = "Error when forwarding request to model APIs")
+    )
+)]
+#[axum::debug_handler]
+async fn import_contacts(
+    State(state): State<ToiState>,
+    user: CurrentUser,
+    body: String,
+) -> Result<Json<ContactImport>, ToiError> {
+    let max_bytes = state.server_config.contact_import_max_bytes;
+    if body.len() > max_bytes {
+        return Err(ToiError::PayloadTooLarge(format!(
+            "vCard documents can't be larger than {max_bytes} bytes"
+        )));
+    }
+    let cards = vcard::parse(&body).map_err(ToiError::Validation)?;
+    let max_batch_size = state.server_config.max_batch_size;
+    if cards.len() > max_batch_size {
+        return Err(ToiError::PayloadTooLarge(format!(
+            "can't import more than {max_batch_size} contacts at once"
+        )));
+    }
+
+    // Skip vCards that can't be contacts or that look like the same person
+    // as an existing contact or an earlier vCard.
+    let mut conn = utils::get_conn(&state.pool).await?;
+    let name_similarity = state.server_config.contact_duplicate_similarity;
+    let mut new_contacts: Vec<NewContactRequest> = vec![];
+    let mut skipped = vec![];
+    for card in cards {
+        let name = card.display_name();
+        let mut skip = |reason: String, existing_contact_id: Option<i32>| {
+            skipped.push(SkippedContact {
+                name: name.clone(),
+                reason,
+                existing_contact_id,
+            });
+        };
+        let mut params = match NewContactRequest::try_from(card) {
+            Ok(params) => params,
+            Err(reason) => {
+                skip(reason, None);
+                continue;
+            }
+        };
+        if let Err(err) = params.normalize_details() {
+            skip(err.to_string(), None);
+            continue;
+        }
+        if let Some(contact) = find_duplicate_contact(&state, user.id, &params, &mut conn).await? {
+            skip(
+                "looks like an existing contact".to_string(),
+                Some(contact.contact.id),
+            );
+            continue;
+        }
+        if new_contacts
+            .iter()
+            .any(|other| params.is_duplicate_of_request(other, name_similarity))
+        {
+            skip(
+                "looks like an earlier contact in the same document".to_string(),
+                None,
+            );
+            continue;
+        }
+        new_contacts.push(params);
+    }
+    if new_contacts.is_empty() {
+        return Ok(Json(ContactImport {
+            imported: vec![],
+            skipped,
+        }));
+    }
+
+    // Every contact is embedded with one request and added in one
+    // transaction.
+    let embedding_request = BatchEmbeddingRequest {
+        input: new_contacts.iter().map(ToString::to_string).collect(),
+    };
+    let embeddings = state.model_client.embed_batch(embedding_request).await?;
+    let imported = conn
+        .transaction::<_, ToiError, _>(|mut conn| {
+            async move {
+                let mut imported = vec![];
+                for (params, embedding) in new_contacts.into_iter().zip(embeddings) {
+                    let NewContactRequest {
+                        first_name,
+                        last_name,
+                        email,
+                        phone,
+                        birthday,
+                        relationship,
+                        emails,
+                        phones,
+                        ..
+                    } = params;
+                    let new_contact = NewContact {
+                        user_id: user.id,
+                        first_name,
+                        last_name,
+                        email,
+                        phone,
+                        birthday,
+                        relationship,
+                        embedding,
+                    };
+                    let contact = diesel::insert_into(schema::contacts::table)
+                        .values(new_contact)
+                        .returning(Contact::as_returning())
+                        .get_result(&mut conn)
+                        .await?;
+                    let emails =
+                        replace_contact_emails(contact.id, emails.unwrap_or_default(), &mut conn)
+                            .await?;
+                    let phones =
+                        replace_contact_phones(contact.id, phones.unwrap_or_default(), &mut conn)
+                            .await?;
+                    imported.push(ContactWithDetails {
+                        contact,
+                        emails,
+                        phones,
+                    });
+                }
+                Ok(imported)
+            }
+            .scope_boxed()
+        })
+        .await?;
+    Ok(Json(ContactImport { imported, skipped }))
 }
 
 /// Delete and return contacts.
@@ -268,13 +750,13 @@ async fn add_contact(
     post,
     path = "/delete",
     extensions(
-        ("x-json-schema-body" = json!(schema_for!(ContactDeleteParams)))
+        ("x-json-schema-body" = json!(schema_for!(DeleteParams<ContactDeleteParams>)))
     ),
-    request_body = ContactDeleteParams,
+    request_body = DeleteParams<ContactDeleteParams>,
     responses(
         (status = 200, description = "Successfully deleted contacts", body = [Contact]),
-        (status = 400, description = "Default JSON elements configured by the user are invalid"),
-        (status = 404, description = "No contacts found"),
+        (status = 400, description = "Default JSON elements configured by the user are invalid, or deleting every item wasn't confirmed"),
+        (status = 404, description = "No contacts found with the given IDs"),
         (status = 422, description = "Error when parsing a response from a model API"),
         (status = 502, description = "Error when forwarding request to model APIs")
     )
@@ -282,9 +764,12 @@ async fn add_contact(
 #[axum::debug_handler]
 async fn delete_matching_contacts(
     State(state): State<ToiState>,
-    Json(params): Json<ContactDeleteParams>,
-) -> Result<Json<Vec<Contact>>, (StatusCode, String)> {
-    let mut conn = state.pool.get().await.map_err(utils::internal_error)?;
+    user: CurrentUser,
+    Json(params): Json<DeleteParams<ContactDeleteParams>>,
+) -> Result<Json<Vec<Contact>>, ToiError> {
+    let params = params.into_checked()?;
+    let mut conn = utils::get_conn(&state.pool).await?;
+    let mut embeddings = EmbeddingCache::default();
     let ContactDeleteParams {
         ids,
         query,
@@ -294,23 +779,31 @@ async fn delete_matching_contacts(
         order_by,
         limit,
     } = params;
+    let requested_ids = ids.clone();
     let params = ContactSearchParams {
         ids,
         birthday: None,
         birthday_falls_on: None,
+        upcoming_within_days: None,
+        timezone: None,
         query,
         use_reranking_filter,
         created_from,
         created_to,
         order_by,
         limit,
+        offset: None,
+        count_only: None,
     };
-    let ids = search_contacts(&state, params, &mut conn).await?;
+    let ids = search_contacts(&state, user.id, params, &mut embeddings, &mut conn)
+        .await?
+        .items;
     let contacts = diesel::delete(schema::contacts::table.filter(schema::contacts::id.eq_any(ids)))
         .returning(Contact::as_returning())
         .load(&mut conn)
         .await
         .map_err(utils::diesel_error)?;
+    utils::check_ids_found(requested_ids.as_deref(), &contacts, "contacts")?;
     Ok(Json(contacts))
 }
 
@@ -329,9 +822,8 @@ async fn delete_matching_contacts(
     ),
     request_body = ContactSearchParams,
     responses(
-        (status = 200, description = "Successfully got contacts", body = [Contact]),
-        (status = 400, description = "Default JSON elements configured by the user are invalid"),
-        (status = 404, description = "No contacts found"),
+        (status = 200, description = "Successfully got contacts or their count", body = SearchResponse<ContactWithDetails>),
+        (status = 400, description = "Invalid timezone, or default JSON elements configured by the user are invalid"),
         (status = 422, description = "Error when parsing a response from a model API"),
         (status = 502, description = "Error when forwarding request to model APIs")
     )
@@ -339,17 +831,34 @@ async fn delete_matching_contacts(
 #[axum::debug_handler]
 async fn get_matching_contacts(
     State(state): State<ToiState>,
+    user: CurrentUser,
     Json(params): Json<ContactSearchParams>,
-) -> Result<Json<Vec<Contact>>, (StatusCode, String)> {
-    let mut conn = state.pool.get().await.map_err(utils::internal_error)?;
-    let ids = search_contacts(&state, params, &mut conn).await?;
+) -> Result<Json<SearchResponse<ContactWithDetails>>, ToiError> {
+    let count_only = params.count_only.unwrap_or_default();
+    let mut conn = utils::get_conn(&state.pool).await?;
+    let mut embeddings = EmbeddingCache::default();
+    let Page {
+        items: ids,
+        total,
+        offset,
+        limit,
+    } = search_contacts(&state, user.id, params, &mut embeddings, &mut conn).await?;
+    if count_only {
+        return Ok(Json(SearchResponse::Count(Count { count: total })));
+    }
     let contacts = schema::contacts::table
         .select(Contact::as_select())
         .filter(schema::contacts::id.eq_any(ids))
         .load(&mut conn)
         .await
         .map_err(utils::diesel_error)?;
-    Ok(Json(contacts))
+    let contacts = load_contact_details(contacts, &mut conn).await?;
+    Ok(Json(SearchResponse::Page(Page {
+        items: contacts,
+        total,
+        offset,
+        limit,
+    })))
 }
 
 /// Update and return a contact.
@@ -367,47 +876,69 @@ async fn get_matching_contacts(
     ),
     request_body = UpdateContactRequest,
     responses(
-        (status = 200, description = "Successfully updated contact", body = Contact),
+        (status = 200, description = "Successfully updated contact", body = ContactWithDetails),
+        (status = 400, description = "An updated email or phone number is invalid"),
         (status = 404, description = "Contact not found")
     )
 )]
 #[axum::debug_handler]
 async fn update_matching_contact(
     State(state): State<ToiState>,
+    user: CurrentUser,
     Json(params): Json<UpdateContactRequest>,
-) -> Result<Json<Contact>, (StatusCode, String)> {
-    let mut conn = state.pool.get().await.map_err(utils::internal_error)?;
+) -> Result<Json<ContactWithDetails>, ToiError> {
+    let mut conn = utils::get_conn(&state.pool).await?;
+    let mut embeddings = EmbeddingCache::default();
     let UpdateContactRequest {
         id,
-        contact_updates,
+        mut contact_updates,
         query,
         use_reranking_filter,
         created_from,
         created_to,
         order_by,
     } = params;
+    contact_updates.normalize_details()?;
     let params = ContactSearchParams {
         ids: id.map(|i| vec![i]),
         birthday: None,
         birthday_falls_on: None,
+        upcoming_within_days: None,
+        timezone: None,
         query,
         use_reranking_filter,
         created_from,
         created_to,
         order_by,
         limit: Some(1),
+        offset: None,
+        count_only: None,
     };
-    let id = search_contacts(&state, params, &mut conn)
+    let id = search_contacts(&state, user.id, params, &mut embeddings, &mut conn)
         .await?
+        .items
         .into_iter()
         .next()
-        .ok_or((StatusCode::NOT_FOUND, "contact not found".to_string()))?;
-    let mut contact = schema::contacts::table
+        .ok_or(ToiError::NotFound("contact not found".to_string()))?;
+    let contact = schema::contacts::table
         .select(Contact::as_select())
         .filter(schema::contacts::id.eq(id))
         .first(&mut conn)
         .await
         .map_err(utils::diesel_error)?;
+    let ContactWithDetails {
+        mut contact,
+        emails,
+        phones,
+    } = load_contact_details(vec![contact], &mut conn)
+        .await?
+        .into_iter()
+        .next()
+        .ok_or(ToiError::NotFound("contact not found".to_string()))?;
+    // Labeled emails and phone numbers are replaced wholesale if they're
+    // given, and kept as-is otherwise.
+    let email_updates = contact_updates.emails.take();
+    let phone_updates = contact_updates.phones.take();
     contact.update(contact_updates);
     let Contact {
         id,
@@ -426,6 +957,17 @@ async fn update_matching_contact(
         phone,
         birthday,
         relationship,
+        emails: Some(
+            email_updates
+                .clone()
+                .unwrap_or_else(|| emails.iter().map(ContactDetail::from).collect()),
+        ),
+        phones: Some(
+            phone_updates
+                .clone()
+                .unwrap_or_else(|| phones.iter().map(ContactDetail::from).collect()),
+        ),
+        allow_duplicate: None,
     };
     let embedding_request = EmbeddingRequest {
         input: new_contact_request.to_string(),
@@ -438,8 +980,10 @@ async fn update_matching_contact(
         phone,
         birthday,
         relationship,
+        ..
     } = new_contact_request;
     let new_contact = NewContact {
+        user_id: user.id,
         first_name,
         last_name,
         email,
@@ -448,11 +992,250 @@ async fn update_matching_contact(
         relationship,
         embedding,
     };
-    let contact = diesel::update(schema::contacts::table.filter(schema::contacts::id.eq(id)))
-        .set(&new_contact)
-        .returning(Contact::as_returning())
-        .get_result(&mut conn)
+    // Within a single transaction, update the contact, and then replace its
+    // labeled emails and phone numbers.
+    let contact = conn
+        .transaction(|mut conn| {
+            async move {
+                let contact =
+                    diesel::update(schema::contacts::table.filter(schema::contacts::id.eq(id)))
+                        .set(&new_contact)
+                        .returning(Contact::as_returning())
+                        .get_result(&mut conn)
+                        .await?;
+                let emails = match email_updates {
+                    Some(emails) => replace_contact_emails(contact.id, emails, &mut conn).await?,
+                    None => emails,
+                };
+                let phones = match phone_updates {
+                    Some(phones) => replace_contact_phones(contact.id, phones, &mut conn).await?,
+                    None => phones,
+                };
+                Ok(ContactWithDetails {
+                    contact,
+                    emails,
+                    phones,
+                })
+            }
+            .scope_boxed()
+        })
         .await
         .map_err(utils::diesel_error)?;
     Ok(Json(contact))
 }
+
+/// Get contacts' birthdays as an iCalendar document with a yearly repeating
+/// all-day event for each contact that has a birthday.
+#[utoipa::path(
+    get,
+    path = "/birthdays.ics",
+    responses(
+        (status = 200, description = "Successfully got birthdays as an iCalendar document", content_type = "text/calendar")
+    )
+)]
+#[axum::debug_handler]
+async fn get_birthday_calendar(
+    State(state): State<ToiState>,
+    user: CurrentUser,
+) -> Result<Response, ToiError> {
+    let mut conn = utils::get_conn(&state.pool).await?;
+    let contacts: Vec<Contact> = schema::contacts::table
+        .select(Contact::as_select())
+        .filter(schema::contacts::user_id.eq(user.id))
+        .filter(schema::contacts::birthday.is_not_null())
+        .order(schema::contacts::id)
+        .load(&mut conn)
+        .await
+        .map_err(utils::diesel_error)?;
+    let mut calendar = CalendarWriter::new();
+    for contact in contacts {
+        let Some(birthday) = contact.birthday else {
+            continue;
+        };
+        let name = match contact.last_name {
+            Some(last_name) => format!("{} {last_name}", contact.first_name),
+            None => contact.first_name,
+        };
+        // Leap day birthdays fall on the last day of February in other years.
+        let rule = if birthday.month() == 2 && birthday.day() == 29 {
+            "FREQ=YEARLY;BYMONTH=2;BYMONTHDAY=-1"
+        } else {
+            "FREQ=YEARLY"
+        };
+        calendar.property("BEGIN", "VEVENT");
+        calendar.property("UID", &format!("birthday-{}@toi", contact.id));
+        calendar.datetime("DTSTAMP", contact.created_at);
+        calendar.date("DTSTART", birthday);
+        if let Some(next_day) = birthday.succ_opt() {
+            calendar.date("DTEND", next_day);
+        }
+        calendar.property("RRULE", rule);
+        calendar.text("SUMMARY", &format!("{name}'s birthday"));
+        calendar.property("TRANSP", "TRANSPARENT");
+        calendar.property("END", "VEVENT");
+    }
+    let body = calendar.finish();
+    Ok(([(header::CONTENT_TYPE, ics::CONTENT_TYPE)], body).into_response())
+}
+
+/// Get a contact using its database-generated ID.
+#[utoipa::path(
+    get,
+    path = "/{id}",
+    params(
+        ("id" = i32, Path, description = "Database-generated contact ID")
+    ),
+    responses(
+        (status = 200, description = "Successfully got a contact", body = ContactWithDetails),
+        (status = 404, description = "Contact not found")
+    )
+)]
+#[axum::debug_handler]
+async fn get_contact(
+    State(state): State<ToiState>,
+    user: CurrentUser,
+    Path(id): Path<i32>,
+) -> Result<Json<ContactWithDetails>, ToiError> {
+    let mut conn = utils::get_conn(&state.pool).await?;
+    let contact = schema::contacts::table
+        .select(Contact::as_select())
+        .filter(schema::contacts::id.eq(id))
+        .filter(schema::contacts::user_id.eq(user.id))
+        .first(&mut conn)
+        .await
+        .map_err(utils::diesel_error)?;
+    let contact = load_contact_details(vec![contact], &mut conn)
+        .await?
+        .into_iter()
+        .next()
+        .ok_or(ToiError::NotFound("contact not found".to_string()))?;
+    Ok(Json(contact))
+}
+
+/// Set the avatar image of a contact, replacing the one they already have.
+#[utoipa::path(
+    put,
+    path = "/avatar",
+    request_body = ContactAvatarRequest,
+    responses(
+        (status = 200, description = "Successfully set the contact's avatar", body = ContactAvatar),
+        (status = 400, description = "The avatar isn't base64-encoded, or isn't a PNG, JPEG, or WebP image of its content type"),
+        (status = 404, description = "Contact not found"),
+        (status = 413, description = "The avatar is too large"),
+        (status = 422, description = "Error when parsing a response from a model API"),
+        (status = 502, description = "Error when forwarding request to model APIs")
+    )
+)]
+#[axum::debug_handler]
+async fn set_matching_contact_avatar(
+    State(state): State<ToiState>,
+    user: CurrentUser,
+    Json(params): Json<ContactAvatarRequest>,
+) -> Result<Json<ContactAvatar>, ToiError> {
+    let ContactAvatarRequest {
+        id,
+        content_type,
+        data,
+        query,
+        use_reranking_filter,
+        created_from,
+        created_to,
+        order_by,
+    } = params;
+    if !AVATAR_CONTENT_TYPES.contains(&content_type.as_str()) {
+        return Err(ToiError::Validation(format!(
+            "avatars must be one of {}, but it's {content_type}",
+            AVATAR_CONTENT_TYPES.join(", ")
+        )));
+    }
+    let data = BASE64_STANDARD
+        .decode(data)
+        .map_err(|err| ToiError::Validation(format!("avatar isn't valid base64: {err}")))?;
+    let max_bytes = state.server_config.contact_avatar_max_bytes;
+    if data.len() > max_bytes {
+        return Err(ToiError::PayloadTooLarge(format!(
+            "avatars can't be larger than {max_bytes} bytes"
+        )));
+    }
+    // The content type is served with the avatar, so it has to be what the
+    // image actually is.
+    if detect_avatar_content_type(&data) != Some(content_type.as_str()) {
+        return Err(ToiError::Validation(format!(
+            "avatar isn't a {content_type} image"
+        )));
+    }
+    let mut conn = utils::get_conn(&state.pool).await?;
+    let mut embeddings = EmbeddingCache::default();
+    let params = ContactSearchParams {
+        ids: id.map(|i| vec![i]),
+        birthday: None,
+        birthday_falls_on: None,
+        upcoming_within_days: None,
+        timezone: None,
+        query,
+        use_reranking_filter,
+        created_from,
+        created_to,
+        order_by,
+        limit: Some(1),
+        offset: None,
+        count_only: None,
+    };
+    let contact_id = search_contacts(&state, user.id, params, &mut embeddings, &mut conn)
+        .await?
+        .items
+        .into_iter()
+        .next()
+        .ok_or(ToiError::NotFound("contact not found".to_string()))?;
+    let new_avatar = NewContactAvatar {
+        contact_id,
+        content_type,
+        data,
+        updated_at: Utc::now(),
+    };
+    let avatar = diesel::insert_into(schema::contact_avatars::table)
+        .values(&new_avatar)
+        .on_conflict(schema::contact_avatars::contact_id)
+        .do_update()
+        .set(&new_avatar)
+        .returning(ContactAvatar::as_returning())
+        .get_result(&mut conn)
+        .await
+        .map_err(utils::diesel_error)?;
+    Ok(Json(avatar))
+}
+
+/// Get the avatar image of a contact using its database-generated ID.
+#[utoipa::path(
+    get,
+    path = "/{id}/avatar",
+    params(
+        ("id" = i32, Path, description = "Database-generated contact ID")
+    ),
+    responses(
+        (status = 200, description = "Successfully got the contact's avatar", content_type = "image/*"),
+        (status = 404, description = "Contact or avatar not found")
+    )
+)]
+#[axum::debug_handler]
+async fn get_contact_avatar(
+    State(state): State<ToiState>,
+    user: CurrentUser,
+    Path(id): Path<i32>,
+) -> Result<Response, ToiError> {
+    let mut conn = utils::get_conn(&state.pool).await?;
+    let (content_type, data) = schema::contact_avatars::table
+        .inner_join(schema::contacts::table)
+        .select((
+            schema::contact_avatars::content_type,
+            schema::contact_avatars::data,
+        ))
+        .filter(schema::contacts::id.eq(id))
+        .filter(schema::contacts::user_id.eq(user.id))
+        .first::<(String, Vec<u8>)>(&mut conn)
+        .await
+        .optional()
+        .map_err(utils::diesel_error)?
+        .ok_or(ToiError::NotFound("contact avatar not found".to_string()))?;
+    Ok(([(header::CONTENT_TYPE, content_type)], data).into_response())
+}
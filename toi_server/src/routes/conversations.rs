@@ -0,0 +1,203 @@
+use axum::{
+    extract::{Path, State},
+    response::Json,
+};
+use diesel::{ExpressionMethods, QueryDsl, SelectableHelper};
+use diesel_async::{AsyncConnection, RunQueryDsl, scoped_futures::ScopedFutureExt};
+use toi::Message;
+use utoipa_axum::{router::OpenApiRouter, routes};
+
+use crate::{
+    auth::CurrentUser,
+    models::{
+        conversations::{
+            Conversation, ConversationMessage, NewConversation, NewConversationMessage,
+            NewConversationMessagesRequest, NewConversationRequest,
+        },
+        error::ToiError,
+        state::ToiState,
+    },
+    schema, utils,
+};
+
+pub fn conversations_router(state: ToiState) -> OpenApiRouter {
+    OpenApiRouter::new()
+        .routes(routes!(add_conversation, get_conversations))
+        .routes(routes!(
+            add_conversation_messages,
+            get_conversation_messages
+        ))
+        .with_state(state)
+}
+
+/// Get all of a user's conversation's messages in the order they were
+/// added.
+pub async fn load_messages(
+    user_id: i32,
+    conversation_id: i32,
+    conn: &mut utils::Conn<'_>,
+) -> Result<Vec<ConversationMessage>, ToiError> {
+    // Make sure the conversation exists and is the user's so a missing
+    // conversation isn't mistaken for an empty one, and so other users'
+    // conversations can't be read.
+    schema::conversations::table
+        .select(schema::conversations::id)
+        .filter(schema::conversations::id.eq(conversation_id))
+        .filter(schema::conversations::user_id.eq(user_id))
+        .first::<i32>(conn)
+        .await
+        .map_err(utils::diesel_error)?;
+    schema::conversation_messages::table
+        .select(ConversationMessage::as_select())
+        .filter(schema::conversation_messages::conversation_id.eq(conversation_id))
+        .order(schema::conversation_messages::sequence)
+        .load(conn)
+        .await
+        .map_err(utils::diesel_error)
+}
+
+/// Append messages to the end of a user's conversation.
+pub async fn append_messages(
+    user_id: i32,
+    conversation_id: i32,
+    messages: Vec<Message>,
+    conn: &mut utils::Conn<'_>,
+) -> Result<Vec<ConversationMessage>, ToiError> {
+    conn.transaction(|mut conn| {
+        async move {
+            // Lock the conversation so concurrent appends are serialized and
+            // each gets its own contiguous range of sequence numbers.
+            // Other users' conversations aren't found.
+            schema::conversations::table
+                .select(schema::conversations::id)
+                .filter(schema::conversations::id.eq(conversation_id))
+                .filter(schema::conversations::user_id.eq(user_id))
+                .for_update()
+                .first::<i32>(&mut conn)
+                .await?;
+            let last_sequence: Option<i32> = schema::conversation_messages::table
+                .select(diesel::dsl::max(schema::conversation_messages::sequence))
+                .filter(schema::conversation_messages::conversation_id.eq(conversation_id))
+                .first(&mut conn)
+                .await?;
+            let next_sequence = last_sequence.map_or(0, |sequence| sequence + 1);
+            let new_messages: Vec<NewConversationMessage> = (next_sequence..)
+                .zip(messages)
+                .map(|(sequence, message)| {
+                    NewConversationMessage::new(conversation_id, sequence, message)
+                })
+                .collect();
+            diesel::insert_into(schema::conversation_messages::table)
+                .values(new_messages)
+                .returning(ConversationMessage::as_returning())
+                .get_results(&mut conn)
+                .await
+        }
+        .scope_boxed()
+    })
+    .await
+    .map_err(utils::diesel_error)
+}
+
+/// Start and return a new conversation.
+#[utoipa::path(
+    post,
+    path = "",
+    request_body = NewConversationRequest,
+    responses(
+        (status = 201, description = "Successfully added a conversation", body = Conversation)
+    )
+)]
+#[axum::debug_handler]
+async fn add_conversation(
+    State(state): State<ToiState>,
+    user: CurrentUser,
+    Json(params): Json<NewConversationRequest>,
+) -> Result<Json<Conversation>, ToiError> {
+    let mut conn = utils::get_conn(&state.pool).await?;
+    let NewConversationRequest { title } = params;
+    let new_conversation = NewConversation {
+        user_id: user.id,
+        title,
+    };
+    let result = diesel::insert_into(schema::conversations::table)
+        .values(new_conversation)
+        .returning(Conversation::as_returning())
+        .get_result(&mut conn)
+        .await
+        .map_err(utils::diesel_error)?;
+    Ok(Json(result))
+}
+
+/// Get all of the user's conversations, newest first.
+#[utoipa::path(
+    get,
+    path = "",
+    responses(
+        (status = 200, description = "Successfully got conversations", body = [Conversation])
+    )
+)]
+#[axum::debug_handler]
+async fn get_conversations(
+    State(state): State<ToiState>,
+    user: CurrentUser,
+) -> Result<Json<Vec<Conversation>>, ToiError> {
+    let mut conn = utils::get_conn(&state.pool).await?;
+    let result = schema::conversations::table
+        .select(Conversation::as_select())
+        .filter(schema::conversations::user_id.eq(user.id))
+        .order(schema::conversations::created_at.desc())
+        .load(&mut conn)
+        .await
+        .map_err(utils::diesel_error)?;
+    Ok(Json(result))
+}
+
+/// Append messages to a conversation and return the added messages.
+#[utoipa::path(
+    post,
+    path = "/{id}/messages",
+    params(
+        ("id" = i32, Path, description = "Conversation ID")
+    ),
+    request_body = NewConversationMessagesRequest,
+    responses(
+        (status = 201, description = "Successfully added messages", body = [ConversationMessage]),
+        (status = 404, description = "Conversation not found")
+    )
+)]
+#[axum::debug_handler]
+async fn add_conversation_messages(
+    State(state): State<ToiState>,
+    user: CurrentUser,
+    Path(id): Path<i32>,
+    Json(params): Json<NewConversationMessagesRequest>,
+) -> Result<Json<Vec<ConversationMessage>>, ToiError> {
+    let mut conn = utils::get_conn(&state.pool).await?;
+    let NewConversationMessagesRequest { messages } = params;
+    let result = append_messages(user.id, id, messages, &mut conn).await?;
+    Ok(Json(result))
+}
+
+/// Get a conversation's messages in the order they were added.
+#[utoipa::path(
+    get,
+    path = "/{id}/messages",
+    params(
+        ("id" = i32, Path, description = "Conversation ID")
+    ),
+    responses(
+        (status = 200, description = "Successfully got messages", body = [ConversationMessage]),
+        (status = 404, description = "Conversation not found")
+    )
+)]
+#[axum::debug_handler]
+async fn get_conversation_messages(
+    State(state): State<ToiState>,
+    user: CurrentUser,
+    Path(id): Path<i32>,
+) -> Result<Json<Vec<ConversationMessage>>, ToiError> {
+    let mut conn = utils::get_conn(&state.pool).await?;
+    let result = load_messages(user.id, id, &mut conn).await?;
+    Ok(Json(result))
+}
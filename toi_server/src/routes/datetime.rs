@@ -1,16 +1,90 @@
-use axum::{extract::Query, http::StatusCode, response::Json};
-use chrono::{DateTime, Datelike, Duration, Utc, Weekday};
+use axum::{extract::Query, response::Json};
+use chrono::{DateTime, Datelike, Duration, LocalResult, NaiveDateTime, TimeZone, Utc, Weekday};
+use chrono_tz::Tz;
 use schemars::schema_for;
 use utoipa_axum::{router::OpenApiRouter, routes};
 
-use crate::models::datetime::{DateTimeShiftRequest, DateTimeWeekdayParams};
+use crate::{
+    models::{
+        datetime::{
+            DateTimeConvertParams, DateTimeResolveParams, DateTimeShiftRequest,
+            DateTimeWeekdayParams, ResolvedDateTime,
+        },
+        error::ToiError,
+    },
+    utils,
+};
 
 pub fn datetime_router() -> OpenApiRouter {
     OpenApiRouter::new()
+        .routes(routes!(convert))
         .routes(routes!(now, shift))
+        .routes(routes!(resolve))
         .routes(routes!(weekday))
 }
 
+fn parse_timezone(timezone: Option<&str>) -> Result<Tz, ToiError> {
+    utils::parse_timezone(timezone, Tz::UTC)
+}
+
+/// Interpret a local datetime in a timezone, rejecting local times that are
+/// skipped or repeated by daylight saving time transitions.
+fn localize(datetime: NaiveDateTime, timezone: Tz) -> Result<ResolvedDateTime, ToiError> {
+    match timezone.from_local_datetime(&datetime) {
+        LocalResult::Single(local) => Ok(ResolvedDateTime {
+            utc: local.with_timezone(&Utc),
+            local: local.fixed_offset(),
+            timezone: timezone.name().to_string(),
+        }),
+        LocalResult::Ambiguous(earliest, latest) => Err(ToiError::Validation(format!(
+            "{datetime} is ambiguous in {timezone} due to a daylight saving time transition \
+            and could be either {} or {}",
+            earliest.fixed_offset(),
+            latest.fixed_offset()
+        ))),
+        LocalResult::None => Err(ToiError::Validation(format!(
+            "{datetime} doesn't exist in {timezone} because it's skipped by a daylight saving \
+            time transition"
+        ))),
+    }
+}
+
+/// Convert a datetime from one timezone to another.
+///
+/// Example queries for converting between timezones using this endpoint:
+/// - What time is 3pm Tokyo time in New York?
+/// - Convert 9am Pacific to Eastern time.
+/// - What's noon UTC in London?
+/// - When is 8pm in Berlin for me?
+#[utoipa::path(
+    get,
+    path = "/convert",
+    extensions(
+        ("x-json-schema-params" = json!(schema_for!(DateTimeConvertParams)))
+    ),
+    params(
+        DateTimeConvertParams
+    ),
+    responses(
+        (status = 200, description = "Successfully converted given datetime", body = ResolvedDateTime),
+        (status = 400, description = "Invalid timezone or datetime skipped or repeated by a daylight saving time transition")
+    ),
+)]
+#[axum::debug_handler]
+async fn convert(
+    Query(params): Query<DateTimeConvertParams>,
+) -> Result<Json<ResolvedDateTime>, ToiError> {
+    let from_timezone = parse_timezone(params.from_timezone.as_deref())?;
+    let to_timezone = parse_timezone(params.to_timezone.as_deref())?;
+    let ResolvedDateTime { utc, .. } = localize(params.datetime, from_timezone)?;
+    let result = ResolvedDateTime {
+        utc,
+        local: utc.with_timezone(&to_timezone).fixed_offset(),
+        timezone: to_timezone.name().to_string(),
+    };
+    Ok(Json(result))
+}
+
 /// Get the current time.
 ///
 /// Example queries for getting the current time using this endpoint:
@@ -26,7 +100,7 @@ pub fn datetime_router() -> OpenApiRouter {
     )
 )]
 #[axum::debug_handler]
-async fn now() -> Result<Json<DateTime<Utc>>, (StatusCode, String)> {
+async fn now() -> Result<Json<DateTime<Utc>>, ToiError> {
     let result = Utc::now();
     Ok(Json(result))
 }
@@ -50,9 +124,7 @@ async fn now() -> Result<Json<DateTime<Utc>>, (StatusCode, String)> {
     )
 )]
 #[axum::debug_handler]
-async fn shift(
-    Json(params): Json<DateTimeShiftRequest>,
-) -> Result<Json<DateTime<Utc>>, (StatusCode, String)> {
+async fn shift(Json(params): Json<DateTimeShiftRequest>) -> Result<Json<DateTime<Utc>>, ToiError> {
     let time_delta = Duration::days(params.days)
         + Duration::weeks(params.weeks)
         + Duration::hours(params.hours)
@@ -61,7 +133,70 @@ async fn shift(
     let result = params
         .datetime
         .checked_add_signed(time_delta)
-        .ok_or((StatusCode::BAD_REQUEST, "duration overflow".to_string()))?;
+        .ok_or(ToiError::Validation("duration overflow".to_string()))?;
+    Ok(Json(result))
+}
+
+/// Resolve a relative date and time like "next Friday at 3pm" into an ISO
+/// datetime.
+///
+/// Example queries for resolving relative datetimes using this endpoint:
+/// - When is next Friday at 3pm in Tokyo time?
+/// - What's the date two weeks from Monday?
+/// - Get the datetime for tomorrow at 9am.
+/// - What date is the Saturday after next?
+#[utoipa::path(
+    get,
+    path = "/resolve",
+    extensions(
+        ("x-json-schema-params" = json!(schema_for!(DateTimeResolveParams)))
+    ),
+    params(
+        DateTimeResolveParams
+    ),
+    responses(
+        (status = 200, description = "Successfully resolved given datetime", body = ResolvedDateTime),
+        (status = 400, description = "Invalid timezone, overflow, or datetime skipped or repeated by a daylight saving time transition")
+    ),
+)]
+#[axum::debug_handler]
+async fn resolve(
+    Query(params): Query<DateTimeResolveParams>,
+) -> Result<Json<ResolvedDateTime>, ToiError> {
+    let DateTimeResolveParams {
+        datetime,
+        timezone,
+        weeks,
+        days,
+        weekday,
+        time,
+    } = params;
+    let timezone = parse_timezone(timezone.as_deref())?;
+    let local = datetime
+        .unwrap_or_else(Utc::now)
+        .with_timezone(&timezone)
+        .naive_local();
+
+    // Shift the date by whole days so times of day stay put across daylight
+    // saving time transitions.
+    let time_delta =
+        Duration::weeks(weeks.unwrap_or_default()) + Duration::days(days.unwrap_or_default());
+    let mut date = local
+        .date()
+        .checked_add_signed(time_delta)
+        .ok_or(ToiError::Validation("duration overflow".to_string()))?;
+
+    // Move forward to the next occurrence of the weekday.
+    if let Some(weekday) = weekday {
+        let days_ahead =
+            (weekday.num_days_from_monday() + 7 - date.weekday().num_days_from_monday()) % 7;
+        let days_ahead = if days_ahead == 0 { 7 } else { days_ahead };
+        date = date
+            .checked_add_signed(Duration::days(days_ahead.into()))
+            .ok_or(ToiError::Validation("duration overflow".to_string()))?;
+    }
+
+    let result = localize(date.and_time(time.unwrap_or(local.time())), timezone)?;
     Ok(Json(result))
 }
 
@@ -86,9 +221,7 @@ async fn shift(
     ),
 )]
 #[axum::debug_handler]
-async fn weekday(
-    Query(params): Query<DateTimeWeekdayParams>,
-) -> Result<Json<Weekday>, (StatusCode, String)> {
+async fn weekday(Query(params): Query<DateTimeWeekdayParams>) -> Result<Json<Weekday>, ToiError> {
     let result = params.datetime.weekday();
     Ok(Json(result))
 }
@@ -1,18 +1,40 @@
-use axum::{extract::State, http::StatusCode, response::Json};
-use chrono::{Datelike, Duration, Month, NaiveDate, NaiveTime};
-use diesel::{BoolExpressionMethods, ExpressionMethods, QueryDsl, SelectableHelper};
-use diesel_async::RunQueryDsl;
-use pgvector::VectorExpressionMethods;
+use axum::{
+    extract::{Path, Query, State},
+    http::{HeaderMap, StatusCode, header},
+    response::{IntoResponse, Json, Response},
+};
+use chrono::{DateTime, Utc};
+use diesel::{
+    BoolExpressionMethods, ExpressionMethods, OptionalExtension, QueryDsl, SelectableHelper,
+};
+use diesel_async::{AsyncConnection, RunQueryDsl, scoped_futures::ScopedFutureExt};
+use pgvector::{Vector, VectorExpressionMethods};
 use schemars::schema_for;
+use std::{cmp::Reverse, collections::HashMap};
 use utoipa_axum::{router::OpenApiRouter, routes};
 
 use crate::{
+    auth::CurrentUser,
+    embeddings::EmbeddedTable,
+    ics::{self, CalendarWriter},
+    idempotency::IdempotencyKey,
     models::{
-        client::{EmbeddingPromptTemplate, EmbeddingRequest, RerankRequest},
-        events::{Event, EventSearchParams, NewEvent, NewEventRequest},
+        client::{EmbeddingCache, EmbeddingRequest},
+        deletion::DeleteParams,
+        error::ToiError,
+        events::{
+            Event, EventCalendarParams, EventCreated, EventOrderBy, EventSearchParams,
+            EventWithPlace, NewEvent, NewEventRequest, UNTIMED_EVENT_DURATION, UpcomingEvent,
+            UpcomingEventsRequest, conflict_end, event_day_window, order_event_times,
+        },
+        pagination::{Count, Page, SearchResponse},
+        places::{Place, PlaceSearchParams},
         state::ToiState,
     },
-    schema, utils,
+    routes::places::search_places,
+    schema,
+    search::{self, RerankOptions},
+    utils,
 };
 
 // Prefixes are used for embedding instructions.
@@ -25,28 +47,104 @@ pub fn events_router(state: ToiState) -> OpenApiRouter {
         .routes(routes!(add_event))
         .routes(routes!(delete_matching_events))
         .routes(routes!(get_matching_events))
+        .routes(routes!(get_upcoming_events))
+        .routes(routes!(get_event_calendar))
+        .routes(routes!(get_event))
         .with_state(state)
 }
 
+/// Find the place an event is at using the place's database-generated ID or
+/// the place that best matches a query, returning `None` if neither is given.
+pub async fn resolve_place(
+    state: &ToiState,
+    user_id: i32,
+    place_id: Option<i32>,
+    place_query: Option<String>,
+    conn: &mut utils::Conn<'_>,
+) -> Result<Option<i32>, (StatusCode, String)> {
+    if place_id.is_none() && place_query.is_none() {
+        return Ok(None);
+    }
+    let place_query_params = PlaceSearchParams {
+        ids: place_id.map(|i| vec![i]),
+        query: place_query,
+        use_reranking_filter: None,
+        created_from: None,
+        created_to: None,
+        near_query: None,
+        radius_km: None,
+        order_by: None,
+        limit: Some(1),
+        offset: None,
+    };
+    let place_id = search_places(state, user_id, place_query_params, conn)
+        .await?
+        .items
+        .into_iter()
+        .next()
+        .ok_or((StatusCode::NOT_FOUND, "place not found".to_string()))?;
+    Ok(Some(place_id))
+}
+
 pub async fn search_events(
     state: &ToiState,
+    user_id: i32,
     params: EventSearchParams,
+    embeddings: &mut EmbeddingCache,
     conn: &mut utils::Conn<'_>,
-) -> Result<Vec<i32>, (StatusCode, String)> {
+) -> Result<Page<i32>, (StatusCode, String)> {
+    // The total is counted alongside a page's items, so it's counted on
+    // its own when the offset is past the end and there aren't any.
+    let count_params = (params.offset.is_some_and(|offset| offset > 0)
+        && !params.count_only.unwrap_or_default())
+    .then(|| EventSearchParams {
+        use_reranking_filter: None,
+        limit: None,
+        offset: None,
+        count_only: Some(true),
+        ..params.clone()
+    });
+    let mut page = search_events_page(state, user_id, params, embeddings, conn).await?;
+    if page.total == 0
+        && let Some(count_params) = count_params
+    {
+        page.total = search_events_page(state, user_id, count_params, embeddings, conn)
+            .await?
+            .total;
+    }
+    Ok(page)
+}
+
+async fn search_events_page(
+    state: &ToiState,
+    user_id: i32,
+    params: EventSearchParams,
+    embeddings: &mut EmbeddingCache,
+    conn: &mut utils::Conn<'_>,
+) -> Result<Page<i32>, (StatusCode, String)> {
     let EventSearchParams {
         ids,
         event_day,
         event_day_falls_on,
+        timezone,
         query,
         use_reranking_filter,
         created_from,
         created_to,
+        occurs_from,
+        occurs_to,
+        upcoming_only,
+        place_id,
+        place_query,
         order_by,
         limit,
+        offset,
+        count_only,
+        ..
     } = params;
 
     let mut sql_query = schema::events::table
-        .select(Event::as_select())
+        .select((Event::as_select(), utils::total_count()))
         .into_boxed();
 
     // Filter items created on or after date.
@@ -59,101 +157,102 @@ pub async fn search_events(
         sql_query = sql_query.filter(schema::events::created_at.le(created_to));
     }
 
-    // Filter items according to event days.
-    if let Some(event_day) = event_day {
-        match event_day_falls_on {
-            Some(utils::DateFallsOn::Month) => {
-                let year = event_day.year();
-                let month = event_day.month();
-                let num_days_in_month = {
-                    let month = u8::try_from(month).map_err(|_| {
-                        (
-                            StatusCode::BAD_REQUEST,
-                            "invalid event day search month".to_string(),
-                        )
-                    })?;
-                    let month = Month::try_from(month).map_err(|_| {
-                        (
-                            StatusCode::BAD_REQUEST,
-                            "invalid event day search month".to_string(),
-                        )
-                    })?;
-                    month.num_days(year).ok_or((
-                        StatusCode::BAD_REQUEST,
-                        "invalid event day search year".to_string(),
-                    ))?
-                };
-                let first_day_of_month = NaiveDate::from_ymd_opt(year, month, 1).ok_or((
+    // Filter items according to event days and the occurrence window. Repeating
+    // events are matched loosely here and then expanded once they're loaded.
+    let event_day_window = match event_day {
+        Some(event_day) => {
+            let timezone =
+                utils::parse_timezone(timezone.as_deref(), state.server_config.timezone)?;
+            let window =
+                event_day_window(event_day, event_day_falls_on.as_ref(), timezone).ok_or((
                     StatusCode::BAD_REQUEST,
                     "invalid event day search".to_string(),
                 ))?;
-                let first_day_of_month_start_time =
-                    first_day_of_month.and_time(NaiveTime::default()).and_utc();
-                let last_day_of_month =
-                    NaiveDate::from_ymd_opt(year, month, num_days_in_month.into()).ok_or((
-                        StatusCode::BAD_REQUEST,
-                        "invalid event day search".to_string(),
-                    ))?;
-                let end_time = NaiveTime::from_hms_opt(23, 59, 59).expect("time should be valid");
-                let last_day_of_month_end_time = last_day_of_month.and_time(end_time).and_utc();
-                sql_query = sql_query.filter(
-                    (schema::events::starts_at
-                        .ge(first_day_of_month_start_time)
-                        .and(schema::events::starts_at.le(last_day_of_month_end_time)))
-                    .or(schema::events::ends_at
-                        .ge(first_day_of_month_start_time)
-                        .and(schema::events::ends_at.le(last_day_of_month_end_time))),
-                );
-            }
-            Some(utils::DateFallsOn::Week) => {
-                let num_days_from_sunday = event_day.weekday().num_days_from_sunday();
-                let this_weeks_sunday = event_day - Duration::days(num_days_from_sunday.into());
-                let this_weeks_start_time =
-                    this_weeks_sunday.and_time(NaiveTime::default()).and_utc();
-                let end_time = NaiveTime::from_hms_opt(23, 59, 59).expect("time should be valid");
-                let this_weeks_saturday = this_weeks_sunday + Duration::days(6);
-                let this_weeks_end_time = this_weeks_saturday.and_time(end_time).and_utc();
-                sql_query = sql_query.filter(
-                    (schema::events::starts_at
-                        .ge(this_weeks_start_time)
-                        .and(schema::events::starts_at.le(this_weeks_end_time)))
-                    .or(schema::events::ends_at
-                        .ge(this_weeks_start_time)
-                        .and(schema::events::ends_at.le(this_weeks_end_time))),
-                );
-            }
-            Some(utils::DateFallsOn::Day) | None => {
-                let day_of_event_start_time = event_day.and_time(NaiveTime::default()).and_utc();
-                let end_time = NaiveTime::from_hms_opt(23, 59, 59).expect("time should be valid");
-                let day_of_event_end_time = event_day.and_time(end_time).and_utc();
-                sql_query = sql_query.filter(
-                    (schema::events::starts_at
-                        .ge(day_of_event_start_time)
-                        .and(schema::events::starts_at.le(day_of_event_end_time)))
-                    .or(schema::events::ends_at
-                        .ge(day_of_event_start_time)
-                        .and(schema::events::ends_at.le(day_of_event_end_time))),
-                );
-            }
+            Some(window)
         }
+        None => None,
+    };
+    let window_start = match (event_day_window, occurs_from) {
+        (Some((window_start, _)), Some(occurs_from)) => Some(window_start.max(occurs_from)),
+        (Some((window_start, _)), None) => Some(window_start),
+        (None, occurs_from) => occurs_from,
+    };
+    let window_end = match (event_day_window, occurs_to) {
+        (Some((_, window_end)), Some(occurs_to)) => Some(window_end.min(occurs_to)),
+        (Some((_, window_end)), None) => Some(window_end),
+        (None, occurs_to) => occurs_to,
+    };
+    if let Some(window_start) = window_start {
+        sql_query = sql_query.filter(
+            schema::events::ends_at
+                .ge(window_start)
+                .or(schema::events::recurrence_frequency.is_not_null().and(
+                    schema::events::recurrence_until
+                        .is_null()
+                        .or(schema::events::recurrence_until.ge(window_start)),
+                )),
+        );
+    }
+    if let Some(window_end) = window_end {
+        sql_query = sql_query.filter(schema::events::starts_at.le(window_end));
+    }
+    let event_window = if window_start.is_some() || window_end.is_some() {
+        Some((
+            window_start.unwrap_or(DateTime::<Utc>::MIN_UTC),
+            window_end.unwrap_or(DateTime::<Utc>::MAX_UTC),
+        ))
+    } else {
+        None
+    };
+
+    // Filter items that haven't started yet. Repeating events are also
+    // matched loosely here and then checked once they're loaded.
+    let upcoming_from = upcoming_only.unwrap_or_default().then(Utc::now);
+    if let Some(upcoming_from) = upcoming_from {
+        sql_query = sql_query.filter(
+            schema::events::starts_at
+                .ge(upcoming_from)
+                .or(schema::events::recurrence_frequency.is_not_null().and(
+                    schema::events::recurrence_until
+                        .is_null()
+                        .or(schema::events::recurrence_until.ge(upcoming_from)),
+                )),
+        );
     }
 
+    // Filter events at a place.
+    if let Some(place_id) = resolve_place(state, user_id, place_id, place_query, conn).await? {
+        sql_query = sql_query.filter(schema::events::place_id.eq(place_id));
+    }
+
+    // Items are only ordered by their distance to the query if they aren't
+    // ordered some other way.
+    let ordered_by_distance = order_by.is_none();
+
     // Order items.
     match order_by {
-        Some(utils::OrderBy::Oldest) => sql_query = sql_query.order(schema::events::created_at),
-        Some(utils::OrderBy::Newest) => {
+        Some(EventOrderBy::Oldest) => sql_query = sql_query.order(schema::events::created_at),
+        Some(EventOrderBy::Newest) => {
             sql_query = sql_query.order(schema::events::created_at.desc());
         }
+        Some(EventOrderBy::StartsSoonest) => {
+            sql_query = sql_query.order(schema::events::starts_at);
+        }
+        Some(EventOrderBy::StartsLatest) => {
+            sql_query = sql_query.order(schema::events::starts_at.desc());
+        }
         None => {
             // By default, filter items similar to a given query.
             if let Some(ref query) = query {
-                let input = EmbeddingPromptTemplate::builder()
-                    .instruction_prefix(INSTRUCTION_PREFIX.to_string())
-                    .query_prefix(QUERY_PREFIX.to_string())
-                    .build()
+                let input = state
+                    .embedding_instructions
+                    .template("events", INSTRUCTION_PREFIX, QUERY_PREFIX)
                     .apply(query);
                 let embedding_request = EmbeddingRequest { input };
-                let embedding = state.model_client.embed(embedding_request).await?;
+                let embedding = state
+                    .model_client
+                    .embed_cached(embedding_request, embeddings)
+                    .await?;
                 sql_query = sql_query
                     .filter(
                         schema::events::embedding
@@ -170,40 +269,197 @@ pub async fn search_events(
         sql_query = sql_query.or_filter(schema::events::id.eq_any(ids));
     }
 
-    // Limit number of items.
-    if let Some(limit) = limit {
+    // Only the user's own items are searched. This comes after the other
+    // filters so items selected by their ids are still only the user's.
+    sql_query = sql_query.filter(schema::events::user_id.eq(user_id));
+
+    // Limit number of items. Only the total is needed when counting items
+    // that don't need to be reranked, so only one item is loaded. Items
+    // ordered by when they start or filtered by when they occur are limited
+    // and counted once they're loaded instead since repeating events are
+    // ordered and filtered by their repeats. Items counted once they're
+    // reranked aren't limited so they're all counted.
+    let count_only = count_only.unwrap_or_default();
+    let order_by_start = order_by.is_some_and(EventOrderBy::by_start);
+    let page_once_loaded = order_by_start || event_window.is_some() || upcoming_from.is_some();
+    let count_total = count_only
+        && !page_once_loaded
+        && !search::needs_reranking(query.as_deref(), use_reranking_filter);
+    if count_total {
+        sql_query = sql_query.limit(1);
+    } else if let Some(limit) = limit.filter(|_| !count_only && !page_once_loaded) {
         sql_query = sql_query.limit(limit);
     }
 
+    // Skip a number of items.
+    if let Some(offset) = offset.filter(|_| !count_only && !page_once_loaded) {
+        sql_query = sql_query.offset(offset);
+    }
+
     // Get all the items that match the query.
-    let events: Vec<Event> = sql_query.load(conn).await.map_err(utils::diesel_error)?;
-    let (ids, documents): (Vec<i32>, Vec<String>) = events
+    let events: Vec<(Event, i64)> = sql_query.load(conn).await.map_err(utils::diesel_error)?;
+    let mut total = events.as_slice().first().map_or(0, |(_, total)| *total);
+    if count_total {
+        return Ok(Page::count(total));
+    }
+
+    // Find when each event, or its next repeat, occurs so repeating events
+    // can be filtered and ordered by it.
+    let mut events: Vec<(Event, DateTime<Utc>)> = events
         .into_iter()
-        .map(|event| (event.id, event.description))
-        .unzip();
-    if ids.is_empty() {
-        return Ok(ids);
+        .filter_map(|(event, _)| {
+            let occurs_at = match (upcoming_from, event_window) {
+                (Some(upcoming_from), event_window) => {
+                    let (window_start, window_end) = event_window
+                        .unwrap_or((DateTime::<Utc>::MIN_UTC, DateTime::<Utc>::MAX_UTC));
+                    event
+                        .next_start_from(upcoming_from.max(window_start))
+                        .filter(|starts_at| *starts_at <= window_end)
+                }
+                (None, Some((window_start, window_end))) => {
+                    event.first_occurrence_within(window_start, window_end)
+                }
+                (None, None) => Some(event.starts_at),
+            }?;
+            Some((event, occurs_at))
+        })
+        .collect();
+    if order_by_start {
+        if order_by == Some(EventOrderBy::StartsLatest) {
+            events.sort_by_key(|(_, occurs_at)| Reverse(*occurs_at));
+        } else {
+            events.sort_by_key(|(_, occurs_at)| *occurs_at);
+        }
+    }
+    if page_once_loaded {
+        total = events.len().try_into().unwrap_or(i64::MAX);
+        if !count_only {
+            let offset = offset
+                .and_then(|offset| usize::try_from(offset).ok())
+                .unwrap_or_default();
+            let limit = limit
+                .and_then(|limit| usize::try_from(limit).ok())
+                .unwrap_or(usize::MAX);
+            events = events.into_iter().skip(offset).take(limit).collect();
+        }
+    }
+    let ordered_ids: Vec<i32> = events.iter().map(|(event, _)| event.id).collect();
+    let mut ids_docs: Vec<(i32, String)> = events
+        .into_iter()
+        .map(|(event, _)| (event.id, event.description))
+        .collect();
+
+    // Items that aren't ordered by their distance to the query are put in
+    // that order so only the furthest ones are left out of reranking.
+    if !ordered_by_distance
+        && search::needs_reranking(query.as_deref(), use_reranking_filter)
+        && let Some(ref query) = query
+    {
+        let input = state
+            .embedding_instructions
+            .template("events", INSTRUCTION_PREFIX, QUERY_PREFIX)
+            .apply(query);
+        ids_docs =
+            search::closest_first(state, EmbeddedTable::Events, input, ids_docs, conn).await?;
+    }
+
+    // Rerank and filter items once more. Reranking orders items by
+    // relevance, so items ordered by when they start are put back in order.
+    let mut ids = search::rerank_filter(
+        state,
+        query,
+        use_reranking_filter,
+        ids_docs,
+        &RerankOptions::default(),
+    )
+    .await?;
+    if order_by_start {
+        ids.sort_by_key(|id| ordered_ids.iter().position(|other| other == id));
     }
 
-    // Rerank and filter items once more.
-    let ids = match (query, use_reranking_filter) {
-        (Some(query), Some(true)) => {
-            let rerank_request = RerankRequest { query, documents };
-            let rerank_response = state.model_client.rerank(rerank_request).await?;
-            rerank_response
-                .results
-                .into_iter()
-                .filter(|item| item.relevance_score >= state.server_config.similarity_threshold)
-                .map(|item| ids[item.index])
-                .collect()
+    if count_only {
+        return Ok(Page::count(ids.len().try_into().unwrap_or(i64::MAX)));
+    }
+
+    Ok(Page {
+        items: ids,
+        total,
+        offset,
+        limit,
+    })
+}
+
+/// Find an existing event that's the same as a new event. Only events that
+/// start at the same time are considered, and the closest one is a
+/// duplicate if it's within the duplicate distance threshold and the
+/// reranking API agrees.
+async fn find_duplicate_event(
+    state: &ToiState,
+    user_id: i32,
+    description: &str,
+    starts_at: DateTime<Utc>,
+    embedding: Vector,
+    conn: &mut utils::Conn<'_>,
+) -> Result<Option<Event>, ToiError> {
+    let event = schema::events::table
+        .select(Event::as_select())
+        .filter(schema::events::user_id.eq(user_id))
+        .filter(schema::events::starts_at.eq(starts_at))
+        .filter(
+            schema::events::embedding
+                .cosine_distance(embedding.clone())
+                .le(state.server_config.duplicate_distance_threshold),
+        )
+        .order(schema::events::embedding.cosine_distance(embedding))
+        .first(conn)
+        .await
+        .optional()
+        .map_err(utils::diesel_error)?;
+    match event {
+        Some(event)
+            if search::is_confirmed_duplicate(state, description, event.description.clone())
+                .await? =>
+        {
+            Ok(Some(event))
         }
-        _ => ids,
-    };
+        _ => Ok(None),
+    }
+}
 
-    Ok(ids)
+/// Find a user's existing events whose times overlap with a new event's.
+/// Events that only touch (one ends when the other starts) don't overlap,
+/// and events that end when they start are assumed to last an hour.
+/// Repeating events are only compared by their first occurrences.
+async fn find_conflicting_events(
+    user_id: i32,
+    starts_at: DateTime<Utc>,
+    ends_at: DateTime<Utc>,
+    conn: &mut utils::Conn<'_>,
+) -> Result<Vec<Event>, ToiError> {
+    schema::events::table
+        .select(Event::as_select())
+        .filter(schema::events::user_id.eq(user_id))
+        .filter(schema::events::starts_at.lt(conflict_end(starts_at, ends_at)))
+        .filter(
+            schema::events::ends_at
+                .gt(starts_at)
+                .or(schema::events::ends_at
+                    .eq(schema::events::starts_at)
+                    .and(schema::events::starts_at.gt(starts_at - UNTIMED_EVENT_DURATION))),
+        )
+        .order(schema::events::starts_at)
+        .load(conn)
+        .await
+        .map_err(utils::diesel_error)
 }
 
-/// Add and return an event.
+/// Add and return an event along with any existing events it conflicts
+/// with.
+///
+/// Events conflict if their times overlap. Events that end when they start
+/// are assumed to last an hour, and events that only touch (one ends when
+/// the other starts) don't conflict. Conflicting events are still added
+/// unless asked not to, and the conflicts should be relayed to the user.
 ///
 /// Example queries for adding an event using this endpoint:
 /// - Add an event with
@@ -217,8 +473,10 @@ pub async fn search_events(
     ),
     request_body = NewEventRequest,
     responses(
-        (status = 201, description = "Successfully added an event", body = Event),
-        (status = 400, description = "Default JSON elements configured by the user are invalid"),
+        (status = 201, description = "Successfully added an event, along with any existing events it conflicts with", body = EventCreated),
+        (status = 400, description = "Invalid event recurrence, event ends before it starts, or default JSON elements configured by the user are invalid"),
+        (status = 404, description = "Place not found"),
+        (status = 409, description = "A similar event already starts at the same time, the event conflicts with existing events and was asked not to, or the idempotency key was already used for a different request", body = Event),
         (status = 422, description = "Error when parsing a response from a model API"),
         (status = 502, description = "Error when forwarding request to model APIs")
     )
@@ -226,31 +484,117 @@ pub async fn search_events(
 #[axum::debug_handler]
 async fn add_event(
     State(state): State<ToiState>,
+    user: CurrentUser,
+    headers: HeaderMap,
     Json(params): Json<NewEventRequest>,
-) -> Result<Json<Event>, (StatusCode, String)> {
-    let mut conn = state.pool.get().await.map_err(utils::internal_error)?;
+) -> Result<Response, (StatusCode, String)> {
+    let idempotency_key =
+        IdempotencyKey::from_request(&state, &headers, user.id, "/events", &params)?;
+    let mut conn = utils::get_conn(&state.pool).await?;
+    // Replay repeats before embedding anything.
+    if let Some(key) = &idempotency_key
+        && let Some(event_created) = key.replay::<EventCreated>(&mut conn).await?
+    {
+        return Ok((StatusCode::OK, Json(event_created)).into_response());
+    }
     let NewEventRequest {
         description,
         starts_at,
         ends_at,
+        recurrence_frequency,
+        recurrence_interval,
+        recurrence_until,
+        place_id,
+        place_query,
+        allow_duplicate,
+        fail_on_conflict,
     } = params;
+    if recurrence_interval.is_some_and(|interval| interval < 1) {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            "event recurrence interval must be at least 1".to_string(),
+        ));
+    }
+    let recurrence_interval = recurrence_frequency.map(|_| recurrence_interval.unwrap_or(1));
+    let (starts_at, ends_at) =
+        order_event_times(starts_at, ends_at, state.server_config.swap_if_reversed)
+            .map_err(|err| (StatusCode::BAD_REQUEST, err))?;
+    let place_id = resolve_place(&state, user.id, place_id, place_query, &mut conn).await?;
     let embedding_request = EmbeddingRequest {
         input: description.clone(),
     };
     let embedding = state.model_client.embed(embedding_request).await?;
+    // Make sure the same event isn't already on the calendar if duplicates
+    // are being checked, returning the existing event if it is.
+    if state.server_config.duplicate_check_enabled
+        && !allow_duplicate.unwrap_or_default()
+        && let Some(event) = find_duplicate_event(
+            &state,
+            user.id,
+            &description,
+            starts_at,
+            embedding.clone(),
+            &mut conn,
+        )
+        .await?
+    {
+        return Ok((StatusCode::CONFLICT, Json(event)).into_response());
+    }
+    let conflicts = find_conflicting_events(user.id, starts_at, ends_at, &mut conn).await?;
+    if fail_on_conflict.unwrap_or_default() && !conflicts.is_empty() {
+        let conflicts: Vec<String> = conflicts
+            .iter()
+            .map(|event| {
+                format!(
+                    "{} (id {}, {} to {})",
+                    event.description, event.id, event.starts_at, event.ends_at
+                )
+            })
+            .collect();
+        return Err((
+            StatusCode::CONFLICT,
+            format!(
+                "event conflicts with existing events: {}",
+                conflicts.join("; ")
+            ),
+        ));
+    }
     let new_event = NewEvent {
+        user_id: user.id,
         description,
         embedding,
         starts_at,
         ends_at,
+        recurrence_frequency,
+        recurrence_interval,
+        recurrence_until,
+        place_id,
     };
-    let result = diesel::insert_into(schema::events::table)
-        .values(new_event)
-        .returning(Event::as_returning())
-        .get_result(&mut conn)
-        .await
-        .map_err(utils::diesel_error)?;
-    Ok(Json(result))
+    let result = conn
+        .transaction::<_, ToiError, _>(|mut conn| {
+            async move {
+                // Check again in case a request with the same key added the
+                // event while this one was embedding it.
+                if let Some(key) = &idempotency_key
+                    && let Some(event_created) = key.replay(&mut conn).await?
+                {
+                    return Ok(event_created);
+                }
+                let event = diesel::insert_into(schema::events::table)
+                    .values(new_event)
+                    .returning(Event::as_returning())
+                    .get_result(&mut conn)
+                    .await?;
+                let event_created = EventCreated { event, conflicts };
+                if let Some(key) = &idempotency_key {
+                    key.store(&event_created, &mut conn).await?;
+                }
+                Ok(event_created)
+            }
+            .scope_boxed()
+        })
+        .await?;
+    Ok((StatusCode::OK, Json(result)).into_response())
 }
 
 /// Delete and return events.
@@ -264,13 +608,13 @@ async fn add_event(
     post,
     path = "/delete",
     extensions(
-        ("x-json-schema-body" = json!(schema_for!(EventSearchParams)))
+        ("x-json-schema-body" = json!(schema_for!(DeleteParams<EventSearchParams>)))
     ),
-    request_body = EventSearchParams,
+    request_body = DeleteParams<EventSearchParams>,
     responses(
         (status = 200, description = "Successfully deleted events", body = [Event]),
-        (status = 400, description = "Default JSON elements configured by the user are invalid"),
-        (status = 404, description = "No events found"),
+        (status = 400, description = "Invalid timezone, default JSON elements configured by the user are invalid, or deleting every item wasn't confirmed"),
+        (status = 404, description = "No events found with the given IDs, or no place found"),
         (status = 422, description = "Error when parsing a response from a model API"),
         (status = 502, description = "Error when forwarding request to model APIs")
     )
@@ -278,15 +622,28 @@ async fn add_event(
 #[axum::debug_handler]
 async fn delete_matching_events(
     State(state): State<ToiState>,
-    Json(params): Json<EventSearchParams>,
+    user: CurrentUser,
+    Json(params): Json<DeleteParams<EventSearchParams>>,
 ) -> Result<Json<Vec<Event>>, (StatusCode, String)> {
-    let mut conn = state.pool.get().await.map_err(utils::internal_error)?;
-    let ids = search_events(&state, params, &mut conn).await?;
+    let params = params.into_checked()?;
+    let mut conn = utils::get_conn(&state.pool).await?;
+    let mut embeddings = EmbeddingCache::default();
+
+    // Items are always needed here, so counting is ignored.
+    let params = EventSearchParams {
+        count_only: None,
+        ..params
+    };
+    let requested_ids = params.ids.clone();
+    let ids = search_events(&state, user.id, params, &mut embeddings, &mut conn)
+        .await?
+        .items;
     let events = diesel::delete(schema::events::table.filter(schema::events::id.eq_any(ids)))
         .returning(Event::as_returning())
         .load(&mut conn)
         .await
         .map_err(utils::diesel_error)?;
+    utils::check_ids_found(requested_ids.as_deref(), &events, "events")?;
     Ok(Json(events))
 }
 
@@ -297,6 +654,7 @@ async fn delete_matching_events(
 /// - List all events
 /// - What events do I have on
 /// - How many events do I have
+/// - Where is my event
 #[utoipa::path(
     post,
     path = "/search",
@@ -305,9 +663,9 @@ async fn delete_matching_events(
     ),
     request_body = EventSearchParams,
     responses(
-        (status = 200, description = "Successfully got events", body = [Event]),
-        (status = 400, description = "Default JSON elements configured by the user are invalid"),
-        (status = 404, description = "No events found"),
+        (status = 200, description = "Successfully got events or their count", body = SearchResponse<EventWithPlace>),
+        (status = 400, description = "Invalid timezone, or default JSON elements configured by the user are invalid"),
+        (status = 404, description = "Place not found"),
         (status = 422, description = "Error when parsing a response from a model API"),
         (status = 502, description = "Error when forwarding request to model APIs")
     )
@@ -315,15 +673,254 @@ async fn delete_matching_events(
 #[axum::debug_handler]
 async fn get_matching_events(
     State(state): State<ToiState>,
+    user: CurrentUser,
     Json(params): Json<EventSearchParams>,
-) -> Result<Json<Vec<Event>>, (StatusCode, String)> {
-    let mut conn = state.pool.get().await.map_err(utils::internal_error)?;
-    let ids = search_events(&state, params, &mut conn).await?;
+) -> Result<Json<SearchResponse<EventWithPlace>>, (StatusCode, String)> {
+    let count_only = params.count_only.unwrap_or_default();
+    let include_place = params.include_place.unwrap_or_default();
+    let mut conn = utils::get_conn(&state.pool).await?;
+    let mut embeddings = EmbeddingCache::default();
+    let Page {
+        items: ids,
+        total,
+        offset,
+        limit,
+    } = search_events(&state, user.id, params, &mut embeddings, &mut conn).await?;
+    if count_only {
+        return Ok(Json(SearchResponse::Count(Count { count: total })));
+    }
+    let mut events: Vec<Event> = schema::events::table
+        .select(Event::as_select())
+        .filter(schema::events::id.eq_any(&ids))
+        .load(&mut conn)
+        .await
+        .map_err(utils::diesel_error)?;
+
+    // Keep events in the order they were searched in (e.g., soonest first).
+    events.sort_by_key(|event| ids.iter().position(|id| *id == event.id));
+
+    // Places for all the events are loaded at once rather than per event.
+    let mut places_by_id: HashMap<i32, Place> = HashMap::new();
+    if include_place {
+        let place_ids: Vec<i32> = events.iter().filter_map(|event| event.place_id).collect();
+        let places: Vec<Place> = schema::places::table
+            .select(Place::as_select())
+            .filter(schema::places::id.eq_any(place_ids))
+            .load(&mut conn)
+            .await
+            .map_err(utils::diesel_error)?;
+        places_by_id.extend(places.into_iter().map(|place| (place.id, place)));
+    }
+    let events = events
+        .into_iter()
+        .map(|event| {
+            let place = event
+                .place_id
+                .and_then(|place_id| places_by_id.get(&place_id).cloned());
+            EventWithPlace { event, place }
+        })
+        .collect();
+    Ok(Json(SearchResponse::Page(Page {
+        items: events,
+        total,
+        offset,
+        limit,
+    })))
+}
+
+/// Get upcoming events along with who's attending them.
+///
+/// Example queries for getting upcoming events using this endpoint:
+/// - What's coming up this week?
+/// - What's on my calendar today?
+/// - What do I have going on tomorrow?
+/// - Anything coming up next week?
+/// - What's on my schedule for the rest of the month?
+#[utoipa::path(
+    post,
+    path = "/upcoming",
+    extensions(
+        ("x-json-schema-body" = json!(schema_for!(UpcomingEventsRequest)))
+    ),
+    request_body = UpcomingEventsRequest,
+    responses(
+        (status = 200, description = "Successfully got upcoming events", body = [UpcomingEvent]),
+        (status = 400, description = "Invalid upcoming window or default JSON elements configured by the user are invalid"),
+        (status = 422, description = "Error when parsing a response from a model API"),
+        (status = 502, description = "Error when forwarding request to model APIs")
+    )
+)]
+#[axum::debug_handler]
+async fn get_upcoming_events(
+    State(state): State<ToiState>,
+    user: CurrentUser,
+    Json(params): Json<UpcomingEventsRequest>,
+) -> Result<Json<Vec<UpcomingEvent>>, (StatusCode, String)> {
+    let mut conn = utils::get_conn(&state.pool).await?;
+    let mut embeddings = EmbeddingCache::default();
+    let UpcomingEventsRequest { window, limit } = params;
+    let (window_start, window_end) = window
+        .range(Utc::now(), state.server_config.timezone)
+        .ok_or((
+            StatusCode::BAD_REQUEST,
+            "upcoming window is out of range".to_string(),
+        ))?;
+    let search_params = EventSearchParams::builder()
+        .occurs_from(window_start)
+        .occurs_to(window_end)
+        .build();
+    let ids = search_events(&state, user.id, search_params, &mut embeddings, &mut conn)
+        .await?
+        .items;
     let events = schema::events::table
         .select(Event::as_select())
-        .filter(schema::events::id.eq_any(ids))
+        .filter(schema::events::id.eq_any(&ids))
         .load(&mut conn)
         .await
         .map_err(utils::diesel_error)?;
-    Ok(Json(events))
+    let attendees: Vec<(i32, String, Option<String>)> = schema::event_attendees::table
+        .inner_join(schema::contacts::table)
+        .select((
+            schema::event_attendees::event_id,
+            schema::contacts::first_name,
+            schema::contacts::last_name,
+        ))
+        .filter(schema::event_attendees::event_id.eq_any(&ids))
+        .order((schema::contacts::first_name, schema::contacts::last_name))
+        .load(&mut conn)
+        .await
+        .map_err(utils::diesel_error)?;
+    let mut attendees_by_event: HashMap<i32, Vec<String>> = HashMap::new();
+    for (event_id, first_name, last_name) in attendees {
+        let name = match last_name {
+            Some(last_name) => format!("{first_name} {last_name}"),
+            None => first_name,
+        };
+        attendees_by_event.entry(event_id).or_default().push(name);
+    }
+    let mut upcoming_events: Vec<UpcomingEvent> = events
+        .into_iter()
+        .filter_map(|event| {
+            let occurs_at = event.first_occurrence_within(window_start, window_end)?;
+            let attendees = attendees_by_event.remove(&event.id).unwrap_or_default();
+            Some(UpcomingEvent {
+                event,
+                occurs_at,
+                attendees,
+            })
+        })
+        .collect();
+    upcoming_events.sort_by_key(|upcoming_event| upcoming_event.occurs_at);
+    if let Some(limit) = limit {
+        upcoming_events.truncate(limit);
+    }
+    Ok(Json(upcoming_events))
+}
+
+/// Get events as an iCalendar document, optionally only including events
+/// that occur within a window.
+#[utoipa::path(
+    get,
+    path = "/export.ics",
+    extensions(
+        ("x-json-schema-params" = json!(schema_for!(EventCalendarParams)))
+    ),
+    params(EventCalendarParams),
+    responses(
+        (status = 200, description = "Successfully got events as an iCalendar document", content_type = "text/calendar"),
+        (status = 400, description = "Window starts after it ends")
+    )
+)]
+#[axum::debug_handler]
+async fn get_event_calendar(
+    State(state): State<ToiState>,
+    user: CurrentUser,
+    Query(params): Query<EventCalendarParams>,
+) -> Result<Response, ToiError> {
+    let EventCalendarParams { from, to } = params;
+    if let (Some(from), Some(to)) = (from, to)
+        && from > to
+    {
+        return Err(ToiError::Validation(
+            "window start must be before its end".to_string(),
+        ));
+    }
+    let mut conn = utils::get_conn(&state.pool).await?;
+    let mut sql_query = schema::events::table
+        .select(Event::as_select())
+        .filter(schema::events::user_id.eq(user.id))
+        .order(schema::events::id)
+        .into_boxed();
+    if let Some(from) = from {
+        sql_query = sql_query.filter(
+            schema::events::ends_at
+                .ge(from)
+                .or(schema::events::recurrence_frequency.is_not_null().and(
+                    schema::events::recurrence_until
+                        .is_null()
+                        .or(schema::events::recurrence_until.ge(from)),
+                )),
+        );
+    }
+    if let Some(to) = to {
+        sql_query = sql_query.filter(schema::events::starts_at.le(to));
+    }
+    let events: Vec<Event> = sql_query
+        .load(&mut conn)
+        .await
+        .map_err(utils::diesel_error)?;
+
+    // Repeating events that are still going on might not have any repeats
+    // that actually fall within the window.
+    let window_start = from.unwrap_or(DateTime::<Utc>::MIN_UTC);
+    let window_end = to.unwrap_or(DateTime::<Utc>::MAX_UTC);
+    let mut calendar = CalendarWriter::new();
+    for event in events {
+        if !event.occurs_within(window_start, window_end) {
+            continue;
+        }
+        calendar.property("BEGIN", "VEVENT");
+        calendar.property("UID", &format!("event-{}@toi", event.id));
+        calendar.datetime("DTSTAMP", event.created_at);
+        calendar.datetime("DTSTART", event.starts_at);
+        calendar.datetime("DTEND", event.ends_at);
+        if let Some(rule) = event.recurrence_rule() {
+            calendar.property("RRULE", &rule);
+        }
+        let summary = event.description.lines().next().unwrap_or_default();
+        calendar.text("SUMMARY", summary);
+        calendar.text("DESCRIPTION", &event.description);
+        calendar.property("END", "VEVENT");
+    }
+    let body = calendar.finish();
+    Ok(([(header::CONTENT_TYPE, ics::CONTENT_TYPE)], body).into_response())
+}
+
+/// Get an event using its database-generated ID.
+#[utoipa::path(
+    get,
+    path = "/{id}",
+    params(
+        ("id" = i32, Path, description = "Database-generated event ID")
+    ),
+    responses(
+        (status = 200, description = "Successfully got an event", body = Event),
+        (status = 404, description = "Event not found")
+    )
+)]
+#[axum::debug_handler]
+async fn get_event(
+    State(state): State<ToiState>,
+    user: CurrentUser,
+    Path(id): Path<i32>,
+) -> Result<Json<Event>, (StatusCode, String)> {
+    let mut conn = utils::get_conn(&state.pool).await?;
+    let event = schema::events::table
+        .select(Event::as_select())
+        .filter(schema::events::id.eq(id))
+        .filter(schema::events::user_id.eq(user.id))
+        .first(&mut conn)
+        .await
+        .map_err(utils::diesel_error)?;
+    Ok(Json(event))
 }
@@ -0,0 +1,332 @@
+use axum::{
+    body::{Body, Bytes},
+    extract::{Path, Query, State},
+    http::header,
+    response::{IntoResponse, Response},
+};
+use diesel::{ExpressionMethods, QueryDsl, SelectableHelper};
+use diesel_async::RunQueryDsl;
+use futures::stream;
+use serde::Serialize;
+use std::collections::HashMap;
+use utoipa_axum::{router::OpenApiRouter, routes};
+
+use crate::{
+    auth::CurrentUser,
+    models::{
+        contacts::{Contact, ContactWithDetails},
+        error::ToiError,
+        events::Event,
+        export::{ContactExport, ExportDomain, ExportFormat, ExportParams, RecipeExport},
+        notes::Note,
+        recipes::Recipe,
+        state::ToiState,
+        todos::Todo,
+        transactions::LinkedTransaction,
+    },
+    ndjson,
+    routes::contacts::load_contact_details,
+    schema, utils,
+};
+
+// Items are loaded and written in batches so large tables aren't buffered
+// in memory.
+const BATCH_SIZE: i64 = 500;
+
+pub fn export_router(state: ToiState) -> OpenApiRouter {
+    OpenApiRouter::new()
+        .routes(routes!(export))
+        .with_state(state)
+}
+
+/// Items that can be exported in order of their database-generated IDs.
+trait ExportRecord: Serialize {
+    fn id(&self) -> i32;
+}
+
+impl ExportRecord for ContactExport {
+    fn id(&self) -> i32 {
+        self.id
+    }
+}
+
+impl ExportRecord for Event {
+    fn id(&self) -> i32 {
+        self.id
+    }
+}
+
+impl ExportRecord for LinkedTransaction {
+    fn id(&self) -> i32 {
+        self.id
+    }
+}
+
+impl ExportRecord for Note {
+    fn id(&self) -> i32 {
+        self.id
+    }
+}
+
+impl ExportRecord for RecipeExport {
+    fn id(&self) -> i32 {
+        self.id
+    }
+}
+
+impl ExportRecord for Todo {
+    fn id(&self) -> i32 {
+        self.id
+    }
+}
+
+fn serialize_batch<T: ExportRecord>(
+    records: &[T],
+    format: ExportFormat,
+    has_headers: bool,
+) -> Result<Vec<u8>, ToiError> {
+    match format {
+        ExportFormat::Jsonl => {
+            let mut buffer = vec![];
+            for record in records {
+                serde_json::to_writer(&mut buffer, record).map_err(utils::internal_error)?;
+                buffer.push(b'\n');
+            }
+            Ok(buffer)
+        }
+        ExportFormat::Csv => {
+            let mut writer = csv::WriterBuilder::new()
+                .has_headers(has_headers)
+                .from_writer(vec![]);
+            for record in records {
+                writer.serialize(record).map_err(utils::internal_error)?;
+            }
+            writer.into_inner().map_err(utils::internal_error)
+        }
+    }
+}
+
+/// Stream every item a user has in a table by repeatedly loading the batch
+/// of items after the last one written.
+fn export_body<T, F, Fut>(
+    pool: utils::Pool,
+    user_id: i32,
+    format: ExportFormat,
+    load_batch: F,
+) -> Body
+where
+    T: ExportRecord + Send + 'static,
+    F: Fn(utils::Pool, i32, i32) -> Fut + Send + 'static,
+    Fut: Future<Output = Result<Vec<T>, ToiError>> + Send + 'static,
+{
+    let stream = stream::unfold((Some(0), true), move |(last_id, is_first_batch)| {
+        let batch = last_id.map(|last_id| load_batch(pool.clone(), user_id, last_id));
+        async move {
+            let result = batch?.await.and_then(|records| {
+                let Some(last_id) = records.last().map(ExportRecord::id) else {
+                    return Ok(None);
+                };
+                let bytes = serialize_batch(&records, format, is_first_batch)?;
+                Ok(Some((last_id, bytes)))
+            });
+            match result {
+                Ok(Some((last_id, bytes))) => {
+                    Some((Ok(Bytes::from(bytes)), (Some(last_id), false)))
+                }
+                Ok(None) => None,
+                Err(err) => Some((Err(std::io::Error::other(err.to_string())), (None, false))),
+            }
+        }
+    });
+    Body::from_stream(stream)
+}
+
+async fn load_contacts(
+    pool: utils::Pool,
+    user_id: i32,
+    last_id: i32,
+) -> Result<Vec<ContactExport>, ToiError> {
+    let mut conn = utils::get_conn(&pool).await?;
+    let contacts: Vec<Contact> = schema::contacts::table
+        .select(Contact::as_select())
+        .filter(schema::contacts::user_id.eq(user_id))
+        .filter(schema::contacts::id.gt(last_id))
+        .order(schema::contacts::id)
+        .limit(BATCH_SIZE)
+        .load(&mut conn)
+        .await
+        .map_err(utils::diesel_error)?;
+    let contacts = load_contact_details(contacts, &mut conn)
+        .await?
+        .into_iter()
+        .map(|contact| {
+            let ContactWithDetails {
+                contact,
+                emails,
+                phones,
+            } = contact;
+            let emails: Vec<String> = emails
+                .into_iter()
+                .map(|email| format!("{}: {}", email.label, email.value))
+                .collect();
+            let phones: Vec<String> = phones
+                .into_iter()
+                .map(|phone| format!("{}: {}", phone.label, phone.value))
+                .collect();
+            ContactExport {
+                id: contact.id,
+                first_name: contact.first_name,
+                last_name: contact.last_name,
+                email: contact.email,
+                phone: contact.phone,
+                birthday: contact.birthday,
+                relationship: contact.relationship,
+                created_at: contact.created_at,
+                emails: emails.join("; "),
+                phones: phones.join("; "),
+            }
+        })
+        .collect();
+    Ok(contacts)
+}
+
+async fn load_events(
+    pool: utils::Pool,
+    user_id: i32,
+    last_id: i32,
+) -> Result<Vec<Event>, ToiError> {
+    let mut conn = utils::get_conn(&pool).await?;
+    schema::events::table
+        .select(Event::as_select())
+        .filter(schema::events::user_id.eq(user_id))
+        .filter(schema::events::id.gt(last_id))
+        .order(schema::events::id)
+        .limit(BATCH_SIZE)
+        .load(&mut conn)
+        .await
+        .map_err(utils::diesel_error)
+}
+
+async fn load_notes(pool: utils::Pool, user_id: i32, last_id: i32) -> Result<Vec<Note>, ToiError> {
+    let mut conn = utils::get_conn(&pool).await?;
+    schema::notes::table
+        .select(Note::as_select())
+        .filter(schema::notes::user_id.eq(user_id))
+        .filter(schema::notes::id.gt(last_id))
+        .order(schema::notes::id)
+        .limit(BATCH_SIZE)
+        .load(&mut conn)
+        .await
+        .map_err(utils::diesel_error)
+}
+
+async fn load_recipes(
+    pool: utils::Pool,
+    user_id: i32,
+    last_id: i32,
+) -> Result<Vec<RecipeExport>, ToiError> {
+    let mut conn = utils::get_conn(&pool).await?;
+    let recipes: Vec<Recipe> = schema::recipes::table
+        .select(Recipe::as_select())
+        .filter(schema::recipes::user_id.eq(user_id))
+        .filter(schema::recipes::id.gt(last_id))
+        .order(schema::recipes::id)
+        .limit(BATCH_SIZE)
+        .load(&mut conn)
+        .await
+        .map_err(utils::diesel_error)?;
+    let recipe_ids: Vec<i32> = recipes.iter().map(|recipe| recipe.id).collect();
+    let recipe_tags: Vec<(i32, String)> = schema::recipe_tags::table
+        .inner_join(schema::tags::table)
+        .select((schema::recipe_tags::recipe_id, schema::tags::name))
+        .filter(schema::recipe_tags::recipe_id.eq_any(&recipe_ids))
+        .order(schema::tags::name)
+        .load(&mut conn)
+        .await
+        .map_err(utils::diesel_error)?;
+    let mut tags_by_recipe: HashMap<i32, Vec<String>> = HashMap::new();
+    for (recipe_id, tag) in recipe_tags {
+        tags_by_recipe.entry(recipe_id).or_default().push(tag);
+    }
+    let recipes = recipes
+        .into_iter()
+        .map(|recipe| RecipeExport {
+            tags: tags_by_recipe
+                .remove(&recipe.id)
+                .unwrap_or_default()
+                .join(", "),
+            id: recipe.id,
+            description: recipe.description,
+            ingredients: recipe.ingredients,
+            instructions: recipe.instructions,
+            created_at: recipe.created_at,
+        })
+        .collect();
+    Ok(recipes)
+}
+
+async fn load_todos(pool: utils::Pool, user_id: i32, last_id: i32) -> Result<Vec<Todo>, ToiError> {
+    let mut conn = utils::get_conn(&pool).await?;
+    schema::todos::table
+        .select(Todo::as_select())
+        .filter(schema::todos::user_id.eq(user_id))
+        .filter(schema::todos::id.gt(last_id))
+        .order(schema::todos::id)
+        .limit(BATCH_SIZE)
+        .load(&mut conn)
+        .await
+        .map_err(utils::diesel_error)
+}
+
+async fn load_transactions(
+    pool: utils::Pool,
+    user_id: i32,
+    last_id: i32,
+) -> Result<Vec<LinkedTransaction>, ToiError> {
+    let mut conn = utils::get_conn(&pool).await?;
+    schema::transactions::table
+        .select(LinkedTransaction::as_select())
+        .filter(schema::transactions::user_id.eq(user_id))
+        .filter(schema::transactions::id.gt(last_id))
+        .order(schema::transactions::id)
+        .limit(BATCH_SIZE)
+        .load(&mut conn)
+        .await
+        .map_err(utils::diesel_error)
+}
+
+/// Export every item of a kind, without embeddings, as JSON lines or CSV.
+#[utoipa::path(
+    get,
+    path = "/{domain}",
+    params(
+        ("domain" = ExportDomain, Path, description = "Kind of items to export"),
+        ExportParams
+    ),
+    responses(
+        (status = 200, description = "Successfully exported items as JSON lines or CSV")
+    )
+)]
+#[axum::debug_handler]
+async fn export(
+    State(state): State<ToiState>,
+    user: CurrentUser,
+    Path(domain): Path<ExportDomain>,
+    Query(params): Query<ExportParams>,
+) -> Response {
+    let ExportParams { format } = params;
+    let pool = state.pool;
+    let body = match domain {
+        ExportDomain::Contacts => export_body(pool, user.id, format, load_contacts),
+        ExportDomain::Events => export_body(pool, user.id, format, load_events),
+        ExportDomain::Notes => export_body(pool, user.id, format, load_notes),
+        ExportDomain::Recipes => export_body(pool, user.id, format, load_recipes),
+        ExportDomain::Todos => export_body(pool, user.id, format, load_todos),
+        ExportDomain::Transactions => export_body(pool, user.id, format, load_transactions),
+    };
+    let content_type = match format {
+        ExportFormat::Jsonl => ndjson::CONTENT_TYPE,
+        ExportFormat::Csv => "text/csv",
+    };
+    ([(header::CONTENT_TYPE, content_type)], body).into_response()
+}
@@ -0,0 +1,75 @@
+use axum::{extract::State, http::StatusCode, response::Json};
+use diesel_async::RunQueryDsl;
+use std::time::Duration;
+use utoipa_axum::{router::OpenApiRouter, routes};
+
+use crate::models::{
+    health::{DependencyHealth, Readiness},
+    state::ToiState,
+};
+
+pub fn health_router(state: ToiState) -> OpenApiRouter {
+    OpenApiRouter::new()
+        .routes(routes!(get_health))
+        .routes(routes!(get_readiness))
+        .with_state(state)
+}
+
+async fn ping_database(state: &ToiState, timeout: Duration) -> Result<(), String> {
+    let query = async {
+        let mut conn = state.pool.get().await.map_err(|err| err.to_string())?;
+        diesel::sql_query("SELECT 1")
+            .execute(&mut conn)
+            .await
+            .map_err(|err| err.to_string())
+    };
+    tokio::time::timeout(timeout, query)
+        .await
+        .map_err(|_| "timed out waiting for the database".to_string())?
+        .map(|_| ())
+}
+
+/// Check whether the server process is up.
+#[utoipa::path(
+    get,
+    path = "/health",
+    responses(
+        (status = 200, description = "Server is up")
+    )
+)]
+#[axum::debug_handler]
+async fn get_health() -> StatusCode {
+    StatusCode::OK
+}
+
+/// Check whether the server and all of its dependencies are usable.
+#[utoipa::path(
+    get,
+    path = "/ready",
+    responses(
+        (status = 200, description = "Server and all dependencies are usable", body = Readiness),
+        (status = 503, description = "At least one dependency isn't usable", body = Readiness)
+    )
+)]
+#[axum::debug_handler]
+async fn get_readiness(State(state): State<ToiState>) -> (StatusCode, Json<Readiness>) {
+    let timeout = Duration::from_secs(state.server_config.readiness_timeout);
+    let (database, embedding, generation, reranking) = tokio::join!(
+        ping_database(&state, timeout),
+        state.model_client.ping_embedding_api(timeout),
+        state.model_client.ping_generation_api(timeout),
+        state.model_client.ping_reranking_api(timeout),
+    );
+    let readiness = Readiness {
+        database: DependencyHealth::from(database),
+        embedding: DependencyHealth::from(embedding),
+        generation: DependencyHealth::from(generation),
+        reranking: DependencyHealth::from(reranking),
+    };
+    let status = if readiness.is_ready() {
+        StatusCode::OK
+    } else {
+        StatusCode::SERVICE_UNAVAILABLE
+    };
+    (status, Json(readiness))
+}
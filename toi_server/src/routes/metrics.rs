@@ -0,0 +1,35 @@
+use axum::{
+    extract::State,
+    http::header,
+    response::{IntoResponse, Response},
+};
+use utoipa_axum::{router::OpenApiRouter, routes};
+
+use crate::models::{error::ToiError, pool::PoolStatus, state::ToiState};
+
+pub fn metrics_router(state: ToiState) -> OpenApiRouter {
+    OpenApiRouter::new()
+        .routes(routes!(get_metrics))
+        .with_state(state)
+}
+
+/// Get request counts and latencies for the model APIs and the server's
+/// routes, along with database connection pool waits, in the Prometheus
+/// text format.
+#[utoipa::path(
+    get,
+    path = "",
+    responses(
+        (status = 200, description = "Successfully got metrics", body = String, content_type = "text/plain"),
+        (status = 404, description = "Metrics aren't enabled")
+    )
+)]
+#[axum::debug_handler]
+async fn get_metrics(State(state): State<ToiState>) -> Result<Response, ToiError> {
+    let pool_status = PoolStatus::from(state.pool.state());
+    let body = state
+        .metrics
+        .render(&pool_status)
+        .ok_or_else(|| ToiError::NotFound("metrics aren't enabled".to_string()))?;
+    Ok(([(header::CONTENT_TYPE, "text/plain; version=0.0.4")], body).into_response())
+}
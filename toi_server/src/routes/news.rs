@@ -3,10 +3,12 @@ use axum::{
     http::StatusCode,
     response::{Json, Redirect},
 };
-use chrono::{Duration, Utc};
+use chrono::{DateTime, Duration, Utc};
 use diesel::{ExpressionMethods, PgSortExpressionMethods, QueryDsl, SelectableHelper};
-use diesel_async::{AsyncConnection, RunQueryDsl, scoped_futures::ScopedFutureExt};
-use rand::seq::SliceRandom;
+use diesel_async::{
+    AsyncConnection, AsyncPgConnection, RunQueryDsl, scoped_futures::ScopedFutureExt,
+};
+use rand::seq::{IndexedRandom, SliceRandom};
 use schemars::schema_for;
 use tracing::debug;
 use utoipa_axum::{router::OpenApiRouter, routes};
@@ -14,14 +16,129 @@ use utoipa_axum::{router::OpenApiRouter, routes};
 use crate::{
     models::{
         client::ApiClientError,
-        news::{Alias, ExpiredRedirect, GetNewsRequest, NewAlias, NewRedirect, News},
+        error::ToiError,
+        news::{Alias, ExpiredRedirect, GetNewsRequest, NewAlias, NewRedirect, News, NewsFeed},
         state::ToiState,
     },
-    schema, utils,
+    schema,
+    search::{self, RerankOptions},
+    utils,
 };
 
 const ALIASES: &str = include_str!("../../data/aliases.txt");
 
+// Characters and length of aliases minted when there aren't enough aliases
+// to recycle for all news items.
+const ALIAS_CHARS: &[u8] = b"abcdefghijklmnopqrstuvwxyz0123456789";
+const ALIAS_LENGTH: usize = 8;
+
+// Source given to Google News articles that don't name their publisher.
+const GOOGLE_NEWS: &str = "Google News";
+
+/// Address that news redirects point back to.
+fn news_addr(state: &ToiState) -> String {
+    format!("127.0.0.1:{}", state.server_config.bind_addr.port())
+}
+
+/// Generate random aliases. These may collide with existing aliases, so
+/// they're only candidates until they're inserted.
+fn random_aliases(count: usize) -> Vec<String> {
+    let mut rng = rand::rng();
+    (0..count)
+        .map(|_| {
+            (0..ALIAS_LENGTH)
+                .filter_map(|_| ALIAS_CHARS.choose(&mut rng).map(|&c| char::from(c)))
+                .collect()
+        })
+        .collect()
+}
+
+/// Mint new, unused aliases, retrying any that collide with existing ones.
+async fn mint_aliases(
+    conn: &mut AsyncPgConnection,
+    server_addr: &str,
+    count: usize,
+) -> diesel::QueryResult<Vec<Alias>> {
+    let mut minted: Vec<Alias> = vec![];
+    while minted.len() < count {
+        let new_aliases: Vec<NewAlias> = random_aliases(count - minted.len())
+            .into_iter()
+            .map(|alias| NewAlias::new(server_addr, alias))
+            .collect();
+        let aliases = diesel::insert_into(schema::news::table)
+            .values(&new_aliases)
+            .on_conflict_do_nothing()
+            .returning(Alias::as_returning())
+            .load(conn)
+            .await?;
+        minted.extend(aliases);
+    }
+    Ok(minted)
+}
+
+/// Assign an alias to each news item, returning the redirects to them.
+/// The least recently used aliases are recycled first, and new aliases
+/// are minted if there aren't enough aliases to go around. Aliases being
+/// recycled by concurrent requests are skipped rather than waited on.
+pub async fn alias_news_items(
+    conn: &mut AsyncPgConnection,
+    server_addr: &str,
+    items: Vec<rss::Item>,
+) -> diesel::QueryResult<Vec<NewRedirect>> {
+    conn.transaction(|mut conn| {
+        async move {
+            // Get the aliases to recycle as redirects for the news items.
+            let mut aliases: Vec<String> = schema::news::table
+                .select(schema::news::alias)
+                .order_by(schema::news::updated_at.asc().nulls_first())
+                .limit(
+                    items
+                        .len()
+                        .try_into()
+                        .expect("news items length should fit in i64"),
+                )
+                .for_update()
+                .skip_locked()
+                .load(&mut conn)
+                .await?;
+            if aliases.len() < items.len() {
+                let minted =
+                    mint_aliases(&mut conn, server_addr, items.len() - aliases.len()).await?;
+                debug!("minted {} news aliases", minted.len());
+                aliases.extend(minted.into_iter().map(|alias| alias.alias));
+            }
+            // Delete all the selected aliases. Have to do this because we
+            // can't batch update. Instead of batch updating, we batch delete
+            // and then batch insert in one transaction.
+            let aliases =
+                diesel::delete(schema::news::table.filter(schema::news::alias.eq_any(&aliases)))
+                    .returning(Alias::as_returning())
+                    .load(&mut conn)
+                    .await?;
+            // Insert the new news items, filling back in the deleted aliases.
+            let news: Vec<News> = aliases
+                .into_iter()
+                .zip(items.into_iter())
+                .map(|(alias, item)| News {
+                    alias: alias.alias,
+                    tinyurl: alias.tinyurl,
+                    url: item.link,
+                    title: item.title,
+                    updated_at: Some(Utc::now()),
+                    source: item.source.and_then(|source| source.title),
+                })
+                .collect();
+            diesel::insert_into(schema::news::table)
+                .values(news)
+                .returning(NewRedirect::as_returning())
+                .load(&mut conn)
+                .await
+        }
+        .scope_boxed()
+    })
+    .await
+}
+
 pub async fn news_router(state: ToiState) -> Result<OpenApiRouter, Box<dyn std::error::Error>> {
     let mut new_aliases: Vec<String> = ALIASES
         .lines()
@@ -36,7 +153,7 @@ pub async fn news_router(state: ToiState) -> Result<OpenApiRouter, Box<dyn std::
     new_aliases.sort();
     new_aliases.dedup();
     new_aliases.shuffle(&mut rand::rng());
-    let server_addr = format!("127.0.0.1:{}", &state.server_config.bind_addr.port());
+    let server_addr = news_addr(&state);
     let new_aliases: Vec<NewAlias> = new_aliases
         .into_iter()
         .map(|alias| NewAlias::new(&server_addr, alias))
@@ -50,6 +167,23 @@ pub async fn news_router(state: ToiState) -> Result<OpenApiRouter, Box<dyn std::
         .execute(&mut conn)
         .await?;
 
+    // Feeds are replaced with the configured ones so feeds removed from the
+    // config aren't picked anymore.
+    let feeds: Vec<NewsFeed> = state
+        .news_config
+        .feeds
+        .iter()
+        .cloned()
+        .map(NewsFeed::from)
+        .collect();
+    diesel::delete(schema::news_feeds::table)
+        .execute(&mut conn)
+        .await?;
+    diesel::insert_into(schema::news_feeds::table)
+        .values(&feeds)
+        .execute(&mut conn)
+        .await?;
+
     drop(conn);
 
     let router = OpenApiRouter::new()
@@ -59,6 +193,76 @@ pub async fn news_router(state: ToiState) -> Result<OpenApiRouter, Box<dyn std::
     Ok(router)
 }
 
+/// Find the configured feed to get news from. Sources are matched against
+/// feed names using the reranking API so they don't have to be named
+/// exactly, and the default feed is used if nothing specific is requested.
+/// Nothing is returned if news should come from Google News instead.
+async fn find_news_feed(
+    state: &ToiState,
+    conn: &mut AsyncPgConnection,
+    body: &GetNewsRequest,
+) -> Result<Option<NewsFeed>, ToiError> {
+    let feeds: Vec<NewsFeed> = schema::news_feeds::table
+        .select(NewsFeed::as_select())
+        .order_by(schema::news_feeds::name)
+        .load(conn)
+        .await
+        .map_err(utils::diesel_error)?;
+    match &body.source {
+        Some(source) => {
+            let ids_docs = feeds
+                .iter()
+                .enumerate()
+                .map(|(index, feed)| {
+                    let id = i32::try_from(index).expect("news feed count should fit in i32");
+                    (id, feed.name.clone())
+                })
+                .collect();
+            let ids = search::rerank_filter(
+                state,
+                Some(source.clone()),
+                Some(true),
+                ids_docs,
+                &RerankOptions::default(),
+            )
+            .await?;
+            let feed = ids
+                .as_slice()
+                .first()
+                .and_then(|id| usize::try_from(*id).ok())
+                .and_then(|index| feeds.into_iter().nth(index));
+            if feed.is_none() {
+                debug!("no news feed matches source={source}");
+            }
+            Ok(feed)
+        }
+        None if body.query.is_none() && body.when.is_none() => {
+            Ok(feeds.into_iter().find(|feed| feed.is_default))
+        }
+        None => Ok(None),
+    }
+}
+
+/// Whether a feed's item matches the query and was published within the
+/// hours asked for. Feeds can't be searched like Google News can, so their
+/// items are filtered after they're fetched. Items without publish dates
+/// are kept.
+fn matches_news_request(item: &rss::Item, query: Option<&str>, when: Option<u8>) -> bool {
+    let matches_query = query.is_none_or(|query| {
+        let query = query.to_lowercase();
+        [&item.title, &item.description]
+            .into_iter()
+            .flatten()
+            .any(|text| text.to_lowercase().contains(&query))
+    });
+    let matches_when = match (when, &item.pub_date) {
+        (Some(when), Some(pub_date)) => DateTime::parse_from_rfc2822(pub_date)
+            .is_ok_and(|pub_date| pub_date >= Utc::now() - Duration::hours(i64::from(when))),
+        _ => true,
+    };
+    matches_query && matches_when
+}
+
 /// Get a specific news article by its alias.
 #[utoipa::path(
     get,
@@ -72,7 +276,7 @@ async fn get_news_article(
     State(state): State<ToiState>,
     Path(alias): Path<String>,
 ) -> Result<Redirect, (StatusCode, String)> {
-    let mut conn = state.pool.get().await.map_err(utils::internal_error)?;
+    let mut conn = utils::get_conn(&state.pool).await?;
     let cutoff = Utc::now() - Duration::hours(24);
     let aliases: Vec<String> = schema::news::table
         .select(schema::news::alias)
@@ -104,6 +308,7 @@ async fn get_news_article(
 /// Example queries for getting news using this endpoint:
 /// - Get news from apnews.com.
 /// - Get news from the past 10 hours.
+/// - Get the top 5 stories from Hacker News.
 /// - Show me good news.
 #[utoipa::path(
     post,
@@ -114,7 +319,7 @@ async fn get_news_article(
     request_body = GetNewsRequest,
     responses(
         (status = 201, description = "Successfully got news", body = [NewRedirect]),
-        (status = 400, description = "Default JSON elements configured by the user are invalid"),
+        (status = 400, description = "Max items is less than 1, or default JSON elements configured by the user are invalid"),
         (status = 422, description = "Error when parsing a response from a model API"),
         (status = 502, description = "Error when forwarding request to model APIs")
     )
@@ -124,7 +329,7 @@ async fn get_news(
     State(state): State<ToiState>,
     Json(body): Json<GetNewsRequest>,
 ) -> Result<Json<Vec<NewRedirect>>, (StatusCode, String)> {
-    let mut conn = state.pool.get().await.map_err(utils::internal_error)?;
+    let mut conn = utils::get_conn(&state.pool).await?;
     // First, expire old links.
     let cutoff = Utc::now() - Duration::hours(24);
     let aliases: Vec<String> = schema::news::table
@@ -140,14 +345,30 @@ async fn get_news(
             .await
             .map_err(utils::diesel_error)?;
     }
-    // Get the RSS query from the body.
-    let (url, params) = body.into();
-    debug!("getting rss feed with {params:?}");
+    let max_items = match body.max_items {
+        Some(max_items) if max_items < 1 => {
+            return Err(ToiError::Validation("max_items must be at least 1".to_string()).into());
+        }
+        Some(max_items) => usize::try_from(max_items).ok(),
+        None => None,
+    };
+    // Get news from a configured feed if one's asked for, or search Google
+    // News otherwise.
+    let (query, when) = (body.query.clone(), body.when);
+    let feed = find_news_feed(&state, &mut conn, &body).await?;
+    let request = match &feed {
+        Some(feed) => {
+            debug!("getting rss feed {}", feed.name);
+            state.api_client.get(&feed.url)
+        }
+        None => {
+            let (url, params) = body.into();
+            debug!("getting rss feed with {params:?}");
+            state.api_client.get(url).query(&params)
+        }
+    };
     // Get RSS items from the feed.
-    let content = state
-        .api_client
-        .get(url)
-        .query(&params)
+    let content = request
         .send()
         .await
         .map_err(|err| ApiClientError::ApiConnection.into_response(&err))?
@@ -155,58 +376,38 @@ async fn get_news(
         .await
         .map_err(|err| ApiClientError::ApiConnection.into_response(&err))?;
     let channel = rss::Channel::read_from(&content[..]).map_err(utils::internal_error)?;
-    let items: Vec<rss::Item> = channel
+    let mut items: Vec<rss::Item> = channel
         .items
         .into_iter()
         .filter(|item| item.title.is_some() && item.link.is_some())
         .collect();
+    // Items from configured feeds are attributed to the feed, while Google
+    // News items keep their publisher.
+    match feed {
+        Some(feed) => {
+            items.retain(|item| matches_news_request(item, query.as_deref(), when));
+            for item in &mut items {
+                item.source = Some(rss::Source {
+                    url: feed.url.clone(),
+                    title: Some(feed.name.clone()),
+                });
+            }
+        }
+        None => {
+            for item in items.iter_mut().filter(|item| item.source.is_none()) {
+                item.source = Some(rss::Source {
+                    url: channel.link.clone(),
+                    title: Some(GOOGLE_NEWS.to_string()),
+                });
+            }
+        }
+    }
+    if let Some(max_items) = max_items {
+        items.truncate(max_items);
+    }
     debug!("got {} news items", items.len());
     // Convert the items into redirects that're sent to the client.
-    let redirects = conn
-        .transaction(|mut conn| {
-            async move {
-                // Get all the aliases used as redirects for the RSS items.
-                let aliases: Vec<String> = schema::news::table
-                    .select(schema::news::alias)
-                    .order_by(schema::news::updated_at.asc().nulls_first())
-                    .limit(
-                        items
-                            .len()
-                            .try_into()
-                            .expect("news items length should fit in i64"),
-                    )
-                    .load(&mut conn)
-                    .await?;
-                // Delete all the selected aliases. Have to do this because we
-                // can't batch update. Instead of batch updating, we batch delete
-                // and then batch insert in one transaction.
-                let aliases = diesel::delete(
-                    schema::news::table.filter(schema::news::alias.eq_any(&aliases)),
-                )
-                .returning(Alias::as_returning())
-                .load(&mut conn)
-                .await?;
-                // Insert the new news items, filling back in the deleted aliases.
-                let news: Vec<News> = aliases
-                    .into_iter()
-                    .zip(items.into_iter())
-                    .map(|(alias, item)| News {
-                        alias: alias.alias,
-                        tinyurl: alias.tinyurl,
-                        url: item.link,
-                        title: item.title,
-                        updated_at: Some(Utc::now()),
-                    })
-                    .collect();
-                let redirects = diesel::insert_into(schema::news::table)
-                    .values(news)
-                    .returning(NewRedirect::as_returning())
-                    .load(&mut conn)
-                    .await?;
-                Ok(redirects)
-            }
-            .scope_boxed()
-        })
+    let redirects = alias_news_items(&mut conn, &news_addr(&state), items)
         .await
         .map_err(utils::diesel_error)?;
     Ok(Json(redirects))
@@ -1,17 +1,44 @@
-use axum::{extract::State, http::StatusCode, response::Json};
-use diesel::{ExpressionMethods, QueryDsl, SelectableHelper};
-use diesel_async::RunQueryDsl;
-use pgvector::VectorExpressionMethods;
+use axum::{
+    extract::{Path, State},
+    http::{HeaderMap, StatusCode},
+    response::{IntoResponse, Json, Response},
+};
+use chrono::{DateTime, Duration, Utc};
+use diesel::{
+    BoolExpressionMethods, ExpressionMethods, OptionalExtension, QueryDsl, SelectableHelper,
+    dsl::sql, sql_types::Double,
+};
+use diesel_async::{
+    AsyncConnection, AsyncPgConnection, RunQueryDsl, scoped_futures::ScopedFutureExt,
+};
+use pgvector::{Vector, VectorExpressionMethods};
 use schemars::schema_for;
+use std::collections::HashMap;
+use tracing::{info, warn};
 use utoipa_axum::{router::OpenApiRouter, routes};
 
 use crate::{
+    auth::CurrentUser,
+    embeddings::EmbeddedTable,
+    idempotency::IdempotencyKey,
     models::{
-        client::{EmbeddingPromptTemplate, EmbeddingRequest, RerankRequest},
-        notes::{NewNote, NewNoteRequest, Note, NoteSearchParams},
+        client::{BatchEmbeddingRequest, EmbeddingCache, EmbeddingRequest},
+        deletion::DeleteParams,
+        error::ToiError,
+        notes::{
+            AppendNoteRequest, ArchiveNotesRequest, BulkNoteImportRequest, NewNote, NewNoteChunk,
+            NewNoteRequest, NewNoteTag, NewNoteTagsRequest, Note, NoteSearchParams,
+            NoteTagSearchParams, NoteTags, PinNotesRequest, chunk_content,
+        },
+        pagination::{Count, Page, SearchResponse},
         state::ToiState,
+        tags::{Tag, TagSearchParams},
     },
-    schema, utils,
+    ndjson,
+    routes::tags::{resolve_tags, search_tags},
+    schema,
+    search::{self, RerankOptions},
+    utils,
 };
 
 // Prefixes are used for embedding instructions.
@@ -22,27 +49,127 @@ const QUERY_PREFIX: &str = "Query: ";
 pub fn notes_router(state: ToiState) -> OpenApiRouter {
     OpenApiRouter::new()
         .routes(routes!(add_note))
+        .routes(routes!(add_notes))
+        .routes(routes!(append_to_matching_note))
+        .routes(routes!(archive_matching_notes))
         .routes(routes!(delete_matching_notes))
         .routes(routes!(get_matching_notes))
+        .routes(routes!(pin_matching_notes))
+        .routes(routes!(purge_deleted_notes))
+        .routes(routes!(restore_matching_notes))
+        .routes(routes!(add_note_tags))
+        .routes(routes!(get_matching_note_tags))
+        .routes(routes!(delete_matching_note_tags))
+        .routes(routes!(get_note))
         .with_state(state)
 }
 
+/// Embed the chunks of a long note so searches can match any part of it.
+/// Notes short enough to not be split into chunks don't get any.
+pub async fn embed_note_chunks(
+    state: &ToiState,
+    content: &str,
+) -> Result<Vec<(String, Vector)>, ToiError> {
+    let chunks = chunk_content(
+        content,
+        state.server_config.note_chunk_chars,
+        state.server_config.note_chunk_overlap_chars,
+    );
+    if chunks.is_empty() {
+        return Ok(vec![]);
+    }
+    let embedding_request = BatchEmbeddingRequest {
+        input: chunks.clone(),
+    };
+    let embeddings = state.model_client.embed_batch(embedding_request).await?;
+    Ok(chunks.into_iter().zip(embeddings).collect())
+}
+
+/// Replace a note's chunks with newly embedded ones. Meant to be called
+/// within the same transaction that adds or updates the note.
+pub async fn replace_note_chunks(
+    note_id: i32,
+    chunks: Vec<(String, Vector)>,
+    conn: &mut AsyncPgConnection,
+) -> diesel::QueryResult<()> {
+    diesel::delete(schema::note_chunks::table.filter(schema::note_chunks::note_id.eq(note_id)))
+        .execute(conn)
+        .await?;
+    let new_note_chunks: Vec<NewNoteChunk> = chunks
+        .into_iter()
+        .zip(0..)
+        .map(|((content, embedding), chunk_index)| NewNoteChunk {
+            note_id,
+            chunk_index,
+            content,
+            embedding,
+        })
+        .collect();
+    diesel::insert_into(schema::note_chunks::table)
+        .values(new_note_chunks)
+        .execute(conn)
+        .await?;
+    Ok(())
+}
+
 pub async fn search_notes(
     state: &ToiState,
+    user_id: i32,
     params: NoteSearchParams,
+    trash: utils::Scope,
     conn: &mut utils::Conn<'_>,
-) -> Result<Vec<i32>, (StatusCode, String)> {
+) -> Result<Page<i32>, ToiError> {
+    // The total is counted alongside a page's items, so it's counted on
+    // its own when the offset is past the end and there aren't any.
+    let count_params = (params.offset.is_some_and(|offset| offset > 0)
+        && !params.count_only.unwrap_or_default())
+    .then(|| NoteSearchParams {
+        use_reranking_filter: None,
+        limit: None,
+        offset: None,
+        count_only: Some(true),
+        ..params.clone()
+    });
+    let mut page = search_notes_page(state, user_id, params, trash.clone(), conn).await?;
+    if page.total == 0
+        && let Some(count_params) = count_params
+    {
+        page.total = search_notes_page(state, user_id, count_params, trash, conn)
+            .await?
+            .total;
+    }
+    Ok(page)
+}
+
+async fn search_notes_page(
+    state: &ToiState,
+    user_id: i32,
+    params: NoteSearchParams,
+    trash: utils::Scope,
+    conn: &mut utils::Conn<'_>,
+) -> Result<Page<i32>, ToiError> {
     let NoteSearchParams {
         ids,
         query,
         use_reranking_filter,
+        exclude_query,
+        exclude_ids,
         created_from,
         created_to,
         order_by,
+        tags,
+        tags_match,
         limit,
+        offset,
+        count_only,
+        include_archived,
+        include_expired,
     } = params;
 
-    let mut sql_query = schema::notes::table.select(Note::as_select()).into_boxed();
+    let mut sql_query = schema::notes::table
+        .select((Note::as_select(), utils::total_count()))
+        .into_boxed();
+    let mut query_embedding = None;
 
     // Filter items created on or after date.
     if let Some(created_from) = created_from {
@@ -54,69 +181,318 @@ pub async fn search_notes(
         sql_query = sql_query.filter(schema::notes::created_at.le(created_to));
     }
 
-    // Order items.
+    // Items are only ordered by their distance to the query if they aren't
+    // ordered some other way.
+    let ordered_by_distance = order_by.is_none();
+
+    // Order items. Pinned items come first unless they're ordered by
+    // similarity to a query.
     match order_by {
-        Some(utils::OrderBy::Oldest) => sql_query = sql_query.order(schema::notes::created_at),
+        Some(utils::OrderBy::Oldest) => {
+            sql_query = sql_query.order((schema::notes::pinned.desc(), schema::notes::created_at));
+        }
         Some(utils::OrderBy::Newest) => {
-            sql_query = sql_query.order(schema::notes::created_at.desc());
+            sql_query = sql_query.order((
+                schema::notes::pinned.desc(),
+                schema::notes::created_at.desc(),
+            ));
         }
+        None if query.is_none() => sql_query = sql_query.order(schema::notes::pinned.desc()),
         None => {
             // By default, filter items similar to a given query.
             if let Some(ref query) = query {
-                let input = EmbeddingPromptTemplate::builder()
-                    .instruction_prefix(INSTRUCTION_PREFIX.to_string())
-                    .query_prefix(QUERY_PREFIX.to_string())
-                    .build()
+                let input = state
+                    .embedding_instructions
+                    .template("notes", INSTRUCTION_PREFIX, QUERY_PREFIX)
                     .apply(query);
                 let embedding_request = EmbeddingRequest { input };
                 let embedding = state.model_client.embed(embedding_request).await?;
+                // Long notes are as close as their closest chunk so a query
+                // about one part of them isn't drowned out by the rest.
+                let distance = || {
+                    sql::<Double>("LEAST(notes.embedding <=> ")
+                        .bind::<pgvector::sql_types::Vector, _>(embedding.clone())
+                        .sql(", (SELECT MIN(note_chunks.embedding <=> ")
+                        .bind::<pgvector::sql_types::Vector, _>(embedding.clone())
+                        .sql(") FROM note_chunks WHERE note_chunks.note_id = notes.id))")
+                };
                 sql_query = sql_query
-                    .filter(
-                        schema::notes::embedding
-                            .cosine_distance(embedding.clone())
-                            .le(state.server_config.distance_threshold),
-                    )
-                    .order(schema::notes::embedding.cosine_distance(embedding));
+                    .filter(distance().le(state.server_config.distance_threshold))
+                    .order(distance());
+                query_embedding = Some(embedding);
             }
         }
     }
 
+    // Filter items with any or all of the tags.
+    if let Some(tags) = tags {
+        let tag_ids = resolve_tags(state, user_id, tags).await?;
+        let note_tags: Vec<(i32, i32)> = schema::note_tags::table
+            .select((schema::note_tags::note_id, schema::note_tags::tag_id))
+            .filter(schema::note_tags::tag_id.eq_any(&tag_ids))
+            .load(conn)
+            .await
+            .map_err(utils::diesel_error)?;
+        let note_ids = tags_match
+            .unwrap_or_default()
+            .matching_ids(note_tags, tag_ids.len());
+        sql_query = sql_query.filter(schema::notes::id.eq_any(note_ids));
+    }
+
     // Filter items according to their ids.
     if let Some(ids) = ids {
         sql_query = sql_query.or_filter(schema::notes::id.eq_any(ids));
     }
 
-    // Limit number of items.
-    if let Some(limit) = limit {
+    // Filter out excluded items. This comes after the inclusive filters so
+    // items selected by their ids are still excluded, and it's part of the
+    // query so excluded items don't count towards the limit.
+    if let Some(exclude_ids) = exclude_ids {
+        sql_query = sql_query.filter(diesel::dsl::not(schema::notes::id.eq_any(exclude_ids)));
+    }
+    if let Some(exclude_query) = exclude_query {
+        let input = state
+            .embedding_instructions
+            .template("notes", INSTRUCTION_PREFIX, QUERY_PREFIX)
+            .apply(&exclude_query);
+        let embedding_request = EmbeddingRequest { input };
+        let embedding = state.model_client.embed(embedding_request).await?;
+        sql_query = sql_query.filter(
+            schema::notes::embedding
+                .cosine_distance(embedding)
+                .gt(state.server_config.exclude_distance_threshold),
+        );
+    }
+
+    // Only the user's own items are searched. This comes after the other
+    // filters so items selected by their ids are still only the user's.
+    sql_query = sql_query.filter(schema::notes::user_id.eq(user_id));
+
+    // Filter items in or out of the trash. This comes after the other
+    // filters so items selected by their ids are still filtered.
+    match trash {
+        utils::Scope::In => sql_query = sql_query.filter(schema::notes::deleted_at.is_not_null()),
+        utils::Scope::Out => sql_query = sql_query.filter(schema::notes::deleted_at.is_null()),
+    }
+
+    // Filter out archived items unless they're asked for. Items in the trash
+    // are restored whether they were archived or not.
+    if trash == utils::Scope::Out && !include_archived.unwrap_or_default() {
+        sql_query = sql_query.filter(schema::notes::archived_at.is_null());
+    }
+
+    // Filter out expired items unless they're asked for. Like archived
+    // items, items in the trash are restored whether they expired or not.
+    if trash == utils::Scope::Out && !include_expired.unwrap_or_default() {
+        sql_query = sql_query.filter(
+            schema::notes::expires_at
+                .is_null()
+                .or(schema::notes::expires_at.gt(Utc::now())),
+        );
+    }
+
+    // Limit number of items. Only the total is needed when counting items
+    // that don't need to be reranked, so only one item is loaded. Items
+    // counted once they're reranked aren't limited so they're all counted.
+    let count_only = count_only.unwrap_or_default();
+    let count_total =
+        count_only && !search::needs_reranking(query.as_deref(), use_reranking_filter);
+    if count_total {
+        sql_query = sql_query.limit(1);
+    } else if let Some(limit) = limit.filter(|_| !count_only) {
         sql_query = sql_query.limit(limit);
     }
 
+    // Skip a number of items.
+    if let Some(offset) = offset.filter(|_| !count_only) {
+        sql_query = sql_query.offset(offset);
+    }
+
     // Get all the items that match the query.
-    let notes: Vec<Note> = sql_query.load(conn).await.map_err(utils::diesel_error)?;
-    let (ids, documents): (Vec<i32>, Vec<String>) = notes
+    let notes: Vec<(Note, i64)> = sql_query.load(conn).await.map_err(utils::diesel_error)?;
+    let total = notes.as_slice().first().map_or(0, |(_, total)| *total);
+    if count_total {
+        return Ok(Page::count(total));
+    }
+    let mut ids_docs: Vec<(i32, String)> = notes
         .into_iter()
-        .map(|note| (note.id, note.content))
-        .unzip();
-    if ids.is_empty() {
-        return Ok(ids);
+        .map(|(note, _)| (note.id, note.content))
+        .collect();
+
+    // Long notes are reranked using their chunk closest to the query rather
+    // than their whole content.
+    if let Some(embedding) = query_embedding
+        && search::needs_reranking(query.as_deref(), use_reranking_filter)
+    {
+        let ids: Vec<i32> = ids_docs.iter().map(|(id, _)| *id).collect();
+        let chunks: Vec<(i32, String)> = schema::note_chunks::table
+            .select((schema::note_chunks::note_id, schema::note_chunks::content))
+            .filter(schema::note_chunks::note_id.eq_any(&ids))
+            .order(schema::note_chunks::embedding.cosine_distance(embedding))
+            .load(conn)
+            .await
+            .map_err(utils::diesel_error)?;
+        let mut closest_chunks = HashMap::new();
+        for (note_id, content) in chunks {
+            closest_chunks.entry(note_id).or_insert(content);
+        }
+        for (id, document) in &mut ids_docs {
+            if let Some(content) = closest_chunks.remove(id) {
+                *document = content;
+            }
+        }
+    }
+
+    // Items that aren't ordered by their distance to the query are put in
+    // that order so only the furthest ones are left out of reranking.
+    if !ordered_by_distance
+        && search::needs_reranking(query.as_deref(), use_reranking_filter)
+        && let Some(ref query) = query
+    {
+        let input = state
+            .embedding_instructions
+            .template("notes", INSTRUCTION_PREFIX, QUERY_PREFIX)
+            .apply(query);
+        ids_docs =
+            search::closest_first(state, EmbeddedTable::Notes, input, ids_docs, conn).await?;
     }
 
     // Rerank and filter items once more.
-    let ids = match (query, use_reranking_filter) {
-        (Some(query), Some(true)) => {
-            let rerank_request = RerankRequest { query, documents };
-            let rerank_response = state.model_client.rerank(rerank_request).await?;
-            rerank_response
-                .results
-                .into_iter()
-                .filter(|item| item.relevance_score >= state.server_config.similarity_threshold)
-                .map(|item| ids[item.index])
-                .collect()
-        }
-        _ => ids,
+    let ids = search::rerank_filter(
+        state,
+        query,
+        use_reranking_filter,
+        ids_docs,
+        &RerankOptions::default(),
+    )
+    .await?;
+
+    if count_only {
+        return Ok(Page::count(ids.len().try_into().unwrap_or(i64::MAX)));
+    }
+
+    Ok(Page {
+        items: ids,
+        total,
+        offset,
+        limit,
+    })
+}
+
+pub async fn search_note_tags(
+    state: &ToiState,
+    user_id: i32,
+    params: NoteTagSearchParams,
+    conn: &mut utils::Conn<'_>,
+) -> Result<(Note, Vec<i32>), ToiError> {
+    let NoteTagSearchParams {
+        note_id,
+        note_query,
+        note_use_reranking_filter,
+        note_created_from,
+        note_created_to,
+        note_order_by,
+        tag_ids,
+        tag_query,
+        tag_use_reranking_filter,
+        tag_use_edit_distance_filter,
+        tag_limit,
+    } = params;
+    let note_query_params = NoteSearchParams {
+        ids: note_id.map(|i| vec![i]),
+        query: note_query,
+        use_reranking_filter: note_use_reranking_filter,
+        exclude_query: None,
+        exclude_ids: None,
+        created_from: note_created_from,
+        created_to: note_created_to,
+        order_by: note_order_by,
+        tags: None,
+        tags_match: None,
+        limit: Some(1),
+        offset: None,
+        count_only: None,
+        include_archived: None,
+        include_expired: None,
     };
+    let note_id = search_notes(state, user_id, note_query_params, utils::Scope::Out, conn)
+        .await?
+        .items
+        .into_iter()
+        .next()
+        .ok_or(ToiError::NotFound("note not found".to_string()))?;
+    let note = schema::notes::table
+        .select(Note::as_select())
+        .filter(schema::notes::id.eq(note_id))
+        .first(conn)
+        .await
+        .map_err(utils::diesel_error)?;
+
+    let mut sql_query = schema::note_tags::table
+        .select(schema::note_tags::tag_id)
+        .filter(schema::note_tags::note_id.eq(note.id))
+        .into_boxed();
 
-    Ok(ids)
+    if let Some(tag_ids) = tag_ids {
+        sql_query = sql_query.filter(schema::note_tags::tag_id.eq_any(tag_ids));
+    }
+
+    let tag_ids = sql_query.load(conn).await.map_err(utils::diesel_error)?;
+    if tag_ids.is_empty() {
+        return Ok((note, tag_ids));
+    }
+
+    let tag_query_params = TagSearchParams {
+        ids: Some(tag_ids),
+        query: tag_query,
+        use_reranking_filter: tag_use_reranking_filter,
+        use_edit_distance_filter: tag_use_edit_distance_filter,
+        order_by: None,
+        limit: tag_limit,
+    };
+    let mut embeddings = EmbeddingCache::default();
+    let tag_ids = search_tags(state, user_id, tag_query_params, &mut embeddings, conn).await?;
+    Ok((note, tag_ids))
+}
+
+/// Find an existing note that says the same thing as a new note. The note
+/// closest to the new note is a duplicate if it's within the duplicate
+/// distance threshold and the reranking API agrees. Notes in the trash or
+/// that have expired aren't considered.
+async fn find_duplicate_note(
+    state: &ToiState,
+    user_id: i32,
+    content: &str,
+    embedding: Vector,
+    conn: &mut utils::Conn<'_>,
+) -> Result<Option<Note>, ToiError> {
+    let note = schema::notes::table
+        .select(Note::as_select())
+        .filter(schema::notes::user_id.eq(user_id))
+        .filter(schema::notes::deleted_at.is_null())
+        .filter(
+            schema::notes::expires_at
+                .is_null()
+                .or(schema::notes::expires_at.gt(Utc::now())),
+        )
+        .filter(
+            schema::notes::embedding
+                .cosine_distance(embedding.clone())
+                .le(state.server_config.duplicate_distance_threshold),
+        )
+        .order(schema::notes::embedding.cosine_distance(embedding))
+        .first(conn)
+        .await
+        .optional()
+        .map_err(utils::diesel_error)?;
+    match note {
+        Some(note)
+            if search::is_confirmed_duplicate(state, content, note.content.clone()).await? =>
+        {
+            Ok(Some(note))
+        }
+        _ => Ok(None),
+    }
 }
 
 /// Add and return a note.
@@ -137,6 +513,7 @@ pub async fn search_notes(
     responses(
         (status = 201, description = "Successfully added a note", body = Note),
         (status = 400, description = "Default JSON elements configured by the user are invalid"),
+        (status = 409, description = "A similar note already exists, or the idempotency key was already used for a different request", body = Note),
         (status = 422, description = "Error when parsing a response from a model API"),
         (status = 502, description = "Error when forwarding request to model APIs")
     )
@@ -144,26 +521,373 @@ pub async fn search_notes(
 #[axum::debug_handler]
 async fn add_note(
     State(state): State<ToiState>,
+    user: CurrentUser,
+    headers: HeaderMap,
     Json(params): Json<NewNoteRequest>,
-) -> Result<Json<Note>, (StatusCode, String)> {
-    let mut conn = state.pool.get().await.map_err(utils::internal_error)?;
-    let NewNoteRequest { content } = params;
+) -> Result<(StatusCode, Json<Note>), ToiError> {
+    let idempotency_key =
+        IdempotencyKey::from_request(&state, &headers, user.id, "/notes", &params)?;
+    let mut conn = utils::get_conn(&state.pool).await?;
+    // Replay repeats before embedding anything.
+    if let Some(key) = &idempotency_key
+        && let Some(note) = key.replay(&mut conn).await?
+    {
+        return Ok((StatusCode::OK, Json(note)));
+    }
+    let NewNoteRequest {
+        content,
+        tags,
+        expires_at,
+        allow_duplicate,
+    } = params;
+    // Get tag IDs for matching tags.
+    let tag_ids = match tags {
+        Some(tags) => resolve_tags(&state, user.id, tags).await?,
+        None => vec![],
+    };
     let embedding_request = EmbeddingRequest {
         input: content.clone(),
     };
     let embedding = state.model_client.embed(embedding_request).await?;
-    let new_note = NewNote { content, embedding };
-    let result = diesel::insert_into(schema::notes::table)
-        .values(new_note)
-        .returning(Note::as_returning())
-        .get_result(&mut conn)
+    // Make sure the same note isn't already kept if duplicates are being
+    // checked, returning the existing note if it is.
+    if state.server_config.duplicate_check_enabled
+        && !allow_duplicate.unwrap_or_default()
+        && let Some(note) =
+            find_duplicate_note(&state, user.id, &content, embedding.clone(), &mut conn).await?
+    {
+        return Ok((StatusCode::CONFLICT, Json(note)));
+    }
+    let chunks = embed_note_chunks(&state, &content).await?;
+    let new_note = NewNote {
+        user_id: user.id,
+        content,
+        embedding,
+        expires_at,
+    };
+    let result = conn
+        .transaction::<_, ToiError, _>(|mut conn| {
+            async move {
+                // Check again in case a request with the same key added the
+                // note while this one was embedding it.
+                if let Some(key) = &idempotency_key
+                    && let Some(note) = key.replay(&mut conn).await?
+                {
+                    return Ok(note);
+                }
+                let note: Note = diesel::insert_into(schema::notes::table)
+                    .values(new_note)
+                    .returning(Note::as_returning())
+                    .get_result(&mut conn)
+                    .await?;
+                // Add the note tags using the note's database-generated ID.
+                let new_note_tags: Vec<NewNoteTag> = tag_ids
+                    .into_iter()
+                    .map(|tag_id| NewNoteTag {
+                        note_id: note.id,
+                        tag_id,
+                    })
+                    .collect();
+                diesel::insert_into(schema::note_tags::table)
+                    .values(new_note_tags)
+                    .execute(&mut conn)
+                    .await?;
+                replace_note_chunks(note.id, chunks, &mut conn).await?;
+                if let Some(key) = &idempotency_key {
+                    key.store(&note, &mut conn).await?;
+                }
+                Ok(note)
+            }
+            .scope_boxed()
+        })
+        .await?;
+    Ok((StatusCode::OK, Json(result)))
+}
+
+/// Import many notes at once and return them.
+///
+/// Only use this endpoint when the user provides a list of several notes to
+/// add together, like when migrating from another notes app.
+///
+/// Example queries for importing notes using this endpoint:
+/// - Import these notes
+/// - Add all of these notes
+/// - Bulk add notes
+/// - Save each of these as a note
+#[utoipa::path(
+    post,
+    path = "/bulk",
+    extensions(
+        ("x-json-schema-body" = json!(schema_for!(BulkNoteImportRequest)))
+    ),
+    request_body = BulkNoteImportRequest,
+    responses(
+        (status = 201, description = "Successfully imported notes", body = [Note]),
+        (status = 400, description = "Default JSON elements configured by the user are invalid"),
+        (status = 413, description = "Too many notes to import at once"),
+        (status = 422, description = "Error when parsing a response from a model API"),
+        (status = 502, description = "Error when forwarding request to model APIs")
+    )
+)]
+#[axum::debug_handler]
+async fn add_notes(
+    State(state): State<ToiState>,
+    user: CurrentUser,
+    Json(params): Json<BulkNoteImportRequest>,
+) -> Result<Json<Vec<Note>>, ToiError> {
+    let BulkNoteImportRequest { notes } = params;
+    let max_batch_size = state.server_config.max_batch_size;
+    if notes.len() > max_batch_size {
+        return Err(ToiError::PayloadTooLarge(format!(
+            "can't import more than {max_batch_size} notes at once"
+        )));
+    }
+    if notes.is_empty() {
+        return Ok(Json(vec![]));
+    }
+    // Get tag IDs for matching tags of each note before adding any of them.
+    let mut contents = vec![];
+    let mut expirations = vec![];
+    let mut tag_ids = vec![];
+    for NewNoteRequest {
+        content,
+        tags,
+        expires_at,
+        ..
+    } in notes
+    {
+        contents.push(content);
+        expirations.push(expires_at);
+        tag_ids.push(match tags {
+            Some(tags) => resolve_tags(&state, user.id, tags).await?,
+            None => vec![],
+        });
+    }
+    let mut conn = utils::get_conn(&state.pool).await?;
+    let embedding_request = BatchEmbeddingRequest {
+        input: contents.clone(),
+    };
+    let embeddings = state.model_client.embed_batch(embedding_request).await?;
+    let mut note_chunks = vec![];
+    for content in &contents {
+        note_chunks.push(embed_note_chunks(&state, content).await?);
+    }
+    let new_notes: Vec<NewNote> = contents
+        .into_iter()
+        .zip(embeddings)
+        .zip(expirations)
+        .map(|((content, embedding), expires_at)| NewNote {
+            user_id: user.id,
+            content,
+            embedding,
+            expires_at,
+        })
+        .collect();
+    // Within a single transaction, add the notes, and then add the note tags
+    // and chunks, so either all notes are added or none are.
+    let result = conn
+        .transaction::<_, ToiError, _>(|mut conn| {
+            async move {
+                let mut notes: Vec<Note> = diesel::insert_into(schema::notes::table)
+                    .values(&new_notes)
+                    .returning(Note::as_returning())
+                    .get_results(&mut conn)
+                    .await?;
+                // IDs are generated in input order.
+                notes.sort_by_key(|note| note.id);
+                let new_note_tags: Vec<NewNoteTag> = notes
+                    .iter()
+                    .zip(tag_ids)
+                    .flat_map(|(note, tag_ids)| {
+                        tag_ids.into_iter().map(|tag_id| NewNoteTag {
+                            note_id: note.id,
+                            tag_id,
+                        })
+                    })
+                    .collect();
+                diesel::insert_into(schema::note_tags::table)
+                    .values(new_note_tags)
+                    .execute(&mut conn)
+                    .await?;
+                for (note, chunks) in notes.iter().zip(note_chunks) {
+                    replace_note_chunks(note.id, chunks, &mut conn).await?;
+                }
+                Ok(notes)
+            }
+            .scope_boxed()
+        })
+        .await?;
+    Ok(Json(result))
+}
+
+/// Add to the end of a note and return it.
+///
+/// Example queries for appending to a note using this endpoint:
+/// - Add this to my note about
+/// - Append to the note
+/// - Add to my groceries note
+/// - Also put in the note
+#[utoipa::path(
+    post,
+    path = "/append",
+    extensions(
+        ("x-json-schema-body" = json!(schema_for!(AppendNoteRequest)))
+    ),
+    request_body = AppendNoteRequest,
+    responses(
+        (status = 200, description = "Successfully appended to a note", body = Note),
+        (status = 400, description = "Default JSON elements configured by the user are invalid"),
+        (status = 404, description = "Note not found"),
+        (status = 422, description = "Error when parsing a response from a model API"),
+        (status = 502, description = "Error when forwarding request to model APIs")
+    )
+)]
+#[axum::debug_handler]
+async fn append_to_matching_note(
+    State(state): State<ToiState>,
+    user: CurrentUser,
+    Json(params): Json<AppendNoteRequest>,
+) -> Result<Json<Note>, ToiError> {
+    let mut conn = utils::get_conn(&state.pool).await?;
+    let AppendNoteRequest {
+        id,
+        append_content,
+        separator,
+        query,
+        use_reranking_filter,
+        created_from,
+        created_to,
+        order_by,
+    } = params;
+    let params = NoteSearchParams {
+        ids: id.map(|i| vec![i]),
+        query,
+        use_reranking_filter,
+        exclude_query: None,
+        exclude_ids: None,
+        created_from,
+        created_to,
+        order_by,
+        tags: None,
+        tags_match: None,
+        limit: Some(1),
+        offset: None,
+        count_only: None,
+        include_archived: None,
+        include_expired: None,
+    };
+    let id = search_notes(&state, user.id, params, utils::Scope::Out, &mut conn)
+        .await?
+        .items
+        .into_iter()
+        .next()
+        .ok_or(ToiError::NotFound("note not found".to_string()))?;
+    let note = schema::notes::table
+        .select(Note::as_select())
+        .filter(schema::notes::id.eq(id))
+        .first(&mut conn)
         .await
         .map_err(utils::diesel_error)?;
-    Ok(Json(result))
+    let content = separator
+        .unwrap_or_default()
+        .join(&note.content, &append_content);
+    let embedding_request = EmbeddingRequest {
+        input: content.clone(),
+    };
+    let embedding = state.model_client.embed(embedding_request).await?;
+    let chunks = embed_note_chunks(&state, &content).await?;
+    // The content and its embedding are updated together in a single atomic
+    // statement. It only applies if the note hasn't changed or been deleted
+    // since it was read so concurrent appends aren't lost. Its chunks are
+    // replaced in the same transaction.
+    let note = conn
+        .transaction::<_, ToiError, _>(|mut conn| {
+            async move {
+                let note: Note = diesel::update(
+                    schema::notes::table
+                        .filter(schema::notes::id.eq(id))
+                        .filter(schema::notes::content.eq(&note.content))
+                        .filter(schema::notes::deleted_at.is_null()),
+                )
+                .set((
+                    schema::notes::content.eq(content),
+                    schema::notes::embedding.eq(embedding),
+                ))
+                .returning(Note::as_returning())
+                .get_result(&mut conn)
+                .await?;
+                replace_note_chunks(note.id, chunks, &mut conn).await?;
+                Ok(note)
+            }
+            .scope_boxed()
+        })
+        .await?;
+    Ok(Json(note))
+}
+
+/// Archive or unarchive and return notes.
+///
+/// Archived notes are kept, but they're left out of searches unless they're
+/// asked for.
+///
+/// Example queries for archiving notes using this endpoint:
+/// - Archive my old notes
+/// - Archive the note about
+/// - Unarchive the note about
+/// - Move notes to the archive
+#[utoipa::path(
+    put,
+    path = "/archive",
+    extensions(
+        ("x-json-schema-body" = json!(schema_for!(ArchiveNotesRequest)))
+    ),
+    request_body = ArchiveNotesRequest,
+    responses(
+        (status = 200, description = "Successfully archived or unarchived notes", body = [Note]),
+        (status = 400, description = "Default JSON elements configured by the user are invalid"),
+        (status = 422, description = "Error when parsing a response from a model API"),
+        (status = 502, description = "Error when forwarding request to model APIs")
+    )
+)]
+#[axum::debug_handler]
+async fn archive_matching_notes(
+    State(state): State<ToiState>,
+    user: CurrentUser,
+    Json(params): Json<ArchiveNotesRequest>,
+) -> Result<Json<Vec<Note>>, ToiError> {
+    let mut conn = utils::get_conn(&state.pool).await?;
+    let ArchiveNotesRequest { params, archived } = params;
+
+    // Items are always needed here, so counting is ignored. Archived items
+    // have to be searched to unarchive them.
+    let params = NoteSearchParams {
+        count_only: None,
+        include_archived: Some(!archived || params.include_archived.unwrap_or_default()),
+        ..params
+    };
+    let ids = search_notes(&state, user.id, params, utils::Scope::Out, &mut conn)
+        .await?
+        .items;
+    // Only items that aren't already in the requested state are updated so
+    // archived items keep when they were first archived.
+    let archived_at = archived.then(Utc::now);
+    let notes = diesel::update(
+        schema::notes::table
+            .filter(schema::notes::id.eq_any(ids))
+            .filter(schema::notes::archived_at.is_null().eq(archived)),
+    )
+    .set(schema::notes::archived_at.eq(archived_at))
+    .returning(Note::as_returning())
+    .load(&mut conn)
+    .await
+    .map_err(utils::diesel_error)?;
+    Ok(Json(notes))
 }
 
 /// Delete and return notes.
 ///
+/// Deleted notes are moved to the trash so they can be restored later.
+///
 /// Example queries for deleting notes using this endpoint:
 /// - Delete all notes
 /// - Erase all notes
@@ -173,13 +897,13 @@ async fn add_note(
     post,
     path = "/delete",
     extensions(
-        ("x-json-schema-body" = json!(schema_for!(NoteSearchParams)))
+        ("x-json-schema-body" = json!(schema_for!(DeleteParams<NoteSearchParams>)))
     ),
-    request_body = NoteSearchParams,
+    request_body = DeleteParams<NoteSearchParams>,
     responses(
         (status = 200, description = "Successfully deleted notes", body = [Note]),
-        (status = 400, description = "Default JSON elements configured by the user are invalid"),
-        (status = 404, description = "No notes found"),
+        (status = 400, description = "Default JSON elements configured by the user are invalid, or deleting every item wasn't confirmed"),
+        (status = 404, description = "No notes found with the given IDs"),
         (status = 422, description = "Error when parsing a response from a model API"),
         (status = 502, description = "Error when forwarding request to model APIs")
     )
@@ -187,20 +911,36 @@ async fn add_note(
 #[axum::debug_handler]
 async fn delete_matching_notes(
     State(state): State<ToiState>,
-    Json(params): Json<NoteSearchParams>,
-) -> Result<Json<Vec<Note>>, (StatusCode, String)> {
-    let mut conn = state.pool.get().await.map_err(utils::internal_error)?;
-    let ids = search_notes(&state, params, &mut conn).await?;
-    let notes = diesel::delete(schema::notes::table.filter(schema::notes::id.eq_any(ids)))
+    user: CurrentUser,
+    Json(params): Json<DeleteParams<NoteSearchParams>>,
+) -> Result<Json<Vec<Note>>, ToiError> {
+    let params = params.into_checked()?;
+    let mut conn = utils::get_conn(&state.pool).await?;
+
+    // Items are always needed here, so counting is ignored.
+    let params = NoteSearchParams {
+        count_only: None,
+        ..params
+    };
+    let requested_ids = params.ids.clone();
+    let ids = search_notes(&state, user.id, params, utils::Scope::Out, &mut conn)
+        .await?
+        .items;
+    let notes = diesel::update(schema::notes::table.filter(schema::notes::id.eq_any(ids)))
+        .set(schema::notes::deleted_at.eq(Utc::now()))
         .returning(Note::as_returning())
         .load(&mut conn)
         .await
         .map_err(utils::diesel_error)?;
+    utils::check_ids_found(requested_ids.as_deref(), &notes, "notes")?;
     Ok(Json(notes))
 }
 
 /// Get notes.
 ///
+/// Notes are streamed as newline-delimited JSON, one note per line, if the
+/// `Accept` header asks for `application/x-ndjson`.
+///
 /// Example queries for getting notes using this endpoint:
 /// - Get all notes
 /// - List all notes
@@ -214,9 +954,11 @@ async fn delete_matching_notes(
     ),
     request_body = NoteSearchParams,
     responses(
-        (status = 200, description = "Successfully got notes", body = [Note]),
+        (status = 200, description = "Successfully got notes or their count", content(
+            (SearchResponse<Note> = "application/json"),
+            (Note = "application/x-ndjson")
+        )),
         (status = 400, description = "Default JSON elements configured by the user are invalid"),
-        (status = 404, description = "No notes found"),
         (status = 422, description = "Error when parsing a response from a model API"),
         (status = 502, description = "Error when forwarding request to model APIs")
     )
@@ -224,15 +966,433 @@ async fn delete_matching_notes(
 #[axum::debug_handler]
 async fn get_matching_notes(
     State(state): State<ToiState>,
+    user: CurrentUser,
+    headers: HeaderMap,
     Json(params): Json<NoteSearchParams>,
-) -> Result<Json<Vec<Note>>, (StatusCode, String)> {
-    let mut conn = state.pool.get().await.map_err(utils::internal_error)?;
-    let ids = search_notes(&state, params, &mut conn).await?;
-    let notes = schema::notes::table
+) -> Result<Response, ToiError> {
+    let count_only = params.count_only.unwrap_or_default();
+    let mut conn = utils::get_conn(&state.pool).await?;
+    let Page {
+        items: ids,
+        total,
+        offset,
+        limit,
+    } = search_notes(&state, user.id, params, utils::Scope::Out, &mut conn).await?;
+    if count_only {
+        return Ok(Json(SearchResponse::<Note>::Count(Count { count: total })).into_response());
+    }
+    if ndjson::is_accepted(&headers) {
+        return Ok(ndjson::stream_items(
+            state.pool.clone(),
+            ids,
+            load_notes,
+            |note| note.id,
+        ));
+    }
+    let mut notes: Vec<Note> = schema::notes::table
+        .select(Note::as_select())
+        .filter(schema::notes::id.eq_any(&ids))
+        .load(&mut conn)
+        .await
+        .map_err(utils::diesel_error)?;
+
+    // Keep notes in the order they were searched in (e.g., pinned first).
+    notes.sort_by_key(|note| ids.iter().position(|id| *id == note.id));
+    Ok(Json(SearchResponse::Page(Page {
+        items: notes,
+        total,
+        offset,
+        limit,
+    }))
+    .into_response())
+}
+
+/// Load a batch of notes for streaming search results.
+async fn load_notes(pool: utils::Pool, ids: Vec<i32>) -> Result<Vec<Note>, ToiError> {
+    let mut conn = utils::get_conn(&pool).await?;
+    schema::notes::table
         .select(Note::as_select())
         .filter(schema::notes::id.eq_any(ids))
         .load(&mut conn)
         .await
+        .map_err(utils::diesel_error)
+}
+
+/// Pin or unpin and return notes.
+///
+/// Pinned notes are listed before other notes unless notes are searched by
+/// similarity to a query.
+///
+/// Example queries for pinning notes using this endpoint:
+/// - Pin the note about
+/// - Pin my wifi password note
+/// - Unpin the note about
+/// - Keep the note at the top
+#[utoipa::path(
+    put,
+    path = "/pin",
+    extensions(
+        ("x-json-schema-body" = json!(schema_for!(PinNotesRequest)))
+    ),
+    request_body = PinNotesRequest,
+    responses(
+        (status = 200, description = "Successfully pinned or unpinned notes", body = [Note]),
+        (status = 400, description = "Default JSON elements configured by the user are invalid"),
+        (status = 422, description = "Error when parsing a response from a model API"),
+        (status = 502, description = "Error when forwarding request to model APIs")
+    )
+)]
+#[axum::debug_handler]
+async fn pin_matching_notes(
+    State(state): State<ToiState>,
+    user: CurrentUser,
+    Json(params): Json<PinNotesRequest>,
+) -> Result<Json<Vec<Note>>, ToiError> {
+    let mut conn = utils::get_conn(&state.pool).await?;
+    let PinNotesRequest { params, pinned } = params;
+
+    // Items are always needed here, so counting is ignored.
+    let params = NoteSearchParams {
+        count_only: None,
+        ..params
+    };
+    let ids = search_notes(&state, user.id, params, utils::Scope::Out, &mut conn)
+        .await?
+        .items;
+    let notes = diesel::update(schema::notes::table.filter(schema::notes::id.eq_any(ids)))
+        .set(schema::notes::pinned.eq(pinned))
+        .returning(Note::as_returning())
+        .load(&mut conn)
+        .await
         .map_err(utils::diesel_error)?;
     Ok(Json(notes))
 }
+
+/// Permanently delete and return notes that have been in the trash for
+/// longer than the server's trash retention period.
+///
+/// Example queries for purging notes using this endpoint:
+/// - Empty the notes trash
+/// - Purge old deleted notes
+/// - Permanently delete notes in the trash
+#[utoipa::path(
+    post,
+    path = "/purge",
+    responses(
+        (status = 200, description = "Successfully purged notes", body = [Note])
+    )
+)]
+#[axum::debug_handler]
+async fn purge_deleted_notes(
+    State(state): State<ToiState>,
+    user: CurrentUser,
+) -> Result<Json<Vec<Note>>, ToiError> {
+    let mut conn = utils::get_conn(&state.pool).await?;
+    let cutoff = Utc::now() - Duration::days(state.server_config.trash_retention_days.into());
+    let notes = diesel::delete(
+        schema::notes::table
+            .filter(schema::notes::user_id.eq(user.id))
+            .filter(schema::notes::deleted_at.le(cutoff)),
+    )
+    .returning(Note::as_returning())
+    .load(&mut conn)
+    .await
+    .map_err(utils::diesel_error)?;
+    Ok(Json(notes))
+}
+
+/// Permanently delete notes that expired more than a grace period before
+/// `now`, returning how many were deleted.
+pub async fn delete_expired_notes(
+    now: DateTime<Utc>,
+    grace: Duration,
+    conn: &mut utils::Conn<'_>,
+) -> Result<usize, ToiError> {
+    diesel::delete(schema::notes::table.filter(schema::notes::expires_at.lt(now - grace)))
+        .execute(conn)
+        .await
+        .map_err(utils::diesel_error)
+}
+
+/// Periodically delete notes that expired more than the grace period ago.
+/// The grace period leaves time to recover notes that expired too soon.
+pub async fn purge_expired_notes(state: ToiState) {
+    let interval_minutes = state.server_config.note_expiry_cleanup_interval_minutes;
+    if interval_minutes == 0 {
+        return;
+    }
+    let grace = Duration::hours(state.server_config.note_expiry_grace_hours.into());
+    let period = std::time::Duration::from_secs(interval_minutes.saturating_mul(60));
+    let mut interval = tokio::time::interval(period);
+    loop {
+        interval.tick().await;
+        let result = match state.pool.get().await {
+            Ok(mut conn) => delete_expired_notes(Utc::now(), grace, &mut conn).await,
+            Err(err) => Err(utils::internal_error(err)),
+        };
+        match result {
+            Ok(num_purged) => info!("purged {num_purged} expired notes"),
+            Err(err) => warn!("couldn't purge expired notes: {err}"),
+        }
+    }
+}
+
+/// Restore and return notes from the trash.
+///
+/// Example queries for restoring notes using this endpoint:
+/// - Restore the note about
+/// - Undelete notes
+/// - Bring back the deleted notes
+/// - Recover notes from the trash
+#[utoipa::path(
+    post,
+    path = "/restore",
+    extensions(
+        ("x-json-schema-body" = json!(schema_for!(NoteSearchParams)))
+    ),
+    request_body = NoteSearchParams,
+    responses(
+        (status = 200, description = "Successfully restored notes", body = [Note]),
+        (status = 400, description = "Default JSON elements configured by the user are invalid"),
+        (status = 404, description = "No notes found with the given IDs"),
+        (status = 422, description = "Error when parsing a response from a model API"),
+        (status = 502, description = "Error when forwarding request to model APIs")
+    )
+)]
+#[axum::debug_handler]
+async fn restore_matching_notes(
+    State(state): State<ToiState>,
+    user: CurrentUser,
+    Json(params): Json<NoteSearchParams>,
+) -> Result<Json<Vec<Note>>, ToiError> {
+    let mut conn = utils::get_conn(&state.pool).await?;
+
+    // Items are always needed here, so counting is ignored.
+    let params = NoteSearchParams {
+        count_only: None,
+        ..params
+    };
+    let requested_ids = params.ids.clone();
+    let ids = search_notes(&state, user.id, params, utils::Scope::In, &mut conn)
+        .await?
+        .items;
+    let notes = diesel::update(schema::notes::table.filter(schema::notes::id.eq_any(ids)))
+        .set(schema::notes::deleted_at.eq(None::<DateTime<Utc>>))
+        .returning(Note::as_returning())
+        .load(&mut conn)
+        .await
+        .map_err(utils::diesel_error)?;
+    utils::check_ids_found(requested_ids.as_deref(), &notes, "notes")?;
+    Ok(Json(notes))
+}
+
+/// Add note tags to existing notes and return the updated notes.
+///
+/// Example queries for adding note tags using this endpoint:
+/// - Tag the note about
+/// - Add note tags to
+/// - Tag my notes on
+#[utoipa::path(
+    post,
+    path = "/tags",
+    extensions(
+        ("x-json-schema-body" = json!(schema_for!(NewNoteTagsRequest)))
+    ),
+    request_body = NewNoteTagsRequest,
+    responses(
+        (status = 201, description = "Successfully added note tags", body = [Note]),
+        (status = 400, description = "Default JSON elements configured by the user are invalid"),
+        (status = 404, description = "No matching tags found"),
+        (status = 422, description = "Error when parsing a response from a model API"),
+        (status = 502, description = "Error when forwarding request to model APIs")
+    )
+)]
+#[axum::debug_handler]
+async fn add_note_tags(
+    State(state): State<ToiState>,
+    user: CurrentUser,
+    Json(params): Json<NewNoteTagsRequest>,
+) -> Result<Json<Vec<Note>>, ToiError> {
+    let mut conn = utils::get_conn(&state.pool).await?;
+    let NewNoteTagsRequest {
+        ids,
+        query,
+        use_reranking_filter,
+        created_from,
+        created_to,
+        order_by,
+        tags,
+        limit,
+    } = params;
+    let params = NoteSearchParams {
+        ids,
+        query,
+        use_reranking_filter,
+        exclude_query: None,
+        exclude_ids: None,
+        created_from,
+        created_to,
+        order_by,
+        tags: None,
+        tags_match: None,
+        limit,
+        offset: None,
+        count_only: None,
+        include_archived: None,
+        include_expired: None,
+    };
+    let note_ids = search_notes(&state, user.id, params, utils::Scope::Out, &mut conn)
+        .await?
+        .items;
+    // Get tag IDs for matching tags.
+    let tag_ids = resolve_tags(&state, user.id, tags).await?;
+    let mut new_note_tags = vec![];
+    for tag_id in tag_ids {
+        for note_id in &note_ids {
+            let new_note_tag = NewNoteTag {
+                note_id: *note_id,
+                tag_id,
+            };
+            new_note_tags.push(new_note_tag);
+        }
+    }
+    // Notes that already have a tag keep it as is.
+    diesel::insert_into(schema::note_tags::table)
+        .values(new_note_tags)
+        .on_conflict_do_nothing()
+        .execute(&mut conn)
+        .await
+        .map_err(utils::diesel_error)?;
+    let notes = schema::notes::table
+        .select(Note::as_select())
+        .filter(schema::notes::id.eq_any(note_ids))
+        .load(&mut conn)
+        .await
+        .map_err(utils::diesel_error)?;
+    Ok(Json(notes))
+}
+
+/// Get note tags.
+///
+/// Example queries for getting note tags using this endpoint:
+/// - Get note tags where
+/// - What tags does the note about
+/// - List tags for my note on
+#[utoipa::path(
+    post,
+    path = "/tags/search",
+    extensions(
+        ("x-json-schema-body" = json!(schema_for!(NoteTagSearchParams)))
+    ),
+    request_body = NoteTagSearchParams,
+    responses(
+        (status = 200, description = "Successfully got note tags", body = NoteTags),
+        (status = 400, description = "Default JSON elements configured by the user are invalid"),
+        (status = 404, description = "No note or note tags found"),
+        (status = 422, description = "Error when parsing a response from a model API"),
+        (status = 502, description = "Error when forwarding request to model APIs")
+    )
+)]
+#[axum::debug_handler]
+async fn get_matching_note_tags(
+    State(state): State<ToiState>,
+    user: CurrentUser,
+    Json(params): Json<NoteTagSearchParams>,
+) -> Result<Json<NoteTags>, ToiError> {
+    let mut conn = utils::get_conn(&state.pool).await?;
+    let (note, ids) = search_note_tags(&state, user.id, params, &mut conn).await?;
+    let tags = schema::tags::table
+        .select(Tag::as_select())
+        .filter(schema::tags::id.eq_any(ids))
+        .load(&mut conn)
+        .await
+        .map_err(utils::diesel_error)?;
+    Ok(Json(NoteTags { note, tags }))
+}
+
+/// Delete and return note tags. The tags themselves are kept.
+///
+/// Example queries for deleting note tags using this endpoint:
+/// - Untag the note about
+/// - Remove note tags for
+/// - Delete the tag from my note on
+#[utoipa::path(
+    post,
+    path = "/tags/delete",
+    extensions(
+        ("x-json-schema-body" = json!(schema_for!(NoteTagSearchParams)))
+    ),
+    request_body = NoteTagSearchParams,
+    responses(
+        (status = 200, description = "Successfully deleted note tags", body = NoteTags),
+        (status = 400, description = "Default JSON elements configured by the user are invalid"),
+        (status = 404, description = "No note or note tags found"),
+        (status = 422, description = "Error when parsing a response from a model API"),
+        (status = 502, description = "Error when forwarding request to model APIs")
+    )
+)]
+#[axum::debug_handler]
+async fn delete_matching_note_tags(
+    State(state): State<ToiState>,
+    user: CurrentUser,
+    Json(params): Json<NoteTagSearchParams>,
+) -> Result<Json<NoteTags>, ToiError> {
+    let mut conn = utils::get_conn(&state.pool).await?;
+    let (note, ids) = search_note_tags(&state, user.id, params, &mut conn).await?;
+    let note_tags = conn
+        .transaction::<_, ToiError, _>(|mut conn| {
+            async move {
+                // Delete note tag items.
+                let ids: Vec<i32> = diesel::delete(
+                    schema::note_tags::table.filter(
+                        schema::note_tags::note_id
+                            .eq(note.id)
+                            .and(schema::note_tags::tag_id.eq_any(ids)),
+                    ),
+                )
+                .returning(schema::note_tags::tag_id)
+                .load(&mut conn)
+                .await?;
+                // Return the actual tag objects.
+                let tags = schema::tags::table
+                    .select(Tag::as_select())
+                    .filter(schema::tags::id.eq_any(ids))
+                    .load(&mut conn)
+                    .await?;
+                Ok(NoteTags { note, tags })
+            }
+            .scope_boxed()
+        })
+        .await?;
+    Ok(Json(note_tags))
+}
+
+/// Get a note using its database-generated ID. Notes in the trash aren't returned.
+#[utoipa::path(
+    get,
+    path = "/{id}",
+    params(
+        ("id" = i32, Path, description = "Database-generated note ID")
+    ),
+    responses(
+        (status = 200, description = "Successfully got a note", body = Note),
+        (status = 404, description = "Note not found")
+    )
+)]
+#[axum::debug_handler]
+async fn get_note(
+    State(state): State<ToiState>,
+    user: CurrentUser,
+    Path(id): Path<i32>,
+) -> Result<Json<Note>, ToiError> {
+    let mut conn = utils::get_conn(&state.pool).await?;
+    let note = schema::notes::table
+        .select(Note::as_select())
+        .filter(schema::notes::id.eq(id))
+        .filter(schema::notes::user_id.eq(user.id))
+        .filter(schema::notes::deleted_at.is_null())
+        .first(&mut conn)
+        .await
+        .map_err(utils::diesel_error)?;
+    Ok(Json(note))
+}
@@ -1,17 +1,32 @@
-use axum::{extract::State, http::StatusCode, response::Json};
-use diesel::{ExpressionMethods, QueryDsl, SelectableHelper};
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    response::Json,
+};
+use diesel::{
+    ExpressionMethods, QueryDsl, SelectableHelper,
+    dsl::sql,
+    expression::SqlLiteral,
+    sql_types::{Double, Nullable},
+};
 use diesel_async::RunQueryDsl;
 use pgvector::VectorExpressionMethods;
 use schemars::schema_for;
+use tracing::warn;
 use utoipa_axum::{router::OpenApiRouter, routes};
 
 use crate::{
+    auth::CurrentUser,
+    embeddings::EmbeddedTable,
     models::{
-        client::{EmbeddingPromptTemplate, EmbeddingRequest, RerankRequest},
+        client::EmbeddingRequest,
+        pagination::Page,
         places::{NewPlace, NewPlaceRequest, Place, PlaceSearchParams, UpdatePlaceRequest},
         state::ToiState,
     },
-    schema, utils,
+    schema,
+    search::{self, RerankOptions},
+    utils,
 };
 
 // Prefixes are used for embedding instructions.
@@ -24,26 +39,93 @@ pub fn places_router(state: ToiState) -> OpenApiRouter {
         .routes(routes!(add_place, update_matching_place))
         .routes(routes!(delete_matching_places))
         .routes(routes!(get_matching_places))
+        .routes(routes!(get_place))
         .with_state(state)
 }
 
+/// Great-circle distance in kilometers between places' coordinates and the
+/// given coordinates using the haversine formula. Coordinates are always
+/// finite, so they're safe to format into SQL.
+fn haversine_distance_km(latitude: f64, longitude: f64) -> SqlLiteral<Nullable<Double>> {
+    sql::<Nullable<Double>>(&format!(
+        "6371.0 * 2.0 * ASIN(LEAST(1.0, SQRT(\
+            POWER(SIN(RADIANS(places.latitude - ({latitude})) / 2.0), 2) + \
+            COS(RADIANS({latitude})) * COS(RADIANS(places.latitude)) * \
+            POWER(SIN(RADIANS(places.longitude - ({longitude})) / 2.0), 2)\
+        )))"
+    ))
+}
+
+/// Geocode a place's address so it can be found by proximity. Places are
+/// still saved without coordinates if their address can't be geocoded.
+async fn geocode_address(state: &ToiState, address: Option<&str>) -> (Option<f64>, Option<f64>) {
+    let Some(address) = address else {
+        return (None, None);
+    };
+    match utils::geocode(
+        address,
+        &state.server_config.geocoding_url,
+        &state.api_client,
+    )
+    .await
+    {
+        Ok((latitude, longitude)) => (Some(latitude), Some(longitude)),
+        Err(err) => {
+            warn!("couldn't geocode place address '{address}': {err}");
+            (None, None)
+        }
+    }
+}
+
 pub async fn search_places(
     state: &ToiState,
+    user_id: i32,
     params: PlaceSearchParams,
     conn: &mut utils::Conn<'_>,
-) -> Result<Vec<i32>, (StatusCode, String)> {
+) -> Result<Page<i32>, (StatusCode, String)> {
+    // The total is counted alongside a page's items, so it's counted on
+    // its own when the offset is past the end and there aren't any.
+    let count_params = params
+        .offset
+        .is_some_and(|offset| offset > 0)
+        .then(|| PlaceSearchParams {
+            use_reranking_filter: None,
+            limit: Some(1),
+            offset: None,
+            ..params.clone()
+        });
+    let mut page = search_places_page(state, user_id, params, conn).await?;
+    if page.total == 0
+        && let Some(count_params) = count_params
+    {
+        page.total = search_places_page(state, user_id, count_params, conn)
+            .await?
+            .total;
+    }
+    Ok(page)
+}
+
+async fn search_places_page(
+    state: &ToiState,
+    user_id: i32,
+    params: PlaceSearchParams,
+    conn: &mut utils::Conn<'_>,
+) -> Result<Page<i32>, (StatusCode, String)> {
     let PlaceSearchParams {
         ids,
         query,
         use_reranking_filter,
         created_from,
         created_to,
+        near_query,
+        radius_km,
         order_by,
         limit,
+        offset,
     } = params;
 
     let mut sql_query = schema::places::table
-        .select(Place::as_select())
+        .select((Place::as_select(), utils::total_count()))
         .into_boxed();
 
     // Filter items created on or after date.
@@ -56,6 +138,30 @@ pub async fn search_places(
         sql_query = sql_query.filter(schema::places::created_at.le(created_to));
     }
 
+    // Filter items near an area. Items without coordinates can't be
+    // compared, so they're excluded.
+    let coordinates = if let Some(near_query) = near_query {
+        let (latitude, longitude) = utils::geocode(
+            &near_query,
+            &state.server_config.geocoding_url,
+            &state.api_client,
+        )
+        .await?;
+        sql_query = sql_query
+            .filter(schema::places::latitude.is_not_null())
+            .filter(schema::places::longitude.is_not_null());
+        if let Some(radius_km) = radius_km {
+            sql_query = sql_query.filter(haversine_distance_km(latitude, longitude).le(radius_km));
+        }
+        Some((latitude, longitude))
+    } else {
+        None
+    };
+
+    // Items are only ordered by their distance to the query if they aren't
+    // ordered some other way.
+    let ordered_by_distance = order_by.is_none() && coordinates.is_none();
+
     // Order items.
     match order_by {
         Some(utils::OrderBy::Oldest) => sql_query = sql_query.order(schema::places::created_at),
@@ -63,12 +169,16 @@ pub async fn search_places(
             sql_query = sql_query.order(schema::places::created_at.desc());
         }
         None => {
+            // Order items nearest first when they're searched by proximity.
+            if let Some((latitude, longitude)) = coordinates {
+                sql_query = sql_query.order(haversine_distance_km(latitude, longitude));
+            }
+
             // By default, filter items similar to a given query.
             if let Some(ref query) = query {
-                let input = EmbeddingPromptTemplate::builder()
-                    .instruction_prefix(INSTRUCTION_PREFIX.to_string())
-                    .query_prefix(QUERY_PREFIX.to_string())
-                    .build()
+                let input = state
+                    .embedding_instructions
+                    .template("places", INSTRUCTION_PREFIX, QUERY_PREFIX)
                     .apply(query);
                 let embedding_request = EmbeddingRequest { input };
                 let embedding = state.model_client.embed(embedding_request).await?;
@@ -78,7 +188,7 @@ pub async fn search_places(
                             .cosine_distance(embedding.clone())
                             .le(state.server_config.distance_threshold),
                     )
-                    .order(schema::places::embedding.cosine_distance(embedding));
+                    .then_order_by(schema::places::embedding.cosine_distance(embedding));
             }
         }
     }
@@ -88,16 +198,26 @@ pub async fn search_places(
         sql_query = sql_query.or_filter(schema::places::id.eq_any(ids));
     }
 
+    // Only the user's own items are searched. This comes after the other
+    // filters so items selected by their ids are still only the user's.
+    sql_query = sql_query.filter(schema::places::user_id.eq(user_id));
+
     // Limit number of items.
     if let Some(limit) = limit {
         sql_query = sql_query.limit(limit);
     }
 
+    // Skip a number of items.
+    if let Some(offset) = offset {
+        sql_query = sql_query.offset(offset);
+    }
+
     // Get all the items that match the query.
-    let places: Vec<Place> = sql_query.load(conn).await.map_err(utils::diesel_error)?;
-    let (ids, documents): (Vec<i32>, Vec<String>) = places
+    let places: Vec<(Place, i64)> = sql_query.load(conn).await.map_err(utils::diesel_error)?;
+    let total = places.as_slice().first().map_or(0, |(_, total)| *total);
+    let mut ids_docs: Vec<(i32, String)> = places
         .into_iter()
-        .map(|place| {
+        .map(|(place, _)| {
             let Place {
                 id,
                 name,
@@ -114,27 +234,38 @@ pub async fn search_places(
             };
             (id, new_place_request.to_string())
         })
-        .unzip();
-    if ids.is_empty() {
-        return Ok(ids);
+        .collect();
+
+    // Items that aren't ordered by their distance to the query are put in
+    // that order so only the furthest ones are left out of reranking.
+    if !ordered_by_distance
+        && search::needs_reranking(query.as_deref(), use_reranking_filter)
+        && let Some(ref query) = query
+    {
+        let input = state
+            .embedding_instructions
+            .template("places", INSTRUCTION_PREFIX, QUERY_PREFIX)
+            .apply(query);
+        ids_docs =
+            search::closest_first(state, EmbeddedTable::Places, input, ids_docs, conn).await?;
     }
 
     // Rerank and filter items once more.
-    let ids = match (query, use_reranking_filter) {
-        (Some(query), Some(true)) => {
-            let rerank_request = RerankRequest { query, documents };
-            let rerank_response = state.model_client.rerank(rerank_request).await?;
-            rerank_response
-                .results
-                .into_iter()
-                .filter(|item| item.relevance_score >= state.server_config.similarity_threshold)
-                .map(|item| ids[item.index])
-                .collect()
-        }
-        _ => ids,
-    };
+    let ids = search::rerank_filter(
+        state,
+        query,
+        use_reranking_filter,
+        ids_docs,
+        &RerankOptions::default(),
+    )
+    .await?;
 
-    Ok(ids)
+    Ok(Page {
+        items: ids,
+        total,
+        offset,
+        limit,
+    })
 }
 
 /// Add and return a place.
@@ -160,13 +291,15 @@ pub async fn search_places(
 #[axum::debug_handler]
 async fn add_place(
     State(state): State<ToiState>,
+    user: CurrentUser,
     Json(params): Json<NewPlaceRequest>,
 ) -> Result<Json<Place>, (StatusCode, String)> {
-    let mut conn = state.pool.get().await.map_err(utils::internal_error)?;
+    let mut conn = utils::get_conn(&state.pool).await?;
     let embedding_request = EmbeddingRequest {
         input: params.to_string(),
     };
     let embedding = state.model_client.embed(embedding_request).await?;
+    let (latitude, longitude) = geocode_address(&state, params.address.as_deref()).await;
     let NewPlaceRequest {
         name,
         description,
@@ -174,11 +307,14 @@ async fn add_place(
         phone,
     } = params;
     let new_place = NewPlace {
+        user_id: user.id,
         name,
         description,
         address,
         phone,
         embedding,
+        latitude,
+        longitude,
     };
     let result = diesel::insert_into(schema::places::table)
         .values(new_place)
@@ -205,7 +341,7 @@ async fn add_place(
     responses(
         (status = 200, description = "Successfully deleted places", body = [Place]),
         (status = 400, description = "Default JSON elements configured by the user are invalid"),
-        (status = 404, description = "No places found"),
+        (status = 404, description = "No places found with the given IDs"),
         (status = 422, description = "Error when parsing a response from a model API"),
         (status = 502, description = "Error when forwarding request to model APIs")
     )
@@ -213,15 +349,20 @@ async fn add_place(
 #[axum::debug_handler]
 async fn delete_matching_places(
     State(state): State<ToiState>,
+    user: CurrentUser,
     Json(params): Json<PlaceSearchParams>,
 ) -> Result<Json<Vec<Place>>, (StatusCode, String)> {
-    let mut conn = state.pool.get().await.map_err(utils::internal_error)?;
-    let ids = search_places(&state, params, &mut conn).await?;
+    let mut conn = utils::get_conn(&state.pool).await?;
+    let requested_ids = params.ids.clone();
+    let ids = search_places(&state, user.id, params, &mut conn)
+        .await?
+        .items;
     let places = diesel::delete(schema::places::table.filter(schema::places::id.eq_any(ids)))
         .returning(Place::as_returning())
         .load(&mut conn)
         .await
         .map_err(utils::diesel_error)?;
+    utils::check_ids_found(requested_ids.as_deref(), &places, "places")?;
     Ok(Json(places))
 }
 
@@ -240,9 +381,8 @@ async fn delete_matching_places(
     ),
     request_body = PlaceSearchParams,
     responses(
-        (status = 200, description = "Successfully got places", body = [Place]),
+        (status = 200, description = "Successfully got places", body = Page<Place>),
         (status = 400, description = "Default JSON elements configured by the user are invalid"),
-        (status = 404, description = "No places found"),
         (status = 422, description = "Error when parsing a response from a model API"),
         (status = 502, description = "Error when forwarding request to model APIs")
     )
@@ -250,17 +390,31 @@ async fn delete_matching_places(
 #[axum::debug_handler]
 async fn get_matching_places(
     State(state): State<ToiState>,
+    user: CurrentUser,
     Json(params): Json<PlaceSearchParams>,
-) -> Result<Json<Vec<Place>>, (StatusCode, String)> {
-    let mut conn = state.pool.get().await.map_err(utils::internal_error)?;
-    let ids = search_places(&state, params, &mut conn).await?;
-    let places = schema::places::table
+) -> Result<Json<Page<Place>>, (StatusCode, String)> {
+    let mut conn = utils::get_conn(&state.pool).await?;
+    let Page {
+        items: ids,
+        total,
+        offset,
+        limit,
+    } = search_places(&state, user.id, params, &mut conn).await?;
+    let mut places: Vec<Place> = schema::places::table
         .select(Place::as_select())
-        .filter(schema::places::id.eq_any(ids))
+        .filter(schema::places::id.eq_any(&ids))
         .load(&mut conn)
         .await
         .map_err(utils::diesel_error)?;
-    Ok(Json(places))
+
+    // Keep places in the order they were searched in (e.g., nearest first).
+    places.sort_by_key(|place| ids.iter().position(|id| *id == place.id));
+    Ok(Json(Page {
+        items: places,
+        total,
+        offset,
+        limit,
+    }))
 }
 
 /// Update and return a place.
@@ -284,9 +438,10 @@ async fn get_matching_places(
 #[axum::debug_handler]
 async fn update_matching_place(
     State(state): State<ToiState>,
+    user: CurrentUser,
     Json(params): Json<UpdatePlaceRequest>,
 ) -> Result<Json<Place>, (StatusCode, String)> {
-    let mut conn = state.pool.get().await.map_err(utils::internal_error)?;
+    let mut conn = utils::get_conn(&state.pool).await?;
     let UpdatePlaceRequest {
         id,
         place_updates,
@@ -302,11 +457,15 @@ async fn update_matching_place(
         use_reranking_filter,
         created_from,
         created_to,
+        near_query: None,
+        radius_km: None,
         order_by,
         limit: Some(1),
+        offset: None,
     };
-    let id = search_places(&state, params, &mut conn)
+    let id = search_places(&state, user.id, params, &mut conn)
         .await?
+        .items
         .into_iter()
         .next()
         .ok_or((StatusCode::NOT_FOUND, "place not found".to_string()))?;
@@ -316,7 +475,13 @@ async fn update_matching_place(
         .first(&mut conn)
         .await
         .map_err(utils::diesel_error)?;
+    let address_updated = place_updates.address.is_some();
     place.update(place_updates);
+    let (latitude, longitude) = if address_updated {
+        geocode_address(&state, place.address.as_deref()).await
+    } else {
+        (place.latitude, place.longitude)
+    };
     let Place {
         id,
         name,
@@ -342,11 +507,14 @@ async fn update_matching_place(
         phone,
     } = new_place_request;
     let new_place = NewPlace {
+        user_id: user.id,
         name,
         description,
         address,
         phone,
         embedding,
+        latitude,
+        longitude,
     };
     let place = diesel::update(schema::places::table.filter(schema::places::id.eq(id)))
         .set(&new_place)
@@ -356,3 +524,32 @@ async fn update_matching_place(
         .map_err(utils::diesel_error)?;
     Ok(Json(place))
 }
+
+/// Get a place using its database-generated ID.
+#[utoipa::path(
+    get,
+    path = "/{id}",
+    params(
+        ("id" = i32, Path, description = "Database-generated place ID")
+    ),
+    responses(
+        (status = 200, description = "Successfully got a place", body = Place),
+        (status = 404, description = "Place not found")
+    )
+)]
+#[axum::debug_handler]
+async fn get_place(
+    State(state): State<ToiState>,
+    user: CurrentUser,
+    Path(id): Path<i32>,
+) -> Result<Json<Place>, (StatusCode, String)> {
+    let mut conn = utils::get_conn(&state.pool).await?;
+    let place = schema::places::table
+        .select(Place::as_select())
+        .filter(schema::places::id.eq(id))
+        .filter(schema::places::user_id.eq(user.id))
+        .first(&mut conn)
+        .await
+        .map_err(utils::diesel_error)?;
+    Ok(Json(place))
+}
@@ -0,0 +1,24 @@
+use axum::{extract::State, response::Json};
+use utoipa_axum::{router::OpenApiRouter, routes};
+
+use crate::models::{pool::PoolStatus, state::ToiState};
+
+pub fn pool_router(state: ToiState) -> OpenApiRouter {
+    OpenApiRouter::new()
+        .routes(routes!(get_pool_status))
+        .with_state(state)
+}
+
+/// Get how many database connections are open and in use, and how long
+/// requests have waited for them.
+#[utoipa::path(
+    get,
+    path = "",
+    responses(
+        (status = 200, description = "Successfully got database connection pool status", body = PoolStatus)
+    )
+)]
+#[axum::debug_handler]
+async fn get_pool_status(State(state): State<ToiState>) -> Json<PoolStatus> {
+    Json(PoolStatus::from(state.pool.state()))
+}
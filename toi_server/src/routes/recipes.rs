@@ -1,22 +1,48 @@
-use axum::{extract::State, http::StatusCode, response::Json};
-use diesel::{BoolExpressionMethods, ExpressionMethods, JoinOnDsl, QueryDsl, SelectableHelper};
+use axum::{
+    extract::{Path, State},
+    http::{HeaderMap, StatusCode},
+    response::Json,
+};
+use diesel::{
+    BoolExpressionMethods, ExpressionMethods, JoinOnDsl, PgTextExpressionMethods, QueryDsl,
+    SelectableHelper,
+    dsl::sql,
+    expression::SqlLiteral,
+    sql_types::{Array, BigInt, Text},
+};
 use diesel_async::{AsyncConnection, RunQueryDsl, scoped_futures::ScopedFutureExt};
 use pgvector::VectorExpressionMethods;
 use schemars::schema_for;
+use std::collections::{BTreeMap, HashMap};
+use toi::{GenerationRequest, Message, MessageRole};
 use utoipa_axum::{router::OpenApiRouter, routes};
 
 use crate::{
+    auth::CurrentUser,
+    embeddings::EmbeddedTable,
+    idempotency::IdempotencyKey,
     models::{
-        client::{EmbeddingPromptTemplate, EmbeddingRequest, RerankRequest},
+        assistant::parse_generated_response,
+        client::{BatchEmbeddingRequest, EmbeddingCache, EmbeddingRequest, TokenUsage},
+        deletion::{DeleteFilters, DeleteParams},
+        error::ToiError,
+        pagination::{Count, Page, SearchResponse},
+        prompts::{RecipeScalePrompt, ShoppingListPrompt, SystemPrompt},
         recipes::{
-            NewRecipe, NewRecipeRequest, NewRecipeTag, NewRecipeTagsRequest, Recipe, RecipePreview,
-            RecipeSearchParams, RecipeTagSearchParams, RecipeTags,
+            GeneratedScaledIngredients, GeneratedShoppingList, NewRecipe, NewRecipeRequest,
+            NewRecipeTag, NewRecipeTagsRequest, Recipe, RecipePreview, RecipePreviewDetailed,
+            RecipePreviewResult, RecipePreviewWithTags, RecipeScaleRequest, RecipeSearchParams,
+            RecipeTagSearchParams, RecipeTags, RecipeWithTags, ScaledRecipe, ShoppingList,
+            ShoppingListCategory, ShoppingListRequest,
         },
         state::ToiState,
         tags::{Tag, TagSearchParams},
+        todos::{NewTodo, Todo},
     },
-    routes::tags::search_tags,
-    schema, utils,
+    routes::tags::{resolve_tags, search_tags},
+    schema,
+    search::{self, RerankOptions},
+    utils,
 };
 
 // Prefixes are used for embedding instructions.
@@ -34,32 +60,76 @@ pub fn recipes_router(state: ToiState) -> OpenApiRouter {
         .routes(routes!(add_recipe_tags))
         .routes(routes!(get_matching_recipe_tags))
         .routes(routes!(delete_matching_recipe_tags))
+        .routes(routes!(scale_recipe))
+        .routes(routes!(make_shopping_list))
+        .routes(routes!(get_recipe))
         .with_state(state)
 }
 
 pub async fn search_recipes(
     state: &ToiState,
+    user_id: i32,
     params: RecipeSearchParams,
+    embeddings: &mut EmbeddingCache,
     conn: &mut utils::Conn<'_>,
-) -> Result<Vec<i32>, (StatusCode, String)> {
+) -> Result<Page<i32>, (StatusCode, String)> {
+    // The total is counted alongside a page's items, so it's counted on
+    // its own when the offset is past the end and there aren't any.
+    let count_params = (params.offset.is_some_and(|offset| offset > 0)
+        && !params.count_only.unwrap_or_default())
+    .then(|| RecipeSearchParams {
+        use_reranking_filter: None,
+        limit: None,
+        offset: None,
+        count_only: Some(true),
+        ..params.clone()
+    });
+    let mut page = search_recipes_page(state, user_id, params, embeddings, conn).await?;
+    if page.total == 0
+        && let Some(count_params) = count_params
+    {
+        page.total = search_recipes_page(state, user_id, count_params, embeddings, conn)
+            .await?
+            .total;
+    }
+    Ok(page)
+}
+
+async fn search_recipes_page(
+    state: &ToiState,
+    user_id: i32,
+    params: RecipeSearchParams,
+    embeddings: &mut EmbeddingCache,
+    conn: &mut utils::Conn<'_>,
+) -> Result<Page<i32>, (StatusCode, String)> {
     let RecipeSearchParams {
         ids,
         query,
         use_reranking_filter,
+        ingredients_query,
         created_from,
         created_to,
         order_by,
         tags,
+        tags_match,
         limit,
+        offset,
+        count_only,
+        ..
     } = params;
 
     let mut sql_query = schema::recipes::table
-        .select(RecipePreview::as_select())
-        .inner_join(
-            schema::recipe_tags::table.on(schema::recipe_tags::recipe_id.eq(schema::recipes::id)),
-        )
+        .select((RecipePreview::as_select(), utils::total_count()))
         .into_boxed();
 
+    // Filter items whose ingredients contain every ingredient word.
+    if let Some(ingredients_query) = ingredients_query {
+        for word in ingredients_query.split_whitespace() {
+            let pattern = format!("%{}%", utils::escape_like_pattern(word));
+            sql_query = sql_query.filter(schema::recipes::ingredients.ilike(pattern));
+        }
+    }
+
     // Filter items created on or after date.
     if let Some(created_from) = created_from {
         sql_query = sql_query.filter(schema::recipes::created_at.ge(created_from));
@@ -70,6 +140,10 @@ pub async fn search_recipes(
         sql_query = sql_query.filter(schema::recipes::created_at.le(created_to));
     }
 
+    // Items are only ordered by their distance to the query if they aren't
+    // ordered some other way.
+    let ordered_by_distance = order_by.is_none();
+
     // Order items.
     match order_by {
         Some(utils::OrderBy::Oldest) => sql_query = sql_query.order(schema::recipes::created_at),
@@ -79,13 +153,15 @@ pub async fn search_recipes(
         None => {
             // By default, filter items similar to a given query.
             if let Some(ref query) = query {
-                let input = EmbeddingPromptTemplate::builder()
-                    .instruction_prefix(INSTRUCTION_PREFIX.to_string())
-                    .query_prefix(QUERY_PREFIX.to_string())
-                    .build()
+                let input = state
+                    .embedding_instructions
+                    .template("recipes", INSTRUCTION_PREFIX, QUERY_PREFIX)
                     .apply(query);
                 let embedding_request = EmbeddingRequest { input };
-                let embedding = state.model_client.embed(embedding_request).await?;
+                let embedding = state
+                    .model_client
+                    .embed_cached(embedding_request, embeddings)
+                    .await?;
                 sql_query = sql_query
                     .filter(
                         schema::recipes::embedding
@@ -97,24 +173,19 @@ pub async fn search_recipes(
         }
     }
 
+    // Filter items with any or all of the tags.
     if let Some(tags) = tags {
-        let mut tag_ids = vec![];
-        for tag in tags {
-            let params = TagSearchParams {
-                ids: None,
-                query: Some(tag),
-                use_reranking_filter: Some(true),
-                use_edit_distance_filter: Some(true),
-                limit: Some(1),
-            };
-            let matching_tag_ids = search_tags(state, params, conn).await?;
-            let tag_id = matching_tag_ids
-                .into_iter()
-                .next()
-                .ok_or((StatusCode::NOT_FOUND, "no matching tags".to_string()))?;
-            tag_ids.push(tag_id);
-        }
-        sql_query = sql_query.filter(schema::recipe_tags::tag_id.eq_any(tag_ids));
+        let tag_ids = resolve_tags(state, user_id, tags).await?;
+        let recipe_tags: Vec<(i32, i32)> = schema::recipe_tags::table
+            .select((schema::recipe_tags::recipe_id, schema::recipe_tags::tag_id))
+            .filter(schema::recipe_tags::tag_id.eq_any(&tag_ids))
+            .load(conn)
+            .await
+            .map_err(utils::diesel_error)?;
+        let recipe_ids = tags_match
+            .unwrap_or_default()
+            .matching_ids(recipe_tags, tag_ids.len());
+        sql_query = sql_query.filter(schema::recipes::id.eq_any(recipe_ids));
     }
 
     // Filter items according to their ids.
@@ -122,43 +193,152 @@ pub async fn search_recipes(
         sql_query = sql_query.or_filter(schema::recipes::id.eq_any(ids));
     }
 
-    // Limit number of items.
-    if let Some(limit) = limit {
+    // Only the user's own items are searched. This comes after the other
+    // filters so items selected by their ids are still only the user's.
+    sql_query = sql_query.filter(schema::recipes::user_id.eq(user_id));
+
+    // Limit number of items. Only the total is needed when counting items
+    // that don't need to be reranked, so only one item is loaded. Items
+    // counted once they're reranked aren't limited so they're all counted.
+    let count_only = count_only.unwrap_or_default();
+    let count_total =
+        count_only && !search::needs_reranking(query.as_deref(), use_reranking_filter);
+    if count_total {
+        sql_query = sql_query.limit(1);
+    } else if let Some(limit) = limit.filter(|_| !count_only) {
         sql_query = sql_query.limit(limit);
     }
 
+    // Skip a number of items.
+    if let Some(offset) = offset.filter(|_| !count_only) {
+        sql_query = sql_query.offset(offset);
+    }
+
     // Get the item that matches the query.
-    let recipe_previews: Vec<RecipePreview> =
+    let recipe_previews: Vec<(RecipePreview, i64)> =
         sql_query.load(conn).await.map_err(utils::diesel_error)?;
-    let (ids, documents): (Vec<i32>, Vec<String>) = recipe_previews
+    let total = recipe_previews
+        .as_slice()
+        .first()
+        .map_or(0, |(_, total)| *total);
+    if count_total {
+        return Ok(Page::count(total));
+    }
+    let mut ids_docs: Vec<(i32, String)> = recipe_previews
         .into_iter()
-        .map(|recipe| (recipe.id, recipe.description))
-        .unzip();
-    if ids.is_empty() {
-        return Ok(ids);
+        .map(|(recipe, _)| (recipe.id, recipe.description))
+        .collect();
+
+    // Items that aren't ordered by their distance to the query are put in
+    // that order so only the furthest ones are left out of reranking.
+    if !ordered_by_distance
+        && search::needs_reranking(query.as_deref(), use_reranking_filter)
+        && let Some(ref query) = query
+    {
+        let input = state
+            .embedding_instructions
+            .template("recipes", INSTRUCTION_PREFIX, QUERY_PREFIX)
+            .apply(query);
+        ids_docs =
+            search::closest_first(state, EmbeddedTable::Recipes, input, ids_docs, conn).await?;
     }
 
     // Rerank and filter items once more.
-    let ids = match (query, use_reranking_filter) {
-        (Some(query), Some(true)) => {
-            let rerank_request = RerankRequest { query, documents };
-            let rerank_response = state.model_client.rerank(rerank_request).await?;
-            rerank_response
-                .results
-                .into_iter()
-                .filter(|item| item.relevance_score >= state.server_config.similarity_threshold)
-                .map(|item| ids[item.index])
-                .collect()
-        }
-        _ => ids,
-    };
+    let ids = search::rerank_filter(
+        state,
+        query,
+        use_reranking_filter,
+        ids_docs,
+        &RerankOptions::default(),
+    )
+    .await?;
 
-    Ok(ids)
+    if count_only {
+        return Ok(Page::count(ids.len().try_into().unwrap_or(i64::MAX)));
+    }
+
+    Ok(Page {
+        items: ids,
+        total,
+        offset,
+        limit,
+    })
+}
+
+/// Load the tags of many recipes at once, keyed by recipe ID. Recipes
+/// without tags aren't included.
+pub async fn load_recipe_tags(
+    recipe_ids: &[i32],
+    conn: &mut utils::Conn<'_>,
+) -> Result<HashMap<i32, Vec<Tag>>, (StatusCode, String)> {
+    let recipe_tags: Vec<(i32, Tag)> = schema::recipe_tags::table
+        .inner_join(schema::tags::table)
+        .select((schema::recipe_tags::recipe_id, Tag::as_select()))
+        .filter(schema::recipe_tags::recipe_id.eq_any(recipe_ids))
+        .order(schema::tags::name)
+        .load(conn)
+        .await
+        .map_err(utils::diesel_error)?;
+    let mut tags_by_recipe: HashMap<i32, Vec<Tag>> = HashMap::new();
+    for (recipe_id, tag) in recipe_tags {
+        tags_by_recipe.entry(recipe_id).or_default().push(tag);
+    }
+    Ok(tags_by_recipe)
+}
+
+/// Names of the tags joined to a recipe in alphabetical order, or an empty
+/// array if it doesn't have any.
+fn recipe_tag_names() -> SqlLiteral<Array<Text>> {
+    sql::<Array<Text>>(
+        "COALESCE(ARRAY_AGG(tags.name ORDER BY tags.name) FILTER (WHERE tags.name IS NOT NULL), '{}')",
+    )
+}
+
+/// Number of non-empty lines in a recipe's ingredients.
+fn ingredient_count() -> SqlLiteral<BigInt> {
+    sql::<BigInt>(
+        "(SELECT COUNT(*) FROM regexp_split_to_table(recipes.ingredients, '\\r?\\n') AS line \
+        WHERE btrim(line) <> '')",
+    )
+}
+
+/// Load previews of many recipes at once with their tag names and
+/// ingredient counts. Tags are grouped by recipe in the query, so recipes
+/// without tags are still loaded.
+pub async fn load_detailed_recipe_previews(
+    recipe_ids: &[i32],
+    conn: &mut utils::Conn<'_>,
+) -> Result<Vec<RecipePreviewDetailed>, (StatusCode, String)> {
+    let recipe_previews: Vec<(RecipePreview, Vec<String>, i64)> = schema::recipes::table
+        .left_join(schema::recipe_tags::table)
+        .left_join(schema::tags::table.on(schema::tags::id.eq(schema::recipe_tags::tag_id)))
+        .filter(schema::recipes::id.eq_any(recipe_ids))
+        .group_by(schema::recipes::id)
+        .select((
+            RecipePreview::as_select(),
+            recipe_tag_names(),
+            ingredient_count(),
+        ))
+        .load(conn)
+        .await
+        .map_err(utils::diesel_error)?;
+    Ok(recipe_previews
+        .into_iter()
+        .map(
+            |(recipe_preview, tag_names, ingredient_count)| RecipePreviewDetailed {
+                recipe_preview,
+                tag_names,
+                ingredient_count,
+            },
+        )
+        .collect())
 }
 
 pub async fn search_recipe_tags(
     state: &ToiState,
+    user_id: i32,
     params: RecipeTagSearchParams,
+    embeddings: &mut EmbeddingCache,
     conn: &mut utils::Conn<'_>,
 ) -> Result<(RecipePreview, Vec<i32>), (StatusCode, String)> {
     let RecipeTagSearchParams {
@@ -178,14 +358,21 @@ pub async fn search_recipe_tags(
         ids: recipe_id.map(|i| vec![i]),
         query: recipe_query,
         use_reranking_filter: recipe_use_reranking_filter,
+        ingredients_query: None,
         created_from: recipe_created_from,
         created_to: recipe_created_to,
         order_by: recipe_order_by,
         tags: None,
+        tags_match: None,
         limit: Some(1),
+        offset: None,
+        count_only: None,
+        include_tags: None,
+        detailed: None,
     };
-    let recipe_id = search_recipes(state, recipe_query_params, conn)
+    let recipe_id = search_recipes(state, user_id, recipe_query_params, embeddings, conn)
         .await?
+        .items
         .into_iter()
         .next()
         .ok_or((StatusCode::NOT_FOUND, "recipe not found".to_string()))?;
@@ -215,9 +402,10 @@ pub async fn search_recipe_tags(
         query: tag_query,
         use_reranking_filter: tag_use_reranking_filter,
         use_edit_distance_filter: tag_use_edit_distance_filter,
+        order_by: None,
         limit: tag_limit,
     };
-    let tag_ids = search_tags(state, tag_query_params, conn).await?;
+    let tag_ids = search_tags(state, user_id, tag_query_params, embeddings, conn).await?;
     Ok((recipe_preview, tag_ids))
 }
 
@@ -237,6 +425,7 @@ pub async fn search_recipe_tags(
     responses(
         (status = 201, description = "Successfully added a recipe", body = Recipe),
         (status = 400, description = "Default JSON elements configured by the user are invalid"),
+        (status = 409, description = "Idempotency key was already used for a different request"),
         (status = 422, description = "Error when parsing a response from a model API"),
         (status = 502, description = "Error when forwarding request to model APIs")
     )
@@ -244,9 +433,20 @@ pub async fn search_recipe_tags(
 #[axum::debug_handler]
 async fn add_recipe(
     State(state): State<ToiState>,
+    user: CurrentUser,
+    headers: HeaderMap,
     Json(params): Json<NewRecipeRequest>,
 ) -> Result<Json<Recipe>, (StatusCode, String)> {
-    let mut conn = state.pool.get().await.map_err(utils::internal_error)?;
+    let idempotency_key =
+        IdempotencyKey::from_request(&state, &headers, user.id, "/recipes", &params)?;
+    // Replay repeats before resolving tags or embedding anything. Tags are
+    // resolved with their own connection, so this one isn't held onto.
+    if let Some(key) = &idempotency_key {
+        let mut conn = utils::get_conn(&state.pool).await?;
+        if let Some(recipe) = key.replay(&mut conn).await? {
+            return Ok(Json(recipe));
+        }
+    }
     let NewRecipeRequest {
         description,
         ingredients,
@@ -254,22 +454,8 @@ async fn add_recipe(
         tags,
     } = params;
     // Get tag IDs for matching tags.
-    let mut tag_ids = vec![];
-    for tag in tags {
-        let params = TagSearchParams {
-            ids: None,
-            query: Some(tag),
-            use_reranking_filter: Some(true),
-            use_edit_distance_filter: Some(true),
-            limit: Some(1),
-        };
-        let matching_tag_ids = search_tags(&state, params, &mut conn).await?;
-        let tag_id = matching_tag_ids
-            .into_iter()
-            .next()
-            .ok_or((StatusCode::NOT_FOUND, "no matching tags".to_string()))?;
-        tag_ids.push(tag_id);
-    }
+    let tag_ids = resolve_tags(&state, user.id, tags).await?;
+    let mut conn = utils::get_conn(&state.pool).await?;
     // Get embedding for recipe description.
     let embedding_request = EmbeddingRequest {
         input: description.clone(),
@@ -277,14 +463,22 @@ async fn add_recipe(
     let embedding = state.model_client.embed(embedding_request).await?;
     // Within a single transaction, add the recipe, and then add the recipe tags.
     let new_recipe = NewRecipe {
+        user_id: user.id,
         description,
         ingredients,
         instructions,
         embedding,
     };
     let recipe = conn
-        .transaction(|mut conn| {
+        .transaction::<_, ToiError, _>(|mut conn| {
             async move {
+                // Check again in case a request with the same key added the
+                // recipe while this one was embedding it.
+                if let Some(key) = &idempotency_key
+                    && let Some(recipe) = key.replay(&mut conn).await?
+                {
+                    return Ok(recipe);
+                }
                 // Insert the new recipe to get its database-generated ID.
                 let recipe: Recipe = diesel::insert_into(schema::recipes::table)
                     .values(new_recipe)
@@ -303,12 +497,14 @@ async fn add_recipe(
                     .values(new_recipe_tags)
                     .execute(&mut conn)
                     .await?;
+                if let Some(key) = &idempotency_key {
+                    key.store(&recipe, &mut conn).await?;
+                }
                 Ok(recipe)
             }
             .scope_boxed()
         })
-        .await
-        .map_err(utils::diesel_error)?;
+        .await?;
     Ok(Json(recipe))
 }
 
@@ -335,9 +531,11 @@ async fn add_recipe(
 #[axum::debug_handler]
 async fn add_recipe_tags(
     State(state): State<ToiState>,
+    user: CurrentUser,
     Json(params): Json<NewRecipeTagsRequest>,
 ) -> Result<Json<Vec<Recipe>>, (StatusCode, String)> {
-    let mut conn = state.pool.get().await.map_err(utils::internal_error)?;
+    let mut conn = utils::get_conn(&state.pool).await?;
+    let mut embeddings = EmbeddingCache::default();
     let NewRecipeTagsRequest {
         ids,
         query,
@@ -352,28 +550,25 @@ async fn add_recipe_tags(
         ids,
         query,
         use_reranking_filter,
+        ingredients_query: None,
         created_from,
         created_to,
         order_by,
         tags: None,
+        tags_match: None,
         limit,
+        offset: None,
+        count_only: None,
+        include_tags: None,
+        detailed: None,
     };
-    let recipe_ids = search_recipes(&state, params, &mut conn).await?;
+    let recipe_ids = search_recipes(&state, user.id, params, &mut embeddings, &mut conn)
+        .await?
+        .items;
     // Get tag IDs for matching tags.
+    let tag_ids = resolve_tags(&state, user.id, tags).await?;
     let mut new_recipe_tags = vec![];
-    for tag in tags {
-        let params = TagSearchParams {
-            ids: None,
-            query: Some(tag),
-            use_reranking_filter: Some(true),
-            use_edit_distance_filter: Some(true),
-            limit: Some(1),
-        };
-        let matching_tag_ids = search_tags(&state, params, &mut conn).await?;
-        let tag_id = matching_tag_ids
-            .into_iter()
-            .next()
-            .ok_or((StatusCode::NOT_FOUND, "no matching tags".to_string()))?;
+    for tag_id in tag_ids {
         for recipe_id in &recipe_ids {
             let new_recipe_tag = NewRecipeTag {
                 recipe_id: *recipe_id,
@@ -406,13 +601,13 @@ async fn add_recipe_tags(
     post,
     path = "/delete",
     extensions(
-        ("x-json-schema-body" = json!(schema_for!(RecipeSearchParams)))
+        ("x-json-schema-body" = json!(schema_for!(DeleteParams<RecipeSearchParams>)))
     ),
-    request_body = RecipeSearchParams,
+    request_body = DeleteParams<RecipeSearchParams>,
     responses(
         (status = 200, description = "Successfully deleted recipes", body = [Recipe]),
-        (status = 400, description = "Default JSON elements configured by the user are invalid"),
-        (status = 404, description = "No recipes found"),
+        (status = 400, description = "Default JSON elements configured by the user are invalid, or deleting every item wasn't confirmed"),
+        (status = 404, description = "No recipes found with the given IDs"),
         (status = 422, description = "Error when parsing a response from a model API"),
         (status = 502, description = "Error when forwarding request to model APIs")
     )
@@ -420,15 +615,28 @@ async fn add_recipe_tags(
 #[axum::debug_handler]
 async fn delete_matching_recipes(
     State(state): State<ToiState>,
-    Json(params): Json<RecipeSearchParams>,
+    user: CurrentUser,
+    Json(params): Json<DeleteParams<RecipeSearchParams>>,
 ) -> Result<Json<Vec<Recipe>>, (StatusCode, String)> {
-    let mut conn = state.pool.get().await.map_err(utils::internal_error)?;
-    let ids = search_recipes(&state, params, &mut conn).await?;
+    let params = params.into_checked()?;
+    let mut conn = utils::get_conn(&state.pool).await?;
+    let mut embeddings = EmbeddingCache::default();
+
+    // Items are always needed here, so counting is ignored.
+    let params = RecipeSearchParams {
+        count_only: None,
+        ..params
+    };
+    let requested_ids = params.ids.clone();
+    let ids = search_recipes(&state, user.id, params, &mut embeddings, &mut conn)
+        .await?
+        .items;
     let recipes = diesel::delete(schema::recipes::table.filter(schema::recipes::id.eq_any(ids)))
         .returning(Recipe::as_returning())
         .load(&mut conn)
         .await
         .map_err(utils::diesel_error)?;
+    utils::check_ids_found(requested_ids.as_deref(), &recipes, "recipes")?;
     Ok(Json(recipes))
 }
 
@@ -444,13 +652,13 @@ async fn delete_matching_recipes(
     post,
     path = "/previews/delete",
     extensions(
-        ("x-json-schema-body" = json!(schema_for!(RecipeSearchParams)))
+        ("x-json-schema-body" = json!(schema_for!(DeleteParams<RecipeSearchParams>)))
     ),
-    request_body = RecipeSearchParams,
+    request_body = DeleteParams<RecipeSearchParams>,
     responses(
         (status = 200, description = "Successfully deleted recipe previews", body = [RecipePreview]),
-        (status = 400, description = "Default JSON elements configured by the user are invalid"),
-        (status = 404, description = "No recipe previews found"),
+        (status = 400, description = "Default JSON elements configured by the user are invalid, or deleting every item wasn't confirmed"),
+        (status = 404, description = "No recipe previews found with the given IDs"),
         (status = 422, description = "Error when parsing a response from a model API"),
         (status = 502, description = "Error when forwarding request to model APIs")
     )
@@ -458,16 +666,29 @@ async fn delete_matching_recipes(
 #[axum::debug_handler]
 async fn delete_matching_recipe_previews(
     State(state): State<ToiState>,
-    Json(params): Json<RecipeSearchParams>,
+    user: CurrentUser,
+    Json(params): Json<DeleteParams<RecipeSearchParams>>,
 ) -> Result<Json<Vec<RecipePreview>>, (StatusCode, String)> {
-    let mut conn = state.pool.get().await.map_err(utils::internal_error)?;
-    let ids = search_recipes(&state, params, &mut conn).await?;
+    let params = params.into_checked()?;
+    let mut conn = utils::get_conn(&state.pool).await?;
+    let mut embeddings = EmbeddingCache::default();
+
+    // Items are always needed here, so counting is ignored.
+    let params = RecipeSearchParams {
+        count_only: None,
+        ..params
+    };
+    let requested_ids = params.ids.clone();
+    let ids = search_recipes(&state, user.id, params, &mut embeddings, &mut conn)
+        .await?
+        .items;
     let recipe_previews =
         diesel::delete(schema::recipes::table.filter(schema::recipes::id.eq_any(ids)))
             .returning(RecipePreview::as_returning())
             .load(&mut conn)
             .await
             .map_err(utils::diesel_error)?;
+    utils::check_ids_found(requested_ids.as_deref(), &recipe_previews, "recipes")?;
     Ok(Json(recipe_previews))
 }
 
@@ -497,10 +718,13 @@ async fn delete_matching_recipe_previews(
 #[axum::debug_handler]
 async fn delete_matching_recipe_tags(
     State(state): State<ToiState>,
+    user: CurrentUser,
     Json(params): Json<RecipeTagSearchParams>,
 ) -> Result<Json<RecipeTags>, (StatusCode, String)> {
-    let mut conn = state.pool.get().await.map_err(utils::internal_error)?;
-    let (recipe_preview, ids) = search_recipe_tags(&state, params, &mut conn).await?;
+    let mut conn = utils::get_conn(&state.pool).await?;
+    let mut embeddings = EmbeddingCache::default();
+    let (recipe_preview, ids) =
+        search_recipe_tags(&state, user.id, params, &mut embeddings, &mut conn).await?;
     let (recipe_preview, tags) = {
         conn.transaction(|mut conn| {
             async move {
@@ -549,9 +773,8 @@ async fn delete_matching_recipe_tags(
     ),
     request_body = RecipeSearchParams,
     responses(
-        (status = 200, description = "Successfully got recipes", body = [Recipe]),
+        (status = 200, description = "Successfully got recipes or their count", body = SearchResponse<RecipeWithTags>),
         (status = 400, description = "Default JSON elements configured by the user are invalid"),
-        (status = 404, description = "No recipes found"),
         (status = 422, description = "Error when parsing a response from a model API"),
         (status = 502, description = "Error when forwarding request to model APIs")
     )
@@ -559,22 +782,56 @@ async fn delete_matching_recipe_tags(
 #[axum::debug_handler]
 async fn get_matching_recipes(
     State(state): State<ToiState>,
+    user: CurrentUser,
     Json(params): Json<RecipeSearchParams>,
-) -> Result<Json<Vec<Recipe>>, (StatusCode, String)> {
-    let mut conn = state.pool.get().await.map_err(utils::internal_error)?;
-    let ids = search_recipes(&state, params, &mut conn).await?;
-    let recipes = schema::recipes::table
+) -> Result<Json<SearchResponse<RecipeWithTags>>, (StatusCode, String)> {
+    let count_only = params.count_only.unwrap_or_default();
+    let include_tags = params.include_tags.unwrap_or_default();
+    let mut conn = utils::get_conn(&state.pool).await?;
+    let mut embeddings = EmbeddingCache::default();
+    let Page {
+        items: ids,
+        total,
+        offset,
+        limit,
+    } = search_recipes(&state, user.id, params, &mut embeddings, &mut conn).await?;
+    if count_only {
+        return Ok(Json(SearchResponse::Count(Count { count: total })));
+    }
+    let recipes: Vec<Recipe> = schema::recipes::table
         .select(Recipe::as_select())
-        .filter(schema::recipes::id.eq_any(ids))
+        .filter(schema::recipes::id.eq_any(&ids))
         .load(&mut conn)
         .await
         .map_err(utils::diesel_error)?;
-    Ok(Json(recipes))
+
+    // Tags for all the recipes are loaded at once rather than per recipe.
+    let mut tags_by_recipe = if include_tags {
+        Some(load_recipe_tags(&ids, &mut conn).await?)
+    } else {
+        None
+    };
+    let recipes = recipes
+        .into_iter()
+        .map(|recipe| RecipeWithTags {
+            tags: tags_by_recipe
+                .as_mut()
+                .map(|tags_by_recipe| tags_by_recipe.remove(&recipe.id).unwrap_or_default()),
+            recipe,
+        })
+        .collect();
+    Ok(Json(SearchResponse::Page(Page {
+        items: recipes,
+        total,
+        offset,
+        limit,
+    })))
 }
 
 /// Get recipe previews.
 ///
-/// Useful for quickly searching through many recipes.
+/// Useful for quickly searching through many recipes. Detailed previews
+/// also have each recipe's tag names and ingredient count.
 ///
 /// Example queries for getting recipe previews using this endpoint:
 /// - Get recipe previews where
@@ -588,9 +845,8 @@ async fn get_matching_recipes(
     ),
     request_body = RecipeSearchParams,
     responses(
-        (status = 200, description = "Successfully got recipe previews", body = [RecipePreview]),
+        (status = 200, description = "Successfully got recipe previews, detailed recipe previews, or their count", body = SearchResponse<RecipePreviewResult>),
         (status = 400, description = "Default JSON elements configured by the user are invalid"),
-        (status = 404, description = "No recipe previews found"),
         (status = 422, description = "Error when parsing a response from a model API"),
         (status = 502, description = "Error when forwarding request to model APIs")
     )
@@ -598,17 +854,68 @@ async fn get_matching_recipes(
 #[axum::debug_handler]
 async fn get_matching_recipe_previews(
     State(state): State<ToiState>,
+    user: CurrentUser,
     Json(params): Json<RecipeSearchParams>,
-) -> Result<Json<Vec<RecipePreview>>, (StatusCode, String)> {
-    let mut conn = state.pool.get().await.map_err(utils::internal_error)?;
-    let ids = search_recipes(&state, params, &mut conn).await?;
-    let recipe_previews = schema::recipes::table
+) -> Result<Json<SearchResponse<RecipePreviewResult>>, (StatusCode, String)> {
+    let count_only = params.count_only.unwrap_or_default();
+    let include_tags = params.include_tags.unwrap_or_default();
+    let detailed = params.detailed.unwrap_or_default();
+    let mut conn = utils::get_conn(&state.pool).await?;
+    let mut embeddings = EmbeddingCache::default();
+    let Page {
+        items: ids,
+        total,
+        offset,
+        limit,
+    } = search_recipes(&state, user.id, params, &mut embeddings, &mut conn).await?;
+    if count_only {
+        return Ok(Json(SearchResponse::Count(Count { count: total })));
+    }
+    if detailed {
+        let recipe_previews = load_detailed_recipe_previews(&ids, &mut conn)
+            .await?
+            .into_iter()
+            .map(RecipePreviewResult::Detailed)
+            .collect();
+        return Ok(Json(SearchResponse::Page(Page {
+            items: recipe_previews,
+            total,
+            offset,
+            limit,
+        })));
+    }
+    let recipe_previews: Vec<RecipePreview> = schema::recipes::table
         .select(RecipePreview::as_select())
-        .filter(schema::recipes::id.eq_any(ids))
+        .filter(schema::recipes::id.eq_any(&ids))
         .load(&mut conn)
         .await
         .map_err(utils::diesel_error)?;
-    Ok(Json(recipe_previews))
+
+    // Tags for all the recipes are loaded at once rather than per recipe.
+    let mut tags_by_recipe = if include_tags {
+        Some(load_recipe_tags(&ids, &mut conn).await?)
+    } else {
+        None
+    };
+    let recipe_previews = recipe_previews
+        .into_iter()
+        .map(|recipe_preview| {
+            RecipePreviewResult::Lean(RecipePreviewWithTags {
+                tags: tags_by_recipe.as_mut().map(|tags_by_recipe| {
+                    tags_by_recipe
+                        .remove(&recipe_preview.id)
+                        .unwrap_or_default()
+                }),
+                recipe_preview,
+            })
+        })
+        .collect();
+    Ok(Json(SearchResponse::Page(Page {
+        items: recipe_previews,
+        total,
+        offset,
+        limit,
+    })))
 }
 
 /// Get recipe tags.
@@ -635,10 +942,13 @@ async fn get_matching_recipe_previews(
 #[axum::debug_handler]
 async fn get_matching_recipe_tags(
     State(state): State<ToiState>,
+    user: CurrentUser,
     Json(params): Json<RecipeTagSearchParams>,
 ) -> Result<Json<RecipeTags>, (StatusCode, String)> {
-    let mut conn = state.pool.get().await.map_err(utils::internal_error)?;
-    let (recipe_preview, ids) = search_recipe_tags(&state, params, &mut conn).await?;
+    let mut conn = utils::get_conn(&state.pool).await?;
+    let mut embeddings = EmbeddingCache::default();
+    let (recipe_preview, ids) =
+        search_recipe_tags(&state, user.id, params, &mut embeddings, &mut conn).await?;
     let tags = schema::tags::table
         .select(Tag::as_select())
         .filter(schema::tags::id.eq_any(ids))
@@ -651,3 +961,334 @@ async fn get_matching_recipe_tags(
     };
     Ok(Json(recipe_tags))
 }
+
+/// Scale a recipe's ingredient quantities and return the original and
+/// scaled ingredients.
+///
+/// Example queries for scaling a recipe using this endpoint:
+/// - Double the recipe
+/// - Scale the recipe for 8 people
+/// - Halve the recipe for
+/// - Triple the ingredients for
+#[utoipa::path(
+    post,
+    path = "/scale",
+    extensions(
+        ("x-json-schema-body" = json!(schema_for!(RecipeScaleRequest)))
+    ),
+    request_body = RecipeScaleRequest,
+    responses(
+        (status = 200, description = "Successfully scaled a recipe", body = ScaledRecipe),
+        (status = 400, description = "Invalid scaling factor or default JSON elements configured by the user are invalid"),
+        (status = 404, description = "No recipe found"),
+        (status = 422, description = "Error when parsing a response from a model API"),
+        (status = 502, description = "Error when forwarding request to model APIs")
+    )
+)]
+#[axum::debug_handler]
+async fn scale_recipe(
+    State(state): State<ToiState>,
+    user: CurrentUser,
+    Json(params): Json<RecipeScaleRequest>,
+) -> Result<Json<ScaledRecipe>, (StatusCode, String)> {
+    let RecipeScaleRequest {
+        recipe_id,
+        recipe_query,
+        recipe_use_reranking_filter,
+        recipe_created_from,
+        recipe_created_to,
+        recipe_order_by,
+        factor,
+        save,
+    } = params;
+    if !factor.is_finite() || factor <= 0.0 {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            "scaling factor must be a positive number".to_string(),
+        ));
+    }
+    let mut conn = utils::get_conn(&state.pool).await?;
+    let mut embeddings = EmbeddingCache::default();
+    let params = RecipeSearchParams {
+        ids: recipe_id.map(|i| vec![i]),
+        query: recipe_query,
+        use_reranking_filter: recipe_use_reranking_filter,
+        ingredients_query: None,
+        created_from: recipe_created_from,
+        created_to: recipe_created_to,
+        order_by: recipe_order_by,
+        tags: None,
+        tags_match: None,
+        limit: Some(1),
+        offset: None,
+        count_only: None,
+        include_tags: None,
+        detailed: None,
+    };
+    let recipe_id = search_recipes(&state, user.id, params, &mut embeddings, &mut conn)
+        .await?
+        .items
+        .into_iter()
+        .next()
+        .ok_or((StatusCode::NOT_FOUND, "recipe not found".to_string()))?;
+    let recipe = schema::recipes::table
+        .select(Recipe::as_select())
+        .filter(schema::recipes::id.eq(recipe_id))
+        .first(&mut conn)
+        .await
+        .map_err(utils::diesel_error)?;
+
+    // Have the generation API rewrite the ingredients with scaled quantities.
+    let system_prompt = RecipeScalePrompt { factor };
+    let message = Message {
+        role: MessageRole::User,
+        content: recipe.ingredients.clone(),
+    };
+    let generation_request = state.server_config.structured_sampling.apply(
+        GenerationRequest::builder()
+            .messages(system_prompt.to_messages(&[message]))
+            .response_format(system_prompt.into_response_format())
+            .build(),
+    );
+    let mut usage = TokenUsage::default();
+    let generated_scaled_ingredients = state
+        .model_client
+        .generate(generation_request, &mut usage)
+        .await?;
+    let GeneratedScaledIngredients {
+        ingredients: scaled_ingredients,
+    } = parse_generated_response(&generated_scaled_ingredients)?;
+
+    // Recipe embeddings only come from their descriptions, so saving scaled
+    // ingredients doesn't change the embedding.
+    let original_ingredients = recipe.ingredients.clone();
+    let recipe = if save == Some(true) {
+        diesel::update(schema::recipes::table.filter(schema::recipes::id.eq(recipe.id)))
+            .set(schema::recipes::ingredients.eq(&scaled_ingredients))
+            .returning(Recipe::as_returning())
+            .get_result(&mut conn)
+            .await
+            .map_err(utils::diesel_error)?
+    } else {
+        recipe
+    };
+    Ok(Json(ScaledRecipe {
+        recipe,
+        factor,
+        original_ingredients,
+        scaled_ingredients,
+    }))
+}
+
+/// Make a shopping list from the ingredients of one or more recipes,
+/// combining shared ingredients and grouping items by store section.
+/// Optionally, add each item on the list as a todo.
+///
+/// Example queries for making a shopping list using this endpoint:
+/// - Make a shopping list for
+/// - What do I need to buy for these recipes
+/// - What groceries do I need to make
+/// - Add what I need to buy for the recipe to my todos
+#[utoipa::path(
+    post,
+    path = "/shopping-list",
+    extensions(
+        ("x-json-schema-body" = json!(schema_for!(ShoppingListRequest)))
+    ),
+    request_body = ShoppingListRequest,
+    responses(
+        (status = 200, description = "Successfully made a shopping list", body = ShoppingList),
+        (status = 400, description = "No recipe filters, invalid servings, or default JSON elements configured by the user are invalid"),
+        (status = 404, description = "No recipes found"),
+        (status = 413, description = "Too many items to add as todos at once"),
+        (status = 422, description = "Error when parsing a response from a model API"),
+        (status = 502, description = "Error when forwarding request to model APIs")
+    )
+)]
+#[axum::debug_handler]
+async fn make_shopping_list(
+    State(state): State<ToiState>,
+    user: CurrentUser,
+    Json(params): Json<ShoppingListRequest>,
+) -> Result<Json<ShoppingList>, (StatusCode, String)> {
+    let ShoppingListRequest {
+        mut params,
+        servings,
+        create_todos,
+        due_at,
+    } = params;
+    if !params.narrows() {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            "no IDs, query, date range, limit, or other filters were given to choose recipes"
+                .to_string(),
+        ));
+    }
+    let servings = servings.unwrap_or_default();
+    if servings
+        .iter()
+        .any(|servings| !servings.factor.is_finite() || servings.factor <= 0.0)
+    {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            "servings factors must be positive numbers".to_string(),
+        ));
+    }
+    params.count_only = None;
+    let mut conn = utils::get_conn(&state.pool).await?;
+    let mut embeddings = EmbeddingCache::default();
+    let ids = search_recipes(&state, user.id, params, &mut embeddings, &mut conn)
+        .await?
+        .items;
+    if ids.is_empty() {
+        return Err((StatusCode::NOT_FOUND, "no recipes found".to_string()));
+    }
+    let mut recipes: Vec<Recipe> = schema::recipes::table
+        .select(Recipe::as_select())
+        .filter(schema::recipes::id.eq_any(&ids))
+        .load(&mut conn)
+        .await
+        .map_err(utils::diesel_error)?;
+    recipes.sort_by_key(|recipe| ids.iter().position(|id| *id == recipe.id));
+
+    // Have the generation API combine every recipe's ingredients into one
+    // list.
+    let content = recipes
+        .iter()
+        .map(|recipe| {
+            let factor = servings
+                .iter()
+                .find(|servings| servings.recipe_id == recipe.id)
+                .map_or(1.0, |servings| servings.factor);
+            format!(
+                "Recipe: {}\nFactor: {factor}\nIngredients:\n{}",
+                recipe.description, recipe.ingredients
+            )
+        })
+        .collect::<Vec<String>>()
+        .join("\n\n");
+    let system_prompt = ShoppingListPrompt;
+    let message = Message {
+        role: MessageRole::User,
+        content,
+    };
+    let generation_request = state.server_config.structured_sampling.apply(
+        GenerationRequest::builder()
+            .messages(system_prompt.to_messages(&[message]))
+            .response_format(system_prompt.into_response_format())
+            .build(),
+    );
+    let mut usage = TokenUsage::default();
+    let generated_shopping_list = state
+        .model_client
+        .generate(generation_request, &mut usage)
+        .await?;
+    let GeneratedShoppingList { items } = parse_generated_response(&generated_shopping_list)?;
+    let mut items_by_category = BTreeMap::new();
+    for item in items {
+        let name = item.item.trim();
+        if !name.is_empty() {
+            items_by_category
+                .entry(item.category)
+                .or_insert_with(Vec::new)
+                .push(name.to_string());
+        }
+    }
+    let categories: Vec<ShoppingListCategory> = items_by_category
+        .into_iter()
+        .map(|(category, items)| ShoppingListCategory { category, items })
+        .collect();
+
+    // Every item is embedded with one request and added with one insert.
+    let mut todos: Vec<Todo> = vec![];
+    if create_todos == Some(true) {
+        let items: Vec<String> = categories
+            .iter()
+            .flat_map(|category| category.items.iter().map(|item| format!("Buy {item}")))
+            .collect();
+        let max_batch_size = state.server_config.max_batch_size;
+        if items.len() > max_batch_size {
+            return Err(ToiError::PayloadTooLarge(format!(
+                "can't add more than {max_batch_size} todos at once"
+            ))
+            .into());
+        }
+        if !items.is_empty() {
+            let embedding_request = BatchEmbeddingRequest {
+                input: items.clone(),
+            };
+            let embeddings = state.model_client.embed_batch(embedding_request).await?;
+            let new_todos: Vec<NewTodo> = items
+                .into_iter()
+                .zip(embeddings)
+                .map(|(item, embedding)| NewTodo {
+                    user_id: user.id,
+                    item,
+                    embedding,
+                    due_at,
+                    completed_at: None,
+                    priority: None,
+                    recurrence_days: None,
+                    event_id: None,
+                })
+                .collect();
+            todos = conn
+                .transaction(|mut conn| {
+                    async move {
+                        diesel::insert_into(schema::todos::table)
+                            .values(&new_todos)
+                            .returning(Todo::as_returning())
+                            .get_results(&mut conn)
+                            .await
+                    }
+                    .scope_boxed()
+                })
+                .await
+                .map_err(utils::diesel_error)?;
+            // IDs are generated in input order.
+            todos.sort_by_key(|todo| todo.id);
+        }
+    }
+    let recipes = recipes
+        .into_iter()
+        .map(|recipe| RecipePreview {
+            id: recipe.id,
+            description: recipe.description,
+            created_at: recipe.created_at,
+        })
+        .collect();
+    Ok(Json(ShoppingList {
+        recipes,
+        categories,
+        todos,
+    }))
+}
+
+/// Get a recipe using its database-generated ID.
+#[utoipa::path(
+    get,
+    path = "/{id}",
+    params(
+        ("id" = i32, Path, description = "Database-generated recipe ID")
+    ),
+    responses(
+        (status = 200, description = "Successfully got a recipe", body = Recipe),
+        (status = 404, description = "Recipe not found")
+    )
+)]
+#[axum::debug_handler]
+async fn get_recipe(
+    State(state): State<ToiState>,
+    user: CurrentUser,
+    Path(id): Path<i32>,
+) -> Result<Json<Recipe>, (StatusCode, String)> {
+    let mut conn = utils::get_conn(&state.pool).await?;
+    let recipe = schema::recipes::table
+        .select(Recipe::as_select())
+        .filter(schema::recipes::id.eq(id))
+        .filter(schema::recipes::user_id.eq(user.id))
+        .first(&mut conn)
+        .await
+        .map_err(utils::diesel_error)?;
+    Ok(Json(recipe))
+}
@@ -0,0 +1,180 @@
+use axum::{
+    extract::{Query, State},
+    response::Json,
+};
+use chrono::{DateTime, Duration, Utc};
+use diesel::{BoolExpressionMethods, ExpressionMethods, QueryDsl, SelectableHelper};
+use diesel_async::RunQueryDsl;
+use schemars::schema_for;
+use tracing::{info, warn};
+use utoipa_axum::{router::OpenApiRouter, routes};
+
+use crate::{
+    auth::CurrentUser,
+    models::{
+        error::ToiError,
+        events::Event,
+        reminders::{Reminder, ReminderKind, ReminderParams},
+        state::ToiState,
+    },
+    schema, utils,
+};
+
+const DEFAULT_WITHIN_HOURS: u32 = 24;
+
+pub fn reminders_router(state: ToiState) -> OpenApiRouter {
+    OpenApiRouter::new()
+        .routes(routes!(get_reminders))
+        .with_state(state)
+}
+
+/// Incomplete todos due and events starting between now and the given
+/// datetime, soonest first. Only a user's own items are included if a user
+/// is given.
+pub async fn upcoming_reminders(
+    user_id: Option<i32>,
+    now: DateTime<Utc>,
+    until: DateTime<Utc>,
+    conn: &mut utils::Conn<'_>,
+) -> Result<Vec<Reminder>, ToiError> {
+    let mut todos_query = schema::todos::table
+        .select((
+            schema::todos::id,
+            schema::todos::item,
+            schema::todos::due_at,
+        ))
+        .filter(schema::todos::completed_at.is_null())
+        .filter(schema::todos::deleted_at.is_null())
+        .filter(schema::todos::due_at.ge(now))
+        .filter(schema::todos::due_at.le(until))
+        .into_boxed();
+    if let Some(user_id) = user_id {
+        todos_query = todos_query.filter(schema::todos::user_id.eq(user_id));
+    }
+    let todos: Vec<(i32, String, Option<DateTime<Utc>>)> =
+        todos_query.load(conn).await.map_err(utils::diesel_error)?;
+    let mut reminders: Vec<Reminder> = todos
+        .into_iter()
+        .filter_map(|(id, item, due_at)| {
+            Some(Reminder {
+                kind: ReminderKind::Todo,
+                id,
+                description: item,
+                occurs_at: due_at?,
+            })
+        })
+        .collect();
+
+    // Recurring events are narrowed down in SQL, and then their next
+    // occurrence is found in Rust.
+    let mut events_query = schema::events::table
+        .select(Event::as_select())
+        .filter(schema::events::starts_at.le(until))
+        .filter(
+            schema::events::starts_at
+                .ge(now)
+                .or(schema::events::recurrence_frequency.is_not_null().and(
+                    schema::events::recurrence_until
+                        .is_null()
+                        .or(schema::events::recurrence_until.ge(now)),
+                )),
+        )
+        .into_boxed();
+    if let Some(user_id) = user_id {
+        events_query = events_query.filter(schema::events::user_id.eq(user_id));
+    }
+    let events: Vec<Event> = events_query.load(conn).await.map_err(utils::diesel_error)?;
+    reminders.extend(events.into_iter().filter_map(|event| {
+        let occurs_at = event
+            .first_occurrence_within(now, until)
+            .filter(|occurs_at| *occurs_at >= now)?;
+        Some(Reminder {
+            kind: ReminderKind::Event,
+            id: event.id,
+            description: event.description,
+            occurs_at,
+        })
+    }));
+    reminders.sort_by_key(|reminder| reminder.occurs_at);
+    Ok(reminders)
+}
+
+/// Periodically log every user's upcoming todos and events so operators can
+/// hook notifications up to the logs. Each scan covers the time until the next
+/// one.
+pub async fn log_upcoming_reminders(state: ToiState) {
+    let interval_hours = state.server_config.reminder_interval_hours;
+    if interval_hours == 0 {
+        return;
+    }
+    let Some(window) = i64::try_from(interval_hours)
+        .ok()
+        .and_then(Duration::try_hours)
+    else {
+        warn!("reminder interval of {interval_hours} hours is too long");
+        return;
+    };
+    let period = std::time::Duration::from_secs(interval_hours.saturating_mul(3600));
+    let mut interval = tokio::time::interval(period);
+    loop {
+        interval.tick().await;
+        let now = Utc::now();
+        let until = now
+            .checked_add_signed(window)
+            .unwrap_or(DateTime::<Utc>::MAX_UTC);
+        let result = match state.pool.get().await {
+            Ok(mut conn) => upcoming_reminders(None, now, until, &mut conn).await,
+            Err(err) => Err(utils::internal_error(err)),
+        };
+        match result {
+            Ok(reminders) => {
+                for reminder in reminders {
+                    info!(
+                        kind = ?reminder.kind,
+                        id = reminder.id,
+                        occurs_at = %reminder.occurs_at,
+                        "upcoming reminder: {}",
+                        reminder.description
+                    );
+                }
+            }
+            Err(err) => warn!("couldn't scan for upcoming reminders: {err}"),
+        }
+    }
+}
+
+/// Get todos that are due soon and events that are starting soon.
+///
+/// Example queries for getting reminders using this endpoint:
+/// - What do I need to do soon?
+/// - What's coming up today?
+/// - Do I have anything due in the next few hours?
+/// - Remind me of what's coming up this week
+#[utoipa::path(
+    get,
+    path = "",
+    extensions(
+        ("x-json-schema-params" = json!(schema_for!(ReminderParams)))
+    ),
+    params(ReminderParams),
+    responses(
+        (status = 200, description = "Successfully got reminders", body = [Reminder]),
+        (status = 400, description = "Reminder window is out of range")
+    )
+)]
+#[axum::debug_handler]
+async fn get_reminders(
+    State(state): State<ToiState>,
+    user: CurrentUser,
+    Query(params): Query<ReminderParams>,
+) -> Result<Json<Vec<Reminder>>, ToiError> {
+    let mut conn = utils::get_conn(&state.pool).await?;
+    let ReminderParams { within_hours } = params;
+    let within_hours = within_hours.unwrap_or(DEFAULT_WITHIN_HOURS);
+    let now = Utc::now();
+    let until = now
+        .checked_add_signed(Duration::hours(within_hours.into()))
+        .ok_or_else(|| ToiError::Validation("reminder window is out of range".to_string()))?;
+    let reminders = upcoming_reminders(Some(user.id), now, until, &mut conn).await?;
+    Ok(Json(reminders))
+}
@@ -0,0 +1,265 @@
+use axum::{extract::State, response::Json};
+use chrono::Utc;
+use diesel::{BoolExpressionMethods, ExpressionMethods, QueryDsl};
+use diesel_async::RunQueryDsl;
+use pgvector::{Vector, VectorExpressionMethods};
+use schemars::schema_for;
+use utoipa_axum::{router::OpenApiRouter, routes};
+
+use crate::{
+    auth::CurrentUser,
+    models::{
+        client::{ApiClientError, EmbeddingRequest, RerankRequest},
+        error::ToiError,
+        search::{SearchAllRequest, SearchAllResult, SearchDomain},
+        state::ToiState,
+    },
+    schema, utils,
+};
+
+// Prefixes are used for embedding instructions.
+const INSTRUCTION_PREFIX: &str = "Instruction: Given a user query, find notes, todos, events, contacts, recipes, or places similar to the one the user mentions";
+const QUERY_PREFIX: &str = "Query: ";
+
+// Max number of items considered from each domain when no limit is given.
+const DEFAULT_LIMIT_PER_DOMAIN: i64 = 5;
+
+// Max number of characters of an item's text returned as its snippet.
+const SNIPPET_CHARS: usize = 200;
+
+pub fn search_router(state: ToiState) -> OpenApiRouter {
+    OpenApiRouter::new()
+        .routes(routes!(search_all))
+        .with_state(state)
+}
+
+/// Get the IDs and text of a domain's items that are closest to an
+/// embedding, closest first. Only the user's own items are searched, and
+/// items in the trash or archive are left out.
+async fn search_domain(
+    state: &ToiState,
+    user_id: i32,
+    domain: SearchDomain,
+    embedding: Vector,
+    limit: i64,
+) -> Result<Vec<(SearchDomain, i32, String)>, ToiError> {
+    let mut conn = utils::get_conn(&state.pool).await?;
+    let distance_threshold = state.server_config.distance_threshold;
+    let ids_docs: Vec<(i32, String)> = match domain {
+        SearchDomain::Contacts => {
+            let contacts: Vec<(i32, String, Option<String>)> = schema::contacts::table
+                .select((
+                    schema::contacts::id,
+                    schema::contacts::first_name,
+                    schema::contacts::last_name,
+                ))
+                .filter(
+                    schema::contacts::embedding
+                        .cosine_distance(embedding.clone())
+                        .le(distance_threshold),
+                )
+                .filter(schema::contacts::user_id.eq(user_id))
+                .order(schema::contacts::embedding.cosine_distance(embedding))
+                .limit(limit)
+                .load(&mut conn)
+                .await
+                .map_err(utils::diesel_error)?;
+            contacts
+                .into_iter()
+                .map(|(id, first_name, last_name)| match last_name {
+                    Some(last_name) => (id, format!("{first_name} {last_name}")),
+                    None => (id, first_name),
+                })
+                .collect()
+        }
+        SearchDomain::Events => schema::events::table
+            .select((schema::events::id, schema::events::description))
+            .filter(
+                schema::events::embedding
+                    .cosine_distance(embedding.clone())
+                    .le(distance_threshold),
+            )
+            .filter(schema::events::user_id.eq(user_id))
+            .order(schema::events::embedding.cosine_distance(embedding))
+            .limit(limit)
+            .load(&mut conn)
+            .await
+            .map_err(utils::diesel_error)?,
+        SearchDomain::Notes => schema::notes::table
+            .select((schema::notes::id, schema::notes::content))
+            .filter(
+                schema::notes::embedding
+                    .cosine_distance(embedding.clone())
+                    .le(distance_threshold),
+            )
+            .filter(schema::notes::user_id.eq(user_id))
+            .filter(schema::notes::deleted_at.is_null())
+            .filter(schema::notes::archived_at.is_null())
+            .filter(
+                schema::notes::expires_at
+                    .is_null()
+                    .or(schema::notes::expires_at.gt(Utc::now())),
+            )
+            .order(schema::notes::embedding.cosine_distance(embedding))
+            .limit(limit)
+            .load(&mut conn)
+            .await
+            .map_err(utils::diesel_error)?,
+        SearchDomain::Places => {
+            let places: Vec<(i32, String, String)> = schema::places::table
+                .select((
+                    schema::places::id,
+                    schema::places::name,
+                    schema::places::description,
+                ))
+                .filter(
+                    schema::places::embedding
+                        .cosine_distance(embedding.clone())
+                        .le(distance_threshold),
+                )
+                .filter(schema::places::user_id.eq(user_id))
+                .order(schema::places::embedding.cosine_distance(embedding))
+                .limit(limit)
+                .load(&mut conn)
+                .await
+                .map_err(utils::diesel_error)?;
+            places
+                .into_iter()
+                .map(|(id, name, description)| (id, format!("{name}: {description}")))
+                .collect()
+        }
+        SearchDomain::Recipes => schema::recipes::table
+            .select((schema::recipes::id, schema::recipes::description))
+            .filter(
+                schema::recipes::embedding
+                    .cosine_distance(embedding.clone())
+                    .le(distance_threshold),
+            )
+            .filter(schema::recipes::user_id.eq(user_id))
+            .order(schema::recipes::embedding.cosine_distance(embedding))
+            .limit(limit)
+            .load(&mut conn)
+            .await
+            .map_err(utils::diesel_error)?,
+        SearchDomain::Todos => schema::todos::table
+            .select((schema::todos::id, schema::todos::item))
+            .filter(
+                schema::todos::embedding
+                    .cosine_distance(embedding.clone())
+                    .le(distance_threshold),
+            )
+            .filter(schema::todos::user_id.eq(user_id))
+            .filter(schema::todos::deleted_at.is_null())
+            .order(schema::todos::embedding.cosine_distance(embedding))
+            .limit(limit)
+            .load(&mut conn)
+            .await
+            .map_err(utils::diesel_error)?,
+    };
+    Ok(ids_docs
+        .into_iter()
+        .map(|(id, document)| (domain, id, document))
+        .collect())
+}
+
+/// Search notes, todos, events, contacts, recipes, and places all at once,
+/// and return the most relevant items first.
+///
+/// Only use this endpoint when the user doesn't say what kind of item
+/// they're looking for.
+///
+/// Example queries for searching everything using this endpoint:
+/// - Find anything about
+/// - Search everything for
+/// - Did I save anything about
+/// - Where did I put the info about
+#[utoipa::path(
+    post,
+    path = "/all",
+    extensions(
+        ("x-json-schema-body" = json!(schema_for!(SearchAllRequest)))
+    ),
+    request_body = SearchAllRequest,
+    responses(
+        (status = 200, description = "Successfully searched everything", body = [SearchAllResult]),
+        (status = 400, description = "Default JSON elements configured by the user are invalid"),
+        (status = 422, description = "Error when parsing a response from a model API"),
+        (status = 502, description = "Error when forwarding request to model APIs")
+    )
+)]
+#[axum::debug_handler]
+async fn search_all(
+    State(state): State<ToiState>,
+    user: CurrentUser,
+    Json(params): Json<SearchAllRequest>,
+) -> Result<Json<Vec<SearchAllResult>>, ToiError> {
+    let SearchAllRequest {
+        query,
+        limit_per_domain,
+        domains,
+    } = params;
+    let limit_per_domain = limit_per_domain.unwrap_or(DEFAULT_LIMIT_PER_DOMAIN);
+    if limit_per_domain < 1 {
+        return Err(ToiError::Validation(
+            "limit_per_domain must be at least 1".to_string(),
+        ));
+    }
+    let mut domains = domains.unwrap_or_else(|| SearchDomain::ALL.to_vec());
+    domains.sort_unstable();
+    domains.dedup();
+
+    // The query is embedded once and compared against every domain, so it
+    // uses its own instruction rather than each domain's.
+    let input = state
+        .embedding_instructions
+        .template("search", INSTRUCTION_PREFIX, QUERY_PREFIX)
+        .apply(&query);
+    let embedding = state.model_client.embed(EmbeddingRequest { input }).await?;
+
+    // Each domain is searched with its own connection so they're searched
+    // concurrently.
+    let candidates: Vec<(SearchDomain, i32, String)> =
+        futures::future::try_join_all(domains.into_iter().map(|domain| {
+            search_domain(&state, user.id, domain, embedding.clone(), limit_per_domain)
+        }))
+        .await?
+        .into_iter()
+        .flatten()
+        .collect();
+    if candidates.is_empty() {
+        return Ok(Json(vec![]));
+    }
+
+    // Items from different domains are only comparable once they're
+    // reranked together.
+    let rerank_request = RerankRequest {
+        query,
+        documents: candidates
+            .iter()
+            .map(|(_, _, document)| document.clone())
+            .collect(),
+    };
+    let rerank_response = state.model_client.rerank(rerank_request).await?;
+    let mut results = vec![];
+    for result in rerank_response.results {
+        let Some((domain, id, document)) = candidates.get(result.index) else {
+            let err = format!(
+                "rerank result index {} is out of range for {} documents",
+                result.index,
+                candidates.len()
+            );
+            return Err(ApiClientError::ResponseJson.into_response(&err));
+        };
+        if result.relevance_score < state.server_config.similarity_threshold {
+            continue;
+        }
+        results.push(SearchAllResult {
+            domain: *domain,
+            id: *id,
+            snippet: document.chars().take(SNIPPET_CHARS).collect(),
+            score: result.relevance_score,
+        });
+    }
+    results.sort_by(|a, b| b.score.total_cmp(&a.score));
+    Ok(Json(results))
+}
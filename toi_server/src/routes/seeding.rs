@@ -0,0 +1,36 @@
+use axum::{extract::State, response::Json};
+use utoipa_axum::{router::OpenApiRouter, routes};
+
+use crate::{
+    auth::CurrentUser,
+    models::{error::ToiError, seeding::SeedReport, state::ToiState},
+    seeding,
+};
+
+pub fn seeding_router(state: ToiState) -> OpenApiRouter {
+    OpenApiRouter::new()
+        .routes(routes!(seed_data))
+        .with_state(state)
+}
+
+/// Add items from the configured seed data directory again, skipping items
+/// that already exist, and return how many were added for each domain.
+#[utoipa::path(
+    post,
+    path = "",
+    responses(
+        (status = 200, description = "Successfully seeded data", body = SeedReport),
+        (status = 400, description = "Seed data isn't configured, or a seed file is malformed"),
+        (status = 404, description = "No matching tags, events, or places for a seeded item"),
+        (status = 422, description = "Error when parsing a response from a model API"),
+        (status = 502, description = "Error when forwarding request to model APIs")
+    )
+)]
+#[axum::debug_handler]
+async fn seed_data(
+    State(state): State<ToiState>,
+    user: CurrentUser,
+) -> Result<Json<SeedReport>, ToiError> {
+    let report = seeding::seed(&state, user.id).await?;
+    Ok(Json(report))
+}
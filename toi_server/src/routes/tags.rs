@@ -1,17 +1,24 @@
 use axum::{extract::State, http::StatusCode, response::Json};
-use diesel::{ExpressionMethods, QueryDsl, SelectableHelper};
-use diesel_async::RunQueryDsl;
+use diesel::{ExpressionMethods, NullableExpressionMethods, QueryDsl, SelectableHelper};
+use diesel_async::{AsyncConnection, RunQueryDsl, scoped_futures::ScopedFutureExt};
 use pgvector::VectorExpressionMethods;
 use schemars::schema_for;
+use std::collections::HashMap;
 use utoipa_axum::{router::OpenApiRouter, routes};
 
 use crate::{
+    auth::CurrentUser,
+    embeddings::EmbeddedTable,
     models::{
-        client::{EmbeddingPromptTemplate, EmbeddingRequest, RerankRequest},
+        client::{EmbeddingCache, EmbeddingRequest},
         state::ToiState,
-        tags::{NewTag, NewTagRequest, Tag, TagSearchParams},
+        tags::{
+            NewTag, NewTagRequest, Tag, TagOrderBy, TagSearchParams, TagWithUsage, UpdateTagRequest,
+        },
     },
-    schema, utils,
+    schema,
+    search::{self, RerankOptions},
+    utils,
 };
 
 const EDIT_SIMILARITY_THRESHOLD: f64 = 0.80;
@@ -26,12 +33,59 @@ pub fn tags_router(state: ToiState) -> OpenApiRouter {
         .routes(routes!(add_tag))
         .routes(routes!(delete_matching_tags))
         .routes(routes!(get_matching_tags))
+        .routes(routes!(update_matching_tag))
         .with_state(state)
 }
 
+/// Resolve each tag name to the ID of its closest matching tag.
+///
+/// Tags are resolved concurrently, each with its own pooled connection, and
+/// repeated names or tags are only resolved and returned once. Returns a 404
+/// naming a tag that has no match. Only the user's own tags are matched.
+pub async fn resolve_tags(
+    state: &ToiState,
+    user_id: i32,
+    tags: Vec<String>,
+) -> Result<Vec<i32>, (StatusCode, String)> {
+    let mut names: Vec<String> = vec![];
+    for tag in tags {
+        if !names.contains(&tag) {
+            names.push(tag);
+        }
+    }
+    let tag_ids = futures::future::try_join_all(names.into_iter().map(|tag| async move {
+        let mut conn = utils::get_conn(&state.pool).await?;
+        let mut embeddings = EmbeddingCache::default();
+        let params = TagSearchParams {
+            ids: None,
+            query: Some(tag.clone()),
+            use_reranking_filter: Some(true),
+            use_edit_distance_filter: Some(true),
+            order_by: None,
+            limit: Some(1),
+        };
+        let matching_tag_ids =
+            search_tags(state, user_id, params, &mut embeddings, &mut conn).await?;
+        matching_tag_ids.into_iter().next().ok_or((
+            StatusCode::NOT_FOUND,
+            format!("no matching tags for '{tag}'"),
+        ))
+    }))
+    .await?;
+    let mut unique_tag_ids = vec![];
+    for tag_id in tag_ids {
+        if !unique_tag_ids.contains(&tag_id) {
+            unique_tag_ids.push(tag_id);
+        }
+    }
+    Ok(unique_tag_ids)
+}
+
 pub async fn search_tags(
     state: &ToiState,
+    user_id: i32,
     params: TagSearchParams,
+    embeddings: &mut EmbeddingCache,
     conn: &mut utils::Conn<'_>,
 ) -> Result<Vec<i32>, (StatusCode, String)> {
     let TagSearchParams {
@@ -39,26 +93,43 @@ pub async fn search_tags(
         query,
         use_reranking_filter,
         use_edit_distance_filter,
+        order_by,
         limit,
     } = params;
 
     let mut sql_query = schema::tags::table.select(Tag::as_select()).into_boxed();
 
     if let Some(ref query) = query {
-        let input = EmbeddingPromptTemplate::builder()
-            .instruction_prefix(INSTRUCTION_PREFIX.to_string())
-            .query_prefix(QUERY_PREFIX.to_string())
-            .build()
+        let input = state
+            .embedding_instructions
+            .template("tags", INSTRUCTION_PREFIX, QUERY_PREFIX)
             .apply(query);
         let embedding_request = EmbeddingRequest { input };
-        let embedding = state.model_client.embed(embedding_request).await?;
-        sql_query = sql_query
-            .filter(
-                schema::tags::embedding
-                    .cosine_distance(embedding.clone())
-                    .le(state.server_config.distance_threshold),
-            )
-            .order(schema::tags::embedding.cosine_distance(embedding));
+        let embedding = state
+            .model_client
+            .embed_cached(embedding_request, embeddings)
+            .await?;
+        sql_query = sql_query.filter(
+            schema::tags::embedding
+                .cosine_distance(embedding.clone())
+                .le(state.server_config.distance_threshold),
+        );
+        if order_by.is_none() {
+            sql_query = sql_query.order(schema::tags::embedding.cosine_distance(embedding));
+        }
+    }
+
+    // Items are only ordered by their distance to the query if they aren't
+    // ordered some other way.
+    let ordered_by_distance = order_by.is_none();
+
+    // Order items. Tags that aren't ordered by similarity to a query are
+    // ordered alphabetically by default so listing them is consistent.
+    match order_by {
+        Some(TagOrderBy::Alphabetical) => sql_query = sql_query.order(schema::tags::name),
+        Some(TagOrderBy::Newest) => sql_query = sql_query.order(schema::tags::created_at.desc()),
+        None if query.is_none() => sql_query = sql_query.order(schema::tags::name),
+        None => {}
     }
 
     // Filter items according to their ids.
@@ -66,6 +137,10 @@ pub async fn search_tags(
         sql_query = sql_query.or_filter(schema::tags::id.eq_any(ids));
     }
 
+    // Only the user's own items are searched. This comes after the other
+    // filters so items selected by their ids are still only the user's.
+    sql_query = sql_query.filter(schema::tags::user_id.eq(user_id));
+
     // Limit number of items.
     if let Some(limit) = limit {
         sql_query = sql_query.limit(limit);
@@ -73,37 +148,28 @@ pub async fn search_tags(
 
     // Get all the items that match the query.
     let tags: Vec<Tag> = sql_query.load(conn).await.map_err(utils::diesel_error)?;
-    let (ids, documents): (Vec<i32>, Vec<String>) =
-        tags.into_iter().map(|tag| (tag.id, tag.name)).unzip();
-    if ids.is_empty() {
-        return Ok(ids);
+    let mut ids_docs: Vec<(i32, String)> = tags.into_iter().map(|tag| (tag.id, tag.name)).collect();
+
+    // Items that aren't ordered by their distance to the query are put in
+    // that order so only the furthest ones are left out of reranking.
+    if !ordered_by_distance
+        && search::needs_reranking(query.as_deref(), use_reranking_filter)
+        && let Some(ref query) = query
+    {
+        let input = state
+            .embedding_instructions
+            .template("tags", INSTRUCTION_PREFIX, QUERY_PREFIX)
+            .apply(query);
+        ids_docs = search::closest_first(state, EmbeddedTable::Tags, input, ids_docs, conn).await?;
     }
 
     // Rerank and filter items once more.
-    let ids = match (query, use_reranking_filter) {
-        (Some(query), Some(true)) => {
-            let rerank_request = RerankRequest {
-                query: query.clone(),
-                documents,
-            };
-            let rerank_response = state.model_client.rerank(rerank_request).await?;
-            rerank_response
-                .results
-                .into_iter()
-                .filter(|item| {
-                    let score = strsim::normalized_damerau_levenshtein(&query, &item.document.text);
-                    let mut result =
-                        item.relevance_score >= state.server_config.similarity_threshold;
-                    if let Some(true) = use_edit_distance_filter {
-                        result &= score >= EDIT_SIMILARITY_THRESHOLD;
-                    }
-                    result
-                })
-                .map(|item| ids[item.index])
-                .collect()
-        }
-        _ => ids,
+    let options = RerankOptions {
+        edit_similarity_threshold: use_edit_distance_filter
+            .unwrap_or_default()
+            .then_some(EDIT_SIMILARITY_THRESHOLD),
     };
+    let ids = search::rerank_filter(state, query, use_reranking_filter, ids_docs, &options).await?;
 
     Ok(ids)
 }
@@ -134,9 +200,11 @@ pub async fn search_tags(
 #[axum::debug_handler]
 async fn add_tag(
     State(state): State<ToiState>,
+    user: CurrentUser,
     Json(params): Json<NewTagRequest>,
 ) -> Result<Json<Tag>, (StatusCode, String)> {
-    let mut conn = state.pool.get().await.map_err(utils::internal_error)?;
+    let mut conn = utils::get_conn(&state.pool).await?;
+    let mut embeddings = EmbeddingCache::default();
     let NewTagRequest { name } = params;
 
     // Make sure a similar tag doesn't already exist.
@@ -145,9 +213,10 @@ async fn add_tag(
         query: Some(name.clone()),
         use_reranking_filter: Some(true),
         use_edit_distance_filter: Some(true),
+        order_by: None,
         limit: Some(1),
     };
-    let ids = search_tags(&state, params, &mut conn).await;
+    let ids = search_tags(&state, user.id, params, &mut embeddings, &mut conn).await;
     match ids {
         Ok(ids) if !ids.is_empty() => {
             return Err((StatusCode::CONFLICT, "tag already exists".to_string()));
@@ -160,7 +229,11 @@ async fn add_tag(
         input: name.clone(),
     };
     let embedding = state.model_client.embed(embedding_request).await?;
-    let new_tag = NewTag { name, embedding };
+    let new_tag = NewTag {
+        user_id: user.id,
+        name,
+        embedding,
+    };
     let result = diesel::insert_into(schema::tags::table)
         .values(new_tag)
         .returning(Tag::as_returning())
@@ -187,7 +260,7 @@ async fn add_tag(
     responses(
         (status = 200, description = "Successfully deleted tags", body = [Tag]),
         (status = 400, description = "Default JSON elements configured by the user are invalid"),
-        (status = 404, description = "No tags found"),
+        (status = 404, description = "No tags found with the given IDs"),
         (status = 422, description = "Error when parsing a response from a model API"),
         (status = 502, description = "Error when forwarding request to model APIs")
     )
@@ -195,19 +268,23 @@ async fn add_tag(
 #[axum::debug_handler]
 async fn delete_matching_tags(
     State(state): State<ToiState>,
+    user: CurrentUser,
     Json(params): Json<TagSearchParams>,
 ) -> Result<Json<Vec<Tag>>, (StatusCode, String)> {
-    let mut conn = state.pool.get().await.map_err(utils::internal_error)?;
-    let ids = search_tags(&state, params, &mut conn).await?;
+    let mut conn = utils::get_conn(&state.pool).await?;
+    let mut embeddings = EmbeddingCache::default();
+    let requested_ids = params.ids.clone();
+    let ids = search_tags(&state, user.id, params, &mut embeddings, &mut conn).await?;
     let tags = diesel::delete(schema::tags::table.filter(schema::tags::id.eq_any(ids)))
         .returning(Tag::as_returning())
         .load(&mut conn)
         .await
         .map_err(utils::diesel_error)?;
+    utils::check_ids_found(requested_ids.as_deref(), &tags, "tags")?;
     Ok(Json(tags))
 }
 
-/// Get tags.
+/// Get tags along with how many recipes, notes, and todos have each tag.
 ///
 /// Example queries for getting tags using this endpoint:
 /// - Get all tags
@@ -222,9 +299,8 @@ async fn delete_matching_tags(
     ),
     request_body = TagSearchParams,
     responses(
-        (status = 200, description = "Successfully got tags", body = [Tag]),
+        (status = 200, description = "Successfully got tags", body = [TagWithUsage]),
         (status = 400, description = "Default JSON elements configured by the user are invalid"),
-        (status = 404, description = "No tags found"),
         (status = 422, description = "Error when parsing a response from a model API"),
         (status = 502, description = "Error when forwarding request to model APIs")
     )
@@ -232,15 +308,216 @@ async fn delete_matching_tags(
 #[axum::debug_handler]
 async fn get_matching_tags(
     State(state): State<ToiState>,
+    user: CurrentUser,
     Json(params): Json<TagSearchParams>,
-) -> Result<Json<Vec<Tag>>, (StatusCode, String)> {
-    let mut conn = state.pool.get().await.map_err(utils::internal_error)?;
-    let ids = search_tags(&state, params, &mut conn).await?;
-    let tags = schema::tags::table
-        .select(Tag::as_select())
-        .filter(schema::tags::id.eq_any(ids))
+) -> Result<Json<Vec<TagWithUsage>>, (StatusCode, String)> {
+    let mut conn = utils::get_conn(&state.pool).await?;
+    let mut embeddings = EmbeddingCache::default();
+    let ids = search_tags(&state, user.id, params, &mut embeddings, &mut conn).await?;
+
+    // Count how many recipes have each tag, including tags that aren't on
+    // any recipes, and then add how many notes and todos have each tag.
+    let tags: Vec<(Tag, i64)> = schema::tags::table
+        .left_join(schema::recipe_tags::table)
+        .filter(schema::tags::id.eq_any(&ids))
+        .group_by(schema::tags::id)
+        .select((
+            Tag::as_select(),
+            diesel::dsl::count(schema::recipe_tags::recipe_id.nullable()),
+        ))
         .load(&mut conn)
         .await
         .map_err(utils::diesel_error)?;
+
+    let note_tag_counts: Vec<(i32, i64)> = schema::note_tags::table
+        .filter(schema::note_tags::tag_id.eq_any(&ids))
+        .group_by(schema::note_tags::tag_id)
+        .select((schema::note_tags::tag_id, diesel::dsl::count_star()))
+        .load(&mut conn)
+        .await
+        .map_err(utils::diesel_error)?;
+    let todo_tag_counts: Vec<(i32, i64)> = schema::todo_tags::table
+        .filter(schema::todo_tags::tag_id.eq_any(&ids))
+        .group_by(schema::todo_tags::tag_id)
+        .select((schema::todo_tags::tag_id, diesel::dsl::count_star()))
+        .load(&mut conn)
+        .await
+        .map_err(utils::diesel_error)?;
+
+    // Keep the order tags were found in.
+    let mut tags_by_id: HashMap<i32, TagWithUsage> = tags
+        .into_iter()
+        .map(|(tag, usage_count)| (tag.id, TagWithUsage { tag, usage_count }))
+        .collect();
+    for (tag_id, usage_count) in note_tag_counts.into_iter().chain(todo_tag_counts) {
+        if let Some(tag) = tags_by_id.get_mut(&tag_id) {
+            tag.usage_count += usage_count;
+        }
+    }
+    let tags = ids.iter().filter_map(|id| tags_by_id.remove(id)).collect();
     Ok(Json(tags))
 }
+
+/// Rename and return a tag, keeping the tag on the recipes, notes, and todos
+/// it's already on.
+///
+/// Example queries for renaming tags using this endpoint:
+/// - Rename the tag
+/// - Change the tag name to
+/// - Merge the tag into
+/// - Fix the spelling of the tag
+#[utoipa::path(
+    put,
+    path = "",
+    extensions(
+        ("x-json-schema-body" = json!(schema_for!(UpdateTagRequest)))
+    ),
+    request_body = UpdateTagRequest,
+    responses(
+        (status = 200, description = "Successfully renamed or merged tag", body = Tag),
+        (status = 400, description = "Default JSON elements configured by the user are invalid"),
+        (status = 404, description = "Tag not found"),
+        (status = 409, description = "A tag with the new name already exists"),
+        (status = 422, description = "Error when parsing a response from a model API"),
+        (status = 502, description = "Error when forwarding request to model APIs")
+    )
+)]
+#[axum::debug_handler]
+async fn update_matching_tag(
+    State(state): State<ToiState>,
+    user: CurrentUser,
+    Json(params): Json<UpdateTagRequest>,
+) -> Result<Json<Tag>, (StatusCode, String)> {
+    let mut conn = utils::get_conn(&state.pool).await?;
+    let mut embeddings = EmbeddingCache::default();
+    let UpdateTagRequest {
+        id,
+        query,
+        use_reranking_filter,
+        new_name,
+        merge,
+    } = params;
+    let params = TagSearchParams {
+        ids: id.map(|i| vec![i]),
+        query,
+        use_reranking_filter,
+        use_edit_distance_filter: None,
+        order_by: None,
+        limit: Some(1),
+    };
+    let id = search_tags(&state, user.id, params, &mut embeddings, &mut conn)
+        .await?
+        .into_iter()
+        .next()
+        .ok_or((StatusCode::NOT_FOUND, "tag not found".to_string()))?;
+
+    // Look for a tag other than the one being renamed that already has the
+    // new name, using the same duplicate check as when adding tags.
+    let params = TagSearchParams {
+        ids: None,
+        query: Some(new_name.clone()),
+        use_reranking_filter: Some(true),
+        use_edit_distance_filter: Some(true),
+        order_by: None,
+        limit: Some(2),
+    };
+    let existing_id = search_tags(&state, user.id, params, &mut embeddings, &mut conn)
+        .await?
+        .into_iter()
+        .find(|existing_id| *existing_id != id);
+
+    let tag = match (existing_id, merge) {
+        (Some(existing_id), Some(true)) => conn
+            .transaction(|mut conn| {
+                async move {
+                    // Recipes that already have the existing tag only need
+                    // the old tag removed.
+                    let tagged_recipe_ids: Vec<i32> = schema::recipe_tags::table
+                        .select(schema::recipe_tags::recipe_id)
+                        .filter(schema::recipe_tags::tag_id.eq(existing_id))
+                        .load(&mut conn)
+                        .await?;
+                    diesel::delete(
+                        schema::recipe_tags::table
+                            .filter(schema::recipe_tags::tag_id.eq(id))
+                            .filter(schema::recipe_tags::recipe_id.eq_any(tagged_recipe_ids)),
+                    )
+                    .execute(&mut conn)
+                    .await?;
+                    diesel::update(schema::recipe_tags::table)
+                        .filter(schema::recipe_tags::tag_id.eq(id))
+                        .set(schema::recipe_tags::tag_id.eq(existing_id))
+                        .execute(&mut conn)
+                        .await?;
+
+                    // Same for notes and todos.
+                    let tagged_note_ids: Vec<i32> = schema::note_tags::table
+                        .select(schema::note_tags::note_id)
+                        .filter(schema::note_tags::tag_id.eq(existing_id))
+                        .load(&mut conn)
+                        .await?;
+                    diesel::delete(
+                        schema::note_tags::table
+                            .filter(schema::note_tags::tag_id.eq(id))
+                            .filter(schema::note_tags::note_id.eq_any(tagged_note_ids)),
+                    )
+                    .execute(&mut conn)
+                    .await?;
+                    diesel::update(schema::note_tags::table)
+                        .filter(schema::note_tags::tag_id.eq(id))
+                        .set(schema::note_tags::tag_id.eq(existing_id))
+                        .execute(&mut conn)
+                        .await?;
+                    let tagged_todo_ids: Vec<i32> = schema::todo_tags::table
+                        .select(schema::todo_tags::todo_id)
+                        .filter(schema::todo_tags::tag_id.eq(existing_id))
+                        .load(&mut conn)
+                        .await?;
+                    diesel::delete(
+                        schema::todo_tags::table
+                            .filter(schema::todo_tags::tag_id.eq(id))
+                            .filter(schema::todo_tags::todo_id.eq_any(tagged_todo_ids)),
+                    )
+                    .execute(&mut conn)
+                    .await?;
+                    diesel::update(schema::todo_tags::table)
+                        .filter(schema::todo_tags::tag_id.eq(id))
+                        .set(schema::todo_tags::tag_id.eq(existing_id))
+                        .execute(&mut conn)
+                        .await?;
+
+                    diesel::delete(schema::tags::table.filter(schema::tags::id.eq(id)))
+                        .execute(&mut conn)
+                        .await?;
+                    schema::tags::table
+                        .select(Tag::as_select())
+                        .filter(schema::tags::id.eq(existing_id))
+                        .first(&mut conn)
+                        .await
+                }
+                .scope_boxed()
+            })
+            .await
+            .map_err(utils::diesel_error)?,
+        (Some(_), _) => {
+            return Err((StatusCode::CONFLICT, "tag already exists".to_string()));
+        }
+        (None, _) => {
+            let embedding_request = EmbeddingRequest {
+                input: new_name.clone(),
+            };
+            let embedding = state.model_client.embed(embedding_request).await?;
+            diesel::update(schema::tags::table)
+                .filter(schema::tags::id.eq(id))
+                .set((
+                    schema::tags::name.eq(new_name),
+                    schema::tags::embedding.eq(embedding),
+                ))
+                .returning(Tag::as_returning())
+                .get_result(&mut conn)
+                .await
+                .map_err(utils::diesel_error)?
+        }
+    };
+    Ok(Json(tag))
+}
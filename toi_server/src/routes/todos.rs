@@ -1,17 +1,44 @@
-use axum::{extract::State, http::StatusCode, response::Json};
-use diesel::{ExpressionMethods, QueryDsl, SelectableHelper};
-use diesel_async::RunQueryDsl;
-use pgvector::VectorExpressionMethods;
+use axum::{
+    extract::{Path, State},
+    http::{HeaderMap, StatusCode},
+    response::Json,
+};
+use chrono::{DateTime, Duration, Utc};
+use diesel::{
+    BoolExpressionMethods, ExpressionMethods, OptionalExtension, QueryDsl, SelectableHelper,
+    expression_methods::PgSortExpressionMethods,
+};
+use diesel_async::{AsyncConnection, RunQueryDsl, scoped_futures::ScopedFutureExt};
+use pgvector::{Vector, VectorExpressionMethods};
 use schemars::schema_for;
+use std::collections::HashMap;
 use utoipa_axum::{router::OpenApiRouter, routes};
 
 use crate::{
+    auth::CurrentUser,
+    embeddings::EmbeddedTable,
+    idempotency::IdempotencyKey,
     models::{
-        client::{EmbeddingPromptTemplate, EmbeddingRequest, RerankRequest},
+        client::{EmbeddingCache, EmbeddingRequest},
+        deletion::DeleteParams,
+        error::ToiError,
+        events::{Event, EventSearchParams},
+        pagination::{Count, Page, SearchResponse},
         state::ToiState,
-        todos::{CompleteTodoRequest, NewTodo, NewTodoRequest, Todo, TodoSearchParams},
+        tags::{Tag, TagSearchParams},
+        todos::{
+            CompleteTodoRequest, NewTodo, NewTodoRequest, NewTodoTag, NewTodoTagsRequest,
+            SnoozeTodoRequest, Todo, TodoOrderBy, TodoSearchParams, TodoTagSearchParams, TodoTags,
+            TodoWithEvent,
+        },
+    },
+    routes::{
+        events::search_events,
+        tags::{resolve_tags, search_tags},
     },
-    schema, utils,
+    schema,
+    search::{self, RerankOptions},
+    utils,
 };
 
 // Prefixes are used for embedding instructions.
@@ -22,20 +49,102 @@ const QUERY_PREFIX: &str = "Query: ";
 pub fn todos_router(state: ToiState) -> OpenApiRouter {
     OpenApiRouter::new()
         .routes(routes!(add_todo, complete_matching_todos))
+        .routes(routes!(snooze_matching_todos))
         .routes(routes!(delete_matching_todos))
         .routes(routes!(get_matching_todos))
+        .routes(routes!(purge_deleted_todos))
+        .routes(routes!(restore_matching_todos))
+        .routes(routes!(add_todo_tags))
+        .routes(routes!(get_matching_todo_tags))
+        .routes(routes!(delete_matching_todo_tags))
+        .routes(routes!(get_todo))
         .with_state(state)
 }
 
+/// Find the event a todo is for using the event's database-generated ID or
+/// the event that best matches a query, returning `None` if neither is given.
+pub async fn resolve_event(
+    state: &ToiState,
+    user_id: i32,
+    event_id: Option<i32>,
+    event_query: Option<String>,
+    conn: &mut utils::Conn<'_>,
+) -> Result<Option<i32>, ToiError> {
+    if event_id.is_none() && event_query.is_none() {
+        return Ok(None);
+    }
+    let event_query_params = EventSearchParams {
+        ids: event_id.map(|i| vec![i]),
+        event_day: None,
+        event_day_falls_on: None,
+        timezone: None,
+        query: event_query,
+        use_reranking_filter: None,
+        created_from: None,
+        created_to: None,
+        occurs_from: None,
+        occurs_to: None,
+        upcoming_only: None,
+        place_id: None,
+        place_query: None,
+        include_place: None,
+        order_by: None,
+        limit: Some(1),
+        offset: None,
+        count_only: None,
+    };
+    let mut embeddings = EmbeddingCache::default();
+    let event_id = search_events(state, user_id, event_query_params, &mut embeddings, conn)
+        .await?
+        .items
+        .into_iter()
+        .next()
+        .ok_or(ToiError::NotFound("event not found".to_string()))?;
+    Ok(Some(event_id))
+}
+
 pub async fn search_todos(
     state: &ToiState,
+    user_id: i32,
     params: TodoSearchParams,
+    trash: utils::Scope,
     conn: &mut utils::Conn<'_>,
-) -> Result<Vec<i32>, (StatusCode, String)> {
+) -> Result<Page<i32>, ToiError> {
+    // The total is counted alongside a page's items, so it's counted on
+    // its own when the offset is past the end and there aren't any.
+    let count_params = (params.offset.is_some_and(|offset| offset > 0)
+        && !params.count_only.unwrap_or_default())
+    .then(|| TodoSearchParams {
+        use_reranking_filter: None,
+        limit: None,
+        offset: None,
+        count_only: Some(true),
+        ..params.clone()
+    });
+    let mut page = search_todos_page(state, user_id, params, trash.clone(), conn).await?;
+    if page.total == 0
+        && let Some(count_params) = count_params
+    {
+        page.total = search_todos_page(state, user_id, count_params, trash, conn)
+            .await?
+            .total;
+    }
+    Ok(page)
+}
+
+async fn search_todos_page(
+    state: &ToiState,
+    user_id: i32,
+    params: TodoSearchParams,
+    trash: utils::Scope,
+    conn: &mut utils::Conn<'_>,
+) -> Result<Page<i32>, ToiError> {
     let TodoSearchParams {
         ids,
         query,
         use_reranking_filter,
+        exclude_query,
+        exclude_ids,
         created_from,
         created_to,
         due_from,
@@ -44,11 +153,22 @@ pub async fn search_todos(
         completed_to,
         incomplete,
         never_due,
+        is_recurring,
+        min_priority,
+        max_priority,
+        event_id,
+        event_query,
+        tags,
+        tags_match,
         order_by,
         limit,
+        offset,
+        count_only,
     } = params;
 
-    let mut sql_query = schema::todos::table.select(Todo::as_select()).into_boxed();
+    let mut sql_query = schema::todos::table
+        .select((Todo::as_select(), utils::total_count()))
+        .into_boxed();
 
     // Filter items created on or after date.
     if let Some(created_from) = created_from {
@@ -98,19 +218,72 @@ pub async fn search_todos(
         }
     }
 
+    // Filter recurring todos.
+    if let Some(scope) = is_recurring {
+        match scope {
+            utils::Scope::In => {
+                sql_query = sql_query.filter(schema::todos::recurrence_days.is_not_null());
+            }
+            utils::Scope::Out => {
+                sql_query = sql_query.filter(schema::todos::recurrence_days.is_null());
+            }
+        }
+    }
+
+    // Filter todos with at least a priority.
+    if let Some(min_priority) = min_priority {
+        sql_query = sql_query.filter(schema::todos::priority.ge(min_priority));
+    }
+
+    // Filter todos with at most a priority.
+    if let Some(max_priority) = max_priority {
+        sql_query = sql_query.filter(schema::todos::priority.le(max_priority));
+    }
+
+    // Filter todos for an event.
+    if let Some(event_id) = resolve_event(state, user_id, event_id, event_query, conn).await? {
+        sql_query = sql_query.filter(schema::todos::event_id.eq(event_id));
+    }
+
+    // Filter items with any or all of the tags.
+    if let Some(tags) = tags {
+        let tag_ids = resolve_tags(state, user_id, tags).await?;
+        let todo_tags: Vec<(i32, i32)> = schema::todo_tags::table
+            .select((schema::todo_tags::todo_id, schema::todo_tags::tag_id))
+            .filter(schema::todo_tags::tag_id.eq_any(&tag_ids))
+            .load(conn)
+            .await
+            .map_err(utils::diesel_error)?;
+        let todo_ids = tags_match
+            .unwrap_or_default()
+            .matching_ids(todo_tags, tag_ids.len());
+        sql_query = sql_query.filter(schema::todos::id.eq_any(todo_ids));
+    }
+
+    // Items are only ordered by their distance to the query if they aren't
+    // ordered some other way.
+    let ordered_by_distance = order_by.is_none();
+
     // Order items.
     match order_by {
-        Some(utils::OrderBy::Oldest) => sql_query = sql_query.order(schema::todos::created_at),
-        Some(utils::OrderBy::Newest) => {
+        Some(TodoOrderBy::Oldest) => sql_query = sql_query.order(schema::todos::created_at),
+        Some(TodoOrderBy::Newest) => {
             sql_query = sql_query.order(schema::todos::created_at.desc());
         }
+        Some(TodoOrderBy::DueSoonest) => {
+            sql_query = sql_query.order(schema::todos::due_at.asc().nulls_last());
+        }
+        Some(TodoOrderBy::HighestPriority) => {
+            sql_query = sql_query
+                .order(schema::todos::priority.desc().nulls_last())
+                .then_order_by(schema::todos::due_at.asc().nulls_last());
+        }
         None => {
             // By default, filter items similar to a given query.
             if let Some(ref query) = query {
-                let input = EmbeddingPromptTemplate::builder()
-                    .instruction_prefix(INSTRUCTION_PREFIX.to_string())
-                    .query_prefix(QUERY_PREFIX.to_string())
-                    .build()
+                let input = state
+                    .embedding_instructions
+                    .template("todos", INSTRUCTION_PREFIX, QUERY_PREFIX)
                     .apply(query);
                 let embedding_request = EmbeddingRequest { input };
                 let embedding = state.model_client.embed(embedding_request).await?;
@@ -130,35 +303,218 @@ pub async fn search_todos(
         sql_query = sql_query.or_filter(schema::todos::id.eq_any(ids));
     }
 
-    // Limit number of items.
-    if let Some(limit) = limit {
+    // Filter out excluded items. This comes after the inclusive filters so
+    // items selected by their ids are still excluded, and it's part of the
+    // query so excluded items don't count towards the limit.
+    if let Some(exclude_ids) = exclude_ids {
+        sql_query = sql_query.filter(diesel::dsl::not(schema::todos::id.eq_any(exclude_ids)));
+    }
+    if let Some(exclude_query) = exclude_query {
+        let input = state
+            .embedding_instructions
+            .template("todos", INSTRUCTION_PREFIX, QUERY_PREFIX)
+            .apply(&exclude_query);
+        let embedding_request = EmbeddingRequest { input };
+        let embedding = state.model_client.embed(embedding_request).await?;
+        sql_query = sql_query.filter(
+            schema::todos::embedding
+                .cosine_distance(embedding)
+                .gt(state.server_config.exclude_distance_threshold),
+        );
+    }
+
+    // Only the user's own items are searched. This comes after the other
+    // filters so items selected by their ids are still only the user's.
+    sql_query = sql_query.filter(schema::todos::user_id.eq(user_id));
+
+    // Filter items in or out of the trash. This comes after the other
+    // filters so items selected by their ids are still filtered.
+    match trash {
+        utils::Scope::In => sql_query = sql_query.filter(schema::todos::deleted_at.is_not_null()),
+        utils::Scope::Out => sql_query = sql_query.filter(schema::todos::deleted_at.is_null()),
+    }
+
+    // Limit number of items. Only the total is needed when counting items
+    // that don't need to be reranked, so only one item is loaded. Items
+    // counted once they're reranked aren't limited so they're all counted.
+    let count_only = count_only.unwrap_or_default();
+    let count_total =
+        count_only && !search::needs_reranking(query.as_deref(), use_reranking_filter);
+    if count_total {
+        sql_query = sql_query.limit(1);
+    } else if let Some(limit) = limit.filter(|_| !count_only) {
         sql_query = sql_query.limit(limit);
     }
 
+    // Skip a number of items.
+    if let Some(offset) = offset.filter(|_| !count_only) {
+        sql_query = sql_query.offset(offset);
+    }
+
     // Get all the items that match the query.
-    let todos: Vec<Todo> = sql_query.load(conn).await.map_err(utils::diesel_error)?;
-    let (ids, documents): (Vec<i32>, Vec<String>) =
-        todos.into_iter().map(|todo| (todo.id, todo.item)).unzip();
-    if ids.is_empty() {
-        return Ok(ids);
+    let todos: Vec<(Todo, i64)> = sql_query.load(conn).await.map_err(utils::diesel_error)?;
+    let total = todos.as_slice().first().map_or(0, |(_, total)| *total);
+    if count_total {
+        return Ok(Page::count(total));
+    }
+    let mut ids_docs: Vec<(i32, String)> = todos
+        .into_iter()
+        .map(|(todo, _)| (todo.id, todo.item))
+        .collect();
+
+    // Items that aren't ordered by their distance to the query are put in
+    // that order so only the furthest ones are left out of reranking.
+    if !ordered_by_distance
+        && search::needs_reranking(query.as_deref(), use_reranking_filter)
+        && let Some(ref query) = query
+    {
+        let input = state
+            .embedding_instructions
+            .template("todos", INSTRUCTION_PREFIX, QUERY_PREFIX)
+            .apply(query);
+        ids_docs =
+            search::closest_first(state, EmbeddedTable::Todos, input, ids_docs, conn).await?;
     }
 
     // Rerank and filter items once more.
-    let ids = match (query, use_reranking_filter) {
-        (Some(query), Some(true)) => {
-            let rerank_request = RerankRequest { query, documents };
-            let rerank_response = state.model_client.rerank(rerank_request).await?;
-            rerank_response
-                .results
-                .into_iter()
-                .filter(|item| item.relevance_score >= state.server_config.similarity_threshold)
-                .map(|item| ids[item.index])
-                .collect()
-        }
-        _ => ids,
+    let ids = search::rerank_filter(
+        state,
+        query,
+        use_reranking_filter,
+        ids_docs,
+        &RerankOptions::default(),
+    )
+    .await?;
+
+    if count_only {
+        return Ok(Page::count(ids.len().try_into().unwrap_or(i64::MAX)));
+    }
+
+    Ok(Page {
+        items: ids,
+        total,
+        offset,
+        limit,
+    })
+}
+
+pub async fn search_todo_tags(
+    state: &ToiState,
+    user_id: i32,
+    params: TodoTagSearchParams,
+    conn: &mut utils::Conn<'_>,
+) -> Result<(Todo, Vec<i32>), ToiError> {
+    let TodoTagSearchParams {
+        todo_id,
+        todo_query,
+        todo_use_reranking_filter,
+        todo_created_from,
+        todo_created_to,
+        todo_order_by,
+        tag_ids,
+        tag_query,
+        tag_use_reranking_filter,
+        tag_use_edit_distance_filter,
+        tag_limit,
+    } = params;
+    let todo_query_params = TodoSearchParams {
+        ids: todo_id.map(|i| vec![i]),
+        query: todo_query,
+        use_reranking_filter: todo_use_reranking_filter,
+        exclude_query: None,
+        exclude_ids: None,
+        created_from: todo_created_from,
+        created_to: todo_created_to,
+        due_from: None,
+        due_to: None,
+        completed_from: None,
+        completed_to: None,
+        incomplete: None,
+        never_due: None,
+        is_recurring: None,
+        min_priority: None,
+        max_priority: None,
+        event_id: None,
+        event_query: None,
+        tags: None,
+        tags_match: None,
+        order_by: todo_order_by,
+        limit: Some(1),
+        offset: None,
+        count_only: None,
     };
+    let todo_id = search_todos(state, user_id, todo_query_params, utils::Scope::Out, conn)
+        .await?
+        .items
+        .into_iter()
+        .next()
+        .ok_or(ToiError::NotFound("todo not found".to_string()))?;
+    let todo = schema::todos::table
+        .select(Todo::as_select())
+        .filter(schema::todos::id.eq(todo_id))
+        .first(conn)
+        .await
+        .map_err(utils::diesel_error)?;
+
+    let mut sql_query = schema::todo_tags::table
+        .select(schema::todo_tags::tag_id)
+        .filter(schema::todo_tags::todo_id.eq(todo.id))
+        .into_boxed();
 
-    Ok(ids)
+    if let Some(tag_ids) = tag_ids {
+        sql_query = sql_query.filter(schema::todo_tags::tag_id.eq_any(tag_ids));
+    }
+
+    let tag_ids = sql_query.load(conn).await.map_err(utils::diesel_error)?;
+    if tag_ids.is_empty() {
+        return Ok((todo, tag_ids));
+    }
+
+    let tag_query_params = TagSearchParams {
+        ids: Some(tag_ids),
+        query: tag_query,
+        use_reranking_filter: tag_use_reranking_filter,
+        use_edit_distance_filter: tag_use_edit_distance_filter,
+        order_by: None,
+        limit: tag_limit,
+    };
+    let mut embeddings = EmbeddingCache::default();
+    let tag_ids = search_tags(state, user_id, tag_query_params, &mut embeddings, conn).await?;
+    Ok((todo, tag_ids))
+}
+
+/// Find an existing todo for the same thing as a new todo. The todo closest
+/// to the new todo is a duplicate if it's within the duplicate distance
+/// threshold and the reranking API agrees. Todos that are complete or in
+/// the trash aren't considered, so finished todos can be added again.
+async fn find_duplicate_todo(
+    state: &ToiState,
+    user_id: i32,
+    item: &str,
+    embedding: Vector,
+    conn: &mut utils::Conn<'_>,
+) -> Result<Option<Todo>, ToiError> {
+    let todo = schema::todos::table
+        .select(Todo::as_select())
+        .filter(schema::todos::user_id.eq(user_id))
+        .filter(schema::todos::deleted_at.is_null())
+        .filter(schema::todos::completed_at.is_null())
+        .filter(
+            schema::todos::embedding
+                .cosine_distance(embedding.clone())
+                .le(state.server_config.duplicate_distance_threshold),
+        )
+        .order(schema::todos::embedding.cosine_distance(embedding))
+        .first(conn)
+        .await
+        .optional()
+        .map_err(utils::diesel_error)?;
+    match todo {
+        Some(todo) if search::is_confirmed_duplicate(state, item, todo.item.clone()).await? => {
+            Ok(Some(todo))
+        }
+        _ => Ok(None),
+    }
 }
 
 /// Add and return a todo.
@@ -177,6 +533,8 @@ pub async fn search_todos(
     responses(
         (status = 201, description = "Successfully added a todo", body = Todo),
         (status = 400, description = "Default JSON elements configured by the user are invalid"),
+        (status = 404, description = "Event not found"),
+        (status = 409, description = "A similar todo that isn't complete already exists, or the idempotency key was already used for a different request", body = Todo),
         (status = 422, description = "Error when parsing a response from a model API"),
         (status = 502, description = "Error when forwarding request to model APIs")
     )
@@ -184,35 +542,108 @@ pub async fn search_todos(
 #[axum::debug_handler]
 async fn add_todo(
     State(state): State<ToiState>,
+    user: CurrentUser,
+    headers: HeaderMap,
     Json(params): Json<NewTodoRequest>,
-) -> Result<Json<Todo>, (StatusCode, String)> {
-    let mut conn = state.pool.get().await.map_err(utils::internal_error)?;
+) -> Result<(StatusCode, Json<Todo>), ToiError> {
+    let idempotency_key =
+        IdempotencyKey::from_request(&state, &headers, user.id, "/todos", &params)?;
+    let mut conn = utils::get_conn(&state.pool).await?;
+    // Replay repeats before embedding anything.
+    if let Some(key) = &idempotency_key
+        && let Some(todo) = key.replay(&mut conn).await?
+    {
+        return Ok((StatusCode::OK, Json(todo)));
+    }
     let NewTodoRequest {
         item,
         due_at,
         completed_at,
+        priority,
+        recurrence_days,
+        event_id,
+        event_query,
+        tags,
+        allow_duplicate,
     } = params;
+    if recurrence_days.is_some_and(|days| days <= 0) {
+        return Err(ToiError::Validation(
+            "recurrence days must be positive".to_string(),
+        ));
+    }
+    let event_id = resolve_event(&state, user.id, event_id, event_query, &mut conn).await?;
+    // Get tag IDs for matching tags.
+    let tag_ids = match tags {
+        Some(tags) => resolve_tags(&state, user.id, tags).await?,
+        None => vec![],
+    };
     let embedding_request = EmbeddingRequest {
         input: item.clone(),
     };
     let embedding = state.model_client.embed(embedding_request).await?;
+    // Make sure the same todo isn't already open if duplicates are being
+    // checked, returning the existing todo if it is.
+    if state.server_config.duplicate_check_enabled
+        && !allow_duplicate.unwrap_or_default()
+        && let Some(todo) =
+            find_duplicate_todo(&state, user.id, &item, embedding.clone(), &mut conn).await?
+    {
+        return Ok((StatusCode::CONFLICT, Json(todo)));
+    }
     let new_todo = NewTodo {
+        user_id: user.id,
         item,
         embedding,
         due_at,
         completed_at,
+        priority,
+        recurrence_days,
+        event_id,
     };
-    let result = diesel::insert_into(schema::todos::table)
-        .values(new_todo)
-        .returning(Todo::as_returning())
-        .get_result(&mut conn)
-        .await
-        .map_err(utils::diesel_error)?;
-    Ok(Json(result))
+    let result = conn
+        .transaction::<_, ToiError, _>(|mut conn| {
+            async move {
+                // Check again in case a request with the same key added the
+                // todo while this one was embedding it.
+                if let Some(key) = &idempotency_key
+                    && let Some(todo) = key.replay(&mut conn).await?
+                {
+                    return Ok(todo);
+                }
+                let todo: Todo = diesel::insert_into(schema::todos::table)
+                    .values(new_todo)
+                    .returning(Todo::as_returning())
+                    .get_result(&mut conn)
+                    .await?;
+                // Add the todo tags using the todo's database-generated ID.
+                let new_todo_tags: Vec<NewTodoTag> = tag_ids
+                    .into_iter()
+                    .map(|tag_id| NewTodoTag {
+                        todo_id: todo.id,
+                        tag_id,
+                    })
+                    .collect();
+                diesel::insert_into(schema::todo_tags::table)
+                    .values(new_todo_tags)
+                    .execute(&mut conn)
+                    .await?;
+                if let Some(key) = &idempotency_key {
+                    key.store(&todo, &mut conn).await?;
+                }
+                Ok(todo)
+            }
+            .scope_boxed()
+        })
+        .await?;
+    Ok((StatusCode::OK, Json(result)))
 }
 
 /// Complete and return todos.
 ///
+/// Recurring todos aren't marked as complete. Instead, their completion is
+/// recorded as when they were last completed, and they're due again after
+/// their recurrence interval.
+///
 /// Example queries for completing todos using this endpoint:
 /// - Complete all todos
 /// - Complete todos
@@ -226,15 +657,16 @@ async fn add_todo(
     request_body = CompleteTodoRequest,
     responses(
         (status = 200, description = "Successfully updated todos", body = Todo),
-        (status = 404, description = "Todos not found")
+        (status = 404, description = "No todos found with the given IDs")
     )
 )]
 #[axum::debug_handler]
 async fn complete_matching_todos(
     State(state): State<ToiState>,
+    user: CurrentUser,
     Json(params): Json<CompleteTodoRequest>,
-) -> Result<Json<Vec<Todo>>, (StatusCode, String)> {
-    let mut conn = state.pool.get().await.map_err(utils::internal_error)?;
+) -> Result<Json<Vec<Todo>>, ToiError> {
+    let mut conn = utils::get_conn(&state.pool).await?;
     let CompleteTodoRequest {
         ids,
         completed_at,
@@ -246,13 +678,19 @@ async fn complete_matching_todos(
         due_to,
         incomplete,
         never_due,
+        is_recurring,
+        min_priority,
+        max_priority,
         order_by,
         limit,
     } = params;
+    let requested_ids = ids.clone();
     let params = TodoSearchParams {
         ids,
         query,
         use_reranking_filter,
+        exclude_query: None,
+        exclude_ids: None,
         created_from,
         created_to,
         due_from,
@@ -261,21 +699,217 @@ async fn complete_matching_todos(
         completed_to: None,
         incomplete,
         never_due,
+        is_recurring,
+        min_priority,
+        max_priority,
+        event_id: None,
+        event_query: None,
+        tags: None,
+        tags_match: None,
         order_by,
         limit,
+        offset: None,
+        count_only: None,
     };
-    let ids = search_todos(&state, params, &mut conn).await?;
-    let todos = diesel::update(schema::todos::table.filter(schema::todos::id.eq_any(ids)))
-        .set(schema::todos::completed_at.eq(completed_at))
-        .returning(Todo::as_returning())
-        .load(&mut conn)
+    let ids = search_todos(&state, user.id, params, utils::Scope::Out, &mut conn)
+        .await?
+        .items;
+    let todos = conn
+        .transaction(|mut conn| {
+            async move {
+                // One-shot todos are simply marked as complete.
+                let mut todos = diesel::update(
+                    schema::todos::table
+                        .filter(schema::todos::id.eq_any(&ids))
+                        .filter(schema::todos::recurrence_days.is_null()),
+                )
+                .set(schema::todos::completed_at.eq(completed_at))
+                .returning(Todo::as_returning())
+                .load(&mut conn)
+                .await?;
+
+                // Recurring todos record the completion and are due again
+                // after their interval, starting from when they were due or,
+                // if they were never due, from when they were completed.
+                let recurring_todos: Vec<Todo> = schema::todos::table
+                    .select(Todo::as_select())
+                    .filter(schema::todos::id.eq_any(&ids))
+                    .filter(schema::todos::recurrence_days.is_not_null())
+                    .for_update()
+                    .load(&mut conn)
+                    .await?;
+                for todo in recurring_todos {
+                    let recurrence_days = todo.recurrence_days.unwrap_or_default();
+                    let due_at = todo
+                        .due_at
+                        .unwrap_or(completed_at)
+                        .checked_add_signed(Duration::days(recurrence_days.into()))
+                        .ok_or(diesel::result::Error::QueryBuilderError(
+                            "recurring todo due date overflow".into(),
+                        ))?;
+                    let todo = diesel::update(schema::todos::table.find(todo.id))
+                        .set((
+                            schema::todos::due_at.eq(due_at),
+                            schema::todos::completed_at.eq(None::<DateTime<Utc>>),
+                            schema::todos::last_completed_at.eq(completed_at),
+                        ))
+                        .returning(Todo::as_returning())
+                        .get_result(&mut conn)
+                        .await?;
+                    todos.push(todo);
+                }
+                Ok(todos)
+            }
+            .scope_boxed()
+        })
         .await
         .map_err(utils::diesel_error)?;
+    utils::check_ids_found(requested_ids.as_deref(), &todos, "todos")?;
+    Ok(Json(todos))
+}
+
+/// Snooze and return todos by pushing back when they're due.
+///
+/// Completed todos are only snoozed if they're reopened too.
+///
+/// Example queries for snoozing todos using this endpoint:
+/// - Snooze the todo until
+/// - Push back the todo by
+/// - Postpone the todo to
+/// - Remind me about the todo later
+#[utoipa::path(
+    put,
+    path = "/snooze",
+    extensions(
+        ("x-json-schema-body" = json!(schema_for!(SnoozeTodoRequest)))
+    ),
+    request_body = SnoozeTodoRequest,
+    responses(
+        (status = 200, description = "Successfully snoozed todos", body = [Todo]),
+        (status = 400, description = "Neither or both of a snooze datetime and number of days were given, or completed todos weren't reopened"),
+        (status = 404, description = "No todos found with the given IDs")
+    )
+)]
+#[axum::debug_handler]
+async fn snooze_matching_todos(
+    State(state): State<ToiState>,
+    user: CurrentUser,
+    Json(params): Json<SnoozeTodoRequest>,
+) -> Result<Json<Vec<Todo>>, ToiError> {
+    let SnoozeTodoRequest {
+        ids,
+        snooze_until,
+        snooze_for_days,
+        reopen,
+        query,
+        use_reranking_filter,
+        created_from,
+        created_to,
+        due_from,
+        due_to,
+        incomplete,
+        never_due,
+        is_recurring,
+        min_priority,
+        max_priority,
+        order_by,
+        limit,
+    } = params;
+    if snooze_until.is_some() == snooze_for_days.is_some() {
+        return Err(ToiError::Validation(
+            "exactly one of snooze_until or snooze_for_days is required".to_string(),
+        ));
+    }
+    let reopen = reopen.unwrap_or_default();
+    let mut conn = utils::get_conn(&state.pool).await?;
+    let requested_ids = ids.clone();
+    let params = TodoSearchParams {
+        ids,
+        query,
+        use_reranking_filter,
+        exclude_query: None,
+        exclude_ids: None,
+        created_from,
+        created_to,
+        due_from,
+        due_to,
+        completed_from: None,
+        completed_to: None,
+        incomplete,
+        never_due,
+        is_recurring,
+        min_priority,
+        max_priority,
+        event_id: None,
+        event_query: None,
+        tags: None,
+        tags_match: None,
+        order_by,
+        limit,
+        offset: None,
+        count_only: None,
+    };
+    let ids = search_todos(&state, user.id, params, utils::Scope::Out, &mut conn)
+        .await?
+        .items;
+    let now = Utc::now();
+    let todos = conn
+        .transaction::<_, ToiError, _>(|mut conn| {
+            async move {
+                let todos: Vec<Todo> = schema::todos::table
+                    .select(Todo::as_select())
+                    .filter(schema::todos::id.eq_any(&ids))
+                    .for_update()
+                    .load(&mut conn)
+                    .await?;
+                if !reopen && todos.iter().any(|todo| todo.completed_at.is_some()) {
+                    return Err(ToiError::Validation(
+                        "completed todos can only be snoozed if they're reopened".to_string(),
+                    ));
+                }
+
+                // Todos are pushed back from when they're due or, if they're
+                // never due, from now.
+                let mut snoozed_todos = Vec::with_capacity(todos.len());
+                for todo in todos {
+                    let due_at = match snooze_until {
+                        Some(snooze_until) => snooze_until,
+                        None => {
+                            let days = Duration::days(snooze_for_days.unwrap_or_default().into());
+                            todo.due_at
+                                .unwrap_or(now)
+                                .checked_add_signed(days)
+                                .ok_or_else(|| {
+                                    ToiError::Validation(
+                                        "snoozed todo due date overflow".to_string(),
+                                    )
+                                })?
+                        }
+                    };
+                    let completed_at = if reopen { None } else { todo.completed_at };
+                    let todo = diesel::update(schema::todos::table.find(todo.id))
+                        .set((
+                            schema::todos::due_at.eq(due_at),
+                            schema::todos::completed_at.eq(completed_at),
+                        ))
+                        .returning(Todo::as_returning())
+                        .get_result(&mut conn)
+                        .await?;
+                    snoozed_todos.push(todo);
+                }
+                Ok(snoozed_todos)
+            }
+            .scope_boxed()
+        })
+        .await?;
+    utils::check_ids_found(requested_ids.as_deref(), &todos, "todos")?;
     Ok(Json(todos))
 }
 
 /// Delete and return todos.
 ///
+/// Deleted todos are moved to the trash so they can be restored later.
+///
 /// Example queries for deleting todos using this endpoint:
 /// - Delete all todos
 /// - Erase all todos
@@ -285,13 +919,13 @@ async fn complete_matching_todos(
     post,
     path = "/delete",
     extensions(
-        ("x-json-schema-body" = json!(schema_for!(TodoSearchParams)))
+        ("x-json-schema-body" = json!(schema_for!(DeleteParams<TodoSearchParams>)))
     ),
-    request_body = TodoSearchParams,
+    request_body = DeleteParams<TodoSearchParams>,
     responses(
         (status = 200, description = "Successfully deleted todos", body = [Todo]),
-        (status = 400, description = "Default JSON elements configured by the user are invalid"),
-        (status = 404, description = "No todos found"),
+        (status = 400, description = "Default JSON elements configured by the user are invalid, or deleting every item wasn't confirmed"),
+        (status = 404, description = "No todos found with the given IDs, or no event found"),
         (status = 422, description = "Error when parsing a response from a model API"),
         (status = 502, description = "Error when forwarding request to model APIs")
     )
@@ -299,15 +933,28 @@ async fn complete_matching_todos(
 #[axum::debug_handler]
 async fn delete_matching_todos(
     State(state): State<ToiState>,
-    Json(params): Json<TodoSearchParams>,
-) -> Result<Json<Vec<Todo>>, (StatusCode, String)> {
-    let mut conn = state.pool.get().await.map_err(utils::internal_error)?;
-    let ids = search_todos(&state, params, &mut conn).await?;
-    let todos = diesel::delete(schema::todos::table.filter(schema::todos::id.eq_any(ids)))
+    user: CurrentUser,
+    Json(params): Json<DeleteParams<TodoSearchParams>>,
+) -> Result<Json<Vec<Todo>>, ToiError> {
+    let params = params.into_checked()?;
+    let mut conn = utils::get_conn(&state.pool).await?;
+
+    // Items are always needed here, so counting is ignored.
+    let params = TodoSearchParams {
+        count_only: None,
+        ..params
+    };
+    let requested_ids = params.ids.clone();
+    let ids = search_todos(&state, user.id, params, utils::Scope::Out, &mut conn)
+        .await?
+        .items;
+    let todos = diesel::update(schema::todos::table.filter(schema::todos::id.eq_any(ids)))
+        .set(schema::todos::deleted_at.eq(Utc::now()))
         .returning(Todo::as_returning())
         .load(&mut conn)
         .await
         .map_err(utils::diesel_error)?;
+    utils::check_ids_found(requested_ids.as_deref(), &todos, "todos")?;
     Ok(Json(todos))
 }
 
@@ -326,9 +973,9 @@ async fn delete_matching_todos(
     ),
     request_body = TodoSearchParams,
     responses(
-        (status = 200, description = "Successfully got todos", body = [Todo]),
+        (status = 200, description = "Successfully got todos or their count", body = SearchResponse<TodoWithEvent>),
         (status = 400, description = "Default JSON elements configured by the user are invalid"),
-        (status = 404, description = "No todos found"),
+        (status = 404, description = "Event not found"),
         (status = 422, description = "Error when parsing a response from a model API"),
         (status = 502, description = "Error when forwarding request to model APIs")
     )
@@ -336,15 +983,355 @@ async fn delete_matching_todos(
 #[axum::debug_handler]
 async fn get_matching_todos(
     State(state): State<ToiState>,
+    user: CurrentUser,
     Json(params): Json<TodoSearchParams>,
-) -> Result<Json<Vec<Todo>>, (StatusCode, String)> {
-    let mut conn = state.pool.get().await.map_err(utils::internal_error)?;
-    let ids = search_todos(&state, params, &mut conn).await?;
-    let todos = schema::todos::table
+) -> Result<Json<SearchResponse<TodoWithEvent>>, ToiError> {
+    let count_only = params.count_only.unwrap_or_default();
+    let include_event = params.event_id.is_some() || params.event_query.is_some();
+    let mut conn = utils::get_conn(&state.pool).await?;
+    let Page {
+        items: ids,
+        total,
+        offset,
+        limit,
+    } = search_todos(&state, user.id, params, utils::Scope::Out, &mut conn).await?;
+    if count_only {
+        return Ok(Json(SearchResponse::Count(Count { count: total })));
+    }
+    let todos: Vec<Todo> = schema::todos::table
         .select(Todo::as_select())
         .filter(schema::todos::id.eq_any(ids))
         .load(&mut conn)
         .await
         .map_err(utils::diesel_error)?;
+
+    // Todos filtered by event are returned with their event.
+    let mut events_by_id: HashMap<i32, Event> = HashMap::new();
+    if include_event {
+        let event_ids: Vec<i32> = todos.iter().filter_map(|todo| todo.event_id).collect();
+        let events: Vec<Event> = schema::events::table
+            .select(Event::as_select())
+            .filter(schema::events::id.eq_any(event_ids))
+            .load(&mut conn)
+            .await
+            .map_err(utils::diesel_error)?;
+        events_by_id.extend(events.into_iter().map(|event| (event.id, event)));
+    }
+    let todos = todos
+        .into_iter()
+        .map(|todo| {
+            let event = todo
+                .event_id
+                .and_then(|event_id| events_by_id.get(&event_id).cloned());
+            TodoWithEvent { todo, event }
+        })
+        .collect();
+    Ok(Json(SearchResponse::Page(Page {
+        items: todos,
+        total,
+        offset,
+        limit,
+    })))
+}
+
+/// Permanently delete and return todos that have been in the trash for
+/// longer than the server's trash retention period.
+///
+/// Example queries for purging todos using this endpoint:
+/// - Empty the todos trash
+/// - Purge old deleted todos
+/// - Permanently delete todos in the trash
+#[utoipa::path(
+    post,
+    path = "/purge",
+    responses(
+        (status = 200, description = "Successfully purged todos", body = [Todo])
+    )
+)]
+#[axum::debug_handler]
+async fn purge_deleted_todos(
+    State(state): State<ToiState>,
+    user: CurrentUser,
+) -> Result<Json<Vec<Todo>>, ToiError> {
+    let mut conn = utils::get_conn(&state.pool).await?;
+    let cutoff = Utc::now() - Duration::days(state.server_config.trash_retention_days.into());
+    let todos = diesel::delete(
+        schema::todos::table
+            .filter(schema::todos::user_id.eq(user.id))
+            .filter(schema::todos::deleted_at.le(cutoff)),
+    )
+    .returning(Todo::as_returning())
+    .load(&mut conn)
+    .await
+    .map_err(utils::diesel_error)?;
+    Ok(Json(todos))
+}
+
+/// Restore and return todos from the trash.
+///
+/// Example queries for restoring todos using this endpoint:
+/// - Restore the todo about
+/// - Undelete todos
+/// - Bring back the deleted todos
+/// - Recover todos from the trash
+#[utoipa::path(
+    post,
+    path = "/restore",
+    extensions(
+        ("x-json-schema-body" = json!(schema_for!(TodoSearchParams)))
+    ),
+    request_body = TodoSearchParams,
+    responses(
+        (status = 200, description = "Successfully restored todos", body = [Todo]),
+        (status = 400, description = "Default JSON elements configured by the user are invalid"),
+        (status = 404, description = "No todos found with the given IDs, or no event found"),
+        (status = 422, description = "Error when parsing a response from a model API"),
+        (status = 502, description = "Error when forwarding request to model APIs")
+    )
+)]
+#[axum::debug_handler]
+async fn restore_matching_todos(
+    State(state): State<ToiState>,
+    user: CurrentUser,
+    Json(params): Json<TodoSearchParams>,
+) -> Result<Json<Vec<Todo>>, ToiError> {
+    let mut conn = utils::get_conn(&state.pool).await?;
+
+    // Items are always needed here, so counting is ignored.
+    let params = TodoSearchParams {
+        count_only: None,
+        ..params
+    };
+    let requested_ids = params.ids.clone();
+    let ids = search_todos(&state, user.id, params, utils::Scope::In, &mut conn)
+        .await?
+        .items;
+    let todos = diesel::update(schema::todos::table.filter(schema::todos::id.eq_any(ids)))
+        .set(schema::todos::deleted_at.eq(None::<DateTime<Utc>>))
+        .returning(Todo::as_returning())
+        .load(&mut conn)
+        .await
+        .map_err(utils::diesel_error)?;
+    utils::check_ids_found(requested_ids.as_deref(), &todos, "todos")?;
+    Ok(Json(todos))
+}
+
+/// Add todo tags to existing todos and return the updated todos.
+///
+/// Example queries for adding todo tags using this endpoint:
+/// - Tag the todo to
+/// - Add todo tags to
+/// - Tag my tasks for
+#[utoipa::path(
+    post,
+    path = "/tags",
+    extensions(
+        ("x-json-schema-body" = json!(schema_for!(NewTodoTagsRequest)))
+    ),
+    request_body = NewTodoTagsRequest,
+    responses(
+        (status = 201, description = "Successfully added todo tags", body = [Todo]),
+        (status = 400, description = "Default JSON elements configured by the user are invalid"),
+        (status = 404, description = "No matching tags found"),
+        (status = 422, description = "Error when parsing a response from a model API"),
+        (status = 502, description = "Error when forwarding request to model APIs")
+    )
+)]
+#[axum::debug_handler]
+async fn add_todo_tags(
+    State(state): State<ToiState>,
+    user: CurrentUser,
+    Json(params): Json<NewTodoTagsRequest>,
+) -> Result<Json<Vec<Todo>>, ToiError> {
+    let mut conn = utils::get_conn(&state.pool).await?;
+    let NewTodoTagsRequest {
+        ids,
+        query,
+        use_reranking_filter,
+        created_from,
+        created_to,
+        order_by,
+        tags,
+        limit,
+    } = params;
+    let params = TodoSearchParams {
+        ids,
+        query,
+        use_reranking_filter,
+        exclude_query: None,
+        exclude_ids: None,
+        created_from,
+        created_to,
+        due_from: None,
+        due_to: None,
+        completed_from: None,
+        completed_to: None,
+        incomplete: None,
+        never_due: None,
+        is_recurring: None,
+        min_priority: None,
+        max_priority: None,
+        event_id: None,
+        event_query: None,
+        tags: None,
+        tags_match: None,
+        order_by,
+        limit,
+        offset: None,
+        count_only: None,
+    };
+    let todo_ids = search_todos(&state, user.id, params, utils::Scope::Out, &mut conn)
+        .await?
+        .items;
+    // Get tag IDs for matching tags.
+    let tag_ids = resolve_tags(&state, user.id, tags).await?;
+    let mut new_todo_tags = vec![];
+    for tag_id in tag_ids {
+        for todo_id in &todo_ids {
+            let new_todo_tag = NewTodoTag {
+                todo_id: *todo_id,
+                tag_id,
+            };
+            new_todo_tags.push(new_todo_tag);
+        }
+    }
+    // Todos that already have a tag keep it as is.
+    diesel::insert_into(schema::todo_tags::table)
+        .values(new_todo_tags)
+        .on_conflict_do_nothing()
+        .execute(&mut conn)
+        .await
+        .map_err(utils::diesel_error)?;
+    let todos = schema::todos::table
+        .select(Todo::as_select())
+        .filter(schema::todos::id.eq_any(todo_ids))
+        .load(&mut conn)
+        .await
+        .map_err(utils::diesel_error)?;
     Ok(Json(todos))
 }
+
+/// Get todo tags.
+///
+/// Example queries for getting todo tags using this endpoint:
+/// - Get todo tags where
+/// - What tags does the todo to
+/// - List tags for my task to
+#[utoipa::path(
+    post,
+    path = "/tags/search",
+    extensions(
+        ("x-json-schema-body" = json!(schema_for!(TodoTagSearchParams)))
+    ),
+    request_body = TodoTagSearchParams,
+    responses(
+        (status = 200, description = "Successfully got todo tags", body = TodoTags),
+        (status = 400, description = "Default JSON elements configured by the user are invalid"),
+        (status = 404, description = "No todo or todo tags found"),
+        (status = 422, description = "Error when parsing a response from a model API"),
+        (status = 502, description = "Error when forwarding request to model APIs")
+    )
+)]
+#[axum::debug_handler]
+async fn get_matching_todo_tags(
+    State(state): State<ToiState>,
+    user: CurrentUser,
+    Json(params): Json<TodoTagSearchParams>,
+) -> Result<Json<TodoTags>, ToiError> {
+    let mut conn = utils::get_conn(&state.pool).await?;
+    let (todo, ids) = search_todo_tags(&state, user.id, params, &mut conn).await?;
+    let tags = schema::tags::table
+        .select(Tag::as_select())
+        .filter(schema::tags::id.eq_any(ids))
+        .load(&mut conn)
+        .await
+        .map_err(utils::diesel_error)?;
+    Ok(Json(TodoTags { todo, tags }))
+}
+
+/// Delete and return todo tags. The tags themselves are kept.
+///
+/// Example queries for deleting todo tags using this endpoint:
+/// - Untag the todo to
+/// - Remove todo tags for
+/// - Delete the tag from my task to
+#[utoipa::path(
+    post,
+    path = "/tags/delete",
+    extensions(
+        ("x-json-schema-body" = json!(schema_for!(TodoTagSearchParams)))
+    ),
+    request_body = TodoTagSearchParams,
+    responses(
+        (status = 200, description = "Successfully deleted todo tags", body = TodoTags),
+        (status = 400, description = "Default JSON elements configured by the user are invalid"),
+        (status = 404, description = "No todo or todo tags found"),
+        (status = 422, description = "Error when parsing a response from a model API"),
+        (status = 502, description = "Error when forwarding request to model APIs")
+    )
+)]
+#[axum::debug_handler]
+async fn delete_matching_todo_tags(
+    State(state): State<ToiState>,
+    user: CurrentUser,
+    Json(params): Json<TodoTagSearchParams>,
+) -> Result<Json<TodoTags>, ToiError> {
+    let mut conn = utils::get_conn(&state.pool).await?;
+    let (todo, ids) = search_todo_tags(&state, user.id, params, &mut conn).await?;
+    let todo_tags = conn
+        .transaction::<_, ToiError, _>(|mut conn| {
+            async move {
+                // Delete todo tag items.
+                let ids: Vec<i32> = diesel::delete(
+                    schema::todo_tags::table.filter(
+                        schema::todo_tags::todo_id
+                            .eq(todo.id)
+                            .and(schema::todo_tags::tag_id.eq_any(ids)),
+                    ),
+                )
+                .returning(schema::todo_tags::tag_id)
+                .load(&mut conn)
+                .await?;
+                // Return the actual tag objects.
+                let tags = schema::tags::table
+                    .select(Tag::as_select())
+                    .filter(schema::tags::id.eq_any(ids))
+                    .load(&mut conn)
+                    .await?;
+                Ok(TodoTags { todo, tags })
+            }
+            .scope_boxed()
+        })
+        .await?;
+    Ok(Json(todo_tags))
+}
+
+/// Get a todo using its database-generated ID. Todos in the trash aren't returned.
+#[utoipa::path(
+    get,
+    path = "/{id}",
+    params(
+        ("id" = i32, Path, description = "Database-generated todo ID")
+    ),
+    responses(
+        (status = 200, description = "Successfully got a todo", body = Todo),
+        (status = 404, description = "Todo not found")
+    )
+)]
+#[axum::debug_handler]
+async fn get_todo(
+    State(state): State<ToiState>,
+    user: CurrentUser,
+    Path(id): Path<i32>,
+) -> Result<Json<Todo>, ToiError> {
+    let mut conn = utils::get_conn(&state.pool).await?;
+    let todo = schema::todos::table
+        .select(Todo::as_select())
+        .filter(schema::todos::id.eq(id))
+        .filter(schema::todos::user_id.eq(user.id))
+        .filter(schema::todos::deleted_at.is_null())
+        .first(&mut conn)
+        .await
+        .map_err(utils::diesel_error)?;
+    Ok(Json(todo))
+}
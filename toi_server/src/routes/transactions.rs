@@ -1,23 +1,35 @@
-use axum::{extract::State, http::StatusCode, response::Json};
+use axum::{
+    extract::{Query, State},
+    http::{HeaderMap, StatusCode},
+    response::{IntoResponse, Json, Response},
+};
 use diesel::{ExpressionMethods, QueryDsl, SelectableHelper};
-use diesel_async::RunQueryDsl;
+use diesel_async::{AsyncConnection, RunQueryDsl, scoped_futures::ScopedFutureExt};
 use pgvector::VectorExpressionMethods;
 use schemars::schema_for;
 use utoipa_axum::{router::OpenApiRouter, routes};
 
 use crate::{
+    auth::CurrentUser,
+    embeddings::EmbeddedTable,
+    idempotency::IdempotencyKey,
     models::{
         accounts::{BankAccount, BankAccountSearchParams},
-        client::{EmbeddingPromptTemplate, EmbeddingRequest, RerankRequest},
+        client::{EmbeddingCache, EmbeddingRequest},
+        error::ToiError,
+        pagination::{Count, Page, SearchResponse},
         state::ToiState,
         transactions::{
             BankAccountHistory, BankAccountTransaction, BankAccountTransactionSearchParams,
             LinkedTransaction, NewBankAccountTransactionRequest, NewLinkedTransaction, Transaction,
-            TransactionSearchParams,
+            TransactionCategorySummary, TransactionSearchParams, TransactionSummaryParams,
         },
     },
+    ndjson,
     routes::accounts::search_bank_accounts,
-    schema, utils,
+    schema,
+    search::{self, RerankOptions},
+    utils,
 };
 
 // Prefixes are used for embedding instructions.
@@ -25,6 +37,9 @@ const INSTRUCTION_PREFIX: &str =
     "Instruction: Given a user query, find transactions stored with details that the user mentions";
 const QUERY_PREFIX: &str = "Query: ";
 
+// Transactions without a category are grouped under this name.
+const UNCATEGORIZED: &str = "uncategorized";
+
 pub fn bank_account_transactions_router(state: ToiState) -> OpenApiRouter {
     OpenApiRouter::new()
         .routes(routes!(add_bank_account_transaction))
@@ -37,12 +52,24 @@ pub fn transactions_router(state: ToiState) -> OpenApiRouter {
     OpenApiRouter::new()
         .routes(routes!(delete_matching_transactions))
         .routes(routes!(get_matching_transactions))
+        .routes(routes!(get_transaction_summary))
         .with_state(state)
 }
 
+/// Categories are matched exactly, so they're trimmed and lowercased
+/// before they're stored or compared. Empty categories and the name used
+/// for grouping transactions without a category are treated as missing.
+fn normalize_category(category: Option<String>) -> Option<String> {
+    category
+        .map(|category| category.trim().to_lowercase())
+        .filter(|category| !category.is_empty() && category != UNCATEGORIZED)
+}
+
 pub async fn search_bank_account_transactions(
     state: &ToiState,
+    user_id: i32,
     params: BankAccountTransactionSearchParams,
+    embeddings: &mut EmbeddingCache,
     conn: &mut utils::Conn<'_>,
 ) -> Result<(BankAccount, Vec<i32>), (StatusCode, String)> {
     let BankAccountTransactionSearchParams {
@@ -59,6 +86,7 @@ pub async fn search_bank_account_transactions(
         transaction_posted_to,
         transaction_order_by,
         transaction_limit,
+        transaction_offset,
     } = params;
     let bank_account_query_params = BankAccountSearchParams {
         ids: bank_account_id.map(|i| vec![i]),
@@ -68,12 +96,15 @@ pub async fn search_bank_account_transactions(
         created_to: bank_account_created_to,
         order_by: bank_account_order_by,
         limit: Some(1),
+        offset: None,
     };
-    let bank_account_id = search_bank_accounts(state, bank_account_query_params, conn)
-        .await?
-        .into_iter()
-        .next()
-        .ok_or((StatusCode::NOT_FOUND, "bank account not found".to_string()))?;
+    let bank_account_id =
+        search_bank_accounts(state, user_id, bank_account_query_params, embeddings, conn)
+            .await?
+            .items
+            .into_iter()
+            .next()
+            .ok_or((StatusCode::NOT_FOUND, "bank account not found".to_string()))?;
     let bank_account = schema::bank_accounts::table
         .select(BankAccount::as_select())
         .filter(schema::bank_accounts::id.eq(bank_account_id))
@@ -88,18 +119,55 @@ pub async fn search_bank_account_transactions(
         use_reranking_filter: transaction_use_reranking_filter,
         posted_from: transaction_posted_from,
         posted_to: transaction_posted_to,
+        category: None,
         order_by: transaction_order_by,
         limit: transaction_limit,
+        offset: transaction_offset,
+        count_only: None,
     };
-    let transaction_ids = search_transactions(state, transaction_query_params, conn).await?;
+    let transaction_ids =
+        search_transactions(state, user_id, transaction_query_params, embeddings, conn)
+            .await?
+            .items;
     Ok((bank_account, transaction_ids))
 }
 
 pub async fn search_transactions(
     state: &ToiState,
+    user_id: i32,
+    params: TransactionSearchParams,
+    embeddings: &mut EmbeddingCache,
+    conn: &mut utils::Conn<'_>,
+) -> Result<Page<i32>, (StatusCode, String)> {
+    // The total is counted alongside a page's items, so it's counted on
+    // its own when the offset is past the end and there aren't any.
+    let count_params = (params.offset.is_some_and(|offset| offset > 0)
+        && !params.count_only.unwrap_or_default())
+    .then(|| TransactionSearchParams {
+        use_reranking_filter: None,
+        limit: None,
+        offset: None,
+        count_only: Some(true),
+        ..params.clone()
+    });
+    let mut page = search_transactions_page(state, user_id, params, embeddings, conn).await?;
+    if page.total == 0
+        && let Some(count_params) = count_params
+    {
+        page.total = search_transactions_page(state, user_id, count_params, embeddings, conn)
+            .await?
+            .total;
+    }
+    Ok(page)
+}
+
+async fn search_transactions_page(
+    state: &ToiState,
+    user_id: i32,
     params: TransactionSearchParams,
+    embeddings: &mut EmbeddingCache,
     conn: &mut utils::Conn<'_>,
-) -> Result<Vec<i32>, (StatusCode, String)> {
+) -> Result<Page<i32>, (StatusCode, String)> {
     let TransactionSearchParams {
         bank_account_id,
         ids,
@@ -107,12 +175,15 @@ pub async fn search_transactions(
         use_reranking_filter,
         posted_from,
         posted_to,
+        category,
         order_by,
         limit,
+        offset,
+        count_only,
     } = params;
 
     let mut sql_query = schema::transactions::table
-        .select(Transaction::as_select())
+        .select((Transaction::as_select(), utils::total_count()))
         .into_boxed();
 
     // Filter items based on parent ID.
@@ -130,6 +201,23 @@ pub async fn search_transactions(
         sql_query = sql_query.filter(schema::transactions::posted_at.le(posted_to));
     }
 
+    // Filter items by their exact category. Asking for uncategorized items
+    // matches items without a category.
+    if let Some(category) = category {
+        match normalize_category(Some(category)) {
+            Some(category) => {
+                sql_query = sql_query.filter(schema::transactions::category.eq(category));
+            }
+            None => {
+                sql_query = sql_query.filter(schema::transactions::category.is_null());
+            }
+        }
+    }
+
+    // Items are only ordered by their distance to the query if they aren't
+    // ordered some other way.
+    let ordered_by_distance = order_by.is_none();
+
     // Order items.
     match order_by {
         Some(utils::OrderBy::Oldest) => {
@@ -141,13 +229,15 @@ pub async fn search_transactions(
         None => {
             // By default, filter items similar to a given query.
             if let Some(ref query) = query {
-                let input = EmbeddingPromptTemplate::builder()
-                    .instruction_prefix(INSTRUCTION_PREFIX.to_string())
-                    .query_prefix(QUERY_PREFIX.to_string())
-                    .build()
+                let input = state
+                    .embedding_instructions
+                    .template("transactions", INSTRUCTION_PREFIX, QUERY_PREFIX)
                     .apply(query);
                 let embedding_request = EmbeddingRequest { input };
-                let embedding = state.model_client.embed(embedding_request).await?;
+                let embedding = state
+                    .model_client
+                    .embed_cached(embedding_request, embeddings)
+                    .await?;
                 sql_query = sql_query
                     .filter(
                         schema::transactions::embedding
@@ -164,37 +254,76 @@ pub async fn search_transactions(
         sql_query = sql_query.or_filter(schema::transactions::id.eq_any(ids));
     }
 
-    // Limit number of items.
-    if let Some(limit) = limit {
+    // Only the user's own items are searched. This comes after the other
+    // filters so items selected by their ids are still only the user's.
+    sql_query = sql_query.filter(schema::transactions::user_id.eq(user_id));
+
+    // Limit number of items. Only the total is needed when counting items
+    // that don't need to be reranked, so only one item is loaded. Items
+    // counted once they're reranked aren't limited so they're all counted.
+    let count_only = count_only.unwrap_or_default();
+    let count_total =
+        count_only && !search::needs_reranking(query.as_deref(), use_reranking_filter);
+    if count_total {
+        sql_query = sql_query.limit(1);
+    } else if let Some(limit) = limit.filter(|_| !count_only) {
         sql_query = sql_query.limit(limit);
     }
 
+    // Skip a number of items.
+    if let Some(offset) = offset.filter(|_| !count_only) {
+        sql_query = sql_query.offset(offset);
+    }
+
     // Get all the items that match the query.
-    let transactions: Vec<Transaction> = sql_query.load(conn).await.map_err(utils::diesel_error)?;
-    let (ids, documents): (Vec<i32>, Vec<String>) = transactions
+    let transactions: Vec<(Transaction, i64)> =
+        sql_query.load(conn).await.map_err(utils::diesel_error)?;
+    let total = transactions
+        .as_slice()
+        .first()
+        .map_or(0, |(_, total)| *total);
+    if count_total {
+        return Ok(Page::count(total));
+    }
+    let mut ids_docs: Vec<(i32, String)> = transactions
         .into_iter()
-        .map(|transaction| (transaction.id, transaction.description))
-        .unzip();
-    if ids.is_empty() {
-        return Ok(ids);
+        .map(|(transaction, _)| (transaction.id, transaction.description))
+        .collect();
+
+    // Items that aren't ordered by their distance to the query are put in
+    // that order so only the furthest ones are left out of reranking.
+    if !ordered_by_distance
+        && search::needs_reranking(query.as_deref(), use_reranking_filter)
+        && let Some(ref query) = query
+    {
+        let input = state
+            .embedding_instructions
+            .template("transactions", INSTRUCTION_PREFIX, QUERY_PREFIX)
+            .apply(query);
+        ids_docs = search::closest_first(state, EmbeddedTable::Transactions, input, ids_docs, conn)
+            .await?;
     }
 
     // Rerank and filter items once more.
-    let ids = match (query, use_reranking_filter) {
-        (Some(query), Some(true)) => {
-            let rerank_request = RerankRequest { query, documents };
-            let rerank_response = state.model_client.rerank(rerank_request).await?;
-            rerank_response
-                .results
-                .into_iter()
-                .filter(|item| item.relevance_score >= state.server_config.similarity_threshold)
-                .map(|item| ids[item.index])
-                .collect()
-        }
-        _ => ids,
-    };
+    let ids = search::rerank_filter(
+        state,
+        query,
+        use_reranking_filter,
+        ids_docs,
+        &RerankOptions::default(),
+    )
+    .await?;
+
+    if count_only {
+        return Ok(Page::count(ids.len().try_into().unwrap_or(i64::MAX)));
+    }
 
-    Ok(ids)
+    Ok(Page {
+        items: ids,
+        total,
+        offset,
+        limit,
+    })
 }
 
 /// Add and return a bank account transaction.
@@ -213,6 +342,7 @@ pub async fn search_transactions(
     responses(
         (status = 201, description = "Successfully added a transaction", body = BankAccountTransaction),
         (status = 400, description = "Default JSON elements configured by the user are invalid"),
+        (status = 409, description = "Idempotency key was already used for a different request"),
         (status = 422, description = "Error when parsing a response from a model API"),
         (status = 502, description = "Error when forwarding request to model APIs")
     )
@@ -220,9 +350,26 @@ pub async fn search_transactions(
 #[axum::debug_handler]
 async fn add_bank_account_transaction(
     State(state): State<ToiState>,
+    user: CurrentUser,
+    headers: HeaderMap,
     Json(params): Json<NewBankAccountTransactionRequest>,
 ) -> Result<Json<BankAccountTransaction>, (StatusCode, String)> {
-    let mut conn = state.pool.get().await.map_err(utils::internal_error)?;
+    let idempotency_key = IdempotencyKey::from_request(
+        &state,
+        &headers,
+        user.id,
+        "/banking/accounts/transactions",
+        &params,
+    )?;
+    let mut conn = utils::get_conn(&state.pool).await?;
+    // Replay repeats before searching for the bank account or embedding
+    // anything.
+    if let Some(key) = &idempotency_key
+        && let Some(bank_account_transaction) = key.replay(&mut conn).await?
+    {
+        return Ok(Json(bank_account_transaction));
+    }
+    let mut embeddings = EmbeddingCache::default();
     let NewBankAccountTransactionRequest {
         bank_account_id,
         bank_account_query,
@@ -233,6 +380,7 @@ async fn add_bank_account_transaction(
         transaction_description,
         transaction_amount,
         transaction_posted_at,
+        transaction_category,
     } = params;
     let bank_account_query_params = BankAccountSearchParams {
         ids: bank_account_id.map(|i| vec![i]),
@@ -242,12 +390,20 @@ async fn add_bank_account_transaction(
         created_to: bank_account_created_to,
         order_by: bank_account_order_by,
         limit: Some(1),
+        offset: None,
     };
-    let bank_account_id = search_bank_accounts(&state, bank_account_query_params, &mut conn)
-        .await?
-        .into_iter()
-        .next()
-        .ok_or((StatusCode::NOT_FOUND, "bank account not found".to_string()))?;
+    let bank_account_id = search_bank_accounts(
+        &state,
+        user.id,
+        bank_account_query_params,
+        &mut embeddings,
+        &mut conn,
+    )
+    .await?
+    .items
+    .into_iter()
+    .next()
+    .ok_or((StatusCode::NOT_FOUND, "bank account not found".to_string()))?;
     let bank_account = schema::bank_accounts::table
         .select(BankAccount::as_select())
         .filter(schema::bank_accounts::id.eq(bank_account_id))
@@ -259,22 +415,41 @@ async fn add_bank_account_transaction(
     };
     let embedding = state.model_client.embed(embedding_request).await?;
     let new_transaction = NewLinkedTransaction {
+        user_id: user.id,
         bank_account_id: bank_account.id,
         description: transaction_description,
         amount: transaction_amount,
         embedding,
         posted_at: transaction_posted_at,
+        category: normalize_category(transaction_category),
     };
-    let transaction = diesel::insert_into(schema::transactions::table)
-        .values(new_transaction)
-        .returning(Transaction::as_returning())
-        .get_result(&mut conn)
-        .await
-        .map_err(utils::diesel_error)?;
-    let bank_account_transaction = BankAccountTransaction {
-        bank_account,
-        transaction,
-    };
+    let bank_account_transaction = conn
+        .transaction::<_, ToiError, _>(|mut conn| {
+            async move {
+                // Check again in case a request with the same key added the
+                // transaction while this one was embedding it.
+                if let Some(key) = &idempotency_key
+                    && let Some(bank_account_transaction) = key.replay(&mut conn).await?
+                {
+                    return Ok(bank_account_transaction);
+                }
+                let transaction = diesel::insert_into(schema::transactions::table)
+                    .values(new_transaction)
+                    .returning(Transaction::as_returning())
+                    .get_result(&mut conn)
+                    .await?;
+                let bank_account_transaction = BankAccountTransaction {
+                    bank_account,
+                    transaction,
+                };
+                if let Some(key) = &idempotency_key {
+                    key.store(&bank_account_transaction, &mut conn).await?;
+                }
+                Ok(bank_account_transaction)
+            }
+            .scope_boxed()
+        })
+        .await?;
     Ok(Json(bank_account_transaction))
 }
 
@@ -295,7 +470,7 @@ async fn add_bank_account_transaction(
     responses(
         (status = 200, description = "Successfully deleted transactions", body = BankAccountHistory),
         (status = 400, description = "Default JSON elements configured by the user are invalid"),
-        (status = 404, description = "No bank account or transactions found"),
+        (status = 404, description = "Bank account not found"),
         (status = 422, description = "Error when parsing a response from a model API"),
         (status = 502, description = "Error when forwarding request to model APIs")
     )
@@ -303,11 +478,14 @@ async fn add_bank_account_transaction(
 #[axum::debug_handler]
 async fn delete_matching_bank_account_transactions(
     State(state): State<ToiState>,
+    user: CurrentUser,
     Json(params): Json<BankAccountTransactionSearchParams>,
 ) -> Result<Json<BankAccountHistory>, (StatusCode, String)> {
-    let mut conn = state.pool.get().await.map_err(utils::internal_error)?;
+    let mut conn = utils::get_conn(&state.pool).await?;
+    let mut embeddings = EmbeddingCache::default();
     let (bank_account, transaction_ids) =
-        search_bank_account_transactions(&state, params, &mut conn).await?;
+        search_bank_account_transactions(&state, user.id, params, &mut embeddings, &mut conn)
+            .await?;
     let transactions = diesel::delete(schema::transactions::table)
         .filter(schema::transactions::id.eq_any(transaction_ids))
         .returning(Transaction::as_returning())
@@ -338,7 +516,6 @@ async fn delete_matching_bank_account_transactions(
     responses(
         (status = 200, description = "Successfully deleted transactions", body = [LinkedTransaction]),
         (status = 400, description = "Default JSON elements configured by the user are invalid"),
-        (status = 404, description = "No transactions found"),
         (status = 422, description = "Error when parsing a response from a model API"),
         (status = 502, description = "Error when forwarding request to model APIs")
     )
@@ -346,10 +523,20 @@ async fn delete_matching_bank_account_transactions(
 #[axum::debug_handler]
 async fn delete_matching_transactions(
     State(state): State<ToiState>,
+    user: CurrentUser,
     Json(params): Json<TransactionSearchParams>,
 ) -> Result<Json<Vec<LinkedTransaction>>, (StatusCode, String)> {
-    let mut conn = state.pool.get().await.map_err(utils::internal_error)?;
-    let transaction_ids = search_transactions(&state, params, &mut conn).await?;
+    let mut conn = utils::get_conn(&state.pool).await?;
+    let mut embeddings = EmbeddingCache::default();
+
+    // Items are always needed here, so counting is ignored.
+    let params = TransactionSearchParams {
+        count_only: None,
+        ..params
+    };
+    let transaction_ids = search_transactions(&state, user.id, params, &mut embeddings, &mut conn)
+        .await?
+        .items;
     let linked_transactions = diesel::delete(schema::transactions::table)
         .filter(schema::transactions::id.eq_any(transaction_ids))
         .returning(LinkedTransaction::as_returning())
@@ -376,7 +563,7 @@ async fn delete_matching_transactions(
     responses(
         (status = 200, description = "Successfully got transactions", body = BankAccountHistory),
         (status = 400, description = "Default JSON elements configured by the user are invalid"),
-        (status = 404, description = "No bank account or transactions found"),
+        (status = 404, description = "Bank account not found"),
         (status = 422, description = "Error when parsing a response from a model API"),
         (status = 502, description = "Error when forwarding request to model APIs")
     )
@@ -384,11 +571,14 @@ async fn delete_matching_transactions(
 #[axum::debug_handler]
 async fn get_matching_bank_account_transactions(
     State(state): State<ToiState>,
+    user: CurrentUser,
     Json(params): Json<BankAccountTransactionSearchParams>,
 ) -> Result<Json<BankAccountHistory>, (StatusCode, String)> {
-    let mut conn = state.pool.get().await.map_err(utils::internal_error)?;
+    let mut conn = utils::get_conn(&state.pool).await?;
+    let mut embeddings = EmbeddingCache::default();
     let (bank_account, transaction_ids) =
-        search_bank_account_transactions(&state, params, &mut conn).await?;
+        search_bank_account_transactions(&state, user.id, params, &mut embeddings, &mut conn)
+            .await?;
     let transactions = schema::transactions::table
         .select(Transaction::as_select())
         .filter(schema::transactions::id.eq_any(transaction_ids))
@@ -404,6 +594,9 @@ async fn get_matching_bank_account_transactions(
 
 /// Get transactions.
 ///
+/// Transactions are streamed as newline-delimited JSON, one transaction per
+/// line, if the `Accept` header asks for `application/x-ndjson`.
+///
 /// Example queries for getting transactions using this endpoint:
 /// - Get all transactions where
 /// - List all transactions
@@ -417,9 +610,11 @@ async fn get_matching_bank_account_transactions(
     ),
     request_body = TransactionSearchParams,
     responses(
-        (status = 200, description = "Successfully got transactions", body = [LinkedTransaction]),
+        (status = 200, description = "Successfully got transactions or their count", content(
+            (SearchResponse<LinkedTransaction> = "application/json"),
+            (LinkedTransaction = "application/x-ndjson")
+        )),
         (status = 400, description = "Default JSON elements configured by the user are invalid"),
-        (status = 404, description = "No transactions found"),
         (status = 422, description = "Error when parsing a response from a model API"),
         (status = 502, description = "Error when forwarding request to model APIs")
     )
@@ -427,15 +622,150 @@ async fn get_matching_bank_account_transactions(
 #[axum::debug_handler]
 async fn get_matching_transactions(
     State(state): State<ToiState>,
+    user: CurrentUser,
+    headers: HeaderMap,
     Json(params): Json<TransactionSearchParams>,
-) -> Result<Json<Vec<LinkedTransaction>>, (StatusCode, String)> {
-    let mut conn = state.pool.get().await.map_err(utils::internal_error)?;
-    let transaction_ids = search_transactions(&state, params, &mut conn).await?;
-    let linked_transactions = schema::transactions::table
+) -> Result<Response, (StatusCode, String)> {
+    let count_only = params.count_only.unwrap_or_default();
+    let mut conn = utils::get_conn(&state.pool).await?;
+    let mut embeddings = EmbeddingCache::default();
+    let Page {
+        items: transaction_ids,
+        total,
+        offset,
+        limit,
+    } = search_transactions(&state, user.id, params, &mut embeddings, &mut conn).await?;
+    if count_only {
+        return Ok(Json(SearchResponse::<LinkedTransaction>::Count(Count {
+            count: total,
+        }))
+        .into_response());
+    }
+    if ndjson::is_accepted(&headers) {
+        return Ok(ndjson::stream_items(
+            state.pool.clone(),
+            transaction_ids,
+            load_transactions,
+            |linked_transaction| linked_transaction.id,
+        ));
+    }
+    let mut linked_transactions: Vec<LinkedTransaction> = schema::transactions::table
         .select(LinkedTransaction::as_select())
-        .filter(schema::transactions::id.eq_any(transaction_ids))
+        .filter(schema::transactions::id.eq_any(&transaction_ids))
         .load(&mut conn)
         .await
         .map_err(utils::diesel_error)?;
-    Ok(Json(linked_transactions))
+
+    // Keep transactions in the order they were searched in so they match
+    // streamed results.
+    linked_transactions.sort_by_key(|linked_transaction| {
+        transaction_ids
+            .iter()
+            .position(|id| *id == linked_transaction.id)
+    });
+    Ok(Json(SearchResponse::Page(Page {
+        items: linked_transactions,
+        total,
+        offset,
+        limit,
+    }))
+    .into_response())
+}
+
+/// Load a batch of transactions for streaming search results.
+async fn load_transactions(
+    pool: utils::Pool,
+    ids: Vec<i32>,
+) -> Result<Vec<LinkedTransaction>, ToiError> {
+    let mut conn = utils::get_conn(&pool).await?;
+    schema::transactions::table
+        .select(LinkedTransaction::as_select())
+        .filter(schema::transactions::id.eq_any(ids))
+        .load(&mut conn)
+        .await
+        .map_err(utils::diesel_error)
+}
+
+/// Get total spending per transaction category.
+///
+/// Example queries for summarizing transactions using this endpoint:
+/// - How much did I spend on groceries?
+/// - How much did I spend on dining last month?
+/// - What did I spend the most on this year?
+/// - Break down my spending by category
+#[utoipa::path(
+    get,
+    path = "/summary",
+    extensions(
+        ("x-json-schema-params" = json!(schema_for!(TransactionSummaryParams)))
+    ),
+    params(TransactionSummaryParams),
+    responses(
+        (status = 200, description = "Successfully summarized transactions", body = Vec<TransactionCategorySummary>),
+    )
+)]
+#[axum::debug_handler]
+async fn get_transaction_summary(
+    State(state): State<ToiState>,
+    user: CurrentUser,
+    Query(params): Query<TransactionSummaryParams>,
+) -> Result<Json<Vec<TransactionCategorySummary>>, (StatusCode, String)> {
+    let mut conn = utils::get_conn(&state.pool).await?;
+    let TransactionSummaryParams {
+        category,
+        posted_from,
+        posted_to,
+    } = params;
+
+    // Aggregate transactions in SQL rather than loading them all.
+    let mut sql_query = schema::transactions::table
+        .group_by(schema::transactions::category)
+        .select((
+            schema::transactions::category,
+            diesel::dsl::sum(schema::transactions::amount),
+            diesel::dsl::count(schema::transactions::id),
+        ))
+        .order(schema::transactions::category)
+        .into_boxed();
+
+    // Only the user's own items are summarized.
+    sql_query = sql_query.filter(schema::transactions::user_id.eq(user.id));
+
+    // Filter items by their exact category.
+    if let Some(category) = category {
+        match normalize_category(Some(category)) {
+            Some(category) => {
+                sql_query = sql_query.filter(schema::transactions::category.eq(category));
+            }
+            None => {
+                sql_query = sql_query.filter(schema::transactions::category.is_null());
+            }
+        }
+    }
+
+    // Filter items posted on or after date.
+    if let Some(posted_from) = posted_from {
+        sql_query = sql_query.filter(schema::transactions::posted_at.ge(posted_from));
+    }
+
+    // Filter items posted on or before date.
+    if let Some(posted_to) = posted_to {
+        sql_query = sql_query.filter(schema::transactions::posted_at.le(posted_to));
+    }
+
+    let summaries: Vec<(Option<String>, Option<f32>, i64)> = sql_query
+        .load(&mut conn)
+        .await
+        .map_err(utils::diesel_error)?;
+    let summaries = summaries
+        .into_iter()
+        .map(
+            |(category, total, transaction_count)| TransactionCategorySummary {
+                category: category.unwrap_or_else(|| UNCATEGORIZED.to_string()),
+                total: total.unwrap_or_default(),
+                transaction_count,
+            },
+        )
+        .collect();
+    Ok(Json(summaries))
 }
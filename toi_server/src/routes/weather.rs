@@ -3,52 +3,53 @@ use axum::{
     http::StatusCode,
     response::Json,
 };
+use chrono::{TimeDelta, Utc};
+use diesel::{ExpressionMethods, OptionalExtension, QueryDsl, SelectableHelper};
+use diesel_async::RunQueryDsl;
 use schemars::schema_for;
-use serde_json::json;
+use tracing::debug;
 use utoipa_axum::{router::OpenApiRouter, routes};
 
-use crate::models::{
-    client::ApiClientError,
-    state::ToiState,
-    weather::{
-        GeocodingResult, GridpointForecast, Point, WeatherAlerts, WeatherQueryParams, ZoneForecast,
+use crate::{
+    models::{
+        client::ApiClientError,
+        state::ToiState,
+        weather::{
+            GeocodeCacheEntry, GridpointForecast, GridpointForecastSummary, HourlyForecast, Point,
+            WeatherAlerts, WeatherAlertsSummary, WeatherQueryParams, WeatherResponse, ZoneForecast,
+            ZoneForecastSummary,
+        },
     },
+    schema, utils,
 };
 
 pub fn weather_router(state: ToiState) -> OpenApiRouter {
     OpenApiRouter::new()
         .routes(routes!(get_weather_alerts))
         .routes(routes!(get_gridpoint_weather_forecast))
+        .routes(routes!(get_hourly_weather_forecast))
         .routes(routes!(get_zone_weather_forecast))
         .with_state(state)
 }
 
-pub async fn geocode(
-    params: &WeatherQueryParams,
+/// Normalize free-form queries so trivially different spellings of the
+/// same area share a cache entry.
+fn normalize_query(query: &str) -> String {
+    query
+        .split_whitespace()
+        .collect::<Vec<&str>>()
+        .join(" ")
+        .to_lowercase()
+}
+
+/// Geocode a query and look up its NWS point metadata using live APIs.
+pub async fn lookup_point(
+    query: String,
+    geocoding_url: &str,
     client: &reqwest::Client,
-) -> Result<Point, (StatusCode, String)> {
+) -> Result<GeocodeCacheEntry, (StatusCode, String)> {
     // Get latitude/longitude by geocoding the given query.
-    let geocoding_params = json!(
-        {
-            "q": params.query,
-            "format": "json"
-        }
-    );
-    let mut results = client
-        .get("https://nominatim.openstreetmap.org/search")
-        .query(&geocoding_params)
-        .send()
-        .await
-        .map_err(|err| ApiClientError::ApiConnection.into_response(&err))?
-        .json::<Vec<GeocodingResult>>()
-        .await
-        .map_err(|err| ApiClientError::ResponseJson.into_response(&err))?;
-    if results.is_empty() {
-        let err = format!("couldn't geocode {}", params.query);
-        return Err(ApiClientError::EmptyResponse.into_response(&err));
-    }
-    let most_relevant_result = results.swap_remove(0);
-    let (latitude, longitude) = (most_relevant_result.lat, most_relevant_result.lon);
+    let (latitude, longitude) = utils::geocode(&query, geocoding_url, client).await?;
 
     // Get the NWS point from latitude/longitude.
     let point = client
@@ -62,7 +63,74 @@ pub async fn geocode(
         .await
         .map_err(|err| ApiClientError::ResponseJson.into_response(&err))?;
 
-    Ok(point)
+    Ok(GeocodeCacheEntry {
+        query,
+        latitude,
+        longitude,
+        forecast: point.properties.forecast,
+        forecast_zone: point.properties.forecast_zone,
+        updated_at: Utc::now(),
+        forecast_hourly: point.properties.forecast_hourly,
+    })
+}
+
+/// Get NWS point metadata for a query, preferring cached results that are
+/// newer than the TTL and falling back to the given lookup otherwise. Fresh
+/// lookups are saved to the cache.
+pub async fn geocode_with_cache<F, Fut>(
+    query: &str,
+    bypass_cache: bool,
+    ttl: TimeDelta,
+    conn: &mut utils::Conn<'_>,
+    lookup: F,
+) -> Result<Point, (StatusCode, String)>
+where
+    F: FnOnce(String) -> Fut,
+    Fut: Future<Output = Result<GeocodeCacheEntry, (StatusCode, String)>>,
+{
+    let query = normalize_query(query);
+    if !bypass_cache {
+        let entry = schema::geocode_cache::table
+            .select(GeocodeCacheEntry::as_select())
+            .filter(schema::geocode_cache::query.eq(&query))
+            .filter(schema::geocode_cache::updated_at.gt(Utc::now() - ttl))
+            .first(conn)
+            .await
+            .optional()
+            .map_err(utils::diesel_error)?;
+        if let Some(entry) = entry {
+            debug!("geocode cache hit for query='{query}'");
+            return Ok(Point::from(entry));
+        }
+    }
+
+    debug!("geocode cache miss for query='{query}'");
+    let entry = lookup(query).await?;
+    diesel::insert_into(schema::geocode_cache::table)
+        .values(&entry)
+        .on_conflict(schema::geocode_cache::query)
+        .do_update()
+        .set(&entry)
+        .execute(conn)
+        .await
+        .map_err(utils::diesel_error)?;
+    Ok(Point::from(entry))
+}
+
+pub async fn geocode(
+    state: &ToiState,
+    params: &WeatherQueryParams,
+) -> Result<Point, (StatusCode, String)> {
+    let mut conn = utils::get_conn(&state.pool).await?;
+    let ttl = TimeDelta::days(state.server_config.geocode_cache_ttl_days.into());
+    geocode_with_cache(
+        &params.query,
+        params.bypass_cache.unwrap_or_default(),
+        ttl,
+        &mut conn,
+        |query| lookup_point(query, &state.server_config.geocoding_url, &state.api_client),
+    )
+    .await
 }
 
 /// Get weather alerts for an area.
@@ -79,7 +147,7 @@ pub async fn geocode(
     ),
     params(WeatherQueryParams),
     responses(
-        (status = 200, description = "Successfully got weather alerts", body = [WeatherAlerts]),
+        (status = 200, description = "Successfully got weather alerts", body = WeatherResponse<WeatherAlertsSummary, WeatherAlerts>),
         (status = 400, description = "Default JSON elements configured by the user are invalid"),
         (status = 404, description = "Forecast zone not found"),
         (status = 422, description = "Error when parsing a response from a model API"),
@@ -88,11 +156,11 @@ pub async fn geocode(
 )]
 #[axum::debug_handler]
 async fn get_weather_alerts(
-    State(client): State<reqwest::Client>,
+    State(state): State<ToiState>,
     Query(params): Query<WeatherQueryParams>,
-) -> Result<Json<WeatherAlerts>, (StatusCode, String)> {
+) -> Result<Json<WeatherResponse<WeatherAlertsSummary, WeatherAlerts>>, (StatusCode, String)> {
     // Get metadata about the latitude/longitude point.
-    let point = geocode(&params, &client).await?;
+    let point = geocode(&state, &params).await?;
 
     // Get the forecast zone and the weather alerts for that zone
     // from the returned metadata.
@@ -103,7 +171,8 @@ async fn get_weather_alerts(
         .next_back()
         .ok_or((StatusCode::NOT_FOUND, "forecast zone not found".to_string()))?;
     let url = format!("https://api.weather.gov/alerts/active/zone/{zone_id}");
-    let alerts = client
+    let alerts = state
+        .api_client
         .get(url)
         .send()
         .await
@@ -111,7 +180,7 @@ async fn get_weather_alerts(
         .json::<WeatherAlerts>()
         .await
         .map_err(|err| ApiClientError::ResponseJson.into_response(&err))?;
-    Ok(Json(alerts))
+    Ok(Json(WeatherResponse::new(alerts, params.verbose)))
 }
 
 /// Get a detailed weather forecast for an area.
@@ -128,7 +197,7 @@ async fn get_weather_alerts(
     ),
     params(WeatherQueryParams),
     responses(
-        (status = 200, description = "Successfully got gridpoint weather forecast", body = [GridpointForecast]),
+        (status = 200, description = "Successfully got gridpoint weather forecast", body = WeatherResponse<GridpointForecastSummary, GridpointForecast>),
         (status = 400, description = "Default JSON elements configured by the user are invalid"),
         (status = 422, description = "Error when parsing a response from a model API"),
         (status = 502, description = "Error when forwarding request to model APIs")
@@ -136,14 +205,16 @@ async fn get_weather_alerts(
 )]
 #[axum::debug_handler]
 async fn get_gridpoint_weather_forecast(
-    State(client): State<reqwest::Client>,
+    State(state): State<ToiState>,
     Query(params): Query<WeatherQueryParams>,
-) -> Result<Json<GridpointForecast>, (StatusCode, String)> {
+) -> Result<Json<WeatherResponse<GridpointForecastSummary, GridpointForecast>>, (StatusCode, String)>
+{
     // Get metadata about the latitude/longitude point.
-    let point = geocode(&params, &client).await?;
+    let point = geocode(&state, &params).await?;
 
     // Get weather forecast from the returned metadata.
-    let forecast = client
+    let forecast = state
+        .api_client
         .get(point.properties.forecast)
         .send()
         .await
@@ -151,7 +222,61 @@ async fn get_gridpoint_weather_forecast(
         .json::<GridpointForecast>()
         .await
         .map_err(|err| ApiClientError::ResponseJson.into_response(&err))?;
-    Ok(Json(forecast))
+    Ok(Json(WeatherResponse::new(forecast, params.verbose)))
+}
+
+/// Get an hourly weather forecast for an area.
+///
+/// Example queries for getting an hourly weather forecast from this endpoint:
+/// - What's the weather at 6
+/// - Hourly forecast for
+/// - Will it rain at 6pm
+/// - What's the temperature going to be this afternoon
+#[utoipa::path(
+    get,
+    path = "/forecast/hourly",
+    extensions(
+        ("x-json-schema-params" = json!(schema_for!(WeatherQueryParams)))
+    ),
+    params(WeatherQueryParams),
+    responses(
+        (status = 200, description = "Successfully got hourly weather forecast", body = WeatherResponse<GridpointForecastSummary, HourlyForecast>),
+        (status = 400, description = "Default JSON elements configured by the user are invalid"),
+        (status = 422, description = "Error when parsing a response from a model API"),
+        (status = 502, description = "Error when forwarding request to model APIs")
+    )
+)]
+#[axum::debug_handler]
+async fn get_hourly_weather_forecast(
+    State(state): State<ToiState>,
+    Query(params): Query<WeatherQueryParams>,
+) -> Result<Json<WeatherResponse<GridpointForecastSummary, HourlyForecast>>, (StatusCode, String)> {
+    // Get metadata about the latitude/longitude point.
+    let point = geocode(&state, &params).await?;
+
+    // Get the hourly weather forecast from the returned metadata. Some areas
+    // don't have hourly forecasts, so the regular forecast is used instead.
+    let (url, note) = match point.properties.forecast_hourly {
+        Some(forecast_hourly) => (forecast_hourly, None),
+        None => (
+            point.properties.forecast,
+            Some("An hourly forecast isn't available for this area, so this is the regular forecast instead.".to_string()),
+        ),
+    };
+    let mut forecast = state
+        .api_client
+        .get(url)
+        .send()
+        .await
+        .map_err(|err| ApiClientError::ApiConnection.into_response(&err))?
+        .json::<GridpointForecast>()
+        .await
+        .map_err(|err| ApiClientError::ResponseJson.into_response(&err))?;
+    forecast.retain_within(params.from, params.to);
+    Ok(Json(WeatherResponse::new(
+        HourlyForecast { note, forecast },
+        params.verbose,
+    )))
 }
 
 /// Get a high-level weather forecast for a broad area.
@@ -167,7 +292,7 @@ async fn get_gridpoint_weather_forecast(
     ),
     params(WeatherQueryParams),
     responses(
-        (status = 200, description = "Successfully got zone weather forecast", body = [ZoneForecast]),
+        (status = 200, description = "Successfully got zone weather forecast", body = WeatherResponse<ZoneForecastSummary, ZoneForecast>),
         (status = 400, description = "Default JSON elements configured by the user are invalid"),
         (status = 422, description = "Error when parsing a response from a model API"),
         (status = 502, description = "Error when forwarding request to model APIs")
@@ -175,14 +300,15 @@ async fn get_gridpoint_weather_forecast(
 )]
 #[axum::debug_handler]
 async fn get_zone_weather_forecast(
-    State(client): State<reqwest::Client>,
+    State(state): State<ToiState>,
     Query(params): Query<WeatherQueryParams>,
-) -> Result<Json<ZoneForecast>, (StatusCode, String)> {
+) -> Result<Json<WeatherResponse<ZoneForecastSummary, ZoneForecast>>, (StatusCode, String)> {
     // Get metadata about the latitude/longitude point.
-    let point = geocode(&params, &client).await?;
+    let point = geocode(&state, &params).await?;
 
     // Get weather forecast from the returned metadata.
-    let forecast = client
+    let forecast = state
+        .api_client
         .get(format!("{}/forecast", point.properties.forecast_zone))
         .send()
         .await
@@ -190,5 +316,5 @@ async fn get_zone_weather_forecast(
         .json::<ZoneForecast>()
         .await
         .map_err(|err| ApiClientError::ResponseJson.into_response(&err))?;
-    Ok(Json(forecast))
+    Ok(Json(WeatherResponse::new(forecast, params.verbose)))
 }
@@ -9,6 +9,56 @@ diesel::table! {
         description -> Text,
         embedding -> Vector,
         created_at -> Timestamptz,
+        user_id -> Int4,
+    }
+}
+
+diesel::table! {
+    use diesel::sql_types::*;
+    use pgvector::sql_types::*;
+
+    chat_responses (id) {
+        id -> Text,
+        content -> Text,
+        complete -> Bool,
+        created_at -> Timestamptz,
+        user_id -> Int4,
+    }
+}
+
+diesel::table! {
+    use diesel::sql_types::*;
+    use pgvector::sql_types::*;
+
+    contact_avatars (contact_id) {
+        contact_id -> Int4,
+        content_type -> Text,
+        data -> Bytea,
+        updated_at -> Timestamptz,
+    }
+}
+
+diesel::table! {
+    use diesel::sql_types::*;
+    use pgvector::sql_types::*;
+
+    contact_emails (id) {
+        id -> Int4,
+        contact_id -> Int4,
+        label -> Text,
+        value -> Text,
+    }
+}
+
+diesel::table! {
+    use diesel::sql_types::*;
+    use pgvector::sql_types::*;
+
+    contact_phones (id) {
+        id -> Int4,
+        contact_id -> Int4,
+        label -> Text,
+        value -> Text,
     }
 }
 
@@ -26,6 +76,33 @@ diesel::table! {
         relationship -> Nullable<Text>,
         embedding -> Vector,
         created_at -> Timestamptz,
+        user_id -> Int4,
+    }
+}
+
+diesel::table! {
+    use diesel::sql_types::*;
+    use pgvector::sql_types::*;
+
+    conversation_messages (id) {
+        id -> Int4,
+        conversation_id -> Int4,
+        sequence -> Int4,
+        role -> Text,
+        content -> Text,
+        created_at -> Timestamptz,
+    }
+}
+
+diesel::table! {
+    use diesel::sql_types::*;
+    use pgvector::sql_types::*;
+
+    conversations (id) {
+        id -> Int4,
+        title -> Nullable<Text>,
+        created_at -> Timestamptz,
+        user_id -> Int4,
     }
 }
 
@@ -50,6 +127,59 @@ diesel::table! {
         created_at -> Timestamptz,
         starts_at -> Timestamptz,
         ends_at -> Timestamptz,
+        recurrence_frequency -> Nullable<Text>,
+        recurrence_interval -> Nullable<Int4>,
+        recurrence_until -> Nullable<Timestamptz>,
+        place_id -> Nullable<Int4>,
+        user_id -> Int4,
+    }
+}
+
+diesel::table! {
+    use diesel::sql_types::*;
+    use pgvector::sql_types::*;
+
+    generation_audit (id) {
+        id -> Int4,
+        purpose -> Text,
+        system_prompt_hash -> Text,
+        api_path -> Nullable<Text>,
+        api_method -> Nullable<Text>,
+        rerank_score -> Nullable<Float8>,
+        output -> Text,
+        prompt_tokens -> Nullable<Int4>,
+        completion_tokens -> Nullable<Int4>,
+        latency_ms -> Int4,
+        created_at -> Timestamptz,
+        user_id -> Int4,
+    }
+}
+
+diesel::table! {
+    use diesel::sql_types::*;
+    use pgvector::sql_types::*;
+
+    geocode_cache (query) {
+        query -> Text,
+        latitude -> Float8,
+        longitude -> Float8,
+        forecast -> Text,
+        forecast_zone -> Text,
+        updated_at -> Timestamptz,
+        forecast_hourly -> Nullable<Text>,
+    }
+}
+
+diesel::table! {
+    use diesel::sql_types::*;
+    use pgvector::sql_types::*;
+
+    idempotency_keys (key, endpoint) {
+        key -> Text,
+        endpoint -> Text,
+        request_hash -> Text,
+        response_body -> Text,
+        created_at -> Timestamptz,
     }
 }
 
@@ -63,6 +193,40 @@ diesel::table! {
         title -> Nullable<Text>,
         url -> Nullable<Text>,
         updated_at -> Nullable<Timestamptz>,
+        source -> Nullable<Text>,
+    }
+}
+
+diesel::table! {
+    use diesel::sql_types::*;
+    use pgvector::sql_types::*;
+
+    news_feeds (name) {
+        name -> Text,
+        url -> Text,
+        is_default -> Bool,
+    }
+}
+
+diesel::table! {
+    use diesel::sql_types::*;
+    use pgvector::sql_types::*;
+
+    note_chunks (note_id, chunk_index) {
+        note_id -> Int4,
+        chunk_index -> Int4,
+        content -> Text,
+        embedding -> Vector,
+    }
+}
+
+diesel::table! {
+    use diesel::sql_types::*;
+    use pgvector::sql_types::*;
+
+    note_tags (note_id, tag_id) {
+        note_id -> Int4,
+        tag_id -> Int4,
     }
 }
 
@@ -75,6 +239,11 @@ diesel::table! {
         content -> Text,
         embedding -> Vector,
         created_at -> Timestamptz,
+        deleted_at -> Nullable<Timestamptz>,
+        pinned -> Bool,
+        archived_at -> Nullable<Timestamptz>,
+        expires_at -> Nullable<Timestamptz>,
+        user_id -> Int4,
     }
 }
 
@@ -92,6 +261,21 @@ diesel::table! {
     }
 }
 
+diesel::table! {
+    use diesel::sql_types::*;
+    use pgvector::sql_types::*;
+
+    pending_actions (id) {
+        id -> Int4,
+        conversation_id -> Nullable<Int4>,
+        message_hash -> Text,
+        request -> Jsonb,
+        description -> Text,
+        created_at -> Timestamptz,
+        user_id -> Int4,
+    }
+}
+
 diesel::table! {
     use diesel::sql_types::*;
     use pgvector::sql_types::*;
@@ -104,6 +288,9 @@ diesel::table! {
         phone -> Nullable<Text>,
         embedding -> Vector,
         created_at -> Timestamptz,
+        latitude -> Nullable<Float8>,
+        longitude -> Nullable<Float8>,
+        user_id -> Int4,
     }
 }
 
@@ -128,6 +315,7 @@ diesel::table! {
         instructions -> Text,
         embedding -> Vector,
         created_at -> Timestamptz,
+        user_id -> Int4,
     }
 }
 
@@ -151,6 +339,18 @@ diesel::table! {
         id -> Int4,
         name -> Text,
         embedding -> Vector,
+        created_at -> Timestamptz,
+        user_id -> Int4,
+    }
+}
+
+diesel::table! {
+    use diesel::sql_types::*;
+    use pgvector::sql_types::*;
+
+    todo_tags (todo_id, tag_id) {
+        todo_id -> Int4,
+        tag_id -> Int4,
     }
 }
 
@@ -165,6 +365,12 @@ diesel::table! {
         created_at -> Timestamptz,
         due_at -> Nullable<Timestamptz>,
         completed_at -> Nullable<Timestamptz>,
+        priority -> Nullable<Int2>,
+        recurrence_days -> Nullable<Int4>,
+        last_completed_at -> Nullable<Timestamptz>,
+        deleted_at -> Nullable<Timestamptz>,
+        event_id -> Nullable<Int4>,
+        user_id -> Int4,
     }
 }
 
@@ -179,29 +385,82 @@ diesel::table! {
         amount -> Float4,
         embedding -> Vector,
         posted_at -> Timestamptz,
+        category -> Nullable<Text>,
+        user_id -> Int4,
+    }
+}
+
+diesel::table! {
+    use diesel::sql_types::*;
+    use pgvector::sql_types::*;
+
+    users (id) {
+        id -> Int4,
+        name -> Text,
+        created_at -> Timestamptz,
     }
 }
 
+diesel::joinable!(bank_accounts -> users (user_id));
+diesel::joinable!(chat_responses -> users (user_id));
+diesel::joinable!(contact_avatars -> contacts (contact_id));
+diesel::joinable!(contact_emails -> contacts (contact_id));
+diesel::joinable!(contact_phones -> contacts (contact_id));
+diesel::joinable!(contacts -> users (user_id));
+diesel::joinable!(conversation_messages -> conversations (conversation_id));
+diesel::joinable!(conversations -> users (user_id));
 diesel::joinable!(event_attendees -> contacts (contact_id));
 diesel::joinable!(event_attendees -> events (event_id));
+diesel::joinable!(events -> places (place_id));
+diesel::joinable!(events -> users (user_id));
+diesel::joinable!(generation_audit -> users (user_id));
+diesel::joinable!(note_chunks -> notes (note_id));
+diesel::joinable!(note_tags -> notes (note_id));
+diesel::joinable!(note_tags -> tags (tag_id));
+diesel::joinable!(notes -> users (user_id));
+diesel::joinable!(pending_actions -> conversations (conversation_id));
+diesel::joinable!(pending_actions -> users (user_id));
+diesel::joinable!(places -> users (user_id));
 diesel::joinable!(recipe_tags -> recipes (recipe_id));
 diesel::joinable!(recipe_tags -> tags (tag_id));
+diesel::joinable!(recipes -> users (user_id));
 diesel::joinable!(searchable_openapi -> openapi (parent_id));
+diesel::joinable!(tags -> users (user_id));
+diesel::joinable!(todo_tags -> tags (tag_id));
+diesel::joinable!(todo_tags -> todos (todo_id));
+diesel::joinable!(todos -> events (event_id));
+diesel::joinable!(todos -> users (user_id));
 diesel::joinable!(transactions -> bank_accounts (bank_account_id));
+diesel::joinable!(transactions -> users (user_id));
 
 diesel::allow_tables_to_appear_in_same_query!(
     bank_accounts,
+    chat_responses,
+    contact_avatars,
+    contact_emails,
+    contact_phones,
     contacts,
+    conversation_messages,
+    conversations,
     event_attendees,
     events,
+    generation_audit,
+    geocode_cache,
+    idempotency_keys,
     news,
+    news_feeds,
+    note_chunks,
+    note_tags,
     notes,
     openapi,
+    pending_actions,
     places,
     recipe_tags,
     recipes,
     searchable_openapi,
     tags,
+    todo_tags,
     todos,
     transactions,
+    users,
 );
@@ -0,0 +1,341 @@
+use bon::Builder;
+use tracing::warn;
+
+use crate::{
+    embeddings::EmbeddedTable,
+    models::{
+        client::{ApiClientError, EmbeddingRequest, RerankRequest, RerankResult},
+        error::ToiError,
+        state::ToiState,
+    },
+    utils,
+};
+
+// Marks the end of documents that are cut down before they're reranked so
+// the reranking API can tell they go on.
+const TRUNCATION_MARKER: &str = "…";
+
+/// Extra filters applied to reranked search results.
+#[derive(Builder, Default)]
+pub struct RerankOptions {
+    /// Only keep documents whose normalized Damerau-Levenshtein similarity
+    /// to the query is at least this much. Useful for matching short items
+    /// like tags where semantically similar isn't similar enough.
+    pub edit_similarity_threshold: Option<f64>,
+}
+
+/// Whether a document is similar enough to the query character-for-character
+/// to pass the edit similarity filter, if there is one.
+fn passes_edit_similarity(query: Option<&str>, document: &str, options: &RerankOptions) -> bool {
+    match (query, options.edit_similarity_threshold) {
+        (Some(query), Some(edit_similarity_threshold)) => {
+            strsim::normalized_damerau_levenshtein(query, document) >= edit_similarity_threshold
+        }
+        _ => true,
+    }
+}
+
+/// Map reranked results (if there are any) back to their item IDs, keeping
+/// results that pass the similarity threshold and any extra filters. Results
+/// are kept in the order the reranking API returns them (i.e., most relevant
+/// first). Without reranked results, the extra filters are applied to the
+/// documents directly and their order is kept.
+fn filter_documents(
+    query: Option<&str>,
+    ids_docs: &[(i32, String)],
+    results: Option<Vec<RerankResult>>,
+    similarity_threshold: f64,
+    options: &RerankOptions,
+) -> Result<Vec<i32>, ToiError> {
+    let Some(results) = results else {
+        return Ok(ids_docs
+            .iter()
+            .filter(|(_, document)| passes_edit_similarity(query, document, options))
+            .map(|(id, _)| *id)
+            .collect());
+    };
+    let mut ids = vec![];
+    for result in results {
+        let Some((id, document)) = ids_docs.get(result.index) else {
+            let err = format!(
+                "rerank result index {} is out of range for {} documents",
+                result.index,
+                ids_docs.len()
+            );
+            return Err(ApiClientError::ResponseJson.into_response(&err));
+        };
+        if result.relevance_score < similarity_threshold {
+            continue;
+        }
+        if !passes_edit_similarity(query, document, options) {
+            continue;
+        }
+        ids.push(*id);
+    }
+    Ok(ids)
+}
+
+/// Whether search results are reranked, which only happens if there's a
+/// query to rerank them against.
+#[must_use]
+pub fn needs_reranking(query: Option<&str>, use_reranking_filter: Option<bool>) -> bool {
+    query.is_some() && use_reranking_filter == Some(true)
+}
+
+/// Documents to send to the reranking API, capped to the first
+/// `max_documents` documents with each one cut down to its first
+/// `max_chars` characters so request bodies stay small. Documents are
+/// expected to be ordered closest to the query first (see
+/// [`closest_first`]), so only the furthest ones are left out. Cut
+/// documents end with a marker.
+fn rerank_documents(
+    ids_docs: &[(i32, String)],
+    max_documents: usize,
+    max_chars: usize,
+) -> Vec<String> {
+    let documents: Vec<String> = ids_docs
+        .iter()
+        .take(max_documents)
+        .map(
+            |(_, document)| match document.char_indices().nth(max_chars) {
+                Some((end, _)) => format!("{}{TRUNCATION_MARKER}", &document[..end]),
+                None => document.clone(),
+            },
+        )
+        .collect();
+    if documents.len() < ids_docs.len() {
+        warn!(
+            "only reranking the top {} of {} documents",
+            documents.len(),
+            ids_docs.len()
+        );
+    }
+    let num_truncated = ids_docs
+        .iter()
+        .zip(&documents)
+        .filter(|((_, document), truncated)| document != *truncated)
+        .count();
+    if num_truncated > 0 {
+        warn!("truncated {num_truncated} rerank documents to {max_chars} characters");
+    }
+    documents
+}
+
+/// Put the documents closest to the query by embedding distance first if
+/// there are too many to rerank, so only the furthest ones are left out of
+/// reranking even if they're ordered some other way (e.g., by when they were
+/// made). Other documents keep their order.
+pub async fn closest_first(
+    state: &ToiState,
+    table: EmbeddedTable,
+    input: String,
+    mut ids_docs: Vec<(i32, String)>,
+    conn: &mut utils::Conn<'_>,
+) -> Result<Vec<(i32, String)>, ToiError> {
+    let max_documents = state.server_config.rerank_max_documents;
+    if ids_docs.len() <= max_documents {
+        return Ok(ids_docs);
+    }
+    let embedding_request = EmbeddingRequest { input };
+    let embedding = state.model_client.embed(embedding_request).await?;
+    let ids: Vec<i32> = ids_docs.iter().map(|(id, _)| *id).collect();
+    let closest_ids = table
+        .closest_ids(&ids, embedding, max_documents, conn)
+        .await?;
+    ids_docs.sort_by_key(|(id, _)| {
+        closest_ids
+            .iter()
+            .position(|closest_id| closest_id == id)
+            .unwrap_or(usize::MAX)
+    });
+    Ok(ids_docs)
+}
+
+/// Rerank and filter search results once more if the query and reranking
+/// filter are given, and apply any extra filters, returning the IDs that are
+/// still relevant. Extra filters that only need the query (like the edit
+/// similarity filter) are applied even if results aren't reranked.
+///
+/// Only the first `rerank_max_documents` results are reranked, so results
+/// past those are dropped when reranking. Results that aren't ordered by
+/// their distance to the query should be put in that order first with
+/// [`closest_first`].
+pub async fn rerank_filter(
+    state: &ToiState,
+    query: Option<String>,
+    use_reranking_filter: Option<bool>,
+    ids_docs: Vec<(i32, String)>,
+    options: &RerankOptions,
+) -> Result<Vec<i32>, ToiError> {
+    let (ids_docs, results) = match (&query, use_reranking_filter) {
+        (Some(query), Some(true)) if !ids_docs.is_empty() => {
+            let documents = rerank_documents(
+                &ids_docs,
+                state.server_config.rerank_max_documents,
+                state.server_config.rerank_max_document_chars,
+            );
+            let num_documents = documents.len();
+            let rerank_request = RerankRequest {
+                query: query.clone(),
+                documents,
+            };
+            let rerank_response = state.model_client.rerank(rerank_request).await?;
+            (&ids_docs[..num_documents], Some(rerank_response.results))
+        }
+        _ => (ids_docs.as_slice(), None),
+    };
+    filter_documents(
+        query.as_deref(),
+        ids_docs,
+        results,
+        state.server_config.similarity_threshold,
+        options,
+    )
+}
+
+/// Whether the reranking API agrees that an existing item found near a new
+/// item by embedding distance is about the same thing, making the new item
+/// a duplicate of it.
+pub async fn is_confirmed_duplicate(
+    state: &ToiState,
+    new_document: &str,
+    existing_document: String,
+) -> Result<bool, ToiError> {
+    let ids = rerank_filter(
+        state,
+        Some(new_document.to_string()),
+        Some(true),
+        vec![(0, existing_document)],
+        &RerankOptions::default(),
+    )
+    .await?;
+    Ok(!ids.is_empty())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::client::RerankDocument;
+
+    fn ids_docs() -> Vec<(i32, String)> {
+        vec![
+            (10, "groceries".to_string()),
+            (20, "grocery".to_string()),
+            (30, "hardware".to_string()),
+        ]
+    }
+
+    fn result(index: usize, relevance_score: f64) -> RerankResult {
+        RerankResult {
+            index,
+            document: RerankDocument {
+                text: String::new(),
+            },
+            relevance_score,
+        }
+    }
+
+    fn edit_distance_options() -> RerankOptions {
+        RerankOptions::builder()
+            .edit_similarity_threshold(0.8)
+            .build()
+    }
+
+    #[test]
+    fn filtering_without_reranking_or_edit_distance() {
+        let ids = filter_documents(
+            Some("groceries"),
+            &ids_docs(),
+            None,
+            0.5,
+            &RerankOptions::default(),
+        );
+        assert_eq!(ids.ok(), Some(vec![10, 20, 30]));
+    }
+
+    #[test]
+    fn filtering_by_reranking_only() {
+        let results = vec![result(2, 0.9), result(0, 0.6), result(1, 0.1)];
+        let ids = filter_documents(
+            Some("groceries"),
+            &ids_docs(),
+            Some(results),
+            0.5,
+            &RerankOptions::default(),
+        );
+        assert_eq!(ids.ok(), Some(vec![30, 10]));
+    }
+
+    #[test]
+    fn filtering_by_edit_distance_only() {
+        let ids = filter_documents(
+            Some("groceries"),
+            &ids_docs(),
+            None,
+            0.5,
+            &edit_distance_options(),
+        );
+        assert_eq!(ids.ok(), Some(vec![10]));
+    }
+
+    #[test]
+    fn filtering_by_reranking_and_edit_distance() {
+        let results = vec![result(2, 0.9), result(1, 0.8), result(0, 0.7)];
+        let ids = filter_documents(
+            Some("groceries"),
+            &ids_docs(),
+            Some(results),
+            0.5,
+            &edit_distance_options(),
+        );
+        assert_eq!(ids.ok(), Some(vec![10]));
+    }
+
+    #[test]
+    fn rejecting_out_of_range_indices() {
+        let results = vec![result(0, 0.9), result(3, 0.9)];
+        let err = filter_documents(
+            Some("groceries"),
+            &ids_docs(),
+            Some(results),
+            0.5,
+            &RerankOptions::default(),
+        );
+        assert!(matches!(err, Err(ToiError::ModelApi(_))));
+    }
+
+    #[test]
+    fn capping_rerank_documents() {
+        let documents = rerank_documents(&ids_docs(), 2, 100);
+        assert_eq!(documents, vec!["groceries", "grocery"]);
+
+        // Results for the capped documents map back to the right items.
+        let results = vec![result(1, 0.9), result(0, 0.6)];
+        let ids = filter_documents(
+            Some("groceries"),
+            &ids_docs()[..documents.len()],
+            Some(results),
+            0.5,
+            &RerankOptions::default(),
+        );
+        assert_eq!(ids.ok(), Some(vec![20, 10]));
+
+        // Documents that weren't sent can't be in the results.
+        let results = vec![result(2, 0.9)];
+        let err = filter_documents(
+            Some("groceries"),
+            &ids_docs()[..documents.len()],
+            Some(results),
+            0.5,
+            &RerankOptions::default(),
+        );
+        assert!(matches!(err, Err(ToiError::ModelApi(_))));
+    }
+
+    #[test]
+    fn truncating_rerank_documents() {
+        let ids_docs = vec![(10, "crème brûlée".to_string()), (20, "flan".to_string())];
+        let documents = rerank_documents(&ids_docs, 50, 5);
+        assert_eq!(documents, vec!["crème…", "flan"]);
+    }
+}
@@ -0,0 +1,566 @@
+use chrono::{DateTime, Utc};
+use diesel::{ExpressionMethods, QueryDsl};
+use diesel_async::{AsyncConnection, RunQueryDsl, scoped_futures::ScopedFutureExt};
+use pgvector::Vector;
+use serde::de::DeserializeOwned;
+use std::collections::HashSet;
+use std::hash::Hash;
+use std::io;
+use std::path::Path;
+use tracing::info;
+
+use crate::{
+    models::{
+        client::BatchEmbeddingRequest,
+        contacts::{NewContact, NewContactRequest},
+        error::ToiError,
+        events::{NewEvent, NewEventRequest, order_event_times},
+        notes::{NewNote, NewNoteRequest, NewNoteTag},
+        recipes::{NewRecipe, NewRecipeRequest, NewRecipeTag},
+        seeding::{SeedCount, SeedReport},
+        state::ToiState,
+        todos::{NewTodo, NewTodoRequest, NewTodoTag},
+    },
+    routes::{
+        contacts::{replace_contact_emails, replace_contact_phones},
+        events::resolve_place,
+        notes::{embed_note_chunks, replace_note_chunks},
+        tags::resolve_tags,
+        todos::resolve_event,
+    },
+    schema, utils,
+};
+
+/// Read a domain's seed file (e.g., `notes.json`) from the seed data
+/// directory. Domains without a seed file don't have anything to seed.
+fn read_seed_file<T: DeserializeOwned>(dir: &Path, domain: &str) -> Result<Vec<T>, ToiError> {
+    let path = dir.join(format!("{domain}.json"));
+    let json = match std::fs::read_to_string(&path) {
+        Ok(json) => json,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(vec![]),
+        Err(err) => {
+            return Err(ToiError::Validation(format!(
+                "couldn't read seed file '{}': {err}",
+                path.display()
+            )));
+        }
+    };
+    serde_json::from_str(&json).map_err(|err| {
+        ToiError::Validation(format!(
+            "seed file '{}' isn't a list of new {domain}: {err}",
+            path.display()
+        ))
+    })
+}
+
+/// Error for an item in a domain's seed file that couldn't be added.
+fn invalid_item(domain: &str, index: usize, reason: impl std::fmt::Display) -> ToiError {
+    ToiError::Validation(format!("{domain}.json item {index} is invalid: {reason}"))
+}
+
+/// Drop items whose key matches an existing item or an earlier item in the
+/// same seed file, returning the rest and how many were dropped.
+fn dedupe<T, K: Eq + Hash>(
+    items: Vec<T>,
+    mut seen: HashSet<K>,
+    key: impl Fn(&T) -> K,
+) -> (Vec<T>, usize) {
+    let num_items = items.len();
+    let items: Vec<T> = items
+        .into_iter()
+        .filter(|item| seen.insert(key(item)))
+        .collect();
+    let skipped = num_items - items.len();
+    (items, skipped)
+}
+
+/// Embed inputs in batches of at most the max batch size.
+async fn embed_all(state: &ToiState, inputs: Vec<String>) -> Result<Vec<Vector>, ToiError> {
+    let mut embeddings = Vec::with_capacity(inputs.len());
+    for input in inputs.chunks(state.server_config.max_batch_size.max(1)) {
+        let embedding_request = BatchEmbeddingRequest {
+            input: input.to_vec(),
+        };
+        embeddings.extend(state.model_client.embed_batch(embedding_request).await?);
+    }
+    Ok(embeddings)
+}
+
+/// Add items from the JSON seed files in the configured seed data directory
+/// for a user. Each domain's seed file is a list of the same requests used
+/// for adding its items one at a time. Items that exactly match an existing
+/// item are skipped, so seeding again only adds what's new.
+///
+/// Every seed file is read before anything is added so a malformed file
+/// doesn't leave the other domains half seeded.
+pub async fn seed(state: &ToiState, user_id: i32) -> Result<SeedReport, ToiError> {
+    let Some(dir) = &state.server_config.seed_data_path else {
+        return Err(ToiError::Validation(
+            "server.seed_data_path isn't configured".to_string(),
+        ));
+    };
+    if !dir.is_dir() {
+        return Err(ToiError::Validation(format!(
+            "seed data path '{}' isn't a directory",
+            dir.display()
+        )));
+    }
+    let contacts = read_seed_file(dir, "contacts")?;
+    let events = read_seed_file(dir, "events")?;
+    let notes = read_seed_file(dir, "notes")?;
+    let recipes = read_seed_file(dir, "recipes")?;
+    let todos = read_seed_file(dir, "todos")?;
+
+    // Events are seeded before todos so seeded todos can be for seeded
+    // events.
+    let mut conn = utils::get_conn(&state.pool).await?;
+    let report = SeedReport {
+        contacts: seed_contacts(state, user_id, contacts, &mut conn).await?,
+        events: seed_events(state, user_id, events, &mut conn).await?,
+        notes: seed_notes(state, user_id, notes, &mut conn).await?,
+        recipes: seed_recipes(state, user_id, recipes, &mut conn).await?,
+        todos: seed_todos(state, user_id, todos, &mut conn).await?,
+    };
+    for (domain, count) in [
+        ("contacts", report.contacts),
+        ("events", report.events),
+        ("notes", report.notes),
+        ("recipes", report.recipes),
+        ("todos", report.todos),
+    ] {
+        info!(
+            "seeded {} {domain} and skipped {} that already exist",
+            count.added, count.skipped
+        );
+    }
+    Ok(report)
+}
+
+/// Contacts match existing contacts if they have the same first and last
+/// names.
+async fn seed_contacts(
+    state: &ToiState,
+    user_id: i32,
+    mut contacts: Vec<NewContactRequest>,
+    conn: &mut utils::Conn<'_>,
+) -> Result<SeedCount, ToiError> {
+    for (index, contact) in contacts.iter_mut().enumerate() {
+        contact
+            .normalize_details()
+            .map_err(|err| invalid_item("contacts", index, err))?;
+    }
+    let first_names: Vec<String> = contacts
+        .iter()
+        .map(|contact| contact.first_name.clone())
+        .collect();
+    let existing: HashSet<(String, Option<String>)> = schema::contacts::table
+        .select((schema::contacts::first_name, schema::contacts::last_name))
+        .filter(schema::contacts::user_id.eq(user_id))
+        .filter(schema::contacts::first_name.eq_any(&first_names))
+        .load::<(String, Option<String>)>(conn)
+        .await
+        .map_err(utils::diesel_error)?
+        .into_iter()
+        .collect();
+    let (contacts, skipped) = dedupe(contacts, existing, |contact| {
+        (contact.first_name.clone(), contact.last_name.clone())
+    });
+    if contacts.is_empty() {
+        return Ok(SeedCount { added: 0, skipped });
+    }
+    let inputs = contacts.iter().map(ToString::to_string).collect();
+    let embeddings = embed_all(state, inputs).await?;
+    let added = contacts.len();
+    conn.transaction::<_, ToiError, _>(|mut conn| {
+        async move {
+            for (params, embedding) in contacts.into_iter().zip(embeddings) {
+                let NewContactRequest {
+                    first_name,
+                    last_name,
+                    email,
+                    phone,
+                    birthday,
+                    relationship,
+                    emails,
+                    phones,
+                    ..
+                } = params;
+                let new_contact = NewContact {
+                    user_id,
+                    first_name,
+                    last_name,
+                    email,
+                    phone,
+                    birthday,
+                    relationship,
+                    embedding,
+                };
+                let contact_id: i32 = diesel::insert_into(schema::contacts::table)
+                    .values(new_contact)
+                    .returning(schema::contacts::id)
+                    .get_result(&mut conn)
+                    .await?;
+                replace_contact_emails(contact_id, emails.unwrap_or_default(), &mut conn).await?;
+                replace_contact_phones(contact_id, phones.unwrap_or_default(), &mut conn).await?;
+            }
+            Ok(())
+        }
+        .scope_boxed()
+    })
+    .await?;
+    Ok(SeedCount { added, skipped })
+}
+
+/// Events match existing events if they have the same description and
+/// start at the same time.
+async fn seed_events(
+    state: &ToiState,
+    user_id: i32,
+    mut events: Vec<NewEventRequest>,
+    conn: &mut utils::Conn<'_>,
+) -> Result<SeedCount, ToiError> {
+    for (index, event) in events.iter_mut().enumerate() {
+        if event
+            .recurrence_interval
+            .is_some_and(|interval| interval < 1)
+        {
+            return Err(invalid_item(
+                "events",
+                index,
+                "event recurrence interval must be at least 1",
+            ));
+        }
+        (event.starts_at, event.ends_at) = order_event_times(
+            event.starts_at,
+            event.ends_at,
+            state.server_config.swap_if_reversed,
+        )
+        .map_err(|err| invalid_item("events", index, err))?;
+    }
+    let descriptions: Vec<String> = events
+        .iter()
+        .map(|event| event.description.clone())
+        .collect();
+    let existing: HashSet<(String, DateTime<Utc>)> = schema::events::table
+        .select((schema::events::description, schema::events::starts_at))
+        .filter(schema::events::user_id.eq(user_id))
+        .filter(schema::events::description.eq_any(&descriptions))
+        .load::<(String, DateTime<Utc>)>(conn)
+        .await
+        .map_err(utils::diesel_error)?
+        .into_iter()
+        .collect();
+    let (events, skipped) = dedupe(events, existing, |event| {
+        (event.description.clone(), event.starts_at)
+    });
+    if events.is_empty() {
+        return Ok(SeedCount { added: 0, skipped });
+    }
+    let inputs = events
+        .iter()
+        .map(|event| event.description.clone())
+        .collect();
+    let embeddings = embed_all(state, inputs).await?;
+    let mut new_events = vec![];
+    for (
+        NewEventRequest {
+            description,
+            starts_at,
+            ends_at,
+            recurrence_frequency,
+            recurrence_interval,
+            recurrence_until,
+            place_id,
+            place_query,
+            ..
+        },
+        embedding,
+    ) in events.into_iter().zip(embeddings)
+    {
+        let place_id = resolve_place(state, user_id, place_id, place_query, conn).await?;
+        new_events.push(NewEvent {
+            user_id,
+            description,
+            embedding,
+            starts_at,
+            ends_at,
+            recurrence_frequency,
+            recurrence_interval: recurrence_frequency.map(|_| recurrence_interval.unwrap_or(1)),
+            recurrence_until,
+            place_id,
+        });
+    }
+    let added = new_events.len();
+    diesel::insert_into(schema::events::table)
+        .values(new_events)
+        .execute(conn)
+        .await
+        .map_err(utils::diesel_error)?;
+    Ok(SeedCount { added, skipped })
+}
+
+/// Notes match existing notes if they have the same content.
+async fn seed_notes(
+    state: &ToiState,
+    user_id: i32,
+    notes: Vec<NewNoteRequest>,
+    conn: &mut utils::Conn<'_>,
+) -> Result<SeedCount, ToiError> {
+    let contents: Vec<String> = notes.iter().map(|note| note.content.clone()).collect();
+    let existing: HashSet<String> = schema::notes::table
+        .select(schema::notes::content)
+        .filter(schema::notes::user_id.eq(user_id))
+        .filter(schema::notes::content.eq_any(&contents))
+        .load::<String>(conn)
+        .await
+        .map_err(utils::diesel_error)?
+        .into_iter()
+        .collect();
+    let (notes, skipped) = dedupe(notes, existing, |note| note.content.clone());
+    if notes.is_empty() {
+        return Ok(SeedCount { added: 0, skipped });
+    }
+    let mut contents = vec![];
+    let mut expirations = vec![];
+    let mut tag_ids = vec![];
+    for NewNoteRequest {
+        content,
+        tags,
+        expires_at,
+        ..
+    } in notes
+    {
+        contents.push(content);
+        expirations.push(expires_at);
+        tag_ids.push(match tags {
+            Some(tags) => resolve_tags(state, user_id, tags).await?,
+            None => vec![],
+        });
+    }
+    let embeddings = embed_all(state, contents.clone()).await?;
+    let mut note_chunks = vec![];
+    for content in &contents {
+        note_chunks.push(embed_note_chunks(state, content).await?);
+    }
+    let new_notes: Vec<NewNote> = contents
+        .into_iter()
+        .zip(embeddings)
+        .zip(expirations)
+        .map(|((content, embedding), expires_at)| NewNote {
+            user_id,
+            content,
+            embedding,
+            expires_at,
+        })
+        .collect();
+    let added = new_notes.len();
+    conn.transaction::<_, ToiError, _>(|mut conn| {
+        async move {
+            let mut note_ids: Vec<i32> = diesel::insert_into(schema::notes::table)
+                .values(&new_notes)
+                .returning(schema::notes::id)
+                .get_results(&mut conn)
+                .await?;
+            // IDs are generated in input order.
+            note_ids.sort_unstable();
+            let new_note_tags: Vec<NewNoteTag> = note_ids
+                .iter()
+                .zip(tag_ids)
+                .flat_map(|(note_id, tag_ids)| {
+                    tag_ids.into_iter().map(|tag_id| NewNoteTag {
+                        note_id: *note_id,
+                        tag_id,
+                    })
+                })
+                .collect();
+            diesel::insert_into(schema::note_tags::table)
+                .values(new_note_tags)
+                .execute(&mut conn)
+                .await?;
+            for (note_id, chunks) in note_ids.into_iter().zip(note_chunks) {
+                replace_note_chunks(note_id, chunks, &mut conn).await?;
+            }
+            Ok(())
+        }
+        .scope_boxed()
+    })
+    .await?;
+    Ok(SeedCount { added, skipped })
+}
+
+/// Recipes match existing recipes if they have the same description.
+async fn seed_recipes(
+    state: &ToiState,
+    user_id: i32,
+    recipes: Vec<NewRecipeRequest>,
+    conn: &mut utils::Conn<'_>,
+) -> Result<SeedCount, ToiError> {
+    let descriptions: Vec<String> = recipes
+        .iter()
+        .map(|recipe| recipe.description.clone())
+        .collect();
+    let existing: HashSet<String> = schema::recipes::table
+        .select(schema::recipes::description)
+        .filter(schema::recipes::user_id.eq(user_id))
+        .filter(schema::recipes::description.eq_any(&descriptions))
+        .load::<String>(conn)
+        .await
+        .map_err(utils::diesel_error)?
+        .into_iter()
+        .collect();
+    let (recipes, skipped) = dedupe(recipes, existing, |recipe| recipe.description.clone());
+    if recipes.is_empty() {
+        return Ok(SeedCount { added: 0, skipped });
+    }
+    let inputs = recipes
+        .iter()
+        .map(|recipe| recipe.description.clone())
+        .collect();
+    let embeddings = embed_all(state, inputs).await?;
+    let mut new_recipes = vec![];
+    let mut tag_ids = vec![];
+    for (
+        NewRecipeRequest {
+            description,
+            ingredients,
+            instructions,
+            tags,
+        },
+        embedding,
+    ) in recipes.into_iter().zip(embeddings)
+    {
+        tag_ids.push(resolve_tags(state, user_id, tags).await?);
+        new_recipes.push(NewRecipe {
+            user_id,
+            description,
+            ingredients,
+            instructions,
+            embedding,
+        });
+    }
+    let added = new_recipes.len();
+    conn.transaction::<_, ToiError, _>(|mut conn| {
+        async move {
+            let mut recipe_ids: Vec<i32> = diesel::insert_into(schema::recipes::table)
+                .values(&new_recipes)
+                .returning(schema::recipes::id)
+                .get_results(&mut conn)
+                .await?;
+            // IDs are generated in input order.
+            recipe_ids.sort_unstable();
+            let new_recipe_tags: Vec<NewRecipeTag> = recipe_ids
+                .into_iter()
+                .zip(tag_ids)
+                .flat_map(|(recipe_id, tag_ids)| {
+                    tag_ids
+                        .into_iter()
+                        .map(move |tag_id| NewRecipeTag { recipe_id, tag_id })
+                })
+                .collect();
+            diesel::insert_into(schema::recipe_tags::table)
+                .values(new_recipe_tags)
+                .execute(&mut conn)
+                .await?;
+            Ok(())
+        }
+        .scope_boxed()
+    })
+    .await?;
+    Ok(SeedCount { added, skipped })
+}
+
+/// Todos match existing todos if they have the same item.
+async fn seed_todos(
+    state: &ToiState,
+    user_id: i32,
+    todos: Vec<NewTodoRequest>,
+    conn: &mut utils::Conn<'_>,
+) -> Result<SeedCount, ToiError> {
+    for (index, todo) in todos.iter().enumerate() {
+        if todo.recurrence_days.is_some_and(|days| days <= 0) {
+            return Err(invalid_item(
+                "todos",
+                index,
+                "recurrence days must be positive",
+            ));
+        }
+    }
+    let items: Vec<String> = todos.iter().map(|todo| todo.item.clone()).collect();
+    let existing: HashSet<String> = schema::todos::table
+        .select(schema::todos::item)
+        .filter(schema::todos::user_id.eq(user_id))
+        .filter(schema::todos::item.eq_any(&items))
+        .load::<String>(conn)
+        .await
+        .map_err(utils::diesel_error)?
+        .into_iter()
+        .collect();
+    let (todos, skipped) = dedupe(todos, existing, |todo| todo.item.clone());
+    if todos.is_empty() {
+        return Ok(SeedCount { added: 0, skipped });
+    }
+    let inputs = todos.iter().map(|todo| todo.item.clone()).collect();
+    let embeddings = embed_all(state, inputs).await?;
+    let mut new_todos = vec![];
+    let mut tag_ids = vec![];
+    for (
+        NewTodoRequest {
+            item,
+            due_at,
+            completed_at,
+            priority,
+            recurrence_days,
+            event_id,
+            event_query,
+            tags,
+            ..
+        },
+        embedding,
+    ) in todos.into_iter().zip(embeddings)
+    {
+        let event_id = resolve_event(state, user_id, event_id, event_query, conn).await?;
+        tag_ids.push(match tags {
+            Some(tags) => resolve_tags(state, user_id, tags).await?,
+            None => vec![],
+        });
+        new_todos.push(NewTodo {
+            user_id,
+            item,
+            embedding,
+            due_at,
+            completed_at,
+            priority,
+            recurrence_days,
+            event_id,
+        });
+    }
+    let added = new_todos.len();
+    conn.transaction::<_, ToiError, _>(|mut conn| {
+        async move {
+            let mut todo_ids: Vec<i32> = diesel::insert_into(schema::todos::table)
+                .values(&new_todos)
+                .returning(schema::todos::id)
+                .get_results(&mut conn)
+                .await?;
+            // IDs are generated in input order.
+            todo_ids.sort_unstable();
+            let new_todo_tags: Vec<NewTodoTag> = todo_ids
+                .into_iter()
+                .zip(tag_ids)
+                .flat_map(|(todo_id, tag_ids)| {
+                    tag_ids
+                        .into_iter()
+                        .map(move |tag_id| NewTodoTag { todo_id, tag_id })
+                })
+                .collect();
+            diesel::insert_into(schema::todo_tags::table)
+                .values(new_todo_tags)
+                .execute(&mut conn)
+                .await?;
+            Ok(())
+        }
+        .scope_boxed()
+    })
+    .await?;
+    Ok(SeedCount { added, skipped })
+}
@@ -0,0 +1,69 @@
+use axum::Router;
+use std::{net::SocketAddr, time::Duration};
+use tokio::{net::TcpListener, sync::oneshot};
+use tracing::{info, warn};
+
+/// Resolves once the process is asked to stop with Ctrl+C or, on unix,
+/// with SIGTERM (e.g., from `docker stop`).
+pub async fn signal() {
+    let ctrl_c = async {
+        tokio::signal::ctrl_c()
+            .await
+            .expect("should be able to listen for ctrl+c");
+    };
+
+    #[cfg(unix)]
+    let terminate = async {
+        tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate())
+            .expect("should be able to listen for SIGTERM")
+            .recv()
+            .await;
+    };
+
+    #[cfg(not(unix))]
+    let terminate = std::future::pending::<()>();
+
+    tokio::select! {
+        () = ctrl_c => {},
+        () = terminate => {},
+    }
+}
+
+/// Serve the router until the shutdown signal resolves. New connections
+/// stop being accepted once the signal resolves, and in-flight requests
+/// (e.g., streaming assistant responses) are given up to the drain timeout
+/// to finish before the server stops anyways.
+pub async fn serve(
+    listener: TcpListener,
+    router: Router,
+    signal: impl Future<Output = ()> + Send + 'static,
+    drain_timeout: Duration,
+) -> std::io::Result<()> {
+    let (draining_tx, draining_rx) = oneshot::channel();
+    let server = axum::serve(
+        listener,
+        router.into_make_service_with_connect_info::<SocketAddr>(),
+    )
+    .with_graceful_shutdown(async move {
+        signal.await;
+        info!("shutting down and draining in-flight requests");
+        let _ = draining_tx.send(());
+    })
+    .into_future();
+    tokio::pin!(server);
+
+    // The server only finishes on its own if it fails or there weren't any
+    // in-flight requests to wait on.
+    tokio::select! {
+        result = &mut server => return result,
+        Ok(()) = draining_rx => {},
+    }
+
+    match tokio::time::timeout(drain_timeout, server).await {
+        Ok(result) => result,
+        Err(_) => {
+            warn!("in-flight requests didn't finish within {drain_timeout:?}");
+            Ok(())
+        }
+    }
+}
@@ -1,4 +1,5 @@
-use axum::http::StatusCode;
+use chrono_tz::Tz;
+use diesel::{dsl::sql, expression::SqlLiteral, sql_types::BigInt};
 use diesel_async::{AsyncPgConnection, pooled_connection::AsyncDieselConnectionManager};
 use schemars::JsonSchema;
 use serde::{Deserialize, Deserializer, Serialize};
@@ -9,13 +10,15 @@ use std::fmt;
 use std::net::SocketAddr;
 use utoipa::ToSchema;
 
+use crate::models::{client::ApiClientError, error::ToiError, weather::GeocodingResult};
+
 pub type Pool = bb8::Pool<AsyncDieselConnectionManager<AsyncPgConnection>>;
 pub type Conn<'a> = bb8::PooledConnection<
     'a,
     diesel_async::pooled_connection::AsyncDieselConnectionManager<diesel_async::AsyncPgConnection>,
 >;
 
-#[derive(Deserialize, Serialize, JsonSchema, ToSchema)]
+#[derive(Clone, Deserialize, Serialize, JsonSchema, ToSchema)]
 pub enum DateFallsOn {
     Month,
     Week,
@@ -91,6 +94,19 @@ where
         .map_err(serde::de::Error::custom)
 }
 
+/// Parse an IANA timezone name (e.g., "America/New_York"), falling back to a
+/// default if there isn't one.
+pub fn parse_timezone(timezone: Option<&str>, default: Tz) -> Result<Tz, ToiError> {
+    match timezone {
+        Some(timezone) => timezone.parse::<Tz>().map_err(|_| {
+            ToiError::Validation(format!(
+                "{timezone} isn't a valid IANA timezone name (e.g., America/New_York)"
+            ))
+        }),
+        None => Ok(default),
+    }
+}
+
 pub fn deserialize_socket_addr<'de, D>(deserializer: D) -> Result<SocketAddr, D::Error>
 where
     D: Deserializer<'de>,
@@ -99,18 +115,116 @@ where
     value.parse().map_err(serde::de::Error::custom)
 }
 
-/// Map Diesel errors into a specific response.
-pub fn diesel_error(err: diesel::result::Error) -> (StatusCode, String) {
+pub fn deserialize_timezone<'de, D>(deserializer: D) -> Result<Tz, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let value = String::deserialize(deserializer)?;
+    value.parse().map_err(serde::de::Error::custom)
+}
+
+/// Total number of rows matching a query's filters, ignoring its offset and
+/// limit. Selected alongside rows so pagination only needs one query.
+pub fn total_count() -> SqlLiteral<BigInt> {
+    sql::<BigInt>("COUNT(*) OVER ()")
+}
+
+/// Escape characters that have special meaning in `LIKE` patterns so user
+/// input is matched literally.
+pub fn escape_like_pattern(s: &str) -> String {
+    s.replace('\\', "\\\\")
+        .replace('%', "\\%")
+        .replace('_', "\\_")
+}
+
+/// Geocode a free-form query into its most relevant latitude/longitude
+/// using a Nominatim-compatible search API.
+pub async fn geocode(
+    query: &str,
+    geocoding_url: &str,
+    client: &reqwest::Client,
+) -> Result<(f64, f64), ToiError> {
+    let geocoding_params = serde_json::json!(
+        {
+            "q": query,
+            "format": "json"
+        }
+    );
+    let mut results = client
+        .get(geocoding_url)
+        .query(&geocoding_params)
+        .send()
+        .await
+        .map_err(|err| ApiClientError::ApiConnection.into_response(&err))?
+        .json::<Vec<GeocodingResult>>()
+        .await
+        .map_err(|err| ApiClientError::ResponseJson.into_response(&err))?;
+    if results.is_empty() {
+        let err = format!("couldn't geocode {query}");
+        return Err(ApiClientError::EmptyResponse.into_response(&err));
+    }
+    let most_relevant_result = results.swap_remove(0);
+    let latitude: f64 = most_relevant_result
+        .lat
+        .parse()
+        .map_err(|err| ApiClientError::ResponseJson.into_response(&err))?;
+    let longitude: f64 = most_relevant_result
+        .lon
+        .parse()
+        .map_err(|err| ApiClientError::ResponseJson.into_response(&err))?;
+    if !latitude.is_finite() || !longitude.is_finite() {
+        let err = format!("invalid coordinates for {query}");
+        return Err(ApiClientError::ResponseJson.into_response(&err));
+    }
+    Ok((latitude, longitude))
+}
+
+/// Get a connection from the pool. Waiting longer than the pool's
+/// connection timeout means every connection is in use, which responds with
+/// a `503 Service Unavailable` rather than leaving the request hanging.
+pub async fn get_conn(pool: &Pool) -> Result<Conn<'_>, ToiError> {
+    pool.get().await.map_err(|err| match err {
+        bb8::RunError::TimedOut => ToiError::Unavailable(
+            "timed out waiting for a database connection since all of them are in use".to_string(),
+        ),
+        bb8::RunError::User(err) => internal_error(err),
+    })
+}
+
+/// Map Diesel errors into a specific error.
+pub fn diesel_error(err: diesel::result::Error) -> ToiError {
     match err {
-        diesel::result::Error::NotFound => (StatusCode::NOT_FOUND, err.to_string()),
+        diesel::result::Error::NotFound => ToiError::NotFound(err.to_string()),
         _ => internal_error(err),
     }
 }
 
-/// Map any error into a `500 Internal Server Error` response.
-pub fn internal_error<E>(err: E) -> (StatusCode, String)
+// Lets transactions fail with errors that don't come from the database,
+// like conflicting idempotency keys.
+impl From<diesel::result::Error> for ToiError {
+    fn from(err: diesel::result::Error) -> Self {
+        diesel_error(err)
+    }
+}
+
+/// Map any error into an error that responds with a
+/// `500 Internal Server Error`.
+pub fn internal_error<E>(err: E) -> ToiError
 where
     E: std::error::Error,
 {
-    (StatusCode::INTERNAL_SERVER_ERROR, err.to_string())
+    ToiError::Database(err.to_string())
+}
+
+/// Check that bulk changes to items selected by their IDs found any of
+/// them, since not finding any usually means the IDs are stale. Bulk
+/// changes that only filter items succeed without changing anything
+/// instead, just like searches that don't match anything.
+pub fn check_ids_found<T>(ids: Option<&[i32]>, items: &[T], kind: &str) -> Result<(), ToiError> {
+    match ids {
+        Some(ids) if !ids.is_empty() && items.is_empty() => Err(ToiError::NotFound(format!(
+            "no {kind} found with IDs {ids:?}"
+        ))),
+        _ => Ok(()),
+    }
 }
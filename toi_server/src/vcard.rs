@@ -0,0 +1,323 @@
+use chrono::NaiveDate;
+
+// Types that only describe how an email or phone number can be used rather
+// than what kind it is, so they're never used as labels.
+const IGNORED_TYPES: [&str; 5] = ["internet", "pref", "text", "voice", "x400"];
+
+/// Email or phone number from a vCard, labeled by its first meaningful type
+/// (e.g., "work" or "cell") if it has one.
+#[derive(Debug, PartialEq)]
+pub struct Detail {
+    pub label: Option<String>,
+    pub value: String,
+}
+
+/// The parts of a vCard (RFC 2426 and RFC 6350) that map onto contacts.
+/// Other properties are ignored.
+#[derive(Debug, Default, PartialEq)]
+pub struct Card {
+    pub formatted_name: Option<String>,
+    pub given_name: Option<String>,
+    pub family_name: Option<String>,
+    pub emails: Vec<Detail>,
+    pub phones: Vec<Detail>,
+    pub birthday: Option<NaiveDate>,
+    pub note: Option<String>,
+}
+
+impl Card {
+    /// Name to refer to the card by, like when it can't be imported.
+    #[must_use]
+    pub fn display_name(&self) -> String {
+        if let Some(formatted_name) = &self.formatted_name {
+            return formatted_name.clone();
+        }
+        let name: Vec<&str> = [&self.given_name, &self.family_name]
+            .into_iter()
+            .flatten()
+            .map(String::as_str)
+            .collect();
+        if name.is_empty() {
+            "unnamed contact".to_string()
+        } else {
+            name.join(" ")
+        }
+    }
+
+    fn property(&mut self, name: &str, params: &[(String, String)], value: &str) {
+        match name {
+            "FN" => self.formatted_name = non_empty(unescape_text(value)),
+            "N" => {
+                let mut components = split_unescaped(value, ';').into_iter();
+                self.family_name = components.next().and_then(non_empty);
+                self.given_name = components.next().and_then(non_empty);
+            }
+            "EMAIL" => {
+                if let Some(value) = non_empty(unescape_text(value)) {
+                    self.emails.push(Detail {
+                        label: label(params),
+                        value,
+                    });
+                }
+            }
+            "TEL" => {
+                let value = unescape_text(value);
+                let value = value.strip_prefix("tel:").unwrap_or(&value);
+                if let Some(value) = non_empty(value.to_string()) {
+                    self.phones.push(Detail {
+                        label: label(params),
+                        value,
+                    });
+                }
+            }
+            "BDAY" => self.birthday = parse_date(value),
+            "NOTE" => self.note = non_empty(unescape_text(value)),
+            _ => {}
+        }
+    }
+}
+
+/// Parse every vCard in a document. Folded lines are unfolded, escaped
+/// characters are unescaped, and properties outside of vCards are ignored.
+pub fn parse(document: &str) -> Result<Vec<Card>, String> {
+    let mut cards = vec![];
+    let mut card: Option<Card> = None;
+    for (number, line) in unfold(document) {
+        if line.trim().is_empty() {
+            continue;
+        }
+        let Some((name, params, value)) = parse_line(&line) else {
+            return Err(format!("line {number} isn't a valid vCard property"));
+        };
+        let is_vcard = value.eq_ignore_ascii_case("VCARD");
+        if name == "BEGIN" && is_vcard {
+            if card.is_some() {
+                return Err(format!("line {number} starts a vCard inside another vCard"));
+            }
+            card = Some(Card::default());
+        } else if name == "END" && is_vcard {
+            cards.extend(card.take());
+        } else if let Some(card) = &mut card {
+            card.property(&name, &params, value);
+        }
+    }
+    if card.is_some() {
+        return Err("the last vCard isn't ended".to_string());
+    }
+    Ok(cards)
+}
+
+/// Join folded lines, which continue onto the next line by starting it with
+/// a space or tab. Lines are numbered by where they start.
+fn unfold(document: &str) -> Vec<(usize, String)> {
+    let mut lines: Vec<(usize, String)> = vec![];
+    for (i, line) in document.lines().enumerate() {
+        match (line.strip_prefix([' ', '\t']), lines.last_mut()) {
+            (Some(continuation), Some((_, previous))) => previous.push_str(continuation),
+            _ => lines.push((i + 1, line.to_string())),
+        }
+    }
+    lines
+}
+
+/// Split a content line into its uppercased name without any group, its
+/// parameters with lowercased names, and its raw value.
+fn parse_line(line: &str) -> Option<(String, Vec<(String, String)>, &str)> {
+    let mut in_quotes = false;
+    let colon = line.char_indices().find_map(|(i, c)| match c {
+        '"' => {
+            in_quotes = !in_quotes;
+            None
+        }
+        ':' if !in_quotes => Some(i),
+        _ => None,
+    })?;
+    let (head, value) = (&line[..colon], &line[colon + 1..]);
+    let mut parts = split_quoted(head, ';').into_iter();
+    let name = parts.next()?;
+    let name = name.rsplit_once('.').map_or(name, |(_, name)| name);
+    if name.is_empty() {
+        return None;
+    }
+    let params = parts
+        .map(|param| match param.split_once('=') {
+            Some((key, value)) => (key.to_lowercase(), value.trim_matches('"').to_string()),
+            // vCard 2.1 lists types without a parameter name.
+            None => ("type".to_string(), param.to_string()),
+        })
+        .collect();
+    Some((name.to_uppercase(), params, value))
+}
+
+/// Split on a separator, except where it's within double quotes.
+fn split_quoted(value: &str, separator: char) -> Vec<&str> {
+    let mut parts = vec![];
+    let mut in_quotes = false;
+    let mut start = 0;
+    for (i, c) in value.char_indices() {
+        if c == '"' {
+            in_quotes = !in_quotes;
+        } else if c == separator && !in_quotes {
+            parts.push(&value[start..i]);
+            start = i + c.len_utf8();
+        }
+    }
+    parts.push(&value[start..]);
+    parts
+}
+
+/// Split a structured value on a separator, except where it's escaped, and
+/// unescape each component.
+fn split_unescaped(value: &str, separator: char) -> Vec<String> {
+    let mut components = vec![];
+    let mut component = String::new();
+    let mut chars = value.chars();
+    while let Some(c) = chars.next() {
+        if c == '\\' {
+            component.push(c);
+            component.extend(chars.next());
+        } else if c == separator {
+            components.push(unescape_text(&component));
+            component.clear();
+        } else {
+            component.push(c);
+        }
+    }
+    components.push(unescape_text(&component));
+    components
+}
+
+/// Unescape backslashes, semicolons, commas, and line breaks in a text
+/// value.
+#[must_use]
+pub fn unescape_text(value: &str) -> String {
+    let mut unescaped = String::with_capacity(value.len());
+    let mut chars = value.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            unescaped.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('n' | 'N') => unescaped.push('\n'),
+            Some(c) => unescaped.push(c),
+            None => unescaped.push('\\'),
+        }
+    }
+    unescaped
+}
+
+/// Label for an email or phone number from its types, skipping types that
+/// don't say what kind it is.
+fn label(params: &[(String, String)]) -> Option<String> {
+    params
+        .iter()
+        .filter(|(key, _)| key == "type")
+        .flat_map(|(_, value)| value.split(','))
+        .map(|kind| kind.trim().to_lowercase())
+        .find(|kind| !kind.is_empty() && !IGNORED_TYPES.contains(&kind.as_str()))
+}
+
+/// Parse a date like `1985-04-12` or `19850412`, ignoring any time after it.
+/// Dates without a year can't be stored, so they're ignored.
+fn parse_date(value: &str) -> Option<NaiveDate> {
+    let date = value.split('T').next()?.trim();
+    NaiveDate::parse_from_str(date, "%Y-%m-%d")
+        .or_else(|_| NaiveDate::parse_from_str(date, "%Y%m%d"))
+        .ok()
+}
+
+fn non_empty(value: String) -> Option<String> {
+    let value = value.trim();
+    (!value.is_empty()).then(|| value.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::NaiveDate;
+
+    use super::*;
+
+    #[test]
+    fn parses_cards() {
+        let document = "BEGIN:VCARD\r\n\
+            VERSION:3.0\r\n\
+            N:Smith;John;;;\r\n\
+            FN:John Smith\r\n\
+            item1.EMAIL;TYPE=INTERNET,WORK:john@example.com\r\n\
+            TEL;TYPE=CELL,VOICE:555-123-4567\r\n\
+            TEL:555-765-4321\r\n\
+            BDAY:1985-04-12\r\n\
+            NOTE:Met at the climbing gym\\, Tuesdays\\nBrings snacks\r\n\
+            END:VCARD\r\n\
+            BEGIN:VCARD\r\n\
+            VERSION:4.0\r\n\
+            FN:Zoë\r\n\
+            TEL;VALUE=uri;TYPE=\"home,voice\":tel:+1-555-000-1111\r\n\
+            BDAY:--0412\r\n\
+            END:VCARD\r\n";
+        let cards = parse(document).expect("document should be valid");
+        assert_eq!(
+            cards,
+            vec![
+                Card {
+                    formatted_name: Some("John Smith".to_string()),
+                    given_name: Some("John".to_string()),
+                    family_name: Some("Smith".to_string()),
+                    emails: vec![Detail {
+                        label: Some("work".to_string()),
+                        value: "john@example.com".to_string(),
+                    }],
+                    phones: vec![
+                        Detail {
+                            label: Some("cell".to_string()),
+                            value: "555-123-4567".to_string(),
+                        },
+                        Detail {
+                            label: None,
+                            value: "555-765-4321".to_string(),
+                        },
+                    ],
+                    birthday: NaiveDate::from_ymd_opt(1985, 4, 12),
+                    note: Some("Met at the climbing gym, Tuesdays\nBrings snacks".to_string()),
+                },
+                Card {
+                    formatted_name: Some("Zoë".to_string()),
+                    phones: vec![Detail {
+                        label: Some("home".to_string()),
+                        value: "+1-555-000-1111".to_string(),
+                    }],
+                    ..Card::default()
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn unfolds_lines() {
+        let document = "BEGIN:VCARD\n\
+            FN:Jean-\n  Luc\n\tPicard\n\
+            NOTE:Captain\\\n  of the Enterprise\n\
+            END:VCARD\n";
+        let cards = parse(document).expect("document should be valid");
+        assert_eq!(cards.len(), 1);
+        assert_eq!(cards[0].formatted_name.as_deref(), Some("Jean- LucPicard"));
+        assert_eq!(cards[0].note.as_deref(), Some("Captain of the Enterprise"));
+    }
+
+    #[test]
+    fn unescapes_structured_names() {
+        let cards = parse("BEGIN:VCARD\nN:O\\;Brien;Mary\\, Ann\nEND:VCARD\n")
+            .expect("document should be valid");
+        assert_eq!(cards[0].family_name.as_deref(), Some("O;Brien"));
+        assert_eq!(cards[0].given_name.as_deref(), Some("Mary, Ann"));
+        assert_eq!(cards[0].display_name(), "Mary, Ann O;Brien");
+    }
+
+    #[test]
+    fn rejects_unended_cards() {
+        assert!(parse("BEGIN:VCARD\nFN:John Smith\n").is_err());
+        assert!(parse("BEGIN:VCARD\nBEGIN:VCARD\nEND:VCARD\n").is_err());
+        assert!(parse("BEGIN:VCARD\nnot a property\nEND:VCARD\n").is_err());
+    }
+}
@@ -1,8 +1,17 @@
+use chrono::{TimeZone, Utc};
 use serial_test::serial;
 use tokio::net::TcpListener;
 use utoipa_axum::router::OpenApiRouter;
 
-use toi_server::models::accounts::{BankAccount, BankAccountSearchParams, NewBankAccountRequest};
+use toi_server::models::{
+    accounts::{
+        BankAccount, BankAccountBalance, BankAccountBalanceParams, BankAccountMergeTarget,
+        BankAccountSearchParams, NewBankAccountRequest, UpdateBankAccountRequest,
+        UpdatedBankAccount,
+    },
+    pagination::Page,
+    transactions::NewBankAccountTransactionRequest,
+};
 
 mod utils;
 
@@ -18,7 +27,10 @@ async fn accounts_routes() -> Result<(), Box<dyn std::error::Error>> {
     let state = toi_server::init(db_connection_url).await?;
     let openapi_router = OpenApiRouter::new().nest(
         "/banking/accounts",
-        toi_server::routes::accounts::accounts_router(state.clone()),
+        toi_server::routes::accounts::accounts_router(state.clone()).nest(
+            "/transactions",
+            toi_server::routes::transactions::bank_account_transactions_router(state.clone()),
+        ),
     );
     let (router, _) = openapi_router.split_for_parts();
     let listener = TcpListener::bind(&state.server_config.bind_addr).await?;
@@ -38,6 +50,16 @@ async fn accounts_routes() -> Result<(), Box<dyn std::error::Error>> {
     let account1 = response.json::<BankAccount>().await?;
     assert_eq!(account1.description, account_description);
 
+    // Retrieve the account using its ID, and fail to retrieve a missing one.
+    let response = client
+        .get(format!("{accounts_url}/{}", account1.id))
+        .send()
+        .await?;
+    let response = utils::assert_ok_response(response).await?;
+    assert_eq!(response.json::<BankAccount>().await?, account1);
+    let response = client.get(format!("{accounts_url}/0")).send().await?;
+    assert_eq!(response.status(), reqwest::StatusCode::NOT_FOUND);
+
     // Retrieve the account using search.
     let search_accounts_url = format!("{accounts_url}/search");
     let params = BankAccountSearchParams::builder()
@@ -49,11 +71,76 @@ async fn accounts_routes() -> Result<(), Box<dyn std::error::Error>> {
         .send()
         .await?;
     let response = utils::assert_ok_response(response).await?;
-    let vec_accounts1 = response.json::<Vec<BankAccount>>().await?;
+    let vec_accounts1 = response.json::<Page<BankAccount>>().await?.items;
     assert_eq!(vec_accounts1, vec![account1]);
 
-    // Delete the account using search.
+    // Make a few deposits and withdrawals.
+    let bank_account_transactions_url = format!("{accounts_url}/transactions");
+    let transactions = [
+        ("paycheck", 100.5, 1),
+        ("groceries", -20.25, 2),
+        ("gas", -30.0, 3),
+        ("refund", 5.0, 4),
+    ];
+    for (description, amount, day) in transactions {
+        let posted_at = Utc
+            .with_ymd_and_hms(2025, 6, day, 12, 0, 0)
+            .single()
+            .ok_or("invalid test datetime")?;
+        let body = NewBankAccountTransactionRequest::builder()
+            .bank_account_id(vec_accounts1[0].id)
+            .transaction_description(description.to_string())
+            .transaction_amount(amount)
+            .transaction_posted_at(posted_at)
+            .build();
+        let response = client
+            .post(&bank_account_transactions_url)
+            .json(&body)
+            .send()
+            .await?;
+        utils::assert_ok_response(response).await?;
+    }
+
+    // Get the balance across all transactions.
+    let balance_url = format!("{accounts_url}/balance");
+    let params = BankAccountBalanceParams::builder()
+        .bank_account_query(account_description.to_string())
+        .build();
+    let response = client.get(&balance_url).query(&params).send().await?;
+    let response = utils::assert_ok_response(response).await?;
+    let balance1 = response.json::<BankAccountBalance>().await?;
+    assert_eq!(balance1.bank_account, vec_accounts1[0]);
+    assert!((balance1.balance - 55.25).abs() < f32::EPSILON);
+    assert_eq!(balance1.transaction_count, 4);
+    assert_eq!(
+        balance1.earliest_posted_at,
+        Utc.with_ymd_and_hms(2025, 6, 1, 12, 0, 0).single()
+    );
+    assert_eq!(
+        balance1.latest_posted_at,
+        Utc.with_ymd_and_hms(2025, 6, 4, 12, 0, 0).single()
+    );
+
+    // Get the balance over a window that excludes the paycheck.
+    let params = BankAccountBalanceParams::builder()
+        .bank_account_id(vec_accounts1[0].id)
+        .posted_from(
+            Utc.with_ymd_and_hms(2025, 6, 2, 0, 0, 0)
+                .single()
+                .ok_or("invalid test datetime")?,
+        )
+        .build();
+    let response = client.get(&balance_url).query(&params).send().await?;
+    let response = utils::assert_ok_response(response).await?;
+    let balance2 = response.json::<BankAccountBalance>().await?;
+    assert!((balance2.balance + 45.25).abs() < f32::EPSILON);
+    assert_eq!(balance2.transaction_count, 3);
+
+    // Delete the account using its id.
     let delete_accounts_url = format!("{accounts_url}/delete");
+    let params = BankAccountSearchParams::builder()
+        .ids(vec![vec_accounts1[0].id])
+        .build();
     let response = client
         .post(delete_accounts_url)
         .json(&params)
@@ -64,3 +151,128 @@ async fn accounts_routes() -> Result<(), Box<dyn std::error::Error>> {
     assert_eq!(vec_accounts2, vec_accounts1);
     Ok(())
 }
+
+#[tokio::test]
+#[serial]
+async fn accounts_update_routes() -> Result<(), Box<dyn std::error::Error>> {
+    // Make sure there's a database URL and it points to a test database so
+    // prod isn't goofed during testing.
+    let db_connection_url = dotenvy::var("DATABASE_URL")?;
+    utils::reset_database(&db_connection_url)?;
+
+    // Initialize the server state.
+    let state = toi_server::init(db_connection_url).await?;
+    let openapi_router = OpenApiRouter::new().nest(
+        "/banking/accounts",
+        toi_server::routes::accounts::accounts_router(state.clone()).nest(
+            "/transactions",
+            toi_server::routes::transactions::bank_account_transactions_router(state.clone()),
+        ),
+    );
+    let (router, _) = openapi_router.split_for_parts();
+    let listener = TcpListener::bind(&state.server_config.bind_addr).await?;
+
+    // Spawn server and create a client for all test requests.
+    let _ = tokio::spawn(async move { axum::serve(listener, router).await });
+    let client = reqwest::Client::new();
+    let accounts_url = format!("http://{}/banking/accounts", state.server_config.bind_addr);
+    let bank_account_transactions_url = format!("{accounts_url}/transactions");
+    let balance_url = format!("{accounts_url}/balance");
+
+    // Make an account and its accidental duplicate, each with a few
+    // transactions.
+    let mut accounts = vec![];
+    for description in ["chase checking", "chase checking account"] {
+        let body = NewBankAccountRequest::builder()
+            .description(description.to_string())
+            .build();
+        let response = client.post(&accounts_url).json(&body).send().await?;
+        let response = utils::assert_ok_response(response).await?;
+        accounts.push(response.json::<BankAccount>().await?);
+    }
+    let transactions = [
+        (accounts[0].id, "paycheck", 100.0, 1),
+        (accounts[0].id, "groceries", -20.0, 2),
+        (accounts[1].id, "gas", -30.0, 3),
+        (accounts[1].id, "refund", 5.0, 4),
+        (accounts[1].id, "coffee", -5.0, 5),
+    ];
+    for (bank_account_id, description, amount, day) in transactions {
+        let posted_at = Utc
+            .with_ymd_and_hms(2025, 6, day, 12, 0, 0)
+            .single()
+            .ok_or("invalid test datetime")?;
+        let body = NewBankAccountTransactionRequest::builder()
+            .bank_account_id(bank_account_id)
+            .transaction_description(description.to_string())
+            .transaction_amount(amount)
+            .transaction_posted_at(posted_at)
+            .build();
+        let response = client
+            .post(&bank_account_transactions_url)
+            .json(&body)
+            .send()
+            .await?;
+        utils::assert_ok_response(response).await?;
+    }
+
+    // Updating needs exactly one of a new description or an account to
+    // merge into.
+    let body = UpdateBankAccountRequest::builder()
+        .id(accounts[0].id)
+        .build();
+    let response = client.put(&accounts_url).json(&body).send().await?;
+    assert_eq!(response.status(), reqwest::StatusCode::BAD_REQUEST);
+
+    // Rename the account, which leaves its transactions alone.
+    let body = UpdateBankAccountRequest::builder()
+        .id(accounts[0].id)
+        .description("Chase Joint Checking".to_string())
+        .build();
+    let response = client.put(&accounts_url).json(&body).send().await?;
+    let response = utils::assert_ok_response(response).await?;
+    let updated = response.json::<UpdatedBankAccount>().await?;
+    assert_eq!(updated.bank_account.id, accounts[0].id);
+    assert_eq!(updated.bank_account.description, "Chase Joint Checking");
+    assert_eq!(updated.bank_account.created_at, accounts[0].created_at);
+    assert_eq!(updated.moved_transaction_count, 0);
+    let params = BankAccountBalanceParams::builder()
+        .bank_account_id(accounts[0].id)
+        .build();
+    let response = client.get(&balance_url).query(&params).send().await?;
+    let response = utils::assert_ok_response(response).await?;
+    let balance = response.json::<BankAccountBalance>().await?;
+    assert_eq!(balance.bank_account, updated.bank_account);
+    assert_eq!(balance.transaction_count, 2);
+
+    // An account can't be merged into itself.
+    let body = UpdateBankAccountRequest::builder()
+        .id(accounts[1].id)
+        .merge_into(BankAccountMergeTarget::builder().id(accounts[1].id).build())
+        .build();
+    let response = client.put(&accounts_url).json(&body).send().await?;
+    assert_eq!(response.status(), reqwest::StatusCode::NOT_FOUND);
+
+    // Merge the duplicate into the renamed account, which moves all of its
+    // transactions over and deletes it.
+    let body = UpdateBankAccountRequest::builder()
+        .id(accounts[1].id)
+        .merge_into(BankAccountMergeTarget::builder().id(accounts[0].id).build())
+        .build();
+    let response = client.put(&accounts_url).json(&body).send().await?;
+    let response = utils::assert_ok_response(response).await?;
+    let merged = response.json::<UpdatedBankAccount>().await?;
+    assert_eq!(merged.bank_account, updated.bank_account);
+    assert_eq!(merged.moved_transaction_count, 3);
+    let response = client.get(&balance_url).query(&params).send().await?;
+    let response = utils::assert_ok_response(response).await?;
+    let balance = response.json::<BankAccountBalance>().await?;
+    assert_eq!(balance.transaction_count, 5);
+    assert!((balance.balance - 50.0).abs() < f32::EPSILON);
+    let response = client
+        .get(format!("{accounts_url}/{}", accounts[1].id))
+        .send()
+        .await?;
+    assert_eq!(response.status(), reqwest::StatusCode::NOT_FOUND);
+    Ok(())
+}
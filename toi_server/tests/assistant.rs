@@ -0,0 +1,1549 @@
+use axum::{Json, body::Body, extract::State, routing::post};
+use chrono::TimeDelta;
+use futures::StreamExt;
+use serde_json::{Value, json};
+use serial_test::serial;
+use std::{
+    convert::Infallible,
+    sync::{
+        Arc, Mutex,
+        atomic::{AtomicUsize, Ordering},
+    },
+    time::Duration,
+};
+use toi::{GenerationRequest, Message, MessageRole, ResumePoint};
+use tokio::{net::TcpListener, sync::Notify};
+use utoipa_axum::router::OpenApiRouter;
+
+use toi_server::{
+    auth::{DEFAULT_USER_ID, TokenAuth, authenticate, find_or_add_user, hash_token},
+    models::{
+        assistant::{AssistantCompletion, ChatResponse},
+        conversations::{Conversation, ConversationMessage, NewConversationRequest},
+        notes::{NewNoteRequest, Note, NoteSearchParams},
+        pagination::Page,
+        todos::{Todo, TodoSearchParams},
+    },
+    routes::assistant::{PendingStep, hash_message, store_pending_action, take_pending_action},
+};
+
+mod utils;
+
+/// Notifies when a mock response stream is dropped, which happens once the
+/// connection it's being written to is closed.
+struct DropGuard(Arc<Notify>);
+
+impl Drop for DropGuard {
+    fn drop(&mut self) {
+        self.0.notify_one();
+    }
+}
+
+/// Mock generation API that streams chunks slowly and never finishes.
+async fn slow_completions(State(dropped): State<Arc<Notify>>) -> Body {
+    let stream = futures::stream::unfold(DropGuard(dropped), |guard| async move {
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        let chunk = "data: {\"choices\":[{\"delta\":{\"content\":\"la \"}}]}\n\n";
+        Some((Ok::<_, Infallible>(chunk), guard))
+    });
+    Body::from_stream(stream)
+}
+
+#[tokio::test]
+#[serial]
+async fn assistant_cancellation() -> Result<(), Box<dyn std::error::Error>> {
+    // Make sure there's a database URL and it points to a test database so
+    // prod isn't goofed during testing.
+    let db_connection_url = dotenvy::var("DATABASE_URL")?;
+    utils::reset_database(&db_connection_url)?;
+
+    // Spawn a slow mock generation API that reports when its upstream
+    // connection is closed.
+    let dropped = Arc::new(Notify::new());
+    let mock_router = axum::Router::new()
+        .route("/v1/chat/completions", post(slow_completions))
+        .with_state(dropped.clone());
+    let mock_listener = TcpListener::bind("127.0.0.1:0").await?;
+    let mock_addr = mock_listener.local_addr()?;
+    let _ = tokio::spawn(async move { axum::serve(mock_listener, mock_router).await });
+
+    // Initialize the server state, pointing generation at the mock API. No
+    // other endpoints are added so nothing needs to be embedded.
+    let mut state = toi_server::init(db_connection_url).await?;
+    state.model_client.generation_api_config.base_url = format!("http://{mock_addr}");
+    let mut openapi_router = OpenApiRouter::new();
+    let openapi = openapi_router.get_openapi_mut();
+    let assistant_router =
+        toi_server::routes::assistant::assistant_router(openapi, state.clone()).await?;
+    let openapi_router = openapi_router.nest("/assistant", assistant_router);
+    let (router, _) = openapi_router.split_for_parts();
+    let listener = TcpListener::bind(&state.server_config.bind_addr).await?;
+
+    // Spawn server and create a client for all test requests.
+    let _ = tokio::spawn(async move { axum::serve(listener, router).await });
+    let client = reqwest::Client::new();
+    let assistant_url = format!("http://{}/assistant", state.server_config.bind_addr);
+
+    // Without any messages, the request goes straight to streaming. Read a
+    // little bit of the response and then hang up.
+    let body = GenerationRequest::builder()
+        .messages(Vec::<Message>::new())
+        .build();
+    let mut response = client.post(&assistant_url).json(&body).send().await?;
+    let response_chunk = response.chunk().await?;
+    assert!(response_chunk.is_some());
+    drop(response);
+    drop(client);
+
+    // The upstream connection should close shortly after.
+    tokio::time::timeout(Duration::from_secs(5), dropped.notified()).await?;
+    Ok(())
+}
+
+/// Mock generation API that takes a while before streaming anything, like
+/// when it's working through a big prompt.
+async fn stalled_completions() -> Body {
+    let stream = futures::stream::iter([
+        "data: {\"choices\":[{\"delta\":{\"content\":\"Hello\"}}]}\n\n",
+        "data: [DONE]\n\n",
+    ])
+    .then(|chunk| async move {
+        if chunk.contains("Hello") {
+            tokio::time::sleep(Duration::from_millis(2500)).await;
+        }
+        Ok::<_, Infallible>(chunk)
+    });
+    Body::from_stream(stream)
+}
+
+#[tokio::test]
+#[serial]
+async fn assistant_keep_alive() -> Result<(), Box<dyn std::error::Error>> {
+    // Make sure there's a database URL and it points to a test database so
+    // prod isn't goofed during testing.
+    let db_connection_url = dotenvy::var("DATABASE_URL")?;
+    utils::reset_database(&db_connection_url)?;
+
+    // Spawn a mock generation API that stalls before responding.
+    let mock_router = axum::Router::new().route("/v1/chat/completions", post(stalled_completions));
+    let mock_listener = TcpListener::bind("127.0.0.1:0").await?;
+    let mock_addr = mock_listener.local_addr()?;
+    let _ = tokio::spawn(async move { axum::serve(mock_listener, mock_router).await });
+
+    // Initialize the server state, pointing generation at the mock API and
+    // sending keep-alives every second.
+    let mut state = toi_server::init(db_connection_url).await?;
+    state.model_client.generation_api_config.base_url = format!("http://{mock_addr}");
+    state.server_config.keep_alive_interval = 1;
+    let mut openapi_router = OpenApiRouter::new();
+    let openapi = openapi_router.get_openapi_mut();
+    let assistant_router =
+        toi_server::routes::assistant::assistant_router(openapi, state.clone()).await?;
+    let openapi_router = openapi_router.nest("/assistant", assistant_router);
+    let (router, _) = openapi_router.split_for_parts();
+    let listener = TcpListener::bind(&state.server_config.bind_addr).await?;
+
+    // Spawn server and create a client for all test requests.
+    let _ = tokio::spawn(async move { axum::serve(listener, router).await });
+    let client = reqwest::Client::new();
+    let assistant_url = format!("http://{}/assistant", state.server_config.bind_addr);
+
+    // Keep-alives are sent while the stream stalls, and the content is still
+    // all there.
+    let body = GenerationRequest::builder()
+        .messages(Vec::<Message>::new())
+        .build();
+    let response = client.post(&assistant_url).json(&body).send().await?;
+    let response = utils::assert_ok_response(response).await?;
+    let text = response.text().await?;
+    assert!(text.starts_with(": keep-alive\n\n"));
+    let data_lines: Vec<&str> = text
+        .lines()
+        .filter_map(|line| line.strip_prefix("data: "))
+        .collect();
+    assert!(data_lines[0].contains("Hello"));
+    assert_eq!(data_lines.last(), Some(&"[DONE]"));
+    Ok(())
+}
+
+/// Mock generation API that streams a whole reply the first time it's
+/// called and then streams slowly without ever finishing.
+async fn flaky_completions(
+    State((calls, dropped)): State<(Arc<AtomicUsize>, Arc<Notify>)>,
+) -> Body {
+    if calls.fetch_add(1, Ordering::SeqCst) == 0 {
+        return Body::from(
+            "data: {\"choices\":[{\"delta\":{\"content\":\"Hello there\"}}]}\n\ndata: [DONE]\n\n",
+        );
+    }
+    slow_completions(State(dropped)).await
+}
+
+/// Get a kept reply, waiting a bit for it since it's stored in the
+/// background once its response stream ends.
+async fn wait_for_chat_response(
+    client: &reqwest::Client,
+    url: &str,
+) -> Result<ChatResponse, Box<dyn std::error::Error>> {
+    for _ in 0..50 {
+        let response = client.get(url).send().await?;
+        if response.status() != reqwest::StatusCode::NOT_FOUND {
+            let response = utils::assert_ok_response(response).await?;
+            return Ok(response.json().await?);
+        }
+        tokio::time::sleep(Duration::from_millis(100)).await;
+    }
+    Err(format!("reply at {url} was never kept").into())
+}
+
+#[tokio::test]
+#[serial]
+async fn assistant_responses() -> Result<(), Box<dyn std::error::Error>> {
+    // Make sure there's a database URL and it points to a test database so
+    // prod isn't goofed during testing.
+    let db_connection_url = dotenvy::var("DATABASE_URL")?;
+    utils::reset_database(&db_connection_url)?;
+
+    // Spawn a mock generation API that finishes its first reply but not the
+    // ones after it.
+    let dropped = Arc::new(Notify::new());
+    let mock_router = axum::Router::new()
+        .route("/v1/chat/completions", post(flaky_completions))
+        .with_state((Arc::new(AtomicUsize::new(0)), dropped.clone()));
+    let mock_listener = TcpListener::bind("127.0.0.1:0").await?;
+    let mock_addr = mock_listener.local_addr()?;
+    let _ = tokio::spawn(async move { axum::serve(mock_listener, mock_router).await });
+
+    // Initialize the server state, pointing generation at the mock API.
+    let mut state = toi_server::init(db_connection_url).await?;
+    state.model_client.generation_api_config.base_url = format!("http://{mock_addr}");
+    let mut openapi_router = OpenApiRouter::new();
+    let openapi = openapi_router.get_openapi_mut();
+    let assistant_router =
+        toi_server::routes::assistant::assistant_router(openapi, state.clone()).await?;
+    let openapi_router = openapi_router.nest("/assistant", assistant_router);
+    let (router, _) = openapi_router.split_for_parts();
+    let listener = TcpListener::bind(&state.server_config.bind_addr).await?;
+
+    // Spawn server and create a client for all test requests.
+    let _ = tokio::spawn(async move { axum::serve(listener, router).await });
+    let client = reqwest::Client::new();
+    let assistant_url = format!("http://{}/assistant", state.server_config.bind_addr);
+    let body = GenerationRequest::builder()
+        .messages(Vec::<Message>::new())
+        .build();
+
+    // A reply that's streamed all the way through is kept as complete.
+    let response = client.post(&assistant_url).json(&body).send().await?;
+    let response = utils::assert_ok_response(response).await?;
+    let response_id = response.headers()["x-toi-response-id"]
+        .to_str()?
+        .to_string();
+    response.text().await?;
+    let chat_response =
+        wait_for_chat_response(&client, &format!("{assistant_url}/responses/{response_id}"))
+            .await?;
+    assert_eq!(chat_response.id, response_id);
+    assert_eq!(chat_response.content, "Hello there");
+    assert!(chat_response.complete);
+
+    // A reply the client hangs up on partway through keeps whatever was
+    // streamed before then.
+    let mut response = client.post(&assistant_url).json(&body).send().await?;
+    let response_id = response.headers()["x-toi-response-id"]
+        .to_str()?
+        .to_string();
+    assert!(response.chunk().await?.is_some());
+    drop(response);
+    tokio::time::timeout(Duration::from_secs(5), dropped.notified()).await?;
+    let chat_response =
+        wait_for_chat_response(&client, &format!("{assistant_url}/responses/{response_id}"))
+            .await?;
+    assert!(chat_response.content.starts_with("la "));
+    assert!(!chat_response.complete);
+
+    // Unknown replies aren't found.
+    let response = client
+        .get(format!("{assistant_url}/responses/unknown"))
+        .send()
+        .await?;
+    assert_eq!(response.status(), reqwest::StatusCode::NOT_FOUND);
+    Ok(())
+}
+
+#[tokio::test]
+#[serial]
+async fn assistant_style_instructions() -> Result<(), Box<dyn std::error::Error>> {
+    // Make sure there's a database URL and it points to a test database so
+    // prod isn't goofed during testing.
+    let db_connection_url = dotenvy::var("DATABASE_URL")?;
+    utils::reset_database(&db_connection_url)?;
+
+    // Initialize the server state. No other endpoints are added so nothing
+    // needs to be embedded.
+    let state = toi_server::init(db_connection_url).await?;
+    let mut openapi_router = OpenApiRouter::new();
+    let openapi = openapi_router.get_openapi_mut();
+    let assistant_router =
+        toi_server::routes::assistant::assistant_router(openapi, state.clone()).await?;
+    let openapi_router = openapi_router.nest("/assistant", assistant_router);
+    let (router, _) = openapi_router.split_for_parts();
+    let listener = TcpListener::bind(&state.server_config.bind_addr).await?;
+
+    // Spawn server and create a client for all test requests.
+    let _ = tokio::spawn(async move { axum::serve(listener, router).await });
+    let client = reqwest::Client::new();
+    let assistant_url = format!("http://{}/assistant", state.server_config.bind_addr);
+
+    // Style instructions that are too long are rejected before any model
+    // APIs are used.
+    let max_chars = state.server_config.max_style_instructions_chars;
+    let body = GenerationRequest::builder()
+        .messages(Vec::<Message>::new())
+        .style_instructions("a".repeat(max_chars + 1))
+        .build();
+    let response = client.post(&assistant_url).json(&body).send().await?;
+    assert_eq!(response.status(), reqwest::StatusCode::BAD_REQUEST);
+    Ok(())
+}
+
+#[tokio::test]
+#[serial]
+async fn assistant_sampling_validation() -> Result<(), Box<dyn std::error::Error>> {
+    // Make sure there's a database URL and it points to a test database so
+    // prod isn't goofed during testing.
+    let db_connection_url = dotenvy::var("DATABASE_URL")?;
+    utils::reset_database(&db_connection_url)?;
+
+    // Initialize the server state. No other endpoints are added so nothing
+    // needs to be embedded.
+    let state = toi_server::init(db_connection_url).await?;
+    let mut openapi_router = OpenApiRouter::new();
+    let openapi = openapi_router.get_openapi_mut();
+    let assistant_router =
+        toi_server::routes::assistant::assistant_router(openapi, state.clone()).await?;
+    let openapi_router = openapi_router.nest("/assistant", assistant_router);
+    let (router, _) = openapi_router.split_for_parts();
+    let listener = TcpListener::bind(&state.server_config.bind_addr).await?;
+
+    // Spawn server and create a client for all test requests.
+    let _ = tokio::spawn(async move { axum::serve(listener, router).await });
+    let client = reqwest::Client::new();
+    let assistant_url = format!("http://{}/assistant", state.server_config.bind_addr);
+
+    // Out of range sampling parameters are rejected before any model APIs
+    // are used.
+    let bodies = [
+        GenerationRequest::builder()
+            .messages(Vec::<Message>::new())
+            .temperature(2.5)
+            .build(),
+        GenerationRequest::builder()
+            .messages(Vec::<Message>::new())
+            .top_p(0.0)
+            .build(),
+        GenerationRequest::builder()
+            .messages(Vec::<Message>::new())
+            .max_tokens(0)
+            .build(),
+    ];
+    for body in bodies {
+        let response = client.post(&assistant_url).json(&body).send().await?;
+        assert_eq!(response.status(), reqwest::StatusCode::BAD_REQUEST);
+    }
+    Ok(())
+}
+
+/// Mock generation API that never responds with valid JSON. Requests are
+/// kept so tests can check how many were made.
+async fn unparseable_completions(
+    State(requests): State<Arc<Mutex<Vec<Value>>>>,
+    Json(body): Json<Value>,
+) -> Json<Value> {
+    requests
+        .lock()
+        .expect("requests lock shouldn't be poisoned")
+        .push(body);
+    Json(json!({
+        "choices": [{"message": {"role": "assistant", "content": "I'm not sure what you mean."}}]
+    }))
+}
+
+#[tokio::test]
+#[serial]
+async fn assistant_unparseable_output() -> Result<(), Box<dyn std::error::Error>> {
+    // Make sure there's a database URL and it points to a test database so
+    // prod isn't goofed during testing.
+    let db_connection_url = dotenvy::var("DATABASE_URL")?;
+    utils::reset_database(&db_connection_url)?;
+
+    // Spawn a mock generation API that only responds with prose.
+    let requests = Arc::new(Mutex::new(vec![]));
+    let mock_router = axum::Router::new()
+        .route("/v1/chat/completions", post(unparseable_completions))
+        .with_state(requests.clone());
+    let mock_listener = TcpListener::bind("127.0.0.1:0").await?;
+    let mock_addr = mock_listener.local_addr()?;
+    let _ = tokio::spawn(async move { axum::serve(mock_listener, mock_router).await });
+
+    // Initialize the server state, pointing generation at the mock API. No
+    // other endpoints are added so nothing needs to be embedded.
+    let mut state = toi_server::init(db_connection_url).await?;
+    state.model_client.generation_api_config.base_url = format!("http://{mock_addr}");
+    let mut openapi_router = OpenApiRouter::new();
+    let openapi = openapi_router.get_openapi_mut();
+    let assistant_router =
+        toi_server::routes::assistant::assistant_router(openapi, state.clone()).await?;
+    let openapi_router = openapi_router.nest("/assistant", assistant_router);
+    let (router, _) = openapi_router.split_for_parts();
+    let listener = TcpListener::bind(&state.server_config.bind_addr).await?;
+
+    // Spawn server and create a client for all test requests.
+    let _ = tokio::spawn(async move { axum::serve(listener, router).await });
+    let client = reqwest::Client::new();
+    let assistant_url = format!("http://{}/assistant", state.server_config.bind_addr);
+
+    // Extracting the command is retried once with a reminder to only respond
+    // with JSON, and then the request fails.
+    let body = GenerationRequest::builder()
+        .messages(vec![Message {
+            role: MessageRole::User,
+            content: "Add a note saying hi".to_string(),
+        }])
+        .build();
+    let response = client.post(&assistant_url).json(&body).send().await?;
+    assert_eq!(response.status(), reqwest::StatusCode::UNPROCESSABLE_ENTITY);
+    let requests = requests
+        .lock()
+        .expect("requests lock shouldn't be poisoned")
+        .clone();
+    assert_eq!(requests.len(), 2);
+    let reminder = requests[1]["messages"]
+        .as_array()
+        .and_then(|messages| messages.last())
+        .expect("retry should have messages");
+    assert_eq!(reminder["role"], "system");
+    assert!(
+        reminder["content"]
+            .as_str()
+            .is_some_and(|content| content.contains("only the JSON object"))
+    );
+    Ok(())
+}
+
+/// Tracks how many embedding requests are in flight at once.
+#[derive(Clone, Default)]
+struct InFlight {
+    current: Arc<AtomicUsize>,
+    max: Arc<AtomicUsize>,
+}
+
+/// Mock embedding API that takes a while to respond so concurrent requests
+/// overlap.
+async fn slow_embeddings(State(in_flight): State<InFlight>) -> Json<Value> {
+    let current = in_flight.current.fetch_add(1, Ordering::SeqCst) + 1;
+    in_flight.max.fetch_max(current, Ordering::SeqCst);
+    tokio::time::sleep(Duration::from_millis(50)).await;
+    in_flight.current.fetch_sub(1, Ordering::SeqCst);
+    Json(json!({"data": [{"embedding": [1.0, 0.0, 0.0], "index": 0}]}))
+}
+
+#[tokio::test]
+#[serial]
+async fn assistant_startup_embedding() -> Result<(), Box<dyn std::error::Error>> {
+    // Make sure there's a database URL and it points to a test database so
+    // prod isn't goofed during testing.
+    let db_connection_url = dotenvy::var("DATABASE_URL")?;
+    utils::reset_database(&db_connection_url)?;
+
+    // Spawn a slow mock embedding API that tracks overlapping requests.
+    let in_flight = InFlight::default();
+    let mock_router = axum::Router::new()
+        .route("/v1/embeddings", post(slow_embeddings))
+        .with_state(in_flight.clone());
+    let mock_listener = TcpListener::bind("127.0.0.1:0").await?;
+    let mock_addr = mock_listener.local_addr()?;
+    let _ = tokio::spawn(async move { axum::serve(mock_listener, mock_router).await });
+
+    // Endpoint descriptions are embedded concurrently when the assistant
+    // router is made.
+    let mut state = toi_server::init(db_connection_url).await?;
+    state.model_client.embedding_api_config.base_url = format!("http://{mock_addr}");
+    let mut openapi_router = OpenApiRouter::new().nest(
+        "/notes",
+        toi_server::routes::notes::notes_router(state.clone()),
+    );
+    let openapi = openapi_router.get_openapi_mut();
+    toi_server::routes::assistant::assistant_router(openapi, state.clone()).await?;
+    assert!(in_flight.max.load(Ordering::SeqCst) > 1);
+    assert!(in_flight.max.load(Ordering::SeqCst) <= state.server_config.embedding_concurrency);
+    Ok(())
+}
+
+/// Scripted model APIs for a two-step plan that adds a note and then a
+/// todo, and for deleting all notes. Summary requests are kept so tests can
+/// check what was summarized. Each call that isn't streamed uses 10 prompt
+/// tokens and 1 completion token.
+#[derive(Clone, Default)]
+struct MockModels {
+    summaries: Arc<Mutex<Vec<Value>>>,
+    fail_todos: Arc<Mutex<bool>>,
+    todo_score: Arc<Mutex<f64>>,
+}
+
+const MOCK_COMMANDS: [&str; 3] = ["add a note", "add a todo", "delete all notes"];
+
+/// Embed inputs by which of the scripted commands they mention so each
+/// command's API is found by search.
+fn mock_embedding(input: &str) -> Vec<f32> {
+    let input = input.to_lowercase();
+    MOCK_COMMANDS
+        .iter()
+        .map(|command| f32::from(u8::from(input.contains(command))))
+        .chain([1.0])
+        .collect()
+}
+
+async fn mock_rerank(Json(request): Json<Value>) -> Json<Value> {
+    let query = request["query"].as_str().unwrap_or_default().to_lowercase();
+    let documents = request["documents"].as_array().cloned().unwrap_or_default();
+    let mut results: Vec<Value> = documents
+        .iter()
+        .enumerate()
+        .map(|(index, document)| {
+            let text = document.as_str().unwrap_or_default();
+            let relevance_score = if text.to_lowercase().contains(&query) {
+                0.99
+            } else {
+                0.01
+            };
+            json!({"index": index, "document": {"text": text}, "relevance_score": relevance_score})
+        })
+        .collect();
+    results.sort_by(|a, b| {
+        b["relevance_score"]
+            .as_f64()
+            .partial_cmp(&a["relevance_score"].as_f64())
+            .expect("scores should be comparable")
+    });
+    Json(json!({"results": results}))
+}
+
+/// Mock reranking API like `mock_rerank`, except documents that don't
+/// contain the query are still relevant enough to be picked.
+async fn lenient_rerank(Json(request): Json<Value>) -> Json<Value> {
+    let Json(mut response) = mock_rerank(Json(request)).await;
+    if let Some(results) = response["results"].as_array_mut() {
+        for result in results {
+            if result["relevance_score"].as_f64() < Some(0.5) {
+                result["relevance_score"] = json!(0.6);
+            }
+        }
+    }
+    Json(response)
+}
+
+/// Mock reranking API like `mock_rerank`, except documents about todos are
+/// scored at the mock models' todo score so they can come close to the most
+/// relevant document.
+async fn todo_rerank(State(models): State<MockModels>, Json(request): Json<Value>) -> Json<Value> {
+    let todo_score = *models
+        .todo_score
+        .lock()
+        .expect("score shouldn't be poisoned");
+    let Json(mut response) = mock_rerank(Json(request)).await;
+    if let Some(results) = response["results"].as_array_mut() {
+        for result in results.iter_mut() {
+            let text = result["document"]["text"]
+                .as_str()
+                .unwrap_or_default()
+                .to_lowercase();
+            if result["relevance_score"].as_f64() < Some(0.5)
+                && (text.contains("todo") || text.contains("task"))
+            {
+                result["relevance_score"] = json!(todo_score);
+            }
+        }
+        results.sort_by(|a, b| {
+            b["relevance_score"]
+                .as_f64()
+                .partial_cmp(&a["relevance_score"].as_f64())
+                .expect("scores should be comparable")
+        });
+    }
+    Json(response)
+}
+
+async fn mock_completions(State(models): State<MockModels>, Json(request): Json<Value>) -> Body {
+    // The final summary is streamed.
+    if request["stream"] == json!(true) {
+        models
+            .summaries
+            .lock()
+            .expect("summaries shouldn't be poisoned")
+            .push(request["messages"].clone());
+        return Body::from(
+            "data: {\"choices\":[{\"delta\":{\"content\":\"Done.\"}}]}\n\ndata: [DONE]\n\n",
+        );
+    }
+    let usage = json!({"prompt_tokens": 10, "completion_tokens": 1});
+
+    // So is the final summary when it's generated all at once, which is the
+    // only call without a response format.
+    if request["response_format"].is_null() {
+        models
+            .summaries
+            .lock()
+            .expect("summaries shouldn't be poisoned")
+            .push(request["messages"].clone());
+        let response = json!({
+            "choices": [{"message": {"role": "assistant", "content": "Done."}}],
+            "usage": usage
+        });
+        return Body::from(response.to_string());
+    }
+
+    // Everything else is scripted by the system prompt, the user's latest
+    // message, and the response format.
+    let messages = request["messages"].as_array().cloned().unwrap_or_default();
+    let system_prompt = messages
+        .first()
+        .and_then(|message| message["content"].as_str())
+        .unwrap_or_default();
+    let latest_message = messages
+        .last()
+        .and_then(|message| message["content"].as_str())
+        .unwrap_or_default()
+        .to_lowercase();
+    let content = if system_prompt.contains("extract the command") {
+        if latest_message.contains("then delete all notes") {
+            json!({
+                "command": "add a note",
+                "target": "buy milk",
+                "steps": ["add a note", "add a todo", "delete all notes"]
+            })
+        } else if latest_message.contains("jot down") {
+            json!({"command": "add a note", "target": "buy milk", "steps": ["add a note"]})
+        } else if latest_message.contains("delete all notes") {
+            json!({"command": "delete all notes", "target": "notes", "steps": ["delete all notes"]})
+        } else if latest_message.contains("add a note") {
+            json!({"command": "add a note", "target": "buy milk", "steps": ["add a note", "add a todo"]})
+        } else {
+            json!({"command": null, "target": null, "steps": []})
+        }
+    } else if system_prompt.contains("confirms") {
+        json!({"confirmed": latest_message.contains("yes")})
+    } else {
+        let properties = &request["response_format"]["json_schema"]["schema"]["properties"];
+        let path = &properties["path"]["enum"][0];
+        match path.as_str() {
+            // Ask which API is meant whenever it's offered.
+            _ if !properties["clarification"].is_null() => json!({
+                "path": path,
+                "method": "POST",
+                "clarification": "Do you want a note or a todo?"
+            }),
+            Some("/notes") => {
+                json!({"path": "/notes", "method": "POST", "body": {"content": "buy milk"}})
+            }
+            Some("/notes/delete") => json!({
+                "path": "/notes/delete",
+                "method": "POST",
+                "body": {"confirm_delete_all": true}
+            }),
+            _ if *models
+                .fail_todos
+                .lock()
+                .expect("flag shouldn't be poisoned") =>
+            {
+                json!({"path": "/todos", "method": "POST", "body": {}})
+            }
+            _ => json!({"path": "/todos", "method": "POST", "body": {"item": "buy milk"}}),
+        }
+    };
+    let response = json!({
+        "choices": [{"message": {"role": "assistant", "content": content.to_string()}}],
+        "usage": usage
+    });
+    Body::from(response.to_string())
+}
+
+#[tokio::test]
+#[serial]
+async fn assistant_plans() -> Result<(), Box<dyn std::error::Error>> {
+    // Make sure there's a database URL and it points to a test database so
+    // prod isn't goofed during testing.
+    let db_connection_url = dotenvy::var("DATABASE_URL")?;
+    utils::reset_database(&db_connection_url)?;
+
+    // Spawn scripted model APIs.
+    let models = MockModels::default();
+    let mock_router = axum::Router::new()
+        .route(
+            "/v1/embeddings",
+            utils::mock_embeddings_with(mock_embedding),
+        )
+        .route("/v1/rerank", post(mock_rerank))
+        .route("/v1/chat/completions", post(mock_completions))
+        .with_state(models.clone());
+    let mock_listener = TcpListener::bind("127.0.0.1:0").await?;
+    let mock_addr = mock_listener.local_addr()?;
+    let _ = tokio::spawn(async move { axum::serve(mock_listener, mock_router).await });
+
+    // Initialize the server state, pointing all model APIs at the mocks.
+    let mut state = toi_server::init(db_connection_url).await?;
+    let mock_url = format!("http://{mock_addr}");
+    state.model_client.embedding_api_config.base_url = mock_url.clone();
+    state.model_client.generation_api_config.base_url = mock_url.clone();
+    state.model_client.reranking_api_config.base_url = mock_url;
+    let mut openapi_router = OpenApiRouter::new()
+        .nest(
+            "/notes",
+            toi_server::routes::notes::notes_router(state.clone()),
+        )
+        .nest(
+            "/todos",
+            toi_server::routes::todos::todos_router(state.clone()),
+        );
+    let openapi = openapi_router.get_openapi_mut();
+    let assistant_router =
+        toi_server::routes::assistant::assistant_router(openapi, state.clone()).await?;
+    let openapi_router = openapi_router.nest("/assistant", assistant_router);
+    let (router, _) = openapi_router.split_for_parts();
+    let listener = TcpListener::bind(&state.server_config.bind_addr).await?;
+
+    // Spawn server and create a client for all test requests.
+    let _ = tokio::spawn(async move { axum::serve(listener, router).await });
+    let client = reqwest::Client::new();
+    let base_url = format!("http://{}", state.server_config.bind_addr);
+    let assistant_url = format!("{base_url}/assistant");
+    let body = GenerationRequest::builder()
+        .messages(vec![Message {
+            role: MessageRole::User,
+            content: "add a note and a todo to buy milk".to_string(),
+        }])
+        .build();
+
+    // Both steps are done in order, and both responses are summarized.
+    let response = client.post(&assistant_url).json(&body).send().await?;
+    let response = utils::assert_ok_response(response).await?;
+    assert!(response.text().await?.contains("Done."));
+    let response = client
+        .post(format!("{base_url}/notes/search"))
+        .json(&NoteSearchParams::builder().build())
+        .send()
+        .await?;
+    let notes = utils::assert_ok_response(response)
+        .await?
+        .json::<Page<Note>>()
+        .await?
+        .items;
+    assert_eq!(notes.len(), 1);
+    assert_eq!(notes[0].content, "buy milk");
+    let response = client
+        .post(format!("{base_url}/todos/search"))
+        .json(&TodoSearchParams::builder().build())
+        .send()
+        .await?;
+    let todos = utils::assert_ok_response(response)
+        .await?
+        .json::<Page<Todo>>()
+        .await?
+        .items;
+    assert_eq!(todos.len(), 1);
+    assert_eq!(todos[0].item, "buy milk");
+    let summary = models
+        .summaries
+        .lock()
+        .expect("summaries shouldn't be poisoned")
+        .pop()
+        .expect("plan should be summarized");
+    let roles: Vec<&str> = summary
+        .as_array()
+        .expect("messages should be an array")
+        .iter()
+        .filter_map(|message| message["role"].as_str())
+        .collect();
+    assert_eq!(
+        roles,
+        vec![
+            "system",
+            "user",
+            "assistant",
+            "user",
+            "assistant",
+            "user",
+            "user"
+        ]
+    );
+    let system_prompt = summary[0]["content"].as_str().unwrap_or_default();
+    assert!(system_prompt.contains(&format!("Timezone: {}", state.server_config.timezone)));
+    assert!(system_prompt.contains("which steps were skipped"));
+    let last_message = summary
+        .as_array()
+        .and_then(|messages| messages.last())
+        .and_then(|message| message["content"].as_str())
+        .unwrap_or_default();
+    assert!(last_message.starts_with("All 2 steps of the plan were done."));
+
+    // A failing step stops the plan, and the failure is summarized.
+    *models
+        .fail_todos
+        .lock()
+        .expect("flag shouldn't be poisoned") = true;
+    let response = client.post(&assistant_url).json(&body).send().await?;
+    utils::assert_ok_response(response).await?;
+    let summary = models
+        .summaries
+        .lock()
+        .expect("summaries shouldn't be poisoned")
+        .pop()
+        .expect("plan should be summarized");
+    let last_message = summary
+        .as_array()
+        .and_then(|messages| messages.last())
+        .and_then(|message| message["content"].as_str())
+        .unwrap_or_default();
+    assert!(last_message.starts_with("The plan stopped at step 2 of 2"));
+
+    // A failing step in the middle of a longer plan also skips the steps
+    // after it, so the notes aren't deleted.
+    let body = GenerationRequest::builder()
+        .messages(vec![Message {
+            role: MessageRole::User,
+            content: "add a note and a todo to buy milk, then delete all notes".to_string(),
+        }])
+        .build();
+    let response = client.post(&assistant_url).json(&body).send().await?;
+    utils::assert_ok_response(response).await?;
+    let summary = models
+        .summaries
+        .lock()
+        .expect("summaries shouldn't be poisoned")
+        .pop()
+        .expect("plan should be summarized");
+    let last_message = summary
+        .as_array()
+        .and_then(|messages| messages.last())
+        .and_then(|message| message["content"].as_str())
+        .unwrap_or_default();
+    assert!(last_message.starts_with("The plan stopped at step 2 of 3"));
+    assert!(last_message.contains("1. `add a note`: succeeded"));
+    assert!(last_message.contains("2. `add a todo`: failed because"));
+    assert!(last_message.contains("3. `delete all notes`: skipped"));
+    let response = client
+        .post(format!("{base_url}/notes/search"))
+        .json(&NoteSearchParams::builder().build())
+        .send()
+        .await?;
+    let notes = utils::assert_ok_response(response)
+        .await?
+        .json::<Page<Note>>()
+        .await?
+        .items;
+    assert_eq!(notes.len(), 2);
+    Ok(())
+}
+
+async fn count_notes(
+    client: &reqwest::Client,
+    base_url: &str,
+) -> Result<usize, Box<dyn std::error::Error>> {
+    let response = client
+        .post(format!("{base_url}/notes/search"))
+        .json(&NoteSearchParams::builder().build())
+        .send()
+        .await?;
+    Ok(utils::assert_ok_response(response)
+        .await?
+        .json::<Page<Note>>()
+        .await?
+        .items
+        .len())
+}
+
+#[tokio::test]
+#[serial]
+async fn assistant_confirmations() -> Result<(), Box<dyn std::error::Error>> {
+    // Make sure there's a database URL and it points to a test database so
+    // prod isn't goofed during testing.
+    let db_connection_url = dotenvy::var("DATABASE_URL")?;
+    utils::reset_database(&db_connection_url)?;
+
+    // Spawn scripted model APIs.
+    let models = MockModels::default();
+    let mock_router = axum::Router::new()
+        .route(
+            "/v1/embeddings",
+            utils::mock_embeddings_with(mock_embedding),
+        )
+        .route("/v1/rerank", post(mock_rerank))
+        .route("/v1/chat/completions", post(mock_completions))
+        .with_state(models.clone());
+    let mock_listener = TcpListener::bind("127.0.0.1:0").await?;
+    let mock_addr = mock_listener.local_addr()?;
+    let _ = tokio::spawn(async move { axum::serve(mock_listener, mock_router).await });
+
+    // Initialize the server state, pointing all model APIs at the mocks.
+    let mut state = toi_server::init(db_connection_url).await?;
+    let mock_url = format!("http://{mock_addr}");
+    state.model_client.embedding_api_config.base_url = mock_url.clone();
+    state.model_client.generation_api_config.base_url = mock_url.clone();
+    state.model_client.reranking_api_config.base_url = mock_url;
+    let mut openapi_router = OpenApiRouter::new().nest(
+        "/notes",
+        toi_server::routes::notes::notes_router(state.clone()),
+    );
+    let openapi = openapi_router.get_openapi_mut();
+    let assistant_router =
+        toi_server::routes::assistant::assistant_router(openapi, state.clone()).await?;
+    let openapi_router = openapi_router.nest("/assistant", assistant_router);
+    let (router, _) = openapi_router.split_for_parts();
+    let listener = TcpListener::bind(&state.server_config.bind_addr).await?;
+
+    // Spawn server and create a client for all test requests.
+    let _ = tokio::spawn(async move { axum::serve(listener, router).await });
+    let client = reqwest::Client::new();
+    let base_url = format!("http://{}", state.server_config.bind_addr);
+    let assistant_url = format!("{base_url}/assistant");
+    let response = client
+        .post(format!("{base_url}/notes"))
+        .json(
+            &NewNoteRequest::builder()
+                .content("buy milk".to_string())
+                .build(),
+        )
+        .send()
+        .await?;
+    utils::assert_ok_response(response).await?;
+    let delete_message = Message {
+        role: MessageRole::User,
+        content: "delete all notes".to_string(),
+    };
+    let delete_body = GenerationRequest::builder()
+        .messages(vec![delete_message.clone()])
+        .build();
+    let confirm_body = GenerationRequest::builder()
+        .messages(vec![
+            delete_message.clone(),
+            Message {
+                role: MessageRole::Assistant,
+                content: "Done.".to_string(),
+            },
+            Message {
+                role: MessageRole::User,
+                content: "yes, do it".to_string(),
+            },
+        ])
+        .build();
+
+    // Deleting isn't done right away. The user is shown what would be
+    // deleted instead.
+    let response = client
+        .post(&assistant_url)
+        .json(&delete_body)
+        .send()
+        .await?;
+    utils::assert_ok_response(response).await?;
+    assert_eq!(count_notes(&client, &base_url).await?, 1);
+    let summary = models
+        .summaries
+        .lock()
+        .expect("summaries shouldn't be poisoned")
+        .pop()
+        .expect("pending action should be described");
+    let last_message = summary
+        .as_array()
+        .and_then(|messages| messages.last())
+        .and_then(|message| message["content"].as_str())
+        .unwrap_or_default();
+    assert!(last_message.contains("These items would be affected"));
+    assert!(last_message.contains("buy milk"));
+
+    // Pending actions expire.
+    let mut conn = state.pool.get().await?;
+    let pending_action = take_pending_action(
+        DEFAULT_USER_ID,
+        None,
+        &hash_message(&delete_message.content),
+        TimeDelta::zero(),
+        &mut conn,
+    )
+    .await?;
+    assert!(pending_action.is_none());
+    drop(conn);
+    let response = client
+        .post(&assistant_url)
+        .json(&confirm_body)
+        .send()
+        .await?;
+    utils::assert_ok_response(response).await?;
+    assert_eq!(count_notes(&client, &base_url).await?, 1);
+
+    // Confirming deletes the notes.
+    let response = client
+        .post(&assistant_url)
+        .json(&delete_body)
+        .send()
+        .await?;
+    utils::assert_ok_response(response).await?;
+    assert_eq!(count_notes(&client, &base_url).await?, 1);
+    let response = client
+        .post(&assistant_url)
+        .json(&confirm_body)
+        .send()
+        .await?;
+    utils::assert_ok_response(response).await?;
+    assert_eq!(count_notes(&client, &base_url).await?, 0);
+    Ok(())
+}
+
+#[tokio::test]
+#[serial]
+async fn assistant_endpoint_hints() -> Result<(), Box<dyn std::error::Error>> {
+    // Make sure there's a database URL and it points to a test database so
+    // prod isn't goofed during testing.
+    let db_connection_url = dotenvy::var("DATABASE_URL")?;
+    utils::reset_database(&db_connection_url)?;
+
+    // Spawn scripted model APIs that find every API somewhat relevant.
+    let models = MockModels::default();
+    let mock_router = axum::Router::new()
+        .route(
+            "/v1/embeddings",
+            utils::mock_embeddings_with(mock_embedding),
+        )
+        .route("/v1/rerank", post(lenient_rerank))
+        .route("/v1/chat/completions", post(mock_completions))
+        .with_state(models.clone());
+    let mock_listener = TcpListener::bind("127.0.0.1:0").await?;
+    let mock_addr = mock_listener.local_addr()?;
+    let _ = tokio::spawn(async move { axum::serve(mock_listener, mock_router).await });
+
+    // Initialize the server state, pointing all model APIs at the mocks.
+    let mut state = toi_server::init(db_connection_url).await?;
+    let mock_url = format!("http://{mock_addr}");
+    state.model_client.embedding_api_config.base_url = mock_url.clone();
+    state.model_client.generation_api_config.base_url = mock_url.clone();
+    state.model_client.reranking_api_config.base_url = mock_url;
+    let mut openapi_router = OpenApiRouter::new()
+        .nest(
+            "/notes",
+            toi_server::routes::notes::notes_router(state.clone()),
+        )
+        .nest(
+            "/todos",
+            toi_server::routes::todos::todos_router(state.clone()),
+        );
+    let openapi = openapi_router.get_openapi_mut();
+    let assistant_router =
+        toi_server::routes::assistant::assistant_router(openapi, state.clone()).await?;
+    let openapi_router = openapi_router.nest("/assistant", assistant_router);
+    let (router, _) = openapi_router.split_for_parts();
+    let listener = TcpListener::bind(&state.server_config.bind_addr).await?;
+
+    // Spawn server and create a client for all test requests.
+    let _ = tokio::spawn(async move { axum::serve(listener, router).await });
+    let client = reqwest::Client::new();
+    let base_url = format!("http://{}", state.server_config.bind_addr);
+    let assistant_url = format!("{base_url}/assistant");
+    let messages = vec![Message {
+        role: MessageRole::User,
+        content: "jot down buy milk".to_string(),
+    }];
+
+    // The command is for notes, but the hint points it at todos instead.
+    let body = GenerationRequest::builder()
+        .messages(messages.clone())
+        .endpoint_hint("todos/".to_string())
+        .build();
+    let response = client.post(&assistant_url).json(&body).send().await?;
+    utils::assert_ok_response(response).await?;
+    assert_eq!(count_notes(&client, &base_url).await?, 0);
+    let response = client
+        .post(format!("{base_url}/todos/search"))
+        .json(&TodoSearchParams::builder().build())
+        .send()
+        .await?;
+    let todos = utils::assert_ok_response(response)
+        .await?
+        .json::<Page<Todo>>()
+        .await?
+        .items;
+    assert_eq!(todos.len(), 1);
+
+    // Without any APIs under the hint, all of them are searched.
+    let body = GenerationRequest::builder()
+        .messages(messages)
+        .endpoint_hint("/recipes".to_string())
+        .build();
+    let response = client.post(&assistant_url).json(&body).send().await?;
+    utils::assert_ok_response(response).await?;
+    assert_eq!(count_notes(&client, &base_url).await?, 1);
+    Ok(())
+}
+
+#[tokio::test]
+#[serial]
+async fn assistant_near_ties() -> Result<(), Box<dyn std::error::Error>> {
+    // Make sure there's a database URL and it points to a test database so
+    // prod isn't goofed during testing.
+    let db_connection_url = dotenvy::var("DATABASE_URL")?;
+    utils::reset_database(&db_connection_url)?;
+
+    // Spawn scripted model APIs that score todo APIs as they're told to.
+    let models = MockModels::default();
+    let mock_router = axum::Router::new()
+        .route(
+            "/v1/embeddings",
+            utils::mock_embeddings_with(mock_embedding),
+        )
+        .route("/v1/rerank", post(todo_rerank))
+        .route("/v1/chat/completions", post(mock_completions))
+        .with_state(models.clone());
+    let mock_listener = TcpListener::bind("127.0.0.1:0").await?;
+    let mock_addr = mock_listener.local_addr()?;
+    let _ = tokio::spawn(async move { axum::serve(mock_listener, mock_router).await });
+
+    // Initialize the server state, pointing all model APIs at the mocks.
+    let mut state = toi_server::init(db_connection_url).await?;
+    let mock_url = format!("http://{mock_addr}");
+    state.model_client.embedding_api_config.base_url = mock_url.clone();
+    state.model_client.generation_api_config.base_url = mock_url.clone();
+    state.model_client.reranking_api_config.base_url = mock_url;
+    state.server_config.api_tie_margin = 0.05;
+    let mut openapi_router = OpenApiRouter::new()
+        .nest(
+            "/notes",
+            toi_server::routes::notes::notes_router(state.clone()),
+        )
+        .nest(
+            "/todos",
+            toi_server::routes::todos::todos_router(state.clone()),
+        );
+    let openapi = openapi_router.get_openapi_mut();
+    let assistant_router =
+        toi_server::routes::assistant::assistant_router(openapi, state.clone()).await?;
+    let openapi_router = openapi_router.nest("/assistant", assistant_router);
+    let (router, _) = openapi_router.split_for_parts();
+    let listener = TcpListener::bind(&state.server_config.bind_addr).await?;
+
+    // Spawn server and create a client for all test requests.
+    let _ = tokio::spawn(async move { axum::serve(listener, router).await });
+    let client = reqwest::Client::new();
+    let base_url = format!("http://{}", state.server_config.bind_addr);
+    let assistant_url = format!("{base_url}/assistant");
+    let body = GenerationRequest::builder()
+        .messages(vec![Message {
+            role: MessageRole::User,
+            content: "jot down buy milk".to_string(),
+        }])
+        .build();
+
+    // Todo APIs scored well below the note API don't get in the way of
+    // adding the note.
+    *models
+        .todo_score
+        .lock()
+        .expect("score shouldn't be poisoned") = 0.9;
+    let response = client.post(&assistant_url).json(&body).send().await?;
+    utils::assert_ok_response(response).await?;
+    assert_eq!(count_notes(&client, &base_url).await?, 1);
+    let summary = models
+        .summaries
+        .lock()
+        .expect("summaries shouldn't be poisoned")
+        .pop()
+        .expect("response should be summarized");
+    let system_prompt = summary[0]["content"].as_str().unwrap_or_default();
+    assert!(!system_prompt.contains("**Question**"));
+
+    // A todo API scored within the tie margin gets the user asked which
+    // one they mean instead of adding another note.
+    *models
+        .todo_score
+        .lock()
+        .expect("score shouldn't be poisoned") = 0.97;
+    let response = client.post(&assistant_url).json(&body).send().await?;
+    utils::assert_ok_response(response).await?;
+    assert_eq!(count_notes(&client, &base_url).await?, 1);
+    let reply = models
+        .summaries
+        .lock()
+        .expect("summaries shouldn't be poisoned")
+        .pop()
+        .expect("question should be asked");
+    let system_prompt = reply[0]["content"].as_str().unwrap_or_default();
+    assert!(system_prompt.contains("**Question**\nDo you want a note or a todo?"));
+    Ok(())
+}
+
+#[tokio::test]
+#[serial]
+async fn assistant_completion() -> Result<(), Box<dyn std::error::Error>> {
+    // Make sure there's a database URL and it points to a test database so
+    // prod isn't goofed during testing.
+    let db_connection_url = dotenvy::var("DATABASE_URL")?;
+    utils::reset_database(&db_connection_url)?;
+
+    // Spawn scripted model APIs.
+    let models = MockModels::default();
+    let mock_router = axum::Router::new()
+        .route(
+            "/v1/embeddings",
+            utils::mock_embeddings_with(mock_embedding),
+        )
+        .route("/v1/rerank", post(mock_rerank))
+        .route("/v1/chat/completions", post(mock_completions))
+        .with_state(models.clone());
+    let mock_listener = TcpListener::bind("127.0.0.1:0").await?;
+    let mock_addr = mock_listener.local_addr()?;
+    let _ = tokio::spawn(async move { axum::serve(mock_listener, mock_router).await });
+
+    // Initialize the server state, pointing all model APIs at the mocks.
+    let mut state = toi_server::init(db_connection_url).await?;
+    let mock_url = format!("http://{mock_addr}");
+    state.model_client.embedding_api_config.base_url = mock_url.clone();
+    state.model_client.generation_api_config.base_url = mock_url.clone();
+    state.model_client.reranking_api_config.base_url = mock_url;
+    let mut openapi_router = OpenApiRouter::new().nest(
+        "/notes",
+        toi_server::routes::notes::notes_router(state.clone()),
+    );
+    let openapi = openapi_router.get_openapi_mut();
+    let assistant_router =
+        toi_server::routes::assistant::assistant_router(openapi, state.clone()).await?;
+    let openapi_router = openapi_router.nest("/assistant", assistant_router);
+    let (router, _) = openapi_router.split_for_parts();
+    let listener = TcpListener::bind(&state.server_config.bind_addr).await?;
+
+    // Spawn server and create a client for all test requests.
+    let _ = tokio::spawn(async move { axum::serve(listener, router).await });
+    let client = reqwest::Client::new();
+    let base_url = format!("http://{}", state.server_config.bind_addr);
+    let completion_url = format!("{base_url}/assistant/completion");
+
+    // Messages without a command are answered like a normal chat, without
+    // making any requests.
+    let body = GenerationRequest::builder()
+        .messages(vec![Message {
+            role: MessageRole::User,
+            content: "how are you?".to_string(),
+        }])
+        .build();
+    let response = client.post(&completion_url).json(&body).send().await?;
+    let response = utils::assert_ok_response(response).await?;
+    let completion = response.json::<AssistantCompletion>().await?;
+    assert_eq!(completion.content, "Done.");
+    assert!(completion.executed_request.is_none());
+    assert_eq!(completion.token_usage.prompt_tokens, 20);
+    assert_eq!(completion.token_usage.completion_tokens, 2);
+    assert_eq!(count_notes(&client, &base_url).await?, 0);
+
+    // Commands are fulfilled with a request to the matching endpoint, and
+    // its response is summarized.
+    let body = GenerationRequest::builder()
+        .messages(vec![Message {
+            role: MessageRole::User,
+            content: "jot down that I need to buy milk".to_string(),
+        }])
+        .build();
+    let response = client.post(&completion_url).json(&body).send().await?;
+    let response = utils::assert_ok_response(response).await?;
+    let completion = response.json::<AssistantCompletion>().await?;
+    assert_eq!(completion.content, "Done.");
+    let executed_request = completion
+        .executed_request
+        .expect("request should be executed");
+    assert_eq!(executed_request.endpoint(), ("/notes", "POST"));
+    assert_eq!(completion.token_usage.prompt_tokens, 30);
+    assert_eq!(completion.token_usage.completion_tokens, 3);
+    assert_eq!(count_notes(&client, &base_url).await?, 1);
+    let summary = models
+        .summaries
+        .lock()
+        .expect("summaries shouldn't be poisoned")
+        .pop()
+        .expect("response should be summarized");
+    let last_message = summary
+        .as_array()
+        .and_then(|messages| messages.last())
+        .and_then(|message| message["content"].as_str())
+        .unwrap_or_default();
+    assert!(last_message.contains("buy milk"));
+
+    // Only streamed responses can be resumed.
+    let body = GenerationRequest::builder()
+        .messages(vec![Message {
+            role: MessageRole::User,
+            content: "how are you?".to_string(),
+        }])
+        .resumable(true)
+        .build();
+    let response = client.post(&completion_url).json(&body).send().await?;
+    assert_eq!(response.status(), reqwest::StatusCode::BAD_REQUEST);
+    Ok(())
+}
+
+#[tokio::test]
+#[serial]
+async fn assistant_users() -> Result<(), Box<dyn std::error::Error>> {
+    // Make sure there's a database URL and it points to a test database so
+    // prod isn't goofed during testing.
+    let db_connection_url = dotenvy::var("DATABASE_URL")?;
+    utils::reset_database(&db_connection_url)?;
+
+    // Spawn scripted model APIs.
+    let models = MockModels::default();
+    let mock_router = axum::Router::new()
+        .route(
+            "/v1/embeddings",
+            utils::mock_embeddings_with(mock_embedding),
+        )
+        .route("/v1/rerank", post(mock_rerank))
+        .route("/v1/chat/completions", post(mock_completions))
+        .with_state(models.clone());
+    let mock_listener = TcpListener::bind("127.0.0.1:0").await?;
+    let mock_addr = mock_listener.local_addr()?;
+    let _ = tokio::spawn(async move { axum::serve(mock_listener, mock_router).await });
+
+    // Initialize the server state with a token for each of two users,
+    // pointing all model APIs at the mocks.
+    let mut state = toi_server::init(db_connection_url).await?;
+    let mock_url = format!("http://{mock_addr}");
+    state.model_client.embedding_api_config.base_url = mock_url.clone();
+    state.model_client.generation_api_config.base_url = mock_url.clone();
+    state.model_client.reranking_api_config.base_url = mock_url;
+    let mut conn = state.pool.get().await?;
+    let alice_id = find_or_add_user("alice", &mut conn).await?;
+    let bob_id = find_or_add_user("bob", &mut conn).await?;
+    drop(conn);
+    state.token_auth = TokenAuth::new(vec![])
+        .with_user(alice_id, vec![hash_token("alice's token")])
+        .with_user(bob_id, vec![hash_token("bob's token")]);
+    let mut openapi_router = OpenApiRouter::new()
+        .nest(
+            "/conversations",
+            toi_server::routes::conversations::conversations_router(state.clone()),
+        )
+        .nest(
+            "/notes",
+            toi_server::routes::notes::notes_router(state.clone()),
+        );
+    let openapi = openapi_router.get_openapi_mut();
+    let assistant_router =
+        toi_server::routes::assistant::assistant_router(openapi, state.clone()).await?;
+    let openapi_router = openapi_router.nest("/assistant", assistant_router).layer(
+        axum::middleware::from_fn_with_state(state.clone(), authenticate),
+    );
+    let (router, _) = openapi_router.split_for_parts();
+    let listener = TcpListener::bind(&state.server_config.bind_addr).await?;
+
+    // Spawn server and create a client for all test requests.
+    let _ = tokio::spawn(async move { axum::serve(listener, router).await });
+    let client = reqwest::Client::new();
+    let base_url = format!("http://{}", state.server_config.bind_addr);
+    let search_notes_url = format!("{base_url}/notes/search");
+
+    // The assistant adds the note as the user that asked for it.
+    let body = GenerationRequest::builder()
+        .messages(vec![Message {
+            role: MessageRole::User,
+            content: "jot down that I need to buy milk".to_string(),
+        }])
+        .build();
+    let response = client
+        .post(format!("{base_url}/assistant/completion"))
+        .bearer_auth("alice's token")
+        .json(&body)
+        .send()
+        .await?;
+    let response = utils::assert_ok_response(response).await?;
+    let completion = response.json::<AssistantCompletion>().await?;
+    assert!(completion.executed_request.is_some());
+    for (token, num_notes) in [("alice's token", 1), ("bob's token", 0)] {
+        let response = client
+            .post(&search_notes_url)
+            .bearer_auth(token)
+            .json(&NoteSearchParams::builder().build())
+            .send()
+            .await?;
+        let notes = utils::assert_ok_response(response)
+            .await?
+            .json::<Page<Note>>()
+            .await?
+            .items;
+        assert_eq!(notes.len(), num_notes, "{token}");
+    }
+
+    // Conversations can only be listed, read, and continued by the user
+    // that started them.
+    let response = client
+        .post(format!("{base_url}/conversations"))
+        .bearer_auth("alice's token")
+        .json(&NewConversationRequest::builder().build())
+        .send()
+        .await?;
+    let conversation = utils::assert_ok_response(response)
+        .await?
+        .json::<Conversation>()
+        .await?;
+    let body = GenerationRequest::builder()
+        .messages(vec![Message {
+            role: MessageRole::User,
+            content: "how are you?".to_string(),
+        }])
+        .conversation_id(conversation.id)
+        .build();
+    let response = client
+        .post(format!("{base_url}/assistant/completion"))
+        .bearer_auth("alice's token")
+        .json(&body)
+        .send()
+        .await?;
+    utils::assert_ok_response(response).await?;
+    let response = client
+        .post(format!("{base_url}/assistant/completion"))
+        .bearer_auth("bob's token")
+        .json(&body)
+        .send()
+        .await?;
+    assert_eq!(response.status(), reqwest::StatusCode::NOT_FOUND);
+    let messages_url = format!("{base_url}/conversations/{}/messages", conversation.id);
+    let response = client
+        .get(&messages_url)
+        .bearer_auth("bob's token")
+        .send()
+        .await?;
+    assert_eq!(response.status(), reqwest::StatusCode::NOT_FOUND);
+    let response = client
+        .get(&messages_url)
+        .bearer_auth("alice's token")
+        .send()
+        .await?;
+    let messages = utils::assert_ok_response(response)
+        .await?
+        .json::<Vec<ConversationMessage>>()
+        .await?;
+    assert_eq!(messages.len(), 2);
+    for (token, num_conversations) in [("alice's token", 1), ("bob's token", 0)] {
+        let response = client
+            .get(format!("{base_url}/conversations"))
+            .bearer_auth(token)
+            .send()
+            .await?;
+        let conversations = utils::assert_ok_response(response)
+            .await?
+            .json::<Vec<Conversation>>()
+            .await?;
+        assert_eq!(conversations.len(), num_conversations, "{token}");
+    }
+
+    // Pending actions can only be confirmed by the user they're waiting on.
+    let pending_step = PendingStep {
+        description: "Delete notes".to_string(),
+        request: serde_json::from_value(json!({
+            "path": "/notes/delete",
+            "method": "POST",
+            "body": {"confirm_delete_all": true}
+        }))?,
+    };
+    let message_hash = hash_message("delete all notes");
+    let ttl = TimeDelta::hours(1);
+    let mut conn = state.pool.get().await?;
+    store_pending_action(
+        alice_id,
+        None,
+        message_hash.clone(),
+        &pending_step,
+        &mut conn,
+    )
+    .await?;
+    let pending_action = take_pending_action(bob_id, None, &message_hash, ttl, &mut conn).await?;
+    assert!(pending_action.is_none());
+    let pending_action = take_pending_action(alice_id, None, &message_hash, ttl, &mut conn).await?;
+    assert!(pending_action.is_some());
+    Ok(())
+}
+
+#[tokio::test]
+#[serial]
+async fn assistant_resume_users() -> Result<(), Box<dyn std::error::Error>> {
+    // Make sure there's a database URL and it points to a test database so
+    // prod isn't goofed during testing.
+    let db_connection_url = dotenvy::var("DATABASE_URL")?;
+    utils::reset_database(&db_connection_url)?;
+
+    // Spawn a mock generation API that streams a whole reply.
+    let mock_router = axum::Router::new()
+        .route("/v1/chat/completions", post(flaky_completions))
+        .with_state((Arc::new(AtomicUsize::new(0)), Arc::new(Notify::new())));
+    let mock_listener = TcpListener::bind("127.0.0.1:0").await?;
+    let mock_addr = mock_listener.local_addr()?;
+    let _ = tokio::spawn(async move { axum::serve(mock_listener, mock_router).await });
+
+    // Initialize the server state with a token for each of two users,
+    // pointing generation at the mock API.
+    let mut state = toi_server::init(db_connection_url).await?;
+    state.model_client.generation_api_config.base_url = format!("http://{mock_addr}");
+    let mut conn = state.pool.get().await?;
+    let alice_id = find_or_add_user("alice", &mut conn).await?;
+    let bob_id = find_or_add_user("bob", &mut conn).await?;
+    drop(conn);
+    state.token_auth = TokenAuth::new(vec![])
+        .with_user(alice_id, vec![hash_token("alice's token")])
+        .with_user(bob_id, vec![hash_token("bob's token")]);
+    let mut openapi_router = OpenApiRouter::new();
+    let openapi = openapi_router.get_openapi_mut();
+    let assistant_router =
+        toi_server::routes::assistant::assistant_router(openapi, state.clone()).await?;
+    let openapi_router = openapi_router.nest("/assistant", assistant_router).layer(
+        axum::middleware::from_fn_with_state(state.clone(), authenticate),
+    );
+    let (router, _) = openapi_router.split_for_parts();
+    let listener = TcpListener::bind(&state.server_config.bind_addr).await?;
+
+    // Spawn server and create a client for all test requests.
+    let _ = tokio::spawn(async move { axum::serve(listener, router).await });
+    let client = reqwest::Client::new();
+    let assistant_url = format!("http://{}/assistant", state.server_config.bind_addr);
+
+    // Alice's response stream has a checkpoint it can be resumed from.
+    let body = GenerationRequest::builder()
+        .messages(Vec::<Message>::new())
+        .resumable(true)
+        .build();
+    let response = client
+        .post(&assistant_url)
+        .bearer_auth("alice's token")
+        .json(&body)
+        .send()
+        .await?;
+    let content = utils::assert_ok_response(response).await?.text().await?;
+    let resume_point = content
+        .lines()
+        .find_map(ResumePoint::from_checkpoint)
+        .ok_or("response stream should have a checkpoint")?;
+
+    // Only Alice can resume it.
+    let body = GenerationRequest::builder()
+        .messages(Vec::<Message>::new())
+        .resume(resume_point)
+        .build();
+    let response = client
+        .post(&assistant_url)
+        .bearer_auth("bob's token")
+        .json(&body)
+        .send()
+        .await?;
+    assert_eq!(response.status(), reqwest::StatusCode::NOT_FOUND);
+    let response = client
+        .post(&assistant_url)
+        .bearer_auth("alice's token")
+        .json(&body)
+        .send()
+        .await?;
+    let resumed = utils::assert_ok_response(response).await?.text().await?;
+    assert!(resumed.contains("Hello there"));
+    Ok(())
+}
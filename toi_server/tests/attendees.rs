@@ -6,7 +6,7 @@ use tokio::net::TcpListener;
 use utoipa_axum::router::OpenApiRouter;
 
 use toi_server::models::{
-    attendees::{AttendeeSearchParams, Attendees},
+    attendees::{AttendeeSearchParams, Attendees, ContactEventSearchParams, ContactEvents},
     contacts::{Contact, NewContactRequest},
     events::{Event, NewEventRequest},
 };
@@ -101,3 +101,116 @@ async fn attendees_routes() -> Result<(), Box<dyn std::error::Error>> {
     assert_eq!(attendees3, attendees1);
     Ok(())
 }
+
+#[tokio::test]
+#[serial]
+async fn contact_events_route() -> Result<(), Box<dyn std::error::Error>> {
+    // Make sure there's a database URL and it points to a test database so
+    // prod isn't goofed during testing.
+    let db_connection_url = dotenvy::var("DATABASE_URL")?;
+    utils::reset_database(&db_connection_url)?;
+
+    // Initialize the server state.
+    let state = toi_server::init(db_connection_url).await?;
+    let openapi_router = OpenApiRouter::new()
+        .nest(
+            "/contacts",
+            toi_server::routes::contacts::contacts_router(state.clone()),
+        )
+        .nest(
+            "/events",
+            toi_server::routes::events::events_router(state.clone()).nest(
+                "/attendees",
+                toi_server::routes::attendees::attendees_router(state.clone()),
+            ),
+        );
+    let (router, _) = openapi_router.split_for_parts();
+    let listener = TcpListener::bind(&state.server_config.bind_addr).await?;
+
+    // Spawn server and create a client for all test requests.
+    let _ = tokio::spawn(async move { axum::serve(listener, router).await });
+    let client = reqwest::Client::new();
+    let contacts_url = format!("http://{}/contacts", state.server_config.bind_addr);
+    let events_url = format!("http://{}/events", state.server_config.bind_addr);
+    let attendees_url = format!("{events_url}/attendees");
+
+    // Make a contact.
+    let body = NewContactRequest::builder()
+        .first_name("Alice".to_string())
+        .build();
+    let response = client.post(&contacts_url).json(&body).send().await?;
+    let response = utils::assert_ok_response(response).await?;
+    let contact1 = response.json::<Contact>().await?;
+
+    // Make three events, out of order, and add the contact to the first two.
+    let mut events = vec![];
+    for (description, starts_at, ends_at) in [
+        (
+            "Board game night",
+            "2025-05-20T00:00:00+0000",
+            "2025-05-20T03:00:00+0000",
+        ),
+        (
+            "Pottery class",
+            "2025-05-10T17:00:00+0000",
+            "2025-05-10T19:00:00+0000",
+        ),
+        (
+            "Dentist appointment",
+            "2025-05-15T14:00:00+0000",
+            "2025-05-15T15:00:00+0000",
+        ),
+    ] {
+        let body = NewEventRequest::builder()
+            .description(description.to_string())
+            .starts_at(DateTime::from_str(starts_at)?)
+            .ends_at(DateTime::from_str(ends_at)?)
+            .build();
+        let response = client.post(&events_url).json(&body).send().await?;
+        let response = utils::assert_ok_response(response).await?;
+        events.push(response.json::<Event>().await?);
+    }
+    for event in &events[..2] {
+        let params = AttendeeSearchParams::builder()
+            .event_id(event.id)
+            .contact_ids(vec![contact1.id])
+            .build();
+        let response = client.post(&attendees_url).json(&params).send().await?;
+        utils::assert_ok_response(response).await?;
+    }
+
+    // Get the contact's events, which come back earliest first.
+    let contact_events_url = format!("{attendees_url}/by-contact");
+    let params = ContactEventSearchParams::builder()
+        .contact_query("Alice".to_string())
+        .build();
+    let response = client
+        .post(&contact_events_url)
+        .json(&params)
+        .send()
+        .await?;
+    let response = utils::assert_ok_response(response).await?;
+    let contact_events1 = response.json::<ContactEvents>().await?;
+    assert_eq!(contact_events1.contact, contact1);
+    let events1: Vec<&str> = contact_events1
+        .events
+        .iter()
+        .map(|event| event.description.as_str())
+        .collect();
+    assert_eq!(events1, vec!["Pottery class", "Board game night"]);
+
+    // Only get the contact's events within a date range.
+    let params = ContactEventSearchParams::builder()
+        .contact_id(contact1.id)
+        .event_occurs_from(DateTime::from_str("2025-05-15T00:00:00+0000")?)
+        .build();
+    let response = client
+        .post(&contact_events_url)
+        .json(&params)
+        .send()
+        .await?;
+    let response = utils::assert_ok_response(response).await?;
+    let contact_events2 = response.json::<ContactEvents>().await?;
+    assert_eq!(contact_events2.events, vec![events.remove(0)]);
+    Ok(())
+}
@@ -0,0 +1,119 @@
+use chrono::{Duration, Utc};
+use serial_test::serial;
+use tokio::net::TcpListener;
+use utoipa_axum::router::OpenApiRouter;
+
+use toi_server::{
+    auth::{DEFAULT_USER_ID, find_or_add_user},
+    models::audit::{AuditPurpose, AuditQueryParams, GenerationAudit, NewGenerationAudit},
+    routes::audit::{insert_generation_audit, purge_generation_audits},
+};
+
+mod utils;
+
+#[tokio::test]
+#[serial]
+async fn generation_audits() -> Result<(), Box<dyn std::error::Error>> {
+    // Make sure there's a database URL and it points to a test database so
+    // prod isn't goofed during testing.
+    let db_connection_url = dotenvy::var("DATABASE_URL")?;
+    utils::reset_database(&db_connection_url)?;
+
+    // Initialize the server state.
+    let state = toi_server::init(db_connection_url).await?;
+    let openapi_router = OpenApiRouter::new().nest(
+        "/admin/audit",
+        toi_server::routes::audit::audit_router(state.clone()),
+    );
+    let (router, _) = openapi_router.split_for_parts();
+    let listener = TcpListener::bind(&state.server_config.bind_addr).await?;
+
+    // Spawn server and create a client for all test requests.
+    let _ = tokio::spawn(async move { axum::serve(listener, router).await });
+    let client = reqwest::Client::new();
+    let audit_url = format!("http://{}/admin/audit", state.server_config.bind_addr);
+
+    // Record a model call for each step of handling a request.
+    let started_at = Utc::now();
+    let mut conn = state.pool.get().await?;
+    for purpose in [
+        AuditPurpose::Classification,
+        AuditPurpose::RequestGeneration,
+        AuditPurpose::Summary,
+    ] {
+        let new_generation_audit = NewGenerationAudit::builder()
+            .user_id(DEFAULT_USER_ID)
+            .purpose(purpose)
+            .system_prompt_hash("hash".to_string())
+            .output("output".to_string())
+            .latency_ms(10)
+            .build();
+        insert_generation_audit(new_generation_audit, &mut conn).await?;
+    }
+
+    // Get all the entries, newest first.
+    let response = client.get(&audit_url).send().await?;
+    let response = utils::assert_ok_response(response).await?;
+    let generation_audits = response.json::<Vec<GenerationAudit>>().await?;
+    let purposes: Vec<AuditPurpose> = generation_audits
+        .iter()
+        .map(|generation_audit| generation_audit.purpose)
+        .collect();
+    assert_eq!(
+        purposes,
+        vec![
+            AuditPurpose::Summary,
+            AuditPurpose::RequestGeneration,
+            AuditPurpose::Classification
+        ]
+    );
+
+    // Filter entries by purpose.
+    let params = AuditQueryParams::builder()
+        .purpose(AuditPurpose::RequestGeneration)
+        .build();
+    let response = client.get(&audit_url).query(&params).send().await?;
+    let response = utils::assert_ok_response(response).await?;
+    let generation_audits = response.json::<Vec<GenerationAudit>>().await?;
+    assert_eq!(generation_audits.len(), 1);
+    assert_eq!(
+        generation_audits[0].purpose,
+        AuditPurpose::RequestGeneration
+    );
+
+    // Filter entries by time.
+    let params = AuditQueryParams::builder()
+        .to(started_at - Duration::hours(1))
+        .build();
+    let response = client.get(&audit_url).query(&params).send().await?;
+    let response = utils::assert_ok_response(response).await?;
+    assert!(response.json::<Vec<GenerationAudit>>().await?.is_empty());
+
+    // Entries for other users' model calls aren't listed.
+    let other_user_id = find_or_add_user("alice", &mut conn).await?;
+    let new_generation_audit = NewGenerationAudit::builder()
+        .user_id(other_user_id)
+        .purpose(AuditPurpose::Chat)
+        .system_prompt_hash("hash".to_string())
+        .output("alice's output".to_string())
+        .latency_ms(10)
+        .build();
+    insert_generation_audit(new_generation_audit, &mut conn).await?;
+    let response = client.get(&audit_url).send().await?;
+    let response = utils::assert_ok_response(response).await?;
+    let generation_audits = response.json::<Vec<GenerationAudit>>().await?;
+    assert_eq!(generation_audits.len(), 3);
+    assert!(
+        generation_audits
+            .iter()
+            .all(|generation_audit| generation_audit.purpose != AuditPurpose::Chat)
+    );
+
+    // Purge entries older than now.
+    let num_purged = purge_generation_audits(Utc::now(), &mut conn).await?;
+    assert_eq!(num_purged, 4);
+    let response = client.get(&audit_url).send().await?;
+    let response = utils::assert_ok_response(response).await?;
+    assert!(response.json::<Vec<GenerationAudit>>().await?.is_empty());
+    Ok(())
+}
@@ -0,0 +1,191 @@
+use axum::routing::post;
+use reqwest::StatusCode;
+use serial_test::serial;
+use tokio::net::TcpListener;
+use utoipa_axum::router::OpenApiRouter;
+
+use toi_server::{
+    auth::{TokenAuth, authenticate, find_or_add_user, hash_token},
+    models::{
+        error::{ErrorCode, ErrorResponse},
+        notes::{NewNoteRequest, Note, NoteSearchParams},
+        pagination::Page,
+    },
+};
+
+mod utils;
+
+#[tokio::test]
+#[serial]
+async fn authenticated_routes() -> Result<(), Box<dyn std::error::Error>> {
+    // Make sure there's a database URL and it points to a test database so
+    // prod isn't goofed during testing.
+    let db_connection_url = dotenvy::var("DATABASE_URL")?;
+    utils::reset_database(&db_connection_url)?;
+
+    // Initialize the server state with a single allowed token. Health checks
+    // don't need a token.
+    let mut state = toi_server::init(db_connection_url).await?;
+    state.token_auth = TokenAuth::new(vec![hash_token("open sesame")]);
+    let openapi_router = OpenApiRouter::new()
+        .nest(
+            "/notes",
+            toi_server::routes::notes::notes_router(state.clone()),
+        )
+        .layer(axum::middleware::from_fn_with_state(
+            state.clone(),
+            authenticate,
+        ))
+        .merge(toi_server::routes::health::health_router(state.clone()));
+    let (router, _) = openapi_router.split_for_parts();
+    let listener = TcpListener::bind(&state.server_config.bind_addr).await?;
+
+    // Spawn server and create a client for all test requests.
+    let _ = tokio::spawn(async move { axum::serve(listener, router).await });
+    let client = reqwest::Client::new();
+    let base_url = format!("http://{}", state.server_config.bind_addr);
+    let search_notes_url = format!("{base_url}/notes/search");
+    let params = NoteSearchParams::builder().build();
+
+    // Requests without a token are rejected.
+    let response = client.post(&search_notes_url).json(&params).send().await?;
+    assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+    let error_response = response.json::<ErrorResponse>().await?;
+    assert_eq!(error_response.error.code, ErrorCode::Unauthorized);
+
+    // Requests with the wrong token are rejected.
+    let response = client
+        .post(&search_notes_url)
+        .bearer_auth("open barley")
+        .json(&params)
+        .send()
+        .await?;
+    assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+    let error_response = response.json::<ErrorResponse>().await?;
+    assert_eq!(error_response.error.code, ErrorCode::Unauthorized);
+
+    // Requests with the right token are allowed through.
+    let response = client
+        .post(&search_notes_url)
+        .bearer_auth("open sesame")
+        .json(&params)
+        .send()
+        .await?;
+    utils::assert_ok_response(response).await?;
+
+    // Health checks are still available.
+    let response = client.get(format!("{base_url}/health")).send().await?;
+    utils::assert_ok_response(response).await?;
+    Ok(())
+}
+
+#[tokio::test]
+#[serial]
+async fn user_scoped_routes() -> Result<(), Box<dyn std::error::Error>> {
+    // Make sure there's a database URL and it points to a test database so
+    // prod isn't goofed during testing.
+    let db_connection_url = dotenvy::var("DATABASE_URL")?;
+    utils::reset_database(&db_connection_url)?;
+
+    // Spawn a mock embedding API.
+    let mock_router = axum::Router::new().route("/v1/embeddings", post(utils::mock_embeddings));
+    let mock_listener = TcpListener::bind("127.0.0.1:0").await?;
+    let mock_addr = mock_listener.local_addr()?;
+    let _ = tokio::spawn(async move { axum::serve(mock_listener, mock_router).await });
+
+    // Initialize the server state with a token for each of two users.
+    let mut state = toi_server::init(db_connection_url).await?;
+    state.model_client.embedding_api_config.base_url = format!("http://{mock_addr}");
+    let mut conn = state.pool.get().await?;
+    let alice_id = find_or_add_user("alice", &mut conn).await?;
+    let bob_id = find_or_add_user("bob", &mut conn).await?;
+    assert_ne!(alice_id, bob_id);
+    assert_eq!(find_or_add_user("alice", &mut conn).await?, alice_id);
+    drop(conn);
+    state.token_auth = TokenAuth::new(vec![])
+        .with_user(alice_id, vec![hash_token("alice's token")])
+        .with_user(bob_id, vec![hash_token("bob's token")]);
+    let openapi_router = OpenApiRouter::new()
+        .nest(
+            "/notes",
+            toi_server::routes::notes::notes_router(state.clone()),
+        )
+        .layer(axum::middleware::from_fn_with_state(
+            state.clone(),
+            authenticate,
+        ));
+    let (router, _) = openapi_router.split_for_parts();
+    let listener = TcpListener::bind(&state.server_config.bind_addr).await?;
+
+    // Spawn server and create a client for all test requests.
+    let _ = tokio::spawn(async move { axum::serve(listener, router).await });
+    let client = reqwest::Client::new();
+    let notes_url = format!("http://{}/notes", state.server_config.bind_addr);
+    let search_notes_url = format!("{notes_url}/search");
+    let delete_notes_url = format!("{notes_url}/delete");
+
+    // Alice adds a note.
+    let body = NewNoteRequest::builder()
+        .content("My locker code is 1234".to_string())
+        .build();
+    let response = client
+        .post(&notes_url)
+        .bearer_auth("alice's token")
+        .json(&body)
+        .send()
+        .await?;
+    let response = utils::assert_ok_response(response).await?;
+    let note = response.json::<Note>().await?;
+
+    // Bob can't find it, even by its ID.
+    let params = NoteSearchParams::builder().ids(vec![note.id]).build();
+    let response = client
+        .post(&search_notes_url)
+        .bearer_auth("bob's token")
+        .json(&params)
+        .send()
+        .await?;
+    let response = utils::assert_ok_response(response).await?;
+    assert!(response.json::<Page<Note>>().await?.items.is_empty());
+    let response = client
+        .get(format!("{notes_url}/{}", note.id))
+        .bearer_auth("bob's token")
+        .send()
+        .await?;
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+
+    // Bob can't delete it either.
+    let response = client
+        .post(&delete_notes_url)
+        .bearer_auth("bob's token")
+        .json(&params)
+        .send()
+        .await?;
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+
+    // Bob's own notes aren't duplicates of Alice's.
+    let response = client
+        .post(&notes_url)
+        .bearer_auth("bob's token")
+        .json(&body)
+        .send()
+        .await?;
+    let response = utils::assert_ok_response(response).await?;
+    assert_eq!(response.status(), StatusCode::OK);
+    let bob_note = response.json::<Note>().await?;
+    assert_ne!(bob_note.id, note.id);
+
+    // Alice still has her note and only her note.
+    let response = client
+        .post(&search_notes_url)
+        .bearer_auth("alice's token")
+        .json(&NoteSearchParams::builder().build())
+        .send()
+        .await?;
+    let response = utils::assert_ok_response(response).await?;
+    let notes = response.json::<Page<Note>>().await?.items;
+    assert_eq!(notes.len(), 1);
+    assert_eq!(notes[0].id, note.id);
+    assert!(notes[0].deleted_at.is_none());
+    Ok(())
+}
@@ -0,0 +1,339 @@
+use std::{io::BufReader, str::FromStr};
+
+use chrono::{DateTime, NaiveDate, TimeDelta};
+use ical::{
+    IcalParser,
+    parser::ical::component::{IcalCalendar, IcalEvent},
+};
+use serial_test::serial;
+use tokio::net::TcpListener;
+use utoipa_axum::router::OpenApiRouter;
+
+use toi_server::models::{
+    contacts::{ContactWithDetails, NewContactRequest},
+    events::{Event, EventCalendarParams, NewEventRequest, RecurrenceFrequency},
+};
+
+mod utils;
+
+/// Check an iCalendar document follows RFC 5545's rules for line breaks,
+/// line folding, and text escaping more strictly than parsers do, returning
+/// its unfolded content lines.
+fn check_calendar(calendar: &str) -> Vec<String> {
+    let body = calendar
+        .strip_suffix("\r\n")
+        .expect("calendar should end with a line break");
+    let mut lines: Vec<String> = vec![];
+    for line in body.split("\r\n") {
+        assert!(
+            !line.contains(['\r', '\n']),
+            "line breaks should be CRLF: {line:?}"
+        );
+        assert!(line.len() <= 75, "line should be folded: {line:?}");
+        match line.strip_prefix(' ') {
+            Some(continuation) => lines
+                .last_mut()
+                .expect("first line shouldn't be a continuation")
+                .push_str(continuation),
+            None => lines.push(line.to_string()),
+        }
+    }
+    assert_eq!(lines.first().map(String::as_str), Some("BEGIN:VCALENDAR"));
+    assert_eq!(lines.last().map(String::as_str), Some("END:VCALENDAR"));
+    for line in &lines {
+        let (name, value) = line.split_once(':').expect("line should have a value");
+        if !["SUMMARY", "DESCRIPTION"].contains(&name) {
+            continue;
+        }
+        let mut chars = value.chars();
+        while let Some(c) = chars.next() {
+            assert!(![',', ';'].contains(&c), "text should be escaped: {line:?}");
+            if c == '\\' {
+                assert!(
+                    chars
+                        .next()
+                        .is_some_and(|c| ['\\', ';', ',', 'n', 'N'].contains(&c)),
+                    "escape should be valid: {line:?}"
+                );
+            }
+        }
+    }
+    lines
+}
+
+/// Parse an iCalendar document with a third-party parser.
+fn parse_calendar(calendar: &str) -> IcalCalendar {
+    let mut parser = IcalParser::new(BufReader::new(calendar.as_bytes()));
+    let parsed = parser
+        .next()
+        .expect("there should be a calendar")
+        .expect("calendar should parse");
+    assert!(parser.next().is_none());
+    parsed
+}
+
+/// Get the value of an event's property.
+fn property<'a>(event: &'a IcalEvent, name: &str) -> &'a str {
+    event
+        .properties
+        .iter()
+        .find(|property| property.name == name)
+        .and_then(|property| property.value.as_deref())
+        .expect("property should be set")
+}
+
+/// Undo text escaping. The parser leaves this up to its users.
+fn unescape(value: &str) -> String {
+    value
+        .replace("\\n", "\n")
+        .replace("\\,", ",")
+        .replace("\\;", ";")
+        .replace("\\\\", "\\")
+}
+
+/// Remove whitespace from text. The parser trims whitespace from the end of
+/// each line before unfolding, so spaces that fall right before a fold are
+/// lost.
+fn without_whitespace(value: &str) -> String {
+    value.split_whitespace().collect()
+}
+
+#[tokio::test]
+#[serial]
+async fn event_calendar() -> Result<(), Box<dyn std::error::Error>> {
+    // Make sure there's a database URL and it points to a test database so
+    // prod isn't goofed during testing.
+    let db_connection_url = dotenvy::var("DATABASE_URL")?;
+    utils::reset_database(&db_connection_url)?;
+
+    // Initialize the server state.
+    let state = toi_server::init(db_connection_url).await?;
+    let openapi_router = OpenApiRouter::new().nest(
+        "/events",
+        toi_server::routes::events::events_router(state.clone()),
+    );
+    let (router, _) = openapi_router.split_for_parts();
+    let listener = TcpListener::bind(&state.server_config.bind_addr).await?;
+
+    // Spawn server and create a client for all test requests.
+    let _ = tokio::spawn(async move { axum::serve(listener, router).await });
+    let client = reqwest::Client::new();
+    let events_url = format!("http://{}/events", state.server_config.bind_addr);
+
+    // Make a one-off event with a long description that needs escaping and
+    // folding, a repeating event, and another one-off event.
+    let dinner_description = "Dinner at Luigi's; bring wine, cheese, and crackers\n\
+        Ask about the reservation under \"Lovelace\" \\ table by the window"
+        .to_string();
+    let standup_starts_at = DateTime::from_str("2025-06-02T09:00:00+0000")?;
+    let dentist_starts_at = DateTime::from_str("2025-08-01T15:00:00+0000")?;
+    let bodies = [
+        NewEventRequest::builder()
+            .description(dinner_description.clone())
+            .starts_at(DateTime::from_str("2025-06-10T18:00:00+0000")?)
+            .ends_at(DateTime::from_str("2025-06-10T20:00:00+0000")?)
+            .build(),
+        NewEventRequest::builder()
+            .description("Team standup".to_string())
+            .starts_at(standup_starts_at)
+            .ends_at(standup_starts_at + TimeDelta::hours(1))
+            .recurrence_frequency(RecurrenceFrequency::Weekly)
+            .recurrence_interval(2)
+            .recurrence_until(DateTime::from_str("2025-06-30T09:00:00+0000")?)
+            .build(),
+        NewEventRequest::builder()
+            .description("Dentist".to_string())
+            .starts_at(dentist_starts_at)
+            .ends_at(dentist_starts_at + TimeDelta::hours(1))
+            .build(),
+    ];
+    let mut events = vec![];
+    for body in &bodies {
+        let response = client.post(&events_url).json(body).send().await?;
+        let response = utils::assert_ok_response(response).await?;
+        events.push(response.json::<Event>().await?);
+    }
+
+    // Export all the events.
+    let calendar_url = format!("{events_url}/export.ics");
+    let response = client.get(&calendar_url).send().await?;
+    let response = utils::assert_ok_response(response).await?;
+    let content_type = response
+        .headers()
+        .get(reqwest::header::CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .unwrap_or_default();
+    assert!(content_type.starts_with("text/calendar"));
+    let calendar = response.text().await?;
+    let lines = check_calendar(&calendar);
+    assert!(
+        lines
+            .iter()
+            .any(|line| line.starts_with("DESCRIPTION:Dinner at Luigi's\\;"))
+    );
+    assert!(calendar.lines().any(|line| line.starts_with(' ')));
+
+    // The parser agrees with what was exported.
+    let parsed = parse_calendar(&calendar);
+    assert_eq!(parsed.events.len(), 3);
+    let uids: Vec<&str> = parsed
+        .events
+        .iter()
+        .map(|event| property(event, "UID"))
+        .collect();
+    let expected_uids: Vec<String> = events
+        .iter()
+        .map(|event| format!("event-{}@toi", event.id))
+        .collect();
+    assert_eq!(uids, expected_uids);
+    let dinner = &parsed.events[0];
+    assert_eq!(property(dinner, "DTSTART"), "20250610T180000Z");
+    assert_eq!(property(dinner, "DTEND"), "20250610T200000Z");
+    assert_eq!(
+        without_whitespace(&unescape(property(dinner, "DESCRIPTION"))),
+        without_whitespace(&dinner_description)
+    );
+    assert!(
+        !dinner
+            .properties
+            .iter()
+            .any(|property| property.name == "RRULE")
+    );
+    let standup = &parsed.events[1];
+    assert_eq!(property(standup, "DTSTART"), "20250602T090000Z");
+    assert_eq!(
+        property(standup, "RRULE"),
+        "FREQ=WEEKLY;INTERVAL=2;UNTIL=20250630T090000Z"
+    );
+
+    // Only export events that occur within a window. The repeating event is
+    // included because of its last repeat.
+    let params = EventCalendarParams::builder()
+        .from(DateTime::from_str("2025-06-20T00:00:00+0000")?)
+        .to(DateTime::from_str("2025-07-31T00:00:00+0000")?)
+        .build();
+    let response = client.get(&calendar_url).query(&params).send().await?;
+    let response = utils::assert_ok_response(response).await?;
+    let calendar = response.text().await?;
+    check_calendar(&calendar);
+    let parsed = parse_calendar(&calendar);
+    assert_eq!(parsed.events.len(), 1);
+    assert_eq!(property(&parsed.events[0], "UID"), expected_uids[1]);
+
+    // Repeating events still going on during a window are left out if none
+    // of their repeats fall within it.
+    let params = EventCalendarParams::builder()
+        .from(DateTime::from_str("2025-06-03T00:00:00+0000")?)
+        .to(DateTime::from_str("2025-06-05T00:00:00+0000")?)
+        .build();
+    let response = client.get(&calendar_url).query(&params).send().await?;
+    let response = utils::assert_ok_response(response).await?;
+    let calendar = response.text().await?;
+    check_calendar(&calendar);
+    assert!(parse_calendar(&calendar).events.is_empty());
+
+    // Windows that end before they start are rejected.
+    let params = EventCalendarParams::builder()
+        .from(DateTime::from_str("2025-07-01T00:00:00+0000")?)
+        .to(DateTime::from_str("2025-06-01T00:00:00+0000")?)
+        .build();
+    let response = client.get(&calendar_url).query(&params).send().await?;
+    assert_eq!(response.status(), reqwest::StatusCode::BAD_REQUEST);
+    Ok(())
+}
+
+#[tokio::test]
+#[serial]
+async fn birthday_calendar() -> Result<(), Box<dyn std::error::Error>> {
+    // Make sure there's a database URL and it points to a test database so
+    // prod isn't goofed during testing.
+    let db_connection_url = dotenvy::var("DATABASE_URL")?;
+    utils::reset_database(&db_connection_url)?;
+
+    // Initialize the server state.
+    let state = toi_server::init(db_connection_url).await?;
+    let openapi_router = OpenApiRouter::new().nest(
+        "/contacts",
+        toi_server::routes::contacts::contacts_router(state.clone()),
+    );
+    let (router, _) = openapi_router.split_for_parts();
+    let listener = TcpListener::bind(&state.server_config.bind_addr).await?;
+
+    // Spawn server and create a client for all test requests.
+    let _ = tokio::spawn(async move { axum::serve(listener, router).await });
+    let client = reqwest::Client::new();
+    let contacts_url = format!("http://{}/contacts", state.server_config.bind_addr);
+
+    // Make contacts with and without birthdays, including a leap day one.
+    let bodies = [
+        NewContactRequest::builder()
+            .first_name("Ada".to_string())
+            .last_name("Lovelace".to_string())
+            .birthday(NaiveDate::from_ymd_opt(1815, 12, 10).expect("date should be valid"))
+            .build(),
+        NewContactRequest::builder()
+            .first_name("Bob".to_string())
+            .build(),
+        NewContactRequest::builder()
+            .first_name("Lee".to_string())
+            .birthday(NaiveDate::from_ymd_opt(2000, 2, 29).expect("date should be valid"))
+            .build(),
+    ];
+    let mut contacts = vec![];
+    for body in &bodies {
+        let response = client.post(&contacts_url).json(body).send().await?;
+        let response = utils::assert_ok_response(response).await?;
+        contacts.push(response.json::<ContactWithDetails>().await?);
+    }
+
+    // Export birthdays as yearly all-day events.
+    let response = client
+        .get(format!("{contacts_url}/birthdays.ics"))
+        .send()
+        .await?;
+    let response = utils::assert_ok_response(response).await?;
+    let content_type = response
+        .headers()
+        .get(reqwest::header::CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .unwrap_or_default();
+    assert!(content_type.starts_with("text/calendar"));
+    let calendar = response.text().await?;
+    check_calendar(&calendar);
+    let parsed = parse_calendar(&calendar);
+    assert_eq!(parsed.events.len(), 2);
+
+    let ada = &parsed.events[0];
+    assert_eq!(
+        property(ada, "UID"),
+        format!("birthday-{}@toi", contacts[0].contact.id)
+    );
+    let dtstart = ada
+        .properties
+        .iter()
+        .find(|property| property.name == "DTSTART")
+        .expect("start should be set");
+    assert_eq!(dtstart.value.as_deref(), Some("18151210"));
+    assert_eq!(
+        dtstart.params,
+        Some(vec![("VALUE".to_string(), vec!["DATE".to_string()])])
+    );
+    assert_eq!(property(ada, "DTEND"), "18151211");
+    assert_eq!(property(ada, "RRULE"), "FREQ=YEARLY");
+    assert_eq!(
+        unescape(property(ada, "SUMMARY")),
+        "Ada Lovelace's birthday"
+    );
+
+    let lee = &parsed.events[1];
+    assert_eq!(
+        property(lee, "UID"),
+        format!("birthday-{}@toi", contacts[2].contact.id)
+    );
+    assert_eq!(property(lee, "DTSTART"), "20000229");
+    assert_eq!(
+        property(lee, "RRULE"),
+        "FREQ=YEARLY;BYMONTH=2;BYMONTHDAY=-1"
+    );
+    Ok(())
+}
@@ -0,0 +1,99 @@
+use axum::routing::post;
+use diesel::{QueryDsl, SelectableHelper};
+use diesel_async::RunQueryDsl;
+use serial_test::serial;
+use tokio::net::TcpListener;
+use utoipa_axum::router::OpenApiRouter;
+
+use toi::{Capabilities, Capability};
+use toi_server::{models::openapi::OpenApiPathItem, schema};
+
+mod utils;
+
+#[tokio::test]
+#[serial]
+async fn capabilities_routes() -> Result<(), Box<dyn std::error::Error>> {
+    // Make sure there's a database URL and it points to a test database so
+    // prod isn't goofed during testing.
+    let db_connection_url = dotenvy::var("DATABASE_URL")?;
+    utils::reset_database(&db_connection_url)?;
+
+    // Spawn a mock embedding API for embedding endpoint descriptions.
+    let mock_router = axum::Router::new().route("/v1/embeddings", post(utils::mock_embeddings));
+    let mock_listener = TcpListener::bind("127.0.0.1:0").await?;
+    let mock_addr = mock_listener.local_addr()?;
+    let _ = tokio::spawn(async move { axum::serve(mock_listener, mock_router).await });
+
+    // Initialize the server state, and then make the assistant router so
+    // the endpoints it can use are stored.
+    let mut state = toi_server::init(db_connection_url).await?;
+    state.model_client.embedding_api_config.base_url = format!("http://{mock_addr}");
+    let mut openapi_router = OpenApiRouter::new()
+        .nest(
+            "/notes",
+            toi_server::routes::notes::notes_router(state.clone()),
+        )
+        .nest(
+            "/todos",
+            toi_server::routes::todos::todos_router(state.clone()),
+        );
+    let openapi = openapi_router.get_openapi_mut();
+    let assistant_router =
+        toi_server::routes::assistant::assistant_router(openapi, state.clone()).await?;
+    let openapi_router = openapi_router.nest("/assistant", assistant_router).nest(
+        "/capabilities",
+        toi_server::routes::capabilities::capabilities_router(state.clone()),
+    );
+    let (router, _) = openapi_router.split_for_parts();
+    let listener = TcpListener::bind(&state.server_config.bind_addr).await?;
+
+    // Spawn server and create a client for all test requests.
+    let _ = tokio::spawn(async move { axum::serve(listener, router).await });
+    let client = reqwest::Client::new();
+    let capabilities_url = format!("http://{}/capabilities", state.server_config.bind_addr);
+
+    // Capabilities match the endpoints stored when the assistant router was
+    // made.
+    let mut conn = state.pool.get().await?;
+    let openapi_path_items: Vec<OpenApiPathItem> = schema::openapi::table
+        .select(OpenApiPathItem::as_select())
+        .load(&mut conn)
+        .await?;
+    let mut expected_endpoints: Vec<Capability> = openapi_path_items
+        .iter()
+        .map(OpenApiPathItem::to_capability)
+        .collect();
+    expected_endpoints.sort_by(|a, b| (&a.path, &a.method).cmp(&(&b.path, &b.method)));
+    assert!(!expected_endpoints.is_empty());
+    let response = client.get(&capabilities_url).send().await?;
+    let response = utils::assert_ok_response(response).await?;
+    let body = response.bytes().await?;
+    let capabilities: Capabilities = serde_json::from_slice(&body)?;
+    assert_eq!(
+        capabilities,
+        Capabilities {
+            version: env!("CARGO_PKG_VERSION").to_string(),
+            domains: vec!["notes".to_string(), "todos".to_string()],
+            embedding_dimensions: Some(3),
+            endpoints: expected_endpoints,
+        }
+    );
+
+    // Summaries are only the first line of each endpoint's description, and
+    // endpoints with params or bodies are marked as such.
+    let search_notes = capabilities
+        .endpoints
+        .iter()
+        .find(|endpoint| endpoint.path == "/notes/search" && endpoint.method == "POST")
+        .expect("searching notes should be a capability");
+    assert!(!search_notes.summary.contains('\n'));
+    assert!(search_notes.has_body);
+    assert!(!search_notes.has_params);
+
+    // The cached response is served until the endpoints are replaced.
+    let response = client.get(&capabilities_url).send().await?;
+    let response = utils::assert_ok_response(response).await?;
+    assert_eq!(response.bytes().await?, body);
+    assert_eq!(state.capabilities.get(), Some(body));
+    Ok(())
+}
@@ -1,10 +1,17 @@
+use base64::{Engine, prelude::BASE64_STANDARD};
+use chrono::{Datelike, Duration, NaiveDate, Utc};
+use reqwest::StatusCode;
 use serial_test::serial;
 use tokio::net::TcpListener;
 use utoipa_axum::router::OpenApiRouter;
 
-use toi_server::models::contacts::{
-    Contact, ContactDeleteParams, ContactSearchParams, ContactUpdates, NewContactRequest,
-    UpdateContactRequest,
+use toi_server::models::{
+    contacts::{
+        Contact, ContactAvatar, ContactAvatarRequest, ContactDeleteParams, ContactDetail,
+        ContactImport, ContactSearchParams, ContactUpdates, ContactWithDetails, NewContactRequest,
+        UpdateContactRequest,
+    },
+    pagination::Page,
 };
 
 mod utils;
@@ -36,26 +43,89 @@ async fn contacts_routes() -> Result<(), Box<dyn std::error::Error>> {
     let first_name = "Marky mark".to_string();
     let body = NewContactRequest::builder()
         .first_name(first_name.clone())
+        .emails(vec![
+            ContactDetail::builder()
+                .label("work".to_string())
+                .value("mark@work.com".to_string())
+                .build(),
+            ContactDetail::builder()
+                .label("personal".to_string())
+                .value("marky@gmail.com".to_string())
+                .build(),
+        ])
         .build();
     let response = client.post(&contacts_url).json(&body).send().await?;
     let response = utils::assert_ok_response(response).await?;
-    let contact1 = response.json::<Contact>().await?;
-    assert_eq!(contact1.first_name, first_name);
+    let contact1 = response.json::<ContactWithDetails>().await?;
+    assert_eq!(contact1.contact.first_name, first_name);
+    assert_eq!(contact1.emails.len(), 2);
+    assert!(contact1.phones.is_empty());
 
-    // Update the contact.
-    let phone = "867-5309".to_string();
+    // Retrieve the contact using its ID, and fail to retrieve a missing one.
+    let response = client
+        .get(format!("{contacts_url}/{}", contact1.contact.id))
+        .send()
+        .await?;
+    let response = utils::assert_ok_response(response).await?;
+    assert_eq!(response.json::<ContactWithDetails>().await?, contact1);
+    let response = client.get(format!("{contacts_url}/0")).send().await?;
+    assert_eq!(response.status(), reqwest::StatusCode::NOT_FOUND);
+
+    // Fail to update the contact with a phone number that isn't one.
+    let body = UpdateContactRequest::builder()
+        .contact_updates(
+            ContactUpdates::builder()
+                .phone("call him after 5".to_string())
+                .build(),
+        )
+        .build();
+    let response = client.put(&contacts_url).json(&body).send().await?;
+    assert_eq!(response.status(), reqwest::StatusCode::BAD_REQUEST);
+
+    // Update the contact, normalizing their phone number.
+    let body = UpdateContactRequest::builder()
+        .contact_updates(
+            ContactUpdates::builder()
+                .phone("(555) 867-5309".to_string())
+                .build(),
+        )
+        .build();
+    let response = client.put(&contacts_url).json(&body).send().await?;
+    let response = utils::assert_ok_response(response).await?;
+    let contact2 = response.json::<ContactWithDetails>().await?;
+    assert_eq!(contact2.contact.phone, Some("555-867-5309".to_string()));
+    assert_eq!(contact2.emails, contact1.emails);
+
+    // Replace the contact's phone numbers.
     let body = UpdateContactRequest::builder()
-        .contact_updates(ContactUpdates::builder().phone(phone.to_string()).build())
+        .id(contact2.contact.id)
+        .contact_updates(
+            ContactUpdates::builder()
+                .phones(vec![
+                    ContactDetail::builder()
+                        .label("mobile".to_string())
+                        .value("555-555-5555".to_string())
+                        .build(),
+                ])
+                .build(),
+        )
         .build();
     let response = client.put(&contacts_url).json(&body).send().await?;
     let response = utils::assert_ok_response(response).await?;
-    let contact2 = response.json::<Contact>().await?;
-    assert_eq!(contact2.phone, Some(phone.to_string()));
+    let contact3 = response.json::<ContactWithDetails>().await?;
+    assert_eq!(contact3.phones.len(), 1);
+    assert_eq!(contact3.phones[0].label, "mobile");
+    let emails: Vec<&str> = contact3
+        .emails
+        .iter()
+        .map(|email| email.value.as_str())
+        .collect();
+    assert_eq!(emails, vec!["mark@work.com", "marky@gmail.com"]);
 
     // Retrieve the contact using search.
     let search_contacts_url = format!("{contacts_url}/search");
     let params = ContactSearchParams::builder()
-        .query("who is marky mark".to_string())
+        .query("whose email is marky@gmail.com".to_string())
         .build();
     let response = client
         .post(search_contacts_url)
@@ -63,8 +133,8 @@ async fn contacts_routes() -> Result<(), Box<dyn std::error::Error>> {
         .send()
         .await?;
     let response = utils::assert_ok_response(response).await?;
-    let vec_contacts1 = response.json::<Vec<Contact>>().await?;
-    assert_eq!(vec_contacts1, vec![contact2]);
+    let vec_contacts1 = response.json::<Page<ContactWithDetails>>().await?.items;
+    assert_eq!(vec_contacts1, vec![contact3]);
 
     // Delete the contact using search.
     let delete_contacts_url = format!("{contacts_url}/delete");
@@ -78,6 +148,449 @@ async fn contacts_routes() -> Result<(), Box<dyn std::error::Error>> {
         .await?;
     let response = utils::assert_ok_response(response).await?;
     let vec_contacts2 = response.json::<Vec<Contact>>().await?;
+    let vec_contacts1: Vec<Contact> = vec_contacts1
+        .into_iter()
+        .map(|contact| contact.contact)
+        .collect();
     assert_eq!(vec_contacts2, vec_contacts1);
     Ok(())
 }
+
+#[tokio::test]
+#[serial]
+async fn contacts_birthday_search() -> Result<(), Box<dyn std::error::Error>> {
+    // Make sure there's a database URL and it points to a test database so
+    // prod isn't goofed during testing.
+    let db_connection_url = dotenvy::var("DATABASE_URL")?;
+    utils::reset_database(&db_connection_url)?;
+
+    // Initialize the server state.
+    let state = toi_server::init(db_connection_url).await?;
+    let openapi_router = OpenApiRouter::new().nest(
+        "/contacts",
+        toi_server::routes::contacts::contacts_router(state.clone()),
+    );
+    let (router, _) = openapi_router.split_for_parts();
+    let listener = TcpListener::bind(&state.server_config.bind_addr).await?;
+
+    // Spawn server and create a client for all test requests.
+    let _ = tokio::spawn(async move { axum::serve(listener, router).await });
+    let client = reqwest::Client::new();
+    let contacts_url = format!("http://{}/contacts", state.server_config.bind_addr);
+    let search_contacts_url = format!("{contacts_url}/search");
+
+    // Make contacts born in past years, one of which has a birthday coming
+    // up in a few days. Its birth year is a leap year so the date is always
+    // valid.
+    let today = Utc::now().date_naive();
+    let soon = (today + Duration::days(3))
+        .with_year(2000)
+        .ok_or("invalid birthday")?;
+    let birthdays = [
+        (
+            "Nye",
+            NaiveDate::from_ymd_opt(1990, 12, 30).ok_or("invalid birthday")?,
+        ),
+        (
+            "Janus",
+            NaiveDate::from_ymd_opt(1985, 1, 2).ok_or("invalid birthday")?,
+        ),
+        (
+            "Leap",
+            NaiveDate::from_ymd_opt(1992, 2, 29).ok_or("invalid birthday")?,
+        ),
+        ("Soon", soon),
+    ];
+    for (first_name, birthday) in birthdays {
+        let body = NewContactRequest::builder()
+            .first_name(first_name.to_string())
+            .birthday(birthday)
+            .build();
+        let response = client.post(&contacts_url).json(&body).send().await?;
+        utils::assert_ok_response(response).await?;
+    }
+
+    // Search for birthdays, returning the first names of matching contacts.
+    let search = |params: serde_json::Value| {
+        let client = client.clone();
+        let search_contacts_url = search_contacts_url.clone();
+        async move {
+            let response = client
+                .post(search_contacts_url)
+                .json(&params)
+                .send()
+                .await?;
+            let response = utils::assert_ok_response(response).await?;
+            let contacts = response.json::<Page<ContactWithDetails>>().await?.items;
+            let mut first_names: Vec<String> = contacts
+                .into_iter()
+                .map(|contact| contact.contact.first_name)
+                .collect();
+            first_names.sort();
+            Ok::<_, Box<dyn std::error::Error>>(first_names)
+        }
+    };
+
+    // The week of New Year's Eve spans two years.
+    let first_names = search(serde_json::json!({
+        "birthday": "2025-12-31",
+        "birthday_falls_on": "Week",
+    }))
+    .await?;
+    assert_eq!(first_names, vec!["Janus", "Nye"]);
+
+    // Birthdays in the same month match regardless of birth year.
+    let first_names = search(serde_json::json!({
+        "birthday": "2026-01-15",
+        "birthday_falls_on": "Month",
+    }))
+    .await?;
+    assert_eq!(first_names, vec!["Janus"]);
+
+    // Feb 29 birthdays fall on Feb 28 in non-leap years.
+    let first_names = search(serde_json::json!({
+        "birthday": "2025-02-28",
+        "birthday_falls_on": "Day",
+    }))
+    .await?;
+    assert_eq!(first_names, vec!["Leap"]);
+
+    // Without a calendar object, birthdays must match exactly.
+    let first_names = search(serde_json::json!({
+        "birthday": "2025-12-30",
+    }))
+    .await?;
+    assert!(first_names.is_empty());
+
+    // Upcoming birthdays are relative to today.
+    let params = ContactSearchParams::builder()
+        .upcoming_within_days(7)
+        .build();
+    let first_names = search(serde_json::to_value(params)?).await?;
+    assert!(first_names.contains(&"Soon".to_string()));
+    Ok(())
+}
+
+#[tokio::test]
+#[serial]
+async fn contacts_duplicates() -> Result<(), Box<dyn std::error::Error>> {
+    // Make sure there's a database URL and it points to a test database so
+    // prod isn't goofed during testing.
+    let db_connection_url = dotenvy::var("DATABASE_URL")?;
+    utils::reset_database(&db_connection_url)?;
+
+    // Initialize the server state.
+    let state = toi_server::init(db_connection_url).await?;
+    let openapi_router = OpenApiRouter::new().nest(
+        "/contacts",
+        toi_server::routes::contacts::contacts_router(state.clone()),
+    );
+    let (router, _) = openapi_router.split_for_parts();
+    let listener = TcpListener::bind(&state.server_config.bind_addr).await?;
+
+    // Spawn server and create a client for all test requests.
+    let _ = tokio::spawn(async move { axum::serve(listener, router).await });
+    let client = reqwest::Client::new();
+    let contacts_url = format!("http://{}/contacts", state.server_config.bind_addr);
+
+    // Make a contact.
+    let body = NewContactRequest::builder()
+        .first_name("John".to_string())
+        .last_name("Smith".to_string())
+        .email("john.smith@example.com".to_string())
+        .phone("555-123-4567".to_string())
+        .build();
+    let response = client.post(&contacts_url).json(&body).send().await?;
+    let response = utils::assert_ok_response(response).await?;
+    let contact = response.json::<ContactWithDetails>().await?;
+
+    // Adding the exact same person again returns the existing contact.
+    let body = NewContactRequest::builder()
+        .first_name("John".to_string())
+        .last_name("Smith".to_string())
+        .email("John.Smith@example.com".to_string())
+        .build();
+    let response = client.post(&contacts_url).json(&body).send().await?;
+    assert_eq!(response.status(), reqwest::StatusCode::CONFLICT);
+    assert_eq!(response.json::<ContactWithDetails>().await?, contact);
+
+    // So does adding the same person with a misspelled name and a
+    // differently formatted phone number.
+    let body = NewContactRequest::builder()
+        .first_name("Jon".to_string())
+        .last_name("Smith".to_string())
+        .phone("(555) 123 4567".to_string())
+        .build();
+    let response = client.post(&contacts_url).json(&body).send().await?;
+    assert_eq!(response.status(), reqwest::StatusCode::CONFLICT);
+    assert_eq!(response.json::<ContactWithDetails>().await?, contact);
+
+    // Unless the duplicate is explicitly allowed.
+    let body = NewContactRequest::builder()
+        .first_name("Jon".to_string())
+        .last_name("Smith".to_string())
+        .phone("(555) 123 4567".to_string())
+        .allow_duplicate(true)
+        .build();
+    let response = client.post(&contacts_url).json(&body).send().await?;
+    let response = utils::assert_ok_response(response).await?;
+    let duplicate = response.json::<ContactWithDetails>().await?;
+    assert_ne!(duplicate.contact.id, contact.contact.id);
+
+    // A different person with the same first name is added like normal.
+    let body = NewContactRequest::builder()
+        .first_name("John".to_string())
+        .last_name("Doe".to_string())
+        .email("john.doe@example.com".to_string())
+        .build();
+    let response = client.post(&contacts_url).json(&body).send().await?;
+    let response = utils::assert_ok_response(response).await?;
+    let other = response.json::<ContactWithDetails>().await?;
+    assert_eq!(other.contact.last_name, Some("Doe".to_string()));
+    Ok(())
+}
+
+#[tokio::test]
+#[serial]
+async fn contacts_import() -> Result<(), Box<dyn std::error::Error>> {
+    // Make sure there's a database URL and it points to a test database so
+    // prod isn't goofed during testing.
+    let db_connection_url = dotenvy::var("DATABASE_URL")?;
+    utils::reset_database(&db_connection_url)?;
+
+    // Initialize the server state.
+    let mut state = toi_server::init(db_connection_url).await?;
+    state.server_config.contact_import_max_bytes = 4096;
+    let openapi_router = OpenApiRouter::new().nest(
+        "/contacts",
+        toi_server::routes::contacts::contacts_router(state.clone()),
+    );
+    let (router, _) = openapi_router.split_for_parts();
+    let listener = TcpListener::bind(&state.server_config.bind_addr).await?;
+
+    // Spawn server and create a client for all test requests.
+    let _ = tokio::spawn(async move { axum::serve(listener, router).await });
+    let client = reqwest::Client::new();
+    let contacts_url = format!("http://{}/contacts", state.server_config.bind_addr);
+    let import_url = format!("{contacts_url}/import");
+
+    // Make a contact that's also in the vCard document.
+    let body = NewContactRequest::builder()
+        .first_name("Marky".to_string())
+        .last_name("Mark".to_string())
+        .email("mark@work.com".to_string())
+        .build();
+    let response = client.post(&contacts_url).json(&body).send().await?;
+    let response = utils::assert_ok_response(response).await?;
+    let existing = response.json::<ContactWithDetails>().await?;
+
+    // Import the document. Folded lines and UTF-8 names come through, and
+    // duplicates and vCards without names are skipped.
+    let response = client
+        .post(&import_url)
+        .header("Content-Type", "text/vcard")
+        .body(include_str!("fixtures/contacts.vcf"))
+        .send()
+        .await?;
+    let response = utils::assert_ok_response(response).await?;
+    let ContactImport { imported, skipped } = response.json::<ContactImport>().await?;
+    assert_eq!(imported.len(), 2);
+    let jose = &imported[0];
+    assert_eq!(jose.contact.first_name, "José");
+    assert_eq!(jose.contact.last_name.as_deref(), Some("Álvarez"));
+    assert_eq!(
+        jose.contact.email.as_deref(),
+        Some("jose.alvarez@example.com")
+    );
+    assert_eq!(jose.contact.phone.as_deref(), Some("555-201-3344"));
+    assert_eq!(jose.contact.birthday, NaiveDate::from_ymd_opt(1990, 7, 4));
+    assert_eq!(
+        jose.contact.relationship.as_deref(),
+        Some(
+            "Neighbor from down the street, waters the plants when we're out of town\nHas a spare key"
+        )
+    );
+    let zoe = &imported[1];
+    assert_eq!(zoe.contact.first_name, "Zoë");
+    assert_eq!(zoe.contact.last_name.as_deref(), Some("Müller"));
+    assert_eq!(zoe.contact.email.as_deref(), Some("zoe@example.com"));
+    assert_eq!(zoe.contact.phone.as_deref(), Some("555-402-7788"));
+    assert_eq!(zoe.emails.len(), 1);
+    assert_eq!(zoe.emails[0].label, "other");
+    assert_eq!(zoe.emails[0].value, "zoe.mueller@example.com");
+
+    let skipped_names: Vec<&str> = skipped
+        .iter()
+        .map(|skipped| skipped.name.as_str())
+        .collect();
+    assert_eq!(
+        skipped_names,
+        vec!["Marky Mark", "José Alvarez", "unnamed contact"]
+    );
+    assert_eq!(skipped[0].existing_contact_id, Some(existing.contact.id));
+    assert_eq!(skipped[1].existing_contact_id, None);
+
+    // Importing the same document again skips everyone.
+    let response = client
+        .post(&import_url)
+        .header("Content-Type", "text/vcard")
+        .body(include_str!("fixtures/contacts.vcf"))
+        .send()
+        .await?;
+    let response = utils::assert_ok_response(response).await?;
+    let ContactImport { imported, skipped } = response.json::<ContactImport>().await?;
+    assert!(imported.is_empty());
+    assert_eq!(skipped.len(), 5);
+
+    // Malformed and oversized documents are rejected.
+    let response = client
+        .post(&import_url)
+        .header("Content-Type", "text/vcard")
+        .body("BEGIN:VCARD\r\nFN:Nobody\r\n")
+        .send()
+        .await?;
+    assert_eq!(response.status(), reqwest::StatusCode::BAD_REQUEST);
+    let response = client
+        .post(&import_url)
+        .header("Content-Type", "text/vcard")
+        .body(include_str!("fixtures/contacts.vcf").repeat(10))
+        .send()
+        .await?;
+    assert_eq!(response.status(), reqwest::StatusCode::PAYLOAD_TOO_LARGE);
+    Ok(())
+}
+
+#[tokio::test]
+#[serial]
+async fn contacts_avatars() -> Result<(), Box<dyn std::error::Error>> {
+    // Make sure there's a database URL and it points to a test database so
+    // prod isn't goofed during testing.
+    let db_connection_url = dotenvy::var("DATABASE_URL")?;
+    utils::reset_database(&db_connection_url)?;
+
+    // Initialize the server state with a small max avatar size.
+    let mut state = toi_server::init(db_connection_url).await?;
+    state.server_config.contact_avatar_max_bytes = 64;
+    let openapi_router = OpenApiRouter::new()
+        .nest(
+            "/contacts",
+            toi_server::routes::contacts::contacts_router(state.clone()),
+        )
+        .nest(
+            "/contacts",
+            toi_server::routes::contacts::contact_avatars_router(state.clone()),
+        );
+    let (router, _) = openapi_router.split_for_parts();
+    let listener = TcpListener::bind(&state.server_config.bind_addr).await?;
+
+    // Spawn server and create a client for all test requests.
+    let _ = tokio::spawn(async move { axum::serve(listener, router).await });
+    let client = reqwest::Client::new();
+    let contacts_url = format!("http://{}/contacts", state.server_config.bind_addr);
+    let avatar_url = format!("{contacts_url}/avatar");
+
+    // Make a couple of contacts.
+    let mut contact_ids = vec![];
+    for (first_name, last_name) in [("Ada", "Lovelace"), ("Charles", "Babbage")] {
+        let body = NewContactRequest::builder()
+            .first_name(first_name.to_string())
+            .last_name(last_name.to_string())
+            .build();
+        let response = client.post(&contacts_url).json(&body).send().await?;
+        let response = utils::assert_ok_response(response).await?;
+        let contact = response.json::<ContactWithDetails>().await?;
+        contact_ids.push(contact.contact.id);
+    }
+    let ada_id = contact_ids[0];
+    let charles_id = contact_ids[1];
+
+    // Contacts don't have avatars to begin with.
+    let response = client
+        .get(format!("{contacts_url}/{ada_id}/avatar"))
+        .send()
+        .await?;
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+
+    // Set an avatar using the contact's ID and get it back.
+    let png = [b"\x89PNG\r\n\x1a\n".as_slice(), &[0; 24]].concat();
+    let body = ContactAvatarRequest::builder()
+        .id(ada_id)
+        .content_type("image/png".to_string())
+        .data(BASE64_STANDARD.encode(&png))
+        .build();
+    let response = client.put(&avatar_url).json(&body).send().await?;
+    let response = utils::assert_ok_response(response).await?;
+    let avatar = response.json::<ContactAvatar>().await?;
+    assert_eq!(avatar.contact_id, ada_id);
+    assert_eq!(avatar.content_type, "image/png");
+    let response = client
+        .get(format!("{contacts_url}/{ada_id}/avatar"))
+        .send()
+        .await?;
+    let response = utils::assert_ok_response(response).await?;
+    assert_eq!(response.headers()["content-type"], "image/png");
+    assert_eq!(response.bytes().await?.as_ref(), png.as_slice());
+
+    // Replace the avatar using a query for the contact.
+    let jpeg = [b"\xff\xd8\xff\xe0".as_slice(), &[0; 28]].concat();
+    let body = ContactAvatarRequest::builder()
+        .query("Ada Lovelace".to_string())
+        .use_reranking_filter(true)
+        .content_type("image/jpeg".to_string())
+        .data(BASE64_STANDARD.encode(&jpeg))
+        .build();
+    let response = client.put(&avatar_url).json(&body).send().await?;
+    let response = utils::assert_ok_response(response).await?;
+    let avatar = response.json::<ContactAvatar>().await?;
+    assert_eq!(avatar.contact_id, ada_id);
+    assert_eq!(avatar.content_type, "image/jpeg");
+    let response = client
+        .get(format!("{contacts_url}/{ada_id}/avatar"))
+        .send()
+        .await?;
+    let response = utils::assert_ok_response(response).await?;
+    assert_eq!(response.headers()["content-type"], "image/jpeg");
+    assert_eq!(response.bytes().await?.as_ref(), jpeg.as_slice());
+
+    // Avatars larger than the max size are rejected.
+    let oversized = [b"\x89PNG\r\n\x1a\n".as_slice(), &[0; 64]].concat();
+    let body = ContactAvatarRequest::builder()
+        .id(charles_id)
+        .content_type("image/png".to_string())
+        .data(BASE64_STANDARD.encode(&oversized))
+        .build();
+    let response = client.put(&avatar_url).json(&body).send().await?;
+    assert_eq!(response.status(), StatusCode::PAYLOAD_TOO_LARGE);
+
+    // So are other kinds of images, images that aren't their content type,
+    // and data that isn't base64-encoded.
+    for (content_type, data) in [
+        ("image/gif", BASE64_STANDARD.encode(b"GIF89a")),
+        ("image/png", BASE64_STANDARD.encode(&jpeg)),
+        ("image/png", "not base64!".to_string()),
+    ] {
+        let body = ContactAvatarRequest::builder()
+            .id(charles_id)
+            .content_type(content_type.to_string())
+            .data(data)
+            .build();
+        let response = client.put(&avatar_url).json(&body).send().await?;
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    }
+    let response = client
+        .get(format!("{contacts_url}/{charles_id}/avatar"))
+        .send()
+        .await?;
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+
+    // Avatars can't be set for contacts that don't exist.
+    let body = ContactAvatarRequest::builder()
+        .id(charles_id + 1)
+        .content_type("image/png".to_string())
+        .data(BASE64_STANDARD.encode(&png))
+        .build();
+    let response = client.put(&avatar_url).json(&body).send().await?;
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+
+    Ok(())
+}
@@ -0,0 +1,95 @@
+use serial_test::serial;
+use toi::{Message, MessageRole};
+use tokio::net::TcpListener;
+use utoipa_axum::router::OpenApiRouter;
+
+use toi_server::models::conversations::{
+    Conversation, ConversationMessage, NewConversationMessagesRequest, NewConversationRequest,
+};
+
+mod utils;
+
+#[tokio::test]
+#[serial]
+async fn conversations_routes() -> Result<(), Box<dyn std::error::Error>> {
+    // Make sure there's a database URL and it points to a test database so
+    // prod isn't goofed during testing.
+    let db_connection_url = dotenvy::var("DATABASE_URL")?;
+    utils::reset_database(&db_connection_url)?;
+
+    // Initialize the server state.
+    let state = toi_server::init(db_connection_url).await?;
+    let openapi_router = OpenApiRouter::new().nest(
+        "/conversations",
+        toi_server::routes::conversations::conversations_router(state.clone()),
+    );
+    let (router, _) = openapi_router.split_for_parts();
+    let listener = TcpListener::bind(&state.server_config.bind_addr).await?;
+
+    // Spawn server and create a client for all test requests.
+    let _ = tokio::spawn(async move { axum::serve(listener, router).await });
+    let client = reqwest::Client::new();
+    let conversations_url = format!("http://{}/conversations", state.server_config.bind_addr);
+
+    // Start a conversation.
+    let body = NewConversationRequest::builder()
+        .title("Car maintenance".to_string())
+        .build();
+    let response = client.post(&conversations_url).json(&body).send().await?;
+    let response = utils::assert_ok_response(response).await?;
+    let conversation = response.json::<Conversation>().await?;
+
+    // Append some messages.
+    let messages_url = format!("{conversations_url}/{}/messages", conversation.id);
+    let body = NewConversationMessagesRequest::builder()
+        .messages(vec![
+            Message {
+                role: MessageRole::User,
+                content: "What oil does my car take?".to_string(),
+            },
+            Message {
+                role: MessageRole::Assistant,
+                content: "Your car takes OW-20 oil.".to_string(),
+            },
+        ])
+        .build();
+    let response = client.post(&messages_url).json(&body).send().await?;
+    let response = utils::assert_ok_response(response).await?;
+    let vec_messages1 = response.json::<Vec<ConversationMessage>>().await?;
+    let sequences: Vec<i32> = vec_messages1.iter().map(|m| m.sequence).collect();
+    assert_eq!(sequences, vec![0, 1]);
+
+    // Concurrent appends should still get unique, contiguous sequences.
+    let body = NewConversationMessagesRequest::builder()
+        .messages(vec![Message {
+            role: MessageRole::User,
+            content: "Thanks!".to_string(),
+        }])
+        .build();
+    let (response1, response2) = tokio::join!(
+        client.post(&messages_url).json(&body).send(),
+        client.post(&messages_url).json(&body).send(),
+    );
+    utils::assert_ok_response(response1?).await?;
+    utils::assert_ok_response(response2?).await?;
+
+    // Fetch all the messages in order.
+    let response = client.get(&messages_url).send().await?;
+    let response = utils::assert_ok_response(response).await?;
+    let vec_messages2 = response.json::<Vec<ConversationMessage>>().await?;
+    let sequences: Vec<i32> = vec_messages2.iter().map(|m| m.sequence).collect();
+    assert_eq!(sequences, vec![0, 1, 2, 3]);
+    assert_eq!(vec_messages2[..2], vec_messages1[..]);
+
+    // List conversations.
+    let response = client.get(&conversations_url).send().await?;
+    let response = utils::assert_ok_response(response).await?;
+    let vec_conversations = response.json::<Vec<Conversation>>().await?;
+    assert_eq!(vec_conversations, vec![conversation]);
+
+    // Missing conversations aren't mistaken for empty ones.
+    let missing_messages_url = format!("{conversations_url}/0/messages");
+    let response = client.get(missing_messages_url).send().await?;
+    assert_eq!(response.status(), reqwest::StatusCode::NOT_FOUND);
+    Ok(())
+}
@@ -1,10 +1,12 @@
-use chrono::{DateTime, Datelike, Duration, Utc, Weekday};
+use chrono::{DateTime, Datelike, Duration, NaiveDate, NaiveTime, TimeZone, Utc, Weekday};
 use serde_json::json;
 use serial_test::serial;
 use tokio::net::TcpListener;
 use utoipa_axum::router::OpenApiRouter;
 
-use toi_server::models::datetime::DateTimeShiftRequest;
+use toi_server::models::datetime::{
+    DateTimeConvertParams, DateTimeResolveParams, DateTimeShiftRequest, ResolvedDateTime,
+};
 
 mod utils;
 
@@ -55,5 +57,69 @@ async fn datetime_routes() -> Result<(), Box<dyn std::error::Error>> {
     let response = utils::assert_ok_response(response).await?;
     let weekday = response.json::<Weekday>().await?;
     assert_eq!(weekday, now.weekday());
+
+    // Convert 3pm Tokyo time to New York time.
+    let convert_url = format!("{datetime_url}/convert");
+    let datetime = NaiveDate::from_ymd_opt(2025, 6, 13)
+        .and_then(|date| date.and_hms_opt(15, 0, 0))
+        .ok_or("invalid test datetime")?;
+    let params = DateTimeConvertParams::builder()
+        .datetime(datetime)
+        .from_timezone("Asia/Tokyo".to_string())
+        .to_timezone("America/New_York".to_string())
+        .build();
+    let response = client.get(&convert_url).query(&params).send().await?;
+    let response = utils::assert_ok_response(response).await?;
+    let resolved = response.json::<ResolvedDateTime>().await?;
+    assert_eq!(
+        resolved.utc,
+        Utc.with_ymd_and_hms(2025, 6, 13, 6, 0, 0)
+            .single()
+            .ok_or("invalid test datetime")?
+    );
+    assert_eq!(resolved.local.to_rfc3339(), "2025-06-13T02:00:00-04:00");
+
+    // Invalid timezone names are rejected.
+    let params = DateTimeConvertParams::builder()
+        .datetime(datetime)
+        .from_timezone("Mars/Olympus_Mons".to_string())
+        .build();
+    let response = client.get(&convert_url).query(&params).send().await?;
+    assert_eq!(response.status(), reqwest::StatusCode::BAD_REQUEST);
+
+    // Local times skipped by daylight saving time are rejected.
+    let skipped_datetime = NaiveDate::from_ymd_opt(2025, 3, 9)
+        .and_then(|date| date.and_hms_opt(2, 30, 0))
+        .ok_or("invalid test datetime")?;
+    let params = DateTimeConvertParams::builder()
+        .datetime(skipped_datetime)
+        .from_timezone("America/New_York".to_string())
+        .build();
+    let response = client.get(&convert_url).query(&params).send().await?;
+    assert_eq!(response.status(), reqwest::StatusCode::BAD_REQUEST);
+
+    // Resolve next Friday at 3pm Tokyo time from a Wednesday.
+    let resolve_url = format!("{datetime_url}/resolve");
+    let params = DateTimeResolveParams::builder()
+        .datetime(
+            Utc.with_ymd_and_hms(2025, 6, 11, 0, 0, 0)
+                .single()
+                .ok_or("invalid test datetime")?,
+        )
+        .timezone("Asia/Tokyo".to_string())
+        .weekday(Weekday::Fri)
+        .time(NaiveTime::from_hms_opt(15, 0, 0).ok_or("invalid test time")?)
+        .build();
+    let response = client.get(&resolve_url).query(&params).send().await?;
+    let response = utils::assert_ok_response(response).await?;
+    let resolved = response.json::<ResolvedDateTime>().await?;
+    assert_eq!(
+        resolved.utc,
+        Utc.with_ymd_and_hms(2025, 6, 13, 6, 0, 0)
+            .single()
+            .ok_or("invalid test datetime")?
+    );
+    assert_eq!(resolved.local.to_rfc3339(), "2025-06-13T15:00:00+09:00");
+    assert_eq!(resolved.timezone, "Asia/Tokyo");
     Ok(())
 }
@@ -0,0 +1,177 @@
+use reqwest::StatusCode;
+use serde_json::{Value, json};
+use serial_test::serial;
+use tokio::net::TcpListener;
+use utoipa_axum::router::OpenApiRouter;
+
+mod utils;
+
+// ID that no item has since the database is reset before testing.
+const MISSING_ID: i32 = 999_999;
+
+/// Items in a response, whether it's a page of search results or a plain
+/// list of changed items.
+fn items(response: &Value) -> Vec<Value> {
+    response
+        .get("items")
+        .unwrap_or(response)
+        .as_array()
+        .cloned()
+        .expect("response should have a list of items")
+}
+
+#[tokio::test]
+#[serial]
+async fn empty_results() -> Result<(), Box<dyn std::error::Error>> {
+    // Make sure there's a database URL and it points to a test database so
+    // prod isn't goofed during testing.
+    let db_connection_url = dotenvy::var("DATABASE_URL")?;
+    utils::reset_database(&db_connection_url)?;
+
+    // Initialize the server state.
+    let state = toi_server::init(db_connection_url).await?;
+    let openapi_router = OpenApiRouter::new()
+        .nest(
+            "/banking/accounts",
+            toi_server::routes::accounts::accounts_router(state.clone()),
+        )
+        .nest(
+            "/banking/transactions",
+            toi_server::routes::transactions::transactions_router(state.clone()),
+        )
+        .nest(
+            "/contacts",
+            toi_server::routes::contacts::contacts_router(state.clone()),
+        )
+        .nest(
+            "/events",
+            toi_server::routes::events::events_router(state.clone()),
+        )
+        .nest(
+            "/notes",
+            toi_server::routes::notes::notes_router(state.clone()),
+        )
+        .nest(
+            "/places",
+            toi_server::routes::places::places_router(state.clone()),
+        )
+        .nest(
+            "/recipes",
+            toi_server::routes::recipes::recipes_router(state.clone()),
+        )
+        .nest(
+            "/tags",
+            toi_server::routes::tags::tags_router(state.clone()),
+        )
+        .nest(
+            "/todos",
+            toi_server::routes::todos::todos_router(state.clone()),
+        );
+    let (router, _) = openapi_router.split_for_parts();
+    let listener = TcpListener::bind(&state.server_config.bind_addr).await?;
+
+    // Spawn server and create a client for all test requests.
+    let _ = tokio::spawn(async move { axum::serve(listener, router).await });
+    let client = reqwest::Client::new();
+    let base_url = format!("http://{}", state.server_config.bind_addr);
+
+    // Searches that don't match anything are empty rather than errors.
+    for prefix in [
+        "banking/accounts",
+        "banking/transactions",
+        "contacts",
+        "events",
+        "notes",
+        "places",
+        "recipes",
+        "recipes/previews",
+        "tags",
+        "todos",
+    ] {
+        let response = client
+            .post(format!("{base_url}/{prefix}/search"))
+            .json(&json!({}))
+            .send()
+            .await?;
+        let response = utils::assert_ok_response(response).await?;
+        assert!(
+            items(&response.json().await?).is_empty(),
+            "{prefix} search should be empty"
+        );
+    }
+
+    // So are bulk changes that only filter items, which don't change
+    // anything. Bulk changes to items selected by IDs that aren't found are
+    // a 404 instead.
+    for prefix in [
+        "banking/accounts",
+        "contacts",
+        "events",
+        "notes",
+        "places",
+        "recipes",
+        "recipes/previews",
+        "tags",
+        "todos",
+    ] {
+        let url = format!("{base_url}/{prefix}/delete");
+        let response = client
+            .post(&url)
+            .json(&json!({"confirm_delete_all": true}))
+            .send()
+            .await?;
+        let response = utils::assert_ok_response(response).await?;
+        assert!(
+            items(&response.json().await?).is_empty(),
+            "{prefix} delete should be empty"
+        );
+        let response = client
+            .post(&url)
+            .json(&json!({"ids": [MISSING_ID]}))
+            .send()
+            .await?;
+        assert_eq!(
+            response.status(),
+            StatusCode::NOT_FOUND,
+            "{prefix} delete by IDs should be a 404"
+        );
+    }
+    let response = client
+        .post(format!("{base_url}/banking/transactions/delete"))
+        .json(&json!({}))
+        .send()
+        .await?;
+    let response = utils::assert_ok_response(response).await?;
+    assert!(items(&response.json().await?).is_empty());
+    for prefix in ["notes", "todos"] {
+        let url = format!("{base_url}/{prefix}/restore");
+        let response = client.post(&url).json(&json!({})).send().await?;
+        let response = utils::assert_ok_response(response).await?;
+        assert!(items(&response.json().await?).is_empty());
+        let response = client
+            .post(&url)
+            .json(&json!({"ids": [MISSING_ID]}))
+            .send()
+            .await?;
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    }
+
+    // Updates work the same way.
+    let todos_url = format!("{base_url}/todos");
+    let response = client.put(&todos_url).json(&json!({})).send().await?;
+    let response = utils::assert_ok_response(response).await?;
+    assert!(items(&response.json().await?).is_empty());
+    let response = client
+        .put(&todos_url)
+        .json(&json!({"ids": [MISSING_ID]}))
+        .send()
+        .await?;
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    let response = client
+        .put(format!("{todos_url}/snooze"))
+        .json(&json!({"ids": [MISSING_ID], "snooze_for_days": 1}))
+        .send()
+        .await?;
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    Ok(())
+}
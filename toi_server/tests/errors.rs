@@ -0,0 +1,70 @@
+use reqwest::StatusCode;
+use serial_test::serial;
+use tokio::net::TcpListener;
+use utoipa_axum::router::OpenApiRouter;
+
+use toi_server::models::{
+    contacts::{ContactUpdates, UpdateContactRequest},
+    error::{ErrorCode, ErrorResponse},
+    notes::NewNoteRequest,
+};
+
+mod utils;
+
+#[tokio::test]
+#[serial]
+async fn error_responses() -> Result<(), Box<dyn std::error::Error>> {
+    // Make sure there's a database URL and it points to a test database so
+    // prod isn't goofed during testing.
+    let db_connection_url = dotenvy::var("DATABASE_URL")?;
+    utils::reset_database(&db_connection_url)?;
+
+    // Initialize the server state, pointing the embedding API somewhere
+    // that can't be reached.
+    let mut state = toi_server::init(db_connection_url).await?;
+    state.model_client.embedding_api_config.base_url = "http://127.0.0.1:1".to_string();
+    let openapi_router = OpenApiRouter::new()
+        .nest(
+            "/contacts",
+            toi_server::routes::contacts::contacts_router(state.clone()),
+        )
+        .nest(
+            "/notes",
+            toi_server::routes::notes::notes_router(state.clone()),
+        );
+    let (router, _) = openapi_router.split_for_parts();
+    let listener = TcpListener::bind(&state.server_config.bind_addr).await?;
+
+    // Spawn server and create a client for all test requests.
+    let _ = tokio::spawn(async move { axum::serve(listener, router).await });
+    let client = reqwest::Client::new();
+    let base_url = format!("http://{}", state.server_config.bind_addr);
+
+    // Updating a contact that doesn't exist is a 404.
+    let contacts_url = format!("{base_url}/contacts");
+    let body = UpdateContactRequest::builder()
+        .id(0)
+        .contact_updates(
+            ContactUpdates::builder()
+                .phone("555-555-5555".to_string())
+                .build(),
+        )
+        .build();
+    let response = client.put(&contacts_url).json(&body).send().await?;
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    let error_response = response.json::<ErrorResponse>().await?;
+    assert_eq!(error_response.error.code, ErrorCode::NotFound);
+    assert!(!error_response.error.message.is_empty());
+
+    // Adding a note while the embedding API is down is a 502.
+    let notes_url = format!("{base_url}/notes");
+    let body = NewNoteRequest::builder()
+        .content("My car takes OW-20 oil".to_string())
+        .build();
+    let response = client.post(&notes_url).json(&body).send().await?;
+    assert_eq!(response.status(), StatusCode::BAD_GATEWAY);
+    let error_response = response.json::<ErrorResponse>().await?;
+    assert_eq!(error_response.error.code, ErrorCode::Upstream);
+    assert!(!error_response.error.detail.is_empty());
+    Ok(())
+}
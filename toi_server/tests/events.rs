@@ -1,11 +1,20 @@
 use std::str::FromStr;
 
-use chrono::DateTime;
+use chrono::{DateTime, NaiveDate, TimeDelta, Utc};
 use serial_test::serial;
 use tokio::net::TcpListener;
 use utoipa_axum::router::OpenApiRouter;
 
-use toi_server::models::events::{Event, EventSearchParams, NewEventRequest};
+use toi_server::models::{
+    attendees::AttendeeSearchParams,
+    contacts::NewContactRequest,
+    events::{
+        Event, EventCreated, EventOrderBy, EventSearchParams, EventWithPlace, NewEventRequest,
+        RecurrenceFrequency, UpcomingEvent, UpcomingEventsRequest, UpcomingWindow,
+    },
+    pagination::{Count, Page},
+    places::{NewPlaceRequest, Place, PlaceSearchParams},
+};
 
 mod utils;
 
@@ -43,6 +52,25 @@ async fn events_routes() -> Result<(), Box<dyn std::error::Error>> {
     let event1 = response.json::<Event>().await?;
     assert_eq!(event1.description, event_description);
 
+    // Retrieve the event using its ID, and fail to retrieve a missing one.
+    let response = client
+        .get(format!("{events_url}/{}", event1.id))
+        .send()
+        .await?;
+    let response = utils::assert_ok_response(response).await?;
+    assert_eq!(response.json::<Event>().await?, event1);
+    let response = client.get(format!("{events_url}/0")).send().await?;
+    assert_eq!(response.status(), reqwest::StatusCode::NOT_FOUND);
+
+    // Events can't end before they start.
+    let body = NewEventRequest::builder()
+        .description("Tire rotation".to_string())
+        .starts_at(DateTime::from_str("2025-05-08T23:38:38+0000")?)
+        .ends_at(DateTime::from_str("2025-05-08T22:38:38+0000")?)
+        .build();
+    let response = client.post(&events_url).json(&body).send().await?;
+    assert_eq!(response.status(), reqwest::StatusCode::BAD_REQUEST);
+
     // Retrieve the event using search.
     let search_events_url = format!("{events_url}/search");
     let params = EventSearchParams::builder()
@@ -50,7 +78,7 @@ async fn events_routes() -> Result<(), Box<dyn std::error::Error>> {
         .build();
     let response = client.post(search_events_url).json(&params).send().await?;
     let response = utils::assert_ok_response(response).await?;
-    let vec_events1 = response.json::<Vec<Event>>().await?;
+    let vec_events1 = response.json::<Page<Event>>().await?.items;
     assert_eq!(vec_events1, vec![event1]);
 
     // Delete the event using search.
@@ -61,3 +89,565 @@ async fn events_routes() -> Result<(), Box<dyn std::error::Error>> {
     assert_eq!(vec_events2, vec_events1);
     Ok(())
 }
+
+/// Add an event, returning the descriptions of the events it conflicts with.
+async fn add_event(
+    client: &reqwest::Client,
+    events_url: &str,
+    description: &str,
+    starts_at: &str,
+    ends_at: &str,
+) -> Result<Vec<String>, Box<dyn std::error::Error>> {
+    let body = NewEventRequest::builder()
+        .description(description.to_string())
+        .starts_at(DateTime::from_str(starts_at)?)
+        .ends_at(DateTime::from_str(ends_at)?)
+        .build();
+    let response = client.post(events_url).json(&body).send().await?;
+    let response = utils::assert_ok_response(response).await?;
+    let event_created = response.json::<EventCreated>().await?;
+    assert_eq!(event_created.event.description, description);
+    Ok(event_created
+        .conflicts
+        .into_iter()
+        .map(|event| event.description)
+        .collect())
+}
+
+#[tokio::test]
+#[serial]
+async fn events_conflicts() -> Result<(), Box<dyn std::error::Error>> {
+    // Make sure there's a database URL and it points to a test database so
+    // prod isn't goofed during testing.
+    let db_connection_url = dotenvy::var("DATABASE_URL")?;
+    utils::reset_database(&db_connection_url)?;
+
+    // Initialize the server state.
+    let state = toi_server::init(db_connection_url).await?;
+    let openapi_router = OpenApiRouter::new().nest(
+        "/events",
+        toi_server::routes::events::events_router(state.clone()),
+    );
+    let (router, _) = openapi_router.split_for_parts();
+    let listener = TcpListener::bind(&state.server_config.bind_addr).await?;
+
+    // Spawn server and create a client for all test requests.
+    let _ = tokio::spawn(async move { axum::serve(listener, router).await });
+    let client = reqwest::Client::new();
+    let events_url = format!("http://{}/events", state.server_config.bind_addr);
+
+    // Nothing conflicts with the first event.
+    let conflicts = add_event(
+        &client,
+        &events_url,
+        "Dentist appointment",
+        "2025-05-08T10:00:00+0000",
+        "2025-05-08T11:00:00+0000",
+    )
+    .await?;
+    assert!(conflicts.is_empty());
+
+    // An overlapping event is still added, but the conflict is returned.
+    let conflicts = add_event(
+        &client,
+        &events_url,
+        "Lunch with Sam",
+        "2025-05-08T10:30:00+0000",
+        "2025-05-08T11:30:00+0000",
+    )
+    .await?;
+    assert_eq!(conflicts, vec!["Dentist appointment"]);
+
+    // Events that only touch don't conflict.
+    let conflicts = add_event(
+        &client,
+        &events_url,
+        "Gym",
+        "2025-05-08T11:30:00+0000",
+        "2025-05-08T12:30:00+0000",
+    )
+    .await?;
+    assert!(conflicts.is_empty());
+
+    // Events that end when they start are assumed to last an hour, both
+    // when they're added and when other events are added after them.
+    let conflicts = add_event(
+        &client,
+        &events_url,
+        "Call mom",
+        "2025-05-08T12:15:00+0000",
+        "2025-05-08T12:15:00+0000",
+    )
+    .await?;
+    assert_eq!(conflicts, vec!["Gym"]);
+    let conflicts = add_event(
+        &client,
+        &events_url,
+        "Pick up package",
+        "2025-05-08T13:00:00+0000",
+        "2025-05-08T13:00:00+0000",
+    )
+    .await?;
+    assert_eq!(conflicts, vec!["Call mom"]);
+    let conflicts = add_event(
+        &client,
+        &events_url,
+        "Team meeting",
+        "2025-05-08T14:00:00+0000",
+        "2025-05-08T15:00:00+0000",
+    )
+    .await?;
+    assert!(conflicts.is_empty());
+
+    // Conflicting events are rejected if they're asked to be, and they
+    // aren't added.
+    let body = NewEventRequest::builder()
+        .description("Code review".to_string())
+        .starts_at(DateTime::from_str("2025-05-08T10:15:00+0000")?)
+        .ends_at(DateTime::from_str("2025-05-08T10:45:00+0000")?)
+        .fail_on_conflict(true)
+        .build();
+    let response = client.post(&events_url).json(&body).send().await?;
+    assert_eq!(response.status(), reqwest::StatusCode::CONFLICT);
+    let message = response.text().await?;
+    assert!(message.contains("Dentist appointment"));
+    assert!(message.contains("Lunch with Sam"));
+    let params = EventSearchParams::builder().count_only(true).build();
+    let response = client
+        .post(format!("{events_url}/search"))
+        .json(&params)
+        .send()
+        .await?;
+    let response = utils::assert_ok_response(response).await?;
+    assert_eq!(response.json::<Count>().await?.count, 6);
+
+    // Events without conflicts are added even if they're asked to fail on
+    // conflicts.
+    let body = NewEventRequest::builder()
+        .description("Code review".to_string())
+        .starts_at(DateTime::from_str("2025-05-08T16:00:00+0000")?)
+        .ends_at(DateTime::from_str("2025-05-08T16:30:00+0000")?)
+        .fail_on_conflict(true)
+        .build();
+    let response = client.post(&events_url).json(&body).send().await?;
+    let response = utils::assert_ok_response(response).await?;
+    assert!(response.json::<EventCreated>().await?.conflicts.is_empty());
+    Ok(())
+}
+
+#[tokio::test]
+#[serial]
+async fn events_start_ordering() -> Result<(), Box<dyn std::error::Error>> {
+    // Make sure there's a database URL and it points to a test database so
+    // prod isn't goofed during testing.
+    let db_connection_url = dotenvy::var("DATABASE_URL")?;
+    utils::reset_database(&db_connection_url)?;
+
+    // Initialize the server state.
+    let state = toi_server::init(db_connection_url).await?;
+    let openapi_router = OpenApiRouter::new().nest(
+        "/events",
+        toi_server::routes::events::events_router(state.clone()),
+    );
+    let (router, _) = openapi_router.split_for_parts();
+    let listener = TcpListener::bind(&state.server_config.bind_addr).await?;
+
+    // Spawn server and create a client for all test requests.
+    let _ = tokio::spawn(async move { axum::serve(listener, router).await });
+    let client = reqwest::Client::new();
+    let events_url = format!("http://{}/events", state.server_config.bind_addr);
+    let search_events_url = format!("{events_url}/search");
+
+    // Make events out of order from when they start, including one that
+    // already happened and a weekly one whose next repeat is the soonest.
+    let now = Utc::now();
+    let starts = [
+        ("Dentist", now + TimeDelta::days(10), None),
+        ("Haircut", now - TimeDelta::days(3), None),
+        ("Dinner", now + TimeDelta::days(3), None),
+        (
+            "Standup",
+            now - TimeDelta::days(6),
+            Some(RecurrenceFrequency::Weekly),
+        ),
+    ];
+    for (description, starts_at, recurrence_frequency) in starts {
+        let body = NewEventRequest::builder()
+            .description(description.to_string())
+            .starts_at(starts_at)
+            .ends_at(starts_at + TimeDelta::hours(1))
+            .maybe_recurrence_frequency(recurrence_frequency)
+            .build();
+        let response = client.post(&events_url).json(&body).send().await?;
+        utils::assert_ok_response(response).await?;
+    }
+    let search = |params: EventSearchParams| {
+        let client = client.clone();
+        let search_events_url = search_events_url.clone();
+        async move {
+            let response = client.post(search_events_url).json(&params).send().await?;
+            let response = utils::assert_ok_response(response).await?;
+            let descriptions: Vec<String> = response
+                .json::<Page<Event>>()
+                .await?
+                .items
+                .into_iter()
+                .map(|event| event.description)
+                .collect();
+            Ok::<_, Box<dyn std::error::Error>>(descriptions)
+        }
+    };
+
+    // Order events by when they start.
+    let params = EventSearchParams::builder()
+        .order_by(EventOrderBy::StartsSoonest)
+        .build();
+    assert_eq!(
+        search(params).await?,
+        vec!["Standup", "Haircut", "Dinner", "Dentist"]
+    );
+    let params = EventSearchParams::builder()
+        .order_by(EventOrderBy::StartsLatest)
+        .build();
+    assert_eq!(
+        search(params).await?,
+        vec!["Dentist", "Dinner", "Haircut", "Standup"]
+    );
+
+    // Only get events that haven't started yet, ordered by their next
+    // repeats.
+    let params = EventSearchParams::builder()
+        .upcoming_only(true)
+        .order_by(EventOrderBy::StartsSoonest)
+        .build();
+    assert_eq!(search(params).await?, vec!["Standup", "Dinner", "Dentist"]);
+
+    // Get the next event.
+    let params = EventSearchParams::builder()
+        .upcoming_only(true)
+        .order_by(EventOrderBy::StartsSoonest)
+        .limit(1)
+        .build();
+    assert_eq!(search(params).await?, vec!["Standup"]);
+
+    // Get the next event on a given day.
+    let params = EventSearchParams::builder()
+        .upcoming_only(true)
+        .event_day((now + TimeDelta::days(10)).date_naive())
+        .order_by(EventOrderBy::StartsSoonest)
+        .limit(1)
+        .build();
+    assert_eq!(search(params).await?, vec!["Dentist"]);
+
+    // Repeating events that don't repeat on a given day don't take up the
+    // page or count towards the total.
+    let params = EventSearchParams::builder()
+        .event_day((now + TimeDelta::days(10)).date_naive())
+        .limit(1)
+        .build();
+    let response = client.post(&search_events_url).json(&params).send().await?;
+    let response = utils::assert_ok_response(response).await?;
+    let page = response.json::<Page<Event>>().await?;
+    assert_eq!(page.total, 1);
+    assert_eq!(page.items.len(), 1);
+    assert_eq!(page.items[0].description, "Dentist");
+    Ok(())
+}
+
+#[tokio::test]
+#[serial]
+async fn events_day_timezones() -> Result<(), Box<dyn std::error::Error>> {
+    // Make sure there's a database URL and it points to a test database so
+    // prod isn't goofed during testing.
+    let db_connection_url = dotenvy::var("DATABASE_URL")?;
+    utils::reset_database(&db_connection_url)?;
+
+    // Initialize the server state.
+    let state = toi_server::init(db_connection_url).await?;
+    let openapi_router = OpenApiRouter::new().nest(
+        "/events",
+        toi_server::routes::events::events_router(state.clone()),
+    );
+    let (router, _) = openapi_router.split_for_parts();
+    let listener = TcpListener::bind(&state.server_config.bind_addr).await?;
+
+    // Spawn server and create a client for all test requests.
+    let _ = tokio::spawn(async move { axum::serve(listener, router).await });
+    let client = reqwest::Client::new();
+    let events_url = format!("http://{}/events", state.server_config.bind_addr);
+    let search_events_url = format!("{events_url}/search");
+
+    // Make events around a day boundary in Chicago, where 9pm is already
+    // the next day in UTC, and around the start of daylight saving time in
+    // New York, when the day is only 23 hours long.
+    let starts = [
+        ("Brunch", "2025-06-01T16:00:00Z"),
+        ("Late dinner", "2025-06-02T02:00:00Z"),
+        ("Late show", "2025-03-10T03:00:00Z"),
+        ("Early flight", "2025-03-10T04:30:00Z"),
+    ];
+    for (description, starts_at) in starts {
+        let starts_at: DateTime<Utc> = starts_at.parse()?;
+        let body = NewEventRequest::builder()
+            .description(description.to_string())
+            .starts_at(starts_at)
+            .ends_at(starts_at + TimeDelta::minutes(30))
+            .build();
+        let response = client.post(&events_url).json(&body).send().await?;
+        utils::assert_ok_response(response).await?;
+    }
+    let search = |event_day: &str, timezone: &str| {
+        let client = client.clone();
+        let search_events_url = search_events_url.clone();
+        let params = event_day.parse::<NaiveDate>().map(|event_day| {
+            EventSearchParams::builder()
+                .event_day(event_day)
+                .timezone(timezone.to_string())
+                .order_by(EventOrderBy::StartsSoonest)
+                .build()
+        });
+        async move {
+            let response = client.post(search_events_url).json(&params?).send().await?;
+            let response = utils::assert_ok_response(response).await?;
+            let descriptions: Vec<String> = response
+                .json::<Page<Event>>()
+                .await?
+                .items
+                .into_iter()
+                .map(|event| event.description)
+                .collect();
+            Ok::<_, Box<dyn std::error::Error>>(descriptions)
+        }
+    };
+
+    // The late dinner is on June 1st in Chicago, but not in UTC.
+    assert_eq!(
+        search("2025-06-01", "America/Chicago").await?,
+        vec!["Brunch", "Late dinner"]
+    );
+    assert_eq!(search("2025-06-01", "UTC").await?, vec!["Brunch"]);
+    assert_eq!(search("2025-06-02", "UTC").await?, vec!["Late dinner"]);
+
+    // The day clocks spring forward ends at midnight local time, which is
+    // an hour earlier in UTC than the day started.
+    assert_eq!(
+        search("2025-03-09", "America/New_York").await?,
+        vec!["Late show"]
+    );
+    assert_eq!(
+        search("2025-03-10", "America/New_York").await?,
+        vec!["Early flight"]
+    );
+
+    // Timezones have to be real IANA timezone names.
+    let params = EventSearchParams::builder()
+        .event_day("2025-06-01".parse()?)
+        .timezone("America/Springfield".to_string())
+        .build();
+    let response = client.post(&search_events_url).json(&params).send().await?;
+    assert_eq!(response.status(), reqwest::StatusCode::BAD_REQUEST);
+    Ok(())
+}
+
+#[tokio::test]
+#[serial]
+async fn upcoming_events_route() -> Result<(), Box<dyn std::error::Error>> {
+    // Make sure there's a database URL and it points to a test database so
+    // prod isn't goofed during testing.
+    let db_connection_url = dotenvy::var("DATABASE_URL")?;
+    utils::reset_database(&db_connection_url)?;
+
+    // Initialize the server state.
+    let state = toi_server::init(db_connection_url).await?;
+    let openapi_router = OpenApiRouter::new()
+        .nest(
+            "/contacts",
+            toi_server::routes::contacts::contacts_router(state.clone()),
+        )
+        .nest(
+            "/events",
+            toi_server::routes::events::events_router(state.clone()).nest(
+                "/attendees",
+                toi_server::routes::attendees::attendees_router(state.clone()),
+            ),
+        );
+    let (router, _) = openapi_router.split_for_parts();
+    let listener = TcpListener::bind(&state.server_config.bind_addr).await?;
+
+    // Spawn server and create a client for all test requests.
+    let _ = tokio::spawn(async move { axum::serve(listener, router).await });
+    let client = reqwest::Client::new();
+    let contacts_url = format!("http://{}/contacts", state.server_config.bind_addr);
+    let events_url = format!("http://{}/events", state.server_config.bind_addr);
+
+    // Make one event in the middle of next week and one the week after.
+    let (window_start, _) = UpcomingWindow::NextWeek
+        .range(Utc::now(), state.server_config.timezone)
+        .ok_or("invalid upcoming window")?;
+    let mut upcoming_events = vec![];
+    for (description, starts_at) in [
+        ("Dentist appointment", window_start + TimeDelta::days(3)),
+        ("Camping trip", window_start + TimeDelta::days(10)),
+    ] {
+        let body = NewEventRequest::builder()
+            .description(description.to_string())
+            .starts_at(starts_at)
+            .ends_at(starts_at + TimeDelta::hours(1))
+            .build();
+        let response = client.post(&events_url).json(&body).send().await?;
+        let response = utils::assert_ok_response(response).await?;
+        upcoming_events.push(response.json::<Event>().await?);
+    }
+
+    // Make a contact that's going to the dentist too.
+    let body = NewContactRequest::builder()
+        .first_name("Ada".to_string())
+        .last_name("Lovelace".to_string())
+        .build();
+    let response = client.post(&contacts_url).json(&body).send().await?;
+    utils::assert_ok_response(response).await?;
+    let attendees_url = format!("{events_url}/attendees");
+    let params = AttendeeSearchParams::builder()
+        .event_id(upcoming_events[0].id)
+        .contact_query("Ada Lovelace".to_string())
+        .build();
+    let response = client.post(&attendees_url).json(&params).send().await?;
+    utils::assert_ok_response(response).await?;
+
+    // Only the event within next week is upcoming, and it comes with the
+    // names of who's attending.
+    let upcoming_events_url = format!("{events_url}/upcoming");
+    let body = UpcomingEventsRequest::builder()
+        .window(UpcomingWindow::NextWeek)
+        .build();
+    let response = client.post(&upcoming_events_url).json(&body).send().await?;
+    let response = utils::assert_ok_response(response).await?;
+    let events = response.json::<Vec<UpcomingEvent>>().await?;
+    assert_eq!(events.len(), 1);
+    assert_eq!(events[0].event, upcoming_events[0]);
+    assert_eq!(events[0].occurs_at, upcoming_events[0].starts_at);
+    assert_eq!(events[0].attendees, vec!["Ada Lovelace".to_string()]);
+    Ok(())
+}
+
+#[tokio::test]
+#[serial]
+async fn events_places() -> Result<(), Box<dyn std::error::Error>> {
+    // Make sure there's a database URL and it points to a test database so
+    // prod isn't goofed during testing.
+    let db_connection_url = dotenvy::var("DATABASE_URL")?;
+    utils::reset_database(&db_connection_url)?;
+
+    // Initialize the server state.
+    let state = toi_server::init(db_connection_url).await?;
+    let openapi_router = OpenApiRouter::new()
+        .nest(
+            "/events",
+            toi_server::routes::events::events_router(state.clone()),
+        )
+        .nest(
+            "/places",
+            toi_server::routes::places::places_router(state.clone()),
+        );
+    let (router, _) = openapi_router.split_for_parts();
+    let listener = TcpListener::bind(&state.server_config.bind_addr).await?;
+
+    // Spawn server and create a client for all test requests.
+    let _ = tokio::spawn(async move { axum::serve(listener, router).await });
+    let client = reqwest::Client::new();
+    let events_url = format!("http://{}/events", state.server_config.bind_addr);
+    let places_url = format!("http://{}/places", state.server_config.bind_addr);
+    let search_events_url = format!("{events_url}/search");
+
+    // Make a place.
+    let body = NewPlaceRequest::builder()
+        .name("Luigi's".to_string())
+        .description("Italian restaurant downtown".to_string())
+        .build();
+    let response = client.post(&places_url).json(&body).send().await?;
+    let response = utils::assert_ok_response(response).await?;
+    let place = response.json::<Place>().await?;
+
+    // Make an event at the place by searching for it, and one that isn't at
+    // any place.
+    let starts_at = Utc::now() + TimeDelta::days(3);
+    let body = NewEventRequest::builder()
+        .description("Friday's dinner".to_string())
+        .starts_at(starts_at)
+        .ends_at(starts_at + TimeDelta::hours(2))
+        .place_query("Luigi's".to_string())
+        .build();
+    let response = client.post(&events_url).json(&body).send().await?;
+    let response = utils::assert_ok_response(response).await?;
+    let event1 = response.json::<Event>().await?;
+    assert_eq!(event1.place_id, Some(place.id));
+    let body = NewEventRequest::builder()
+        .description("Dentist appointment".to_string())
+        .starts_at(starts_at)
+        .ends_at(starts_at + TimeDelta::hours(1))
+        .build();
+    let response = client.post(&events_url).json(&body).send().await?;
+    let response = utils::assert_ok_response(response).await?;
+    assert_eq!(response.json::<Event>().await?.place_id, None);
+
+    // Events can't be linked to missing places.
+    let body = NewEventRequest::builder()
+        .description("Lunch".to_string())
+        .starts_at(starts_at)
+        .ends_at(starts_at + TimeDelta::hours(1))
+        .place_id(0)
+        .build();
+    let response = client.post(&events_url).json(&body).send().await?;
+    assert_eq!(response.status(), reqwest::StatusCode::NOT_FOUND);
+
+    // Search events at the place, which come with the place when it's asked
+    // for.
+    let params = EventSearchParams::builder()
+        .place_query("Luigi's".to_string())
+        .build();
+    let response = client.post(&search_events_url).json(&params).send().await?;
+    let response = utils::assert_ok_response(response).await?;
+    let events = response.json::<Page<EventWithPlace>>().await?.items;
+    assert_eq!(
+        events,
+        vec![EventWithPlace {
+            event: event1.clone(),
+            place: None,
+        }]
+    );
+    let params = EventSearchParams::builder()
+        .query("Friday's dinner".to_string())
+        .include_place(true)
+        .build();
+    let response = client.post(&search_events_url).json(&params).send().await?;
+    let response = utils::assert_ok_response(response).await?;
+    let events = response.json::<Page<EventWithPlace>>().await?.items;
+    assert_eq!(
+        events,
+        vec![EventWithPlace {
+            event: event1.clone(),
+            place: Some(place),
+        }]
+    );
+
+    // Deleting the place leaves the event without one rather than deleting
+    // it too.
+    let params = PlaceSearchParams::builder()
+        .query("Luigi's".to_string())
+        .build();
+    let response = client
+        .post(format!("{places_url}/delete"))
+        .json(&params)
+        .send()
+        .await?;
+    utils::assert_ok_response(response).await?;
+    let response = client
+        .get(format!("{events_url}/{}", event1.id))
+        .send()
+        .await?;
+    let response = utils::assert_ok_response(response).await?;
+    let event = response.json::<Event>().await?;
+    assert_eq!(event.place_id, None);
+    assert_eq!(event.description, event1.description);
+    Ok(())
+}
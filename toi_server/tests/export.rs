@@ -0,0 +1,80 @@
+use serial_test::serial;
+use tokio::net::TcpListener;
+use utoipa_axum::router::OpenApiRouter;
+
+use toi_server::models::{
+    export::{ExportFormat, ExportParams},
+    notes::{NewNoteRequest, Note},
+};
+
+mod utils;
+
+#[tokio::test]
+#[serial]
+async fn export_routes() -> Result<(), Box<dyn std::error::Error>> {
+    // Make sure there's a database URL and it points to a test database so
+    // prod isn't goofed during testing.
+    let db_connection_url = dotenvy::var("DATABASE_URL")?;
+    utils::reset_database(&db_connection_url)?;
+
+    // Initialize the server state.
+    let state = toi_server::init(db_connection_url).await?;
+    let openapi_router = OpenApiRouter::new()
+        .nest(
+            "/export",
+            toi_server::routes::export::export_router(state.clone()),
+        )
+        .nest(
+            "/notes",
+            toi_server::routes::notes::notes_router(state.clone()),
+        );
+    let (router, _) = openapi_router.split_for_parts();
+    let listener = TcpListener::bind(&state.server_config.bind_addr).await?;
+
+    // Spawn server and create a client for all test requests.
+    let _ = tokio::spawn(async move { axum::serve(listener, router).await });
+    let client = reqwest::Client::new();
+    let base_url = format!("http://{}", state.server_config.bind_addr);
+
+    // Make a couple of notes.
+    let notes_url = format!("{base_url}/notes");
+    let mut notes = vec![];
+    for content in ["My car takes OW-20 oil", "The wifi password is hunter2"] {
+        let body = NewNoteRequest::builder()
+            .content(content.to_string())
+            .build();
+        let response = client.post(&notes_url).json(&body).send().await?;
+        let response = utils::assert_ok_response(response).await?;
+        notes.push(response.json::<Note>().await?);
+    }
+
+    // Export notes as JSON lines.
+    let export_notes_url = format!("{base_url}/export/notes");
+    let response = client.get(&export_notes_url).send().await?;
+    let response = utils::assert_ok_response(response).await?;
+    let text = response.text().await?;
+    let exported_notes = text
+        .lines()
+        .map(serde_json::from_str::<Note>)
+        .collect::<Result<Vec<Note>, _>>()?;
+    assert_eq!(exported_notes, notes);
+    assert!(!text.contains("embedding"));
+
+    // Export notes as CSV.
+    let params = ExportParams::builder().format(ExportFormat::Csv).build();
+    let response = client.get(&export_notes_url).query(&params).send().await?;
+    let response = utils::assert_ok_response(response).await?;
+    let text = response.text().await?;
+    let lines: Vec<&str> = text.lines().collect();
+    assert_eq!(lines.len(), 3);
+    assert_eq!(lines[0], "id,content,created_at");
+    assert!(lines[1].contains("OW-20"));
+
+    // Unknown domains are rejected.
+    let response = client
+        .get(format!("{base_url}/export/secrets"))
+        .send()
+        .await?;
+    assert_eq!(response.status(), reqwest::StatusCode::BAD_REQUEST);
+    Ok(())
+}
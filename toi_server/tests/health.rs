@@ -0,0 +1,48 @@
+use reqwest::StatusCode;
+use serial_test::serial;
+use tokio::net::TcpListener;
+use utoipa_axum::router::OpenApiRouter;
+
+use toi_server::models::health::{DependencyStatus, Readiness};
+
+mod utils;
+
+#[tokio::test]
+#[serial]
+async fn health_routes() -> Result<(), Box<dyn std::error::Error>> {
+    // Make sure there's a database URL and it points to a test database so
+    // prod isn't goofed during testing.
+    let db_connection_url = dotenvy::var("DATABASE_URL")?;
+    utils::reset_database(&db_connection_url)?;
+
+    // Initialize the server state.
+    let state = toi_server::init(db_connection_url).await?;
+    let openapi_router =
+        OpenApiRouter::new().merge(toi_server::routes::health::health_router(state.clone()));
+    let (router, _) = openapi_router.split_for_parts();
+    let listener = TcpListener::bind(&state.server_config.bind_addr).await?;
+
+    // Spawn server and create a client for all test requests.
+    let _ = tokio::spawn(async move { axum::serve(listener, router).await });
+    let client = reqwest::Client::new();
+    let base_url = format!("http://{}", state.server_config.bind_addr);
+
+    // The process is up.
+    let health_url = format!("{base_url}/health");
+    let response = client.get(health_url).send().await?;
+    utils::assert_ok_response(response).await?;
+
+    // The database is always available during tests, but the model APIs
+    // may not be, so only the database status is strictly checked.
+    let ready_url = format!("{base_url}/ready");
+    let response = client.get(ready_url).send().await?;
+    let status = response.status();
+    let readiness = response.json::<Readiness>().await?;
+    assert_eq!(readiness.database.status, DependencyStatus::Ok);
+    if readiness.is_ready() {
+        assert_eq!(status, StatusCode::OK);
+    } else {
+        assert_eq!(status, StatusCode::SERVICE_UNAVAILABLE);
+    }
+    Ok(())
+}
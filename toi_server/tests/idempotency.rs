@@ -0,0 +1,147 @@
+use serial_test::serial;
+use tokio::net::TcpListener;
+use utoipa_axum::router::OpenApiRouter;
+
+use toi_server::models::{
+    error::{ErrorCode, ErrorResponse},
+    notes::{NewNoteRequest, Note, NoteSearchParams},
+    pagination::Page,
+};
+
+mod utils;
+
+#[tokio::test]
+#[serial]
+async fn idempotency_keys() -> Result<(), Box<dyn std::error::Error>> {
+    // Make sure there's a database URL and it points to a test database so
+    // prod isn't goofed during testing.
+    let db_connection_url = dotenvy::var("DATABASE_URL")?;
+    utils::reset_database(&db_connection_url)?;
+
+    // Initialize the server state.
+    let state = toi_server::init(db_connection_url).await?;
+    let openapi_router = OpenApiRouter::new().nest(
+        "/notes",
+        toi_server::routes::notes::notes_router(state.clone()),
+    );
+    let (router, _) = openapi_router.split_for_parts();
+    let listener = TcpListener::bind(&state.server_config.bind_addr).await?;
+
+    // Spawn server and create a client for all test requests.
+    let _ = tokio::spawn(async move { axum::serve(listener, router).await });
+    let client = reqwest::Client::new();
+    let notes_url = format!("http://{}/notes", state.server_config.bind_addr);
+
+    // Repeating a request with the same key replays the original response
+    // instead of adding another note.
+    let body = NewNoteRequest::builder()
+        .content("My car takes OW-20 oil".to_string())
+        .build();
+    let mut notes = vec![];
+    for _ in 0..2 {
+        let response = client
+            .post(&notes_url)
+            .header("Idempotency-Key", "turn-1")
+            .json(&body)
+            .send()
+            .await?;
+        let response = utils::assert_ok_response(response).await?;
+        notes.push(response.json::<Note>().await?);
+    }
+    assert_eq!(notes[0], notes[1]);
+
+    // Reusing the key for a different request is a conflict.
+    let other_body = NewNoteRequest::builder()
+        .content("My bike takes 80 PSI".to_string())
+        .build();
+    let response = client
+        .post(&notes_url)
+        .header("Idempotency-Key", "turn-1")
+        .json(&other_body)
+        .send()
+        .await?;
+    assert_eq!(response.status(), reqwest::StatusCode::CONFLICT);
+    let error_response = response.json::<ErrorResponse>().await?;
+    assert_eq!(error_response.error.code, ErrorCode::Conflict);
+
+    // Requests without a key or with a different key add notes like usual.
+    let response = client.post(&notes_url).json(&body).send().await?;
+    utils::assert_ok_response(response).await?;
+    let response = client
+        .post(&notes_url)
+        .header("Idempotency-Key", "turn-2")
+        .json(&other_body)
+        .send()
+        .await?;
+    utils::assert_ok_response(response).await?;
+    let params = NoteSearchParams::builder().build();
+    let response = client
+        .post(format!("{notes_url}/search"))
+        .json(&params)
+        .send()
+        .await?;
+    let response = utils::assert_ok_response(response).await?;
+    assert_eq!(response.json::<Page<Note>>().await?.total, 3);
+
+    // Empty keys are rejected.
+    let response = client
+        .post(&notes_url)
+        .header("Idempotency-Key", " ")
+        .json(&body)
+        .send()
+        .await?;
+    assert_eq!(response.status(), reqwest::StatusCode::BAD_REQUEST);
+    Ok(())
+}
+
+#[tokio::test]
+#[serial]
+async fn idempotency_keys_expire() -> Result<(), Box<dyn std::error::Error>> {
+    // Make sure there's a database URL and it points to a test database so
+    // prod isn't goofed during testing.
+    let db_connection_url = dotenvy::var("DATABASE_URL")?;
+    utils::reset_database(&db_connection_url)?;
+
+    // Initialize the server state so keys expire right away.
+    let mut state = toi_server::init(db_connection_url).await?;
+    state.server_config.idempotency_ttl_hours = 0;
+    let openapi_router = OpenApiRouter::new().nest(
+        "/notes",
+        toi_server::routes::notes::notes_router(state.clone()),
+    );
+    let (router, _) = openapi_router.split_for_parts();
+    let listener = TcpListener::bind(&state.server_config.bind_addr).await?;
+
+    // Spawn server and create a client for all test requests.
+    let _ = tokio::spawn(async move { axum::serve(listener, router).await });
+    let client = reqwest::Client::new();
+    let notes_url = format!("http://{}/notes", state.server_config.bind_addr);
+
+    // Expired keys don't replay anything, so each request adds a note, and
+    // they can be reused for different requests.
+    let bodies = [
+        NewNoteRequest::builder()
+            .content("My car takes OW-20 oil".to_string())
+            .build(),
+        NewNoteRequest::builder()
+            .content("My car takes OW-20 oil".to_string())
+            .build(),
+        NewNoteRequest::builder()
+            .content("My bike takes 80 PSI".to_string())
+            .build(),
+    ];
+    let mut ids = vec![];
+    for body in &bodies {
+        let response = client
+            .post(&notes_url)
+            .header("Idempotency-Key", "turn-1")
+            .json(body)
+            .send()
+            .await?;
+        let response = utils::assert_ok_response(response).await?;
+        ids.push(response.json::<Note>().await?.id);
+    }
+    ids.dedup();
+    assert_eq!(ids.len(), 3);
+    Ok(())
+}
@@ -0,0 +1,155 @@
+use axum::{http::StatusCode as MockStatusCode, routing::post};
+use serial_test::serial;
+use tokio::net::TcpListener;
+use utoipa_axum::router::OpenApiRouter;
+
+use toi_server::{
+    metrics::Metrics,
+    models::notes::{NewNoteRequest, NoteSearchParams},
+};
+
+mod utils;
+
+async fn failing_rerank() -> MockStatusCode {
+    MockStatusCode::INTERNAL_SERVER_ERROR
+}
+
+/// Get the value of a series from scraped metrics, or zero if it hasn't
+/// been recorded yet.
+fn metric(body: &str, series: &str) -> f64 {
+    body.lines()
+        .find_map(|line| line.strip_prefix(series)?.strip_prefix(' '))
+        .map_or(0.0, |value| {
+            value.parse().expect("metric values should be numbers")
+        })
+}
+
+#[tokio::test]
+#[serial]
+async fn metrics_routes() -> Result<(), Box<dyn std::error::Error>> {
+    // Make sure there's a database URL and it points to a test database so
+    // prod isn't goofed during testing.
+    let db_connection_url = dotenvy::var("DATABASE_URL")?;
+    utils::reset_database(&db_connection_url)?;
+
+    // Spawn a mock embedding API and a reranking API that always fails.
+    let mock_router = axum::Router::new()
+        .route("/v1/embeddings", post(utils::mock_embeddings))
+        .route("/v1/rerank", post(failing_rerank));
+    let mock_listener = TcpListener::bind("127.0.0.1:0").await?;
+    let mock_addr = mock_listener.local_addr()?;
+    let _ = tokio::spawn(async move { axum::serve(mock_listener, mock_router).await });
+
+    // Initialize the server state with metrics enabled, pointing the model
+    // APIs at the mocks, and count requests to every route.
+    let mut state = toi_server::init(db_connection_url).await?;
+    let metrics = Metrics::new(true);
+    state.metrics = metrics.clone();
+    state.model_client.metrics = metrics;
+    let mock_url = format!("http://{mock_addr}");
+    state.model_client.embedding_api_config.base_url = mock_url.clone();
+    state.model_client.reranking_api_config.base_url = mock_url;
+    let openapi_router = OpenApiRouter::new()
+        .nest(
+            "/notes",
+            toi_server::routes::notes::notes_router(state.clone()),
+        )
+        .nest(
+            "/metrics",
+            toi_server::routes::metrics::metrics_router(state.clone()),
+        );
+    let (router, _) = openapi_router.split_for_parts();
+    let router = router.route_layer(axum::middleware::from_fn_with_state(
+        state.clone(),
+        toi_server::metrics::track_requests,
+    ));
+    let listener = TcpListener::bind(&state.server_config.bind_addr).await?;
+
+    // Spawn server and create a client for all test requests.
+    let _ = tokio::spawn(async move { axum::serve(listener, router).await });
+    let client = reqwest::Client::new();
+    let notes_url = format!("http://{}/notes", state.server_config.bind_addr);
+    let metrics_url = format!("http://{}/metrics", state.server_config.bind_addr);
+
+    // Nothing has called the model APIs yet.
+    let response = client.get(&metrics_url).send().await?;
+    let response = utils::assert_ok_response(response).await?;
+    assert!(
+        response.headers()["content-type"]
+            .to_str()?
+            .starts_with("text/plain")
+    );
+    let body = response.text().await?;
+    assert_eq!(
+        metric(
+            &body,
+            "toi_model_requests_total{api=\"embedding\",outcome=\"success\"}"
+        ),
+        0.0
+    );
+    assert!(body.contains("toi_db_pool_gets_total{outcome=\"waited\"}"));
+    assert!(body.contains("toi_db_pool_wait_seconds_total"));
+
+    // Add a couple of notes, and then search them so the failing reranking
+    // API is called.
+    for content in ["My car takes OW-20 oil", "My bike needs new tires"] {
+        let body = NewNoteRequest::builder()
+            .content(content.to_string())
+            .build();
+        let response = client.post(&notes_url).json(&body).send().await?;
+        utils::assert_ok_response(response).await?;
+    }
+    let params = NoteSearchParams::builder()
+        .query("oil".to_string())
+        .use_reranking_filter(true)
+        .build();
+    let response = client
+        .post(format!("{notes_url}/search"))
+        .json(&params)
+        .send()
+        .await?;
+    assert!(!response.status().is_success());
+
+    // Model API calls are counted by outcome.
+    let response = client.get(&metrics_url).send().await?;
+    let response = utils::assert_ok_response(response).await?;
+    let body = response.text().await?;
+    assert!(
+        metric(
+            &body,
+            "toi_model_requests_total{api=\"embedding\",outcome=\"success\"}"
+        ) >= 3.0
+    );
+    assert_eq!(
+        metric(
+            &body,
+            "toi_model_requests_total{api=\"reranking\",outcome=\"error\"}"
+        ),
+        1.0
+    );
+    assert_eq!(
+        metric(
+            &body,
+            "toi_model_request_duration_seconds_count{api=\"reranking\",outcome=\"error\"}"
+        ),
+        1.0
+    );
+
+    // Requests are counted by route rather than by URL.
+    assert_eq!(
+        metric(
+            &body,
+            "toi_http_requests_total{method=\"POST\",route=\"/notes\",status=\"201\"}"
+        ),
+        2.0
+    );
+    assert_eq!(
+        metric(
+            &body,
+            "toi_http_requests_total{method=\"GET\",route=\"/metrics\",status=\"200\"}"
+        ),
+        1.0
+    );
+    assert!(body.contains("toi_http_requests_total{method=\"POST\",route=\"/notes/search\""));
+    Ok(())
+}
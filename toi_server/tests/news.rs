@@ -0,0 +1,224 @@
+use axum::{
+    Json,
+    routing::{get, post},
+};
+use diesel::QueryDsl;
+use diesel_async::RunQueryDsl;
+use serde_json::{Value, json};
+use serial_test::serial;
+use std::collections::HashSet;
+use tokio::net::TcpListener;
+use utoipa_axum::router::OpenApiRouter;
+
+use toi_server::{
+    models::{config::NewsFeedConfig, news::NewRedirect},
+    routes::news::{alias_news_items, news_router},
+    schema,
+};
+
+mod utils;
+
+#[tokio::test]
+#[serial]
+async fn news_aliases() -> Result<(), Box<dyn std::error::Error>> {
+    // Make sure there's a database URL and it points to a test database so
+    // prod isn't goofed during testing.
+    let db_connection_url = dotenvy::var("DATABASE_URL")?;
+    utils::reset_database(&db_connection_url)?;
+
+    // Initialize the server state and the alias table.
+    let state = toi_server::init(db_connection_url).await?;
+    let _ = news_router(state.clone()).await?;
+    let mut conn = state.pool.get().await?;
+    let alias_count: i64 = schema::news::table.count().get_result(&mut conn).await?;
+    let server_addr = format!("127.0.0.1:{}", state.server_config.bind_addr.port());
+
+    // Request redirects for more news items than there are aliases.
+    let item_count = usize::try_from(alias_count)? + 10;
+    let items: Vec<rss::Item> = (0..item_count)
+        .map(|i| rss::Item {
+            title: Some(format!("Article {i}")),
+            link: Some(format!("https://example.com/articles/{i}")),
+            ..Default::default()
+        })
+        .collect();
+    let redirects = alias_news_items(&mut conn, &server_addr, items).await?;
+
+    // Every item gets its own redirect, and the alias table grows to fit.
+    assert_eq!(redirects.len(), item_count);
+    let tinyurls: HashSet<&str> = redirects
+        .iter()
+        .map(|redirect| redirect.tinyurl.as_str())
+        .collect();
+    assert_eq!(tinyurls.len(), item_count);
+    assert!(redirects.iter().all(|redirect| redirect.title.is_some()));
+    let new_alias_count: i64 = schema::news::table.count().get_result(&mut conn).await?;
+    assert_eq!(new_alias_count, alias_count + 10);
+    Ok(())
+}
+
+/// RSS feed with an item for each title.
+fn mock_feed(name: &str, titles: &[&str]) -> String {
+    let items: String = titles
+        .iter()
+        .enumerate()
+        .map(|(i, title)| {
+            format!("<item><title>{title}</title><link>https://example.com/{i}</link></item>")
+        })
+        .collect();
+    format!(
+        r#"<?xml version="1.0"?><rss version="2.0"><channel><title>{name}</title><link>https://example.com</link><description>{name}</description>{items}</channel></rss>"#
+    )
+}
+
+/// Rerank documents by how similar they are to the query
+/// character-for-character, so names only need to be close.
+async fn mock_rerank(Json(request): Json<Value>) -> Json<Value> {
+    let query = request["query"].as_str().unwrap_or_default().to_lowercase();
+    let documents = request["documents"].as_array().cloned().unwrap_or_default();
+    let mut results: Vec<Value> = documents
+        .iter()
+        .enumerate()
+        .map(|(index, document)| {
+            let text = document.as_str().unwrap_or_default();
+            let relevance_score =
+                strsim::normalized_damerau_levenshtein(&query, &text.to_lowercase());
+            json!({"index": index, "document": {"text": text}, "relevance_score": relevance_score})
+        })
+        .collect();
+    results.sort_by(|a, b| {
+        b["relevance_score"]
+            .as_f64()
+            .partial_cmp(&a["relevance_score"].as_f64())
+            .expect("scores should be comparable")
+    });
+    Json(json!({"results": results}))
+}
+
+#[tokio::test]
+#[serial]
+async fn news_feeds() -> Result<(), Box<dyn std::error::Error>> {
+    // Make sure there's a database URL and it points to a test database so
+    // prod isn't goofed during testing.
+    let db_connection_url = dotenvy::var("DATABASE_URL")?;
+    utils::reset_database(&db_connection_url)?;
+
+    // Spawn mock RSS feeds and a mock reranking API.
+    let mock_router = axum::Router::new()
+        .route(
+            "/hn/rss",
+            get(|| async {
+                mock_feed(
+                    "Hacker News",
+                    &["Show HN: A Rust web server", "Ask HN: Favorite editors?"],
+                )
+            }),
+        )
+        .route(
+            "/bbc/rss",
+            get(|| async {
+                mock_feed(
+                    "BBC News - World",
+                    &["Storms hit the coast", "Markets rally", "Elections held"],
+                )
+            }),
+        )
+        .route("/v1/rerank", post(mock_rerank));
+    let mock_listener = TcpListener::bind("127.0.0.1:0").await?;
+    let mock_addr = mock_listener.local_addr()?;
+    let _ = tokio::spawn(async move { axum::serve(mock_listener, mock_router).await });
+
+    // Initialize the server state with two feeds, one of them the default,
+    // pointing the reranking API at the mock.
+    let mut state = toi_server::init(db_connection_url).await?;
+    state.model_client.reranking_api_config.base_url = format!("http://{mock_addr}");
+    state.news_config.feeds = vec![
+        NewsFeedConfig {
+            name: "Hacker News".to_string(),
+            url: format!("http://{mock_addr}/hn/rss"),
+            default: true,
+        },
+        NewsFeedConfig {
+            name: "BBC World".to_string(),
+            url: format!("http://{mock_addr}/bbc/rss"),
+            default: false,
+        },
+    ];
+    let openapi_router = OpenApiRouter::new().nest("/news", news_router(state.clone()).await?);
+    let (router, _) = openapi_router.split_for_parts();
+    let listener = TcpListener::bind(&state.server_config.bind_addr).await?;
+
+    // Spawn server and create a client for all test requests.
+    let _ = tokio::spawn(async move { axum::serve(listener, router).await });
+    let client = reqwest::Client::new();
+    let news_url = format!("http://{}/news", state.server_config.bind_addr);
+
+    // The configured feeds are loaded at startup.
+    let mut conn = state.pool.get().await?;
+    let feed_count: i64 = schema::news_feeds::table
+        .count()
+        .get_result(&mut conn)
+        .await?;
+    assert_eq!(feed_count, 2);
+
+    // News comes from the default feed if nothing specific is asked for.
+    let redirects: Vec<NewRedirect> = client
+        .post(&news_url)
+        .json(&json!({}))
+        .send()
+        .await?
+        .json()
+        .await?;
+    assert_eq!(redirects.len(), 2);
+    assert!(
+        redirects
+            .iter()
+            .all(|redirect| redirect.source.as_deref() == Some("Hacker News"))
+    );
+
+    // Sources don't have to match feed names exactly, and the number of
+    // articles can be limited.
+    let redirects: Vec<NewRedirect> = client
+        .post(&news_url)
+        .json(&json!({"source": "bbc wrld", "max_items": 2}))
+        .send()
+        .await?
+        .json()
+        .await?;
+    let titles: Vec<Option<&str>> = redirects
+        .iter()
+        .map(|redirect| redirect.title.as_deref())
+        .collect();
+    assert_eq!(
+        titles,
+        vec![Some("Storms hit the coast"), Some("Markets rally")]
+    );
+    assert!(
+        redirects
+            .iter()
+            .all(|redirect| redirect.source.as_deref() == Some("BBC World"))
+    );
+
+    // Articles from feeds are filtered by the query.
+    let redirects: Vec<NewRedirect> = client
+        .post(&news_url)
+        .json(&json!({"source": "hackernews", "query": "rust"}))
+        .send()
+        .await?
+        .json()
+        .await?;
+    assert_eq!(redirects.len(), 1);
+    assert_eq!(
+        redirects[0].title.as_deref(),
+        Some("Show HN: A Rust web server")
+    );
+
+    // At least one article has to be asked for.
+    let response = client
+        .post(&news_url)
+        .json(&json!({"max_items": 0}))
+        .send()
+        .await?;
+    assert_eq!(response.status(), reqwest::StatusCode::BAD_REQUEST);
+    Ok(())
+}
@@ -0,0 +1,204 @@
+use axum::{Json, extract::State, routing::post};
+use diesel::{ExpressionMethods, QueryDsl};
+use diesel_async::RunQueryDsl;
+use serde_json::{Value, json};
+use serial_test::serial;
+use std::sync::{Arc, Mutex};
+use tokio::net::TcpListener;
+use utoipa_axum::router::OpenApiRouter;
+
+use toi_server::{
+    models::{
+        notes::{NewNoteRequest, Note, NoteSearchParams},
+        pagination::Page,
+    },
+    schema,
+};
+
+mod utils;
+
+/// Topics a long note covers, each in its own paragraph.
+const TOPICS: [&str; 4] = ["budget", "hiring", "roadmap", "offsite"];
+
+/// Embed inputs by how often they mention each topic so inputs about the
+/// same topics are close to each other.
+fn mock_embedding(input: &str) -> Vec<f32> {
+    let input = input.to_lowercase();
+    let mut embedding = vec![0.1];
+    for topic in TOPICS {
+        let mentions = input.matches(topic).count();
+        embedding.push(f32::from(u8::try_from(mentions).unwrap_or(u8::MAX)));
+    }
+    embedding
+}
+
+/// Rerank documents in the order they're given, keeping track of the
+/// documents in each request.
+async fn mock_rerank(
+    State(requests): State<Arc<Mutex<Vec<Vec<String>>>>>,
+    Json(request): Json<Value>,
+) -> Json<Value> {
+    let documents: Vec<String> = request["documents"]
+        .as_array()
+        .cloned()
+        .unwrap_or_default()
+        .iter()
+        .map(|document| document.as_str().unwrap_or_default().to_string())
+        .collect();
+    let results: Vec<Value> = documents
+        .iter()
+        .zip(0..)
+        .map(|(text, index)| {
+            let relevance_score = 0.9 - 0.1 * f64::from(index);
+            json!({"index": index, "document": {"text": text}, "relevance_score": relevance_score})
+        })
+        .collect();
+    requests
+        .lock()
+        .expect("requests shouldn't be poisoned")
+        .push(documents);
+    Json(json!({"results": results}))
+}
+
+#[tokio::test]
+#[serial]
+async fn searching_long_notes_by_chunks() -> Result<(), Box<dyn std::error::Error>> {
+    // Make sure there's a database URL and it points to a test database so
+    // prod isn't goofed during testing.
+    let db_connection_url = dotenvy::var("DATABASE_URL")?;
+    utils::reset_database(&db_connection_url)?;
+
+    // Spawn mock embedding and reranking APIs.
+    let requests = Arc::new(Mutex::new(vec![]));
+    let mock_router = axum::Router::new()
+        .route(
+            "/v1/embeddings",
+            utils::mock_embeddings_with(mock_embedding),
+        )
+        .route("/v1/rerank", post(mock_rerank))
+        .with_state(requests.clone());
+    let mock_listener = TcpListener::bind("127.0.0.1:0").await?;
+    let mock_addr = mock_listener.local_addr()?;
+    let _ = tokio::spawn(async move { axum::serve(mock_listener, mock_router).await });
+
+    // Initialize the server state so notes are split into small chunks and
+    // anything in the trash can be purged, pointing the model APIs at the
+    // mocks.
+    let mut state = toi_server::init(db_connection_url).await?;
+    state.server_config.note_chunk_chars = 200;
+    state.server_config.note_chunk_overlap_chars = 50;
+    state.server_config.trash_retention_days = 0;
+    let mock_url = format!("http://{mock_addr}");
+    state.model_client.embedding_api_config.base_url = mock_url.clone();
+    state.model_client.reranking_api_config.base_url = mock_url;
+    let openapi_router = OpenApiRouter::new().nest(
+        "/notes",
+        toi_server::routes::notes::notes_router(state.clone()),
+    );
+    let (router, _) = openapi_router.split_for_parts();
+    let listener = TcpListener::bind(&state.server_config.bind_addr).await?;
+
+    // Spawn server and create a client for all test requests.
+    let _ = tokio::spawn(async move { axum::serve(listener, router).await });
+    let client = reqwest::Client::new();
+    let notes_url = format!("http://{}/notes", state.server_config.bind_addr);
+
+    // Make a long note with a paragraph per topic, padded so the last
+    // paragraph is a chunk of its own, and a couple of shorter decoys that
+    // mention the last topic along with others. As a whole, the long note is
+    // further from a query about its last paragraph than the decoys are.
+    let paragraphs = [
+        "Budget: the budget for next year grows by ten percent, and the budget review happens every quarter.",
+        "Hiring: hiring two engineers is the priority, and hiring managers meet weekly.",
+        "Roadmap: the roadmap covers the new app, and the roadmap is shared with everyone.",
+        "Offsite: the offsite is in the mountains in May, and the offsite agenda is still open.",
+    ];
+    let long_content: String = paragraphs
+        .iter()
+        .map(|paragraph| format!("{paragraph:<199}\n"))
+        .collect();
+    let contents = [
+        long_content.clone(),
+        "Offsite budget".to_string(),
+        "Offsite plans for hiring and budget".to_string(),
+    ];
+    let mut notes = vec![];
+    for content in contents {
+        let body = NewNoteRequest::builder().content(content).build();
+        let response = client.post(&notes_url).json(&body).send().await?;
+        let response = utils::assert_ok_response(response).await?;
+        notes.push(response.json::<Note>().await?);
+    }
+
+    // Only the long note is chunked.
+    let mut conn = state.pool.get().await?;
+    let chunk_note_ids: Vec<i32> = schema::note_chunks::table
+        .select(schema::note_chunks::note_id)
+        .distinct()
+        .load(&mut conn)
+        .await?;
+    assert_eq!(chunk_note_ids, vec![notes[0].id]);
+
+    // A query about the long note's last paragraph finds it ahead of the
+    // decoys, and the whole note is returned.
+    let params = NoteSearchParams::builder()
+        .query("offsite".to_string())
+        .build();
+    let response = client
+        .post(format!("{notes_url}/search"))
+        .json(&params)
+        .send()
+        .await?;
+    let response = utils::assert_ok_response(response).await?;
+    let found_notes = response.json::<Page<Note>>().await?.items;
+    assert_eq!(found_notes, notes);
+
+    // The long note is reranked using its matching chunk rather than its
+    // whole content.
+    let params = NoteSearchParams::builder()
+        .query("offsite".to_string())
+        .use_reranking_filter(true)
+        .build();
+    let response = client
+        .post(format!("{notes_url}/search"))
+        .json(&params)
+        .send()
+        .await?;
+    let response = utils::assert_ok_response(response).await?;
+    let ids: Vec<i32> = response
+        .json::<Page<Note>>()
+        .await?
+        .items
+        .into_iter()
+        .map(|note| note.id)
+        .collect();
+    assert_eq!(ids, notes.iter().map(|note| note.id).collect::<Vec<_>>());
+    {
+        let requests = requests.lock().expect("requests shouldn't be poisoned");
+        assert_eq!(requests.len(), 1);
+        let documents = &requests[0];
+        assert_eq!(documents.len(), 3);
+        assert!(documents[0].chars().count() <= 200);
+        assert!(documents[0].starts_with(paragraphs[3]));
+        assert_eq!(documents[1], notes[1].content);
+        assert_eq!(documents[2], notes[2].content);
+    }
+
+    // Deleting and purging the notes removes their chunks too.
+    let response = client
+        .post(format!("{notes_url}/delete"))
+        .json(&params)
+        .send()
+        .await?;
+    utils::assert_ok_response(response).await?;
+    let response = client.post(format!("{notes_url}/purge")).send().await?;
+    let response = utils::assert_ok_response(response).await?;
+    assert_eq!(response.json::<Vec<Note>>().await?.len(), 3);
+    let num_chunks: i64 = schema::note_chunks::table
+        .filter(schema::note_chunks::note_id.eq(notes[0].id))
+        .count()
+        .get_result(&mut conn)
+        .await?;
+    assert_eq!(num_chunks, 0);
+    Ok(())
+}
@@ -1,11 +1,42 @@
+use axum::{Json, extract::State, http::StatusCode, routing::post};
+use chrono::{Duration, Utc};
+use serde_json::Value;
 use serial_test::serial;
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+};
 use tokio::net::TcpListener;
 use utoipa_axum::router::OpenApiRouter;
 
-use toi_server::models::notes::{NewNoteRequest, Note, NoteSearchParams};
+use toi_server::embeddings::EmbeddingInstructions;
+use toi_server::models::{
+    deletion::DeleteParams,
+    notes::{
+        AppendNoteRequest, ArchiveNotesRequest, BulkNoteImportRequest, NewNoteRequest,
+        NewNoteTagsRequest, Note, NoteSearchParams, NoteSeparator, NoteTagSearchParams, NoteTags,
+        PinNotesRequest,
+    },
+    pagination::{Count, Page},
+    tags::{NewTagRequest, Tag, TagMatch, TagSearchParams},
+};
+use toi_server::routes::notes::delete_expired_notes;
 
 mod utils;
 
+/// Mock embedding API that records the inputs it's asked to embed and then
+/// fails since only the inputs are needed.
+async fn record_embedding_inputs(
+    State(inputs): State<Arc<Mutex<Vec<Value>>>>,
+    Json(body): Json<Value>,
+) -> StatusCode {
+    inputs
+        .lock()
+        .expect("inputs lock shouldn't be poisoned")
+        .push(body["input"].clone());
+    StatusCode::INTERNAL_SERVER_ERROR
+}
+
 #[tokio::test]
 #[serial]
 async fn notes_routes() -> Result<(), Box<dyn std::error::Error>> {
@@ -38,21 +69,1019 @@ async fn notes_routes() -> Result<(), Box<dyn std::error::Error>> {
     let note1 = response.json::<Note>().await?;
     assert_eq!(note1.content, note_content);
 
+    // Retrieve the note using its ID, and fail to retrieve a missing one.
+    let response = client
+        .get(format!("{notes_url}/{}", note1.id))
+        .send()
+        .await?;
+    let response = utils::assert_ok_response(response).await?;
+    assert_eq!(response.json::<Note>().await?, note1);
+    let response = client.get(format!("{notes_url}/0")).send().await?;
+    assert_eq!(response.status(), reqwest::StatusCode::NOT_FOUND);
+
     // Retrieve the note using search.
     let search_notes_url = format!("{notes_url}/search");
     let params = NoteSearchParams::builder()
         .query("what's my car oil type".to_string())
         .build();
-    let response = client.post(search_notes_url).json(&params).send().await?;
+    let response = client.post(&search_notes_url).json(&params).send().await?;
     let response = utils::assert_ok_response(response).await?;
-    let vec_notes1 = response.json::<Vec<Note>>().await?;
+    let page_notes = response.json::<Page<Note>>().await?;
+    assert_eq!(page_notes.total, 1);
+    let vec_notes1 = page_notes.items;
     assert_eq!(vec_notes1, vec![note1]);
 
-    // Delete the note using search.
+    // Count the notes with the same filters, with and without reranking.
+    for use_reranking_filter in [false, true] {
+        let params = NoteSearchParams::builder()
+            .query("what's my car oil type".to_string())
+            .use_reranking_filter(use_reranking_filter)
+            .build();
+        let response = client.post(&search_notes_url).json(&params).send().await?;
+        let response = utils::assert_ok_response(response).await?;
+        let num_notes = response.json::<Page<Note>>().await?.items.len();
+        let params = NoteSearchParams {
+            count_only: Some(true),
+            ..params
+        };
+        let response = client.post(&search_notes_url).json(&params).send().await?;
+        let response = utils::assert_ok_response(response).await?;
+        let count = response.json::<Count>().await?.count;
+        assert_eq!(usize::try_from(count)?, num_notes);
+    }
+
+    // Delete the note using search, moving it to the trash.
     let delete_notes_url = format!("{notes_url}/delete");
     let response = client.post(delete_notes_url).json(&params).send().await?;
     let response = utils::assert_ok_response(response).await?;
     let vec_notes2 = response.json::<Vec<Note>>().await?;
+    assert_eq!(vec_notes2.len(), 1);
+    assert_eq!(vec_notes2[0].id, vec_notes1[0].id);
+    assert!(vec_notes2[0].deleted_at.is_some());
+    Ok(())
+}
+
+#[tokio::test]
+#[serial]
+async fn notes_trash_routes() -> Result<(), Box<dyn std::error::Error>> {
+    // Make sure there's a database URL and it points to a test database so
+    // prod isn't goofed during testing.
+    let db_connection_url = dotenvy::var("DATABASE_URL")?;
+    utils::reset_database(&db_connection_url)?;
+
+    // Initialize the server state so anything in the trash can be purged.
+    let mut state = toi_server::init(db_connection_url).await?;
+    state.server_config.trash_retention_days = 0;
+    let openapi_router = OpenApiRouter::new().nest(
+        "/notes",
+        toi_server::routes::notes::notes_router(state.clone()),
+    );
+    let (router, _) = openapi_router.split_for_parts();
+    let listener = TcpListener::bind(&state.server_config.bind_addr).await?;
+
+    // Spawn server and create a client for all test requests.
+    let _ = tokio::spawn(async move { axum::serve(listener, router).await });
+    let client = reqwest::Client::new();
+    let notes_url = format!("http://{}/notes", state.server_config.bind_addr);
+    let search_notes_url = format!("{notes_url}/search");
+    let delete_notes_url = format!("{notes_url}/delete");
+    let restore_notes_url = format!("{notes_url}/restore");
+    let purge_notes_url = format!("{notes_url}/purge");
+
+    // Make a note and delete it.
+    let body = NewNoteRequest::builder()
+        .content("The wifi password is hunter2".to_string())
+        .build();
+    let response = client.post(&notes_url).json(&body).send().await?;
+    let response = utils::assert_ok_response(response).await?;
+    let note1 = response.json::<Note>().await?;
+    let params = NoteSearchParams::builder()
+        .query("wifi password".to_string())
+        .build();
+    let response = client.post(&delete_notes_url).json(&params).send().await?;
+    utils::assert_ok_response(response).await?;
+
+    // The deleted note is hidden from search.
+    let response = client.post(&search_notes_url).json(&params).send().await?;
+    let response = utils::assert_ok_response(response).await?;
+    assert!(response.json::<Page<Note>>().await?.items.is_empty());
+
+    // Restore the note, and then it's searchable again.
+    let response = client.post(&restore_notes_url).json(&params).send().await?;
+    let response = utils::assert_ok_response(response).await?;
+    let vec_notes1 = response.json::<Vec<Note>>().await?;
+    assert_eq!(vec_notes1, vec![note1]);
+    let response = client.post(&search_notes_url).json(&params).send().await?;
+    let response = utils::assert_ok_response(response).await?;
+    let vec_notes2 = response.json::<Page<Note>>().await?.items;
     assert_eq!(vec_notes2, vec_notes1);
+
+    // Purging doesn't touch notes that aren't in the trash.
+    let response = client.post(&purge_notes_url).send().await?;
+    let response = utils::assert_ok_response(response).await?;
+    assert!(response.json::<Vec<Note>>().await?.is_empty());
+
+    // Delete the note again and purge it for good, after which it can't be
+    // restored.
+    let response = client.post(&delete_notes_url).json(&params).send().await?;
+    utils::assert_ok_response(response).await?;
+    let response = client.post(&purge_notes_url).send().await?;
+    let response = utils::assert_ok_response(response).await?;
+    let vec_notes3 = response.json::<Vec<Note>>().await?;
+    assert_eq!(vec_notes3.len(), 1);
+    assert_eq!(vec_notes3[0].id, vec_notes1[0].id);
+    let response = client.post(&restore_notes_url).json(&params).send().await?;
+    let response = utils::assert_ok_response(response).await?;
+    assert!(response.json::<Vec<Note>>().await?.is_empty());
+    Ok(())
+}
+
+#[tokio::test]
+#[serial]
+async fn bulk_notes_routes() -> Result<(), Box<dyn std::error::Error>> {
+    // Make sure there's a database URL and it points to a test database so
+    // prod isn't goofed during testing.
+    let db_connection_url = dotenvy::var("DATABASE_URL")?;
+    utils::reset_database(&db_connection_url)?;
+
+    // Initialize the server state with a small batch size.
+    let mut state = toi_server::init(db_connection_url).await?;
+    state.server_config.max_batch_size = 3;
+    let openapi_router = OpenApiRouter::new().nest(
+        "/notes",
+        toi_server::routes::notes::notes_router(state.clone()),
+    );
+    let (router, _) = openapi_router.split_for_parts();
+    let listener = TcpListener::bind(&state.server_config.bind_addr).await?;
+
+    // Spawn server and create a client for all test requests.
+    let _ = tokio::spawn(async move { axum::serve(listener, router).await });
+    let client = reqwest::Client::new();
+    let bulk_notes_url = format!("http://{}/notes/bulk", state.server_config.bind_addr);
+
+    // Import a few notes and make sure they come back in order.
+    let contents = vec![
+        "My car takes OW-20 oil".to_string(),
+        "The wifi password is on the fridge".to_string(),
+        "Water the plants on Sundays".to_string(),
+    ];
+    let body = BulkNoteImportRequest::builder()
+        .notes(
+            contents
+                .iter()
+                .map(|content| NewNoteRequest::builder().content(content.clone()).build())
+                .collect(),
+        )
+        .build();
+    let response = client.post(&bulk_notes_url).json(&body).send().await?;
+    let response = utils::assert_ok_response(response).await?;
+    let vec_notes = response.json::<Vec<Note>>().await?;
+    let vec_contents: Vec<String> = vec_notes.into_iter().map(|note| note.content).collect();
+    assert_eq!(vec_contents, contents);
+
+    // Importing more notes than the batch size allows is rejected.
+    let body = BulkNoteImportRequest::builder()
+        .notes(
+            (0..4)
+                .map(|i| {
+                    NewNoteRequest::builder()
+                        .content(format!("Note {i}"))
+                        .build()
+                })
+                .collect(),
+        )
+        .build();
+    let response = client.post(&bulk_notes_url).json(&body).send().await?;
+    assert_eq!(response.status(), reqwest::StatusCode::PAYLOAD_TOO_LARGE);
+    Ok(())
+}
+
+#[tokio::test]
+#[serial]
+async fn notes_exclusion() -> Result<(), Box<dyn std::error::Error>> {
+    // Make sure there's a database URL and it points to a test database so
+    // prod isn't goofed during testing.
+    let db_connection_url = dotenvy::var("DATABASE_URL")?;
+    utils::reset_database(&db_connection_url)?;
+
+    // Initialize the server state.
+    let state = toi_server::init(db_connection_url).await?;
+    let openapi_router = OpenApiRouter::new().nest(
+        "/notes",
+        toi_server::routes::notes::notes_router(state.clone()),
+    );
+    let (router, _) = openapi_router.split_for_parts();
+    let listener = TcpListener::bind(&state.server_config.bind_addr).await?;
+
+    // Spawn server and create a client for all test requests.
+    let _ = tokio::spawn(async move { axum::serve(listener, router).await });
+    let client = reqwest::Client::new();
+    let notes_url = format!("http://{}/notes", state.server_config.bind_addr);
+    let search_notes_url = format!("{notes_url}/search");
+
+    // Make some notes.
+    let mut ids = vec![];
+    for content in [
+        "My car takes OW-20 oil",
+        "The wifi password is hunter2",
+        "The garage door code is 1234",
+    ] {
+        let body = NewNoteRequest::builder()
+            .content(content.to_string())
+            .build();
+        let response = client.post(&notes_url).json(&body).send().await?;
+        let response = utils::assert_ok_response(response).await?;
+        ids.push(response.json::<Note>().await?.id);
+    }
+
+    // Notes selected by their IDs are still excluded by the exclude query.
+    let params = NoteSearchParams::builder()
+        .ids(ids.clone())
+        .exclude_query("My car takes OW-20 oil".to_string())
+        .build();
+    let response = client.post(&search_notes_url).json(&params).send().await?;
+    let response = utils::assert_ok_response(response).await?;
+    let note_ids: Vec<i32> = response
+        .json::<Page<Note>>()
+        .await?
+        .items
+        .into_iter()
+        .map(|note| note.id)
+        .collect();
+    assert!(!note_ids.contains(&ids[0]));
+    assert!(note_ids.contains(&ids[1]));
+    assert!(note_ids.contains(&ids[2]));
+
+    // Excluded notes don't take up room in the results, and they aren't
+    // counted.
+    let params = NoteSearchParams::builder()
+        .ids(ids.clone())
+        .exclude_ids(vec![ids[0]])
+        .limit(2)
+        .build();
+    let response = client.post(&search_notes_url).json(&params).send().await?;
+    let response = utils::assert_ok_response(response).await?;
+    let page_notes = response.json::<Page<Note>>().await?;
+    let mut note_ids: Vec<i32> = page_notes.items.into_iter().map(|note| note.id).collect();
+    note_ids.sort_unstable();
+    assert_eq!(note_ids, vec![ids[1], ids[2]]);
+    assert_eq!(page_notes.total, 2);
+    Ok(())
+}
+
+#[tokio::test]
+#[serial]
+async fn notes_append() -> Result<(), Box<dyn std::error::Error>> {
+    // Make sure there's a database URL and it points to a test database so
+    // prod isn't goofed during testing.
+    let db_connection_url = dotenvy::var("DATABASE_URL")?;
+    utils::reset_database(&db_connection_url)?;
+
+    // Initialize the server state.
+    let state = toi_server::init(db_connection_url).await?;
+    let openapi_router = OpenApiRouter::new().nest(
+        "/notes",
+        toi_server::routes::notes::notes_router(state.clone()),
+    );
+    let (router, _) = openapi_router.split_for_parts();
+    let listener = TcpListener::bind(&state.server_config.bind_addr).await?;
+
+    // Spawn server and create a client for all test requests.
+    let _ = tokio::spawn(async move { axum::serve(listener, router).await });
+    let client = reqwest::Client::new();
+    let notes_url = format!("http://{}/notes", state.server_config.bind_addr);
+    let append_notes_url = format!("{notes_url}/append");
+
+    // Make a note.
+    let body = NewNoteRequest::builder()
+        .content("Groceries: eggs".to_string())
+        .build();
+    let response = client.post(&notes_url).json(&body).send().await?;
+    let response = utils::assert_ok_response(response).await?;
+    let note = response.json::<Note>().await?;
+
+    // Append to the note found by searching, on a new line by default.
+    let body = AppendNoteRequest::builder()
+        .append_content("olive oil".to_string())
+        .query("groceries".to_string())
+        .build();
+    let response = client.post(&append_notes_url).json(&body).send().await?;
+    let response = utils::assert_ok_response(response).await?;
+    let appended_note = response.json::<Note>().await?;
+    assert_eq!(appended_note.id, note.id);
+    assert_eq!(appended_note.content, "Groceries: eggs\nolive oil");
+
+    // Append to the note using its ID with a comma.
+    let body = AppendNoteRequest::builder()
+        .id(note.id)
+        .append_content("bread".to_string())
+        .separator(NoteSeparator::Comma)
+        .build();
+    let response = client.post(&append_notes_url).json(&body).send().await?;
+    let response = utils::assert_ok_response(response).await?;
+    let appended_note = response.json::<Note>().await?;
+    assert_eq!(appended_note.content, "Groceries: eggs\nolive oil, bread");
+
+    // The appended note is stored.
+    let response = client
+        .get(format!("{notes_url}/{}", note.id))
+        .send()
+        .await?;
+    let response = utils::assert_ok_response(response).await?;
+    assert_eq!(response.json::<Note>().await?, appended_note);
+
+    // Missing notes can't be appended to.
+    let body = AppendNoteRequest::builder()
+        .id(0)
+        .append_content("bread".to_string())
+        .build();
+    let response = client.post(&append_notes_url).json(&body).send().await?;
+    assert_eq!(response.status(), reqwest::StatusCode::NOT_FOUND);
+    Ok(())
+}
+
+#[tokio::test]
+#[serial]
+async fn notes_embedding_instructions() -> Result<(), Box<dyn std::error::Error>> {
+    // Make sure there's a database URL and it points to a test database so
+    // prod isn't goofed during testing.
+    let db_connection_url = dotenvy::var("DATABASE_URL")?;
+    utils::reset_database(&db_connection_url)?;
+
+    // Spawn a mock embedding API that records what it's asked to embed.
+    let inputs = Arc::new(Mutex::new(vec![]));
+    let mock_router = axum::Router::new()
+        .route("/v1/embeddings", post(record_embedding_inputs))
+        .with_state(inputs.clone());
+    let mock_listener = TcpListener::bind("127.0.0.1:0").await?;
+    let mock_addr = mock_listener.local_addr()?;
+    let _ = tokio::spawn(async move { axum::serve(mock_listener, mock_router).await });
+
+    // Initialize the server state, pointing embedding at the mock API and
+    // overriding the instruction for notes.
+    let mut state = toi_server::init(db_connection_url).await?;
+    state.model_client.embedding_api_config.base_url = format!("http://{mock_addr}");
+    state.embedding_instructions = EmbeddingInstructions::new(HashMap::from([(
+        "notes".to_string(),
+        "Instruction: Find my notes".to_string(),
+    )]));
+    let openapi_router = OpenApiRouter::new().nest(
+        "/notes",
+        toi_server::routes::notes::notes_router(state.clone()),
+    );
+    let (router, _) = openapi_router.split_for_parts();
+    let listener = TcpListener::bind(&state.server_config.bind_addr).await?;
+
+    // Spawn server and create a client for all test requests.
+    let _ = tokio::spawn(async move { axum::serve(listener, router).await });
+    let client = reqwest::Client::new();
+    let search_notes_url = format!("http://{}/notes/search", state.server_config.bind_addr);
+
+    // The search fails since the mock API doesn't embed anything, but the
+    // query it was asked to embed uses the overriding instruction.
+    let params = NoteSearchParams::builder()
+        .query("groceries".to_string())
+        .build();
+    let response = client.post(&search_notes_url).json(&params).send().await?;
+    assert!(!response.status().is_success());
+    let inputs = inputs
+        .lock()
+        .expect("inputs lock shouldn't be poisoned")
+        .clone();
+    assert_eq!(
+        inputs,
+        vec![Value::String(
+            "Instruction: Find my notes\nQuery: groceries".to_string()
+        )]
+    );
+    Ok(())
+}
+
+#[tokio::test]
+#[serial]
+async fn notes_delete_all_guard() -> Result<(), Box<dyn std::error::Error>> {
+    // Make sure there's a database URL and it points to a test database so
+    // prod isn't goofed during testing.
+    let db_connection_url = dotenvy::var("DATABASE_URL")?;
+    utils::reset_database(&db_connection_url)?;
+
+    // Initialize the server state.
+    let state = toi_server::init(db_connection_url).await?;
+    let openapi_router = OpenApiRouter::new().nest(
+        "/notes",
+        toi_server::routes::notes::notes_router(state.clone()),
+    );
+    let (router, _) = openapi_router.split_for_parts();
+    let listener = TcpListener::bind(&state.server_config.bind_addr).await?;
+
+    // Spawn server and create a client for all test requests.
+    let _ = tokio::spawn(async move { axum::serve(listener, router).await });
+    let client = reqwest::Client::new();
+    let notes_url = format!("http://{}/notes", state.server_config.bind_addr);
+    let search_notes_url = format!("{notes_url}/search");
+    let delete_notes_url = format!("{notes_url}/delete");
+    for content in ["The wifi password is hunter2", "Buy more olive oil"] {
+        let body = NewNoteRequest::builder()
+            .content(content.to_string())
+            .build();
+        let response = client.post(&notes_url).json(&body).send().await?;
+        utils::assert_ok_response(response).await?;
+    }
+
+    // Deleting without any filters is refused unless it's confirmed.
+    let params = NoteSearchParams::builder().build();
+    let response = client.post(&delete_notes_url).json(&params).send().await?;
+    assert_eq!(response.status(), reqwest::StatusCode::BAD_REQUEST);
+    assert!(response.text().await?.contains("confirm_delete_all"));
+    let response = client.post(&search_notes_url).json(&params).send().await?;
+    let response = utils::assert_ok_response(response).await?;
+    assert_eq!(response.json::<Page<Note>>().await?.items.len(), 2);
+
+    // Confirmed deletes get rid of everything.
+    let params = DeleteParams::builder()
+        .params(NoteSearchParams::builder().build())
+        .confirm_delete_all(true)
+        .build();
+    let response = client.post(&delete_notes_url).json(&params).send().await?;
+    let response = utils::assert_ok_response(response).await?;
+    assert_eq!(response.json::<Vec<Note>>().await?.len(), 2);
+    let params = NoteSearchParams::builder().build();
+    let response = client.post(&search_notes_url).json(&params).send().await?;
+    let response = utils::assert_ok_response(response).await?;
+    assert!(response.json::<Page<Note>>().await?.items.is_empty());
+    Ok(())
+}
+
+#[tokio::test]
+#[serial]
+async fn notes_pins_and_archives() -> Result<(), Box<dyn std::error::Error>> {
+    // Make sure there's a database URL and it points to a test database so
+    // prod isn't goofed during testing.
+    let db_connection_url = dotenvy::var("DATABASE_URL")?;
+    utils::reset_database(&db_connection_url)?;
+
+    // Initialize the server state.
+    let state = toi_server::init(db_connection_url).await?;
+    let openapi_router = OpenApiRouter::new().nest(
+        "/notes",
+        toi_server::routes::notes::notes_router(state.clone()),
+    );
+    let (router, _) = openapi_router.split_for_parts();
+    let listener = TcpListener::bind(&state.server_config.bind_addr).await?;
+
+    // Spawn server and create a client for all test requests.
+    let _ = tokio::spawn(async move { axum::serve(listener, router).await });
+    let client = reqwest::Client::new();
+    let notes_url = format!("http://{}/notes", state.server_config.bind_addr);
+    let search_notes_url = format!("{notes_url}/search");
+    let pin_notes_url = format!("{notes_url}/pin");
+    let archive_notes_url = format!("{notes_url}/archive");
+
+    // Make a few notes.
+    let body = BulkNoteImportRequest::builder()
+        .notes(
+            [
+                "Old apartment gate code is 1234",
+                "My car takes OW-20 oil",
+                "The wifi password is hunter2",
+            ]
+            .into_iter()
+            .map(|content| {
+                NewNoteRequest::builder()
+                    .content(content.to_string())
+                    .build()
+            })
+            .collect(),
+        )
+        .build();
+    let response = client
+        .post(format!("{notes_url}/bulk"))
+        .json(&body)
+        .send()
+        .await?;
+    let response = utils::assert_ok_response(response).await?;
+    let notes = response.json::<Vec<Note>>().await?;
+    assert!(
+        notes
+            .iter()
+            .all(|note| !note.pinned && note.archived_at.is_none())
+    );
+    let ids: Vec<i32> = notes.iter().map(|note| note.id).collect();
+
+    // Pin the newest note, and then it's listed first.
+    let body = PinNotesRequest::builder()
+        .params(NoteSearchParams::builder().ids(vec![ids[2]]).build())
+        .pinned(true)
+        .build();
+    let response = client.put(&pin_notes_url).json(&body).send().await?;
+    let response = utils::assert_ok_response(response).await?;
+    let pinned_notes = response.json::<Vec<Note>>().await?;
+    assert_eq!(pinned_notes.len(), 1);
+    assert!(pinned_notes[0].pinned);
+    let params = NoteSearchParams::builder().build();
+    let response = client.post(&search_notes_url).json(&params).send().await?;
+    let response = utils::assert_ok_response(response).await?;
+    let page_notes = response.json::<Page<Note>>().await?;
+    assert_eq!(page_notes.items.first().map(|note| note.id), Some(ids[2]));
+
+    // Archive the oldest note, and then it's left out of searches unless
+    // archived notes are included.
+    let body = ArchiveNotesRequest::builder()
+        .params(NoteSearchParams::builder().ids(vec![ids[0]]).build())
+        .archived(true)
+        .build();
+    let response = client.put(&archive_notes_url).json(&body).send().await?;
+    let response = utils::assert_ok_response(response).await?;
+    let archived_notes = response.json::<Vec<Note>>().await?;
+    assert_eq!(archived_notes.len(), 1);
+    assert!(archived_notes[0].archived_at.is_some());
+    let params = NoteSearchParams::builder().build();
+    let response = client.post(&search_notes_url).json(&params).send().await?;
+    let response = utils::assert_ok_response(response).await?;
+    let page_notes = response.json::<Page<Note>>().await?;
+    assert_eq!(page_notes.total, 2);
+    assert!(page_notes.items.iter().all(|note| note.id != ids[0]));
+    let params = NoteSearchParams::builder().include_archived(true).build();
+    let response = client.post(&search_notes_url).json(&params).send().await?;
+    let response = utils::assert_ok_response(response).await?;
+    assert_eq!(response.json::<Page<Note>>().await?.total, 3);
+
+    // Unarchive the note, and then it's searchable again.
+    let body = ArchiveNotesRequest::builder()
+        .params(NoteSearchParams::builder().ids(vec![ids[0]]).build())
+        .archived(false)
+        .build();
+    let response = client.put(&archive_notes_url).json(&body).send().await?;
+    let response = utils::assert_ok_response(response).await?;
+    let unarchived_notes = response.json::<Vec<Note>>().await?;
+    assert_eq!(unarchived_notes.len(), 1);
+    assert!(unarchived_notes[0].archived_at.is_none());
+    let params = NoteSearchParams::builder().build();
+    let response = client.post(&search_notes_url).json(&params).send().await?;
+    let response = utils::assert_ok_response(response).await?;
+    assert_eq!(response.json::<Page<Note>>().await?.total, 3);
+    Ok(())
+}
+
+#[tokio::test]
+#[serial]
+async fn notes_search_streaming() -> Result<(), Box<dyn std::error::Error>> {
+    // Make sure there's a database URL and it points to a test database so
+    // prod isn't goofed during testing.
+    let db_connection_url = dotenvy::var("DATABASE_URL")?;
+    utils::reset_database(&db_connection_url)?;
+
+    // Initialize the server state with a batch size big enough to import
+    // more notes than are streamed in one batch.
+    let mut state = toi_server::init(db_connection_url).await?;
+    state.server_config.max_batch_size = 250;
+    let openapi_router = OpenApiRouter::new().nest(
+        "/notes",
+        toi_server::routes::notes::notes_router(state.clone()),
+    );
+    let (router, _) = openapi_router.split_for_parts();
+    let listener = TcpListener::bind(&state.server_config.bind_addr).await?;
+
+    // Spawn server and create a client for all test requests.
+    let _ = tokio::spawn(async move { axum::serve(listener, router).await });
+    let client = reqwest::Client::new();
+    let notes_url = format!("http://{}/notes", state.server_config.bind_addr);
+    let search_notes_url = format!("{notes_url}/search");
+
+    // Make enough notes that they're streamed in more than one batch.
+    let body = BulkNoteImportRequest::builder()
+        .notes(
+            (0..250)
+                .map(|i| {
+                    NewNoteRequest::builder()
+                        .content(format!("Note {i}"))
+                        .build()
+                })
+                .collect(),
+        )
+        .build();
+    let response = client
+        .post(format!("{notes_url}/bulk"))
+        .json(&body)
+        .send()
+        .await?;
+    utils::assert_ok_response(response).await?;
+
+    // Pin a note from the first batch so the search order isn't just the
+    // order the notes were made in.
+    let params = NoteSearchParams::builder().build();
+    let response = client.post(&search_notes_url).json(&params).send().await?;
+    let response = utils::assert_ok_response(response).await?;
+    let page_notes = response.json::<Page<Note>>().await?;
+    let pinned_id = page_notes.items[240].id;
+    let body = PinNotesRequest::builder()
+        .params(NoteSearchParams::builder().ids(vec![pinned_id]).build())
+        .pinned(true)
+        .build();
+    let response = client
+        .put(format!("{notes_url}/pin"))
+        .json(&body)
+        .send()
+        .await?;
+    utils::assert_ok_response(response).await?;
+
+    // Streamed notes come one per line in the same order as buffered ones.
+    let response = client.post(&search_notes_url).json(&params).send().await?;
+    let response = utils::assert_ok_response(response).await?;
+    let page_notes = response.json::<Page<Note>>().await?;
+    assert_eq!(page_notes.total, 250);
+    assert_eq!(page_notes.items[0].id, pinned_id);
+    let response = client
+        .post(&search_notes_url)
+        .header(reqwest::header::ACCEPT, "application/x-ndjson")
+        .json(&params)
+        .send()
+        .await?;
+    let response = utils::assert_ok_response(response).await?;
+    assert_eq!(
+        response.headers()[reqwest::header::CONTENT_TYPE],
+        "application/x-ndjson"
+    );
+    let body = response.text().await?;
+    let streamed_notes = body
+        .lines()
+        .map(serde_json::from_str)
+        .collect::<Result<Vec<Note>, _>>()?;
+    assert_eq!(streamed_notes, page_notes.items);
+
+    // Counting still responds with JSON.
+    let params = NoteSearchParams::builder().count_only(true).build();
+    let response = client
+        .post(&search_notes_url)
+        .header(reqwest::header::ACCEPT, "application/x-ndjson")
+        .json(&params)
+        .send()
+        .await?;
+    let response = utils::assert_ok_response(response).await?;
+    assert_eq!(response.json::<Count>().await?.count, 250);
+    Ok(())
+}
+
+#[tokio::test]
+#[serial]
+async fn notes_expiry() -> Result<(), Box<dyn std::error::Error>> {
+    // Make sure there's a database URL and it points to a test database so
+    // prod isn't goofed during testing.
+    let db_connection_url = dotenvy::var("DATABASE_URL")?;
+    utils::reset_database(&db_connection_url)?;
+
+    // Initialize the server state.
+    let state = toi_server::init(db_connection_url).await?;
+    let openapi_router = OpenApiRouter::new().nest(
+        "/notes",
+        toi_server::routes::notes::notes_router(state.clone()),
+    );
+    let (router, _) = openapi_router.split_for_parts();
+    let listener = TcpListener::bind(&state.server_config.bind_addr).await?;
+
+    // Spawn server and create a client for all test requests.
+    let _ = tokio::spawn(async move { axum::serve(listener, router).await });
+    let client = reqwest::Client::new();
+    let notes_url = format!("http://{}/notes", state.server_config.bind_addr);
+    let search_notes_url = format!("{notes_url}/search");
+
+    // Make a note that already expired, one that expires tomorrow, and one
+    // that never expires.
+    let now = Utc::now();
+    let body = BulkNoteImportRequest::builder()
+        .notes(vec![
+            NewNoteRequest::builder()
+                .content("Parking spot is level 3 row F".to_string())
+                .expires_at(now - Duration::hours(1))
+                .build(),
+            NewNoteRequest::builder()
+                .content("Dinner reservation is under Smith".to_string())
+                .expires_at(now + Duration::days(1))
+                .build(),
+            NewNoteRequest::builder()
+                .content("My car takes OW-20 oil".to_string())
+                .build(),
+        ])
+        .build();
+    let response = client
+        .post(format!("{notes_url}/bulk"))
+        .json(&body)
+        .send()
+        .await?;
+    let response = utils::assert_ok_response(response).await?;
+    let notes = response.json::<Vec<Note>>().await?;
+    let ids: Vec<i32> = notes.iter().map(|note| note.id).collect();
+    assert!(notes[0].expires_at.is_some());
+    assert!(notes[2].expires_at.is_none());
+
+    // The expired note is left out of searches unless expired notes are
+    // included.
+    let params = NoteSearchParams::builder().build();
+    let response = client.post(&search_notes_url).json(&params).send().await?;
+    let response = utils::assert_ok_response(response).await?;
+    let page_notes = response.json::<Page<Note>>().await?;
+    assert_eq!(page_notes.total, 2);
+    assert!(page_notes.items.iter().all(|note| note.id != ids[0]));
+    let params = NoteSearchParams::builder()
+        .ids(vec![ids[0]])
+        .include_expired(true)
+        .build();
+    let response = client.post(&search_notes_url).json(&params).send().await?;
+    let response = utils::assert_ok_response(response).await?;
+    let page_notes = response.json::<Page<Note>>().await?;
+    assert_eq!(page_notes.items.len(), 1);
+    assert_eq!(page_notes.items[0].id, ids[0]);
+
+    // Nothing is deleted while expired notes are within the grace period.
+    let grace = Duration::hours(24);
+    let mut conn = state.pool.get().await?;
+    let num_deleted = delete_expired_notes(now, grace, &mut conn).await?;
+    assert_eq!(num_deleted, 0);
+
+    // A few days later, both expiring notes are past the grace period and
+    // are deleted for good.
+    let num_deleted = delete_expired_notes(now + Duration::days(3), grace, &mut conn).await?;
+    assert_eq!(num_deleted, 2);
+    let params = NoteSearchParams::builder().include_expired(true).build();
+    let response = client.post(&search_notes_url).json(&params).send().await?;
+    let response = utils::assert_ok_response(response).await?;
+    let page_notes = response.json::<Page<Note>>().await?;
+    assert_eq!(page_notes.total, 1);
+    assert_eq!(page_notes.items[0].id, ids[2]);
+    Ok(())
+}
+
+#[tokio::test]
+#[serial]
+async fn notes_duplicates() -> Result<(), Box<dyn std::error::Error>> {
+    // Make sure there's a database URL and it points to a test database so
+    // prod isn't goofed during testing.
+    let db_connection_url = dotenvy::var("DATABASE_URL")?;
+    utils::reset_database(&db_connection_url)?;
+
+    // Initialize the server state with duplicates being checked.
+    let mut state = toi_server::init(db_connection_url).await?;
+    state.server_config.duplicate_check_enabled = true;
+    let openapi_router = OpenApiRouter::new().nest(
+        "/notes",
+        toi_server::routes::notes::notes_router(state.clone()),
+    );
+    let (router, _) = openapi_router.split_for_parts();
+    let listener = TcpListener::bind(&state.server_config.bind_addr).await?;
+
+    // Spawn server and create a client for all test requests.
+    let _ = tokio::spawn(async move { axum::serve(listener, router).await });
+    let client = reqwest::Client::new();
+    let notes_url = format!("http://{}/notes", state.server_config.bind_addr);
+
+    // Make a note.
+    let body = NewNoteRequest::builder()
+        .content("My car takes OW-20 oil".to_string())
+        .build();
+    let response = client.post(&notes_url).json(&body).send().await?;
+    let response = utils::assert_ok_response(response).await?;
+    let note = response.json::<Note>().await?;
+
+    // Adding the same note again returns the existing note instead.
+    let body = NewNoteRequest::builder()
+        .content("my car takes OW-20 oil.".to_string())
+        .build();
+    let response = client.post(&notes_url).json(&body).send().await?;
+    assert_eq!(response.status(), reqwest::StatusCode::CONFLICT);
+    assert_eq!(response.json::<Note>().await?, note);
+
+    // Unless the duplicate is explicitly allowed.
+    let body = NewNoteRequest::builder()
+        .content("my car takes OW-20 oil.".to_string())
+        .allow_duplicate(true)
+        .build();
+    let response = client.post(&notes_url).json(&body).send().await?;
+    let response = utils::assert_ok_response(response).await?;
+    let duplicate = response.json::<Note>().await?;
+    assert_ne!(duplicate.id, note.id);
+
+    // A different note is added like normal.
+    let body = NewNoteRequest::builder()
+        .content("My bike needs new tires".to_string())
+        .build();
+    let response = client.post(&notes_url).json(&body).send().await?;
+    let response = utils::assert_ok_response(response).await?;
+    let other = response.json::<Note>().await?;
+    assert_ne!(other.id, note.id);
+    Ok(())
+}
+
+#[tokio::test]
+#[serial]
+async fn note_tags() -> Result<(), Box<dyn std::error::Error>> {
+    // Make sure there's a database URL and it points to a test database so
+    // prod isn't goofed during testing.
+    let db_connection_url = dotenvy::var("DATABASE_URL")?;
+    utils::reset_database(&db_connection_url)?;
+
+    // Initialize the server state.
+    let state = toi_server::init(db_connection_url).await?;
+    let openapi_router = OpenApiRouter::new()
+        .nest(
+            "/notes",
+            toi_server::routes::notes::notes_router(state.clone()),
+        )
+        .nest(
+            "/tags",
+            toi_server::routes::tags::tags_router(state.clone()),
+        );
+    let (router, _) = openapi_router.split_for_parts();
+    let listener = TcpListener::bind(&state.server_config.bind_addr).await?;
+
+    // Spawn server and create a client for all test requests.
+    let _ = tokio::spawn(async move { axum::serve(listener, router).await });
+    let client = reqwest::Client::new();
+    let tags_url = format!("http://{}/tags", state.server_config.bind_addr);
+    let notes_url = format!("http://{}/notes", state.server_config.bind_addr);
+
+    // Make tags, and then make notes with shared and disjoint tags.
+    let mut tag_ids = vec![];
+    for name in ["work", "urgent", "home"] {
+        let body = NewTagRequest::builder().name(name.to_string()).build();
+        let response = client.post(&tags_url).json(&body).send().await?;
+        let response = utils::assert_ok_response(response).await?;
+        tag_ids.push(response.json::<Tag>().await?.id);
+    }
+    let note_tag_ids = |note_tags: NoteTags| -> Vec<i32> {
+        let mut ids: Vec<i32> = note_tags.tags.into_iter().map(|tag| tag.id).collect();
+        ids.sort_unstable();
+        ids
+    };
+    let mut ids = vec![];
+    for (content, tags) in [
+        ("Send the quarterly report", vec!["work", "urgent"]),
+        ("Book a meeting room", vec!["work"]),
+        ("Fix the leaky faucet", vec!["home"]),
+    ] {
+        let body = NewNoteRequest::builder()
+            .content(content.to_string())
+            .tags(tags.into_iter().map(str::to_string).collect())
+            .build();
+        let response = client.post(&notes_url).json(&body).send().await?;
+        let response = utils::assert_ok_response(response).await?;
+        ids.push(response.json::<Note>().await?.id);
+    }
+
+    // Notes come with the tags they were added with.
+    let search_note_tags_url = format!("{notes_url}/tags/search");
+    let params = NoteTagSearchParams::builder().note_id(ids[0]).build();
+    let response = client
+        .post(&search_note_tags_url)
+        .json(&params)
+        .send()
+        .await?;
+    let response = utils::assert_ok_response(response).await?;
+    let note_tags = response.json::<NoteTags>().await?;
+    assert_eq!(note_tags.note.id, ids[0]);
+    assert_eq!(note_tag_ids(note_tags), tag_ids[..2]);
+
+    // Notes with any of the tags match by default, and notes with all of
+    // the tags match when asked for.
+    let search_notes_url = format!("{notes_url}/search");
+    for (tags_match, expected_ids) in [
+        (None, vec![ids[0], ids[1]]),
+        (Some(TagMatch::Any), vec![ids[0], ids[1]]),
+        (Some(TagMatch::All), vec![ids[0]]),
+    ] {
+        let params = NoteSearchParams::builder()
+            .tags(vec!["work".to_string(), "urgent".to_string()])
+            .maybe_tags_match(tags_match)
+            .build();
+        let response = client.post(&search_notes_url).json(&params).send().await?;
+        let response = utils::assert_ok_response(response).await?;
+        let mut note_ids: Vec<i32> = response
+            .json::<Page<Note>>()
+            .await?
+            .items
+            .into_iter()
+            .map(|note| note.id)
+            .collect();
+        note_ids.sort_unstable();
+        assert_eq!(note_ids, expected_ids);
+    }
+
+    // Tag an existing note. Tagging it again keeps the one tag.
+    for _ in 0..2 {
+        let body = NewNoteTagsRequest::builder()
+            .ids(vec![ids[2]])
+            .tags(vec!["urgent".to_string()])
+            .build();
+        let response = client
+            .post(format!("{notes_url}/tags"))
+            .json(&body)
+            .send()
+            .await?;
+        let response = utils::assert_ok_response(response).await?;
+        let notes = response.json::<Vec<Note>>().await?;
+        assert_eq!(notes.len(), 1);
+        assert_eq!(notes[0].id, ids[2]);
+    }
+    let params = NoteTagSearchParams::builder().note_id(ids[2]).build();
+    let response = client
+        .post(&search_note_tags_url)
+        .json(&params)
+        .send()
+        .await?;
+    let response = utils::assert_ok_response(response).await?;
+    let note_tags = response.json::<NoteTags>().await?;
+    assert_eq!(note_tag_ids(note_tags), tag_ids[1..]);
+
+    // Remove a tag from a note. The tag itself is kept.
+    let params = NoteTagSearchParams::builder()
+        .note_id(ids[2])
+        .tag_ids(vec![tag_ids[2]])
+        .build();
+    let response = client
+        .post(format!("{notes_url}/tags/delete"))
+        .json(&params)
+        .send()
+        .await?;
+    let response = utils::assert_ok_response(response).await?;
+    let note_tags = response.json::<NoteTags>().await?;
+    assert_eq!(note_tag_ids(note_tags), vec![tag_ids[2]]);
+    let params = NoteSearchParams::builder()
+        .tags(vec!["home".to_string()])
+        .build();
+    let response = client.post(&search_notes_url).json(&params).send().await?;
+    let response = utils::assert_ok_response(response).await?;
+    assert_eq!(response.json::<Page<Note>>().await?.total, 0);
+
+    // Deleting a tag removes it from every note it was on.
+    let params = TagSearchParams::builder().ids(vec![tag_ids[1]]).build();
+    let response = client
+        .post(format!("{tags_url}/delete"))
+        .json(&params)
+        .send()
+        .await?;
+    utils::assert_ok_response(response).await?;
+    let params = NoteTagSearchParams::builder().note_id(ids[0]).build();
+    let response = client
+        .post(&search_note_tags_url)
+        .json(&params)
+        .send()
+        .await?;
+    let response = utils::assert_ok_response(response).await?;
+    let note_tags = response.json::<NoteTags>().await?;
+    assert_eq!(note_tag_ids(note_tags), vec![tag_ids[0]]);
+    Ok(())
+}
+
+#[tokio::test]
+#[serial]
+async fn notes_pagination() -> Result<(), Box<dyn std::error::Error>> {
+    // Make sure there's a database URL and it points to a test database so
+    // prod isn't goofed during testing.
+    let db_connection_url = dotenvy::var("DATABASE_URL")?;
+    utils::reset_database(&db_connection_url)?;
+
+    // Initialize the server state.
+    let state = toi_server::init(db_connection_url).await?;
+    let openapi_router = OpenApiRouter::new().nest(
+        "/notes",
+        toi_server::routes::notes::notes_router(state.clone()),
+    );
+    let (router, _) = openapi_router.split_for_parts();
+    let listener = TcpListener::bind(&state.server_config.bind_addr).await?;
+
+    // Spawn server and create a client for all test requests.
+    let _ = tokio::spawn(async move { axum::serve(listener, router).await });
+    let client = reqwest::Client::new();
+    let notes_url = format!("http://{}/notes", state.server_config.bind_addr);
+    let search_notes_url = format!("{notes_url}/search");
+
+    // Make a few notes.
+    for content in [
+        "Old apartment gate code is 1234",
+        "My car takes OW-20 oil",
+        "The wifi password is hunter2",
+    ] {
+        let body = NewNoteRequest::builder()
+            .content(content.to_string())
+            .build();
+        let response = client.post(&notes_url).json(&body).send().await?;
+        utils::assert_ok_response(response).await?;
+    }
+
+    // A page within the results has its items and the total.
+    let params = NoteSearchParams::builder().offset(1).limit(1).build();
+    let response = client.post(&search_notes_url).json(&params).send().await?;
+    let response = utils::assert_ok_response(response).await?;
+    let page_notes = response.json::<Page<Note>>().await?;
+    assert_eq!(page_notes.items.len(), 1);
+    assert_eq!(page_notes.total, 3);
+
+    // A page past the end of the results doesn't have any items, but it
+    // still has the total.
+    let params = NoteSearchParams::builder().offset(5).limit(1).build();
+    let response = client.post(&search_notes_url).json(&params).send().await?;
+    let response = utils::assert_ok_response(response).await?;
+    let page_notes = response.json::<Page<Note>>().await?;
+    assert!(page_notes.items.is_empty());
+    assert_eq!(page_notes.total, 3);
     Ok(())
 }
@@ -1,9 +1,20 @@
+use axum::{
+    Json,
+    extract::{ConnectInfo, Query, State},
+    http::HeaderMap,
+    routing::get,
+};
+use serde_json::{Value, json};
 use serial_test::serial;
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex};
 use tokio::net::TcpListener;
 use utoipa_axum::router::OpenApiRouter;
 
-use toi_server::models::places::{
-    NewPlaceRequest, Place, PlaceSearchParams, PlaceUpdates, UpdatePlaceRequest,
+use toi_server::models::{
+    pagination::Page,
+    places::{NewPlaceRequest, Place, PlaceSearchParams, PlaceUpdates, UpdatePlaceRequest},
 };
 
 mod utils;
@@ -42,6 +53,16 @@ async fn places_routes() -> Result<(), Box<dyn std::error::Error>> {
     let place1 = response.json::<Place>().await?;
     assert_eq!(place1.name, name);
 
+    // Retrieve the place using its ID, and fail to retrieve a missing one.
+    let response = client
+        .get(format!("{places_url}/{}", place1.id))
+        .send()
+        .await?;
+    let response = utils::assert_ok_response(response).await?;
+    assert_eq!(response.json::<Place>().await?, place1);
+    let response = client.get(format!("{places_url}/0")).send().await?;
+    assert_eq!(response.status(), reqwest::StatusCode::NOT_FOUND);
+
     // Update the place.
     let phone = "867-5309".to_string();
     let body = UpdatePlaceRequest::builder()
@@ -59,7 +80,7 @@ async fn places_routes() -> Result<(), Box<dyn std::error::Error>> {
         .build();
     let response = client.post(search_places_url).json(&params).send().await?;
     let response = utils::assert_ok_response(response).await?;
-    let vec_places1 = response.json::<Vec<Place>>().await?;
+    let vec_places1 = response.json::<Page<Place>>().await?.items;
     assert_eq!(vec_places1, vec![place2]);
 
     // Delete the place using search.
@@ -70,3 +91,224 @@ async fn places_routes() -> Result<(), Box<dyn std::error::Error>> {
     assert_eq!(vec_places2, vec_places1);
     Ok(())
 }
+
+/// Stubbed geocoder that responds like Nominatim for a few known areas.
+async fn geocode_stub(Query(params): Query<HashMap<String, String>>) -> Json<Value> {
+    let coordinates = match params.get("q").map(String::as_str) {
+        Some("downtown") => Some(("30.2672", "-97.7431")),
+        Some("1 Congress Ave") => Some(("30.2640", "-97.7470")),
+        Some("2 Lamar Blvd") => Some(("30.2800", "-97.7600")),
+        Some("3 Main St, Dallas") => Some(("32.7767", "-96.7970")),
+        _ => None,
+    };
+    let results = coordinates.map_or_else(Vec::new, |(lat, lon)| {
+        vec![json!({
+            "name": params["q"],
+            "addresstype": "road",
+            "lat": lat,
+            "lon": lon,
+            "display_name": params["q"],
+        })]
+    });
+    Json(Value::Array(results))
+}
+
+#[tokio::test]
+#[serial]
+async fn searching_places_by_proximity() -> Result<(), Box<dyn std::error::Error>> {
+    // Make sure there's a database URL and it points to a test database so
+    // prod isn't goofed during testing.
+    let db_connection_url = dotenvy::var("DATABASE_URL")?;
+    utils::reset_database(&db_connection_url)?;
+
+    // Spawn the stubbed geocoder so tests don't depend on a live API.
+    let geocoder_listener = TcpListener::bind("127.0.0.1:0").await?;
+    let geocoder_addr = geocoder_listener.local_addr()?;
+    let geocoder_router = axum::Router::new().route("/search", get(geocode_stub));
+    let _ = tokio::spawn(async move { axum::serve(geocoder_listener, geocoder_router).await });
+
+    // Initialize the server state using the stubbed geocoder.
+    let mut state = toi_server::init(db_connection_url).await?;
+    state.server_config.geocoding_url = format!("http://{geocoder_addr}/search");
+    let openapi_router = OpenApiRouter::new().nest(
+        "/places",
+        toi_server::routes::places::places_router(state.clone()),
+    );
+    let (router, _) = openapi_router.split_for_parts();
+    let listener = TcpListener::bind(&state.server_config.bind_addr).await?;
+
+    // Spawn server and create a client for all test requests.
+    let _ = tokio::spawn(async move { axum::serve(listener, router).await });
+    let client = reqwest::Client::new();
+    let places_url = format!("http://{}/places", state.server_config.bind_addr);
+
+    // Make places with and without geocodable addresses.
+    let mut places = vec![];
+    for (name, address) in [
+        ("Nearby Coffee", Some("2 Lamar Blvd")),
+        ("Closest Coffee", Some("1 Congress Ave")),
+        ("Faraway Coffee", Some("3 Main St, Dallas")),
+        ("Unknown Coffee", Some("Nowhere in particular")),
+        ("Homeless Coffee", None),
+    ] {
+        let body = NewPlaceRequest::builder()
+            .name(name.to_string())
+            .description("A coffee shop".to_string())
+            .maybe_address(address.map(str::to_string))
+            .build();
+        let response = client.post(&places_url).json(&body).send().await?;
+        let response = utils::assert_ok_response(response).await?;
+        places.push(response.json::<Place>().await?);
+    }
+    assert!(places[0].latitude.is_some() && places[0].longitude.is_some());
+    assert!(places[3].latitude.is_none() && places[3].longitude.is_none());
+    assert!(places[4].latitude.is_none() && places[4].longitude.is_none());
+
+    // Only places with coordinates are matched, nearest first.
+    let search_places_url = format!("{places_url}/search");
+    let params = PlaceSearchParams::builder()
+        .near_query("downtown".to_string())
+        .build();
+    let response = client.post(&search_places_url).json(&params).send().await?;
+    let response = utils::assert_ok_response(response).await?;
+    let names: Vec<String> = response
+        .json::<Page<Place>>()
+        .await?
+        .items
+        .into_iter()
+        .map(|place| place.name)
+        .collect();
+    assert_eq!(
+        names,
+        vec!["Closest Coffee", "Nearby Coffee", "Faraway Coffee"]
+    );
+
+    // Places outside the radius are excluded.
+    let params = PlaceSearchParams::builder()
+        .near_query("downtown".to_string())
+        .radius_km(10.0)
+        .build();
+    let response = client.post(&search_places_url).json(&params).send().await?;
+    let response = utils::assert_ok_response(response).await?;
+    let names: Vec<String> = response
+        .json::<Page<Place>>()
+        .await?
+        .items
+        .into_iter()
+        .map(|place| place.name)
+        .collect();
+    assert_eq!(names, vec!["Closest Coffee", "Nearby Coffee"]);
+
+    // Places without coordinates are still reachable by plain search.
+    let params = PlaceSearchParams::builder()
+        .query("Homeless Coffee".to_string())
+        .build();
+    let response = client.post(&search_places_url).json(&params).send().await?;
+    let response = utils::assert_ok_response(response).await?;
+    let page = response.json::<Page<Place>>().await?;
+    assert!(page.items.contains(&places[4]));
+
+    // Updating the address re-geocodes the place.
+    let body = UpdatePlaceRequest::builder()
+        .id(places[3].id)
+        .place_updates(
+            PlaceUpdates::builder()
+                .address("1 Congress Ave".to_string())
+                .build(),
+        )
+        .build();
+    let response = client.put(&places_url).json(&body).send().await?;
+    let response = utils::assert_ok_response(response).await?;
+    let place = response.json::<Place>().await?;
+    assert_eq!(place.latitude, places[1].latitude);
+    assert_eq!(place.longitude, places[1].longitude);
+    Ok(())
+}
+
+/// Remote port and user agent of every request made to a geocoder.
+type GeocoderRequests = Arc<Mutex<Vec<(u16, Option<String>)>>>;
+
+/// Stubbed geocoder that records where its requests come from.
+async fn recording_geocode_stub(
+    State(requests): State<GeocoderRequests>,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    headers: HeaderMap,
+    query: Query<HashMap<String, String>>,
+) -> Json<Value> {
+    let user_agent = headers
+        .get("user-agent")
+        .and_then(|value| value.to_str().ok())
+        .map(str::to_string);
+    requests
+        .lock()
+        .expect("lock shouldn't be poisoned")
+        .push((addr.port(), user_agent));
+    geocode_stub(query).await
+}
+
+#[tokio::test]
+#[serial]
+async fn geocoding_reuses_connections() -> Result<(), Box<dyn std::error::Error>> {
+    // Make sure there's a database URL and it points to a test database so
+    // prod isn't goofed during testing.
+    let db_connection_url = dotenvy::var("DATABASE_URL")?;
+    utils::reset_database(&db_connection_url)?;
+
+    // Spawn a stubbed geocoder that records the port each request comes
+    // from.
+    let requests = GeocoderRequests::default();
+    let geocoder_listener = TcpListener::bind("127.0.0.1:0").await?;
+    let geocoder_addr = geocoder_listener.local_addr()?;
+    let geocoder_router = axum::Router::new()
+        .route("/search", get(recording_geocode_stub))
+        .with_state(requests.clone());
+    let _ = tokio::spawn(async move {
+        axum::serve(
+            geocoder_listener,
+            geocoder_router.into_make_service_with_connect_info::<SocketAddr>(),
+        )
+        .await
+    });
+
+    // Initialize the server state using the stubbed geocoder.
+    let mut state = toi_server::init(db_connection_url).await?;
+    state.server_config.geocoding_url = format!("http://{geocoder_addr}/search");
+    let openapi_router = OpenApiRouter::new().nest(
+        "/places",
+        toi_server::routes::places::places_router(state.clone()),
+    );
+    let (router, _) = openapi_router.split_for_parts();
+    let listener = TcpListener::bind(&state.server_config.bind_addr).await?;
+
+    // Spawn server and create a client for all test requests.
+    let _ = tokio::spawn(async move { axum::serve(listener, router).await });
+    let client = reqwest::Client::new();
+    let places_url = format!("http://{}/places", state.server_config.bind_addr);
+
+    // Make a couple of places, each of which is geocoded.
+    for (name, address) in [
+        ("Nearby Coffee", "2 Lamar Blvd"),
+        ("Closest Coffee", "1 Congress Ave"),
+    ] {
+        let body = NewPlaceRequest::builder()
+            .name(name.to_string())
+            .description("A coffee shop".to_string())
+            .address(address.to_string())
+            .build();
+        let response = client.post(&places_url).json(&body).send().await?;
+        utils::assert_ok_response(response).await?;
+    }
+
+    // Both lookups went over the same connection from the shared client,
+    // which sends the configured user agent.
+    let requests = requests.lock().expect("lock shouldn't be poisoned").clone();
+    assert_eq!(requests.len(), 2);
+    assert_eq!(requests[0].0, requests[1].0);
+    for (_, user_agent) in &requests {
+        assert_eq!(
+            user_agent.as_deref(),
+            Some(state.server_config.user_agent.as_str())
+        );
+    }
+    Ok(())
+}
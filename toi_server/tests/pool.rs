@@ -0,0 +1,102 @@
+use std::time::Duration;
+
+use axum::{http::StatusCode as MockStatusCode, routing::post};
+use diesel_async::{AsyncPgConnection, pooled_connection::AsyncDieselConnectionManager};
+use reqwest::StatusCode;
+use serial_test::serial;
+use tokio::net::TcpListener;
+use utoipa_axum::router::OpenApiRouter;
+
+use toi_server::models::{
+    error::{ErrorCode, ErrorResponse},
+    notes::NewNoteRequest,
+    pool::PoolStatus,
+};
+
+mod utils;
+
+/// Mock embedding API that takes a while and then fails, so the request
+/// that's waiting on it holds onto its database connection.
+async fn slow_embeddings() -> MockStatusCode {
+    tokio::time::sleep(Duration::from_secs(1)).await;
+    MockStatusCode::INTERNAL_SERVER_ERROR
+}
+
+#[tokio::test]
+#[serial]
+async fn pool_exhaustion() -> Result<(), Box<dyn std::error::Error>> {
+    // Make sure there's a database URL and it points to a test database so
+    // prod isn't goofed during testing.
+    let db_connection_url = dotenvy::var("DATABASE_URL")?;
+    utils::reset_database(&db_connection_url)?;
+
+    // Spawn a mock embedding API that's slow to respond.
+    let mock_router = axum::Router::new().route("/v1/embeddings", post(slow_embeddings));
+    let mock_listener = TcpListener::bind("127.0.0.1:0").await?;
+    let mock_addr = mock_listener.local_addr()?;
+    let _ = tokio::spawn(async move { axum::serve(mock_listener, mock_router).await });
+
+    // Initialize the server state with a pool that only has one connection
+    // and doesn't wait long for it.
+    let mut state = toi_server::init(db_connection_url.clone()).await?;
+    state.model_client.embedding_api_config.base_url = format!("http://{mock_addr}");
+    let manager = AsyncDieselConnectionManager::<AsyncPgConnection>::new(db_connection_url);
+    state.pool = bb8::Pool::builder()
+        .max_size(1)
+        .connection_timeout(Duration::from_millis(300))
+        .build(manager)
+        .await?;
+    let openapi_router = OpenApiRouter::new()
+        .nest(
+            "/notes",
+            toi_server::routes::notes::notes_router(state.clone()),
+        )
+        .nest(
+            "/admin/pool",
+            toi_server::routes::pool::pool_router(state.clone()),
+        );
+    let (router, _) = openapi_router.split_for_parts();
+    let listener = TcpListener::bind(&state.server_config.bind_addr).await?;
+
+    // Spawn server and create a client for all test requests.
+    let _ = tokio::spawn(async move { axum::serve(listener, router).await });
+    let client = reqwest::Client::new();
+    let notes_url = format!("http://{}/notes", state.server_config.bind_addr);
+    let pool_url = format!("http://{}/admin/pool", state.server_config.bind_addr);
+
+    // Nothing has waited on the pool yet.
+    let response = client.get(&pool_url).send().await?;
+    let response = utils::assert_ok_response(response).await?;
+    let pool_status = response.json::<PoolStatus>().await?;
+    assert_eq!(pool_status.get_timed_out, 0);
+
+    // Adding two notes at once means one of them holds the only connection
+    // while waiting on the embedding API, so the other times out waiting
+    // for a connection.
+    let body = NewNoteRequest::builder()
+        .content("My car takes OW-20 oil".to_string())
+        .build();
+    let (first, second) = tokio::join!(
+        client.post(&notes_url).json(&body).send(),
+        client.post(&notes_url).json(&body).send()
+    );
+    let (first, second) = (first?, second?);
+    let (busy, other) = if first.status() == StatusCode::SERVICE_UNAVAILABLE {
+        (first, second)
+    } else {
+        (second, first)
+    };
+    assert_eq!(busy.status(), StatusCode::SERVICE_UNAVAILABLE);
+    assert_eq!(other.status(), StatusCode::UNPROCESSABLE_ENTITY);
+    let error_response = busy.json::<ErrorResponse>().await?;
+    assert_eq!(error_response.error.code, ErrorCode::Unavailable);
+    assert!(!error_response.error.detail.is_empty());
+
+    // The timeout shows up in the pool's status.
+    let response = client.get(&pool_url).send().await?;
+    let response = utils::assert_ok_response(response).await?;
+    let pool_status = response.json::<PoolStatus>().await?;
+    assert_eq!(pool_status.connections, 1);
+    assert_eq!(pool_status.get_timed_out, 1);
+    Ok(())
+}
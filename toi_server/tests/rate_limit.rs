@@ -0,0 +1,71 @@
+use reqwest::{StatusCode, header};
+use serial_test::serial;
+use tokio::net::TcpListener;
+use utoipa_axum::router::OpenApiRouter;
+
+use toi_server::{
+    models::{
+        error::{ErrorCode, ErrorResponse},
+        notes::NoteSearchParams,
+    },
+    rate_limit::{RateLimiter, rate_limit},
+};
+
+mod utils;
+
+#[tokio::test]
+#[serial]
+async fn rate_limited_routes() -> Result<(), Box<dyn std::error::Error>> {
+    // Make sure there's a database URL and it points to a test database so
+    // prod isn't goofed during testing.
+    let db_connection_url = dotenvy::var("DATABASE_URL")?;
+    utils::reset_database(&db_connection_url)?;
+
+    // Initialize the server state with a small burst and a slow refill so
+    // the limit is hit right away. Health checks aren't limited.
+    let mut state = toi_server::init(db_connection_url).await?;
+    state.rate_limiter = RateLimiter::new(1, 2);
+    let openapi_router = OpenApiRouter::new()
+        .nest(
+            "/notes",
+            toi_server::routes::notes::notes_router(state.clone()),
+        )
+        .layer(axum::middleware::from_fn_with_state(
+            state.clone(),
+            rate_limit,
+        ))
+        .merge(toi_server::routes::health::health_router(state.clone()));
+    let (router, _) = openapi_router.split_for_parts();
+    let listener = TcpListener::bind(&state.server_config.bind_addr).await?;
+
+    // Spawn server and create a client for all test requests.
+    let _ = tokio::spawn(async move { axum::serve(listener, router).await });
+    let client = reqwest::Client::new();
+    let base_url = format!("http://{}", state.server_config.bind_addr);
+    let search_notes_url = format!("{base_url}/notes/search");
+
+    // The burst is allowed through.
+    let params = NoteSearchParams::builder().build();
+    for _ in 0..2 {
+        let response = client.post(&search_notes_url).json(&params).send().await?;
+        utils::assert_ok_response(response).await?;
+    }
+
+    // The next request is over the limit and says when to retry.
+    let response = client.post(&search_notes_url).json(&params).send().await?;
+    assert_eq!(response.status(), StatusCode::TOO_MANY_REQUESTS);
+    let retry_after: u64 = response
+        .headers()
+        .get(header::RETRY_AFTER)
+        .ok_or("missing retry after header")?
+        .to_str()?
+        .parse()?;
+    assert!((1..=60).contains(&retry_after));
+    let error_response = response.json::<ErrorResponse>().await?;
+    assert_eq!(error_response.error.code, ErrorCode::RateLimited);
+
+    // Health checks are still available.
+    let response = client.get(format!("{base_url}/health")).send().await?;
+    utils::assert_ok_response(response).await?;
+    Ok(())
+}
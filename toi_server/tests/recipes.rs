@@ -1,14 +1,83 @@
+use axum::{extract::State, response::Json, routing::post};
+use serde_json::{Value, json};
 use serial_test::serial;
+use std::{
+    sync::{
+        Arc,
+        atomic::{AtomicUsize, Ordering},
+    },
+    time::Duration,
+};
 use tokio::net::TcpListener;
 use utoipa_axum::router::OpenApiRouter;
 
 use toi_server::models::{
-    recipes::{NewRecipeRequest, Recipe, RecipeSearchParams, RecipeTagSearchParams, RecipeTags},
+    deletion::DeleteParams,
+    pagination::Page,
+    recipes::{
+        NewRecipeRequest, Recipe, RecipePreview, RecipePreviewDetailed, RecipePreviewWithTags,
+        RecipeScaleRequest, RecipeSearchParams, RecipeServings, RecipeTagSearchParams, RecipeTags,
+        RecipeWithTags, ScaledRecipe, ShoppingCategory, ShoppingList, ShoppingListRequest,
+    },
     tags::{NewTagRequest, Tag},
 };
 
 mod utils;
 
+/// Mock embedding API that counts how many times it's called and embeds
+/// everything the same.
+async fn counting_embeddings(State(calls): State<Arc<AtomicUsize>>) -> Json<Value> {
+    calls.fetch_add(1, Ordering::SeqCst);
+    Json(json!({"data": [{"embedding": [1.0, 0.0, 0.0]}]}))
+}
+
+/// Tracks how many mock model API requests are in flight at once.
+#[derive(Clone, Default)]
+struct InFlight {
+    current: Arc<AtomicUsize>,
+    max: Arc<AtomicUsize>,
+}
+
+/// Mock embedding API that takes a while to respond so concurrent requests
+/// overlap. Inputs ending with a tag name are embedded along that tag's own
+/// axis so each tag query only matches its tag.
+async fn slow_tag_embeddings(
+    State(in_flight): State<InFlight>,
+    Json(body): Json<Value>,
+) -> Json<Value> {
+    let current = in_flight.current.fetch_add(1, Ordering::SeqCst) + 1;
+    in_flight.max.fetch_max(current, Ordering::SeqCst);
+    tokio::time::sleep(Duration::from_millis(50)).await;
+    in_flight.current.fetch_sub(1, Ordering::SeqCst);
+    let input = body["input"].as_str().unwrap_or_default();
+    let embedding = if input.ends_with("asian") {
+        [1.0, 0.0, 0.0]
+    } else if input.ends_with("rice") {
+        [0.0, 1.0, 0.0]
+    } else if input.ends_with("italian") {
+        [0.0, 0.0, 1.0]
+    } else {
+        [1.0, 1.0, 1.0]
+    };
+    Json(json!({"data": [{"embedding": embedding}]}))
+}
+
+/// Mock generation API that always merges ingredients into the same
+/// shopping list.
+async fn shopping_list_completions() -> Json<Value> {
+    let content = json!({
+        "items": [
+            {"category": "pantry", "item": "24 lasagna noodles"},
+            {"category": "dairy", "item": "3 cups ricotta"},
+            {"category": "produce", "item": "2 cups spinach"},
+            {"category": "dairy", "item": "2 cups mozzarella"}
+        ]
+    });
+    Json(json!({
+        "choices": [{"message": {"role": "assistant", "content": content.to_string()}}]
+    }))
+}
+
 #[tokio::test]
 #[serial]
 async fn recipes_routes() -> Result<(), Box<dyn std::error::Error>> {
@@ -59,6 +128,16 @@ async fn recipes_routes() -> Result<(), Box<dyn std::error::Error>> {
     let recipe1 = response.json::<Recipe>().await?;
     assert_eq!(recipe1.description, description);
 
+    // Retrieve the recipe using its ID, and fail to retrieve a missing one.
+    let response = client
+        .get(format!("{recipes_url}/{}", recipe1.id))
+        .send()
+        .await?;
+    let response = utils::assert_ok_response(response).await?;
+    assert_eq!(response.json::<Recipe>().await?, recipe1);
+    let response = client.get(format!("{recipes_url}/0")).send().await?;
+    assert_eq!(response.status(), reqwest::StatusCode::NOT_FOUND);
+
     // Retrieve the recipe tag using search.
     let search_recipe_tags_url = format!("{recipes_url}/tags/search");
     let params = RecipeTagSearchParams::builder()
@@ -82,7 +161,7 @@ async fn recipes_routes() -> Result<(), Box<dyn std::error::Error>> {
         .build();
     let response = client.post(search_recipes_url).json(&params).send().await?;
     let response = utils::assert_ok_response(response).await?;
-    let vec_recipes1 = response.json::<Vec<Recipe>>().await?;
+    let vec_recipes1 = response.json::<Page<Recipe>>().await?.items;
     assert_eq!(vec_recipes1, vec![recipe1]);
 
     // Delete the recipe using search.
@@ -91,5 +170,742 @@ async fn recipes_routes() -> Result<(), Box<dyn std::error::Error>> {
     let response = utils::assert_ok_response(response).await?;
     let vec_recipes2 = response.json::<Vec<Recipe>>().await?;
     assert_eq!(vec_recipes2, vec_recipes1);
+
+    // Make a recipe where an ingredient only appears in its ingredients.
+    let body = NewRecipeRequest::builder()
+        .description("warm breakfast soup".to_string())
+        .ingredients("2 tbsp white miso paste, 1 block silken tofu, 2 scallions".to_string())
+        .instructions("1. simmer water, 2. whisk in miso, 3. add tofu and scallions".to_string())
+        .tags(vec!["asian".to_string()])
+        .build();
+    let response = client.post(&recipes_url).json(&body).send().await?;
+    let response = utils::assert_ok_response(response).await?;
+    let recipe2 = response.json::<Recipe>().await?;
+
+    // Retrieve the recipe using only its ingredients.
+    let search_recipes_url = format!("{recipes_url}/search");
+    let params = RecipeSearchParams::builder()
+        .ingredients_query("MISO tofu".to_string())
+        .build();
+    let response = client
+        .post(&search_recipes_url)
+        .json(&params)
+        .send()
+        .await?;
+    let response = utils::assert_ok_response(response).await?;
+    let vec_recipes3 = response.json::<Page<Recipe>>().await?.items;
+    assert_eq!(vec_recipes3, vec![recipe2]);
+
+    // Ingredients and the query must both match.
+    let params = RecipeSearchParams::builder()
+        .query("breakfast soup".to_string())
+        .ingredients_query("jasmine".to_string())
+        .build();
+    let response = client
+        .post(&search_recipes_url)
+        .json(&params)
+        .send()
+        .await?;
+    let response = utils::assert_ok_response(response).await?;
+    let vec_recipes4 = response.json::<Page<Recipe>>().await?.items;
+    assert!(vec_recipes4.is_empty());
+    Ok(())
+}
+
+#[tokio::test]
+#[serial]
+async fn recipe_tags_embedding_cache() -> Result<(), Box<dyn std::error::Error>> {
+    // Make sure there's a database URL and it points to a test database so
+    // prod isn't goofed during testing.
+    let db_connection_url = dotenvy::var("DATABASE_URL")?;
+    utils::reset_database(&db_connection_url)?;
+
+    // Spawn a mock embedding API that counts calls.
+    let calls = Arc::new(AtomicUsize::new(0));
+    let mock_router = axum::Router::new()
+        .route("/v1/embeddings", post(counting_embeddings))
+        .with_state(calls.clone());
+    let mock_listener = TcpListener::bind("127.0.0.1:0").await?;
+    let mock_addr = mock_listener.local_addr()?;
+    let _ = tokio::spawn(async move { axum::serve(mock_listener, mock_router).await });
+
+    // Initialize the server state, pointing embedding at the mock API.
+    let mut state = toi_server::init(db_connection_url).await?;
+    state.model_client.embedding_api_config.base_url = format!("http://{mock_addr}");
+    let openapi_router = OpenApiRouter::new()
+        .nest(
+            "/recipes",
+            toi_server::routes::recipes::recipes_router(state.clone()),
+        )
+        .nest(
+            "/tags",
+            toi_server::routes::tags::tags_router(state.clone()),
+        );
+    let (router, _) = openapi_router.split_for_parts();
+    let listener = TcpListener::bind(&state.server_config.bind_addr).await?;
+
+    // Spawn server and create a client for all test requests.
+    let _ = tokio::spawn(async move { axum::serve(listener, router).await });
+    let client = reqwest::Client::new();
+    let tags_url = format!("http://{}/tags", state.server_config.bind_addr);
+    let recipes_url = format!("http://{}/recipes", state.server_config.bind_addr);
+
+    // Make a tag and a recipe with that tag.
+    let body = NewTagRequest::builder().name("asian".to_string()).build();
+    let response = client.post(&tags_url).json(&body).send().await?;
+    utils::assert_ok_response(response).await?;
+    let body = NewRecipeRequest::builder()
+        .description("steamed jasmine rice".to_string())
+        .ingredients("jasmine rice".to_string())
+        .instructions("1. wash rice, 2. cook rice".to_string())
+        .tags(vec!["asian".to_string()])
+        .build();
+    let response = client.post(&recipes_url).json(&body).send().await?;
+    utils::assert_ok_response(response).await?;
+
+    // Searching recipe tags embeds the recipe query and tag query once each.
+    calls.store(0, Ordering::SeqCst);
+    let params = RecipeTagSearchParams::builder()
+        .recipe_query("rice".to_string())
+        .tag_query("asian".to_string())
+        .build();
+    let response = client
+        .post(format!("{recipes_url}/tags/search"))
+        .json(&params)
+        .send()
+        .await?;
+    utils::assert_ok_response(response).await?;
+    assert_eq!(calls.load(Ordering::SeqCst), 2);
+
+    // Repeated tags are only embedded once within a request.
+    calls.store(0, Ordering::SeqCst);
+    let params = RecipeSearchParams::builder()
+        .query("rice".to_string())
+        .tags(vec!["asian".to_string(), "asian".to_string()])
+        .build();
+    let response = client
+        .post(format!("{recipes_url}/search"))
+        .json(&params)
+        .send()
+        .await?;
+    utils::assert_ok_response(response).await?;
+    assert_eq!(calls.load(Ordering::SeqCst), 2);
+    Ok(())
+}
+
+#[tokio::test]
+#[serial]
+async fn recipe_search_with_tags() -> Result<(), Box<dyn std::error::Error>> {
+    // Make sure there's a database URL and it points to a test database so
+    // prod isn't goofed during testing.
+    let db_connection_url = dotenvy::var("DATABASE_URL")?;
+    utils::reset_database(&db_connection_url)?;
+
+    // Initialize the server state.
+    let state = toi_server::init(db_connection_url).await?;
+    let openapi_router = OpenApiRouter::new()
+        .nest(
+            "/recipes",
+            toi_server::routes::recipes::recipes_router(state.clone()),
+        )
+        .nest(
+            "/tags",
+            toi_server::routes::tags::tags_router(state.clone()),
+        );
+    let (router, _) = openapi_router.split_for_parts();
+    let listener = TcpListener::bind(&state.server_config.bind_addr).await?;
+
+    // Spawn server and create a client for all test requests.
+    let _ = tokio::spawn(async move { axum::serve(listener, router).await });
+    let client = reqwest::Client::new();
+    let tags_url = format!("http://{}/tags", state.server_config.bind_addr);
+    let recipes_url = format!("http://{}/recipes", state.server_config.bind_addr);
+
+    // Make tags, and then make recipes with shared and disjoint tags.
+    for name in ["asian", "rice", "italian"] {
+        let body = NewTagRequest::builder().name(name.to_string()).build();
+        let response = client.post(&tags_url).json(&body).send().await?;
+        utils::assert_ok_response(response).await?;
+    }
+    let mut ids = vec![];
+    for (description, tags) in [
+        ("steamed jasmine rice", vec!["asian", "rice"]),
+        ("miso soup", vec!["asian"]),
+        ("spaghetti carbonara", vec!["italian"]),
+    ] {
+        let body = NewRecipeRequest::builder()
+            .description(description.to_string())
+            .ingredients(description.to_string())
+            .instructions("1. cook".to_string())
+            .tags(tags.into_iter().map(str::to_string).collect())
+            .build();
+        let response = client.post(&recipes_url).json(&body).send().await?;
+        let response = utils::assert_ok_response(response).await?;
+        ids.push(response.json::<Recipe>().await?.id);
+    }
+    let expected_tags = |id: i32| -> Vec<&str> {
+        match ids.iter().position(|other| *other == id) {
+            Some(0) => vec!["asian", "rice"],
+            Some(1) => vec!["asian"],
+            _ => vec!["italian"],
+        }
+    };
+
+    // Recipes come with all their tags when they're asked for.
+    let params = RecipeSearchParams::builder()
+        .ids(ids.clone())
+        .include_tags(true)
+        .build();
+    let response = client
+        .post(format!("{recipes_url}/search"))
+        .json(&params)
+        .send()
+        .await?;
+    let response = utils::assert_ok_response(response).await?;
+    let recipes = response.json::<Page<RecipeWithTags>>().await?.items;
+    assert_eq!(recipes.len(), 3);
+    for recipe in recipes {
+        let tags: Vec<String> = recipe
+            .tags
+            .expect("tags should be included")
+            .into_iter()
+            .map(|tag| tag.name)
+            .collect();
+        assert_eq!(tags, expected_tags(recipe.recipe.id));
+    }
+
+    // So do recipe previews.
+    let response = client
+        .post(format!("{recipes_url}/previews/search"))
+        .json(&params)
+        .send()
+        .await?;
+    let response = utils::assert_ok_response(response).await?;
+    let recipe_previews = response.json::<Page<RecipePreviewWithTags>>().await?.items;
+    assert_eq!(recipe_previews.len(), 3);
+    for recipe_preview in recipe_previews {
+        let tags: Vec<String> = recipe_preview
+            .tags
+            .expect("tags should be included")
+            .into_iter()
+            .map(|tag| tag.name)
+            .collect();
+        assert_eq!(tags, expected_tags(recipe_preview.recipe_preview.id));
+    }
+
+    // Recipes keep their flat shape when tags aren't asked for.
+    let params = RecipeSearchParams::builder().ids(ids).build();
+    let response = client
+        .post(format!("{recipes_url}/search"))
+        .json(&params)
+        .send()
+        .await?;
+    let response = utils::assert_ok_response(response).await?;
+    let page = response.json::<Value>().await?;
+    let items = page["items"].as_array().expect("items should be an array");
+    assert_eq!(items.len(), 3);
+    assert!(items.iter().all(|item| item.get("tags").is_none()));
+    Ok(())
+}
+
+#[tokio::test]
+#[serial]
+async fn recipe_previews_detailed() -> Result<(), Box<dyn std::error::Error>> {
+    // Make sure there's a database URL and it points to a test database so
+    // prod isn't goofed during testing.
+    let db_connection_url = dotenvy::var("DATABASE_URL")?;
+    utils::reset_database(&db_connection_url)?;
+
+    // Initialize the server state.
+    let state = toi_server::init(db_connection_url).await?;
+    let openapi_router = OpenApiRouter::new()
+        .nest(
+            "/recipes",
+            toi_server::routes::recipes::recipes_router(state.clone()),
+        )
+        .nest(
+            "/tags",
+            toi_server::routes::tags::tags_router(state.clone()),
+        );
+    let (router, _) = openapi_router.split_for_parts();
+    let listener = TcpListener::bind(&state.server_config.bind_addr).await?;
+
+    // Spawn server and create a client for all test requests.
+    let _ = tokio::spawn(async move { axum::serve(listener, router).await });
+    let client = reqwest::Client::new();
+    let tags_url = format!("http://{}/tags", state.server_config.bind_addr);
+    let recipes_url = format!("http://{}/recipes", state.server_config.bind_addr);
+
+    // Make a recipe with a couple of tags and a recipe without any.
+    for name in ["rice", "asian"] {
+        let body = NewTagRequest::builder().name(name.to_string()).build();
+        let response = client.post(&tags_url).json(&body).send().await?;
+        utils::assert_ok_response(response).await?;
+    }
+    let mut ids = vec![];
+    for (description, ingredients, tags) in [
+        (
+            "steamed jasmine rice",
+            "2 cups jasmine rice\n\n3 cups water\n",
+            vec!["rice", "asian"],
+        ),
+        ("buttered toast", "1 slice bread", vec![]),
+    ] {
+        let body = NewRecipeRequest::builder()
+            .description(description.to_string())
+            .ingredients(ingredients.to_string())
+            .instructions("1. cook".to_string())
+            .tags(tags.into_iter().map(str::to_string).collect())
+            .build();
+        let response = client.post(&recipes_url).json(&body).send().await?;
+        let response = utils::assert_ok_response(response).await?;
+        ids.push(response.json::<Recipe>().await?.id);
+    }
+
+    // Detailed previews have their tag names grouped together and count
+    // their ingredient lines, including recipes without tags.
+    let params = RecipeSearchParams::builder().detailed(true).build();
+    let response = client
+        .post(format!("{recipes_url}/previews/search"))
+        .json(&params)
+        .send()
+        .await?;
+    let response = utils::assert_ok_response(response).await?;
+    let page = response.json::<Page<RecipePreviewDetailed>>().await?;
+    assert_eq!(page.total, 2);
+    let mut recipe_previews = page.items;
+    recipe_previews.sort_by_key(|recipe_preview| recipe_preview.recipe_preview.id);
+    let details: Vec<(i32, Vec<&str>, i64)> = recipe_previews
+        .iter()
+        .map(|recipe_preview| {
+            (
+                recipe_preview.recipe_preview.id,
+                recipe_preview
+                    .tag_names
+                    .iter()
+                    .map(String::as_str)
+                    .collect(),
+                recipe_preview.ingredient_count,
+            )
+        })
+        .collect();
+    assert_eq!(
+        details,
+        vec![(ids[0], vec!["asian", "rice"], 2), (ids[1], vec![], 1)]
+    );
+
+    // Previews keep their lean shape by default.
+    let params = RecipeSearchParams::builder().ids(ids).build();
+    let response = client
+        .post(format!("{recipes_url}/previews/search"))
+        .json(&params)
+        .send()
+        .await?;
+    let response = utils::assert_ok_response(response).await?;
+    let page = response.json::<Value>().await?;
+    let items = page["items"].as_array().expect("items should be an array");
+    assert_eq!(items.len(), 2);
+    assert!(
+        items
+            .iter()
+            .all(|item| item.get("tag_names").is_none() && item.get("ingredient_count").is_none())
+    );
+    Ok(())
+}
+
+#[tokio::test]
+#[serial]
+async fn scale_recipe_route() -> Result<(), Box<dyn std::error::Error>> {
+    // Make sure there's a database URL and it points to a test database so
+    // prod isn't goofed during testing.
+    let db_connection_url = dotenvy::var("DATABASE_URL")?;
+    utils::reset_database(&db_connection_url)?;
+
+    // Initialize the server state.
+    let state = toi_server::init(db_connection_url).await?;
+    let openapi_router = OpenApiRouter::new()
+        .nest(
+            "/recipes",
+            toi_server::routes::recipes::recipes_router(state.clone()),
+        )
+        .nest(
+            "/tags",
+            toi_server::routes::tags::tags_router(state.clone()),
+        );
+    let (router, _) = openapi_router.split_for_parts();
+    let listener = TcpListener::bind(&state.server_config.bind_addr).await?;
+
+    // Spawn server and create a client for all test requests.
+    let _ = tokio::spawn(async move { axum::serve(listener, router).await });
+    let client = reqwest::Client::new();
+    let tags_url = format!("http://{}/tags", state.server_config.bind_addr);
+    let recipes_url = format!("http://{}/recipes", state.server_config.bind_addr);
+    let scale_recipe_url = format!("{recipes_url}/scale");
+
+    // Make a recipe.
+    let body = NewTagRequest::builder().name("italian".to_string()).build();
+    let response = client.post(&tags_url).json(&body).send().await?;
+    utils::assert_ok_response(response).await?;
+    let body = NewRecipeRequest::builder()
+        .description("lasagna".to_string())
+        .ingredients("2 cups ricotta, 1 lb ground beef, 12 lasagna noodles".to_string())
+        .instructions("1. brown beef, 2. layer noodles, beef, and ricotta, 3. bake".to_string())
+        .tags(vec!["italian".to_string()])
+        .build();
+    let response = client.post(&recipes_url).json(&body).send().await?;
+    let response = utils::assert_ok_response(response).await?;
+    let recipe = response.json::<Recipe>().await?;
+
+    // Scaling by a nonsense factor is rejected.
+    let body = RecipeScaleRequest::builder()
+        .recipe_id(recipe.id)
+        .factor(0.0)
+        .build();
+    let response = client.post(&scale_recipe_url).json(&body).send().await?;
+    assert_eq!(response.status(), reqwest::StatusCode::BAD_REQUEST);
+
+    // Scaling without saving leaves the stored recipe alone.
+    let body = RecipeScaleRequest::builder()
+        .recipe_query("lasagna".to_string())
+        .factor(2.0)
+        .build();
+    let response = client.post(&scale_recipe_url).json(&body).send().await?;
+    let response = utils::assert_ok_response(response).await?;
+    let scaled_recipe = response.json::<ScaledRecipe>().await?;
+    assert_eq!(scaled_recipe.recipe, recipe);
+    assert_eq!(scaled_recipe.original_ingredients, recipe.ingredients);
+    assert!(!scaled_recipe.scaled_ingredients.is_empty());
+
+    // Saving replaces the stored recipe's ingredients.
+    let body = RecipeScaleRequest::builder()
+        .recipe_id(recipe.id)
+        .factor(2.0)
+        .save(true)
+        .build();
+    let response = client.post(&scale_recipe_url).json(&body).send().await?;
+    let response = utils::assert_ok_response(response).await?;
+    let scaled_recipe = response.json::<ScaledRecipe>().await?;
+    assert_eq!(scaled_recipe.recipe.id, recipe.id);
+    assert_eq!(
+        scaled_recipe.recipe.ingredients,
+        scaled_recipe.scaled_ingredients
+    );
+    assert_eq!(scaled_recipe.original_ingredients, recipe.ingredients);
+    Ok(())
+}
+
+#[tokio::test]
+#[serial]
+async fn recipe_tags_resolved_concurrently() -> Result<(), Box<dyn std::error::Error>> {
+    // Make sure there's a database URL and it points to a test database so
+    // prod isn't goofed during testing.
+    let db_connection_url = dotenvy::var("DATABASE_URL")?;
+    utils::reset_database(&db_connection_url)?;
+
+    // Spawn a slow mock embedding API that tracks overlapping requests.
+    let in_flight = InFlight::default();
+    let mock_router = axum::Router::new()
+        .route("/v1/embeddings", post(slow_tag_embeddings))
+        .with_state(in_flight.clone());
+    let mock_listener = TcpListener::bind("127.0.0.1:0").await?;
+    let mock_addr = mock_listener.local_addr()?;
+    let _ = tokio::spawn(async move { axum::serve(mock_listener, mock_router).await });
+
+    // Initialize the server state, pointing embedding at the mock API.
+    let mut state = toi_server::init(db_connection_url).await?;
+    state.model_client.embedding_api_config.base_url = format!("http://{mock_addr}");
+    let openapi_router = OpenApiRouter::new()
+        .nest(
+            "/recipes",
+            toi_server::routes::recipes::recipes_router(state.clone()),
+        )
+        .nest(
+            "/tags",
+            toi_server::routes::tags::tags_router(state.clone()),
+        );
+    let (router, _) = openapi_router.split_for_parts();
+    let listener = TcpListener::bind(&state.server_config.bind_addr).await?;
+
+    // Spawn server and create a client for all test requests.
+    let _ = tokio::spawn(async move { axum::serve(listener, router).await });
+    let client = reqwest::Client::new();
+    let tags_url = format!("http://{}/tags", state.server_config.bind_addr);
+    let recipes_url = format!("http://{}/recipes", state.server_config.bind_addr);
+    for name in ["asian", "rice", "italian"] {
+        let body = NewTagRequest::builder().name(name.to_string()).build();
+        let response = client.post(&tags_url).json(&body).send().await?;
+        utils::assert_ok_response(response).await?;
+    }
+
+    // Tags are resolved concurrently, and repeated tags are only added once.
+    in_flight.max.store(0, Ordering::SeqCst);
+    let body = NewRecipeRequest::builder()
+        .description("fried rice".to_string())
+        .ingredients("rice".to_string())
+        .instructions("1. fry rice".to_string())
+        .tags(
+            ["asian", "rice", "italian", "asian"]
+                .into_iter()
+                .map(str::to_string)
+                .collect(),
+        )
+        .build();
+    let response = client.post(&recipes_url).json(&body).send().await?;
+    let response = utils::assert_ok_response(response).await?;
+    let recipe = response.json::<Recipe>().await?;
+    assert!(in_flight.max.load(Ordering::SeqCst) > 1);
+    let params = RecipeSearchParams::builder()
+        .ids(vec![recipe.id])
+        .include_tags(true)
+        .build();
+    let response = client
+        .post(format!("{recipes_url}/search"))
+        .json(&params)
+        .send()
+        .await?;
+    let response = utils::assert_ok_response(response).await?;
+    let recipes = response.json::<Page<RecipeWithTags>>().await?.items;
+    let recipe = recipes.into_iter().next().expect("recipe should be found");
+    let mut tags: Vec<String> = recipe
+        .tags
+        .expect("tags should be included")
+        .into_iter()
+        .map(|tag| tag.name)
+        .collect();
+    tags.sort();
+    assert_eq!(tags, vec!["asian", "italian", "rice"]);
+
+    // A tag that doesn't match is named in the error.
+    let body = NewRecipeRequest::builder()
+        .description("tiramisu".to_string())
+        .ingredients("mascarpone".to_string())
+        .instructions("1. layer".to_string())
+        .tags(vec!["italian".to_string(), "xylophone".to_string()])
+        .build();
+    let response = client.post(&recipes_url).json(&body).send().await?;
+    assert_eq!(response.status(), reqwest::StatusCode::NOT_FOUND);
+    assert!(response.text().await?.contains("xylophone"));
+    Ok(())
+}
+
+#[tokio::test]
+#[serial]
+async fn recipes_delete_all_guard() -> Result<(), Box<dyn std::error::Error>> {
+    // Make sure there's a database URL and it points to a test database so
+    // prod isn't goofed during testing.
+    let db_connection_url = dotenvy::var("DATABASE_URL")?;
+    utils::reset_database(&db_connection_url)?;
+
+    // Initialize the server state.
+    let state = toi_server::init(db_connection_url).await?;
+    let openapi_router = OpenApiRouter::new().nest(
+        "/recipes",
+        toi_server::routes::recipes::recipes_router(state.clone()),
+    );
+    let (router, _) = openapi_router.split_for_parts();
+    let listener = TcpListener::bind(&state.server_config.bind_addr).await?;
+
+    // Spawn server and create a client for all test requests.
+    let _ = tokio::spawn(async move { axum::serve(listener, router).await });
+    let client = reqwest::Client::new();
+    let recipes_url = format!("http://{}/recipes", state.server_config.bind_addr);
+    let body = NewRecipeRequest::builder()
+        .description("steamed jasmine rice".to_string())
+        .ingredients("jasmine rice".to_string())
+        .instructions("1. wash rice, 2. cook rice".to_string())
+        .tags(vec![])
+        .build();
+    let response = client.post(&recipes_url).json(&body).send().await?;
+    utils::assert_ok_response(response).await?;
+
+    // Neither recipes nor their previews are deleted in bulk without
+    // confirmation.
+    let params = RecipeSearchParams::builder().build();
+    for path in ["delete", "previews/delete"] {
+        let response = client
+            .post(format!("{recipes_url}/{path}"))
+            .json(&params)
+            .send()
+            .await?;
+        assert_eq!(response.status(), reqwest::StatusCode::BAD_REQUEST);
+    }
+
+    // Confirmed deletes go through.
+    let params = DeleteParams::builder()
+        .params(RecipeSearchParams::builder().build())
+        .confirm_delete_all(true)
+        .build();
+    let response = client
+        .post(format!("{recipes_url}/previews/delete"))
+        .json(&params)
+        .send()
+        .await?;
+    let response = utils::assert_ok_response(response).await?;
+    assert_eq!(response.json::<Vec<RecipePreview>>().await?.len(), 1);
+    Ok(())
+}
+
+#[tokio::test]
+#[serial]
+async fn shopping_list_route() -> Result<(), Box<dyn std::error::Error>> {
+    // Make sure there's a database URL and it points to a test database so
+    // prod isn't goofed during testing.
+    let db_connection_url = dotenvy::var("DATABASE_URL")?;
+    utils::reset_database(&db_connection_url)?;
+
+    // Spawn mock embedding and generation APIs.
+    let mock_router = axum::Router::new()
+        .route("/v1/embeddings", post(utils::mock_embeddings))
+        .route("/v1/chat/completions", post(shopping_list_completions));
+    let mock_listener = TcpListener::bind("127.0.0.1:0").await?;
+    let mock_addr = mock_listener.local_addr()?;
+    let _ = tokio::spawn(async move { axum::serve(mock_listener, mock_router).await });
+
+    // Initialize the server state, pointing embedding and generation at the
+    // mock APIs.
+    let mut state = toi_server::init(db_connection_url).await?;
+    state.model_client.embedding_api_config.base_url = format!("http://{mock_addr}");
+    state.model_client.generation_api_config.base_url = format!("http://{mock_addr}");
+    let openapi_router = OpenApiRouter::new()
+        .nest(
+            "/recipes",
+            toi_server::routes::recipes::recipes_router(state.clone()),
+        )
+        .nest(
+            "/tags",
+            toi_server::routes::tags::tags_router(state.clone()),
+        );
+    let (router, _) = openapi_router.split_for_parts();
+    let listener = TcpListener::bind(&state.server_config.bind_addr).await?;
+
+    // Spawn server and create a client for all test requests.
+    let _ = tokio::spawn(async move { axum::serve(listener, router).await });
+    let client = reqwest::Client::new();
+    let tags_url = format!("http://{}/tags", state.server_config.bind_addr);
+    let recipes_url = format!("http://{}/recipes", state.server_config.bind_addr);
+    let shopping_list_url = format!("{recipes_url}/shopping-list");
+
+    // Make two recipes that share ingredients.
+    let body = NewTagRequest::builder().name("italian".to_string()).build();
+    let response = client.post(&tags_url).json(&body).send().await?;
+    utils::assert_ok_response(response).await?;
+    let mut recipe_ids = vec![];
+    for (description, ingredients) in [
+        (
+            "lasagna",
+            "2 cups ricotta, 1 cup mozzarella, 12 lasagna noodles",
+        ),
+        (
+            "spinach lasagna",
+            "1 cup ricotta, 1 cup mozzarella, 2 cups spinach, 12 lasagna noodles",
+        ),
+    ] {
+        let body = NewRecipeRequest::builder()
+            .description(description.to_string())
+            .ingredients(ingredients.to_string())
+            .instructions("1. layer everything, 2. bake".to_string())
+            .tags(vec!["italian".to_string()])
+            .build();
+        let response = client.post(&recipes_url).json(&body).send().await?;
+        let response = utils::assert_ok_response(response).await?;
+        recipe_ids.push(response.json::<Recipe>().await?.id);
+    }
+
+    // Shopping for every recipe without saying which is rejected, and so
+    // are nonsense servings.
+    let body = ShoppingListRequest::builder()
+        .params(RecipeSearchParams::builder().build())
+        .build();
+    let response = client.post(&shopping_list_url).json(&body).send().await?;
+    assert_eq!(response.status(), reqwest::StatusCode::BAD_REQUEST);
+    let body = ShoppingListRequest::builder()
+        .params(
+            RecipeSearchParams::builder()
+                .ids(recipe_ids.clone())
+                .build(),
+        )
+        .servings(vec![
+            RecipeServings::builder()
+                .recipe_id(recipe_ids[0])
+                .factor(-1.0)
+                .build(),
+        ])
+        .build();
+    let response = client.post(&shopping_list_url).json(&body).send().await?;
+    assert_eq!(response.status(), reqwest::StatusCode::BAD_REQUEST);
+
+    // Shared ingredients are merged and grouped by category, and no todos
+    // are made unless asked for.
+    let body = ShoppingListRequest::builder()
+        .params(
+            RecipeSearchParams::builder()
+                .ids(recipe_ids.clone())
+                .build(),
+        )
+        .build();
+    let response = client.post(&shopping_list_url).json(&body).send().await?;
+    let response = utils::assert_ok_response(response).await?;
+    let shopping_list = response.json::<ShoppingList>().await?;
+    let mut ids: Vec<i32> = shopping_list
+        .recipes
+        .iter()
+        .map(|recipe| recipe.id)
+        .collect();
+    ids.sort_unstable();
+    assert_eq!(ids, recipe_ids);
+    let categories: Vec<ShoppingCategory> = shopping_list
+        .categories
+        .iter()
+        .map(|category| category.category)
+        .collect();
+    assert_eq!(
+        categories,
+        vec![
+            ShoppingCategory::Produce,
+            ShoppingCategory::Dairy,
+            ShoppingCategory::Pantry
+        ]
+    );
+    assert_eq!(
+        shopping_list.categories[1].items,
+        vec![
+            "3 cups ricotta".to_string(),
+            "2 cups mozzarella".to_string()
+        ]
+    );
+    assert!(shopping_list.todos.is_empty());
+
+    // Each item becomes a due todo when asked for.
+    let due_at = chrono::Utc::now();
+    let body = ShoppingListRequest::builder()
+        .params(
+            RecipeSearchParams::builder()
+                .ids(recipe_ids.clone())
+                .build(),
+        )
+        .create_todos(true)
+        .due_at(due_at)
+        .build();
+    let response = client.post(&shopping_list_url).json(&body).send().await?;
+    let response = utils::assert_ok_response(response).await?;
+    let shopping_list = response.json::<ShoppingList>().await?;
+    let items: Vec<&str> = shopping_list
+        .todos
+        .iter()
+        .map(|todo| todo.item.as_str())
+        .collect();
+    assert_eq!(
+        items,
+        vec![
+            "Buy 2 cups spinach",
+            "Buy 3 cups ricotta",
+            "Buy 2 cups mozzarella",
+            "Buy 24 lasagna noodles"
+        ]
+    );
+    assert!(shopping_list.todos.iter().all(|todo| {
+        todo.due_at
+            .is_some_and(|at| (at - due_at).num_seconds() == 0)
+    }));
     Ok(())
 }
@@ -0,0 +1,127 @@
+use chrono::{Duration, Utc};
+use serial_test::serial;
+use tokio::net::TcpListener;
+use utoipa_axum::router::OpenApiRouter;
+
+use toi_server::models::{
+    events::{Event, NewEventRequest, RecurrenceFrequency},
+    reminders::{Reminder, ReminderKind, ReminderParams},
+    todos::{NewTodoRequest, Todo},
+};
+
+mod utils;
+
+#[tokio::test]
+#[serial]
+async fn reminders_routes() -> Result<(), Box<dyn std::error::Error>> {
+    // Make sure there's a database URL and it points to a test database so
+    // prod isn't goofed during testing.
+    let db_connection_url = dotenvy::var("DATABASE_URL")?;
+    utils::reset_database(&db_connection_url)?;
+
+    // Initialize the server state.
+    let state = toi_server::init(db_connection_url).await?;
+    let openapi_router = OpenApiRouter::new()
+        .nest(
+            "/events",
+            toi_server::routes::events::events_router(state.clone()),
+        )
+        .nest(
+            "/reminders",
+            toi_server::routes::reminders::reminders_router(state.clone()),
+        )
+        .nest(
+            "/todos",
+            toi_server::routes::todos::todos_router(state.clone()),
+        );
+    let (router, _) = openapi_router.split_for_parts();
+    let listener = TcpListener::bind(&state.server_config.bind_addr).await?;
+
+    // Spawn server and create a client for all test requests.
+    let _ = tokio::spawn(async move { axum::serve(listener, router).await });
+    let client = reqwest::Client::new();
+    let base_url = format!("http://{}", state.server_config.bind_addr);
+    let now = Utc::now();
+
+    // Make todos due soon, due later, and already completed.
+    let mut todos = vec![];
+    for (item, due_in, completed) in [
+        ("pay rent", Duration::hours(3), false),
+        ("renew passport", Duration::days(30), false),
+        ("buy milk", Duration::hours(1), true),
+    ] {
+        let body = NewTodoRequest::builder()
+            .item(item.to_string())
+            .due_at(now + due_in)
+            .maybe_completed_at(completed.then_some(now))
+            .build();
+        let response = client
+            .post(format!("{base_url}/todos"))
+            .json(&body)
+            .send()
+            .await?;
+        let response = utils::assert_ok_response(response).await?;
+        todos.push(response.json::<Todo>().await?);
+    }
+
+    // Make an event starting soon, and a weekly event that started a few
+    // weeks ago and happens again soon.
+    let mut events = vec![];
+    for (description, starts_in, recurrence_frequency) in [
+        ("dentist appointment", Duration::hours(5), None),
+        (
+            "book club",
+            Duration::hours(2) - Duration::weeks(3),
+            Some(RecurrenceFrequency::Weekly),
+        ),
+    ] {
+        let body = NewEventRequest::builder()
+            .description(description.to_string())
+            .starts_at(now + starts_in)
+            .ends_at(now + starts_in + Duration::hours(1))
+            .maybe_recurrence_frequency(recurrence_frequency)
+            .build();
+        let response = client
+            .post(format!("{base_url}/events"))
+            .json(&body)
+            .send()
+            .await?;
+        let response = utils::assert_ok_response(response).await?;
+        events.push(response.json::<Event>().await?);
+    }
+
+    // Only incomplete todos and events within the next day are included,
+    // soonest first.
+    let params = ReminderParams::builder().build();
+    let response = client
+        .get(format!("{base_url}/reminders"))
+        .query(&params)
+        .send()
+        .await?;
+    let response = utils::assert_ok_response(response).await?;
+    let reminders = response.json::<Vec<Reminder>>().await?;
+    let reminders: Vec<(ReminderKind, i32)> = reminders
+        .into_iter()
+        .map(|reminder| (reminder.kind, reminder.id))
+        .collect();
+    assert_eq!(
+        reminders,
+        vec![
+            (ReminderKind::Event, events[1].id),
+            (ReminderKind::Todo, todos[0].id),
+            (ReminderKind::Event, events[0].id),
+        ]
+    );
+
+    // Narrowing the window drops later items.
+    let params = ReminderParams::builder().within_hours(4).build();
+    let response = client
+        .get(format!("{base_url}/reminders"))
+        .query(&params)
+        .send()
+        .await?;
+    let response = utils::assert_ok_response(response).await?;
+    let reminders = response.json::<Vec<Reminder>>().await?;
+    assert_eq!(reminders.len(), 2);
+    Ok(())
+}
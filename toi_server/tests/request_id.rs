@@ -0,0 +1,60 @@
+use serial_test::serial;
+use tokio::net::TcpListener;
+use utoipa_axum::router::OpenApiRouter;
+use uuid::Uuid;
+
+use toi_server::request_id::{PARENT_REQUEST_HEADER, REQUEST_ID_HEADER};
+
+mod utils;
+
+fn request_id(response: &reqwest::Response) -> Option<Uuid> {
+    response
+        .headers()
+        .get(&REQUEST_ID_HEADER)
+        .and_then(|request_id| request_id.to_str().ok())
+        .and_then(|request_id| request_id.parse().ok())
+}
+
+#[tokio::test]
+#[serial]
+async fn request_ids() -> Result<(), Box<dyn std::error::Error>> {
+    // Make sure there's a database URL and it points to a test database so
+    // prod isn't goofed during testing.
+    let db_connection_url = dotenvy::var("DATABASE_URL")?;
+    utils::reset_database(&db_connection_url)?;
+
+    // Initialize the server state.
+    let state = toi_server::init(db_connection_url).await?;
+    let openapi_router =
+        OpenApiRouter::new().merge(toi_server::routes::health::health_router(state.clone()));
+    let (router, _) = openapi_router.split_for_parts();
+    let router = router.layer(axum::middleware::from_fn(
+        toi_server::request_id::request_id,
+    ));
+    let listener = TcpListener::bind(&state.server_config.bind_addr).await?;
+
+    // Spawn server and create a client for all test requests.
+    let _ = tokio::spawn(async move { axum::serve(listener, router).await });
+    let client = reqwest::Client::new();
+    let health_url = format!("http://{}/health", state.server_config.bind_addr);
+
+    // Each request gets its own ID.
+    let response = client.get(&health_url).send().await?;
+    let response = utils::assert_ok_response(response).await?;
+    let parent_request_id = request_id(&response).expect("response should have a request ID");
+    let response = client.get(&health_url).send().await?;
+    let response = utils::assert_ok_response(response).await?;
+    let other_request_id = request_id(&response).expect("response should have a request ID");
+    assert_ne!(parent_request_id, other_request_id);
+
+    // Requests made by other requests still get their own ID.
+    let response = client
+        .get(&health_url)
+        .header(&PARENT_REQUEST_HEADER, parent_request_id.to_string())
+        .send()
+        .await?;
+    let response = utils::assert_ok_response(response).await?;
+    let child_request_id = request_id(&response).expect("response should have a request ID");
+    assert_ne!(child_request_id, parent_request_id);
+    Ok(())
+}
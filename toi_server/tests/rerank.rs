@@ -0,0 +1,169 @@
+use axum::{Json, extract::State, routing::post};
+use serde_json::{Value, json};
+use serial_test::serial;
+use std::sync::{Arc, Mutex};
+use tokio::net::TcpListener;
+use utoipa_axum::router::OpenApiRouter;
+
+use toi_server::models::{
+    notes::{NewNoteRequest, Note, NoteSearchParams},
+    pagination::Page,
+};
+
+mod utils;
+
+/// Embed inputs so the more exclamation marks they have, the further they
+/// are from queries without any.
+fn mock_embedding(input: &str) -> Vec<f32> {
+    let marks = input.matches('!').count();
+    vec![1.0, 0.1 * f32::from(u8::try_from(marks).unwrap_or(u8::MAX))]
+}
+
+/// Rerank documents mentioning filters first, keeping track of the
+/// documents in each request.
+async fn mock_rerank(
+    State(requests): State<Arc<Mutex<Vec<Vec<String>>>>>,
+    Json(request): Json<Value>,
+) -> Json<Value> {
+    let documents: Vec<String> = request["documents"]
+        .as_array()
+        .cloned()
+        .unwrap_or_default()
+        .iter()
+        .map(|document| document.as_str().unwrap_or_default().to_string())
+        .collect();
+    let mut results: Vec<Value> = documents
+        .iter()
+        .enumerate()
+        .map(|(index, text)| {
+            let relevance_score = if text.contains("filter") { 0.99 } else { 0.6 };
+            json!({"index": index, "document": {"text": text}, "relevance_score": relevance_score})
+        })
+        .collect();
+    results.sort_by(|a, b| {
+        b["relevance_score"]
+            .as_f64()
+            .partial_cmp(&a["relevance_score"].as_f64())
+            .expect("scores should be comparable")
+    });
+    requests
+        .lock()
+        .expect("requests shouldn't be poisoned")
+        .push(documents);
+    Json(json!({"results": results}))
+}
+
+#[tokio::test]
+#[serial]
+async fn rerank_limits() -> Result<(), Box<dyn std::error::Error>> {
+    // Make sure there's a database URL and it points to a test database so
+    // prod isn't goofed during testing.
+    let db_connection_url = dotenvy::var("DATABASE_URL")?;
+    utils::reset_database(&db_connection_url)?;
+
+    // Spawn mock embedding and reranking APIs.
+    let requests = Arc::new(Mutex::new(vec![]));
+    let mock_router = axum::Router::new()
+        .route(
+            "/v1/embeddings",
+            utils::mock_embeddings_with(mock_embedding),
+        )
+        .route("/v1/rerank", post(mock_rerank))
+        .with_state(requests.clone());
+    let mock_listener = TcpListener::bind("127.0.0.1:0").await?;
+    let mock_addr = mock_listener.local_addr()?;
+    let _ = tokio::spawn(async move { axum::serve(mock_listener, mock_router).await });
+
+    // Initialize the server state so only a couple of short documents are
+    // reranked and notes aren't chunked, pointing the model APIs at the mocks.
+    let mut state = toi_server::init(db_connection_url).await?;
+    state.server_config.note_chunk_chars = 10_000;
+    state.server_config.rerank_max_documents = 2;
+    state.server_config.rerank_max_document_chars = 100;
+    let mock_url = format!("http://{mock_addr}");
+    state.model_client.embedding_api_config.base_url = mock_url.clone();
+    state.model_client.reranking_api_config.base_url = mock_url;
+    let openapi_router = OpenApiRouter::new().nest(
+        "/notes",
+        toi_server::routes::notes::notes_router(state.clone()),
+    );
+    let (router, _) = openapi_router.split_for_parts();
+    let listener = TcpListener::bind(&state.server_config.bind_addr).await?;
+
+    // Spawn server and create a client for all test requests.
+    let _ = tokio::spawn(async move { axum::serve(listener, router).await });
+    let client = reqwest::Client::new();
+    let notes_url = format!("http://{}/notes", state.server_config.bind_addr);
+
+    // Make an oversized note and a couple of others that are further from
+    // the query.
+    let oversized_content = format!("Oil change!{}", " and then some".repeat(500));
+    let contents = [
+        oversized_content.clone(),
+        "Oil filter!!".to_string(),
+        "Oil pan!!!".to_string(),
+    ];
+    let mut notes = vec![];
+    for content in contents {
+        let body = NewNoteRequest::builder().content(content).build();
+        let response = client.post(&notes_url).json(&body).send().await?;
+        let response = utils::assert_ok_response(response).await?;
+        notes.push(response.json::<Note>().await?);
+    }
+
+    // Only the two closest notes are reranked, and their results still map
+    // back to the right notes.
+    let params = NoteSearchParams::builder()
+        .query("oil".to_string())
+        .use_reranking_filter(true)
+        .build();
+    let response = client
+        .post(format!("{notes_url}/search"))
+        .json(&params)
+        .send()
+        .await?;
+    let response = utils::assert_ok_response(response).await?;
+    let ids: Vec<i32> = response
+        .json::<Page<Note>>()
+        .await?
+        .items
+        .into_iter()
+        .map(|note| note.id)
+        .collect();
+    assert_eq!(ids, vec![notes[1].id, notes[0].id]);
+
+    // The oversized note was cut down to its first characters and marked
+    // as cut.
+    let requests = requests.lock().expect("requests shouldn't be poisoned");
+    assert_eq!(requests.len(), 1);
+    let documents = &requests[0];
+    assert_eq!(documents.len(), 2);
+    let truncated = documents[0]
+        .strip_suffix('…')
+        .expect("cut documents should be marked");
+    assert_eq!(truncated.chars().count(), 100);
+    assert!(oversized_content.starts_with(truncated));
+    assert_eq!(documents[1], "Oil filter!!");
+    drop(requests);
+
+    // Notes that are ordered some other way are still capped to the two
+    // closest ones.
+    let params = json!({
+        "query": "oil",
+        "use_reranking_filter": true,
+        "order_by": "Newest",
+    });
+    let response = client
+        .post(format!("{notes_url}/search"))
+        .json(&params)
+        .send()
+        .await?;
+    utils::assert_ok_response(response).await?;
+    let requests = requests.lock().expect("requests shouldn't be poisoned");
+    assert_eq!(requests.len(), 2);
+    let documents = &requests[1];
+    assert_eq!(documents.len(), 2);
+    assert!(documents[0].starts_with("Oil change!"));
+    assert_eq!(documents[1], "Oil filter!!");
+    Ok(())
+}
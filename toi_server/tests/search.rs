@@ -0,0 +1,140 @@
+use std::str::FromStr;
+
+use chrono::DateTime;
+use serial_test::serial;
+use tokio::net::TcpListener;
+use utoipa_axum::router::OpenApiRouter;
+
+use toi_server::models::{
+    events::{Event, NewEventRequest},
+    notes::{NewNoteRequest, Note},
+    search::{SearchAllRequest, SearchAllResult, SearchDomain},
+    todos::{NewTodoRequest, Todo},
+};
+
+mod utils;
+
+#[tokio::test]
+#[serial]
+async fn search_all_routes() -> Result<(), Box<dyn std::error::Error>> {
+    // Make sure there's a database URL and it points to a test database so
+    // prod isn't goofed during testing.
+    let db_connection_url = dotenvy::var("DATABASE_URL")?;
+    utils::reset_database(&db_connection_url)?;
+
+    // Initialize the server state.
+    let state = toi_server::init(db_connection_url).await?;
+    let openapi_router = OpenApiRouter::new()
+        .nest(
+            "/events",
+            toi_server::routes::events::events_router(state.clone()),
+        )
+        .nest(
+            "/notes",
+            toi_server::routes::notes::notes_router(state.clone()),
+        )
+        .nest(
+            "/search",
+            toi_server::routes::search::search_router(state.clone()),
+        )
+        .nest(
+            "/todos",
+            toi_server::routes::todos::todos_router(state.clone()),
+        );
+    let (router, _) = openapi_router.split_for_parts();
+    let listener = TcpListener::bind(&state.server_config.bind_addr).await?;
+
+    // Spawn server and create a client for all test requests.
+    let _ = tokio::spawn(async move { axum::serve(listener, router).await });
+    let client = reqwest::Client::new();
+    let base_url = format!("http://{}", state.server_config.bind_addr);
+    let search_all_url = format!("{base_url}/search/all");
+
+    // Save things about the cabin trip as a note, a todo, and an event, along
+    // with a note about something else.
+    let body = NewNoteRequest::builder()
+        .content("The cabin trip gate code is 4321".to_string())
+        .build();
+    let response = client
+        .post(format!("{base_url}/notes"))
+        .json(&body)
+        .send()
+        .await?;
+    let response = utils::assert_ok_response(response).await?;
+    let note = response.json::<Note>().await?;
+    let body = NewNoteRequest::builder()
+        .content("My car takes OW-20 oil".to_string())
+        .build();
+    let response = client
+        .post(format!("{base_url}/notes"))
+        .json(&body)
+        .send()
+        .await?;
+    let response = utils::assert_ok_response(response).await?;
+    let other_note = response.json::<Note>().await?;
+    let body = NewTodoRequest::builder()
+        .item("Pack sleeping bags for the cabin trip".to_string())
+        .build();
+    let response = client
+        .post(format!("{base_url}/todos"))
+        .json(&body)
+        .send()
+        .await?;
+    let response = utils::assert_ok_response(response).await?;
+    let todo = response.json::<Todo>().await?;
+    let body = NewEventRequest::builder()
+        .description("Cabin trip with the family".to_string())
+        .starts_at(DateTime::from_str("2025-07-04T15:00:00+0000")?)
+        .ends_at(DateTime::from_str("2025-07-06T15:00:00+0000")?)
+        .build();
+    let response = client
+        .post(format!("{base_url}/events"))
+        .json(&body)
+        .send()
+        .await?;
+    let response = utils::assert_ok_response(response).await?;
+    let event = response.json::<Event>().await?;
+
+    // Searching everything finds the cabin trip in each domain, most
+    // relevant first, and leaves out the unrelated note.
+    let body = SearchAllRequest::builder()
+        .query("cabin trip".to_string())
+        .build();
+    let response = client.post(&search_all_url).json(&body).send().await?;
+    let response = utils::assert_ok_response(response).await?;
+    let results = response.json::<Vec<SearchAllResult>>().await?;
+    let found = |domain: SearchDomain, id: i32| {
+        results
+            .iter()
+            .any(|result| result.domain == domain && result.id == id)
+    };
+    assert!(found(SearchDomain::Notes, note.id));
+    assert!(found(SearchDomain::Todos, todo.id));
+    assert!(found(SearchDomain::Events, event.id));
+    assert!(!found(SearchDomain::Notes, other_note.id));
+    assert!(results.is_sorted_by(|a, b| a.score >= b.score));
+
+    // Searching some domains only returns items from them.
+    let body = SearchAllRequest::builder()
+        .query("cabin trip".to_string())
+        .domains(vec![SearchDomain::Todos])
+        .build();
+    let response = client.post(&search_all_url).json(&body).send().await?;
+    let response = utils::assert_ok_response(response).await?;
+    let results = response.json::<Vec<SearchAllResult>>().await?;
+    assert!(!results.is_empty());
+    assert!(
+        results
+            .iter()
+            .all(|result| result.domain == SearchDomain::Todos)
+    );
+
+    // Limits have to be positive.
+    let body = SearchAllRequest::builder()
+        .query("cabin trip".to_string())
+        .limit_per_domain(0)
+        .build();
+    let response = client.post(&search_all_url).json(&body).send().await?;
+    assert_eq!(response.status(), reqwest::StatusCode::BAD_REQUEST);
+    Ok(())
+}
@@ -0,0 +1,188 @@
+use axum::{Json, extract::State, routing::post};
+use reqwest::StatusCode;
+use serde_json::{Value, json};
+use serial_test::serial;
+use std::sync::{Arc, Mutex};
+use tokio::net::TcpListener;
+use utoipa_axum::router::OpenApiRouter;
+
+use toi_server::{
+    auth::DEFAULT_USER_ID,
+    models::{
+        notes::Note,
+        pagination::Page,
+        seeding::{SeedCount, SeedReport},
+    },
+};
+
+mod utils;
+
+/// Mock embedding API that embeds every input the same way, keeping track
+/// of how many inputs are in each request.
+async fn mock_embeddings(
+    State(batch_sizes): State<Arc<Mutex<Vec<usize>>>>,
+    Json(request): Json<Value>,
+) -> Json<Value> {
+    batch_sizes
+        .lock()
+        .expect("batch sizes shouldn't be poisoned")
+        .push(utils::embedding_inputs(&request).len());
+    utils::embedding_response(&request, |_| vec![1.0, 0.0, 0.0])
+}
+
+fn count(added: usize, skipped: usize) -> SeedCount {
+    SeedCount { added, skipped }
+}
+
+#[tokio::test]
+#[serial]
+async fn seeding_from_fixtures() -> Result<(), Box<dyn std::error::Error>> {
+    // Make sure there's a database URL and it points to a test database so
+    // prod isn't goofed during testing.
+    let db_connection_url = dotenvy::var("DATABASE_URL")?;
+    utils::reset_database(&db_connection_url)?;
+
+    // Spawn a mock embedding API.
+    let batch_sizes = Arc::new(Mutex::new(vec![]));
+    let mock_router = axum::Router::new()
+        .route("/v1/embeddings", post(mock_embeddings))
+        .with_state(batch_sizes.clone());
+    let mock_listener = TcpListener::bind("127.0.0.1:0").await?;
+    let mock_addr = mock_listener.local_addr()?;
+    let _ = tokio::spawn(async move { axum::serve(mock_listener, mock_router).await });
+
+    // Write seed files for every domain. One of the notes is repeated.
+    let seed_dir = std::env::temp_dir().join("toi_seeding_test");
+    let _ = std::fs::remove_dir_all(&seed_dir);
+    std::fs::create_dir_all(&seed_dir)?;
+    let write = |domain: &str, items: Value| {
+        std::fs::write(seed_dir.join(format!("{domain}.json")), items.to_string())
+    };
+    write(
+        "contacts",
+        json!([{"first_name": "Ada", "last_name": "Lovelace", "phone": "555 123 4567"}]),
+    )?;
+    write(
+        "events",
+        json!([{
+            "description": "Dentist appointment",
+            "starts_at": "2025-07-10T15:00:00Z",
+            "ends_at": "2025-07-10T16:00:00Z"
+        }]),
+    )?;
+    write(
+        "notes",
+        json!([
+            {"content": "My car takes OW-20 oil"},
+            {"content": "The wifi password is hunter2"},
+            {"content": "My car takes OW-20 oil"},
+            {"content": "The garage code is 1234"}
+        ]),
+    )?;
+    write(
+        "recipes",
+        json!([{
+            "description": "Pancakes",
+            "ingredients": "Flour\nEggs\nMilk",
+            "instructions": "Mix and fry",
+            "tags": []
+        }]),
+    )?;
+    write(
+        "todos",
+        json!([{"item": "Buy oil"}, {"item": "Call the dentist", "priority": 2}]),
+    )?;
+
+    // Initialize the server state with a small batch size so notes are
+    // embedded in more than one batch.
+    let mut state = toi_server::init(db_connection_url).await?;
+    state.server_config.max_batch_size = 2;
+    state.server_config.seed_data_path = Some(seed_dir.clone());
+    state.model_client.embedding_api_config.base_url = format!("http://{mock_addr}");
+    let openapi_router = OpenApiRouter::new()
+        .nest(
+            "/admin/seed",
+            toi_server::routes::seeding::seeding_router(state.clone()),
+        )
+        .nest(
+            "/notes",
+            toi_server::routes::notes::notes_router(state.clone()),
+        );
+    let (router, _) = openapi_router.split_for_parts();
+    let listener = TcpListener::bind(&state.server_config.bind_addr).await?;
+
+    // Spawn server and create a client for all test requests.
+    let _ = tokio::spawn(async move { axum::serve(listener, router).await });
+    let client = reqwest::Client::new();
+    let seed_url = format!("http://{}/admin/seed", state.server_config.bind_addr);
+    let notes_url = format!("http://{}/notes", state.server_config.bind_addr);
+
+    // Everything is added the first time except the repeated note, and
+    // items are embedded in batches rather than one at a time.
+    let report = toi_server::seeding::seed(&state, DEFAULT_USER_ID).await?;
+    assert_eq!(
+        report,
+        SeedReport {
+            contacts: count(1, 0),
+            events: count(1, 0),
+            notes: count(3, 1),
+            recipes: count(1, 0),
+            todos: count(2, 0),
+        }
+    );
+    assert_eq!(
+        *batch_sizes
+            .lock()
+            .expect("batch sizes shouldn't be poisoned"),
+        vec![1, 1, 2, 1, 1, 2]
+    );
+    let response = client
+        .post(format!("{notes_url}/search"))
+        .json(&json!({}))
+        .send()
+        .await?;
+    let response = utils::assert_ok_response(response).await?;
+    let notes = response.json::<Page<Note>>().await?;
+    assert_eq!(notes.items.len(), 3);
+
+    // Seeding again only adds what's new.
+    write(
+        "notes",
+        json!([
+            {"content": "My car takes OW-20 oil"},
+            {"content": "The wifi password is hunter2"},
+            {"content": "The garage code is 1234"},
+            {"content": "The spare key is under the mat"}
+        ]),
+    )?;
+    let response = client.post(&seed_url).send().await?;
+    let response = utils::assert_ok_response(response).await?;
+    let report = response.json::<SeedReport>().await?;
+    assert_eq!(
+        report,
+        SeedReport {
+            contacts: count(0, 1),
+            events: count(0, 1),
+            notes: count(1, 3),
+            recipes: count(0, 1),
+            todos: count(0, 2),
+        }
+    );
+
+    // Malformed seed files are rejected before anything is added.
+    write("notes", json!([{"content": "The gate code is 4321"}]))?;
+    write("todos", json!([{"task": "Buy oil"}]))?;
+    let response = client.post(&seed_url).send().await?;
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    let response = client
+        .post(format!("{notes_url}/search"))
+        .json(&json!({}))
+        .send()
+        .await?;
+    let response = utils::assert_ok_response(response).await?;
+    let notes = response.json::<Page<Note>>().await?;
+    assert_eq!(notes.items.len(), 4);
+
+    std::fs::remove_dir_all(&seed_dir)?;
+    Ok(())
+}
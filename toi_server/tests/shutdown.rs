@@ -0,0 +1,88 @@
+use axum::{
+    Router,
+    body::{Body, Bytes},
+    routing::get,
+};
+use futures::stream;
+use std::time::Duration;
+use tokio::{net::TcpListener, sync::oneshot, time::Instant};
+
+// A slow streaming endpoint that's similar to streamed assistant responses.
+fn streaming_router(chunks: usize) -> Router {
+    Router::new().route(
+        "/stream",
+        get(move || async move {
+            let stream = stream::unfold(0, move |i| async move {
+                if i == chunks {
+                    return None;
+                }
+                tokio::time::sleep(Duration::from_millis(100)).await;
+                Some((
+                    Ok::<_, std::io::Error>(Bytes::from(format!("{i}\n"))),
+                    i + 1,
+                ))
+            });
+            Body::from_stream(stream)
+        }),
+    )
+}
+
+#[tokio::test]
+async fn draining_in_flight_streams() -> Result<(), Box<dyn std::error::Error>> {
+    let listener = TcpListener::bind("127.0.0.1:0").await?;
+    let addr = listener.local_addr()?;
+    let (signal_tx, signal_rx) = oneshot::channel::<()>();
+    let signal = async move {
+        let _ = signal_rx.await;
+    };
+    let server = tokio::spawn(toi_server::shutdown::serve(
+        listener,
+        streaming_router(5),
+        signal,
+        Duration::from_secs(5),
+    ));
+
+    // Start streaming, then ask the server to stop partway through.
+    let mut response = reqwest::get(format!("http://{addr}/stream")).await?;
+    let first_chunk = response.chunk().await?;
+    assert_eq!(first_chunk, Some(Bytes::from("0\n")));
+    signal_tx.send(()).map_err(|()| "server stopped early")?;
+
+    // The rest of the stream still makes it through.
+    let rest = response.text().await?;
+    assert_eq!(rest, "1\n2\n3\n4\n");
+    tokio::time::timeout(Duration::from_secs(1), server).await???;
+
+    // New connections aren't accepted anymore.
+    assert!(reqwest::get(format!("http://{addr}/stream")).await.is_err());
+    Ok(())
+}
+
+#[tokio::test]
+async fn dropping_streams_after_drain_timeout() -> Result<(), Box<dyn std::error::Error>> {
+    let listener = TcpListener::bind("127.0.0.1:0").await?;
+    let addr = listener.local_addr()?;
+    let (signal_tx, signal_rx) = oneshot::channel::<()>();
+    let signal = async move {
+        let _ = signal_rx.await;
+    };
+    let drain_timeout = Duration::from_millis(200);
+    let server = tokio::spawn(toi_server::shutdown::serve(
+        listener,
+        streaming_router(usize::MAX),
+        signal,
+        drain_timeout,
+    ));
+
+    // Start a stream that never finishes on its own, then ask the server
+    // to stop.
+    let mut response = reqwest::get(format!("http://{addr}/stream")).await?;
+    response.chunk().await?;
+    let start = Instant::now();
+    signal_tx.send(()).map_err(|()| "server stopped early")?;
+
+    // The server stops anyways once the drain timeout is up.
+    tokio::time::timeout(Duration::from_secs(2), server).await???;
+    assert!(start.elapsed() >= drain_timeout);
+    Ok(())
+}
@@ -1,8 +1,17 @@
+use diesel::{ExpressionMethods, QueryDsl};
+use diesel_async::RunQueryDsl;
+use reqwest::StatusCode;
 use serial_test::serial;
 use tokio::net::TcpListener;
 use utoipa_axum::router::OpenApiRouter;
 
-use toi_server::models::tags::{NewTagRequest, Tag, TagSearchParams};
+use toi_server::{
+    models::{
+        recipes::{NewRecipeRequest, Recipe},
+        tags::{NewTagRequest, Tag, TagOrderBy, TagSearchParams, TagWithUsage, UpdateTagRequest},
+    },
+    schema,
+};
 
 mod utils;
 
@@ -64,3 +73,193 @@ async fn tags_routes() -> Result<(), Box<dyn std::error::Error>> {
     assert_eq!(vec_tags2, vec_tags1);
     Ok(())
 }
+
+#[tokio::test]
+#[serial]
+async fn renaming_tags() -> Result<(), Box<dyn std::error::Error>> {
+    // Make sure there's a database URL and it points to a test database so
+    // prod isn't goofed during testing.
+    let db_connection_url = dotenvy::var("DATABASE_URL")?;
+    utils::reset_database(&db_connection_url)?;
+
+    // Initialize the server state.
+    let state = toi_server::init(db_connection_url).await?;
+    let mut conn = state.pool.get().await?;
+    let openapi_router = OpenApiRouter::new()
+        .nest(
+            "/recipes",
+            toi_server::routes::recipes::recipes_router(state.clone()),
+        )
+        .nest(
+            "/tags",
+            toi_server::routes::tags::tags_router(state.clone()),
+        );
+    let (router, _) = openapi_router.split_for_parts();
+    let listener = TcpListener::bind(&state.server_config.bind_addr).await?;
+
+    // Spawn server and create a client for all test requests.
+    let _ = tokio::spawn(async move { axum::serve(listener, router).await });
+    let client = reqwest::Client::new();
+    let tags_url = format!("http://{}/tags", state.server_config.bind_addr);
+    let recipes_url = format!("http://{}/recipes", state.server_config.bind_addr);
+
+    // Make tags.
+    let mut tags = vec![];
+    for name in ["week-night", "dinner", "supper"] {
+        let body = NewTagRequest::builder().name(name.to_string()).build();
+        let response = client.post(&tags_url).json(&body).send().await?;
+        let response = utils::assert_ok_response(response).await?;
+        tags.push(response.json::<Tag>().await?);
+    }
+
+    // Make recipes where one has both tags that'll be merged.
+    let mut recipes = vec![];
+    for (description, tags) in [
+        (
+            "spaghetti and meatballs",
+            vec!["week-night", "dinner", "supper"],
+        ),
+        ("grilled cheese", vec!["supper"]),
+    ] {
+        let body = NewRecipeRequest::builder()
+            .description(description.to_string())
+            .ingredients("stuff".to_string())
+            .instructions("cook the stuff".to_string())
+            .tags(tags.into_iter().map(str::to_string).collect())
+            .build();
+        let response = client.post(&recipes_url).json(&body).send().await?;
+        let response = utils::assert_ok_response(response).await?;
+        recipes.push(response.json::<Recipe>().await?);
+    }
+
+    // Rename a tag without losing its recipes.
+    let body = UpdateTagRequest::builder()
+        .id(tags[0].id)
+        .new_name("weeknight".to_string())
+        .build();
+    let response = client.put(&tags_url).json(&body).send().await?;
+    let response = utils::assert_ok_response(response).await?;
+    let renamed_tag = response.json::<Tag>().await?;
+    assert_eq!(renamed_tag.id, tags[0].id);
+    assert_eq!(renamed_tag.name, "weeknight");
+    let recipe_ids: Vec<i32> = schema::recipe_tags::table
+        .select(schema::recipe_tags::recipe_id)
+        .filter(schema::recipe_tags::tag_id.eq(renamed_tag.id))
+        .load(&mut conn)
+        .await?;
+    assert_eq!(recipe_ids, vec![recipes[0].id]);
+
+    // Renaming a tag to one that already exists is a conflict.
+    let body = UpdateTagRequest::builder()
+        .id(tags[2].id)
+        .new_name("dinner".to_string())
+        .build();
+    let response = client.put(&tags_url).json(&body).send().await?;
+    assert_eq!(response.status(), StatusCode::CONFLICT);
+
+    // Unless the tags are merged, in which case recipes that had both tags
+    // only keep one.
+    let body = UpdateTagRequest::builder()
+        .id(tags[2].id)
+        .new_name("dinner".to_string())
+        .merge(true)
+        .build();
+    let response = client.put(&tags_url).json(&body).send().await?;
+    let response = utils::assert_ok_response(response).await?;
+    let merged_tag = response.json::<Tag>().await?;
+    assert_eq!(merged_tag, tags[1]);
+    let mut recipe_ids: Vec<i32> = schema::recipe_tags::table
+        .select(schema::recipe_tags::recipe_id)
+        .filter(schema::recipe_tags::tag_id.eq(merged_tag.id))
+        .load(&mut conn)
+        .await?;
+    recipe_ids.sort_unstable();
+    assert_eq!(recipe_ids, vec![recipes[0].id, recipes[1].id]);
+    let old_tag_count: i64 = schema::tags::table
+        .filter(schema::tags::id.eq(tags[2].id))
+        .count()
+        .get_result(&mut conn)
+        .await?;
+    assert_eq!(old_tag_count, 0);
+    Ok(())
+}
+
+#[tokio::test]
+#[serial]
+async fn listing_tags() -> Result<(), Box<dyn std::error::Error>> {
+    // Make sure there's a database URL and it points to a test database so
+    // prod isn't goofed during testing.
+    let db_connection_url = dotenvy::var("DATABASE_URL")?;
+    utils::reset_database(&db_connection_url)?;
+
+    // Initialize the server state.
+    let state = toi_server::init(db_connection_url).await?;
+    let openapi_router = OpenApiRouter::new()
+        .nest(
+            "/recipes",
+            toi_server::routes::recipes::recipes_router(state.clone()),
+        )
+        .nest(
+            "/tags",
+            toi_server::routes::tags::tags_router(state.clone()),
+        );
+    let (router, _) = openapi_router.split_for_parts();
+    let listener = TcpListener::bind(&state.server_config.bind_addr).await?;
+
+    // Spawn server and create a client for all test requests.
+    let _ = tokio::spawn(async move { axum::serve(listener, router).await });
+    let client = reqwest::Client::new();
+    let tags_url = format!("http://{}/tags", state.server_config.bind_addr);
+    let search_tags_url = format!("{tags_url}/search");
+    let recipes_url = format!("http://{}/recipes", state.server_config.bind_addr);
+
+    // Make tags, and a recipe with only one of them.
+    for name in ["soup", "asian", "dessert"] {
+        let body = NewTagRequest::builder().name(name.to_string()).build();
+        let response = client.post(&tags_url).json(&body).send().await?;
+        utils::assert_ok_response(response).await?;
+    }
+    let body = NewRecipeRequest::builder()
+        .description("chicken noodle soup".to_string())
+        .ingredients("chicken, noodles, broth".to_string())
+        .instructions("simmer everything".to_string())
+        .tags(vec!["soup".to_string()])
+        .build();
+    let response = client.post(&recipes_url).json(&body).send().await?;
+    utils::assert_ok_response(response).await?;
+
+    // Tags are listed alphabetically by default, with how many recipes
+    // they're on, including tags that aren't on any.
+    let params = TagSearchParams::builder().build();
+    let response = client.post(&search_tags_url).json(&params).send().await?;
+    let response = utils::assert_ok_response(response).await?;
+    let tags: Vec<(String, i64)> = response
+        .json::<Vec<TagWithUsage>>()
+        .await?
+        .into_iter()
+        .map(|tag| (tag.tag.name, tag.usage_count))
+        .collect();
+    assert_eq!(
+        tags,
+        vec![
+            ("asian".to_string(), 0),
+            ("dessert".to_string(), 0),
+            ("soup".to_string(), 1)
+        ]
+    );
+
+    // Tags can be listed newest first too.
+    let params = TagSearchParams::builder()
+        .order_by(TagOrderBy::Newest)
+        .build();
+    let response = client.post(&search_tags_url).json(&params).send().await?;
+    let response = utils::assert_ok_response(response).await?;
+    let names: Vec<String> = response
+        .json::<Vec<TagWithUsage>>()
+        .await?
+        .into_iter()
+        .map(|tag| tag.tag.name)
+        .collect();
+    assert_eq!(names, vec!["dessert", "asian", "soup"]);
+    Ok(())
+}
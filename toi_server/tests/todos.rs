@@ -1,8 +1,18 @@
+use chrono::{Duration, TimeZone, Utc};
+use serde_json::json;
 use serial_test::serial;
 use tokio::net::TcpListener;
 use utoipa_axum::router::OpenApiRouter;
 
-use toi_server::models::todos::{NewTodoRequest, Todo, TodoSearchParams};
+use toi_server::models::{
+    events::{Event, EventSearchParams, NewEventRequest},
+    pagination::{Count, Page},
+    tags::{NewTagRequest, Tag, TagMatch, TagSearchParams},
+    todos::{
+        CompleteTodoRequest, NewTodoRequest, NewTodoTagsRequest, SnoozeTodoRequest, Todo,
+        TodoOrderBy, TodoSearchParams, TodoTagSearchParams, TodoTags, TodoWithEvent,
+    },
+};
 
 mod utils;
 
@@ -36,21 +46,692 @@ async fn todos_routes() -> Result<(), Box<dyn std::error::Error>> {
     let todo1 = response.json::<Todo>().await?;
     assert_eq!(todo1.item, item);
 
+    // Retrieve the todo using its ID, and fail to retrieve a missing one.
+    let response = client
+        .get(format!("{todos_url}/{}", todo1.id))
+        .send()
+        .await?;
+    let response = utils::assert_ok_response(response).await?;
+    assert_eq!(response.json::<Todo>().await?, todo1);
+    let response = client.get(format!("{todos_url}/0")).send().await?;
+    assert_eq!(response.status(), reqwest::StatusCode::NOT_FOUND);
+
     // Retrieve the todo using search.
     let search_todos_url = format!("{todos_url}/search");
     let params = TodoSearchParams::builder()
         .query("change my car oil".to_string())
         .build();
-    let response = client.post(search_todos_url).json(&params).send().await?;
+    let response = client.post(&search_todos_url).json(&params).send().await?;
     let response = utils::assert_ok_response(response).await?;
-    let vec_todos1 = response.json::<Vec<Todo>>().await?;
+    let vec_todos1 = response.json::<Page<Todo>>().await?.items;
     assert_eq!(vec_todos1, vec![todo1]);
 
-    // Delete the todo using search.
+    // Count the todos with the same filters.
+    let count_params = TodoSearchParams::builder()
+        .query("change my car oil".to_string())
+        .count_only(true)
+        .build();
+    let response = client
+        .post(&search_todos_url)
+        .json(&count_params)
+        .send()
+        .await?;
+    let response = utils::assert_ok_response(response).await?;
+    let count = response.json::<Count>().await?.count;
+    assert_eq!(usize::try_from(count)?, vec_todos1.len());
+
+    // Make a more urgent todo and retrieve it by ordering on priority.
+    let body = NewTodoRequest::builder()
+        .item("Renew my passport".to_string())
+        .priority(5)
+        .build();
+    let response = client.post(&todos_url).json(&body).send().await?;
+    let response = utils::assert_ok_response(response).await?;
+    let todo2 = response.json::<Todo>().await?;
+    assert_eq!(todo2.priority, Some(5));
+    let priority_params = TodoSearchParams::builder()
+        .order_by(TodoOrderBy::HighestPriority)
+        .limit(1)
+        .build();
+    let response = client
+        .post(&search_todos_url)
+        .json(&priority_params)
+        .send()
+        .await?;
+    let response = utils::assert_ok_response(response).await?;
+    let vec_todos3 = response.json::<Page<Todo>>().await?.items;
+    assert_eq!(vec_todos3, vec![todo2]);
+
+    // Delete the todo using search, moving it to the trash.
     let delete_todos_url = format!("{todos_url}/delete");
     let response = client.post(delete_todos_url).json(&params).send().await?;
     let response = utils::assert_ok_response(response).await?;
     let vec_todos2 = response.json::<Vec<Todo>>().await?;
-    assert_eq!(vec_todos2, vec_todos1);
+    assert_eq!(vec_todos2.len(), 1);
+    assert_eq!(vec_todos2[0].id, vec_todos1[0].id);
+    assert!(vec_todos2[0].deleted_at.is_some());
+
+    // The deleted todo is hidden from search, even when selected by its id.
+    let ids_params = TodoSearchParams::builder()
+        .ids(vec![vec_todos1[0].id])
+        .build();
+    let response = client
+        .post(&search_todos_url)
+        .json(&ids_params)
+        .send()
+        .await?;
+    let response = utils::assert_ok_response(response).await?;
+    assert!(response.json::<Page<Todo>>().await?.items.is_empty());
+
+    // Restore the todo, and then it's searchable again.
+    let restore_todos_url = format!("{todos_url}/restore");
+    let response = client
+        .post(restore_todos_url)
+        .json(&ids_params)
+        .send()
+        .await?;
+    let response = utils::assert_ok_response(response).await?;
+    let vec_todos6 = response.json::<Vec<Todo>>().await?;
+    assert_eq!(vec_todos6, vec_todos1);
+    let response = client.post(&search_todos_url).json(&params).send().await?;
+    let response = utils::assert_ok_response(response).await?;
+    let vec_todos7 = response.json::<Page<Todo>>().await?.items;
+    assert_eq!(vec_todos7, vec_todos1);
+
+    // Make a recurring todo and a one-shot todo.
+    let due_at = Utc
+        .with_ymd_and_hms(2025, 6, 1, 9, 0, 0)
+        .single()
+        .ok_or("invalid test datetime")?;
+    let body = NewTodoRequest::builder()
+        .item("Water the plants".to_string())
+        .due_at(due_at)
+        .recurrence_days(3)
+        .build();
+    let response = client.post(&todos_url).json(&body).send().await?;
+    let response = utils::assert_ok_response(response).await?;
+    let recurring_todo = response.json::<Todo>().await?;
+    assert_eq!(recurring_todo.recurrence_days, Some(3));
+    let body = NewTodoRequest::builder()
+        .item("Take out the trash".to_string())
+        .due_at(due_at)
+        .build();
+    let response = client.post(&todos_url).json(&body).send().await?;
+    let response = utils::assert_ok_response(response).await?;
+    let one_shot_todo = response.json::<Todo>().await?;
+
+    // Only the recurring todo is in the recurring scope.
+    let recurring_params = json!({"is_recurring": "In"});
+    let response = client
+        .post(&search_todos_url)
+        .json(&recurring_params)
+        .send()
+        .await?;
+    let response = utils::assert_ok_response(response).await?;
+    let vec_todos4 = response.json::<Page<Todo>>().await?.items;
+    assert_eq!(vec_todos4, vec![recurring_todo]);
+
+    // Complete both todos at once. The one-shot todo is completed while the
+    // recurring todo is due again later.
+    let completed_at = due_at + Duration::hours(1);
+    let body = CompleteTodoRequest::builder()
+        .ids(vec![vec_todos4[0].id, one_shot_todo.id])
+        .completed_at(completed_at)
+        .build();
+    let response = client.put(&todos_url).json(&body).send().await?;
+    let response = utils::assert_ok_response(response).await?;
+    let vec_todos5 = response.json::<Vec<Todo>>().await?;
+    assert_eq!(vec_todos5.len(), 2);
+    for todo in vec_todos5 {
+        if todo.id == one_shot_todo.id {
+            assert_eq!(todo.completed_at, Some(completed_at));
+            assert_eq!(todo.due_at, Some(due_at));
+        } else {
+            assert_eq!(todo.completed_at, None);
+            assert_eq!(todo.due_at, Some(due_at + Duration::days(3)));
+            assert_eq!(todo.last_completed_at, Some(completed_at));
+        }
+    }
+    Ok(())
+}
+
+#[tokio::test]
+#[serial]
+async fn todos_exclusion() -> Result<(), Box<dyn std::error::Error>> {
+    // Make sure there's a database URL and it points to a test database so
+    // prod isn't goofed during testing.
+    let db_connection_url = dotenvy::var("DATABASE_URL")?;
+    utils::reset_database(&db_connection_url)?;
+
+    // Initialize the server state.
+    let state = toi_server::init(db_connection_url).await?;
+    let openapi_router = OpenApiRouter::new().nest(
+        "/todos",
+        toi_server::routes::todos::todos_router(state.clone()),
+    );
+    let (router, _) = openapi_router.split_for_parts();
+    let listener = TcpListener::bind(&state.server_config.bind_addr).await?;
+
+    // Spawn server and create a client for all test requests.
+    let _ = tokio::spawn(async move { axum::serve(listener, router).await });
+    let client = reqwest::Client::new();
+    let todos_url = format!("http://{}/todos", state.server_config.bind_addr);
+    let search_todos_url = format!("{todos_url}/search");
+
+    // Make some todos with different priorities.
+    let mut ids = vec![];
+    for (item, priority) in [
+        ("Finish the quarterly work report", 3),
+        ("Buy groceries", 2),
+        ("Call the plumber", 1),
+    ] {
+        let body = NewTodoRequest::builder()
+            .item(item.to_string())
+            .priority(priority)
+            .build();
+        let response = client.post(&todos_url).json(&body).send().await?;
+        let response = utils::assert_ok_response(response).await?;
+        ids.push(response.json::<Todo>().await?.id);
+    }
+
+    // Todos matching the exclude query are left out even when they match
+    // every other filter.
+    let params = TodoSearchParams::builder()
+        .ids(ids.clone())
+        .exclude_query("Finish the quarterly work report".to_string())
+        .build();
+    let response = client.post(&search_todos_url).json(&params).send().await?;
+    let response = utils::assert_ok_response(response).await?;
+    let todo_ids: Vec<i32> = response
+        .json::<Page<Todo>>()
+        .await?
+        .items
+        .into_iter()
+        .map(|todo| todo.id)
+        .collect();
+    assert!(!todo_ids.contains(&ids[0]));
+    assert!(todo_ids.contains(&ids[1]));
+
+    // Todos are excluded before ordering and limiting, so the highest
+    // priority todo that isn't excluded comes first.
+    let params = TodoSearchParams::builder()
+        .ids(ids.clone())
+        .exclude_ids(vec![ids[0]])
+        .order_by(TodoOrderBy::HighestPriority)
+        .limit(1)
+        .build();
+    let response = client.post(&search_todos_url).json(&params).send().await?;
+    let response = utils::assert_ok_response(response).await?;
+    let page_todos = response.json::<Page<Todo>>().await?;
+    let todo_ids: Vec<i32> = page_todos.items.into_iter().map(|todo| todo.id).collect();
+    assert_eq!(todo_ids, vec![ids[1]]);
+    assert_eq!(page_todos.total, 2);
+    Ok(())
+}
+
+#[tokio::test]
+#[serial]
+async fn todos_events() -> Result<(), Box<dyn std::error::Error>> {
+    // Make sure there's a database URL and it points to a test database so
+    // prod isn't goofed during testing.
+    let db_connection_url = dotenvy::var("DATABASE_URL")?;
+    utils::reset_database(&db_connection_url)?;
+
+    // Initialize the server state.
+    let state = toi_server::init(db_connection_url).await?;
+    let openapi_router = OpenApiRouter::new()
+        .nest(
+            "/events",
+            toi_server::routes::events::events_router(state.clone()),
+        )
+        .nest(
+            "/todos",
+            toi_server::routes::todos::todos_router(state.clone()),
+        );
+    let (router, _) = openapi_router.split_for_parts();
+    let listener = TcpListener::bind(&state.server_config.bind_addr).await?;
+
+    // Spawn server and create a client for all test requests.
+    let _ = tokio::spawn(async move { axum::serve(listener, router).await });
+    let client = reqwest::Client::new();
+    let events_url = format!("http://{}/events", state.server_config.bind_addr);
+    let todos_url = format!("http://{}/todos", state.server_config.bind_addr);
+    let search_todos_url = format!("{todos_url}/search");
+
+    // Make an event.
+    let starts_at = Utc::now() + Duration::days(3);
+    let body = NewEventRequest::builder()
+        .description("Friday's dinner party".to_string())
+        .starts_at(starts_at)
+        .ends_at(starts_at + Duration::hours(3))
+        .build();
+    let response = client.post(&events_url).json(&body).send().await?;
+    let response = utils::assert_ok_response(response).await?;
+    let event = response.json::<Event>().await?;
+
+    // Make a todo for the event by searching for it, and one that isn't for
+    // any event.
+    let body = NewTodoRequest::builder()
+        .item("Buy wine".to_string())
+        .event_query("dinner party".to_string())
+        .build();
+    let response = client.post(&todos_url).json(&body).send().await?;
+    let response = utils::assert_ok_response(response).await?;
+    let todo1 = response.json::<Todo>().await?;
+    let todo1_id = todo1.id;
+    assert_eq!(todo1.event_id, Some(event.id));
+    let body = NewTodoRequest::builder()
+        .item("Buy dog food".to_string())
+        .build();
+    let response = client.post(&todos_url).json(&body).send().await?;
+    let response = utils::assert_ok_response(response).await?;
+    assert_eq!(response.json::<Todo>().await?.event_id, None);
+
+    // Todos can't be linked to missing events.
+    let body = NewTodoRequest::builder()
+        .item("Buy flowers".to_string())
+        .event_id(0)
+        .build();
+    let response = client.post(&todos_url).json(&body).send().await?;
+    assert_eq!(response.status(), reqwest::StatusCode::NOT_FOUND);
+
+    // Search todos for the event, which come with the event.
+    let params = TodoSearchParams::builder()
+        .event_query("party".to_string())
+        .build();
+    let response = client.post(&search_todos_url).json(&params).send().await?;
+    let response = utils::assert_ok_response(response).await?;
+    let todos = response.json::<Page<TodoWithEvent>>().await?.items;
+    assert_eq!(
+        todos,
+        vec![TodoWithEvent {
+            todo: todo1,
+            event: Some(event),
+        }]
+    );
+
+    // Deleting the event leaves the todo without one, and it can still be
+    // completed and deleted.
+    let params = EventSearchParams::builder()
+        .query("dinner party".to_string())
+        .build();
+    let response = client
+        .post(format!("{events_url}/delete"))
+        .json(&params)
+        .send()
+        .await?;
+    utils::assert_ok_response(response).await?;
+    let body = CompleteTodoRequest::builder()
+        .ids(vec![todo1_id])
+        .completed_at(Utc::now())
+        .build();
+    let response = client.put(&todos_url).json(&body).send().await?;
+    let response = utils::assert_ok_response(response).await?;
+    let todos = response.json::<Vec<Todo>>().await?;
+    assert_eq!(todos.len(), 1);
+    assert_eq!(todos[0].event_id, None);
+    assert!(todos[0].completed_at.is_some());
+    let params = TodoSearchParams::builder().ids(vec![todo1_id]).build();
+    let response = client
+        .post(format!("{todos_url}/delete"))
+        .json(&params)
+        .send()
+        .await?;
+    let response = utils::assert_ok_response(response).await?;
+    assert_eq!(response.json::<Vec<Todo>>().await?.len(), 1);
+    Ok(())
+}
+
+#[tokio::test]
+#[serial]
+async fn todo_tags() -> Result<(), Box<dyn std::error::Error>> {
+    // Make sure there's a database URL and it points to a test database so
+    // prod isn't goofed during testing.
+    let db_connection_url = dotenvy::var("DATABASE_URL")?;
+    utils::reset_database(&db_connection_url)?;
+
+    // Initialize the server state.
+    let state = toi_server::init(db_connection_url).await?;
+    let openapi_router = OpenApiRouter::new()
+        .nest(
+            "/todos",
+            toi_server::routes::todos::todos_router(state.clone()),
+        )
+        .nest(
+            "/tags",
+            toi_server::routes::tags::tags_router(state.clone()),
+        );
+    let (router, _) = openapi_router.split_for_parts();
+    let listener = TcpListener::bind(&state.server_config.bind_addr).await?;
+
+    // Spawn server and create a client for all test requests.
+    let _ = tokio::spawn(async move { axum::serve(listener, router).await });
+    let client = reqwest::Client::new();
+    let tags_url = format!("http://{}/tags", state.server_config.bind_addr);
+    let todos_url = format!("http://{}/todos", state.server_config.bind_addr);
+
+    // Make tags, and then make todos with shared and disjoint tags.
+    let mut tag_ids = vec![];
+    for name in ["work", "urgent", "home"] {
+        let body = NewTagRequest::builder().name(name.to_string()).build();
+        let response = client.post(&tags_url).json(&body).send().await?;
+        let response = utils::assert_ok_response(response).await?;
+        tag_ids.push(response.json::<Tag>().await?.id);
+    }
+    let todo_tag_ids = |todo_tags: TodoTags| -> Vec<i32> {
+        let mut ids: Vec<i32> = todo_tags.tags.into_iter().map(|tag| tag.id).collect();
+        ids.sort_unstable();
+        ids
+    };
+    let mut ids = vec![];
+    for (item, tags) in [
+        ("Send the quarterly report", vec!["work", "urgent"]),
+        ("Book a meeting room", vec!["work"]),
+        ("Fix the leaky faucet", vec!["home"]),
+    ] {
+        let body = NewTodoRequest::builder()
+            .item(item.to_string())
+            .tags(tags.into_iter().map(str::to_string).collect())
+            .build();
+        let response = client.post(&todos_url).json(&body).send().await?;
+        let response = utils::assert_ok_response(response).await?;
+        ids.push(response.json::<Todo>().await?.id);
+    }
+
+    // Todos come with the tags they were added with.
+    let search_todo_tags_url = format!("{todos_url}/tags/search");
+    let params = TodoTagSearchParams::builder().todo_id(ids[0]).build();
+    let response = client
+        .post(&search_todo_tags_url)
+        .json(&params)
+        .send()
+        .await?;
+    let response = utils::assert_ok_response(response).await?;
+    let todo_tags = response.json::<TodoTags>().await?;
+    assert_eq!(todo_tags.todo.id, ids[0]);
+    assert_eq!(todo_tag_ids(todo_tags), tag_ids[..2]);
+
+    // Todos with any of the tags match by default, and todos with all of
+    // the tags match when asked for.
+    let search_todos_url = format!("{todos_url}/search");
+    for (tags_match, expected_ids) in [
+        (None, vec![ids[0], ids[1]]),
+        (Some(TagMatch::Any), vec![ids[0], ids[1]]),
+        (Some(TagMatch::All), vec![ids[0]]),
+    ] {
+        let params = TodoSearchParams::builder()
+            .tags(vec!["work".to_string(), "urgent".to_string()])
+            .maybe_tags_match(tags_match)
+            .build();
+        let response = client.post(&search_todos_url).json(&params).send().await?;
+        let response = utils::assert_ok_response(response).await?;
+        let mut todo_ids: Vec<i32> = response
+            .json::<Page<Todo>>()
+            .await?
+            .items
+            .into_iter()
+            .map(|todo| todo.id)
+            .collect();
+        todo_ids.sort_unstable();
+        assert_eq!(todo_ids, expected_ids);
+    }
+
+    // Tag an existing todo. Tagging it again keeps the one tag.
+    for _ in 0..2 {
+        let body = NewTodoTagsRequest::builder()
+            .ids(vec![ids[2]])
+            .tags(vec!["urgent".to_string()])
+            .build();
+        let response = client
+            .post(format!("{todos_url}/tags"))
+            .json(&body)
+            .send()
+            .await?;
+        let response = utils::assert_ok_response(response).await?;
+        let todos = response.json::<Vec<Todo>>().await?;
+        assert_eq!(todos.len(), 1);
+        assert_eq!(todos[0].id, ids[2]);
+    }
+    let params = TodoTagSearchParams::builder().todo_id(ids[2]).build();
+    let response = client
+        .post(&search_todo_tags_url)
+        .json(&params)
+        .send()
+        .await?;
+    let response = utils::assert_ok_response(response).await?;
+    let todo_tags = response.json::<TodoTags>().await?;
+    assert_eq!(todo_tag_ids(todo_tags), tag_ids[1..]);
+
+    // Remove a tag from a todo. The tag itself is kept.
+    let params = TodoTagSearchParams::builder()
+        .todo_id(ids[2])
+        .tag_ids(vec![tag_ids[2]])
+        .build();
+    let response = client
+        .post(format!("{todos_url}/tags/delete"))
+        .json(&params)
+        .send()
+        .await?;
+    let response = utils::assert_ok_response(response).await?;
+    let todo_tags = response.json::<TodoTags>().await?;
+    assert_eq!(todo_tag_ids(todo_tags), vec![tag_ids[2]]);
+    let params = TodoSearchParams::builder()
+        .tags(vec!["home".to_string()])
+        .build();
+    let response = client.post(&search_todos_url).json(&params).send().await?;
+    let response = utils::assert_ok_response(response).await?;
+    assert_eq!(response.json::<Page<Todo>>().await?.total, 0);
+
+    // Deleting a tag removes it from every todo it was on.
+    let params = TagSearchParams::builder().ids(vec![tag_ids[1]]).build();
+    let response = client
+        .post(format!("{tags_url}/delete"))
+        .json(&params)
+        .send()
+        .await?;
+    utils::assert_ok_response(response).await?;
+    let params = TodoTagSearchParams::builder().todo_id(ids[0]).build();
+    let response = client
+        .post(&search_todo_tags_url)
+        .json(&params)
+        .send()
+        .await?;
+    let response = utils::assert_ok_response(response).await?;
+    let todo_tags = response.json::<TodoTags>().await?;
+    assert_eq!(todo_tag_ids(todo_tags), vec![tag_ids[0]]);
+    Ok(())
+}
+
+#[tokio::test]
+#[serial]
+async fn todos_snooze() -> Result<(), Box<dyn std::error::Error>> {
+    // Make sure there's a database URL and it points to a test database so
+    // prod isn't goofed during testing.
+    let db_connection_url = dotenvy::var("DATABASE_URL")?;
+    utils::reset_database(&db_connection_url)?;
+
+    // Initialize the server state.
+    let state = toi_server::init(db_connection_url).await?;
+    let openapi_router = OpenApiRouter::new().nest(
+        "/todos",
+        toi_server::routes::todos::todos_router(state.clone()),
+    );
+    let (router, _) = openapi_router.split_for_parts();
+    let listener = TcpListener::bind(&state.server_config.bind_addr).await?;
+
+    // Spawn server and create a client for all test requests.
+    let _ = tokio::spawn(async move { axum::serve(listener, router).await });
+    let client = reqwest::Client::new();
+    let todos_url = format!("http://{}/todos", state.server_config.bind_addr);
+    let snooze_todos_url = format!("{todos_url}/snooze");
+
+    // Make a todo that's due, one that's never due, and one that's already
+    // done.
+    let due_at = Utc
+        .with_ymd_and_hms(2025, 6, 2, 9, 0, 0)
+        .single()
+        .ok_or("invalid test datetime")?;
+    let bodies = [
+        NewTodoRequest::builder()
+            .item("Go to the dentist".to_string())
+            .due_at(due_at)
+            .build(),
+        NewTodoRequest::builder()
+            .item("Clean the garage".to_string())
+            .build(),
+        NewTodoRequest::builder()
+            .item("File taxes".to_string())
+            .due_at(due_at)
+            .completed_at(due_at)
+            .build(),
+    ];
+    let mut todos = vec![];
+    for body in bodies {
+        let response = client.post(&todos_url).json(&body).send().await?;
+        let response = utils::assert_ok_response(response).await?;
+        todos.push(response.json::<Todo>().await?);
+    }
+
+    // Snoozing needs exactly one of a datetime or a number of days.
+    let body = SnoozeTodoRequest::builder().ids(vec![todos[0].id]).build();
+    let response = client.put(&snooze_todos_url).json(&body).send().await?;
+    assert_eq!(response.status(), reqwest::StatusCode::BAD_REQUEST);
+
+    // Snooze the due todo until a specific datetime.
+    let snooze_until = Utc
+        .with_ymd_and_hms(2025, 6, 9, 9, 0, 0)
+        .single()
+        .ok_or("invalid test datetime")?;
+    let body = SnoozeTodoRequest::builder()
+        .ids(vec![todos[0].id])
+        .snooze_until(snooze_until)
+        .build();
+    let response = client.put(&snooze_todos_url).json(&body).send().await?;
+    let response = utils::assert_ok_response(response).await?;
+    let snoozed_todos = response.json::<Vec<Todo>>().await?;
+    assert_eq!(snoozed_todos.len(), 1);
+    assert_eq!(snoozed_todos[0].id, todos[0].id);
+    assert_eq!(snoozed_todos[0].due_at, Some(snooze_until));
+
+    // Snooze it again for a few days, which pushes it back from when it's
+    // due, and snooze the todo that's never due, which pushes it back from
+    // now.
+    let before = Utc::now();
+    let body = SnoozeTodoRequest::builder()
+        .ids(vec![todos[0].id, todos[1].id])
+        .snooze_for_days(3)
+        .build();
+    let response = client.put(&snooze_todos_url).json(&body).send().await?;
+    let response = utils::assert_ok_response(response).await?;
+    let mut snoozed_todos = response.json::<Vec<Todo>>().await?;
+    let after = Utc::now();
+    snoozed_todos.sort_by_key(|todo| todo.id);
+    assert_eq!(snoozed_todos.len(), 2);
+    assert_eq!(
+        snoozed_todos[0].due_at,
+        Some(snooze_until + Duration::days(3))
+    );
+    let never_due_at = snoozed_todos[1].due_at.ok_or("todo should be due")?;
+    assert!(never_due_at >= before + Duration::days(3));
+    assert!(never_due_at <= after + Duration::days(3));
+
+    // Completed todos can't be snoozed unless they're reopened.
+    let body = SnoozeTodoRequest::builder()
+        .ids(vec![todos[2].id])
+        .snooze_for_days(1)
+        .build();
+    let response = client.put(&snooze_todos_url).json(&body).send().await?;
+    assert_eq!(response.status(), reqwest::StatusCode::BAD_REQUEST);
+    let response = client
+        .get(format!("{todos_url}/{}", todos[2].id))
+        .send()
+        .await?;
+    let response = utils::assert_ok_response(response).await?;
+    assert_eq!(response.json::<Todo>().await?, todos[2]);
+    let body = SnoozeTodoRequest::builder()
+        .ids(vec![todos[2].id])
+        .snooze_for_days(1)
+        .reopen(true)
+        .build();
+    let response = client.put(&snooze_todos_url).json(&body).send().await?;
+    let response = utils::assert_ok_response(response).await?;
+    let snoozed_todos = response.json::<Vec<Todo>>().await?;
+    assert_eq!(snoozed_todos.len(), 1);
+    assert_eq!(snoozed_todos[0].due_at, Some(due_at + Duration::days(1)));
+    assert_eq!(snoozed_todos[0].completed_at, None);
+    Ok(())
+}
+
+#[tokio::test]
+#[serial]
+async fn todos_duplicates() -> Result<(), Box<dyn std::error::Error>> {
+    // Make sure there's a database URL and it points to a test database so
+    // prod isn't goofed during testing.
+    let db_connection_url = dotenvy::var("DATABASE_URL")?;
+    utils::reset_database(&db_connection_url)?;
+
+    // Initialize the server state with duplicates being checked.
+    let mut state = toi_server::init(db_connection_url).await?;
+    state.server_config.duplicate_check_enabled = true;
+    let openapi_router = OpenApiRouter::new().nest(
+        "/todos",
+        toi_server::routes::todos::todos_router(state.clone()),
+    );
+    let (router, _) = openapi_router.split_for_parts();
+    let listener = TcpListener::bind(&state.server_config.bind_addr).await?;
+
+    // Spawn server and create a client for all test requests.
+    let _ = tokio::spawn(async move { axum::serve(listener, router).await });
+    let client = reqwest::Client::new();
+    let todos_url = format!("http://{}/todos", state.server_config.bind_addr);
+
+    // Make a todo that's open and one that's already done.
+    let done_at = Utc
+        .with_ymd_and_hms(2025, 6, 2, 9, 0, 0)
+        .single()
+        .ok_or("invalid test datetime")?;
+    let bodies = [
+        NewTodoRequest::builder()
+            .item("Buy milk".to_string())
+            .build(),
+        NewTodoRequest::builder()
+            .item("File taxes".to_string())
+            .completed_at(done_at)
+            .build(),
+    ];
+    let mut todos = vec![];
+    for body in bodies {
+        let response = client.post(&todos_url).json(&body).send().await?;
+        let response = utils::assert_ok_response(response).await?;
+        todos.push(response.json::<Todo>().await?);
+    }
+
+    // Adding the open todo again returns the existing todo instead.
+    let body = NewTodoRequest::builder()
+        .item("buy milk".to_string())
+        .build();
+    let response = client.post(&todos_url).json(&body).send().await?;
+    assert_eq!(response.status(), reqwest::StatusCode::CONFLICT);
+    assert_eq!(response.json::<Todo>().await?, todos[0]);
+
+    // Unless the duplicate is explicitly allowed.
+    let body = NewTodoRequest::builder()
+        .item("buy milk".to_string())
+        .allow_duplicate(true)
+        .build();
+    let response = client.post(&todos_url).json(&body).send().await?;
+    let response = utils::assert_ok_response(response).await?;
+    let duplicate = response.json::<Todo>().await?;
+    assert_ne!(duplicate.id, todos[0].id);
+
+    // Todos that are done can be added again, and different todos are added
+    // like normal.
+    for item in ["File taxes", "Clean the garage"] {
+        let body = NewTodoRequest::builder().item(item.to_string()).build();
+        let response = client.post(&todos_url).json(&body).send().await?;
+        let response = utils::assert_ok_response(response).await?;
+        let todo = response.json::<Todo>().await?;
+        assert!(todos.iter().all(|existing| existing.id != todo.id));
+    }
     Ok(())
 }
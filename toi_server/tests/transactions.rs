@@ -1,12 +1,15 @@
+use serde_json::json;
 use serial_test::serial;
 use tokio::net::TcpListener;
 use utoipa_axum::router::OpenApiRouter;
 
 use toi_server::models::{
     accounts::{BankAccount, NewBankAccountRequest},
+    pagination::Page,
     transactions::{
         BankAccountHistory, BankAccountTransaction, BankAccountTransactionSearchParams,
-        NewBankAccountTransactionRequest,
+        LinkedTransaction, NewBankAccountTransactionRequest, TransactionCategorySummary,
+        TransactionSummaryParams,
     },
 };
 
@@ -95,3 +98,202 @@ async fn transactions_routes() -> Result<(), Box<dyn std::error::Error>> {
     assert_eq!(bank_account_history2, bank_account_history1);
     Ok(())
 }
+
+#[tokio::test]
+#[serial]
+async fn transaction_category_summary() -> Result<(), Box<dyn std::error::Error>> {
+    // Make sure there's a database URL and it points to a test database so
+    // prod isn't goofed during testing.
+    let db_connection_url = dotenvy::var("DATABASE_URL")?;
+    utils::reset_database(&db_connection_url)?;
+
+    // Initialize the server state.
+    let state = toi_server::init(db_connection_url).await?;
+    let openapi_router = OpenApiRouter::new()
+        .nest(
+            "/banking/accounts",
+            toi_server::routes::accounts::accounts_router(state.clone()).nest(
+                "/transactions",
+                toi_server::routes::transactions::bank_account_transactions_router(state.clone()),
+            ),
+        )
+        .nest(
+            "/banking/transactions",
+            toi_server::routes::transactions::transactions_router(state.clone()),
+        );
+    let (router, _) = openapi_router.split_for_parts();
+    let listener = TcpListener::bind(&state.server_config.bind_addr).await?;
+
+    // Spawn server and create a client for all test requests.
+    let _ = tokio::spawn(async move { axum::serve(listener, router).await });
+    let client = reqwest::Client::new();
+    let base_url = format!("http://{}/banking", state.server_config.bind_addr);
+    let accounts_url = format!("{base_url}/accounts");
+
+    // Make an account.
+    let account_description = "checking".to_string();
+    let body = NewBankAccountRequest::builder()
+        .description(account_description.clone())
+        .build();
+    let response = client.post(&accounts_url).json(&body).send().await?;
+    utils::assert_ok_response(response).await?;
+
+    // Make transactions with and without categories. Categories are
+    // normalized before they're stored.
+    let bank_account_transactions_url = format!("{accounts_url}/transactions");
+    for (description, amount, category) in [
+        ("farmers market", -20.0, Some(" Groceries ")),
+        ("supermarket", -30.5, Some("groceries")),
+        ("paycheck", 1000.0, Some("income")),
+        ("atm withdrawal", -40.0, None),
+    ] {
+        let body = NewBankAccountTransactionRequest::builder()
+            .bank_account_query(account_description.clone())
+            .transaction_description(description.to_string())
+            .transaction_amount(amount)
+            .maybe_transaction_category(category.map(str::to_string))
+            .build();
+        let response = client
+            .post(&bank_account_transactions_url)
+            .json(&body)
+            .send()
+            .await?;
+        let response = utils::assert_ok_response(response).await?;
+        let bank_account_transaction = response.json::<BankAccountTransaction>().await?;
+        assert_eq!(
+            bank_account_transaction.transaction.category,
+            category.map(|category| category.trim().to_lowercase())
+        );
+    }
+
+    // Summarize all categories.
+    let summary_url = format!("{base_url}/transactions/summary");
+    let params = TransactionSummaryParams::builder().build();
+    let response = client.get(&summary_url).query(&params).send().await?;
+    let response = utils::assert_ok_response(response).await?;
+    let summaries = response.json::<Vec<TransactionCategorySummary>>().await?;
+    let categories: Vec<&str> = summaries
+        .iter()
+        .map(|summary| summary.category.as_str())
+        .collect();
+    assert_eq!(categories, vec!["groceries", "income", "uncategorized"]);
+    assert!((summaries[0].total + 50.5).abs() < f32::EPSILON);
+    assert_eq!(summaries[0].transaction_count, 2);
+    assert!((summaries[2].total + 40.0).abs() < f32::EPSILON);
+    assert_eq!(summaries[2].transaction_count, 1);
+
+    // Summarize a single category.
+    let params = TransactionSummaryParams::builder()
+        .category("GROCERIES".to_string())
+        .build();
+    let response = client.get(&summary_url).query(&params).send().await?;
+    let response = utils::assert_ok_response(response).await?;
+    let summaries = response.json::<Vec<TransactionCategorySummary>>().await?;
+    assert_eq!(summaries.len(), 1);
+    assert_eq!(summaries[0].category, "groceries");
+    assert_eq!(summaries[0].transaction_count, 2);
+    Ok(())
+}
+
+#[tokio::test]
+#[serial]
+async fn transactions_search_streaming() -> Result<(), Box<dyn std::error::Error>> {
+    // Make sure there's a database URL and it points to a test database so
+    // prod isn't goofed during testing.
+    let db_connection_url = dotenvy::var("DATABASE_URL")?;
+    utils::reset_database(&db_connection_url)?;
+
+    // Initialize the server state.
+    let state = toi_server::init(db_connection_url).await?;
+    let openapi_router = OpenApiRouter::new()
+        .nest(
+            "/banking/accounts",
+            toi_server::routes::accounts::accounts_router(state.clone()).nest(
+                "/transactions",
+                toi_server::routes::transactions::bank_account_transactions_router(state.clone()),
+            ),
+        )
+        .nest(
+            "/banking/transactions",
+            toi_server::routes::transactions::transactions_router(state.clone()),
+        );
+    let (router, _) = openapi_router.split_for_parts();
+    let listener = TcpListener::bind(&state.server_config.bind_addr).await?;
+
+    // Spawn server and create a client for all test requests.
+    let _ = tokio::spawn(async move { axum::serve(listener, router).await });
+    let client = reqwest::Client::new();
+    let base_url = format!("http://{}/banking", state.server_config.bind_addr);
+    let accounts_url = format!("{base_url}/accounts");
+
+    // Make an account and a few transactions.
+    let account_description = "checking".to_string();
+    let body = NewBankAccountRequest::builder()
+        .description(account_description.clone())
+        .build();
+    let response = client.post(&accounts_url).json(&body).send().await?;
+    utils::assert_ok_response(response).await?;
+    let bank_account_transactions_url = format!("{accounts_url}/transactions");
+    for (description, amount) in [
+        ("farmers market", -20.0),
+        ("supermarket", -30.5),
+        ("paycheck", 1000.0),
+        ("atm withdrawal", -40.0),
+    ] {
+        let body = NewBankAccountTransactionRequest::builder()
+            .bank_account_query(account_description.clone())
+            .transaction_description(description.to_string())
+            .transaction_amount(amount)
+            .build();
+        let response = client
+            .post(&bank_account_transactions_url)
+            .json(&body)
+            .send()
+            .await?;
+        utils::assert_ok_response(response).await?;
+    }
+
+    // Streamed transactions come one per line in the same order as
+    // buffered ones.
+    let search_transactions_url = format!("{base_url}/transactions/search");
+    let params = json!({"order_by": "Newest"});
+    let response = client
+        .post(&search_transactions_url)
+        .json(&params)
+        .send()
+        .await?;
+    let response = utils::assert_ok_response(response).await?;
+    let page_transactions = response.json::<Page<LinkedTransaction>>().await?;
+    let descriptions: Vec<&str> = page_transactions
+        .items
+        .iter()
+        .map(|transaction| transaction.description.as_str())
+        .collect();
+    assert_eq!(
+        descriptions,
+        vec![
+            "atm withdrawal",
+            "paycheck",
+            "supermarket",
+            "farmers market"
+        ]
+    );
+    let response = client
+        .post(&search_transactions_url)
+        .header(reqwest::header::ACCEPT, "application/x-ndjson")
+        .json(&params)
+        .send()
+        .await?;
+    let response = utils::assert_ok_response(response).await?;
+    assert_eq!(
+        response.headers()[reqwest::header::CONTENT_TYPE],
+        "application/x-ndjson"
+    );
+    let body = response.text().await?;
+    let streamed_transactions = body
+        .lines()
+        .map(serde_json::from_str)
+        .collect::<Result<Vec<LinkedTransaction>, _>>()?;
+    assert_eq!(streamed_transactions, page_transactions.items);
+    Ok(())
+}
@@ -1,6 +1,11 @@
 #![allow(dead_code)]
 
+use axum::{
+    Json,
+    routing::{MethodRouter, post},
+};
 use reqwest::Response;
+use serde_json::{Value, json};
 use std::path::PathBuf;
 use std::process::{Command, Output};
 
@@ -28,3 +33,40 @@ pub fn reset_database(db_connection_url: &str) -> Result<Output, String> {
         .output()
         .map_err(|err| format!("{err:?}"))
 }
+
+/// Inputs of a request to a mock embedding API, which can have one input or
+/// a batch of them.
+pub fn embedding_inputs(request: &Value) -> Vec<String> {
+    match &request["input"] {
+        Value::Array(inputs) => inputs
+            .iter()
+            .map(|input| input.as_str().unwrap_or_default().to_string())
+            .collect(),
+        input => vec![input.as_str().unwrap_or_default().to_string()],
+    }
+}
+
+/// Respond to a request to a mock embedding API, embedding each of its
+/// inputs with the given function.
+pub fn embedding_response(request: &Value, embed: impl Fn(&str) -> Vec<f32>) -> Json<Value> {
+    let data: Vec<Value> = embedding_inputs(request)
+        .iter()
+        .enumerate()
+        .map(|(index, input)| json!({"embedding": embed(input), "index": index}))
+        .collect();
+    Json(json!({"data": data}))
+}
+
+/// Mock embedding API that embeds every input the same way.
+pub async fn mock_embeddings(Json(request): Json<Value>) -> Json<Value> {
+    embedding_response(&request, |_| vec![1.0, 0.0, 0.0])
+}
+
+/// Route for a mock embedding API that embeds each input of a request with
+/// the given function.
+pub fn mock_embeddings_with<S>(embed: fn(&str) -> Vec<f32>) -> MethodRouter<S>
+where
+    S: Clone + Send + Sync + 'static,
+{
+    post(move |Json(request): Json<Value>| async move { embedding_response(&request, embed) })
+}
@@ -0,0 +1,168 @@
+use chrono::{TimeDelta, Utc};
+use reqwest::StatusCode;
+use serial_test::serial;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use toi_server::{
+    models::weather::{
+        AlertSummary, ForecastSummary, GeocodeCacheEntry, GridpointForecast,
+        GridpointForecastSummary, WeatherAlerts, WeatherAlertsSummary, WeatherResponse,
+        ZoneForecast, ZoneForecastPeriod, ZoneForecastSummary,
+    },
+    routes::weather::geocode_with_cache,
+};
+
+mod utils;
+
+#[tokio::test]
+#[serial]
+async fn geocode_cache() -> Result<(), Box<dyn std::error::Error>> {
+    // Make sure there's a database URL and it points to a test database so
+    // prod isn't goofed during testing.
+    let db_connection_url = dotenvy::var("DATABASE_URL")?;
+    utils::reset_database(&db_connection_url)?;
+
+    // Initialize the server state.
+    let state = toi_server::init(db_connection_url).await?;
+    let mut conn = state.pool.get().await?;
+
+    // Mock lookup that counts how many times it's called rather than
+    // reaching out to live geocoding APIs.
+    let lookups = AtomicUsize::new(0);
+    let lookup = |query: String| {
+        lookups.fetch_add(1, Ordering::SeqCst);
+        async move {
+            Ok::<_, (StatusCode, String)>(GeocodeCacheEntry {
+                query,
+                latitude: 30.2672,
+                longitude: -97.7431,
+                forecast: "https://api.weather.gov/gridpoints/EWX/156,91/forecast".to_string(),
+                forecast_zone: "https://api.weather.gov/zones/forecast/TXZ192".to_string(),
+                updated_at: Utc::now(),
+                forecast_hourly: Some(
+                    "https://api.weather.gov/gridpoints/EWX/156,91/forecast/hourly".to_string(),
+                ),
+            })
+        }
+    };
+    let ttl = TimeDelta::days(30);
+
+    // The first lookup is a miss.
+    let point = geocode_with_cache("Austin,  TX", false, ttl, &mut conn, lookup).await;
+    let point = point.map_err(|(_, err)| err)?;
+    assert_eq!(lookups.load(Ordering::SeqCst), 1);
+    assert!(point.properties.forecast_zone.ends_with("TXZ192"));
+    assert!(
+        point
+            .properties
+            .forecast_hourly
+            .is_some_and(|forecast_hourly| forecast_hourly.ends_with("/hourly"))
+    );
+
+    // The same query, normalized, is a hit.
+    let point = geocode_with_cache("austin, tx", false, ttl, &mut conn, lookup).await;
+    let point = point.map_err(|(_, err)| err)?;
+    assert_eq!(lookups.load(Ordering::SeqCst), 1);
+    assert!(point.properties.forecast_zone.ends_with("TXZ192"));
+
+    // Expired results are looked up again.
+    let point = geocode_with_cache("austin, tx", false, TimeDelta::zero(), &mut conn, lookup).await;
+    point.map_err(|(_, err)| err)?;
+    assert_eq!(lookups.load(Ordering::SeqCst), 2);
+
+    // Bypassing the cache always looks results up again.
+    let point = geocode_with_cache("austin, tx", true, ttl, &mut conn, lookup).await;
+    point.map_err(|(_, err)| err)?;
+    assert_eq!(lookups.load(Ordering::SeqCst), 3);
+    Ok(())
+}
+
+#[test]
+fn summarizing_gridpoint_forecasts() -> Result<(), Box<dyn std::error::Error>> {
+    let forecast: GridpointForecast =
+        serde_json::from_str(include_str!("fixtures/nws_gridpoint_forecast.json"))?;
+    let summary = GridpointForecastSummary::from(forecast);
+    assert_eq!(summary.note, None);
+    assert_eq!(
+        summary.periods,
+        vec![
+            ForecastSummary {
+                name: "Today".to_string(),
+                start_time: "2025-06-24T15:00:00Z".parse()?,
+                end_time: "2025-06-24T23:00:00Z".parse()?,
+                temperature: "96 F".to_string(),
+                wind: "5 to 10 mph S".to_string(),
+                short_forecast: "Slight Chance Showers And Thunderstorms".to_string(),
+                precipitation_chance: Some(20),
+            },
+            ForecastSummary {
+                name: "Tonight".to_string(),
+                start_time: "2025-06-24T23:00:00Z".parse()?,
+                end_time: "2025-06-25T11:00:00Z".parse()?,
+                temperature: "76 F".to_string(),
+                wind: "5 mph SE".to_string(),
+                short_forecast: "Mostly Clear".to_string(),
+                precipitation_chance: None,
+            },
+        ]
+    );
+
+    // The full forecast is only returned when it's asked for.
+    for (verbose, is_verbose) in [(None, false), (Some(false), false), (Some(true), true)] {
+        let forecast: GridpointForecast =
+            serde_json::from_str(include_str!("fixtures/nws_gridpoint_forecast.json"))?;
+        let response: WeatherResponse<GridpointForecastSummary, GridpointForecast> =
+            WeatherResponse::new(forecast, verbose);
+        let response = serde_json::to_string(&response)?;
+        assert_eq!(response.contains("detailedForecast"), is_verbose);
+    }
+    Ok(())
+}
+
+#[test]
+fn summarizing_zone_forecasts() -> Result<(), Box<dyn std::error::Error>> {
+    let forecast: ZoneForecast =
+        serde_json::from_str(include_str!("fixtures/nws_zone_forecast.json"))?;
+    let summary = ZoneForecastSummary::from(forecast);
+    let names: Vec<&str> = summary
+        .periods
+        .iter()
+        .map(|period| period.name.as_str())
+        .collect();
+    assert_eq!(names, vec!["Today", "Tonight"]);
+    assert_eq!(
+        summary.periods[1],
+        ZoneForecastPeriod {
+            name: "Tonight".to_string(),
+            detailed_forecast: "Mostly clear. Lows in the mid 70s. Southeast winds around 5 mph."
+                .to_string(),
+        }
+    );
+    Ok(())
+}
+
+#[test]
+fn summarizing_weather_alerts() -> Result<(), Box<dyn std::error::Error>> {
+    let alerts: WeatherAlerts = serde_json::from_str(include_str!("fixtures/nws_alerts.json"))?;
+    let summary = WeatherAlertsSummary::from(alerts);
+    assert_eq!(
+        summary.alerts,
+        vec![
+            AlertSummary {
+                event: "Heat Advisory".to_string(),
+                severity: "Moderate".to_string(),
+                headline: "Heat Advisory issued June 24 at 3:41AM CDT until June 24 at 8:00PM CDT by NWS Austin/San Antonio TX".to_string(),
+                effective: "2025-06-24T08:41:00Z".parse()?,
+                ends: Some("2025-06-25T01:00:00Z".parse()?),
+            },
+            AlertSummary {
+                event: "Special Weather Statement".to_string(),
+                severity: "Minor".to_string(),
+                headline: "Special Weather Statement issued June 24 at 4:05PM CDT by NWS Austin/San Antonio TX".to_string(),
+                effective: "2025-06-24T21:05:00Z".parse()?,
+                ends: None,
+            },
+        ]
+    );
+    Ok(())
+}